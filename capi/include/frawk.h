@@ -0,0 +1,83 @@
+/*
+ * C bindings for embedding frawk programs as record transformers.
+ *
+ * Compile a program once with frawk_compile, then start any number of runs of
+ * it with frawk_start. Each run has its own variables; starting it executes
+ * the BEGIN block. Feed it records one at a time with frawk_feed, and end its
+ * input with frawk_finish, which executes the END block. After each of these
+ * calls, frawk_output returns what the program printed to standard output
+ * during that call. Output to files and commands is written as usual.
+ *
+ *     char *err = NULL;
+ *     frawk_program *prog = frawk_compile("{ print $2, $1 }", NULL, 0, &err);
+ *     frawk_run *run = frawk_start(prog);
+ *     if (frawk_feed(run, "a b", 3, &err) == 1) {
+ *         size_t len;
+ *         const char *out = frawk_output(run, &len);  // "b a\n"
+ *     }
+ *     frawk_finish(run, NULL, &err);
+ *     frawk_run_free(run);
+ *     frawk_program_free(prog);
+ *
+ * Programs are run with frawk's bytecode interpreter, on the calling thread. A
+ * run must only be used from the thread that started it.
+ */
+#ifndef FRAWK_H
+#define FRAWK_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct FrawkProgram frawk_program;
+typedef struct FrawkRun frawk_run;
+
+/*
+ * Compile src, along with nvars assignments of the form "name=value" (as with
+ * frawk's -v) to make before BEGIN. Returns NULL and sets *error (if error is
+ * not NULL) if the program does not compile.
+ */
+frawk_program *frawk_compile(const char *src, const char *const *vars,
+                             size_t nvars, char **error);
+void frawk_program_free(frawk_program *prog);
+
+/* Start a new run of prog, executing its BEGIN block. */
+frawk_run *frawk_start(const frawk_program *prog);
+
+/*
+ * Process the len bytes at record as the next record of input. Returns 1 if
+ * the program read the record, 0 if it had already finished (for example by
+ * calling exit), and -1 (setting *error) if it failed.
+ */
+int frawk_feed(frawk_run *run, const char *record, size_t len, char **error);
+
+/*
+ * End the input, executing the END block, and store the program's exit status
+ * in *status (if status is not NULL). Returns 0, or -1 (setting *error) if the
+ * program failed.
+ */
+int frawk_finish(frawk_run *run, int *status, char **error);
+
+/*
+ * What the program printed to standard output during the last call to
+ * frawk_start, frawk_feed or frawk_finish on run. The output is not
+ * NUL-terminated, and is only valid until the next call on run.
+ */
+const char *frawk_output(const frawk_run *run, size_t *len);
+
+/*
+ * Free a run. If it has not finished, its input is ended and its END block
+ * runs in the background.
+ */
+void frawk_run_free(frawk_run *run);
+
+/* Free an error message returned by one of the functions above. */
+void frawk_error_free(char *error);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FRAWK_H */
@@ -0,0 +1,167 @@
+//! C bindings for running frawk programs as record transformers, built on `frawk::embed`. The
+//! functions are declared in `include/frawk.h`, which describes how to use them.
+//!
+//! Errors are returned as strings allocated here, which callers release with `frawk_error_free`.
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use frawk::embed::{Program, Run};
+
+pub struct FrawkProgram(Program);
+pub struct FrawkRun(Run);
+
+unsafe fn set_error(error: *mut *mut c_char, msg: String) {
+    if error.is_null() {
+        return;
+    }
+    // Messages are not expected to contain NULs, but must not be cut short if they do.
+    let msg = CString::new(msg.replace('\0', "\\0")).unwrap();
+    *error = msg.into_raw();
+}
+
+/// Compile a program, along with `nvars` assignments of the form `name=value` to make before it
+/// runs. Returns NULL and sets `*error` (if `error` is not NULL) if the program does not compile.
+///
+/// # Safety
+/// `src` and each of the `vars` must be NUL-terminated strings, and `vars` must point to `nvars`
+/// of them (it may be NULL if `nvars` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn frawk_compile(
+    src: *const c_char,
+    vars: *const *const c_char,
+    nvars: usize,
+    error: *mut *mut c_char,
+) -> *mut FrawkProgram {
+    let utf8 = |s: *const c_char| {
+        CStr::from_ptr(s)
+            .to_str()
+            .map_err(|_| String::from("program text and variables must be UTF-8"))
+    };
+    let compiled = (|| {
+        let vars: Vec<&str> = if nvars == 0 {
+            Vec::new()
+        } else {
+            let vars = slice::from_raw_parts(vars, nvars);
+            vars.iter().map(|v| utf8(*v)).collect::<Result<_, _>>()?
+        };
+        Program::compile(utf8(src)?, &vars)
+    })();
+    match compiled {
+        Ok(prog) => Box::into_raw(Box::new(FrawkProgram(prog))),
+        Err(e) => {
+            set_error(error, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free a program returned by `frawk_compile`. Runs of the program are unaffected.
+///
+/// # Safety
+/// `prog` must be NULL or a program returned by `frawk_compile` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn frawk_program_free(prog: *mut FrawkProgram) {
+    if !prog.is_null() {
+        drop(Box::from_raw(prog));
+    }
+}
+
+/// Start a new run of `prog`, executing its BEGIN block.
+///
+/// # Safety
+/// `prog` must be a program returned by `frawk_compile` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn frawk_start(prog: *const FrawkProgram) -> *mut FrawkRun {
+    Box::into_raw(Box::new(FrawkRun((*prog).0.start())))
+}
+
+/// Process the `len` bytes at `record` as the next record of input. Returns 1 if the program read
+/// the record, 0 if it had already finished, and -1 (setting `*error`) if it failed.
+///
+/// # Safety
+/// `run` must be a run returned by `frawk_start` that has not been freed, and `record` must point
+/// to `len` bytes (it may be NULL if `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn frawk_feed(
+    run: *mut FrawkRun,
+    record: *const c_char,
+    len: usize,
+    error: *mut *mut c_char,
+) -> c_int {
+    let record: &[u8] = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(record as *const u8, len)
+    };
+    match (*run).0.feed(record) {
+        Ok(read) => read as c_int,
+        Err(e) => {
+            set_error(error, e);
+            -1
+        }
+    }
+}
+
+/// End the input, running the END block, and store the program's exit status in `*status` (if
+/// `status` is not NULL). Returns 0, or -1 (setting `*error`) if the program failed.
+///
+/// # Safety
+/// `run` must be a run returned by `frawk_start` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn frawk_finish(
+    run: *mut FrawkRun,
+    status: *mut c_int,
+    error: *mut *mut c_char,
+) -> c_int {
+    match (*run).0.finish() {
+        Ok(rc) => {
+            if !status.is_null() {
+                *status = rc as c_int;
+            }
+            0
+        }
+        Err(e) => {
+            set_error(error, e);
+            -1
+        }
+    }
+}
+
+/// The output to standard output from the last call to `frawk_start`, `frawk_feed` or
+/// `frawk_finish` on `run`, storing its length in `*len`. The output is not NUL-terminated, and
+/// is only valid until the next call on `run`.
+///
+/// # Safety
+/// `run` must be a run returned by `frawk_start` that has not been freed, and `len` must not be
+/// NULL.
+#[no_mangle]
+pub unsafe extern "C" fn frawk_output(run: *const FrawkRun, len: *mut usize) -> *const c_char {
+    let output = (*run).0.output();
+    *len = output.len();
+    output.as_ptr() as *const c_char
+}
+
+/// Free a run returned by `frawk_start`. If it has not finished, its input is ended and its END
+/// block runs in the background.
+///
+/// # Safety
+/// `run` must be NULL or a run returned by `frawk_start` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn frawk_run_free(run: *mut FrawkRun) {
+    if !run.is_null() {
+        drop(Box::from_raw(run));
+    }
+}
+
+/// Free an error message returned by one of the other functions.
+///
+/// # Safety
+/// `error` must be NULL or an error message from this library that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn frawk_error_free(error: *mut c_char) {
+    if !error.is_null() {
+        drop(CString::from_raw(error));
+    }
+}
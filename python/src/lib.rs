@@ -0,0 +1,7 @@
+//! The `frawk` Python extension module. Its contents are defined in `frawk::python`.
+use pyo3::prelude::*;
+
+#[pymodule]
+fn frawk(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    frawk_lib::python::init(py, m)
+}
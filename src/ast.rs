@@ -19,9 +19,14 @@
 ///    to take advantage of that, but it would probably involve building out def-use chains (which
 ///    we currently don't do), and we'd want to verify that performance didn't degrade when the
 ///    patterns are _not sparse_ in the input.
+use std::hash::Hash;
+
+use hashbrown::HashSet;
+
 use crate::arena::{self, Arena};
 use crate::builtins::Function;
 use crate::common::{Either, FileSpec, Stage};
+use crate::lexer::Loc;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Unop {
@@ -42,9 +47,48 @@ static_map!(
 pub struct FunDec<'a, 'b, I> {
     pub name: I,
     pub args: Vec<I>,
+    // Declared types of the arguments (`function f(x: int)`) and of the return value.
+    pub arg_types: Vec<Option<TypeAnn>>,
+    pub ret_type: Option<TypeAnn>,
     pub body: &'a Stmt<'a, 'b, I>,
 }
 
+/// A scalar type written in a type annotation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScalarAnn {
+    Int,
+    Float,
+    Str,
+}
+
+/// A type written in a type annotation: in a `# @type NAME: TYPE` pragma, or on a function's
+/// parameters or return value. Annotations constrain type inference, which fails if the
+/// annotated variable is used in a way that gives it a different type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TypeAnn {
+    Scalar(ScalarAnn),
+    Map { key: ScalarAnn, val: ScalarAnn },
+}
+
+impl std::fmt::Display for ScalarAnn {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScalarAnn::Int => write!(f, "int"),
+            ScalarAnn::Float => write!(f, "float"),
+            ScalarAnn::Str => write!(f, "str"),
+        }
+    }
+}
+
+impl std::fmt::Display for TypeAnn {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TypeAnn::Scalar(s) => write!(f, "{}", s),
+            TypeAnn::Map { key, val } => write!(f, "map[{}]{}", key, val),
+        }
+    }
+}
+
 pub enum Pattern<'a, 'b, I> {
     Null,
     Bool(&'a Expr<'a, 'b, I>),
@@ -58,6 +102,10 @@ pub struct Prog<'a, 'b, I> {
     // FS
     pub field_sep: Option<&'b [u8]>,
     pub prelude_vardecs: Vec<(I, &'a Expr<'a, 'b, I>)>,
+    // Named constants, from -D flags and `const` declarations. Their values are literals.
+    pub consts: Vec<(I, &'a Expr<'a, 'b, I>)>,
+    // Types of global variables, declared with `# @type NAME: TYPE`.
+    pub var_types: Vec<(I, TypeAnn)>,
     // OFS
     pub output_sep: Option<&'b [u8]>,
     // ORS
@@ -66,10 +114,42 @@ pub struct Prog<'a, 'b, I> {
     pub begin: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub prepare: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub end: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    pub beginfile: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    pub endfile: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub pats: arena::Vec<'a, (Pattern<'a, 'b, I>, Option<&'a Stmt<'a, 'b, I>>)>,
     pub stage: Stage<()>,
     pub argv: Vec<&'b str>,
+    // Assignments given as operands (`NAME=VALUE`) among the input files, each with the ARGIND of
+    // the file it precedes. They are made when the main loop reaches that file, or before END
+    // for assignments after the last file.
+    pub file_assignments: Vec<(i64, I, &'a Expr<'a, 'b, I>)>,
     pub parse_header: bool,
+    // Disable gawk and frawk extensions, for compatibility with one-true-awk.
+    pub traditional: bool,
+    // Whether calls to `assert` are checked, or compiled away.
+    pub asserts: bool,
+    // Report memory usage (via dump_state) when the program finishes.
+    pub mem_profile: bool,
+    // Poll for SIGUSR1 once per record in the main loop, calling dump_state when it is received.
+    pub dump_on_signal: bool,
+    // Annotate statements with their source line, for debug info in compiled code.
+    pub debug_lines: bool,
+    // Save global variables to the --checkpoint file, between records and when the program
+    // finishes.
+    pub checkpoint: bool,
+    // The value of NR in the --resume checkpoint, whose variables are restored before BEGIN.
+    pub resume_nr: Option<i64>,
+    // Variable names referenced in the program (excluding function parameters), in the order they
+    // first appear; see `add_ident`. Used to expand calls to dump_state().
+    pub idents: Vec<I>,
+    ident_set: HashSet<I>,
+    // Helper functions sorting with a user-defined comparison function, keyed by the comparison
+    // function's name; see `parsing::add_helpers`.
+    pub sort_helpers: Vec<(I, I)>,
+    // The numbers of arguments passed in `@f(args)` calls, and the string constants that could
+    // name the functions they call; see `parsing::indirect_call`.
+    pub indirect_calls: Vec<usize>,
+    pub function_names: Vec<I>,
 }
 
 fn parse_header<'a, 'b, I: From<&'b str> + Clone>(
@@ -101,13 +181,16 @@ fn parse_header<'a, 'b, I: From<&'b str> + Clone>(
     let body = arena.alloc(Expr(arena.alloc(Call(
         Either::Right(Function::SetFI),
         arena.alloc_slice(&[loop_var, loop_var]),
+        None,
     ))));
 
     let block = arena.new_vec_from_slice(&[
         arena.alloc(For(Some(init), Some(cond), Some(update), body)),
-        arena.alloc(Expr(
-            arena.alloc(Call(Either::Right(Function::UpdateUsedFields), &[])),
-        )),
+        arena.alloc(Expr(arena.alloc(Call(
+            Either::Right(Function::UpdateUsedFields),
+            &[],
+            None,
+        )))),
     ]);
     begin.push(arena.alloc(If(
         arena.alloc(Binop(
@@ -120,20 +203,150 @@ fn parse_header<'a, 'b, I: From<&'b str> + Clone>(
     )));
 }
 
+fn file_hooks<'a, 'b, I: From<&'b str> + Clone>(
+    arena: &'a Arena,
+    beginfile: &arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    endfile: &arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+    inner: &mut arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+) {
+    use {self::Binop::*, self::Expr::*, Stmt::*};
+    // Pick an illegal frawk identifier.
+    const BOUNDARY: &str = "--file-boundary";
+    // With file hooks enabled, the main input also stops at the start and end of each file (see
+    // `LineReader::file_boundary`), so we prepend the following to the main loop (before NR and
+    // FNR are incremented):
+    // BOUNDARY = FileBoundary()
+    // if (BOUNDARY) {
+    //   if (BOUNDARY == 1) { BEGINFILE... } else { ENDFILE... }
+    //   next
+    // }
+    let boundary = arena.alloc(Var(BOUNDARY.into()));
+    inner.push(arena.alloc(Expr(arena.alloc(Assign(
+        boundary,
+        arena.alloc(Call(Either::Right(Function::FileBoundary), &[], None)),
+    )))));
+    let mut on_boundary = arena.vec_with_capacity(2);
+    on_boundary.push(arena.alloc(If(
+        arena.alloc(Binop(EQ, boundary, arena.alloc(ILit(1)))),
+        arena.alloc(Block(beginfile.clone())),
+        Some(arena.alloc(Block(endfile.clone()))),
+    )));
+    on_boundary.push(arena.alloc(Next));
+    inner.push(arena.alloc(If(boundary, arena.alloc(Block(on_boundary)), None)));
+}
+
+// Desugar assignment operands (see `Prog::file_assignments`), adding the assignments for each new
+// file to the start of the main loop, and returning those still to be made before END.
+fn file_assignments<'a, 'b, I: From<&'b str> + Clone>(
+    arena: &'a Arena,
+    assigns: &[(i64, I, &'a Expr<'a, 'b, I>)],
+    inner: &mut arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
+) -> arena::Vec<'a, &'a Stmt<'a, 'b, I>> {
+    use {self::Binop::*, self::Expr::*, Stmt::*};
+    // The number of assignments made so far. Each one is made once, in order, even if the file it
+    // precedes has no records.
+    const DONE: &str = "--assigned";
+    // For the i'th assignment:
+    // if (DONE < i+1) { NAME = VALUE; DONE = i+1 }
+    let assign = |i: usize, name: &I, value: &'a self::Expr<'a, 'b, I>| {
+        let mut block = arena.vec_with_capacity(2);
+        block.push(arena.alloc(Expr(
+            arena.alloc(Assign(arena.alloc(Var(name.clone())), value)),
+        )));
+        block.push(arena.alloc(Expr(arena.alloc(Assign(
+            arena.alloc(Var(DONE.into())),
+            arena.alloc(ILit(i as i64 + 1)),
+        )))));
+        arena.alloc(If(
+            arena.alloc(Binop(
+                LT,
+                arena.alloc(Var(DONE.into())),
+                arena.alloc(ILit(i as i64 + 1)),
+            )),
+            arena.alloc(Block(block)),
+            None,
+        ))
+    };
+    // At the first record of each file (see `file_hooks`), make the assignments preceding it:
+    // if (FNR == 0) { if (ARGIND >= <index>) <assign> ... }
+    let mut on_new_file = arena.vec_with_capacity(assigns.len());
+    let mut before_end = arena.vec_with_capacity(assigns.len());
+    for (i, (ix, name, value)) in assigns.iter().enumerate() {
+        on_new_file.push(arena.alloc(If(
+            arena.alloc(Binop(
+                GTE,
+                arena.alloc(Var("ARGIND".into())),
+                arena.alloc(ILit(*ix)),
+            )),
+            assign(i, name, value),
+            None,
+        )));
+        before_end.push(assign(i, name, value));
+    }
+    inner.push(arena.alloc(If(
+        arena.alloc(Binop(
+            EQ,
+            arena.alloc(Var("FNR".into())),
+            arena.alloc(ILit(0)),
+        )),
+        arena.alloc(Block(on_new_file)),
+        None,
+    )));
+    before_end
+}
+
+impl<'a, 'b, I: Hash + Eq + Clone> Prog<'a, 'b, I> {
+    /// Record a reference to the variable `i`, if it has not been referenced already.
+    pub(crate) fn add_ident(&mut self, i: I) {
+        if self.ident_set.insert(i.clone()) {
+            self.idents.push(i);
+        }
+    }
+
+    /// Forget the variables first referenced after the first `start` that are among `params`, the
+    /// parameters of the function just parsed: they are local to that function.
+    pub(crate) fn remove_params(&mut self, start: usize, params: &[I]) {
+        for i in self.idents.split_off(start) {
+            if params.contains(&i) {
+                self.ident_set.remove(&i);
+            } else {
+                self.idents.push(i);
+            }
+        }
+    }
+}
+
 impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
     pub(crate) fn from_stage(arena: &'a Arena, stage: Stage<()>) -> Self {
         Prog {
             field_sep: None,
             prelude_vardecs: Vec::new(),
+            consts: Vec::new(),
+            var_types: Vec::new(),
             output_sep: None,
             output_record_sep: None,
             decs: arena.new_vec(),
             begin: arena.new_vec(),
             prepare: arena.new_vec(),
             end: arena.new_vec(),
+            beginfile: arena.new_vec(),
+            endfile: arena.new_vec(),
             pats: arena.new_vec(),
             argv: Vec::new(),
+            file_assignments: Vec::new(),
             parse_header: false,
+            traditional: false,
+            asserts: true,
+            mem_profile: false,
+            dump_on_signal: false,
+            debug_lines: false,
+            checkpoint: false,
+            resume_nr: None,
+            idents: Vec::new(),
+            ident_set: HashSet::new(),
+            sort_helpers: Vec::new(),
+            indirect_calls: Vec::new(),
+            function_names: Vec::new(),
             stage,
         }
     }
@@ -153,11 +366,6 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             )))));
         }
 
-        // for -H
-        if self.parse_header {
-            parse_header(arena, &mut begin);
-        }
-
         // Support "output csv/tsv" mode
         if let Some(sep) = self.output_sep {
             begin.push(arena.alloc(Expr(arena.alloc(Assign(
@@ -177,6 +385,18 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             arena.alloc(Var("SUBSEP".into())),
             arena.alloc(StrLit(&[0o034u8])),
         )))));
+        // For --resume: restore(); NR = <saved NR>
+        // This comes before -v assignments, so that they take precedence.
+        if let Some(nr) = self.resume_nr {
+            begin.push(arena.alloc(Expr(arena.alloc(Call(
+                Either::Right(Function::Restore),
+                &[],
+                None,
+            )))));
+            begin.push(arena.alloc(Expr(
+                arena.alloc(Assign(arena.alloc(Var("NR".into())), arena.alloc(ILit(nr)))),
+            )));
+        }
         // Desugar -v flags
         for (ident, exp) in self.prelude_vardecs.iter() {
             begin.push(arena.alloc(Expr(
@@ -199,10 +419,38 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             }
         }
 
+        // for -H. The header is read once ARGV is set, as reading it opens the first input file.
+        if self.parse_header {
+            parse_header(arena, &mut begin);
+        }
+
         begin.extend(self.begin.iter().cloned());
 
         // Desugar patterns into if statements, with the usual desugaring for an empty action.
         let mut inner = arena.vec_with_capacity(10);
+        if self.checkpoint {
+            // if (CheckpointDue()) checkpoint()
+            // This runs before NR is incremented, so the checkpoint reflects the records
+            // processed so far, and not the one just read.
+            inner.push(arena.alloc(If(
+                arena.alloc(Call(Either::Right(Function::CheckpointDue), &[], None)),
+                arena.alloc(Expr(arena.alloc(Call(
+                    Either::Right(Function::Checkpoint),
+                    &[],
+                    None,
+                )))),
+                None,
+            )));
+        }
+        let before_end = if self.file_assignments.is_empty() {
+            arena.new_vec()
+        } else {
+            file_assignments(arena, &self.file_assignments, &mut inner)
+        };
+        let has_file_hooks = !self.beginfile.is_empty() || !self.endfile.is_empty();
+        if has_file_hooks {
+            file_hooks(arena, &self.beginfile, &self.endfile, &mut inner);
+        }
         inner.push(arena.alloc(Expr(arena.alloc(Inc {
             is_inc: true,
             is_post: false,
@@ -213,6 +461,19 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             is_post: false,
             x: arena.alloc(Var("FNR".into())),
         }))));
+        if self.dump_on_signal && matches!(self.stage, Stage::Main(_)) {
+            // if (DumpRequested()) dump_state()
+            // Lets users inspect a long-running script by sending it SIGUSR1.
+            inner.push(arena.alloc(If(
+                arena.alloc(Call(Either::Right(Function::DumpRequested), &[], None)),
+                arena.alloc(Expr(arena.alloc(Call(
+                    Either::Right(Function::DumpState),
+                    &[],
+                    None,
+                )))),
+                None,
+            )));
+        }
         let init_len = inner.len();
         for (pat, body) in self.pats.iter() {
             let body = if let Some(body) = body {
@@ -282,7 +543,11 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             }
         }
 
-        if !self.end.is_empty() || !self.prepare.is_empty() || inner.len() > init_len {
+        if !self.end.is_empty()
+            || !self.prepare.is_empty()
+            || has_file_hooks
+            || inner.len() > init_len
+        {
             // Wrap the whole thing in a while((getline) > 0) { } statement.
             let main_portion = arena.alloc(While(
                 /*is_toplevel=*/ true,
@@ -299,11 +564,27 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             });
         }
         if !self.end.is_empty() {
-            end = Some(arena.alloc(Stmt::Block(self.end.clone())));
+            let mut block = before_end;
+            block.extend(self.end.iter().cloned());
+            end = Some(arena.alloc(Stmt::Block(block)));
         }
         match self.stage {
             Stage::Main(_) => {
                 begin.extend(main_loop.into_iter().chain(end));
+                if self.checkpoint {
+                    begin.push(arena.alloc(Expr(arena.alloc(Call(
+                        Either::Right(Function::Checkpoint),
+                        &[],
+                        None,
+                    )))));
+                }
+                if self.mem_profile {
+                    begin.push(arena.alloc(Expr(arena.alloc(Call(
+                        Either::Right(Function::DumpState),
+                        &[],
+                        None,
+                    )))));
+                }
                 Stage::Main(arena.alloc(Stmt::Block(begin)))
             }
             Stage::Par { .. } => Stage::Par {
@@ -359,9 +640,12 @@ pub enum Expr<'a, 'b, I> {
     FLit(f64),
     StrLit(&'b [u8]),
     PatLit(&'b [u8]),
+    // `@/re/`: a regex constant with its own type; see `types::BaseTy::Regex`.
+    RegexLit(&'b [u8]),
     Unop(Unop, &'a Expr<'a, 'b, I>),
     Binop(Binop, &'a Expr<'a, 'b, I>, &'a Expr<'a, 'b, I>),
-    Call(Either<I, Function>, &'a [&'a Expr<'a, 'b, I>]),
+    // Calls written in the program by name carry their location, for assert's error message.
+    Call(Either<I, Function>, &'a [&'a Expr<'a, 'b, I>], Option<Loc>),
     Var(I),
     Index(&'a Expr<'a, 'b, I>, &'a Expr<'a, 'b, I>),
     Assign(
@@ -434,3 +718,28 @@ pub enum Stmt<'a, 'b, I> {
     NextFile,
     Return(Option<&'a Expr<'a, 'b, I>>),
 }
+
+impl<'a, 'b, I> Stmt<'a, 'b, I> {
+    // Whether this statement contains a `next`, or a `nextfile` if `nextfile` is set.
+    pub(crate) fn contains_next(&self, nextfile: bool) -> bool {
+        use Stmt::*;
+        match self {
+            Next => true,
+            NextFile => nextfile,
+            Block(stmts) => stmts.iter().any(|s| s.contains_next(nextfile)),
+            If(_, t, f) => {
+                t.contains_next(nextfile) || f.map_or(false, |f| f.contains_next(nextfile))
+            }
+            For(init, _, update, body) => [init, update]
+                .iter()
+                .flat_map(|s| s.iter())
+                .chain(std::iter::once(body))
+                .any(|s| s.contains_next(nextfile)),
+            DoWhile(_, body) | While(_, _, body) | ForEach(_, _, body) => {
+                body.contains_next(nextfile)
+            }
+            StartCond(_) | EndCond(_) | LastCond(_) | Expr(_) | Print(..) | Printf(..) | Break
+            | Continue | Return(_) => false,
+        }
+    }
+}
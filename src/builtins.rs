@@ -22,39 +22,100 @@ pub enum Function {
     Nextline,
     ReadErrStdin,
     NextlineStdin,
+    // Whether the last read of the main input stopped at the start or end of a file rather than
+    // at a record; used to run BEGINFILE and ENDFILE.
+    FileBoundary,
     NextlineCmd,
     ReadLineStdinFused,
     NextFile,
     Setcol,
+    // Replace $0 without reading any input, returning the new NF.
+    SetRecord,
+    // Split $0 again with a different separator, returning the new NF.
+    Resplit,
     Split,
     Length,
+    BLength,
     Contains,
     Delete,
     Clear,
+    CopyMap,
     Match,
+    // match(s, re, arr), which also stores the text of the match and its capture groups in arr.
+    // It replaces Match when a third argument is passed.
+    MatchArr,
+    MatchAll,
     SubstrIndex,
+    // Compares two strings numerically if both look like numbers, and as strings otherwise,
+    // returning -1, 0 or 1. Comparisons between field values are rewritten to use it during cfg
+    // construction.
+    StrNumCmp,
     Sub,
     GSub,
+    // sub and gsub on $0, which substitute into the record directly rather than assigning to $0.
+    SubLine,
+    GSubLine,
     GenSub,
     EscapeCSV,
     EscapeTSV,
+    EscapeJSON,
     JoinCols,
     JoinCSV,
     JoinTSV,
+    JoinJSON,
+    // The key for a value printed in JSON output mode, given its column (if any) and position.
+    JsonKey,
+    // The MessagePack counterparts of EscapeJSON, JoinJSON and JsonKey, for --omsgpack.
+    EscapeMsgPack,
+    JoinMsgPack,
+    MsgPackKey,
     Substr,
     ToInt,
     HexToInt,
     Rand,
     Srand,
     ReseedRng,
+    Sleep,
+    TimeOfDay,
+    Monotonic,
+    Strftime,
+    ParseDate,
     System,
+    CmdStatus,
+    UsePty,
+    HllAdd,
+    HllCount,
+    Sample,
+    Asorti,
+    TopK,
     // For header-parsing logic
     UpdateUsedFields,
     SetFI,
     ToUpper,
     ToLower,
+    Chr,
+    Ord,
+    GetXml,
+    Unpack,
     IncMap,
     Exit,
+    // assert(cond, msg, location); the location is filled in by the parser.
+    Assert,
+    // dump_state(name1, val1, name2, val2, ...); the global variables are filled in during cfg
+    // construction.
+    DumpState,
+    // Whether a state dump was requested via SIGUSR1.
+    DumpRequested,
+    // checkpoint(name1, val1, ...) and restore(name1, var1, ...) save the global variables to the
+    // --checkpoint file, and restore them from the --resume file. Like dump_state, the variables
+    // are filled in during cfg construction.
+    Checkpoint,
+    Restore,
+    // Whether a checkpoint is due to be written.
+    CheckpointDue,
+    // Marks the source line of the following statements; only emitted when debug info is
+    // requested.
+    SetLine,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -201,7 +262,9 @@ static_map!(
     ["close", Function::Close],
     ["split", Function::Split],
     ["length", Function::Length],
+    ["blength", Function::BLength],
     ["match", Function::Match],
+    ["matchall", Function::MatchAll],
     ["sub", Function::Sub],
     ["gsub", Function::GSub],
     ["gensub", Function::GenSub],
@@ -227,15 +290,38 @@ static_map!(
     ["join_fields", Function::JoinCols],
     ["join_csv", Function::JoinCSV],
     ["join_tsv", Function::JoinTSV],
+    ["join_json", Function::JoinJSON],
     ["escape_csv", Function::EscapeCSV],
     ["escape_tsv", Function::EscapeTSV],
+    ["escape_json", Function::EscapeJSON],
     ["rand", Function::Rand],
     ["srand", Function::Srand],
     ["index", Function::SubstrIndex],
     ["toupper", Function::ToUpper],
     ["tolower", Function::ToLower],
+    ["chr", Function::Chr],
+    ["ord", Function::Ord],
+    ["getxml", Function::GetXml],
+    ["unpack", Function::Unpack],
+    ["set_record", Function::SetRecord],
+    ["resplit", Function::Resplit],
+    ["sleep", Function::Sleep],
+    ["gettimeofday", Function::TimeOfDay],
+    ["monotonic", Function::Monotonic],
+    ["strftime", Function::Strftime],
+    ["parsedate", Function::ParseDate],
     ["system", Function::System],
-    ["exit", Function::Exit]
+    ["cmdstatus", Function::CmdStatus],
+    ["usepty", Function::UsePty],
+    ["hll_add", Function::HllAdd],
+    ["hll_count", Function::HllCount],
+    ["sample", Function::Sample],
+    ["asorti", Function::Asorti],
+    ["copy", Function::CopyMap],
+    ["topk", Function::TopK],
+    ["exit", Function::Exit],
+    ["assert", Function::Assert],
+    ["dump_state", Function::DumpState]
 );
 
 impl<'a> TryFrom<&'a str> for Function {
@@ -258,6 +344,49 @@ impl<'a> IsSprintf for &'a str {
 }
 
 impl Function {
+    // Whether this function is a gawk or frawk extension, rather than one available in
+    // one-true-awk. These are treated as ordinary identifiers in traditional mode.
+    pub(crate) fn is_extension(&self) -> bool {
+        use Function::*;
+        matches!(
+            self,
+            GenSub
+                | BLength
+                | MatchAll
+                | Chr
+                | Ord
+                | HexToInt
+                | IntFunc(_)
+                | JoinCols
+                | JoinCSV
+                | JoinTSV
+                | JoinJSON
+                | EscapeCSV
+                | EscapeTSV
+                | EscapeJSON
+                | GetXml
+                | Unpack
+                | SetRecord
+                | Resplit
+                | Sleep
+                | TimeOfDay
+                | Monotonic
+                | Strftime
+                | ParseDate
+                | CmdStatus
+                | UsePty
+                | HllAdd
+                | HllCount
+                | Sample
+                | Asorti
+                | CopyMap
+                | TopK
+                | Assert
+                | DumpState
+                | Function::FloatFunc(self::FloatFunc::Log2 | self::FloatFunc::Log10)
+        )
+    }
+
     // feedback allows for certain functions to propagate type information back to their arguments.
     pub(crate) fn feedback(&self, args: &[NodeIx], res: NodeIx, ctx: &mut types::TypeContext) {
         use types::{BaseTy, Constraint, TVar::*};
@@ -275,6 +404,41 @@ impl Function {
                 );
                 ctx.nw.add_dep(arg1, args[1], Constraint::Flows(()));
             }
+            Function::Asorti => {
+                let is_map = ctx.constant(Some(Map {
+                    key: None,
+                    val: None,
+                }));
+                ctx.nw.add_dep(is_map, args[0], Constraint::Flows(()));
+                let dest = ctx.constant(
+                    Map {
+                        key: BaseTy::Int,
+                        val: BaseTy::Str,
+                    }
+                    .abs(),
+                );
+                ctx.nw.add_dep(dest, args[1], Constraint::Flows(()));
+            }
+            Function::MatchArr => {
+                let dest = ctx.constant(
+                    Map {
+                        key: BaseTy::Str,
+                        val: BaseTy::Str,
+                    }
+                    .abs(),
+                );
+                ctx.nw.add_dep(dest, args[2], Constraint::Flows(()));
+            }
+            Function::MatchAll => {
+                let dest = ctx.constant(
+                    Map {
+                        key: BaseTy::Int,
+                        val: BaseTy::Str,
+                    }
+                    .abs(),
+                );
+                ctx.nw.add_dep(dest, args[2], Constraint::Flows(()));
+            }
             Function::Clear => {
                 let is_map = ctx.constant(Some(Map {
                     key: None,
@@ -282,6 +446,16 @@ impl Function {
                 }));
                 ctx.nw.add_dep(is_map, args[0], Constraint::Flows(()));
             }
+            Function::CopyMap => {
+                let is_map = ctx.constant(Some(Map {
+                    key: None,
+                    val: None,
+                }));
+                ctx.nw.add_dep(is_map, args[1], Constraint::Flows(()));
+                // Both maps must end up with the same type.
+                ctx.nw.add_dep(args[1], args[0], Constraint::Flows(()));
+                ctx.nw.add_dep(args[0], args[1], Constraint::Flows(()));
+            }
             Function::Contains => {
                 let arr = args[0];
                 let query = args[1];
@@ -292,6 +466,21 @@ impl Function {
                 let query = args[1];
                 ctx.nw.add_dep(query, arr, Constraint::KeyIn(()));
             }
+            Function::Sample => {
+                let int_keys = ctx.constant(Some(Map {
+                    key: Some(BaseTy::Int),
+                    val: None,
+                }));
+                ctx.nw.add_dep(int_keys, args[0], Constraint::Flows(()));
+                ctx.nw.add_dep(args[2], args[0], Constraint::ValIn(()));
+            }
+            Function::TopK => {
+                let heap = ctx.constant(Some(Map {
+                    key: Some(BaseTy::Str),
+                    val: Some(BaseTy::Float),
+                }));
+                ctx.nw.add_dep(heap, args[0], Constraint::Flows(()));
+            }
             Function::IncMap => {
                 let arr = args[0];
                 let k = args[1];
@@ -312,6 +501,7 @@ impl Function {
     pub(crate) fn type_sig(
         &self,
         incoming: &[compile::Ty],
+        ieee_div: bool,
         // TODO make the return type optional?
     ) -> Result<(SmallVec<compile::Ty>, compile::Ty)> {
         use {
@@ -345,7 +535,7 @@ impl Function {
             },
             Unop(Column) => (smallvec![Int], Str),
             Binop(Concat) => (smallvec![Str; 2], Str),
-            SubstrIndex | Binop(IsMatch) => (smallvec![Str; 2], Int),
+            SubstrIndex | StrNumCmp | HllAdd | Binop(IsMatch) => (smallvec![Str; 2], Int),
             // Not doesn't unconditionally convert to integers before negating it. Nonempty strings
             // are considered "truthy". Floating point numbers are converted beforehand:
             //    !5 == !1 == 0
@@ -366,6 +556,8 @@ impl Function {
                 },
                 Int,
             ),
+            // With --ieee-div, `x % 0` is nan, so the result must be a float.
+            Binop(Mod) if ieee_div => (smallvec![Float; 2], Float),
             Binop(Plus) | Binop(Minus) | Binop(Mod) | Binop(Mult) => {
                 arith_sig(incoming[0], incoming[1])
             }
@@ -380,6 +572,44 @@ impl Function {
                 MapStrInt | MapStrStr | MapStrFloat => (smallvec![incoming[0], Str], Int),
                 _ => return err!("invalid input spec fo Delete: {:?}", incoming),
             },
+            Asorti => {
+                if incoming[0].is_array() {
+                    (smallvec![incoming[0], MapIntStr, Str], Int)
+                } else {
+                    return err!("invalid input spec for asorti: {:?}", incoming);
+                }
+            }
+            MatchArr => {
+                if incoming[2] == MapStrStr {
+                    (smallvec![Str, Str, MapStrStr], Int)
+                } else {
+                    return err!("invalid input spec for match: {:?}", incoming);
+                }
+            }
+            MatchAll => {
+                if incoming[2] == MapIntStr {
+                    (smallvec![Str, Str, MapIntStr, Int], Int)
+                } else {
+                    return err!("invalid input spec for matchall: {:?}", incoming);
+                }
+            }
+            CopyMap => {
+                if incoming[0].is_array() && incoming[0] == incoming[1] {
+                    (smallvec![incoming[0], incoming[1]], Int)
+                } else {
+                    return err!("invalid input spec for copy: {:?}", incoming);
+                }
+            }
+            Sample => match incoming[0] {
+                MapIntInt | MapIntStr | MapIntFloat => {
+                    (smallvec![incoming[0], Int, incoming[0].val()?], Int)
+                }
+                _ => return err!("invalid input spec for sample: {:?}", incoming),
+            },
+            TopK => match incoming[0] {
+                MapStrFloat => (smallvec![MapStrFloat, Int, Str, Float], Float),
+                _ => return err!("invalid input spec for topk: {:?}", incoming),
+            },
             IncMap => {
                 let map = incoming[0];
                 if !map.is_array() {
@@ -403,9 +633,14 @@ impl Function {
                 }
             }
             Srand => (smallvec![Int], Int),
-            System | HexToInt => (smallvec![Str], Int),
+            System | CmdStatus | UsePty | HllCount | HexToInt => (smallvec![Str], Int),
             ReseedRng => (smallvec![], Int),
-            Rand => (smallvec![], Float),
+            Rand | TimeOfDay | Monotonic => (smallvec![], Float),
+            Strftime => (smallvec![Str, Float, Str], Str),
+            ParseDate => (smallvec![Str], Float),
+            Chr => (smallvec![Int], Str),
+            Ord => (smallvec![Str], Int),
+            Sleep => (smallvec![Float], Int),
             ToInt => {
                 let inc = incoming[0];
                 match inc {
@@ -422,27 +657,59 @@ impl Function {
             ReadErrCmd | ReadErr => (smallvec![Str], Int),
             UpdateUsedFields | NextFile | ReadLineStdinFused => (smallvec![], Int),
             NextlineStdin => (smallvec![], Str),
-            ReadErrStdin => (smallvec![], Int),
+            ReadErrStdin | FileBoundary => (smallvec![], Int),
             // irrelevant return type
             Setcol => (smallvec![Int, Str], Int),
-            Length => (smallvec![incoming[0]], Int),
+            Length => match incoming[0] {
+                // Numbers are measured by the length of their string form.
+                Int | Float => (smallvec![Str], Int),
+                inc => (smallvec![inc], Int),
+            },
+            BLength => (smallvec![Str], Int),
             Close => (smallvec![Str], Str),
             Sub | GSub => (smallvec![Str, Str, Str], Int),
+            SubLine | GSubLine => (smallvec![Str, Str], Int),
             GenSub => (smallvec![Str, Str, Str, Str], Str),
-            ToUpper | ToLower | EscapeCSV | EscapeTSV => (smallvec![Str], Str),
-            Substr => (smallvec![Str, Int, Int], Str),
+            ToUpper | ToLower | EscapeCSV | EscapeTSV | EscapeJSON | EscapeMsgPack => {
+                (smallvec![Str], Str)
+            }
+            Substr => (smallvec![Str, Float, Float], Str),
             Match => (smallvec![Str, Str], Int),
+            GetXml => (smallvec![Str, Str], Str),
+            Unpack | SetRecord | Resplit => (smallvec![Str], Int),
             Exit => (smallvec![Int], Null),
+            Assert => (smallvec![Int, Str, Str], Null),
+            // Maps are passed through as-is; everything else is printed as a string.
+            DumpState => (
+                incoming
+                    .iter()
+                    .map(|ty| if ty.is_array() { *ty } else { Str })
+                    .collect(),
+                Null,
+            ),
+            DumpRequested | CheckpointDue => (smallvec![], Int),
+            Checkpoint => (
+                incoming
+                    .iter()
+                    .map(|ty| if ty.is_array() { *ty } else { Str })
+                    .collect(),
+                Null,
+            ),
+            // Variables are restored in place, so they keep their types.
+            Restore => (incoming.iter().cloned().collect(), Null),
+            SetLine => (smallvec![Int], Null),
             // Split's second input can be a map of either type
             Split => {
                 if let MapIntStr | MapStrStr = incoming[1] {
-                    (smallvec![Str, incoming[1], Str], Int)
+                    (smallvec![Str, incoming[1], Str, Int], Int)
                 } else {
                     return err!("invalid input spec for split: {:?}", incoming);
                 }
             }
             JoinCols => (smallvec![Int, Int, Str], Str),
-            JoinCSV | JoinTSV => (smallvec![Int, Int], Str),
+            JoinCSV | JoinTSV | JoinJSON | JsonKey | JoinMsgPack | MsgPackKey => {
+                (smallvec![Int, Int], Str)
+            }
             SetFI => (smallvec![Int, Int], Int),
         })
     }
@@ -450,21 +717,27 @@ impl Function {
     pub(crate) fn arity(&self) -> Option<usize> {
         use Function::*;
         Some(match self {
+            DumpState | Checkpoint | Restore => return None,
             FloatFunc(ff) => ff.arity(),
             IntFunc(bw) => bw.arity(),
-            UpdateUsedFields | Rand | ReseedRng | ReadErrStdin | NextlineStdin | NextFile
-            | ReadLineStdinFused => 0,
-            Exit | ToUpper | ToLower | Clear | Srand | System | HexToInt | ToInt | EscapeCSV
-            | EscapeTSV | Close | Length | ReadErr | ReadErrCmd | Nextline | NextlineCmd
-            | Unop(_) => 1,
-            SetFI | SubstrIndex | Match | Setcol | Binop(_) => 2,
-            JoinCSV | JoinTSV | Delete | Contains => 2,
-            IncMap | JoinCols | Substr | Sub | GSub | Split => 3,
-            GenSub => 4,
+            UpdateUsedFields | Rand | ReseedRng | TimeOfDay | Monotonic | ReadErrStdin
+            | FileBoundary | NextlineStdin | NextFile | ReadLineStdinFused | DumpRequested
+            | CheckpointDue => 0,
+            Exit | SetLine | ToUpper | ToLower | Clear | Srand | System | HexToInt | ToInt
+            | EscapeCSV | EscapeTSV | EscapeJSON | EscapeMsgPack | Close | Length | BLength
+            | ReadErr | ReadErrCmd | Nextline | NextlineCmd | Unpack | SetRecord | Resplit
+            | Sleep | CmdStatus | UsePty | HllCount | ParseDate | Chr | Ord | Unop(_) => 1,
+            SetFI | SubstrIndex | StrNumCmp | HllAdd | Match | GetXml | Setcol | SubLine
+            | GSubLine | Binop(_) => 2,
+            JoinCSV | JoinTSV | JoinJSON | JsonKey | JoinMsgPack | MsgPackKey | Delete
+            | Contains | CopyMap => 2,
+            IncMap | JoinCols | Substr | Sub | GSub | Sample | Asorti | Strftime | Assert
+            | MatchArr => 3,
+            GenSub | Split | MatchAll | TopK => 4,
         })
     }
 
-    pub(crate) fn step(&self, args: &[types::State]) -> Result<types::State> {
+    pub(crate) fn step(&self, args: &[types::State], ieee_div: bool) -> Result<types::State> {
         use {
             ast::{Binop::*, Unop::*},
             types::{BaseTy, TVar::*},
@@ -473,9 +746,8 @@ impl Function {
         fn step_arith(x: &types::State, y: &types::State) -> types::State {
             use BaseTy::*;
             match (x, y) {
-                (Some(Scalar(Some(Str | Float))), _) | (_, Some(Scalar(Some(Str | Float)))) => {
-                    Scalar(Float).abs()
-                }
+                (Some(Scalar(Some(Str | Regex | Float))), _)
+                | (_, Some(Scalar(Some(Str | Regex | Float)))) => Scalar(Float).abs(),
                 (_, _) => Scalar(Int).abs(),
             }
         }
@@ -483,26 +755,34 @@ impl Function {
             IntFunc(bw) => Ok(bw.ret_state()),
             FloatFunc(ff) => Ok(ff.ret_state()),
             Unop(Neg) | Unop(Pos) => match &args[0] {
-                Some(Scalar(Some(BaseTy::Str))) | Some(Scalar(Some(BaseTy::Float))) => {
+                Some(Scalar(Some(BaseTy::Str | BaseTy::Regex | BaseTy::Float))) => {
                     Ok(Scalar(BaseTy::Float).abs())
                 }
                 x => Ok(*x),
             },
+            Binop(Mod) if ieee_div => Ok(Scalar(BaseTy::Float).abs()),
             Binop(Plus) | Binop(Minus) | Binop(Mod) | Binop(Mult) => {
                 Ok(step_arith(&args[0], &args[1]))
             }
-            Rand | Binop(Div) | Binop(Pow) => Ok(Scalar(BaseTy::Float).abs()),
+            Rand | TimeOfDay | Monotonic | ParseDate | TopK | Binop(Div) | Binop(Pow) => {
+                Ok(Scalar(BaseTy::Float).abs())
+            }
             Setcol => Ok(Scalar(BaseTy::Null).abs()),
             Clear | SubstrIndex | Srand | ReseedRng | Unop(Not) | Binop(IsMatch) | Binop(LT)
-            | Binop(GT) | Binop(LTE) | Binop(GTE) | Binop(EQ) | Length | Split | ReadErr
-            | ReadErrCmd | ReadErrStdin | Contains | Delete | Match | Sub | GSub | ToInt
-            | System | HexToInt => Ok(Scalar(BaseTy::Int).abs()),
-            ToUpper | ToLower | JoinCSV | JoinTSV | JoinCols | EscapeCSV | EscapeTSV | Substr
-            | Unop(Column) | Binop(Concat) | Nextline | NextlineCmd | NextlineStdin | GenSub => {
-                Ok(Scalar(BaseTy::Str).abs())
+            | Binop(GT) | Binop(LTE) | Binop(GTE) | Binop(EQ) | Length | BLength | Split
+            | ReadErr | ReadErrCmd | ReadErrStdin | FileBoundary | Contains | Delete | Match
+            | MatchArr | Sub | GSub | SubLine | GSubLine | ToInt | System | CmdStatus | UsePty
+            | HllAdd | HllCount | HexToInt | DumpRequested | CheckpointDue | Unpack | SetRecord
+            | Resplit | Sleep | Sample | Asorti | MatchAll | Ord | CopyMap | StrNumCmp => {
+                Ok(Scalar(BaseTy::Int).abs())
             }
+            ToUpper | ToLower | JoinCSV | JoinTSV | JoinJSON | JoinCols | EscapeCSV | EscapeTSV
+            | EscapeJSON | JsonKey | EscapeMsgPack | JoinMsgPack | MsgPackKey | Substr
+            | Unop(Column) | Binop(Concat) | Nextline | NextlineCmd | NextlineStdin | GenSub
+            | GetXml | Strftime | Chr => Ok(Scalar(BaseTy::Str).abs()),
             IncMap => Ok(step_arith(&types::val_of(&args[0])?, &args[2])),
-            Exit | SetFI | UpdateUsedFields | NextFile | ReadLineStdinFused | Close => Ok(None),
+            Exit | Assert | DumpState | Checkpoint | Restore | SetLine | SetFI
+            | UpdateUsedFields | NextFile | ReadLineStdinFused | Close => Ok(None),
         }
     }
 }
@@ -526,14 +806,16 @@ pub(crate) enum Variable {
     FNR = 11,
     PID = 12,
     FI = 13,
+    ARGIND = 14,
+    RT = 15,
 }
 
 impl From<Variable> for compile::Ty {
     fn from(v: Variable) -> compile::Ty {
         use Variable::*;
         match v {
-            FS | OFS | ORS | RS | FILENAME => compile::Ty::Str,
-            PID | ARGC | NF | NR | FNR | RSTART | RLENGTH => compile::Ty::Int,
+            FS | OFS | ORS | RS | RT | FILENAME => compile::Ty::Str,
+            PID | ARGC | ARGIND | NF | NR | FNR | RSTART | RLENGTH => compile::Ty::Int,
             ARGV => compile::Ty::MapIntStr,
             FI => compile::Ty::MapStrInt,
         }
@@ -542,6 +824,7 @@ impl From<Variable> for compile::Ty {
 
 pub(crate) struct Variables<'a> {
     pub argc: Int,
+    pub argind: Int,
     pub argv: IntMap<Str<'a>>,
     pub fs: Str<'a>,
     pub ofs: Str<'a>,
@@ -551,6 +834,7 @@ pub(crate) struct Variables<'a> {
     pub nr: Int,
     pub fnr: Int,
     pub filename: Str<'a>,
+    pub rt: Str<'a>,
     pub rstart: Int,
     pub rlength: Int,
     pub pid: Int,
@@ -561,6 +845,7 @@ impl<'a> Default for Variables<'a> {
     fn default() -> Variables<'a> {
         Variables {
             argc: 0,
+            argind: 0,
             argv: Default::default(),
             fs: " ".into(),
             ofs: " ".into(),
@@ -570,6 +855,7 @@ impl<'a> Default for Variables<'a> {
             fnr: 0,
             nf: 0,
             filename: Default::default(),
+            rt: Default::default(),
             rstart: 0,
             pid: 0,
             rlength: -1,
@@ -582,13 +868,16 @@ impl<'a> Variables<'a> {
         use Variable::*;
         Ok(match var {
             ARGC => self.argc,
+            ARGIND => self.argind,
             NF => self.nf,
             NR => self.nr,
             FNR => self.fnr,
             RSTART => self.rstart,
             RLENGTH => self.rlength,
             PID => self.pid,
-            FI | ORS | OFS | FS | RS | FILENAME | ARGV => return err!("var {} not an int", var),
+            FI | ORS | OFS | FS | RS | RT | FILENAME | ARGV => {
+                return err!("var {} not an int", var)
+            }
         })
     }
 
@@ -596,13 +885,16 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             ARGC => self.argc = i,
+            ARGIND => self.argind = i,
             NF => self.nf = i,
             NR => self.nr = i,
             FNR => self.fnr = i,
             RSTART => self.rstart = i,
             RLENGTH => self.rlength = i,
             PID => self.pid = i,
-            FI | ORS | OFS | FS | RS | FILENAME | ARGV => return err!("var {} not an int", var),
+            FI | ORS | OFS | FS | RS | RT | FILENAME | ARGV => {
+                return err!("var {} not an int", var)
+            }
         }
         Ok(())
     }
@@ -615,7 +907,8 @@ impl<'a> Variables<'a> {
             ORS => self.ors.clone(),
             RS => self.rs.clone(),
             FILENAME => self.filename.clone(),
-            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH => {
+            RT => self.rt.clone(),
+            FI | PID | ARGC | ARGIND | ARGV | NF | NR | FNR | RSTART | RLENGTH => {
                 return err!("var {} not a string", var)
             }
         })
@@ -629,7 +922,8 @@ impl<'a> Variables<'a> {
             ORS => self.ors = s,
             RS => self.rs = s,
             FILENAME => self.filename = s,
-            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH => {
+            RT => self.rt = s,
+            FI | PID | ARGC | ARGIND | ARGV | NF | NR | FNR | RSTART | RLENGTH => {
                 return err!("var {} not a string", var)
             }
         };
@@ -640,7 +934,8 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             ARGV => Ok(self.argv.clone()),
-            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH => {
+            FI | PID | ORS | OFS | ARGC | ARGIND | NF | NR | FNR | FS | RS | RT | FILENAME
+            | RSTART | RLENGTH => {
                 err!("var {} is not an int-keyed map", var)
             }
         }
@@ -653,7 +948,8 @@ impl<'a> Variables<'a> {
                 self.argv = m;
                 Ok(())
             }
-            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH => {
+            FI | PID | ORS | OFS | ARGC | ARGIND | NF | NR | FNR | FS | RS | RT | FILENAME
+            | RSTART | RLENGTH => {
                 err!("var {} is not an int-keyed map", var)
             }
         }
@@ -662,8 +958,8 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             FI => Ok(self.fi.clone()),
-            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART
-            | RLENGTH => {
+            ARGV | PID | ORS | OFS | ARGC | ARGIND | NF | NR | FNR | FS | RS | RT | FILENAME
+            | RSTART | RLENGTH => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
@@ -676,8 +972,8 @@ impl<'a> Variables<'a> {
                 self.fi = m;
                 Ok(())
             }
-            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART
-            | RLENGTH => {
+            ARGV | PID | ORS | OFS | ARGC | ARGIND | NF | NR | FNR | FS | RS | RT | FILENAME
+            | RSTART | RLENGTH => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
@@ -685,10 +981,17 @@ impl<'a> Variables<'a> {
 }
 
 impl Variable {
+    // Whether this variable is a gawk or frawk extension, rather than one available in
+    // one-true-awk. These are treated as ordinary variables in traditional mode.
+    pub(crate) fn is_extension(&self) -> bool {
+        use Variable::*;
+        matches!(self, PID | FI | ARGIND | RT)
+    }
+
     pub(crate) fn ty(&self) -> types::TVar<types::BaseTy> {
         use Variable::*;
         match self {
-            PID | ARGC | NF | FNR | NR | RSTART | RLENGTH => {
+            PID | ARGC | ARGIND | NF | FNR | NR | RSTART | RLENGTH => {
                 types::TVar::Scalar(types::BaseTy::Int)
             }
             // NB: For full compliance, this may have to be Str -> Str
@@ -721,7 +1024,7 @@ impl Variable {
                 key: types::BaseTy::Str,
                 val: types::BaseTy::Int,
             },
-            ORS | OFS | FS | RS | FILENAME => types::TVar::Scalar(types::BaseTy::Str),
+            ORS | OFS | FS | RS | RT | FILENAME => types::TVar::Scalar(types::BaseTy::Str),
         }
     }
 }
@@ -755,6 +1058,8 @@ impl TryFrom<usize> for Variable {
             11 => Ok(FNR),
             12 => Ok(PID),
             13 => Ok(FI),
+            14 => Ok(ARGIND),
+            15 => Ok(RT),
             _ => Err(()),
         }
     }
@@ -775,5 +1080,7 @@ static_map!(
     ["RSTART", Variable::RSTART],
     ["RLENGTH", Variable::RLENGTH],
     ["PID", Variable::PID],
-    ["FI", Variable::FI]
+    ["FI", Variable::FI],
+    ["ARGIND", Variable::ARGIND],
+    ["RT", Variable::RT]
 );
@@ -3,10 +3,10 @@ use std::marker::PhantomData;
 use std::sync::Arc;
 
 use crate::builtins::{Bitwise, FloatFunc, Variable};
-use crate::common::{FileSpec, NumTy};
+use crate::common::{FileSpec, NumTy, Result, Stage};
 use crate::compile::{self, Ty};
 use crate::interp::{index, index_mut, Storage};
-use crate::runtime::{self, Float, Int, Str, UniqueStr};
+use crate::runtime::{self, Float, Int, Str, TypedRegex, UniqueStr};
 
 use regex::bytes::Regex;
 
@@ -68,6 +68,7 @@ pub(crate) enum Instr<'a> {
     StoreConstStr(Reg<Str<'a>>, UniqueStr<'a>),
     StoreConstInt(Reg<Int>, Int),
     StoreConstFloat(Reg<Float>, Float),
+    StoreConstRegex(Reg<TypedRegex>, TypedRegex),
 
     // Conversions
     IntToStr(Reg<Str<'a>>, Reg<Int>),
@@ -77,6 +78,7 @@ pub(crate) enum Instr<'a> {
     FloatToInt(Reg<Int>, Reg<Float>),
     IntToFloat(Reg<Float>, Reg<Int>),
     StrToFloat(Reg<Float>, Reg<Str<'a>>),
+    RegexToStr(Reg<Str<'a>>, Reg<TypedRegex>),
 
     // Assignment
     // Note, for now we do not support iterator moves. Iterators own their own copy of an array,
@@ -110,6 +112,9 @@ pub(crate) enum Instr<'a> {
         /* new seed */ Reg<Int>,
     ),
     ReseedRng(/* previous seed */ Reg<Int>),
+    Sleep(Reg<Int>, /* seconds */ Reg<Float>),
+    TimeOfDay(Reg<Float>),
+    Monotonic(Reg<Float>),
 
     // String processing
     Concat(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
@@ -118,6 +123,9 @@ pub(crate) enum Instr<'a> {
     IsMatchConst(Reg<Int>, Reg<Str<'a>>, Arc<Regex>),
     Match(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     MatchConst(Reg<Int>, Reg<Str<'a>>, Arc<Regex>),
+    // Matches against a regex value, which carries its own compiled pattern.
+    IsMatchRegex(Reg<Int>, Reg<Str<'a>>, Reg<TypedRegex>),
+    MatchRegex(Reg<Int>, Reg<Str<'a>>, Reg<TypedRegex>),
     // index(s, t) returns index of substring t in s, 0 if it does not appear.
     SubstrIndex(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     LenStr(Reg<Int>, Reg<Str<'a>>),
@@ -133,6 +141,17 @@ pub(crate) enum Instr<'a> {
         /*for*/ Reg<Str<'a>>,
         /*in*/ Reg<Str<'a>>,
     ),
+    // sub and gsub on $0, which substitute into the current record directly.
+    SubLine(
+        Reg<Int>,
+        /*pat*/ Reg<Str<'a>>,
+        /*for*/ Reg<Str<'a>>,
+    ),
+    GSubLine(
+        Reg<Int>,
+        /*pat*/ Reg<Str<'a>>,
+        /*for*/ Reg<Str<'a>>,
+    ),
     GenSubDynamic(
         Reg<Str<'a>>,
         /*pat*/ Reg<Str<'a>>,
@@ -142,7 +161,9 @@ pub(crate) enum Instr<'a> {
     ),
     EscapeCSV(Reg<Str<'a>>, Reg<Str<'a>>),
     EscapeTSV(Reg<Str<'a>>, Reg<Str<'a>>),
-    Substr(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>, Reg<Int>),
+    EscapeJSON(Reg<Str<'a>>, Reg<Str<'a>>),
+    EscapeMsgPack(Reg<Str<'a>>, Reg<Str<'a>>),
+    Substr(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Float>, Reg<Float>),
 
     // Comparison
     LTFloat(Reg<Int>, Reg<Float>, Reg<Float>),
@@ -160,9 +181,18 @@ pub(crate) enum Instr<'a> {
     EQFloat(Reg<Int>, Reg<Float>, Reg<Float>),
     EQInt(Reg<Int>, Reg<Int>, Reg<Int>),
     EQStr(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    // Compares two strings as numbers if both look like numbers, yielding -1, 0 or 1.
+    StrNumCmp(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
 
     // Columns
     SetColumn(Reg<Int> /* dst column */, Reg<Str<'a>>),
+    // Decode $0 according to a format string, replacing the fields with the result.
+    Unpack(
+        Reg<Int>,     /* number of fields */
+        Reg<Str<'a>>, /* format */
+    ),
+    // Split $0 again using the given separator, for the rest of the current record.
+    Resplit(Reg<Int> /* number of fields */, Reg<Str<'a>>),
     GetColumn(Reg<Str<'a>>, Reg<Int>),
     JoinCSV(
         Reg<Str<'a>>, /* dst */
@@ -174,6 +204,27 @@ pub(crate) enum Instr<'a> {
         Reg<Int>,     /* start col */
         Reg<Int>,     /* end col */
     ),
+    JoinJSON(
+        Reg<Str<'a>>, /* dst */
+        Reg<Int>,     /* start col */
+        Reg<Int>,     /* end col */
+    ),
+    // The key for a value in a JSON output record; see runtime::json::json_key.
+    JsonKey(
+        Reg<Str<'a>>, /* dst */
+        Reg<Int>,     /* column, or 0 */
+        Reg<Int>,     /* position */
+    ),
+    JoinMsgPack(
+        Reg<Str<'a>>, /* dst */
+        Reg<Int>,     /* start col */
+        Reg<Int>,     /* end col */
+    ),
+    MsgPackKey(
+        Reg<Str<'a>>, /* dst */
+        Reg<Int>,     /* column, or 0 */
+        Reg<Int>,     /* position */
+    ),
     JoinColumns(
         Reg<Str<'a>>, /* dst */
         Reg<Int>,     /* start col */
@@ -182,11 +233,26 @@ pub(crate) enum Instr<'a> {
     ),
     ToUpperAscii(Reg<Str<'a>>, Reg<Str<'a>>),
     ToLowerAscii(Reg<Str<'a>>, Reg<Str<'a>>),
+    GetXml(
+        Reg<Str<'a>>, /* dst */
+        Reg<Str<'a>>, /* markup */
+        Reg<Str<'a>>, /* path */
+    ),
+    Strftime(
+        Reg<Str<'a>>, /* dst */
+        Reg<Str<'a>>, /* format */
+        Reg<Float>,   /* timestamp */
+        Reg<Str<'a>>, /* time zone */
+    ),
+    ParseDate(Reg<Float>, Reg<Str<'a>>),
+    Chr(Reg<Str<'a>>, Reg<Int>),
+    Ord(Reg<Int>, Reg<Str<'a>>),
 
     // File reading.
     ReadErr(Reg<Int>, Reg<Str<'a>>, /*is_file=*/ bool),
     NextLine(Reg<Str<'a>>, Reg<Str<'a>>, /*is_file=*/ bool),
     ReadErrStdin(Reg<Int>),
+    FileBoundary(Reg<Int>),
     NextLineStdin(Reg<Str<'a>>),
     // Fetches line directly into $0.
     NextLineStdinFused(),
@@ -197,18 +263,35 @@ pub(crate) enum Instr<'a> {
     // keep this as a separate instruction to make static analysis easier.
     SetFI(Reg<Int>, Reg<Int>),
 
-    // Split
+    // Split, with the maximum number of fields as the last argument
     SplitInt(
         Reg<Int>,
         Reg<Str<'a>>,
         Reg<runtime::IntMap<Str<'a>>>,
         Reg<Str<'a>>,
+        Reg<Int>,
     ),
     SplitStr(
         Reg<Int>,
         Reg<Str<'a>>,
         Reg<runtime::StrMap<'a, Str<'a>>>,
         Reg<Str<'a>>,
+        Reg<Int>,
+    ),
+    // match(s, pat, arr)
+    MatchArr(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<Str<'a>>,
+        Reg<runtime::StrMap<'a, Str<'a>>>,
+    ),
+    // matchall(s, pat, arr, group)
+    MatchAll(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<Str<'a>>,
+        Reg<runtime::IntMap<Str<'a>>>,
+        Reg<Int>,
     ),
     Sprintf {
         dst: Reg<Str<'a>>,
@@ -226,7 +309,54 @@ pub(crate) enum Instr<'a> {
     },
     Close(Reg<Str<'a>>),
     RunCmd(Reg<Int>, Reg<Str<'a>>),
+    // Close the command, wait for it to exit and store its exit status.
+    CmdStatus(Reg<Int>, Reg<Str<'a>>),
+    // Read from future instances of the command through a pseudo-terminal.
+    UsePty(Reg<Int>, Reg<Str<'a>>),
+    // Add a value to the named HyperLogLog sketch, storing whether the sketch changed.
+    HllAdd(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    // Estimate the number of distinct values added to the named sketch.
+    HllCount(Reg<Int>, Reg<Str<'a>>),
     Exit(Reg<Int>),
+    // Abort execution if the condition is zero, reporting the message and source location.
+    Assert(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    // Abort execution if a float used as an array index is not an integer. Only emitted with
+    // --strict.
+    CheckIndex(Reg<Float>),
+    // Abort execution if the divisor of `/` (or of `%`, if the flag is set) is zero. Not emitted
+    // with --ieee-div.
+    CheckDivisor(Reg<Float>, /*is_mod=*/ bool),
+    CheckDivisorInt(Reg<Int>),
+    // Print a variable's name and value to stderr, for dump_state().
+    DumpScalar(Reg<Str<'a>>, Reg<Str<'a>>),
+    DumpMap {
+        name: Reg<Str<'a>>,
+        map_ty: Ty,
+        map: NumTy,
+    },
+    // Set to 1 (and reset) if a state dump was requested by sending the process SIGUSR1.
+    DumpRequested(Reg<Int>),
+    // Save a variable in the next checkpoint, for checkpoint().
+    CheckpointScalar(Reg<Str<'a>>, Reg<Str<'a>>),
+    CheckpointMap {
+        name: Reg<Str<'a>>,
+        map_ty: Ty,
+        map: NumTy,
+    },
+    // Write the variables saved since the last checkpoint to the --checkpoint file.
+    WriteCheckpoint(),
+    // Set to 1 (and reset) if --checkpoint-interval has passed since the last checkpoint was due.
+    CheckpointDue(Reg<Int>),
+    // Set a variable to its value in the --resume checkpoint, if it has one. Maps have the saved
+    // entries added to them.
+    RestoreVar {
+        name: Reg<Str<'a>>,
+        ty: Ty,
+        var: NumTy,
+    },
+    // Marks the source line of the instructions that follow. Used for debug info in compiled code,
+    // and for the locations of errors reported with --strict.
+    SetLine(u32),
 
     // Map operations
     Lookup {
@@ -275,6 +405,41 @@ pub(crate) enum Instr<'a> {
         dst: NumTy,
         by: Reg<Float>,
     },
+    // Replace the contents of `to` with a copy of the contents of `from`, storing the number of
+    // entries copied.
+    CopyMap {
+        map_ty: Ty,
+        dst: Reg<Int>,
+        to: NumTy,
+        from: NumTy,
+    },
+    // Offer `val` to the reservoir sample of up to `k` values kept in `map` (which has integer
+    // keys), storing the number of values offered to `map` so far.
+    Sample {
+        map_ty: Ty,
+        dst: Reg<Int>,
+        map: NumTy,
+        k: Reg<Int>,
+        val: NumTy,
+    },
+    // Replace the contents of `dest` with the keys of `src`, sorted in the order named by `how`,
+    // storing the number of keys.
+    SortKeys {
+        map_ty: Ty,
+        dst: Reg<Int>,
+        src: NumTy,
+        dest: Reg<runtime::IntMap<Str<'a>>>,
+        how: Reg<Str<'a>>,
+    },
+    // Add a weight to a key in a map holding at most `k` keys, evicting the lightest keys as
+    // needed, and store the key's new weight.
+    TopK(
+        Reg<Float>,
+        Reg<runtime::StrMap<'a, Float>>,
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<Float>,
+    ),
     IterBegin {
         map_ty: Ty,
         dst: NumTy,
@@ -317,12 +482,45 @@ pub(crate) enum Instr<'a> {
 
     // Functions
     // TODO: we may need to push iterators as well?
-    Push(Ty, NumTy),
-    Pop(Ty, NumTy),
+    PushFrame(Arc<FrameRegs>),
+    PopFrame(Arc<FrameRegs>),
     Call(usize),
     Ret,
 }
 
+/// A group of registers that are pushed onto (or popped from) the stack with a single instruction,
+/// used to pass arguments and to save local variables across function calls. Registers of each
+/// type are pushed in order and popped in reverse order.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct FrameRegs(pub(crate) [Vec<NumTy>; compile::NUM_TYPES]);
+
+impl FrameRegs {
+    pub(crate) fn add(&mut self, reg: NumTy, ty: Ty) -> Result<()> {
+        use Ty::*;
+        match ty {
+            Null => Ok(()),
+            Int | Float | Str | Regex | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
+            | MapStrFloat | MapStrStr => {
+                self.0[ty as usize].push(reg);
+                Ok(())
+            }
+            IterInt | IterStr => err!("invalid argument type: {:?}", ty),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.iter().all(Vec::is_empty)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (NumTy, Ty)> + '_ {
+        use std::convert::TryFrom;
+        self.0.iter().enumerate().flat_map(|(ty, regs)| {
+            let ty = Ty::try_from(ty as u32).unwrap();
+            regs.iter().map(move |reg| (*reg, ty))
+        })
+    }
+}
+
 impl<T> Reg<T> {
     pub(crate) fn index(&self) -> usize {
         self.0 as usize
@@ -401,6 +599,7 @@ macro_rules! impl_get {
 impl_get!(Int, ints, Int);
 impl_get!(Str<'a>, strs, Str, 'a);
 impl_get!(Float, floats, Float);
+impl_get!(TypedRegex, regexes, Regex);
 impl_get!(runtime::IntMap<Float>, maps_int_float, MapIntFloat);
 impl_get!(runtime::IntMap<Int>, maps_int_int, MapIntInt);
 impl_get!(runtime::IntMap<Str<'a>>, maps_int_str, MapIntStr, 'a);
@@ -412,16 +611,33 @@ impl_get!(runtime::Iter<Str<'a>>, iters_str, IterStr, 'a);
 
 // Helpful for avoiding big match statements when computing basic walks of the bytecode.
 impl<'a> Instr<'a> {
+    /// Copy the string constant in a `StoreConstStr` out of the arena it was parsed into, so that
+    /// the instruction can outlive the arena.
+    #[cfg(feature = "embed")]
+    pub(crate) fn unmoor(self) -> Instr<'static> {
+        // String constants are the only part of an instruction borrowed from the arena, and are
+        // copied out of it below; registers only use the lifetime as a marker.
+        match unsafe { std::mem::transmute::<Instr<'a>, Instr<'static>>(self) } {
+            Instr::StoreConstStr(dst, s) => Instr::StoreConstStr(dst, s.unmoor()),
+            instr => instr,
+        }
+    }
+
     pub(crate) fn accum(&self, mut f: impl FnMut(NumTy, compile::Ty)) {
         use Instr::*;
         match self {
             StoreConstStr(sr, _s) => sr.accum(&mut f),
             StoreConstInt(ir, _i) => ir.accum(&mut f),
             StoreConstFloat(fr, _f) => fr.accum(&mut f),
+            StoreConstRegex(rr, _re) => rr.accum(&mut f),
             IntToStr(sr, ir) => {
                 sr.accum(&mut f);
                 ir.accum(&mut f)
             }
+            RegexToStr(sr, rr) => {
+                sr.accum(&mut f);
+                rr.accum(&mut f)
+            }
             FloatToStr(sr, fr) => {
                 sr.accum(&mut f);
                 fr.accum(&mut f);
@@ -527,6 +743,11 @@ impl<'a> Instr<'a> {
                 seed.accum(&mut f)
             }
             ReseedRng(res) => res.accum(&mut f),
+            Sleep(res, secs) => {
+                res.accum(&mut f);
+                secs.accum(&mut f)
+            }
+            TimeOfDay(res) | Monotonic(res) => res.accum(&mut f),
             StartsWithConst(res, s, _) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
@@ -550,6 +771,11 @@ impl<'a> Instr<'a> {
                 res.accum(&mut f);
                 src.accum(&mut f);
             }
+            MatchRegex(res, src, pat) | IsMatchRegex(res, src, pat) => {
+                res.accum(&mut f);
+                src.accum(&mut f);
+                pat.accum(&mut f);
+            }
             SubstrIndex(res, s, t) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
@@ -565,6 +791,11 @@ impl<'a> Instr<'a> {
                 s.accum(&mut f);
                 in_s.accum(&mut f);
             }
+            GSubLine(res, pat, s) | SubLine(res, pat, s) => {
+                res.accum(&mut f);
+                pat.accum(&mut f);
+                s.accum(&mut f);
+            }
             GenSubDynamic(res, pat, s, how, in_s) => {
                 res.accum(&mut f);
                 pat.accum(&mut f);
@@ -572,7 +803,7 @@ impl<'a> Instr<'a> {
                 how.accum(&mut f);
                 in_s.accum(&mut f);
             }
-            EscapeCSV(res, s) | EscapeTSV(res, s) => {
+            EscapeCSV(res, s) | EscapeTSV(res, s) | EscapeJSON(res, s) | EscapeMsgPack(res, s) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
             }
@@ -657,19 +888,40 @@ impl<'a> Instr<'a> {
                 l.accum(&mut f);
                 r.accum(&mut f);
             }
+            StrNumCmp(res, l, r) => {
+                res.accum(&mut f);
+                l.accum(&mut f);
+                r.accum(&mut f);
+            }
             SetColumn(dst, src) => {
                 dst.accum(&mut f);
                 src.accum(&mut f)
             }
+            Unpack(dst, fmt) => {
+                dst.accum(&mut f);
+                fmt.accum(&mut f)
+            }
+            Resplit(dst, fs) => {
+                dst.accum(&mut f);
+                fs.accum(&mut f)
+            }
             GetColumn(dst, src) => {
                 dst.accum(&mut f);
                 src.accum(&mut f)
             }
-            JoinCSV(dst, start, end) | JoinTSV(dst, start, end) => {
+            JoinCSV(dst, start, end)
+            | JoinTSV(dst, start, end)
+            | JoinJSON(dst, start, end)
+            | JoinMsgPack(dst, start, end) => {
                 dst.accum(&mut f);
                 start.accum(&mut f);
                 end.accum(&mut f);
             }
+            JsonKey(dst, col, pos) | MsgPackKey(dst, col, pos) => {
+                dst.accum(&mut f);
+                col.accum(&mut f);
+                pos.accum(&mut f);
+            }
             JoinColumns(dst, start, end, sep) => {
                 dst.accum(&mut f);
                 start.accum(&mut f);
@@ -680,17 +932,55 @@ impl<'a> Instr<'a> {
                 dst.accum(&mut f);
                 src.accum(&mut f);
             }
-            SplitInt(flds, to_split, arr, pat) => {
+            GetXml(dst, doc, path) => {
+                dst.accum(&mut f);
+                doc.accum(&mut f);
+                path.accum(&mut f);
+            }
+            Strftime(dst, fmt, ts, tz) => {
+                dst.accum(&mut f);
+                fmt.accum(&mut f);
+                ts.accum(&mut f);
+                tz.accum(&mut f);
+            }
+            ParseDate(dst, s) => {
+                dst.accum(&mut f);
+                s.accum(&mut f);
+            }
+            Chr(dst, n) => {
+                dst.accum(&mut f);
+                n.accum(&mut f);
+            }
+            Ord(dst, s) => {
+                dst.accum(&mut f);
+                s.accum(&mut f);
+            }
+            SplitInt(flds, to_split, arr, pat, limit) => {
                 flds.accum(&mut f);
                 to_split.accum(&mut f);
                 arr.accum(&mut f);
                 pat.accum(&mut f);
+                limit.accum(&mut f);
             }
-            SplitStr(flds, to_split, arr, pat) => {
+            SplitStr(flds, to_split, arr, pat, limit) => {
                 flds.accum(&mut f);
                 to_split.accum(&mut f);
                 arr.accum(&mut f);
                 pat.accum(&mut f);
+                limit.accum(&mut f);
+            }
+            MatchArr(dst, s, pat, arr) => {
+                dst.accum(&mut f);
+                s.accum(&mut f);
+                pat.accum(&mut f);
+                arr.accum(&mut f);
+            }
+            MatchAll(dst, s, pat, arr, group) => {
+                dst.accum(&mut f);
+                s.accum(&mut f);
+                pat.accum(&mut f);
+                arr.accum(&mut f);
+                group.accum(&mut f);
             }
             Sprintf { dst, fmt, args } => {
                 dst.accum(&mut f);
@@ -717,11 +1007,58 @@ impl<'a> Instr<'a> {
                 }
             }
             Close(file) => file.accum(&mut f),
-            RunCmd(dst, cmd) => {
+            RunCmd(dst, cmd) | CmdStatus(dst, cmd) | UsePty(dst, cmd) => {
                 dst.accum(&mut f);
                 cmd.accum(&mut f);
             }
+            HllAdd(dst, sketch, value) => {
+                dst.accum(&mut f);
+                sketch.accum(&mut f);
+                value.accum(&mut f);
+            }
+            TopK(dst, heap, k, key, weight) => {
+                dst.accum(&mut f);
+                heap.accum(&mut f);
+                k.accum(&mut f);
+                key.accum(&mut f);
+                weight.accum(&mut f);
+            }
+            HllCount(dst, sketch) => {
+                dst.accum(&mut f);
+                sketch.accum(&mut f);
+            }
             Exit(code) => code.accum(&mut f),
+            Assert(cond, msg, loc) => {
+                cond.accum(&mut f);
+                msg.accum(&mut f);
+                loc.accum(&mut f);
+            }
+            CheckIndex(fr) => fr.accum(&mut f),
+            CheckDivisor(fr, _) => fr.accum(&mut f),
+            CheckDivisorInt(ir) => ir.accum(&mut f),
+            DumpScalar(name, val) => {
+                name.accum(&mut f);
+                val.accum(&mut f);
+            }
+            DumpMap { name, map_ty, map } => {
+                name.accum(&mut f);
+                f(*map, *map_ty);
+            }
+            DumpRequested(dst) => dst.accum(&mut f),
+            CheckpointScalar(name, val) => {
+                name.accum(&mut f);
+                val.accum(&mut f);
+            }
+            CheckpointMap { name, map_ty, map } => {
+                name.accum(&mut f);
+                f(*map, *map_ty);
+            }
+            WriteCheckpoint() => {}
+            CheckpointDue(dst) => dst.accum(&mut f),
+            RestoreVar { name, ty, var } => {
+                name.accum(&mut f);
+                f(*var, *ty);
+            }
             Lookup {
                 map_ty,
                 dst,
@@ -792,6 +1129,40 @@ impl<'a> Instr<'a> {
                 f(*dst, map_ty.val().unwrap());
                 by.accum(&mut f);
             }
+            CopyMap {
+                map_ty,
+                dst,
+                to,
+                from,
+            } => {
+                dst.accum(&mut f);
+                f(*to, *map_ty);
+                f(*from, *map_ty);
+            }
+            Sample {
+                map_ty,
+                dst,
+                map,
+                k,
+                val,
+            } => {
+                dst.accum(&mut f);
+                f(*map, *map_ty);
+                k.accum(&mut f);
+                f(*val, map_ty.val().unwrap());
+            }
+            SortKeys {
+                map_ty,
+                dst,
+                src,
+                dest,
+                how,
+            } => {
+                dst.accum(&mut f);
+                f(*src, *map_ty);
+                dest.accum(&mut f);
+                how.accum(&mut f);
+            }
             LoadVarStr(dst, _var) => dst.accum(&mut f),
             StoreVarStr(_var, src) => src.accum(&mut f),
             LoadVarInt(dst, _var) => dst.accum(&mut f),
@@ -826,15 +1197,169 @@ impl<'a> Instr<'a> {
                 file.accum(&mut f)
             }
             ReadErrStdin(dst) => dst.accum(&mut f),
+            FileBoundary(dst) => dst.accum(&mut f),
             NextLineStdin(dst) => dst.accum(&mut f),
             JmpIf(cond, _lbl) => cond.accum(&mut f),
-            Push(ty, reg) => f(*reg, *ty),
-            Pop(ty, reg) => f(*reg, *ty),
+            PushFrame(regs) | PopFrame(regs) => {
+                for (reg, ty) in regs.iter() {
+                    f(reg, ty)
+                }
+            }
             SetFI(key, val) => {
                 key.accum(&mut f);
                 val.accum(&mut f);
             }
-            UpdateUsedFields() | NextFile() | NextLineStdinFused() | Call(_) | Jmp(_) | Ret => {}
+            UpdateUsedFields() | NextFile() | NextLineStdinFused() | SetLine(_) | Call(_)
+            | Jmp(_) | Ret => {}
+        }
+    }
+}
+
+/// Check that `instrs` is well-formed: every register is in bounds for its type, every
+/// dynamically-typed operand has a type the interpreter supports, jumps and calls land inside a
+/// function, and no function can fall off the end of its instructions.
+///
+/// The interpreter relies on these properties to skip bounds checks in release builds, so this
+/// has to run before the bytecode is executed.
+pub(crate) fn verify(
+    instrs: &[Vec<Instr>],
+    main: &Stage<usize>,
+    regs: impl Fn(Ty) -> usize,
+) -> Result<()> {
+    use Instr::*;
+    for main_fn in main.iter().cloned() {
+        if instrs.get(main_fn).map_or(true, |is| is.is_empty()) {
+            return err!(
+                "invalid bytecode: main function {} has no instructions",
+                main_fn
+            );
+        }
+    }
+    for (func, body) in instrs.iter().enumerate() {
+        for (cur, inst) in body.iter().enumerate() {
+            let mut problem = match inst {
+                Lookup { map_ty, .. }
+                | Contains { map_ty, .. }
+                | Delete { map_ty, .. }
+                | Clear { map_ty, .. }
+                | Len { map_ty, .. }
+                | Store { map_ty, .. }
+                | IncInt { map_ty, .. }
+                | IncFloat { map_ty, .. }
+                | CopyMap { map_ty, .. }
+                | Sample { map_ty, .. }
+                | SortKeys { map_ty, .. }
+                | IterBegin { map_ty, .. }
+                | DumpMap { map_ty, .. }
+                | CheckpointMap { map_ty, .. }
+                | AllocMap(map_ty, _)
+                    if !map_ty.is_array() =>
+                {
+                    Some(format!("expected a map type, found {:?}", map_ty))
+                }
+                IterHasNext { iter_ty, .. } | IterGetNext { iter_ty, .. } if !iter_ty.is_iter() => {
+                    Some(format!("expected an iterator type, found {:?}", iter_ty))
+                }
+                Mov(ty, _, _)
+                | LoadSlot { ty, .. }
+                | StoreSlot { ty, .. }
+                | RestoreVar { ty, .. }
+                    if *ty == Ty::Null || ty.is_iter() =>
+                {
+                    Some(format!("unsupported operand type {:?}", ty))
+                }
+                PushFrame(regs) | PopFrame(regs)
+                    if regs.iter().any(|(_, ty)| ty == Ty::Null || ty.is_iter()) =>
+                {
+                    Some(String::from("unsupported operand type in frame"))
+                }
+                JmpIf(_, Label(dst)) | Jmp(Label(dst)) if *dst >= body.len() => {
+                    Some(String::from("jump target is out of bounds"))
+                }
+                Call(callee) if instrs.get(*callee).map_or(true, |is| is.is_empty()) => {
+                    Some(String::from("call to a function with no instructions"))
+                }
+                _ => None,
+            };
+            if problem.is_none() {
+                // Only safe to call once the types above have been checked.
+                inst.accum(|reg, ty| {
+                    if problem.is_none() && ty != Ty::Null && reg as usize >= regs(ty) {
+                        problem = Some(format!(
+                            "register {} is out of bounds for type {:?}",
+                            reg, ty
+                        ));
+                    }
+                });
+            }
+            if let Some(problem) = problem {
+                return err!(
+                    "invalid bytecode in function {} at instruction {} ({:?}): {}",
+                    func,
+                    cur,
+                    inst,
+                    problem
+                );
+            }
+        }
+        match body.last() {
+            None | Some(Ret) | Some(Jmp(_)) => {}
+            Some(_) => {
+                return err!(
+                    "invalid bytecode: function {} does not end in a return or a jump",
+                    func
+                )
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regs(ty: Ty) -> usize {
+        if let Ty::Int = ty {
+            2
+        } else {
+            0
+        }
+    }
+
+    #[test]
+    fn verify_accepts_well_formed() {
+        let instrs = vec![vec![
+            Instr::StoreConstInt(1.into(), 1),
+            Instr::JmpIf(1.into(), Label(3)),
+            Instr::Jmp(Label(0)),
+            Instr::Ret,
+        ]];
+        assert!(verify(&instrs, &Stage::Main(0), regs).is_ok());
+    }
+
+    #[test]
+    fn verify_rejects_malformed() {
+        let bad = vec![
+            // register out of bounds
+            vec![Instr::StoreConstInt(2.into(), 1), Instr::Ret],
+            // jump out of bounds
+            vec![Instr::Jmp(Label(2)), Instr::Ret],
+            // falls off the end
+            vec![Instr::StoreConstInt(0.into(), 1)],
+            // call to a missing function
+            vec![Instr::Call(1), Instr::Ret],
+            // map operation on a scalar
+            vec![Instr::AllocMap(Ty::Int, 0), Instr::Ret],
+        ];
+        for body in bad {
+            let instrs = vec![body];
+            assert!(
+                verify(&instrs, &Stage::Main(0), regs).is_err(),
+                "{:?}",
+                instrs
+            );
         }
+        assert!(verify(&[], &Stage::Main(0), regs).is_err());
     }
 }
@@ -3,6 +3,8 @@ use crate::ast::{self, Expr, Stmt, Unop};
 use crate::builtins::{self, IsSprintf};
 use crate::common::{Either, FileSpec, Graph, NodeIx, NumTy, Result, Stage};
 use crate::dom;
+use crate::lexer::Loc;
+use crate::runtime;
 
 use hashbrown::{HashMap, HashSet};
 use petgraph::Direction;
@@ -137,6 +139,10 @@ impl Ident {
 pub enum Escaper {
     CSV,
     TSV,
+    // Print one JSON object per `print` statement; see runtime::json.
+    JSON,
+    // Print one MessagePack map per `print` statement; see runtime::msgpack.
+    MsgPack,
     Identity,
 }
 
@@ -152,6 +158,7 @@ pub(crate) enum PrimVal<'a> {
     ILit(i64),
     FLit(f64),
     StrLit(&'a [u8]),
+    RegexLit(&'a [u8]),
 }
 
 #[derive(Debug, Clone)]
@@ -279,6 +286,131 @@ fn valid_lhs<I>(e: &ast::Expr<I>) -> bool {
     matches!(e, Index(..) | Var(..) | Unop(ast::Unop::Column, _))
 }
 
+// As in POSIX awk, values read from input that look like numbers ("strnums") compare numerically
+// with one another, while other strings compare lexicographically. Fields are always strnums, as
+// are the variables assigned only fields, values read by getline, or values from -v flags and
+// assignment operands. Variables assigned anything else (or modified by sub, gsub or
+// increments, bound by a for-in loop, or used as function parameters) are not.
+struct StrNums<'c, 'a, 'b, I> {
+    // The values of -v flags and assignment operands.
+    inputs: &'c [&'a Expr<'a, 'b, I>],
+    assigned: HashSet<I>,
+    excluded: HashSet<I>,
+}
+
+impl<'c, 'a, 'b, I: Hash + Eq + Clone> StrNums<'c, 'a, 'b, I>
+where
+    builtins::Function: TryFrom<I>,
+{
+    fn is_input(&self, e: &Expr<'a, 'b, I>) -> bool {
+        match e {
+            Expr::Unop(Unop::Column, _) => true,
+            Expr::Assign(_, to) => self.is_input(to),
+            e => self.inputs.iter().any(|i| std::ptr::eq(*i, e)),
+        }
+    }
+
+    fn exclude(&mut self, e: &Expr<'a, 'b, I>) {
+        if let Expr::Var(v) = e {
+            self.excluded.insert(v.clone());
+        }
+    }
+
+    fn stmt(&mut self, s: &Stmt<'a, 'b, I>) {
+        use Stmt::*;
+        match s {
+            StartCond(_) | EndCond(_) | LastCond(_) | Break | Continue | Next | NextFile => {}
+            Expr(e) | Return(Some(e)) => self.expr(e),
+            Return(None) => {}
+            Block(stmts) => stmts.iter().for_each(|s| self.stmt(s)),
+            Print(args, out) => {
+                args.iter().for_each(|e| self.expr(e));
+                out.iter().for_each(|(e, _)| self.expr(e));
+            }
+            Printf(fmt, args, out) => {
+                self.expr(fmt);
+                args.iter().for_each(|e| self.expr(e));
+                out.iter().for_each(|(e, _)| self.expr(e));
+            }
+            If(cond, t, f) => {
+                self.expr(cond);
+                self.stmt(t);
+                f.iter().for_each(|f| self.stmt(f));
+            }
+            For(init, cond, update, body) => {
+                init.iter().chain(update.iter()).for_each(|s| self.stmt(s));
+                cond.iter().for_each(|e| self.expr(e));
+                self.stmt(body);
+            }
+            DoWhile(cond, body) | While(_, cond, body) => {
+                self.expr(cond);
+                self.stmt(body);
+            }
+            ForEach(v, arr, body) => {
+                self.excluded.insert(v.clone());
+                self.expr(arr);
+                self.stmt(body);
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &Expr<'a, 'b, I>) {
+        use ast::Expr::*;
+        match e {
+            ILit(_) | FLit(_) | StrLit(_) | PatLit(_) | RegexLit(_) | Var(_) | ReadStdin
+            | Cond(_) => {}
+            Unop(_, x) => self.expr(x),
+            Binop(_, x, y) | Index(x, y) | And(x, y) | Or(x, y) => {
+                self.expr(x);
+                self.expr(y);
+            }
+            ITE(c, t, f) => {
+                self.expr(c);
+                self.expr(t);
+                self.expr(f);
+            }
+            Call(f, args, _) => {
+                let f = match f {
+                    Either::Left(f) => builtins::Function::try_from(f.clone()).ok(),
+                    Either::Right(f) => Some(*f),
+                };
+                if let (Some(builtins::Function::Sub | builtins::Function::GSub), Some(s)) =
+                    (f, args.get(2))
+                {
+                    self.exclude(s);
+                }
+                args.iter().for_each(|e| self.expr(e));
+            }
+            Assign(x, to) => {
+                if let Var(v) = x {
+                    if self.is_input(to) {
+                        self.assigned.insert(v.clone());
+                    } else {
+                        self.excluded.insert(v.clone());
+                    }
+                }
+                self.expr(x);
+                self.expr(to);
+            }
+            AssignOp(x, _, to) => {
+                self.exclude(x);
+                self.expr(x);
+                self.expr(to);
+            }
+            Inc { x, .. } => {
+                self.exclude(x);
+                self.expr(x);
+            }
+            Getline { into, from, .. } => {
+                if let Some(Var(v)) = into {
+                    self.assigned.insert(v.clone());
+                }
+                into.iter().chain(from.iter()).for_each(|e| self.expr(e));
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct ProgramContext<'a, I> {
     shared: GlobalContext<I>,
@@ -293,6 +425,17 @@ pub(crate) struct ProgramContext<'a, I> {
     pub fold_regex_constants: bool,
     // Thread through information regarding header columns used.
     pub parse_header: bool,
+    // Follow IEEE 754 when dividing by zero (--ieee-div), rather than stopping with an error. This
+    // leaves out the checks on divisors, and makes `%` work on floats; see `runtime::arith`.
+    pub ieee_div: bool,
+    // Whether the program has BEGINFILE or ENDFILE actions, which need the main input to stop at
+    // the start and end of each file; see `ChainedReader::file_hooks`.
+    pub file_hooks: bool,
+    // Settings for running the program, which are handed to the runtime when it starts.
+    pub config: runtime::Config,
+    // Declared types of variables (keyed by the `low` field of their Ident), along with their
+    // names.
+    pub var_types: HashMap<NumTy, (I, ast::TypeAnn)>,
 }
 
 impl<'a, I> ProgramContext<'a, I> {
@@ -452,6 +595,7 @@ where
         };
         let mut func_table: HashMap<FunctionName<I>, NumTy> = Default::default();
         let mut funcs: Vec<Function<'a, I>> = Default::default();
+        let mut var_types = HashMap::default();
         for fundec in p.decs.iter() {
             if func_table
                 .insert(
@@ -463,7 +607,11 @@ where
                 return err!("duplicate function found for name {}", fundec.name);
             }
             if let Ok(bi) = builtins::Function::try_from(fundec.name.clone()) {
-                return err!("attempted redefinition of builtin function {}", bi);
+                // Functions that are not part of standard AWK may be shadowed, so that scripts
+                // defining their own copy, chr, assert, etc. keep working.
+                if !bi.is_extension() {
+                    return err!("attempted redefinition of builtin function {}", bi);
+                }
             }
             // All exit blocks simply return the designated return node. Return statements in the
             // AST will becode assignments to this variable followed by an unconditional jump to
@@ -479,9 +627,13 @@ where
             f.args = fundec
                 .args
                 .iter()
-                .map(|i| {
+                .zip(fundec.arg_types.iter())
+                .map(|(i, ty)| {
                     let name = i.clone();
                     let id = shared.fresh_local();
+                    if let Some(ty) = ty {
+                        var_types.insert(id.low, (name.clone(), *ty));
+                    }
                     f.args_map.insert(i.clone(), ix);
                     // Args are just like standard local variables --- in fact it's a major
                     // use-case for arguments in AWK.
@@ -492,8 +644,80 @@ where
                 })
                 .collect();
             f.ret = ret;
+            f.ret_type = fundec.ret_type;
             funcs.push(f);
         }
+        // Constants are folded into literals wherever they are referenced.
+        let mut consts: HashMap<I, PrimVal<'a>> = Default::default();
+        for (name, value) in p.consts.iter() {
+            if builtins::Variable::try_from(name.clone())
+                .map_or(false, |bi| !(p.traditional && bi.is_extension()))
+            {
+                return user_err!(
+                    "attempted to declare builtin variable {} as a constant",
+                    name
+                );
+            }
+            let value = match **value {
+                Expr::ILit(n) => PrimVal::ILit(n),
+                Expr::FLit(n) => PrimVal::FLit(n),
+                Expr::StrLit(s) => PrimVal::StrLit(s),
+                _ => return user_err!("value of constant {} must be a literal", name),
+            };
+            if consts.insert(name.clone(), value).is_some() {
+                return user_err!("duplicate constant found for name {}", name);
+            }
+        }
+        // The variables printed by dump_state(), in the order they first appear in the program.
+        let globals: Vec<(I, &'a [u8])> = {
+            let mut seen = HashSet::<I>::default();
+            p.prelude_vardecs
+                .iter()
+                .map(|(i, _)| i)
+                .chain(p.idents.iter())
+                .filter(|i| {
+                    builtins::Variable::try_from((*i).clone())
+                        .map_or(true, |bi| p.traditional && bi.is_extension())
+                })
+                .filter(|i| !consts.contains_key(*i))
+                .filter(|i| seen.insert((*i).clone()))
+                .map(|i| (i.clone(), arena.alloc_bytes(i.to_string().as_bytes())))
+                .collect()
+        };
+        let sort_helpers: HashMap<Vec<u8>, I> = p
+            .sort_helpers
+            .iter()
+            .map(|(cmp, helper)| (cmp.to_string().into_bytes(), helper.clone()))
+            .collect();
+        let stage = p.desugar_stage(arena);
+        let strnums: HashSet<I> = {
+            let inputs: Vec<_> = p
+                .prelude_vardecs
+                .iter()
+                .map(|(_, e)| *e)
+                .chain(p.file_assignments.iter().map(|(_, _, e)| *e))
+                .collect();
+            let mut s = StrNums {
+                inputs: &inputs[..],
+                assigned: Default::default(),
+                excluded: Default::default(),
+            };
+            for fundec in p.decs.iter() {
+                s.excluded.extend(fundec.args.iter().cloned());
+                s.stmt(fundec.body);
+            }
+            stage.iter().for_each(|stmt| s.stmt(stmt));
+            let excluded = s.excluded;
+            s.assigned
+                .into_iter()
+                .filter(|v| !excluded.contains(v))
+                .filter(|v| {
+                    builtins::Variable::try_from(v.clone())
+                        .map_or(true, |bi| p.traditional && bi.is_extension())
+                })
+                .collect()
+        };
+        let file_hooks = !(p.beginfile.is_empty() && p.endfile.is_empty());
         // Now that we have all the functions in place, it's time to fill them up and convert them
         // to SSA.
         macro_rules! fill {
@@ -506,6 +730,14 @@ where
                         f: &mut func,
                         func_table: &func_table,
                         parse_header: p.parse_header,
+                        traditional: p.traditional,
+                        asserts: p.asserts,
+                        file_hooks,
+                        globals: &globals,
+                        consts: &consts,
+                        sort_helpers: &sort_helpers,
+                        strnums: &strnums,
+                        arena,
                     }
                     .fill(s)?;
                     func_table.insert($name, offset as NumTy);
@@ -526,12 +758,20 @@ where
                 f: funcs.get_mut(f as usize).unwrap(),
                 func_table: &func_table,
                 parse_header: p.parse_header,
+                traditional: p.traditional,
+                asserts: p.asserts,
+                file_hooks,
+                globals: &globals,
+                consts: &consts,
+                sort_helpers: &sort_helpers,
+                strnums: &strnums,
+                arena,
             }
             .fill(fundec.body)?;
         }
 
         // Bind the main function
-        let main_offset = match p.desugar_stage(arena) {
+        let main_offset = match stage {
             Stage::Main(main_stmt) => {
                 Stage::Main(fill!(Some(main_stmt), FunctionName::MainLoop).unwrap())
             }
@@ -560,6 +800,26 @@ where
             }
         };
 
+        // Variables whose types are declared but that are never used have no identifier, and
+        // nothing to check.
+        let mut declared: HashSet<&I> = HashSet::default();
+        for (name, ty) in p.var_types.iter() {
+            if !declared.insert(name) {
+                return user_err!("duplicate type annotation found for {}", name);
+            }
+            if builtins::Variable::try_from(name.clone())
+                .map_or(false, |bi| !(p.traditional && bi.is_extension()))
+            {
+                return user_err!("attempted to declare the type of builtin variable {}", name);
+            }
+            if consts.contains_key(name) {
+                return user_err!("attempted to declare the type of constant {}", name);
+            }
+            if let Some(id) = shared.hm.get(name) {
+                var_types.insert(id.low, (name.clone(), *ty));
+            }
+        }
+
         Ok(ProgramContext {
             shared,
             funcs,
@@ -567,6 +827,10 @@ where
             allow_arbitrary_commands: false,
             fold_regex_constants: false,
             parse_header: p.parse_header,
+            ieee_div: false,
+            file_hooks,
+            config: Default::default(),
+            var_types,
         })
     }
 }
@@ -576,6 +840,23 @@ struct View<'a, 'b, I> {
     f: &'a mut Function<'b, I>,
     func_table: &'a HashMap<FunctionName<I>, NumTy>,
     parse_header: bool,
+    // Treat gawk and frawk extension builtins as ordinary identifiers.
+    traditional: bool,
+    // Whether calls to assert are checked; otherwise they are compiled away, along with their
+    // arguments.
+    asserts: bool,
+    // Whether the program has BEGINFILE or ENDFILE actions, which only run from the main loop.
+    file_hooks: bool,
+    // Named variables (and their names), used to fill in the arguments to dump_state().
+    globals: &'a [(I, &'b [u8])],
+    // Named constants and their values.
+    consts: &'a HashMap<I, PrimVal<'b>>,
+    // The helpers that sort with a user-defined comparison function, by the function's name.
+    sort_helpers: &'a HashMap<Vec<u8>, I>,
+    // Variables holding values read from input (see `StrNums`).
+    strnums: &'a HashSet<I>,
+    // Used to allocate string literals generated during conversion.
+    arena: &'b arena::Arena,
 }
 
 #[derive(Debug)]
@@ -631,6 +912,8 @@ pub(crate) struct Function<'a, I> {
     args_map: HashMap<I, NumTy>,
     pub args: SmallVec<Arg<I>>,
     ret: Ident,
+    // The declared return type, if any.
+    pub ret_type: Option<ast::TypeAnn>,
     pub cfg: Cfg<'a>,
 
     defsites: HashMap<Ident, HashSet<NodeIx>>,
@@ -666,6 +949,7 @@ impl<'a, I> Function<'a, I> {
             args: Default::default(),
             args_map: Default::default(),
             ret: Ident::unused(),
+            ret_type: None,
             cfg,
             defsites: Default::default(),
             orig: Default::default(),
@@ -701,6 +985,31 @@ where
     builtins::Function: TryFrom<I>,
     I: IsSprintf,
 {
+    // Whether `e` is a field, or a variable holding values read from input (see `StrNums`).
+    fn is_strnum<'c>(&self, e: &Expr<'c, 'b, I>) -> bool {
+        match e {
+            Expr::Unop(Unop::Column, _) => true,
+            Expr::Var(v) => self.strnums.contains(v) && !self.consts.contains_key(v),
+            _ => false,
+        }
+    }
+
+    // Look up `id` as a builtin variable. Extension variables are ordinary identifiers in
+    // traditional mode.
+    fn builtin_var(&self, id: &I) -> Option<builtins::Variable> {
+        builtins::Variable::try_from(id.clone())
+            .ok()
+            .filter(|bi| !(self.traditional && bi.is_extension()))
+    }
+
+    // Look up `id` as a named constant. Function parameters shadow constants.
+    fn const_val(&self, id: &I) -> Option<PrimVal<'b>> {
+        if self.f.args_map.contains_key(id) {
+            return None;
+        }
+        self.consts.get(id).cloned()
+    }
+
     fn fill<'c>(&mut self, stmt: &'c Stmt<'c, 'b, I>) -> Result<()> {
         // Add a Cfg corresponding to `stmt`
         let _next = self.convert_stmt(stmt, self.f.entry)?;
@@ -839,6 +1148,83 @@ where
                 };
                 current_open = next;
 
+                // In JSON and MessagePack output modes, `print` and `print $0` write the whole
+                // record as an object. Otherwise, each value is keyed by the name of its column (if
+                // it is one) or by its position.
+                let structured = match self.ctx.esc {
+                    Escaper::JSON => {
+                        Some((builtins::Function::JoinJSON, builtins::Function::JsonKey))
+                    }
+                    Escaper::MsgPack => Some((
+                        builtins::Function::JoinMsgPack,
+                        builtins::Function::MsgPackKey,
+                    )),
+                    _ => None,
+                };
+                if let Some((join, key_fn)) = structured {
+                    // MessagePack values delimit themselves, so records are not followed by ORS.
+                    let is_json = matches!(self.ctx.esc, Escaper::JSON);
+                    if let [] | [ast::Expr::Unop(Unop::Column, ast::Expr::ILit(0))] = vs {
+                        let record = self.to_val(
+                            PrimExpr::CallBuiltin(
+                                join,
+                                smallvec![PrimVal::ILit(1), PrimVal::ILit(i64::max_value())],
+                            ),
+                            current_open,
+                        )?;
+                        let mut print_args = smallvec![record];
+                        if is_json {
+                            print_args.push(ors);
+                        }
+                        self.add_stmt(current_open, PrimStmt::PrintAll(print_args, out.clone()))?;
+                        return Ok(current_open);
+                    }
+                    let mut print_args = SmallVec::with_capacity(vs.len() * 3 + 2);
+                    print_args.push(PrimVal::StrLit(if is_json {
+                        b"{"
+                    } else {
+                        self.arena
+                            .alloc_bytes(&runtime::msgpack::map_header(vs.len()))
+                    }));
+                    for (i, v) in vs.iter().enumerate() {
+                        if i > 0 && is_json {
+                            print_args.push(PrimVal::StrLit(b","));
+                        }
+                        let (col, value) = if let ast::Expr::Unop(Unop::Column, c) = v {
+                            let (next, col) = self.convert_val(c, current_open)?;
+                            current_open = next;
+                            let value = self.to_val(
+                                PrimExpr::CallBuiltin(
+                                    builtins::Function::Unop(Unop::Column),
+                                    smallvec![col.clone()],
+                                ),
+                                current_open,
+                            )?;
+                            (col, value)
+                        } else {
+                            let (next, value) = self.convert_val(*v, current_open)?;
+                            current_open = next;
+                            (PrimVal::ILit(0), value)
+                        };
+                        let key = self.to_val(
+                            PrimExpr::CallBuiltin(
+                                key_fn,
+                                smallvec![col, PrimVal::ILit(i as i64 + 1)],
+                            ),
+                            current_open,
+                        )?;
+                        print_args.push(key);
+                        let value = self.escape(value, current_open)?;
+                        print_args.push(value);
+                    }
+                    if is_json {
+                        print_args.push(PrimVal::StrLit(b"}"));
+                        print_args.push(ors);
+                    }
+                    self.add_stmt(current_open, PrimStmt::PrintAll(print_args, out.clone()))?;
+                    return Ok(current_open);
+                }
+
                 // Why a macro? breaking this out into methods too easily runs afoul of aliasing
                 // rules, a previous version here had to split out several local variables into
                 // parameters of outer functions; it was a lot more code.
@@ -940,6 +1326,9 @@ where
                 f
             }
             ForEach(v, array, body) => {
+                if self.const_val(v).is_some() {
+                    return user_err!("cannot assign to constant {}", v);
+                }
                 let v_id = self.get_identifier(v);
                 let (next, array_val) = self.convert_val(array, current_open)?;
                 current_open = next;
@@ -1036,12 +1425,13 @@ where
         let res_expr = match expr {
             ILit(n) => PrimExpr::Val(PrimVal::ILit(*n)),
             FLit(n) => PrimExpr::Val(PrimVal::FLit(*n)),
-            PatLit(_) if in_cond => {
+            PatLit(_) | RegexLit(_) if in_cond => {
                 use ast::{Binop::*, Expr::*, Unop::*};
                 return self
                     .convert_expr(&Binop(IsMatch, &Unop(Column, &ILit(0)), expr), current_open);
             }
             PatLit(s) | StrLit(s) => PrimExpr::Val(PrimVal::StrLit(s)),
+            RegexLit(s) => PrimExpr::Val(PrimVal::RegexLit(s)),
             Cond(cond) => {
                 let id = self.get_cond(*cond);
                 PrimExpr::Val(PrimVal::Var(id))
@@ -1057,6 +1447,23 @@ where
             Binop(op, e1, e2) => {
                 let (next, v1) = self.convert_val(e1, current_open)?;
                 let (next, v2) = self.convert_val(e2, next)?;
+                if self.is_strnum(e1) && self.is_strnum(e2) {
+                    use ast::Binop::*;
+                    if let LT | GT | LTE | GTE | EQ = op {
+                        // Compare the result of `strnum_cmp(e1, e2)` to 0.
+                        let cmp = self.to_val(
+                            PrimExpr::CallBuiltin(builtins::Function::StrNumCmp, smallvec![v1, v2]),
+                            next,
+                        )?;
+                        return Ok((
+                            next,
+                            PrimExpr::CallBuiltin(
+                                builtins::Function::Binop(*op),
+                                smallvec![cmp, PrimVal::ILit(0)],
+                            ),
+                        ));
+                    }
+                }
                 return Ok((
                     next,
                     PrimExpr::CallBuiltin(builtins::Function::Binop(*op), smallvec![v1, v2]),
@@ -1088,7 +1495,9 @@ where
                 );
             }
             Var(id) => {
-                if let Ok(bi) = builtins::Variable::try_from(id.clone()) {
+                if let Some(v) = self.const_val(id) {
+                    PrimExpr::Val(v)
+                } else if let Some(bi) = self.builtin_var(id) {
                     // To maximize compatibility with other scripts, we don't have FI in scope as a
                     // builtin if we are not parsing the header line.
                     if matches!(bi, builtins::Variable::FI) && !self.parse_header {
@@ -1107,7 +1516,7 @@ where
                 let (next, ix_v) = self.convert_val_inner(ix, next, in_cond)?;
                 return Ok((next, PrimExpr::Index(arr_v, ix_v)));
             }
-            Call(fname, args) => return self.call(current_open, fname, args),
+            Call(fname, args, loc) => return self.call(current_open, fname, args, loc.as_ref()),
             Assign(Index(arr, ix), to) => {
                 return self.do_assign_index(
                     arr,
@@ -1122,7 +1531,11 @@ where
                     // We don't need in_cond here, it would seem, because there aren't
                     // subexpressions which should be considered patterns.
                     return self.convert_expr(
-                        &Expr::Call(Either::Right(builtins::Function::IncMap), &[arr, ix, to]),
+                        &Expr::Call(
+                            Either::Right(builtins::Function::IncMap),
+                            &[arr, ix, to],
+                            None,
+                        ),
                         current_open,
                     );
                 }
@@ -1210,7 +1623,7 @@ where
                     ),
                 )?;
                 return self.convert_expr(
-                    &ast::Expr::Call(Either::Right(ReadErrStdin), &[]),
+                    &ast::Expr::Call(Either::Right(ReadErrStdin), &[], None),
                     current_open,
                 );
             }
@@ -1246,8 +1659,10 @@ where
                 let read_err = if *is_file { ReadErr } else { ReadErrCmd };
                 match (from, into) {
                     // an unadorned `getline` is uses the "fused" stdin construct, which in turn
-                    // enables some optimizations.
-                    (None /* stdin */, None /* $0 */) => {
+                    // enables some optimizations. With file hooks, that construct stops at the
+                    // start and end of each file for the main loop, so `getline` reads into $0 as
+                    // `getline var` does.
+                    (None /* stdin */, None /* $0 */) if !self.file_hooks => {
                         return self.convert_expr_inner(
                             &ast::Expr::ReadStdin,
                             current_open,
@@ -1268,12 +1683,12 @@ where
                         let (next, _) = self.convert_expr(
                             &ast::Expr::Assign(
                                 into,
-                                &ast::Expr::Call(Either::Right(next_line), &[from]),
+                                &ast::Expr::Call(Either::Right(next_line), &[from], None),
                             ),
                             current_open,
                         )?;
                         return self.convert_expr(
-                            &ast::Expr::Call(Either::Right(read_err), &[from]),
+                            &ast::Expr::Call(Either::Right(read_err), &[from], None),
                             next,
                         );
                     }
@@ -1281,12 +1696,12 @@ where
                         let (next, _) = self.convert_expr(
                             &ast::Expr::Assign(
                                 into,
-                                &ast::Expr::Call(Either::Right(NextlineStdin), &[]),
+                                &ast::Expr::Call(Either::Right(NextlineStdin), &[], None),
                             ),
                             current_open,
                         )?;
                         return self.convert_expr(
-                            &ast::Expr::Call(Either::Right(ReadErrStdin), &[]),
+                            &ast::Expr::Call(Either::Right(ReadErrStdin), &[], None),
                             next,
                         );
                     }
@@ -1322,6 +1737,39 @@ where
         }
         Ok((current_open, PrimExpr::Sprintf(fmt, res)))
     }
+    // assert(cond, msg) => assert(!!cond, msg, loc), with an empty message if none is given, where
+    // loc is the location of the call. Disabled assertions are compiled away without evaluating
+    // their arguments.
+    fn do_assert<'c>(
+        &mut self,
+        args: &'c [&'c Expr<'c, 'b, I>],
+        loc: Option<&Loc>,
+        current_open: NodeIx,
+    ) -> Result<(NodeIx, PrimExpr<'b>)> {
+        use ast::Unop::Not;
+        if args.is_empty() || args.len() > 2 {
+            return user_err!(
+                "assert takes either 1 or 2 arguments, we got {}",
+                args.len()
+            );
+        }
+        if !self.asserts {
+            return Ok((current_open, PrimExpr::Val(PrimVal::ILit(1))));
+        }
+        // Use the truthiness of the condition, rather than its numeric value.
+        let cond = &Expr::Unop(Not, &Expr::Unop(Not, args[0]));
+        let (next, cond) = self.convert_val(cond, current_open)?;
+        let (next, msg) = match args.get(1) {
+            Some(msg) => self.convert_val(msg, next)?,
+            None => (next, PrimVal::StrLit(b"")),
+        };
+        let loc = loc.map_or_else(String::new, |loc| loc.to_string());
+        let loc = PrimVal::StrLit(self.arena.alloc_bytes(loc.as_bytes()));
+        Ok((
+            next,
+            PrimExpr::CallBuiltin(builtins::Function::Assert, smallvec![cond, msg, loc]),
+        ))
+    }
     fn do_assign<'c>(
         &mut self,
         v: &'c Expr<'c, 'b, I>,
@@ -1342,9 +1790,10 @@ where
     ) -> Result<(NodeIx, PrimExpr<'b>)> {
         use ast::Expr::*;
         match v {
+            Var(i) if self.const_val(i).is_some() => user_err!("cannot assign to constant {}", i),
             Var(i) => Ok((
                 current_open,
-                if let Ok(b) = builtins::Variable::try_from(i.clone()) {
+                if let Some(b) = self.builtin_var(i) {
                     // We collect some data on which builtins are assigned to, and if they are
                     // assigned to a string literal. This is used for triggering some fast paths
                     // for field splitting; it is not as precise as the method for inferring used
@@ -1589,6 +2038,7 @@ where
         current_open: NodeIx,
         fname: &Either<I, builtins::Function>,
         args: &'c [&'c Expr<'c, 'b, I>],
+        loc: Option<&Loc>,
     ) -> Result<(NodeIx, PrimExpr<'b>)> {
         // Handle call expressions. This is pretty complicated because AWK has several rules that
         // "fill in missing arguments".
@@ -1599,7 +2049,25 @@ where
                 return self.do_sprintf(args, current_open);
             }
             Either::Left(fname) => {
-                if let Ok(bi) = builtins::Function::try_from(fname.clone()) {
+                let named = builtins::Function::try_from(fname.clone()).ok();
+                let shadowed = self.traditional
+                    || self
+                        .func_table
+                        .contains_key(&FunctionName::Named(fname.clone()));
+                match (named, args) {
+                    (Some(builtins::Function::Assert), _) if !shadowed => {
+                        return self.do_assert(args, loc, current_open);
+                    }
+                    // asorti(src, dest, "cmp") => --asorti-cmp(src, dest), if cmp is a function.
+                    (Some(builtins::Function::Asorti), [_, _, Expr::StrLit(how)]) if !shadowed => {
+                        if let Some(helper) = self.sort_helpers.get(*how) {
+                            let helper = Either::Left(helper.clone());
+                            return self.call(current_open, &helper, &args[..2], None);
+                        }
+                    }
+                    _ => {}
+                }
+                if let Some(bi) = named.filter(|bi| !(shadowed && bi.is_extension())) {
                     // Okay, there's a builtin in here.
                     Either::Right(bi)
                 } else {
@@ -1638,14 +2106,55 @@ where
             }
             // Now to "fill in the extras."
             Either::Right(mut bi) => {
-                // split(string, array) => split(string, array, FS)
-                if bi == builtins::Function::Split && args.len() == 2 {
-                    let fs = self.fresh_local();
-                    self.add_stmt(
-                        current_open,
-                        PrimStmt::AsgnVar(fs, PrimExpr::LoadBuiltin(builtins::Variable::FS)),
-                    )?;
-                    prim_args.push(PrimVal::Var(fs));
+                // split(string, array) => split(string, array, FS, 0)
+                // split(string, array, fs) => split(string, array, fs, 0)
+                if bi == builtins::Function::Split && args.len() < 4 {
+                    if args.len() == 2 {
+                        let fs = self.fresh_local();
+                        self.add_stmt(
+                            current_open,
+                            PrimStmt::AsgnVar(fs, PrimExpr::LoadBuiltin(builtins::Variable::FS)),
+                        )?;
+                        prim_args.push(PrimVal::Var(fs));
+                    }
+                    prim_args.push(PrimVal::ILit(0));
+                }
+
+                // strftime() => strftime("%a %b %e %H:%M:%S %Z %Y", gettimeofday(), "")
+                if bi == builtins::Function::Strftime && args.len() < 3 {
+                    if args.is_empty() {
+                        // Too long to be stored inline, so allocate it with the same alignment as
+                        // the other string literals.
+                        let fmt = self.arena.alloc_bytes(b"%a %b %e %H:%M:%S %Z %Y");
+                        prim_args.push(PrimVal::StrLit(fmt));
+                    }
+                    if args.len() < 2 {
+                        let now = self.fresh_local();
+                        self.add_stmt(
+                            current_open,
+                            PrimStmt::AsgnVar(
+                                now,
+                                PrimExpr::CallBuiltin(builtins::Function::TimeOfDay, smallvec![]),
+                            ),
+                        )?;
+                        prim_args.push(PrimVal::Var(now));
+                    }
+                    prim_args.push(PrimVal::StrLit(b""));
+                }
+
+                // asorti(src, dest) => asorti(src, dest, "@ind_str_asc")
+                if bi == builtins::Function::Asorti && args.len() == 2 {
+                    prim_args.push(PrimVal::StrLit(b"@ind_str_asc"));
+                }
+
+                // match(s, re, arr) => match_arr(s, re, arr)
+                if bi == builtins::Function::Match && args.len() == 3 {
+                    bi = builtins::Function::MatchArr;
+                }
+
+                // matchall(s, re, arr) => matchall(s, re, arr, 0)
+                if bi == builtins::Function::MatchAll && args.len() == 3 {
+                    prim_args.push(PrimVal::ILit(0));
                 }
 
                 // join_fields(start, end) => join_{c,t}sv (if in csv/tsv output mode)
@@ -1654,7 +2163,7 @@ where
                     match self.ctx.esc {
                         Escaper::CSV => bi = builtins::Function::JoinCSV,
                         Escaper::TSV => bi = builtins::Function::JoinTSV,
-                        Escaper::Identity => {
+                        Escaper::JSON | Escaper::MsgPack | Escaper::Identity => {
                             let fs = self.fresh_local();
                             self.add_stmt(
                                 current_open,
@@ -1668,12 +2177,38 @@ where
                     }
                 }
 
-                // substr(s, a) => substr(s, a, INT_MAX); as we always clamp the second value to
-                // the length of s.
+                // substr(s, a) => substr(s, a, inf), as substr ignores positions past the end of
+                // s.
                 if bi == builtins::Function::Substr && args.len() == 2 {
-                    // We clamp indexes anyways, we'll just put a big number in as the
-                    // rightmost index.
-                    prim_args.push(PrimVal::ILit(i64::max_value()));
+                    prim_args.push(PrimVal::FLit(f64::INFINITY));
+                }
+
+                // dump_state() => dump_state("x", x, "y", y, ...) for every variable in scope, and
+                // likewise for checkpoint() and restore().
+                if let builtins::Function::DumpState
+                | builtins::Function::Checkpoint
+                | builtins::Function::Restore = bi
+                {
+                    if !args.is_empty() {
+                        return err!("{} takes no arguments, we got {}", bi, args.len());
+                    }
+                    for (name, name_bytes) in self.globals.iter() {
+                        if self.f.args_map.contains_key(name) {
+                            // Shadowed by a function parameter.
+                            continue;
+                        }
+                        let (next, v) = self.convert_val(&Expr::Var(name.clone()), open)?;
+                        if bi == builtins::Function::Restore {
+                            // restore() assigns to the variables in place, so they cannot be
+                            // renamed into locals of main.
+                            if let Some(id) = self.ctx.hm.get(name) {
+                                self.ctx.local_globals.remove(&id.low);
+                            }
+                        }
+                        open = next;
+                        prim_args.push(PrimVal::StrLit(*name_bytes));
+                        prim_args.push(v);
+                    }
                 }
 
                 // srand() => the special "reseed rng" function
@@ -1689,14 +2224,20 @@ where
                 // expression in the appropriate locations.
                 if let builtins::Function::Sub | builtins::Function::GSub = bi {
                     let assignee = match args.len() {
-                        3 => args[2],
-                        2 => {
-                            // If a third argument isn't provided, we assume you mean $0.
-                            let e = &Expr::Unop(ast::Unop::Column, &Expr::ILit(0));
-                            let (next, v) = self.convert_val(e, open)?;
-                            open = next;
-                            prim_args.push(v);
-                            e
+                        // If a third argument isn't provided, we assume you mean $0. Substitutions
+                        // into $0 are performed on the record itself, which is only split again if
+                        // its fields are read after a substitution is made.
+                        3 if !matches!(args[2], Expr::Unop(ast::Unop::Column, Expr::ILit(0))) => {
+                            args[2]
+                        }
+                        2 | 3 => {
+                            prim_args.truncate(2);
+                            let bi = if bi == builtins::Function::Sub {
+                                builtins::Function::SubLine
+                            } else {
+                                builtins::Function::GSubLine
+                            };
+                            return Ok((open, PrimExpr::CallBuiltin(bi, prim_args)));
                         }
                         n => {
                             return err!("{} takes either 2 or 3 arguments, we got {}", bi, n);
@@ -1755,6 +2296,8 @@ where
             Escaper::CSV => builtins::Function::EscapeCSV,
 
             Escaper::TSV => builtins::Function::EscapeTSV,
+            Escaper::JSON => builtins::Function::EscapeJSON,
+            Escaper::MsgPack => builtins::Function::EscapeMsgPack,
             Escaper::Identity => return Ok(v),
         };
         let e = PrimExpr::CallBuiltin(builtin, smallvec![v]);
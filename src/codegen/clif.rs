@@ -134,6 +134,8 @@ struct Shared {
     // them across calls to `register_external_fn`.
     sig: Signature,
     handles: Handles,
+    check_numbers: bool,
+    strict: bool,
 }
 
 /// Toplevel information
@@ -172,6 +174,7 @@ fn ty_to_param(ty: compile::Ty, ptr_ty: Type) -> Result<AbiParam> {
         Float => types::F64,
         MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr | Str => ptr_ty,
         IterInt | IterStr => return err!("attempt to take iterator as parameter"),
+        Regex => return err!("attempt to take regex as parameter"),
         // We assume that null parameters are omitted from the argument list ahead of time
         // Null => return err!("attempt to take null as parameter"),
     };
@@ -191,6 +194,7 @@ fn ty_to_clifty(ty: compile::Ty, ptr_ty: Type) -> Result<Type> {
         MapIntInt | MapIntFloat | MapIntStr => Ok(ptr_ty),
         MapStrInt | MapStrFloat | MapStrStr => Ok(ptr_ty),
         IterInt | IterStr => err!("taking type of an iterator"),
+        Regex => err!("taking type of a regex"),
     }
 }
 
@@ -239,6 +243,8 @@ impl Generator {
             external_funcs: Default::default(),
             sig: cctx.func.signature.clone(),
             handles: Default::default(),
+            check_numbers: typer.check_numbers,
+            strict: typer.strict,
         };
         let mut global = Generator {
             shared,
@@ -788,6 +794,7 @@ impl<'a> View<'a> {
                 Ok(self.call_external(alloc_fn, &[]))
             }
             IterInt | IterStr => err!("iterators do not have default values"),
+            Regex => err!("regexes do not have default values"),
         }
     }
 
@@ -822,6 +829,7 @@ impl<'a> View<'a> {
                     self.builder.def_var(var, default_v);
                 }
                 IterInt | IterStr => return err!("attempting to default-initialize iterator type"),
+                Regex => return err!("attempting to default-initialize regex type"),
             }
         }
         Ok(())
@@ -849,6 +857,7 @@ impl<'a> View<'a> {
                 self.builder.declare_var(next_var, cl_ty);
             }
             IterInt | IterStr => return err!("iterators cannot be declared"),
+            Regex => return err!("regexes cannot be declared"),
         }
         Ok(next_var)
     }
@@ -867,7 +876,7 @@ impl<'a> View<'a> {
                 self.builder.declare_var(base, self.void_ptr_ty());
                 Ok(IterState { bytes, cur, base })
             }
-            Null | Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
+            Null | Int | Float | Str | Regex | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
             | MapStrFloat | MapStrStr => err!(
                 "attempting to declare iterator variable for non-iterator type: {:?}",
                 ty
@@ -885,7 +894,7 @@ impl<'a> View<'a> {
                 external!(ref_map)
             }
             Str => external!(ref_str),
-            Null | Int | Float | IterInt | IterStr => return,
+            Null | Int | Float | Regex | IterInt | IterStr => return,
         };
         self.call_external_void(func, &[v]);
     }
@@ -903,7 +912,7 @@ impl<'a> View<'a> {
             MapStrFloat => external!(drop_strfloat),
             MapStrStr => external!(drop_strstr),
             Str => external!(drop_str),
-            Null | Int | Float | IterInt | IterStr => return,
+            Null | Int | Float | Regex | IterInt | IterStr => return,
         };
         self.call_external_void(func, &[v]);
     }
@@ -1148,6 +1157,7 @@ impl<'a> View<'a> {
             }
             Null => {}
             IterInt | IterStr => return err!("attempting to store an iterator value"),
+            Regex => return err!("attempting to store a regex value"),
         }
         Ok(())
     }
@@ -1174,6 +1184,7 @@ impl<'a> View<'a> {
                 self.bind_val_inner((dst, ty), src, skip_drop)?;
             }
             IterInt | IterStr => return err!("attempting to apply `mov` to an iterator!"),
+            Regex => return err!("attempting to apply `mov` to a regex"),
             Null => {
                 let zero = self.const_int(0);
                 self.bind_val_inner((dst, ty), zero, skip_drop)?;
@@ -1311,6 +1322,7 @@ impl<'a> CodeGenerator for View<'a> {
             }
             Str => Ok(val),
             IterInt | IterStr => err!("attempting to load an iterator pointer"),
+            Regex => err!("attempting to load a regex"),
             Null => Ok(self.const_int(0)),
         }
     }
@@ -1341,6 +1353,14 @@ impl<'a> CodeGenerator for View<'a> {
         &mut self.shared.handles
     }
 
+    fn check_numbers(&self) -> bool {
+        self.shared.check_numbers
+    }
+
+    fn strict(&self) -> bool {
+        self.shared.strict
+    }
+
     fn call_void(&mut self, func: *const u8, args: &mut [Self::Val]) -> Result<()> {
         self.call_external_void(func, args);
         Ok(())
@@ -1477,7 +1497,7 @@ impl<'a> CodeGenerator for View<'a> {
             MapStrInt => (external!(len_strint), external!(iter_strint)),
             MapStrStr => (external!(len_strstr), external!(iter_strstr)),
             MapStrFloat => (external!(len_strfloat), external!(iter_strfloat)),
-            IterInt | IterStr | Int | Float | Str | Null => {
+            IterInt | IterStr | Int | Float | Str | Regex | Null => {
                 return err!("iterating over non-map type: {:?}", map.1)
             }
         };
@@ -1524,4 +1544,31 @@ impl<'a> CodeGenerator for View<'a> {
         self.ref_val(dst.1, dst_ptr);
         Ok(())
     }
+
+    fn check_divisor(&mut self, div: Ref, is_mod: bool) -> Result<()> {
+        let divv = self.get_val(div)?;
+        let is_zero = match div.1 {
+            compile::Ty::Float => {
+                let zero = self.builder.ins().f64const(0.0);
+                self.builder.ins().fcmp(FloatCC::Equal, divv, zero)
+            }
+            compile::Ty::Int => self.builder.ins().icmp_imm(IntCC::Equal, divv, 0),
+            ty => return err!("non-numeric divisor: {:?}", ty),
+        };
+        let err_block = self.builder.create_block();
+        let next_block = self.builder.create_block();
+        self.builder.set_cold_block(err_block);
+        self.builder.ins().brnz(is_zero, err_block, &[]);
+        self.builder.ins().jump(next_block, &[]);
+
+        self.builder.switch_to_block(err_block);
+        let rt = self.runtime_val();
+        let is_modv = self.const_int(is_mod as i64);
+        self.call_void(intrinsic!(division_by_zero), &mut [rt, is_modv])?;
+        self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+
+        // Code for the rest of the current basic block goes in `next_block`.
+        self.builder.switch_to_block(next_block);
+        Ok(())
+    }
 }
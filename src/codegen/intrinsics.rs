@@ -5,14 +5,15 @@
 //! underlying runtime library.
 use super::{Backend, FunctionAttr, Sig};
 use crate::runtime::{
-    self,
+    self, arith,
     printf::{printf, FormatArg},
     splitter::{
         batch::{ByteReader, CSVReader, WhitespaceOffsets},
         chunk::{ChunkProducer, OffsetChunk},
         regex::RegexSplitter,
     },
-    ChainedReader, FileRead, Float, Int, IntMap, Line, LineReader, RegexCache, Str, StrMap,
+    strict, ArgvReader, ChainedReader, FileRead, Float, Int, IntMap, Line, LineReader, RegexCache,
+    Str, StrMap,
 };
 use crate::{
     builtins::Variable,
@@ -21,6 +22,19 @@ use crate::{
     pushdown::FieldSet,
 };
 
+#[cfg(feature = "arrow")]
+use crate::runtime::splitter::arrow::ArrowReader;
+use crate::runtime::splitter::auto::AutoReader;
+#[cfg(feature = "avro")]
+use crate::runtime::splitter::avro::AvroReader;
+use crate::runtime::splitter::binary::BinaryReader;
+#[cfg(feature = "json")]
+use crate::runtime::splitter::json::JsonReader;
+use crate::runtime::splitter::socket::SocketReader;
+#[cfg(feature = "sqlite")]
+use crate::runtime::splitter::sqlite::SqliteReader;
+use crate::runtime::splitter::xml::XmlReader;
+
 use libc::c_void;
 use paste::paste;
 use rand::{self, Rng};
@@ -89,48 +103,89 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
             register_inner!($name, [ $($param),* ], [$($attr),*], wrap_ret!($ret));
             register!($($rest)*);
         };
+        ([$($attr:tt),*] $name:ident ($($param:expr),*); $($rest:tt)*) => {
+            register!([$($attr),*] $name($($param),*) -> []; $($rest)*);
+        };
         () => {};
     }
 
     register! {
-        ref_str(str_ref_ty);
-        drop_str(str_ref_ty);
-        drop_str_slow(str_ref_ty, int_ty);
-        ref_map(map_ty);
-        [ReadOnly] int_to_str(int_ty) -> str_ty;
-        [ReadOnly] float_to_str(float_ty) -> str_ty;
-        [ReadOnly] str_to_int(str_ref_ty) -> int_ty;
+        [NoUnwind] ref_str(str_ref_ty);
+        [NoUnwind] drop_str(str_ref_ty);
+        [NoUnwind] drop_str_slow(str_ref_ty, int_ty);
+        [NoUnwind] ref_map(map_ty);
+        [ReadOnly, NoUnwind] int_to_str(int_ty) -> str_ty;
+        [ReadOnly, NoUnwind] float_to_str(float_ty) -> str_ty;
+        [ReadOnly, NoUnwind] str_to_int(str_ref_ty) -> int_ty;
         [ReadOnly] hex_str_to_int(str_ref_ty) -> int_ty;
-        [ReadOnly] str_to_float(str_ref_ty) -> float_ty;
-        [ReadOnly] str_len(str_ref_ty) -> int_ty;
+        [ReadOnly, NoUnwind] str_to_float(str_ref_ty) -> float_ty;
+        strict_str_to_int(rt_ty, str_ref_ty) -> int_ty;
+        strict_str_to_float(rt_ty, str_ref_ty) -> float_ty;
+        check_index(rt_ty, float_ty);
+        [Cold] division_by_zero(rt_ty, int_ty);
+        set_line(int_ty);
+        [ReadOnly, NoUnwind] str_len(str_ref_ty) -> int_ty;
         starts_with_const(str_ref_ty, rt_ty, int_ty) -> int_ty;
-        concat(str_ref_ty, str_ref_ty) -> str_ty;
-        [ReadOnly] match_pat(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
-        [ReadOnly] match_const_pat(str_ref_ty, rt_ty) -> int_ty;
+        [NoUnwind] concat(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly, NoUnwind] match_pat(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly, NoUnwind] match_const_pat(str_ref_ty, rt_ty) -> int_ty;
         [ReadOnly] match_pat_loc(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        match_pat_captures(rt_ty, str_ref_ty, str_ref_ty, map_ty) -> int_ty;
         [ReadOnly] match_const_pat_loc(rt_ty, str_ref_ty, rt_ty) -> int_ty;
         [ReadOnly] substr_index(str_ref_ty, str_ref_ty) -> int_ty;
         subst_first(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> int_ty;
         subst_all(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        subst_first_line(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        subst_all_line(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
         gen_subst(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
-        escape_csv(str_ref_ty) -> str_ty;
+        escape_csv(rt_ty, str_ref_ty) -> str_ty;
         escape_tsv(str_ref_ty) -> str_ty;
-        substr(str_ref_ty, int_ty, int_ty) -> str_ty;
-        [ReadOnly] get_col(rt_ty, int_ty) -> str_ty;
+        escape_json(str_ref_ty) -> str_ty;
+        escape_msgpack(str_ref_ty) -> str_ty;
+        substr(str_ref_ty, float_ty, float_ty) -> str_ty;
+        [ReadOnly, NoUnwind] get_col(rt_ty, int_ty) -> str_ty;
         [ReadOnly] join_csv(rt_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] join_tsv(rt_ty, int_ty, int_ty) -> str_ty;
+        [ReadOnly] join_json(rt_ty, int_ty, int_ty) -> str_ty;
+        [ReadOnly] json_key(rt_ty, int_ty, int_ty) -> str_ty;
+        [ReadOnly] join_msgpack(rt_ty, int_ty, int_ty) -> str_ty;
+        [ReadOnly] msgpack_key(rt_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] join_cols(rt_ty, int_ty, int_ty, str_ref_ty) -> str_ty;
         [ReadOnly] to_upper_ascii(str_ref_ty) -> str_ty;
         [ReadOnly] to_lower_ascii(str_ref_ty) -> str_ty;
+        [ReadOnly] get_xml(rt_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] strftime(rt_ty, str_ref_ty, float_ty, str_ref_ty) -> str_ty;
+        parsedate(str_ref_ty) -> float_ty;
+        chr(rt_ty, int_ty) -> str_ty;
+        ord(rt_ty, str_ref_ty) -> int_ty;
         set_col(rt_ty, int_ty, str_ref_ty);
-        split_int(rt_ty, str_ref_ty, map_ty, str_ref_ty) -> int_ty;
-        split_str(rt_ty, str_ref_ty, map_ty, str_ref_ty) -> int_ty;
+        unpack(rt_ty, str_ref_ty) -> int_ty;
+        resplit(rt_ty, str_ref_ty) -> int_ty;
+        split_int(rt_ty, str_ref_ty, map_ty, str_ref_ty, int_ty) -> int_ty;
+        split_str(rt_ty, str_ref_ty, map_ty, str_ref_ty, int_ty) -> int_ty;
+        match_all(rt_ty, str_ref_ty, str_ref_ty, map_ty, int_ty) -> int_ty;
         rand_float(rt_ty) -> float_ty;
         seed_rng(rt_ty, int_ty) -> int_ty;
         reseed_rng(rt_ty) -> int_ty;
+        sleep_secs(float_ty) -> int_ty;
+        time_of_day() -> float_ty;
+        monotonic_time() -> float_ty;
 
         exit(rt_ty, int_ty);
+        assert_cond(rt_ty, int_ty, str_ref_ty, str_ref_ty);
+        dump_scalar(rt_ty, str_ref_ty, str_ref_ty);
+        dump_requested(rt_ty) -> int_ty;
+        checkpoint_scalar(rt_ty, str_ref_ty, str_ref_ty);
+        write_checkpoint(rt_ty);
+        checkpoint_due(rt_ty) -> int_ty;
+        restore_int(rt_ty, str_ref_ty, int_ty) -> int_ty;
+        restore_float(rt_ty, str_ref_ty, float_ty) -> float_ty;
+        restore_str(rt_ty, str_ref_ty, str_ref_ty) -> str_ty;
         run_system(str_ref_ty) -> int_ty;
+        cmd_status(rt_ty, str_ref_ty) -> int_ty;
+        use_pty(str_ref_ty) -> int_ty;
+        hll_add(str_ref_ty, str_ref_ty) -> int_ty;
+        hll_count(str_ref_ty) -> int_ty;
         print_all_stdout(rt_ty, pa_args_ty, int_ty);
         print_all_file(rt_ty, pa_args_ty, int_ty, str_ref_ty, int_ty);
         sprintf_impl(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty) -> str_ty;
@@ -139,6 +194,7 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         close_file(rt_ty, str_ref_ty);
         read_err(rt_ty, str_ref_ty, int_ty) -> int_ty;
         read_err_stdin(rt_ty) -> int_ty;
+        file_boundary(rt_ty) -> int_ty;
         next_line(rt_ty, str_ref_ty, int_ty) -> str_ty;
         next_line_stdin(rt_ty) -> str_ty;
         next_line_stdin_fused(rt_ty);
@@ -163,95 +219,133 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         [ReadOnly, ArgmemOnly] _frawk_exp(float_ty) -> float_ty;
         [ReadOnly, ArgmemOnly] _frawk_atan2(float_ty, float_ty) -> float_ty;
 
-        load_var_str(rt_ty, int_ty) -> str_ty;
-        store_var_str(rt_ty, int_ty, str_ref_ty);
-        [ReadOnly] load_var_int(rt_ty, int_ty) -> int_ty;
-        store_var_int(rt_ty, int_ty, int_ty);
+        [NoUnwind] load_var_str(rt_ty, int_ty) -> str_ty;
+        [NoUnwind] store_var_str(rt_ty, int_ty, str_ref_ty);
+        [ReadOnly, NoUnwind] load_var_int(rt_ty, int_ty) -> int_ty;
+        [NoUnwind] store_var_int(rt_ty, int_ty, int_ty);
         [ReadOnly] load_var_intmap(rt_ty, int_ty) -> map_ty;
         store_var_intmap(rt_ty, int_ty, map_ty);
         [ReadOnly] load_var_strmap(rt_ty, int_ty) -> map_ty;
         store_var_strmap(rt_ty, int_ty, map_ty);
 
-        [ReadOnly] str_lt(str_ref_ty, str_ref_ty) -> int_ty;
-        [ReadOnly] str_gt(str_ref_ty, str_ref_ty) -> int_ty;
-        [ReadOnly] str_lte(str_ref_ty, str_ref_ty) -> int_ty;
-        [ReadOnly] str_gte(str_ref_ty, str_ref_ty) -> int_ty;
-        [ReadOnly] str_eq(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly, NoUnwind] str_lt(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly, NoUnwind] str_gt(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly, NoUnwind] str_lte(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly, NoUnwind] str_gte(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly, NoUnwind] str_eq(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly, NoUnwind] strnum_cmp(str_ref_ty, str_ref_ty) -> int_ty;
 
         drop_iter_int(iter_int_ty, int_ty);
         drop_iter_str(iter_str_ty, int_ty);
 
         alloc_intint() -> map_ty;
         iter_intint(map_ty) -> iter_int_ty;
-        [ReadOnly] len_intint(map_ty) -> int_ty;
-        [ReadOnly] lookup_intint(map_ty, int_ty) -> int_ty;
-        [ReadOnly] contains_intint(map_ty, int_ty) -> int_ty;
-        insert_intint(map_ty, int_ty, int_ty);
+        [ReadOnly, NoUnwind] len_intint(map_ty) -> int_ty;
+        [ReadOnly, NoUnwind] lookup_intint(map_ty, int_ty) -> int_ty;
+        [ReadOnly, NoUnwind] contains_intint(map_ty, int_ty) -> int_ty;
+        [NoUnwind] insert_intint(map_ty, int_ty, int_ty);
         delete_intint(map_ty, int_ty);
         clear_intint(map_ty);
+        copy_intint(map_ty, map_ty) -> int_ty;
+        dump_intint(rt_ty, str_ref_ty, map_ty);
+        checkpoint_intint(rt_ty, str_ref_ty, map_ty);
+        restore_intint(rt_ty, str_ref_ty, map_ty);
         drop_intint(map_ty);
-        inc_int_intint(map_ty, int_ty, int_ty) -> int_ty;
-        inc_float_intint(map_ty, int_ty, float_ty) -> int_ty;
+        [NoUnwind] inc_int_intint(map_ty, int_ty, int_ty) -> int_ty;
+        [NoUnwind] inc_float_intint(map_ty, int_ty, float_ty) -> int_ty;
 
         alloc_intfloat() -> map_ty;
         iter_intfloat(map_ty) -> iter_int_ty;
-        [ReadOnly] len_intfloat(map_ty) -> int_ty;
-        [ReadOnly] lookup_intfloat(map_ty, int_ty) -> float_ty;
-        [ReadOnly] contains_intfloat(map_ty, int_ty) -> int_ty;
-        insert_intfloat(map_ty, int_ty, float_ty);
+        [ReadOnly, NoUnwind] len_intfloat(map_ty) -> int_ty;
+        [ReadOnly, NoUnwind] lookup_intfloat(map_ty, int_ty) -> float_ty;
+        [ReadOnly, NoUnwind] contains_intfloat(map_ty, int_ty) -> int_ty;
+        [NoUnwind] insert_intfloat(map_ty, int_ty, float_ty);
         delete_intfloat(map_ty, int_ty);
         clear_intfloat(map_ty);
+        copy_intfloat(map_ty, map_ty) -> int_ty;
+        dump_intfloat(rt_ty, str_ref_ty, map_ty);
+        checkpoint_intfloat(rt_ty, str_ref_ty, map_ty);
+        restore_intfloat(rt_ty, str_ref_ty, map_ty);
         drop_intfloat(map_ty);
-        inc_int_intfloat(map_ty, int_ty, int_ty) -> float_ty;
-        inc_float_intfloat(map_ty, int_ty, float_ty) -> float_ty;
+        [NoUnwind] inc_int_intfloat(map_ty, int_ty, int_ty) -> float_ty;
+        [NoUnwind] inc_float_intfloat(map_ty, int_ty, float_ty) -> float_ty;
 
         alloc_intstr() -> map_ty;
         iter_intstr(map_ty) -> iter_int_ty;
-        [ReadOnly] len_intstr(map_ty) -> int_ty;
-        [ReadOnly] lookup_intstr(map_ty, int_ty) -> str_ty;
-        [ReadOnly] contains_intstr(map_ty, int_ty) -> int_ty;
-        insert_intstr(map_ty, int_ty, str_ref_ty);
+        [ReadOnly, NoUnwind] len_intstr(map_ty) -> int_ty;
+        [ReadOnly, NoUnwind] lookup_intstr(map_ty, int_ty) -> str_ty;
+        [ReadOnly, NoUnwind] contains_intstr(map_ty, int_ty) -> int_ty;
+        [NoUnwind] insert_intstr(map_ty, int_ty, str_ref_ty);
         delete_intstr(map_ty, int_ty);
         clear_intstr(map_ty);
+        copy_intstr(map_ty, map_ty) -> int_ty;
+        dump_intstr(rt_ty, str_ref_ty, map_ty);
+        checkpoint_intstr(rt_ty, str_ref_ty, map_ty);
+        restore_intstr(rt_ty, str_ref_ty, map_ty);
         drop_intstr(map_ty);
-        inc_int_intstr(map_ty, int_ty, int_ty) -> str_ty;
-        inc_float_intstr(map_ty, int_ty, float_ty) -> str_ty;
+        [NoUnwind] inc_int_intstr(map_ty, int_ty, int_ty) -> str_ty;
+        [NoUnwind] inc_float_intstr(map_ty, int_ty, float_ty) -> str_ty;
+
+        sample_intint(rt_ty, map_ty, int_ty, int_ty) -> int_ty;
+        sample_intfloat(rt_ty, map_ty, int_ty, float_ty) -> int_ty;
+        sample_intstr(rt_ty, map_ty, int_ty, str_ref_ty) -> int_ty;
+
+        sort_keys_intint(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        sort_keys_intfloat(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        sort_keys_intstr(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        sort_keys_strint(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        sort_keys_strfloat(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        sort_keys_strstr(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+
+        topk(rt_ty, map_ty, int_ty, str_ref_ty, float_ty) -> float_ty;
 
         alloc_strint() -> map_ty;
         iter_strint(map_ty) -> iter_str_ty;
-        [ReadOnly] len_strint(map_ty) -> int_ty;
-        [ReadOnly] lookup_strint(map_ty, str_ref_ty) -> int_ty;
-        [ReadOnly] contains_strint(map_ty, str_ref_ty) -> int_ty;
-        insert_strint(map_ty, str_ref_ty, int_ty);
+        [ReadOnly, NoUnwind] len_strint(map_ty) -> int_ty;
+        [ReadOnly, NoUnwind] lookup_strint(map_ty, str_ref_ty) -> int_ty;
+        [ReadOnly, NoUnwind] contains_strint(map_ty, str_ref_ty) -> int_ty;
+        [NoUnwind] insert_strint(map_ty, str_ref_ty, int_ty);
         delete_strint(map_ty, str_ref_ty);
         clear_strint(map_ty);
+        copy_strint(map_ty, map_ty) -> int_ty;
+        dump_strint(rt_ty, str_ref_ty, map_ty);
+        checkpoint_strint(rt_ty, str_ref_ty, map_ty);
+        restore_strint(rt_ty, str_ref_ty, map_ty);
         drop_strint(map_ty);
-        inc_int_strint(map_ty, str_ref_ty, int_ty) -> int_ty;
-        inc_float_strint(map_ty, str_ref_ty, float_ty) -> int_ty;
+        [NoUnwind] inc_int_strint(map_ty, str_ref_ty, int_ty) -> int_ty;
+        [NoUnwind] inc_float_strint(map_ty, str_ref_ty, float_ty) -> int_ty;
 
         alloc_strfloat() -> map_ty;
         iter_strfloat(map_ty) -> iter_str_ty;
-        [ReadOnly] len_strfloat(map_ty) -> int_ty;
-        [ReadOnly] lookup_strfloat(map_ty, str_ref_ty) -> float_ty;
-        [ReadOnly] contains_strfloat(map_ty, str_ref_ty) -> int_ty;
-        insert_strfloat(map_ty, str_ref_ty, float_ty);
+        [ReadOnly, NoUnwind] len_strfloat(map_ty) -> int_ty;
+        [ReadOnly, NoUnwind] lookup_strfloat(map_ty, str_ref_ty) -> float_ty;
+        [ReadOnly, NoUnwind] contains_strfloat(map_ty, str_ref_ty) -> int_ty;
+        [NoUnwind] insert_strfloat(map_ty, str_ref_ty, float_ty);
         delete_strfloat(map_ty, str_ref_ty);
         clear_strfloat(map_ty);
+        copy_strfloat(map_ty, map_ty) -> int_ty;
+        dump_strfloat(rt_ty, str_ref_ty, map_ty);
+        checkpoint_strfloat(rt_ty, str_ref_ty, map_ty);
+        restore_strfloat(rt_ty, str_ref_ty, map_ty);
         drop_strfloat(map_ty);
-        inc_int_strfloat(map_ty, str_ref_ty, int_ty) -> float_ty;
-        inc_float_strfloat(map_ty, str_ref_ty, float_ty) -> float_ty;
+        [NoUnwind] inc_int_strfloat(map_ty, str_ref_ty, int_ty) -> float_ty;
+        [NoUnwind] inc_float_strfloat(map_ty, str_ref_ty, float_ty) -> float_ty;
 
         alloc_strstr() -> map_ty;
         iter_strstr(map_ty) -> iter_str_ty;
-        [ReadOnly] len_strstr(map_ty) -> int_ty;
-        [ReadOnly] lookup_strstr(map_ty, str_ref_ty) -> str_ty;
-        [ReadOnly] contains_strstr(map_ty, str_ref_ty) -> int_ty;
-        insert_strstr(map_ty, str_ref_ty, str_ref_ty);
+        [ReadOnly, NoUnwind] len_strstr(map_ty) -> int_ty;
+        [ReadOnly, NoUnwind] lookup_strstr(map_ty, str_ref_ty) -> str_ty;
+        [ReadOnly, NoUnwind] contains_strstr(map_ty, str_ref_ty) -> int_ty;
+        [NoUnwind] insert_strstr(map_ty, str_ref_ty, str_ref_ty);
         delete_strstr(map_ty, str_ref_ty);
         clear_strstr(map_ty);
+        copy_strstr(map_ty, map_ty) -> int_ty;
+        dump_strstr(rt_ty, str_ref_ty, map_ty);
+        checkpoint_strstr(rt_ty, str_ref_ty, map_ty);
+        restore_strstr(rt_ty, str_ref_ty, map_ty);
         drop_strstr(map_ty);
-        inc_int_strstr(map_ty, str_ref_ty, int_ty) -> str_ty;
-        inc_float_strstr(map_ty, str_ref_ty, float_ty) -> str_ty;
+        [NoUnwind] inc_int_strstr(map_ty, str_ref_ty, int_ty) -> str_ty;
+        [NoUnwind] inc_float_strstr(map_ty, str_ref_ty, float_ty) -> str_ty;
 
         load_slot_int(rt_ty, int_ty) -> int_ty;
         load_slot_float(rt_ty, int_ty) -> float_ty;
@@ -280,13 +374,15 @@ macro_rules! fail {
     ($rt:expr, $($es:expr),+) => {{
         #[cfg(test)]
         {
+            // Tests panic rather than exiting, so they have no use for the runtime.
+            let _ = $rt;
             eprintln_ignore!("failure in runtime {}. Halting execution", format!($($es),*));
             panic!("failure in runtime")
         }
         #[cfg(not(test))]
         {
             eprintln_ignore!("failure in runtime {}. Halting execution", format!($($es),*));
-            exit!($rt, 1)
+            exit!($rt, crate::common::EXIT_RUNTIME)
         }
     }}
 }
@@ -352,22 +448,57 @@ macro_rules! with_input {
             $crate::codegen::intrinsics::InputData::V2($p) => $body,
             $crate::codegen::intrinsics::InputData::V3($p) => $body,
             $crate::codegen::intrinsics::InputData::V4($p) => $body,
+            #[cfg(feature = "sqlite")]
+            $crate::codegen::intrinsics::InputData::V5($p) => $body,
+            #[cfg(feature = "arrow")]
+            $crate::codegen::intrinsics::InputData::V6($p) => $body,
+            #[cfg(feature = "avro")]
+            $crate::codegen::intrinsics::InputData::V7($p) => $body,
+            $crate::codegen::intrinsics::InputData::V8($p) => $body,
+            $crate::codegen::intrinsics::InputData::V9($p) => $body,
+            $crate::codegen::intrinsics::InputData::V10($p) => $body,
+            $crate::codegen::intrinsics::InputData::V11($p) => $body,
+            $crate::codegen::intrinsics::InputData::V12($p) => $body,
+            #[cfg(feature = "json")]
+            $crate::codegen::intrinsics::InputData::V13($p) => $body,
         }
     };
 }
 
 pub(crate) type InputTuple<LR> = (<LR as LineReader>::Line, FileRead<LR>);
 pub(crate) enum InputData {
-    V1(InputTuple<CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>),
-    V2(InputTuple<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>>),
-    V3(InputTuple<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>),
-    V4(InputTuple<ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>>),
+    V1(InputTuple<ArgvReader<CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>>),
+    V2(
+        InputTuple<
+            ArgvReader<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>>,
+        >,
+    ),
+    V3(InputTuple<ArgvReader<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>>),
+    V4(InputTuple<ArgvReader<ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>>>),
+    #[cfg(feature = "sqlite")]
+    V5(InputTuple<ChainedReader<SqliteReader>>),
+    #[cfg(feature = "arrow")]
+    V6(InputTuple<ChainedReader<ArrowReader>>),
+    #[cfg(feature = "avro")]
+    V7(InputTuple<ChainedReader<AvroReader>>),
+    V8(InputTuple<ChainedReader<XmlReader>>),
+    V9(InputTuple<ChainedReader<BinaryReader<Box<dyn io::Read + Send>>>>),
+    V10(
+        InputTuple<
+            ArgvReader<ChainedReader<CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>>,
+        >,
+    ),
+    V11(InputTuple<ChainedReader<SocketReader>>),
+    V12(InputTuple<ChainedReader<AutoReader>>),
+    #[cfg(feature = "json")]
+    V13(InputTuple<ChainedReader<JsonReader>>),
 }
 
 pub(crate) trait IntoRuntime {
     fn into_runtime<'a>(
         self,
         ff: impl runtime::writers::FileFactory,
+        config: runtime::Config,
         used_fields: &FieldSet,
         named_columns: Option<Vec<&[u8]>>,
         cancel_signal: CancelSignal,
@@ -380,17 +511,16 @@ macro_rules! impl_into_runtime {
             fn into_runtime<'a>(
                 self,
                 ff: impl runtime::writers::FileFactory,
+                config: runtime::Config,
                 used_fields: &FieldSet,
                 named_columns: Option<Vec<&[u8]>>,
                 cancel_signal: CancelSignal,
             ) -> Runtime<'a> {
+                let read_files = FileRead::new(self, used_fields.clone(), named_columns, &config);
                 Runtime {
                     concurrent: false,
-                    input_data: InputData::$var((
-                        Default::default(),
-                        FileRead::new(self, used_fields.clone(), named_columns),
-                    )),
-                    core: crate::interp::Core::new(ff),
+                    input_data: InputData::$var((read_files.new_line(), read_files)),
+                    core: crate::interp::Core::new(ff, config),
                     cleanup: Cleanup::null(),
                     cancel_signal,
                 }
@@ -399,23 +529,50 @@ macro_rules! impl_into_runtime {
 
         impl From<FileRead<$ty>> for InputData {
             fn from(v: FileRead<$ty>) -> InputData {
-                InputData::$var((Default::default(), v))
+                InputData::$var((v.new_line(), v))
             }
         }
     };
 }
 
-impl_into_runtime!(CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>, V1);
 impl_into_runtime!(
-    ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>,
+    ArgvReader<CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>,
+    V1
+);
+impl_into_runtime!(
+    ArgvReader<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>>,
     V2
 );
-impl_into_runtime!(ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>, V3);
-impl_into_runtime!(ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>, V4);
+impl_into_runtime!(
+    ArgvReader<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>,
+    V3
+);
+impl_into_runtime!(
+    ArgvReader<ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>>,
+    V4
+);
+#[cfg(feature = "sqlite")]
+impl_into_runtime!(ChainedReader<SqliteReader>, V5);
+#[cfg(feature = "arrow")]
+impl_into_runtime!(ChainedReader<ArrowReader>, V6);
+#[cfg(feature = "avro")]
+impl_into_runtime!(ChainedReader<AvroReader>, V7);
+impl_into_runtime!(ChainedReader<XmlReader>, V8);
+impl_into_runtime!(ChainedReader<BinaryReader<Box<dyn io::Read + Send>>>, V9);
+impl_into_runtime!(
+    ArgvReader<ChainedReader<CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>>,
+    V10
+);
+impl_into_runtime!(ChainedReader<SocketReader>, V11);
+impl_into_runtime!(ChainedReader<AutoReader>, V12);
+#[cfg(feature = "json")]
+impl_into_runtime!(ChainedReader<JsonReader>, V13);
 
 pub(crate) struct Runtime<'a> {
-    pub(crate) core: crate::interp::Core<'a>,
+    // NB: input_data is dropped before core, so that pipes from commands are closed before Core
+    // waits for those commands to exit.
     pub(crate) input_data: InputData,
+    pub(crate) core: crate::interp::Core<'a>,
     #[allow(unused)]
     pub(crate) concurrent: bool,
     pub(crate) cancel_signal: CancelSignal,
@@ -424,10 +581,19 @@ pub(crate) struct Runtime<'a> {
 
 impl<'a> Runtime<'a> {
     fn reset_file_vars(&mut self) {
-        self.core.vars.fnr = 0;
-        self.core.vars.filename = with_input!(&mut self.input_data, |(_, read_files)| {
-            read_files.stdin_filename().upcast()
+        let (filename, file_index, fnr) = with_input!(&mut self.input_data, |(_, read_files)| {
+            (
+                read_files.stdin_filename().upcast(),
+                read_files.stdin_file_index(),
+                read_files.stdin_file_records(),
+            )
         });
+        self.core.vars.fnr = fnr;
+        self.core.vars.filename = filename;
+        // ARGIND stays at 0 when there are no input files and we are reading standard input.
+        if self.core.vars.argc > 1 {
+            self.core.vars.argind = file_index;
+        }
     }
 }
 
@@ -441,6 +607,95 @@ pub(crate) unsafe extern "C" fn exit(runtime: *mut c_void, code: Int) {
     exit!(runtime, code as i32);
 }
 
+pub(crate) unsafe extern "C" fn assert_cond(
+    runtime: *mut c_void,
+    cond: Int,
+    msg: *mut U128,
+    loc: *mut U128,
+) {
+    if cond == 0 {
+        let rt = &*(runtime as *mut Runtime);
+        let msg = &*(msg as *mut Str);
+        let loc = &*(loc as *mut Str);
+        let err = runtime::assertion_failure(msg, loc, &rt.core.vars);
+        fail!(runtime, "{}", err)
+    }
+}
+
+pub(crate) unsafe extern "C" fn dump_scalar(runtime: *mut c_void, name: *mut U128, val: *mut U128) {
+    let rt = &*(runtime as *mut Runtime);
+    let name = &*(name as *mut Str);
+    let val = &*(val as *mut Str);
+    runtime::dump_scalar(name, val, rt.core.config.mem_profile);
+}
+
+pub(crate) unsafe extern "C" fn dump_requested(runtime: *mut c_void) -> Int {
+    let rt = &*(runtime as *mut Runtime);
+    runtime::take_dump_request(rt.core.config.mem_profile) as Int
+}
+
+pub(crate) unsafe extern "C" fn checkpoint_scalar(
+    runtime: *mut c_void,
+    name: *mut U128,
+    val: *mut U128,
+) {
+    let rt = &*(runtime as *mut Runtime);
+    let name = &*(name as *mut Str);
+    let val = &*(val as *mut Str);
+    rt.core.config.checkpoint.save_scalar(name, val);
+}
+
+pub(crate) unsafe extern "C" fn write_checkpoint(runtime: *mut c_void) {
+    let rt = &*(runtime as *mut Runtime);
+    if let Err(e) = rt.core.config.checkpoint.write(rt.core.vars.nr) {
+        fail!(runtime, "{}", e)
+    }
+}
+
+pub(crate) unsafe extern "C" fn checkpoint_due(runtime: *mut c_void) -> Int {
+    let rt = &*(runtime as *mut Runtime);
+    rt.core.config.checkpoint.take_checkpoint_request() as Int
+}
+
+pub(crate) unsafe extern "C" fn restore_int(
+    runtime: *mut c_void,
+    name: *mut U128,
+    cur: Int,
+) -> Int {
+    let rt = &*(runtime as *mut Runtime);
+    rt.core
+        .config
+        .checkpoint
+        .restore_scalar(&*(name as *mut Str), cur)
+}
+
+pub(crate) unsafe extern "C" fn restore_float(
+    runtime: *mut c_void,
+    name: *mut U128,
+    cur: Float,
+) -> Float {
+    let rt = &*(runtime as *mut Runtime);
+    rt.core
+        .config
+        .checkpoint
+        .restore_scalar(&*(name as *mut Str), cur)
+}
+
+pub(crate) unsafe extern "C" fn restore_str(
+    runtime: *mut c_void,
+    name: *mut U128,
+    cur: *mut U128,
+) -> U128 {
+    let rt = &*(runtime as *mut Runtime);
+    let cur = (&*(cur as *mut Str)).clone();
+    let res = rt
+        .core
+        .config
+        .checkpoint
+        .restore_scalar(&*(name as *mut Str), cur);
+    mem::transmute::<Str, U128>(res)
+}
+
 pub(crate) unsafe extern "C" fn run_system(cmd: *mut U128) -> Int {
     let s: &Str = &*(cmd as *mut Str);
     s.with_bytes(runtime::run_command)
@@ -461,6 +716,19 @@ pub(crate) unsafe extern "C" fn reseed_rng(runtime: *mut c_void) -> Int {
     runtime.core.reseed_random() as Int
 }
 
+pub(crate) unsafe extern "C" fn sleep_secs(secs: f64) -> Int {
+    runtime::sleep(secs);
+    0
+}
+
+pub(crate) unsafe extern "C" fn time_of_day() -> f64 {
+    runtime::time_of_day()
+}
+
+pub(crate) unsafe extern "C" fn monotonic_time() -> f64 {
+    runtime::monotonic()
+}
+
 pub(crate) unsafe extern "C" fn read_err(
     runtime: *mut c_void,
     file: *mut c_void,
@@ -488,21 +756,34 @@ pub(crate) unsafe extern "C" fn read_err_stdin(runtime: *mut c_void) -> Int {
         .read_err_stdin())
 }
 
+pub(crate) unsafe extern "C" fn file_boundary(runtime: *mut c_void) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    with_input!(&mut runtime.input_data, |(_, read_files)| read_files
+        .stdin_file_boundary())
+}
+
 pub(crate) unsafe extern "C" fn next_line_stdin_fused(runtime: *mut c_void) {
     let runtime = &mut *(runtime as *mut Runtime);
+    runtime.core.flush_warnings();
     let changed = try_abort!(
         runtime,
         with_input!(&mut runtime.input_data, |(line, read_files)| {
-            runtime
-                .core
-                .regexes
-                .get_line_stdin_reuse(&runtime.core.vars.rs, read_files, line)
+            runtime.core.regexes.get_line_stdin_reuse(
+                &runtime.core.vars.rs,
+                runtime.core.vars.argc,
+                &runtime.core.vars.argv,
+                read_files,
+                line,
+            )
         }),
         "unexpected error when reading line from stdin:"
     );
     if changed {
         runtime.reset_file_vars();
     }
+    runtime.core.vars.rt = with_input!(&mut runtime.input_data, |(_, read_files)| read_files
+        .stdin_terminator()
+        .upcast());
 }
 
 pub(crate) unsafe extern "C" fn next_file(runtime: *mut c_void) {
@@ -517,19 +798,25 @@ pub(crate) unsafe extern "C" fn next_file(runtime: *mut c_void) {
 
 pub(crate) unsafe extern "C" fn next_line_stdin(runtime: *mut c_void) -> U128 {
     let runtime = &mut *(runtime as *mut Runtime);
+    runtime.core.flush_warnings();
     let (changed, res) = try_abort!(
         runtime,
         with_input!(&mut runtime.input_data, |(_, read_files)| {
-            runtime
-                .core
-                .regexes
-                .get_line_stdin(&runtime.core.vars.rs, read_files)
+            runtime.core.regexes.get_line_stdin(
+                &runtime.core.vars.rs,
+                runtime.core.vars.argc,
+                &runtime.core.vars.argv,
+                read_files,
+            )
         }),
         "unexpected error when reading line from stdin:"
     );
     if changed {
         runtime.reset_file_vars();
     }
+    runtime.core.vars.rt = with_input!(&mut runtime.input_data, |(_, read_files)| read_files
+        .stdin_terminator()
+        .upcast());
     mem::transmute::<Str, U128>(res)
 }
 
@@ -572,6 +859,7 @@ pub(crate) unsafe extern "C" fn split_str(
     to_split: *mut c_void,
     into_arr: *mut c_void,
     pat: *mut c_void,
+    limit: Int,
 ) -> Int {
     let runtime = &mut *(runtime as *mut Runtime);
     let into_arr = mem::transmute::<*mut c_void, StrMap<Str>>(into_arr);
@@ -580,7 +868,7 @@ pub(crate) unsafe extern "C" fn split_str(
     if let Err(e) = runtime
         .core
         .regexes
-        .split_regex_strmap(pat, to_split, &into_arr)
+        .split_regex_strmap(pat, to_split, &into_arr, limit)
     {
         fail!(runtime, "failed to split string: {}", e);
     }
@@ -594,6 +882,7 @@ pub(crate) unsafe extern "C" fn split_int(
     to_split: *mut c_void,
     into_arr: *mut c_void,
     pat: *mut c_void,
+    limit: Int,
 ) -> Int {
     let runtime = &mut *(runtime as *mut Runtime);
     let into_arr = mem::transmute::<*mut c_void, IntMap<Str>>(into_arr);
@@ -602,7 +891,7 @@ pub(crate) unsafe extern "C" fn split_int(
     if let Err(e) = runtime
         .core
         .regexes
-        .split_regex_intmap(pat, to_split, &into_arr)
+        .split_regex_intmap(pat, to_split, &into_arr, limit)
     {
         fail!(runtime, "failed to split string: {}", e);
     }
@@ -611,6 +900,29 @@ pub(crate) unsafe extern "C" fn split_int(
     res
 }
 
+pub(crate) unsafe extern "C" fn match_all(
+    runtime: *mut c_void,
+    s: *mut c_void,
+    pat: *mut c_void,
+    into_arr: *mut c_void,
+    group: Int,
+) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let into_arr = mem::transmute::<*mut c_void, IntMap<Str>>(into_arr);
+    let s = &*(s as *mut Str);
+    let pat = &*(pat as *mut Str);
+    if let Err(e) = runtime
+        .core
+        .regexes
+        .match_all_intmap(pat, s, &into_arr, group)
+    {
+        fail!(runtime, "matchall: {}", e);
+    }
+    let res = into_arr.len() as Int;
+    mem::forget((into_arr, s, pat));
+    res
+}
+
 pub(crate) unsafe extern "C" fn get_col(runtime: *mut c_void, col: Int) -> U128 {
     let runtime = &mut *(runtime as *mut Runtime);
     let col_str = with_input!(&mut runtime.input_data, |(line, _)| {
@@ -629,8 +941,9 @@ pub(crate) unsafe extern "C" fn get_col(runtime: *mut c_void, col: Int) -> U128
 }
 
 pub(crate) unsafe extern "C" fn join_csv(runtime: *mut c_void, start: Int, end: Int) -> U128 {
-    let sep: Str<'static> = ",".into();
     let runtime = &mut *(runtime as *mut Runtime);
+    let dialect = runtime.core.config.csv_output;
+    let sep = dialect.sep_str();
     let res = try_abort!(
         runtime,
         with_input!(&mut runtime.input_data, |(line, _)| {
@@ -639,7 +952,7 @@ pub(crate) unsafe extern "C" fn join_csv(runtime: *mut c_void, start: Int, end:
                 line.nf(&runtime.core.vars.fs, &mut runtime.core.regexes),
                 "nf:"
             );
-            line.join_cols(start, end, &sep, nf, |s| runtime::escape_csv(&s))
+            line.join_cols(start, end, &sep, nf, |s| runtime::escape_csv(&s, dialect))
         }),
         "join_csv:"
     );
@@ -664,6 +977,56 @@ pub(crate) unsafe extern "C" fn join_tsv(runtime: *mut c_void, start: Int, end:
     mem::transmute::<Str, U128>(res)
 }
 
+pub(crate) unsafe extern "C" fn join_json(runtime: *mut c_void, start: Int, end: Int) -> U128 {
+    let sep: Str<'static> = ",".into();
+    let runtime = &mut *(runtime as *mut Runtime);
+    let mut pairs = runtime::json::ColumnPairs::new(&runtime.core.vars.fi, start);
+    let res = try_abort!(
+        runtime,
+        with_input!(&mut runtime.input_data, |(line, _)| {
+            let nf = try_abort!(
+                runtime,
+                line.nf(&runtime.core.vars.fs, &mut runtime.core.regexes),
+                "nf:"
+            );
+            line.join_cols(start, end, &sep, nf, |s| pairs.pair(s))
+        }),
+        "join_json:"
+    );
+    mem::transmute::<Str, U128>(runtime::json::object(res))
+}
+
+pub(crate) unsafe extern "C" fn json_key(runtime: *mut c_void, col: Int, pos: Int) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let res = runtime::json::json_key(&runtime.core.vars.fi, col, pos);
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn join_msgpack(runtime: *mut c_void, start: Int, end: Int) -> U128 {
+    let sep: Str<'static> = "".into();
+    let runtime = &mut *(runtime as *mut Runtime);
+    let mut pairs = runtime::msgpack::ColumnPairs::new(&runtime.core.vars.fi, start);
+    let res = try_abort!(
+        runtime,
+        with_input!(&mut runtime.input_data, |(line, _)| {
+            let nf = try_abort!(
+                runtime,
+                line.nf(&runtime.core.vars.fs, &mut runtime.core.regexes),
+                "nf:"
+            );
+            line.join_cols(start, end, &sep, nf, |s| pairs.pair(s))
+        }),
+        "join_msgpack:"
+    );
+    mem::transmute::<Str, U128>(pairs.map(res))
+}
+
+pub(crate) unsafe extern "C" fn msgpack_key(runtime: *mut c_void, col: Int, pos: Int) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let res = runtime::msgpack::msgpack_key(&runtime.core.vars.fi, col, pos);
+    mem::transmute::<Str, U128>(res)
+}
+
 pub(crate) unsafe extern "C" fn join_cols(
     runtime: *mut c_void,
     start: Int,
@@ -686,6 +1049,48 @@ pub(crate) unsafe extern "C" fn join_cols(
     mem::transmute::<Str, U128>(res)
 }
 
+pub(crate) unsafe extern "C" fn get_xml(
+    runtime: *mut c_void,
+    doc: *mut U128,
+    path: *mut U128,
+) -> U128 {
+    let runtime = runtime as *mut Runtime;
+    let doc = &*(doc as *mut Str);
+    let path = &*(path as *mut Str);
+    let res = try_abort!(runtime, runtime::xml::get(doc, path), "getxml:");
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn strftime(
+    runtime: *mut c_void,
+    fmt: *mut U128,
+    ts: Float,
+    tz: *mut U128,
+) -> U128 {
+    let runtime = runtime as *mut Runtime;
+    let fmt = &*(fmt as *mut Str);
+    let tz = &*(tz as *mut Str);
+    let res = fmt.with_bytes(|fmt| tz.with_bytes(|tz| runtime::strftime(fmt, ts, tz)));
+    let res = try_abort!(runtime, res, "strftime:");
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn parsedate(s: *mut U128) -> Float {
+    let s = &*(s as *mut Str);
+    s.with_bytes(runtime::parsedate)
+}
+
+pub(crate) unsafe extern "C" fn chr(runtime: *mut c_void, n: Int) -> U128 {
+    let rt = &*(runtime as *mut Runtime);
+    mem::transmute::<Str, U128>(runtime::chr(n, rt.core.config.utf8_chars))
+}
+
+pub(crate) unsafe extern "C" fn ord(runtime: *mut c_void, s: *mut U128) -> Int {
+    let rt = &*(runtime as *mut Runtime);
+    let s = &*(s as *mut Str);
+    s.with_bytes(|bs| runtime::ord(bs, rt.core.config.utf8_chars))
+}
+
 pub(crate) unsafe extern "C" fn to_upper_ascii(s: *mut U128) -> U128 {
     let res = (&*(s as *mut Str as *const Str)).to_upper_ascii();
     mem::transmute::<Str, U128>(res)
@@ -709,6 +1114,35 @@ pub(crate) unsafe extern "C" fn set_col(runtime: *mut c_void, col: Int, s: *mut
     }
 }
 
+pub(crate) unsafe extern "C" fn unpack(runtime: *mut c_void, fmt: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let fmt = &*(fmt as *mut Str);
+    let res = with_input!(&mut runtime.input_data, |(line, _)| {
+        runtime::unpack::unpack_line(
+            line,
+            fmt,
+            &runtime.core.vars.fs,
+            &runtime.core.vars.ofs,
+            &mut runtime.core.regexes,
+        )
+    });
+    try_abort!(runtime, res, "unpack:")
+}
+
+pub(crate) unsafe extern "C" fn resplit(runtime: *mut c_void, fs: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let fs = &*(fs as *mut Str);
+    let res = with_input!(&mut runtime.input_data, |(line, _)| {
+        line.resplit(
+            fs,
+            &runtime.core.vars.fs,
+            &runtime.core.vars.ofs,
+            &mut runtime.core.regexes,
+        )
+    });
+    try_abort!(runtime, res, "resplit:") as Int
+}
+
 pub(crate) unsafe extern "C" fn str_len(s: *mut c_void) -> usize {
     let s = &*(s as *mut Str);
     s.len()
@@ -771,6 +1205,25 @@ pub(crate) unsafe extern "C" fn match_pat_loc(
     res as Int
 }
 
+pub(crate) unsafe extern "C" fn match_pat_captures(
+    runtime: *mut c_void,
+    s: *mut c_void,
+    pat: *mut c_void,
+    into_arr: *mut c_void,
+) -> Int {
+    let runtime = runtime as *mut Runtime;
+    let into_arr = mem::transmute::<*mut c_void, StrMap<Str>>(into_arr);
+    let s = &*(s as *mut Str);
+    let pat = &*(pat as *mut Str);
+    let res = try_abort!(
+        runtime,
+        (*runtime).core.match_regex_captures(s, pat, &into_arr),
+        "match_pat_captures:"
+    );
+    mem::forget((into_arr, s, pat));
+    res as Int
+}
+
 pub(crate) unsafe extern "C" fn match_const_pat_loc(
     runtime: *mut c_void,
     s: *mut c_void,
@@ -807,7 +1260,7 @@ pub(crate) unsafe extern "C" fn subst_first(
         runtime
             .core
             .regexes
-            .with_regex(pat, |re| in_s.subst_first(re, s))
+            .with_regex_subst(pat, s, |re, s| in_s.subst_first(re, s))
     );
     *in_s = subbed;
     new as Int
@@ -828,12 +1281,45 @@ pub(crate) unsafe extern "C" fn subst_all(
         runtime
             .core
             .regexes
-            .with_regex(pat, |re| in_s.subst_all(re, s))
+            .with_regex_subst(pat, s, |re, s| in_s.subst_all(re, s))
     );
     *in_s = subbed;
     nsubs
 }
 
+unsafe fn subst_line(runtime: *mut c_void, pat: *mut U128, s: *mut U128, global: bool) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let pat = &*(pat as *mut Str);
+    let s = &*(s as *mut Str);
+    let res = with_input!(&mut runtime.input_data, |(line, _)| {
+        line.subst_line(
+            pat,
+            s,
+            global,
+            &runtime.core.vars.fs,
+            &runtime.core.vars.ofs,
+            &mut runtime.core.regexes,
+        )
+    });
+    try_abort!(runtime, res)
+}
+
+pub(crate) unsafe extern "C" fn subst_first_line(
+    runtime: *mut c_void,
+    pat: *mut U128,
+    s: *mut U128,
+) -> Int {
+    subst_line(runtime, pat, s, /*global=*/ false)
+}
+
+pub(crate) unsafe extern "C" fn subst_all_line(
+    runtime: *mut c_void,
+    pat: *mut U128,
+    s: *mut U128,
+) -> Int {
+    subst_line(runtime, pat, s, /*global=*/ true)
+}
+
 pub(crate) unsafe extern "C" fn gen_subst(
     runtime: *mut c_void,
     pat: *mut U128,
@@ -846,35 +1332,38 @@ pub(crate) unsafe extern "C" fn gen_subst(
     let pat = &*(pat as *mut Str);
     let how = &*(how as *mut Str);
     let in_s = &mut *(in_s as *mut Str);
+    let warnings = &runtime.core.config.warnings;
     let subbed = try_abort!(
         runtime,
         runtime
             .core
             .regexes
-            .with_regex(pat, |re| in_s.gen_subst_dynamic(re, s, how))
+            .with_regex_fallible(pat, |re| { in_s.gen_subst_dynamic(re, s, how, warnings) })
     );
     mem::transmute::<Str, U128>(subbed)
 }
 
-pub(crate) unsafe extern "C" fn escape_csv(s: *mut U128) -> U128 {
-    mem::transmute::<Str, U128>(runtime::escape_csv(&*(s as *mut Str)))
+pub(crate) unsafe extern "C" fn escape_csv(runtime: *mut c_void, s: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let dialect = runtime.core.config.csv_output;
+    mem::transmute::<Str, U128>(runtime::escape_csv(&*(s as *mut Str), dialect))
 }
 
 pub(crate) unsafe extern "C" fn escape_tsv(s: *mut U128) -> U128 {
     mem::transmute::<Str, U128>(runtime::escape_tsv(&*(s as *mut Str)))
 }
 
-pub(crate) unsafe extern "C" fn substr(base: *mut U128, l: Int, r: Int) -> U128 {
-    use std::cmp::{max, min};
+pub(crate) unsafe extern "C" fn escape_json(s: *mut U128) -> U128 {
+    mem::transmute::<Str, U128>(runtime::escape_json(&*(s as *mut Str)))
+}
+
+pub(crate) unsafe extern "C" fn escape_msgpack(s: *mut U128) -> U128 {
+    mem::transmute::<Str, U128>(runtime::msgpack::escape_msgpack(&*(s as *mut Str)))
+}
+
+pub(crate) unsafe extern "C" fn substr(base: *mut U128, l: Float, r: Float) -> U128 {
     let base = &*(base as *mut Str);
-    let len = base.len();
-    let l = max(0, l - 1);
-    if l as usize >= len {
-        mem::transmute::<Str, U128>(Str::default())
-    } else {
-        let r = min(len as Int, l.saturating_add(r)) as usize;
-        mem::transmute::<Str, U128>(base.slice(l as usize, r))
-    }
+    mem::transmute::<Str, U128>(runtime::substr(base, l, r))
 }
 
 pub(crate) unsafe extern "C" fn ref_str(s: *mut c_void) {
@@ -930,6 +1419,48 @@ pub(crate) unsafe extern "C" fn str_to_float(s: *mut c_void) -> Float {
     runtime::convert::<&Str, Float>(s)
 }
 
+pub(crate) unsafe extern "C" fn strict_str_to_int(runtime: *mut c_void, s: *mut c_void) -> Int {
+    let rt = &*(runtime as *mut Runtime);
+    let s = &*(s as *mut Str);
+    if strict::checks_numbers(&rt.core.config) {
+        if let Err(e) = strict::check_number(s, &rt.core.config, &rt.core.vars) {
+            fail!(runtime, "{}", e)
+        }
+    }
+    runtime::convert::<&Str, Int>(s)
+}
+
+pub(crate) unsafe extern "C" fn strict_str_to_float(runtime: *mut c_void, s: *mut c_void) -> Float {
+    let rt = &*(runtime as *mut Runtime);
+    let s = &*(s as *mut Str);
+    if strict::checks_numbers(&rt.core.config) {
+        if let Err(e) = strict::check_number(s, &rt.core.config, &rt.core.vars) {
+            fail!(runtime, "{}", e)
+        }
+    }
+    s.with_bytes(|bs| runtime::strtod_locale(bs, rt.core.config.decimal_comma))
+}
+
+pub(crate) unsafe extern "C" fn check_index(runtime: *mut c_void, f: Float) {
+    let rt = &*(runtime as *mut Runtime);
+    if let Err(e) = strict::check_index(f, &rt.core.vars) {
+        fail!(runtime, "{}", e)
+    }
+}
+
+pub(crate) unsafe extern "C" fn division_by_zero(runtime: *mut c_void, is_mod: Int) {
+    let rt = &*(runtime as *mut Runtime);
+    fail!(
+        runtime,
+        "{}",
+        arith::division_by_zero(is_mod != 0, &rt.core.vars)
+    )
+}
+
+pub(crate) unsafe extern "C" fn set_line(line: Int) {
+    strict::set_line(line as u32)
+}
+
 pub(crate) unsafe extern "C" fn load_var_str(rt: *mut c_void, var: usize) -> U128 {
     let runtime = &mut *(rt as *mut Runtime);
     if let Ok(var) = Variable::try_from(var) {
@@ -1036,6 +1567,12 @@ str_compare! {
     str_lt(<); str_gt(>); str_lte(<=); str_gte(>=); str_eq(==);
 }
 
+pub(crate) unsafe extern "C" fn strnum_cmp(s1: *mut U128, s2: *mut U128) -> Int {
+    let s1 = &*(s1 as *mut Str);
+    let s2 = &*(s2 as *mut Str);
+    runtime::strnum_cmp(s1, s2)
+}
+
 pub(crate) unsafe extern "C" fn drop_iter_int(iter: *mut Int, len: usize) {
     mem::drop(Box::from_raw(slice::from_raw_parts_mut(iter, len)))
 }
@@ -1127,10 +1664,12 @@ pub(crate) unsafe extern "C" fn printf_impl_file(
     let rt = rt as *mut Runtime;
     try_abort!(
         rt,
-        (*rt)
-            .core
-            .write_files
-            .printf(output_wrapped, &*(spec as *mut Str), &format_args[..],)
+        (*rt).core.write_files.printf(
+            output_wrapped,
+            &*(spec as *mut Str),
+            &format_args[..],
+            &(*rt).core.config,
+        )
     )
 }
 
@@ -1146,7 +1685,8 @@ pub(crate) unsafe extern "C" fn sprintf_impl(
     let rt = &mut *(rt as *mut _);
     let format_args = wrap_args(rt, args, tys, num_args);
     let spec = &*(spec as *mut Str);
-    if let Err(e) = spec.with_bytes(|bs| printf(&mut buf, bs, &format_args[..])) {
+    let config = &rt.core.config;
+    if let Err(e) = spec.with_bytes(|bs| printf(&mut buf, bs, &format_args[..], config)) {
         fail!(rt, "unexpected failure during sprintf: {}", e);
     }
     mem::transmute::<Str, U128>(buf.into_str())
@@ -1160,10 +1700,12 @@ pub(crate) unsafe extern "C" fn printf_impl_stdout(
     num_args: Int,
 ) {
     let format_args = wrap_args(&mut *(rt as *mut _), args, tys, num_args);
-    let res = (*(rt as *mut Runtime)).core.write_files.printf(
+    let rt = rt as *mut Runtime;
+    let res = (*rt).core.write_files.printf(
         None,
         &*(spec as *mut Str),
         &format_args[..],
+        &(*rt).core.config,
     );
     if res.is_err() {
         exit!(rt);
@@ -1177,6 +1719,30 @@ pub(crate) unsafe extern "C" fn close_file(rt: *mut c_void, file: *mut U128) {
     try_abort!(rt, rt.core.write_files.close(file));
 }
 
+pub(crate) unsafe extern "C" fn use_pty(cmd: *mut U128) -> Int {
+    let cmd: &Str = &*(cmd as *mut Str);
+    cmd.with_bytes(runtime::use_pty)
+}
+
+pub(crate) unsafe extern "C" fn hll_add(sketch: *mut U128, value: *mut U128) -> Int {
+    let sketch: &Str = &*(sketch as *mut Str);
+    let value: &Str = &*(value as *mut Str);
+    sketch.with_bytes(|sketch| value.with_bytes(|value| runtime::hll_add(sketch, value)))
+}
+
+pub(crate) unsafe extern "C" fn hll_count(sketch: *mut U128) -> Int {
+    let sketch: &Str = &*(sketch as *mut Str);
+    sketch.with_bytes(runtime::hll_count)
+}
+
+pub(crate) unsafe extern "C" fn cmd_status(rt: *mut c_void, cmd: *mut U128) -> Int {
+    let rt = &mut *(rt as *mut Runtime);
+    let cmd = &*(cmd as *mut Str);
+    with_input!(&mut rt.input_data, |(_, read_files)| read_files.close(cmd));
+    try_abort!(rt, rt.core.write_files.close_command(cmd));
+    cmd.with_bytes(runtime::command_status)
+}
+
 pub(crate) unsafe extern "C" fn _frawk_cos(f: Float) -> Float {
     f.cos()
 }
@@ -1367,6 +1933,52 @@ macro_rules! map_impl {
                 mem::forget(map);
             }
 
+            pub(crate) unsafe extern "C" fn [<copy_ $ty>](to: *mut c_void, from: *mut c_void) -> Int {
+                debug_assert!(!to.is_null() && !from.is_null());
+                let to = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(to);
+                let from = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(from);
+                to.copy_from(&from);
+                let res = to.len() as Int;
+                mem::forget((to, from));
+                res
+            }
+
+            pub(crate) unsafe extern "C" fn [<dump_ $ty>](
+                runtime: *mut c_void,
+                name: *mut U128,
+                map: *mut c_void,
+            ) {
+                debug_assert!(!map.is_null());
+                let rt = &*(runtime as *mut Runtime);
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
+                runtime::dump_map(&*(name as *mut Str), &map, rt.core.config.mem_profile);
+                mem::forget(map);
+            }
+
+            pub(crate) unsafe extern "C" fn [<checkpoint_ $ty>](
+                runtime: *mut c_void,
+                name: *mut U128,
+                map: *mut c_void,
+            ) {
+                debug_assert!(!map.is_null());
+                let rt = &*(runtime as *mut Runtime);
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
+                rt.core.config.checkpoint.save_map(&*(name as *mut Str), &map);
+                mem::forget(map);
+            }
+
+            pub(crate) unsafe extern "C" fn [<restore_ $ty>](
+                runtime: *mut c_void,
+                name: *mut U128,
+                map: *mut c_void,
+            ) {
+                debug_assert!(!map.is_null());
+                let rt = &*(runtime as *mut Runtime);
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
+                rt.core.config.checkpoint.restore_map(&*(name as *mut Str), &map);
+                mem::forget(map);
+            }
+
             pub(crate) unsafe extern "C" fn [<drop_ $ty>](map: *mut c_void) {
                 debug_assert!(!map.is_null());
                 drop_map_generic::<$k, $v>(map)
@@ -1400,6 +2012,78 @@ map_impl!(strint, Str, Int);
 map_impl!(strfloat, Str, Float);
 map_impl!(strstr, Str, Str);
 
+macro_rules! sample_impl {
+    ($ty:ident, $v:tt) => {
+        paste! {
+            pub(crate) unsafe extern "C" fn [<sample_ $ty>](
+                runtime: *mut c_void,
+                map: *mut c_void,
+                k: Int,
+                v: in_ty!($v),
+            ) -> Int {
+                debug_assert!(!map.is_null());
+                let runtime = &mut *(runtime as *mut Runtime);
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<Int, $v>>(map);
+                let val = convert_in!($v, &v);
+                let core = &mut runtime.core;
+                let res = core.samples.sample(&mut core.rng, &map, k, val);
+                mem::forget(map);
+                res
+            }
+        }
+    };
+}
+
+sample_impl!(intint, Int);
+sample_impl!(intfloat, Float);
+sample_impl!(intstr, Str);
+
+macro_rules! sort_keys_impl {
+    ($ty:ident, $k:tt, $v:tt) => {
+        paste! {
+            pub(crate) unsafe extern "C" fn [<sort_keys_ $ty>](
+                runtime: *mut c_void,
+                src: *mut c_void,
+                dest: *mut c_void,
+                how: *mut U128,
+            ) -> Int {
+                debug_assert!(!src.is_null() && !dest.is_null());
+                let src = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(src);
+                let dest = mem::transmute::<*mut c_void, runtime::IntMap<Str>>(dest);
+                let how = &*(how as *mut Str);
+                let decimal_comma = (*(runtime as *mut Runtime)).core.config.decimal_comma;
+                let res = runtime::sort_keys(&src, &dest, how, decimal_comma);
+                mem::forget(src);
+                mem::forget(dest);
+                try_abort!(runtime, res)
+            }
+        }
+    };
+}
+
+sort_keys_impl!(intint, Int, Int);
+sort_keys_impl!(intfloat, Int, Float);
+sort_keys_impl!(intstr, Int, Str);
+sort_keys_impl!(strint, Str, Int);
+sort_keys_impl!(strfloat, Str, Float);
+sort_keys_impl!(strstr, Str, Str);
+
+pub(crate) unsafe extern "C" fn topk(
+    runtime: *mut c_void,
+    heap: *mut c_void,
+    k: Int,
+    key: *mut U128,
+    weight: Float,
+) -> Float {
+    debug_assert!(!heap.is_null());
+    let runtime = &mut *(runtime as *mut Runtime);
+    let heap = mem::transmute::<*mut c_void, runtime::StrMap<Float>>(heap);
+    let key = &*(key as *mut Str);
+    let res = runtime.core.top_k.add(&heap, k, key, weight);
+    mem::forget(heap);
+    res
+}
+
 macro_rules! slot_impl {
     ($name:ident, $ty:tt) => {
         paste! {
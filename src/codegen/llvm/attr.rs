@@ -13,6 +13,9 @@ fn cstr(fa: &FunctionAttr) -> (*const libc::c_char, usize) {
     match fa {
         ReadOnly => cstr_len!("readonly"),
         ArgmemOnly => cstr_len!("argmemonly"),
+        NoUnwind => cstr_len!("nounwind"),
+        AlwaysInline => cstr_len!("alwaysinline"),
+        Cold => cstr_len!("cold"),
     }
 }
 
@@ -12,7 +12,6 @@ use libc::c_void;
 use llvm_sys::{
     self,
     prelude::{LLVMContextRef, LLVMModuleRef, LLVMTypeRef, LLVMValueRef},
-    support::LLVMAddSymbol,
 };
 use smallvec;
 use std::cell::RefCell;
@@ -84,6 +83,14 @@ impl IntrinsicMap {
         }
     }
 
+    /// The names and addresses of the intrinsics that have been declared in the module so far.
+    pub(crate) fn declared(&self) -> impl Iterator<Item = (*const libc::c_char, *mut c_void)> + '_ {
+        self.map
+            .values()
+            .filter(|intr| matches!(&*intr.data.borrow(), Either::Right(_)))
+            .map(|intr| (intr.name, intr.func))
+    }
+
     pub(crate) unsafe fn get(&self, func: *const u8) -> LLVMValueRef {
         use llvm_sys::core::*;
         let intr = &self.map[&(func as usize)];
@@ -93,7 +100,6 @@ impl IntrinsicMap {
             Either::Left(ty) => *ty,
             Either::Right(v) => return *v,
         };
-        LLVMAddSymbol(intr.name, intr.func);
         let func = LLVMAddFunction(self.module, intr.name, ty);
         LLVMSetLinkage(func, llvm_sys::LLVMLinkage::LLVMExternalLinkage);
         if !intr.attrs.is_empty() {
@@ -6,7 +6,8 @@ pub(crate) mod intrinsics;
 use crate::builtins;
 use crate::bytecode::Accum;
 use crate::codegen::{
-    self, intrinsics::register_all, Backend, CodeGenerator, Handles, Jit, Ref, Sig, StrReg,
+    self, intrinsics::register_all, Backend, CodeGenerator, FunctionAttr, Handles, Jit, Ref, Sig,
+    StrReg,
 };
 use crate::common::{Either, FileSpec, NodeIx, NumTy, Result, Stage};
 use crate::compile::{self, Ty, Typer};
@@ -17,9 +18,13 @@ use libc::c_char;
 use llvm_sys::{
     analysis::{LLVMVerifierFailureAction, LLVMVerifyModule},
     core::*,
-    execution_engine::*,
+    debuginfo::*,
+    error::*,
+    execution_engine::LLVMCreateGDBRegistrationListener,
+    orc2::{ee::*, lljit::*, *},
     prelude::*,
     target::*,
+    target_machine::*,
 };
 use petgraph::visit::Dfs;
 use smallvec::smallvec;
@@ -27,7 +32,7 @@ use smallvec::smallvec;
 use intrinsics::IntrinsicMap;
 
 use std::ffi::{CStr, CString};
-use std::mem::{self, MaybeUninit};
+use std::mem;
 use std::ptr;
 
 pub(crate) use codegen::Config;
@@ -38,6 +43,9 @@ type BuiltinFunc = builtin_functions::Function;
 
 type SmallVec<T> = smallvec::SmallVec<[T; 2]>;
 
+// Functions with at most this many (bytecode-level) instructions are marked `alwaysinline`.
+const ALWAYS_INLINE_MAX_INSTS: usize = 32;
+
 #[derive(Clone)]
 struct IterState {
     iter_ptr: LLVMValueRef,  // ptr to elt type
@@ -52,13 +60,187 @@ struct Function {
     locals: HashMap<(NumTy, Ty), LLVMValueRef>,
     iters: HashMap<(NumTy, Ty), IterState>,
     skip_drop: HashSet<(NumTy, Ty)>,
+    // String registers that are only ever read as the source of a move; see `moved_strs`.
+    moved_strs: HashSet<NumTy>,
     args: SmallVec<(NumTy, Ty)>,
     id: usize,
+    // The function's debug info scope; null unless debug info is enabled.
+    subprogram: LLVMMetadataRef,
+}
+
+// Line-table debug info for the module. Everything is attributed to a single "<program>" file,
+// with line numbers taken from the awk source.
+struct DebugInfo {
+    builder: LLVMDIBuilderRef,
+    file: LLVMMetadataRef,
+    fn_ty: LLVMMetadataRef,
+    is_optimized: bool,
+}
+
+impl DebugInfo {
+    unsafe fn new(ctx: LLVMContextRef, module: LLVMModuleRef, is_optimized: bool) -> DebugInfo {
+        const FILE: &str = "<program>";
+        const PRODUCER: &str = "frawk";
+        const VERSION_KEY: &str = "Debug Info Version";
+        let builder = LLVMCreateDIBuilder(module);
+        let file = LLVMDIBuilderCreateFile(
+            builder,
+            FILE.as_ptr() as *const c_char,
+            FILE.len(),
+            c_str!(""),
+            0,
+        );
+        LLVMDIBuilderCreateCompileUnit(
+            builder,
+            LLVMDWARFSourceLanguage::LLVMDWARFSourceLanguageC,
+            file,
+            PRODUCER.as_ptr() as *const c_char,
+            PRODUCER.len(),
+            is_optimized as LLVMBool,
+            c_str!(""),
+            0,
+            /*RuntimeVer=*/ 0,
+            c_str!(""),
+            0,
+            LLVMDWARFEmissionKind::LLVMDWARFEmissionKindLineTablesOnly,
+            /*DWOId=*/ 0,
+            /*SplitDebugInlining=*/ 0,
+            /*DebugInfoForProfiling=*/ 0,
+            c_str!(""),
+            0,
+            c_str!(""),
+            0,
+        );
+        let version = LLVMValueAsMetadata(LLVMConstInt(
+            LLVMIntTypeInContext(ctx, 32),
+            LLVMDebugMetadataVersion() as u64,
+            /*sign_extend=*/ 0,
+        ));
+        LLVMAddModuleFlag(
+            module,
+            llvm_sys::LLVMModuleFlagBehavior::LLVMModuleFlagBehaviorWarning,
+            VERSION_KEY.as_ptr() as *const c_char,
+            VERSION_KEY.len(),
+            version,
+        );
+        let fn_ty =
+            LLVMDIBuilderCreateSubroutineType(builder, file, ptr::null_mut(), 0, LLVMDIFlagZero);
+        DebugInfo {
+            builder,
+            file,
+            fn_ty,
+            is_optimized,
+        }
+    }
+
+    unsafe fn subprogram(&self, name: &str, linkage_name: &str) -> LLVMMetadataRef {
+        LLVMDIBuilderCreateFunction(
+            self.builder,
+            self.file,
+            name.as_ptr() as *const c_char,
+            name.len(),
+            linkage_name.as_ptr() as *const c_char,
+            linkage_name.len(),
+            self.file,
+            /*LineNo=*/ 0,
+            self.fn_ty,
+            /*IsLocalToUnit=*/ 1,
+            /*IsDefinition=*/ 1,
+            /*ScopeLine=*/ 0,
+            LLVMDIFlagZero,
+            self.is_optimized as LLVMBool,
+        )
+    }
+}
+
+unsafe fn host_target_machine(opt_level: usize) -> Result<LLVMTargetMachineRef> {
+    let triple = LLVMGetDefaultTargetTriple();
+    let mut target = ptr::null_mut();
+    let mut err = ptr::null_mut();
+    if LLVMGetTargetFromTriple(triple, &mut target, &mut err) != 0 {
+        let res = err!(
+            "failed to look up target: {}",
+            CStr::from_ptr(err).to_string_lossy()
+        );
+        LLVMDisposeMessage(err);
+        LLVMDisposeMessage(triple);
+        return res;
+    }
+    let cpu = LLVMGetHostCPUName();
+    let features = LLVMGetHostCPUFeatures();
+    let level = match opt_level {
+        0 => LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+        1 => LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+        2 => LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+        _ => LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
+    };
+    let tm = LLVMCreateTargetMachine(
+        target,
+        triple,
+        cpu,
+        features,
+        level,
+        LLVMRelocMode::LLVMRelocDefault,
+        LLVMCodeModel::LLVMCodeModelJITDefault,
+    );
+    LLVMDisposeMessage(triple);
+    LLVMDisposeMessage(cpu);
+    LLVMDisposeMessage(features);
+    Ok(tm)
+}
+
+// This is the object linking layer that LLJIT uses by default, but registered with GDB's JIT
+// interface so that debuggers can symbolize the generated code.
+extern "C" fn create_object_layer(
+    _ctx: *mut libc::c_void,
+    session: LLVMOrcExecutionSessionRef,
+    _triple: *const c_char,
+) -> LLVMOrcObjectLayerRef {
+    unsafe {
+        let layer = LLVMOrcCreateRTDyldObjectLinkingLayerWithSectionMemoryManager(session);
+        LLVMOrcRTDyldObjectLinkingLayerRegisterJITEventListener(
+            layer,
+            LLVMCreateGDBRegistrationListener(),
+        );
+        layer
+    }
+}
+
+unsafe fn check_error(err: LLVMErrorRef) -> Result<()> {
+    if err.is_null() {
+        return Ok(());
+    }
+    let msg = LLVMGetErrorMessage(err);
+    let res = err!("JIT error: {}", CStr::from_ptr(msg).to_string_lossy());
+    LLVMDisposeErrorMessage(msg);
+    res
+}
+
+unsafe fn set_debug_location(
+    ctx: LLVMContextRef,
+    builder: LLVMBuilderRef,
+    line: u32,
+    scope: LLVMMetadataRef,
+) {
+    let loc = LLVMDIBuilderCreateDebugLocation(ctx, line, 0, scope, ptr::null_mut());
+    LLVMSetCurrentDebugLocation2(builder, loc);
+}
+
+// Where a function finds a global variable.
+#[derive(Copy, Clone)]
+enum GlobalRef {
+    // A global variable in the LLVM module. This is the common case; the main function initializes
+    // all of these variables and drops them once it is finished.
+    Module(LLVMValueRef),
+    // A pointer passed as the parameter at the given index. We pass globals as parameters for
+    // parallel scripts, where several threads run the same code with separate copies of the
+    // globals.
+    Param(usize),
 }
 
 struct FuncInfo {
     val: LLVMValueRef,
-    globals: HashMap<(NumTy, Ty), usize>,
+    globals: HashMap<(NumTy, Ty), GlobalRef>,
     num_args: usize,
 }
 
@@ -78,6 +260,8 @@ struct View<'a> {
     printfs: &'a mut HashMap<(SmallVec<Ty>, PrintfKind), LLVMValueRef>,
     prints: &'a mut HashMap<(usize, /*stdout*/ bool), LLVMValueRef>,
     handles: &'a mut Handles,
+    check_numbers: bool,
+    strict: bool,
     drop_str: LLVMValueRef,
     // We keep an extra builder always pointed at the start of the function. This is because
     // binding new string values requires an `alloca`; and we do not want to call `alloca` where a
@@ -145,13 +329,12 @@ impl<'a> CodeGenerator for View<'a> {
                 return Ok(());
             }
             use Ty::*;
-            if let Some(ix) = self.decls[self.f.id].globals.get(&val) {
+            if let Some(param) = self.global_ptr(val) {
                 // We're storing into a global variable. If it's a string or map, that means we have to
                 // alter the reference counts appropriately.
                 //  - if Str, call drop, store, call ref.
                 //  - if Map, load the value, drop it, ref `to` then store it
                 //  - otherwise, just store it directly
-                let param = LLVMGetParam(self.f.val, *ix as libc::c_uint);
                 let new_global = to;
                 match val.1 {
                     MapIntInt | MapIntStr | MapIntFloat | MapStrInt | MapStrStr | MapStrFloat => {
@@ -263,6 +446,21 @@ impl<'a> CodeGenerator for View<'a> {
         self.handles
     }
 
+    fn check_numbers(&self) -> bool {
+        self.check_numbers
+    }
+
+    fn strict(&self) -> bool {
+        self.strict
+    }
+
+    fn set_debug_line(&mut self, line: u32) -> Result<()> {
+        if !self.f.subprogram.is_null() {
+            unsafe { set_debug_location(self.ctx, self.f.builder, line, self.f.subprogram) };
+        }
+        Ok(())
+    }
+
     fn call_intrinsic(&mut self, func: codegen::Op, args: &mut [Self::Val]) -> Result<Self::Val> {
         use codegen::Op::*;
         fn to_pred(cmp: codegen::Cmp, is_float: bool) -> Either<Pred, FPred> {
@@ -322,7 +520,27 @@ impl<'a> CodeGenerator for View<'a> {
                             Mul => LLVMBuildMul(self.f.builder, args[0], args[1], c_str!("")),
                             Minus => LLVMBuildSub(self.f.builder, args[0], args[1], c_str!("")),
                             Add => LLVMBuildAdd(self.f.builder, args[0], args[1], c_str!("")),
-                            Mod => LLVMBuildSRem(self.f.builder, args[0], args[1], c_str!("")),
+                            Mod => {
+                                // `srem` is undefined for INT_MIN % -1, but x % -1 is always 0,
+                                // as is x % 1.
+                                let minus_one = self.const_int(-1);
+                                let is_minus_one = LLVMBuildICmp(
+                                    self.f.builder,
+                                    Pred::LLVMIntEQ,
+                                    args[1],
+                                    minus_one,
+                                    c_str!(""),
+                                );
+                                let one = self.const_int(1);
+                                let r = LLVMBuildSelect(
+                                    self.f.builder,
+                                    is_minus_one,
+                                    one,
+                                    args[1],
+                                    c_str!(""),
+                                );
+                                LLVMBuildSRem(self.f.builder, args[0], r, c_str!(""))
+                            }
                             Neg => {
                                 let zero = self.const_int(0);
                                 LLVMBuildSub(self.f.builder, zero, args[0], c_str!(""))
@@ -465,8 +683,19 @@ impl<'a> CodeGenerator for View<'a> {
         unsafe {
             let sv = self.get_val((src, ty))?;
             if let Ty::Str = ty {
-                self.call(intrinsic!(ref_str), &mut [sv]);
                 let loaded = LLVMBuildLoad(self.f.builder, sv, c_str!(""));
+                if self.f.moved_strs.contains(&src)
+                    && !self.is_global((src, ty))
+                    && !self.f.args.contains(&(src, ty))
+                {
+                    // `src` is never read again, so we can transfer its reference to `dst` rather
+                    // than taking a new one. Clearing out `src` means the drop at function exit
+                    // (or when `src` is next bound) is a no-op.
+                    let zero = LLVMConstInt(self.tmap.get_ty(Ty::Str), 0, /*sign_extend=*/ 0);
+                    LLVMBuildStore(self.f.builder, zero, sv);
+                } else {
+                    self.call(intrinsic!(ref_str), &mut [sv]);
+                }
                 self.bind_val((dst, Ty::Str), loaded)
             } else {
                 if ty.is_array() {
@@ -546,6 +775,40 @@ impl<'a> CodeGenerator for View<'a> {
         }
         self.bind_val(dst, res)
     }
+
+    fn check_divisor(&mut self, div: Ref, is_mod: bool) -> Result<()> {
+        let divv = self.get_val(div)?;
+        unsafe {
+            let is_zero = match div.1 {
+                Ty::Float => LLVMBuildFCmp(
+                    self.f.builder,
+                    FPred::LLVMRealOEQ,
+                    divv,
+                    self.const_float(0.0),
+                    c_str!(""),
+                ),
+                Ty::Int => LLVMBuildICmp(
+                    self.f.builder,
+                    Pred::LLVMIntEQ,
+                    divv,
+                    self.const_int(0),
+                    c_str!(""),
+                ),
+                ty => return err!("non-numeric divisor: {:?}", ty),
+            };
+            let err_bb = LLVMAppendBasicBlockInContext(self.ctx, self.f.val, c_str!(""));
+            let next_bb = LLVMAppendBasicBlockInContext(self.ctx, self.f.val, c_str!(""));
+            LLVMBuildCondBr(self.f.builder, is_zero, err_bb, next_bb);
+            LLVMPositionBuilderAtEnd(self.f.builder, err_bb);
+            let rt = self.runtime_val();
+            let is_modv = self.const_int(is_mod as i64);
+            self.call(intrinsic!(division_by_zero), &mut [rt, is_modv]);
+            LLVMBuildUnreachable(self.f.builder);
+            // Code for the rest of the current basic block goes in `next_bb`.
+            LLVMPositionBuilderAtEnd(self.f.builder, next_bb);
+        }
+        Ok(())
+    }
 }
 
 impl Drop for Function {
@@ -612,7 +875,13 @@ pub(crate) struct Generator<'a, 'b> {
     types: &'b mut Typer<'a>,
     ctx: LLVMContextRef,
     module: LLVMModuleRef,
-    engine: LLVMExecutionEngineRef,
+    // Owns `ctx`. The module and context are handed off to `jit` when it is created, at which
+    // point this is set to null.
+    tsc: LLVMOrcThreadSafeContextRef,
+    // The target machine for the host, which the JIT takes ownership of when it is created.
+    target_machine: LLVMTargetMachineRef,
+    // Created lazily; see `Generator::jit`.
+    jit: LLVMOrcLLJITRef,
     decls: Vec<FuncInfo>,
     funcs: Vec<Function>,
     handles: Handles,
@@ -623,6 +892,7 @@ pub(crate) struct Generator<'a, 'b> {
     // We pass raw regex pointers in the generated code. These ensure we do not free them
     // before the code is run.
     cfg: Config,
+    debug: Option<DebugInfo>,
 
     // Specialized implementation of string destruction.
     drop_str: LLVMValueRef,
@@ -631,11 +901,93 @@ pub(crate) struct Generator<'a, 'b> {
 impl<'a, 'b> Drop for Generator<'a, 'b> {
     fn drop(&mut self) {
         unsafe {
-            LLVMDisposeModule(self.module);
+            if let Some(debug) = &self.debug {
+                LLVMDisposeDIBuilder(debug.builder);
+            }
+            if !self.tsc.is_null() {
+                // The module was never passed to the JIT.
+                LLVMDisposeModule(self.module);
+                LLVMOrcDisposeThreadSafeContext(self.tsc);
+            }
+            if !self.target_machine.is_null() {
+                LLVMDisposeTargetMachine(self.target_machine);
+            }
+            if !self.jit.is_null() {
+                LLVMConsumeError(LLVMOrcDisposeLLJIT(self.jit));
+            }
         }
     }
 }
 
+// Find the string registers in `frame` whose value can be moved out of (rather than copied) when
+// they are the source of a `Mov`. A register qualifies if it is defined exactly once and its only
+// use is a single `Mov`: because definitions dominate uses, nothing can observe the register
+// between the move and its next definition, so the ref_str/drop_str pair that a copy would entail
+// is unnecessary.
+//
+// Registers holding implicitly-declared variables (subscript 0) are never defined in the frame,
+// and phi nodes alias the storage of their predecessors, so neither is a candidate.
+fn moved_strs(frame: &compile::Frame) -> HashSet<NumTy> {
+    use compile::HighLevel::*;
+    let mut mentions = HashMap::<NumTy, usize>::new();
+    let mut movs = SmallVec::new();
+    let mut excluded: HashSet<NumTy> = frame
+        .locals
+        .iter()
+        .filter_map(|(local, (reg, ty))| {
+            if local.sub == 0 && *ty == Ty::Str {
+                Some(*reg)
+            } else {
+                None
+            }
+        })
+        .collect();
+    let mut mention = |reg: NumTy, ty: Ty| {
+        if ty == Ty::Str {
+            *mentions.entry(reg).or_insert(0) += 1;
+        }
+    };
+    for node in frame.cfg.raw_nodes() {
+        for inst in node.weight.insts.iter() {
+            match inst {
+                Either::Left(ll) => {
+                    if let crate::bytecode::Instr::Mov(Ty::Str, _, src) = ll {
+                        movs.push(*src);
+                    }
+                    ll.accum(&mut mention)
+                }
+                Either::Right(Call {
+                    dst_reg,
+                    dst_ty,
+                    args,
+                    ..
+                }) => {
+                    mention(*dst_reg, *dst_ty);
+                    for (reg, ty) in args.iter() {
+                        mention(*reg, *ty);
+                    }
+                }
+                Either::Right(Phi(dst, ty, preds)) => {
+                    if *ty == Ty::Str {
+                        excluded.insert(*dst);
+                    }
+                    mention(*dst, *ty);
+                    for (_, reg) in preds.iter() {
+                        mention(*reg, *ty);
+                    }
+                }
+                Either::Right(Ret(reg, ty)) | Either::Right(DropIter(reg, ty)) => {
+                    mention(*reg, *ty)
+                }
+            }
+        }
+    }
+    // One mention for the definition, and one for the move.
+    movs.into_iter()
+        .filter(|src| mentions.get(src) == Some(&2) && !excluded.contains(src))
+        .collect()
+}
+
 unsafe fn alloc_local(
     builder: LLVMBuilderRef,
     ty: Ty,
@@ -677,6 +1029,7 @@ unsafe fn alloc_local(
             v_loc
         }
         IterInt | IterStr => return err!("we should not be default-allocating any iterators"),
+        Regex => return err!("regex values are not supported by the LLVM backend"),
     };
     Ok(val)
 }
@@ -690,6 +1043,8 @@ macro_rules! view_at {
             tmap: &$slf.type_map,
             intrinsics: &mut $slf.intrinsics,
             handles: &mut $slf.handles,
+            check_numbers: $slf.types.check_numbers,
+            strict: $slf.types.strict,
             decls: &$slf.decls,
             printfs: &mut $slf.printfs,
             prints: &mut $slf.prints,
@@ -705,9 +1060,18 @@ impl<'a, 'b> Jit for Generator<'a, 'b> {
     fn main_pointers(&mut self) -> Result<Stage<*const u8>> {
         unsafe {
             let main = self.gen_main()?;
+            if self.cfg.perf_map {
+                self.gen_perf_map_sentinel();
+            }
             self.verify()?;
             self.optimize(main.iter().map(|(_, x)| x).cloned())?;
-            Ok(main.map(|(name, _)| LLVMGetFunctionAddress(self.engine, name) as *const u8))
+            let res = main
+                .map_ref(|(name, _)| self.lookup(*name).map(|addr| addr as *const u8))
+                .transpose()?;
+            if self.cfg.perf_map {
+                self.write_perf_map(main.iter().map(|(name, _)| *name))?;
+            }
+            Ok(res)
         }
     }
 }
@@ -715,7 +1079,19 @@ impl<'a, 'b> Jit for Generator<'a, 'b> {
 impl<'a, 'b> Generator<'a, 'b> {
     pub unsafe fn optimize(&mut self, mains: impl Iterator<Item = LLVMValueRef>) -> Result<()> {
         // Based on optimize_module in weld, in turn based on similar code in the LLVM opt tool.
-        use llvm_sys::transforms::pass_manager_builder::*;
+        //
+        // Optimization levels trade compile time against the speed of the generated code:
+        // * -O0 runs no passes at all.
+        // * -O1 only runs per-function passes.
+        // * -O2 and -O3 also run module-level passes, including the inliner (which is more
+        //   aggressive at -O3).
+        use llvm_sys::transforms::{ipo::*, pass_manager_builder::*};
+        let module_passes = match self.cfg.opt_level {
+            0 => return Ok(()),
+            1 => false,
+            2 | 3 => true,
+            _ => return err!("unrecognized opt level"),
+        };
         let mpm = LLVMCreatePassManager();
         let fpm = LLVMCreateFunctionPassManagerForModule(self.module);
 
@@ -723,15 +1099,20 @@ impl<'a, 'b> Generator<'a, 'b> {
         LLVMPassManagerBuilderSetOptLevel(builder, self.cfg.opt_level as u32);
         LLVMPassManagerBuilderSetSizeLevel(builder, 0);
         match self.cfg.opt_level {
-            0 => {}
-            1 => LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 50),
             2 => LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 100),
             3 => LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 250),
-            _ => return err!("unrecognized opt level"),
+            _ => {}
         };
 
         LLVMPassManagerBuilderPopulateFunctionPassManager(builder, fpm);
-        LLVMPassManagerBuilderPopulateModulePassManager(builder, mpm);
+        if module_passes {
+            // Globals are passed to functions by pointer. Argument promotion passes the values
+            // directly to functions that only read them, and dead argument elimination removes
+            // globals that are no longer used after inlining and other optimizations.
+            LLVMAddArgumentPromotionPass(mpm);
+            LLVMPassManagerBuilderPopulateModulePassManager(builder, mpm);
+            LLVMAddDeadArgEliminationPass(mpm);
+        }
         LLVMPassManagerBuilderDispose(builder);
 
         for f in self.decls.iter() {
@@ -749,7 +1130,9 @@ impl<'a, 'b> Generator<'a, 'b> {
         }
 
         LLVMFinalizeFunctionPassManager(fpm);
-        LLVMRunPassManager(mpm, self.module);
+        if module_passes {
+            LLVMRunPassManager(mpm, self.module);
+        }
         LLVMDisposePassManager(fpm);
         LLVMDisposePassManager(mpm);
         Ok(())
@@ -759,30 +1142,34 @@ impl<'a, 'b> Generator<'a, 'b> {
         if llvm_sys::support::LLVMLoadLibraryPermanently(ptr::null()) != 0 {
             return err!("failed to load in-process library");
         }
-        let ctx = LLVMContextCreate();
-        let module = LLVMModuleCreateWithNameInContext(c_str!("frawk_main"), ctx);
         // JIT-specific initialization.
         LLVM_InitializeNativeTarget();
         LLVM_InitializeNativeAsmPrinter();
         LLVM_InitializeNativeAsmParser();
-        LLVMLinkInMCJIT();
-        let mut maybe_engine = MaybeUninit::<LLVMExecutionEngineRef>::uninit();
-        let mut err: *mut c_char = ptr::null_mut();
-        if LLVMCreateExecutionEngineForModule(maybe_engine.as_mut_ptr(), module, &mut err) != 0 {
-            let res = err!(
-                "failed to create program: {}",
-                CStr::from_ptr(err).to_str().unwrap()
-            );
-            LLVMDisposeMessage(err);
-            return res;
-        }
-        let engine = maybe_engine.assume_init();
+        let target_machine = host_target_machine(cfg.opt_level)?;
+        let tsc = LLVMOrcCreateNewThreadSafeContext();
+        let ctx = LLVMOrcThreadSafeContextGetContext(tsc);
+        let module = LLVMModuleCreateWithNameInContext(c_str!("frawk_main"), ctx);
+        // Optimizations need to know the target's data layout.
+        let triple = LLVMGetTargetMachineTriple(target_machine);
+        LLVMSetTarget(module, triple);
+        LLVMDisposeMessage(triple);
+        let data_layout = LLVMCreateTargetDataLayout(target_machine);
+        LLVMSetModuleDataLayout(module, data_layout);
+        LLVMDisposeTargetData(data_layout);
+        let debug = if cfg.debug_info {
+            Some(DebugInfo::new(ctx, module, cfg.opt_level > 0))
+        } else {
+            None
+        };
         let nframes = types.frames.len();
         let mut res = Generator {
             types,
             ctx,
             module,
-            engine,
+            tsc,
+            target_machine,
+            jit: ptr::null_mut(),
             decls: Vec::with_capacity(nframes),
             funcs: Vec::with_capacity(nframes),
             type_map: TypeMap::new(ctx),
@@ -791,6 +1178,7 @@ impl<'a, 'b> Generator<'a, 'b> {
             prints: Default::default(),
             handles: Default::default(),
             cfg,
+            debug,
             drop_str: ptr::null_mut(),
         };
         res.build_map();
@@ -826,7 +1214,7 @@ impl<'a, 'b> Generator<'a, 'b> {
         let mains = self.gen_main()?;
         self.verify()?;
         self.optimize(mains.iter().map(|(_, x)| x).cloned())?;
-        let addr = LLVMGetFunctionAddress(self.engine, c_str!("__frawk_main"));
+        let addr = self.lookup(c_str!("__frawk_main"))?;
         ptr::read_volatile(&addr);
         Ok(())
     }
@@ -858,6 +1246,8 @@ impl<'a, 'b> Generator<'a, 'b> {
         // See the IterState type and its uses for more info.
         self.type_map.init(Ty::IterInt, TypeRef::null());
         self.type_map.init(Ty::IterStr, TypeRef::null());
+        // Programs with regex values run in the bytecode interpreter instead.
+        self.type_map.init(Ty::Regex, TypeRef::null());
         self.type_map
             .init(Ty::Null, make(self.type_map.get_ty(Ty::Int)));
     }
@@ -877,6 +1267,8 @@ impl<'a, 'b> Generator<'a, 'b> {
     unsafe fn build_decls(&mut self) {
         let global_refs = self.types.get_global_refs();
         debug_assert_eq!(global_refs.len(), self.types.func_info.len());
+        let module_globals = matches!(self.types.stage(), Stage::Main(_));
+        let mut globals_by_reg = HashMap::new();
         let mut arg_tys = SmallVec::new();
         for (i, (info, refs)) in self
             .types
@@ -892,10 +1284,22 @@ impl<'a, 'b> Generator<'a, 'b> {
             arg_tys.extend(info.arg_tys.iter().map(|ty| self.llvm_ty(*ty)));
             // Then, we add on the referenced globals.
             for (reg, ty) in refs.iter().cloned() {
-                let ix = arg_tys.len();
-                arg_tys.push(self.llvm_ptr_ty(ty));
-                // Vals are ignored if we are main.
-                globals.insert((reg, ty), ix);
+                let loc = if module_globals {
+                    let module = self.module;
+                    let val_ty = self.type_map.get_ty(ty);
+                    *globals_by_reg.entry((reg, ty)).or_insert_with(|| {
+                        let name = CString::new(format!("_frawk_global_{}_{:?}", reg, ty)).unwrap();
+                        let gv = LLVMAddGlobal(module, val_ty, name.as_ptr());
+                        LLVMSetInitializer(gv, LLVMConstNull(val_ty));
+                        LLVMSetLinkage(gv, llvm_sys::LLVMLinkage::LLVMInternalLinkage);
+                        GlobalRef::Module(gv)
+                    })
+                } else {
+                    let ix = arg_tys.len();
+                    arg_tys.push(self.llvm_ptr_ty(ty));
+                    GlobalRef::Param(ix)
+                };
+                globals.insert((reg, ty), loc);
             }
             // Finally, we add a pointer to the runtime; always the last parameter.
             arg_tys.push(self.type_map.runtime_ty);
@@ -906,12 +1310,27 @@ impl<'a, 'b> Generator<'a, 'b> {
                 /*IsVarArg=*/ 0,
             );
             let builder = LLVMCreateBuilderInContext(self.ctx);
+            let mut subprogram = ptr::null_mut();
             let val = if is_called {
                 let val = LLVMAddFunction(self.module, name.as_ptr(), ty);
+                if let Some(debug) = &self.debug {
+                    subprogram =
+                        debug.subprogram(&self.types.frames[i].name, name.to_str().unwrap());
+                    LLVMSetSubprogram(val, subprogram);
+                }
                 // We make these private, as we generate a separate main that calls into them. This
                 // way, function bodies that get inlined into main do not have to show up in
-                // generated code.
-                LLVMSetLinkage(val, llvm_sys::LLVMLinkage::LLVMLinkerPrivateLinkage);
+                // generated code. Perf maps need to look functions up by name, though.
+                if !self.cfg.perf_map {
+                    LLVMSetLinkage(val, llvm_sys::LLVMLinkage::LLVMLinkerPrivateLinkage);
+                }
+                // Small helper functions are always inlined, regardless of the inliner's cost
+                // model: passing globals as parameters makes calls to them fairly expensive.
+                if self.types.frames[i].num_insts() <= ALWAYS_INLINE_MAX_INSTS
+                    && !self.types.is_recursive(i)
+                {
+                    attr::add_function_attrs(self.ctx, val, &[FunctionAttr::AlwaysInline]);
+                }
                 val
             } else {
                 ptr::null_mut()
@@ -934,8 +1353,10 @@ impl<'a, 'b> Generator<'a, 'b> {
                 iters: Default::default(),
                 locals: Default::default(),
                 skip_drop: Default::default(),
+                moved_strs: Default::default(),
                 args,
                 id,
+                subprogram,
             });
             arg_tys.clear();
         }
@@ -960,25 +1381,48 @@ impl<'a, 'b> Generator<'a, 'b> {
         let builder = LLVMCreateBuilderInContext(self.ctx);
         let bb = LLVMAppendBasicBlockInContext(self.ctx, decl, c_str!(""));
         LLVMPositionBuilderAtEnd(builder, bb);
+        if let Some(debug) = &self.debug {
+            // Calls into functions with debug info must themselves have a location.
+            let name_str = CStr::from_ptr(name).to_str().unwrap();
+            let subprogram = debug.subprogram(name_str, name_str);
+            LLVMSetSubprogram(decl, subprogram);
+            set_debug_location(self.ctx, builder, 0, subprogram);
+        }
 
         // For now, iterate over each element of the stage and call each component in sequence.
-        // We need to allocate all of the global variables that our main function uses, and then
-        // pass them as arguments, along with the runtime.
+        // We need to initialize all of the global variables that our main function uses (passing
+        // them as arguments if necessary), along with the runtime.
         let main_info = &self.decls[main_offset];
         let mut args: SmallVec<_> = smallvec![ptr::null_mut(); main_info.num_args];
-        let mut to_drop = SmallVec::with_capacity(args.len());
-        for ((_reg, ty), arg_ix) in main_info.globals.iter() {
+        let mut to_drop = SmallVec::with_capacity(main_info.globals.len());
+        for ((_reg, ty), global) in main_info.globals.iter() {
             let local = self.alloc_local(builder, *ty)?;
-            let param = if ty.is_array() || matches!(ty, Ty::Str) {
-                // Already a pointer; we're good to go!
-                to_drop.push((local, *ty));
-                local
-            } else {
-                let loc = LLVMBuildAlloca(builder, self.llvm_ty(*ty), c_str!(""));
-                LLVMBuildStore(builder, local, loc);
-                loc
-            };
-            args[*arg_ix] = param;
+            match global {
+                GlobalRef::Module(gv) => {
+                    // `local` is a pointer for strings and maps, and a value otherwise.
+                    let init = if ty.is_array() || matches!(ty, Ty::Str) {
+                        LLVMBuildLoad(builder, local, c_str!(""))
+                    } else {
+                        local
+                    };
+                    LLVMBuildStore(builder, init, *gv);
+                    if ty.is_array() || matches!(ty, Ty::Str) {
+                        to_drop.push((*gv, *ty));
+                    }
+                }
+                GlobalRef::Param(arg_ix) => {
+                    let param = if ty.is_array() || matches!(ty, Ty::Str) {
+                        // Already a pointer; we're good to go!
+                        to_drop.push((local, *ty));
+                        local
+                    } else {
+                        let loc = LLVMBuildAlloca(builder, self.llvm_ty(*ty), c_str!(""));
+                        LLVMBuildStore(builder, local, loc);
+                        loc
+                    };
+                    args[*arg_ix] = param;
+                }
+            }
         }
         // Pass the runtime last.
         args[main_info.num_args - 1] = LLVMGetParam(decl, 0);
@@ -1016,9 +1460,136 @@ impl<'a, 'b> Generator<'a, 'b> {
         Ok((name, decl))
     }
 
+    // An empty function added after all of the others; its address marks the end of the last
+    // function in the perf map. The JIT lays out functions in the order they appear in the module.
+    unsafe fn gen_perf_map_sentinel(&mut self) {
+        let ty = LLVMFunctionType(LLVMVoidTypeInContext(self.ctx), ptr::null_mut(), 0, 0);
+        let decl = LLVMAddFunction(self.module, c_str!("__frawk_perf_end"), ty);
+        let builder = LLVMCreateBuilderInContext(self.ctx);
+        let bb = LLVMAppendBasicBlockInContext(self.ctx, decl, c_str!(""));
+        LLVMPositionBuilderAtEnd(builder, bb);
+        LLVMBuildRetVoid(builder);
+        LLVMDisposeBuilder(builder);
+    }
+
+    // Append the addresses of the main functions and UDFs to /tmp/perf-PID.map, the format that
+    // `perf` uses to symbolize JIT-compiled code. UDFs are named after the awk functions they
+    // were generated from.
+    unsafe fn write_perf_map(&mut self, mains: impl Iterator<Item = *const c_char>) -> Result<()> {
+        use std::io::Write;
+        let mut syms: Vec<(u64, String)> = Vec::new();
+        for name in mains {
+            let addr = self.lookup(name)?;
+            syms.push((addr, CStr::from_ptr(name).to_string_lossy().into_owned()));
+        }
+        for i in 0..self.decls.len() {
+            if self.decls[i].val.is_null() {
+                continue;
+            }
+            let name = CString::new(format!("_frawk_udf_{}", i)).unwrap();
+            let addr = self.lookup(name.as_ptr()).unwrap_or(0);
+            syms.push((addr, format!("frawk::{}", self.types.frames[i].name)));
+        }
+        let end = self.lookup(c_str!("__frawk_perf_end"))?;
+        syms.retain(|(addr, _)| *addr != 0);
+        syms.sort_by_key(|(addr, _)| *addr);
+        let mut map = String::new();
+        for (i, (addr, name)) in syms.iter().enumerate() {
+            let next = syms.get(i + 1).map(|(addr, _)| *addr).unwrap_or(end);
+            if next > *addr {
+                map.push_str(&format!("{:x} {:x} {}\n", addr, next - addr, name));
+            }
+        }
+        let path = format!("/tmp/perf-{}.map", std::process::id());
+        let res = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| f.write_all(map.as_bytes()));
+        if let Err(e) = res {
+            return err!("failed to write perf map to {}: {}", path, e);
+        }
+        Ok(())
+    }
+
+    // Returns the JIT, creating it and handing it the module if this is the first call. Code is
+    // generated for the whole module the first time one of its functions is looked up.
+    unsafe fn jit(&mut self) -> Result<LLVMOrcLLJITRef> {
+        if !self.jit.is_null() {
+            return Ok(self.jit);
+        }
+        let builder = LLVMOrcCreateLLJITBuilder();
+        LLVMOrcLLJITBuilderSetJITTargetMachineBuilder(
+            builder,
+            LLVMOrcJITTargetMachineBuilderCreateFromTargetMachine(self.target_machine),
+        );
+        self.target_machine = ptr::null_mut();
+        LLVMOrcLLJITBuilderSetObjectLinkingLayerCreator(
+            builder,
+            create_object_layer,
+            ptr::null_mut(),
+        );
+        // Takes ownership of the builder.
+        check_error(LLVMOrcCreateLLJIT(&mut self.jit, builder))?;
+        let jit = self.jit;
+        let dylib = LLVMOrcLLJITGetMainJITDylib(jit);
+
+        // Calls into the runtime are resolved using the intrinsics table. Anything else (e.g. libm
+        // functions that LLVM intrinsics are lowered to) is looked up in the current process.
+        let mut syms: Vec<_> = self
+            .intrinsics
+            .declared()
+            .map(|(name, addr)| LLVMJITCSymbolMapPair {
+                Name: LLVMOrcLLJITMangleAndIntern(jit, name),
+                Sym: LLVMJITEvaluatedSymbol {
+                    Address: addr as LLVMOrcJITTargetAddress,
+                    Flags: LLVMJITSymbolFlags {
+                        GenericFlags: LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsExported
+                            as u8,
+                        TargetFlags: 0,
+                    },
+                },
+            })
+            .collect();
+        let runtime_syms = LLVMOrcAbsoluteSymbols(syms.as_mut_ptr(), syms.len());
+        let err = LLVMOrcJITDylibDefine(dylib, runtime_syms);
+        if !err.is_null() {
+            LLVMOrcDisposeMaterializationUnit(runtime_syms);
+        }
+        check_error(err)?;
+        let mut process_syms = ptr::null_mut();
+        check_error(LLVMOrcCreateDynamicLibrarySearchGeneratorForProcess(
+            &mut process_syms,
+            LLVMOrcLLJITGetGlobalPrefix(jit),
+            None,
+            ptr::null_mut(),
+        ))?;
+        LLVMOrcJITDylibAddGenerator(dylib, process_syms);
+
+        // The thread-safe module shares ownership of the context with `tsc`; after this, the JIT
+        // is responsible for freeing both the module and the context.
+        let tsm = LLVMOrcCreateNewThreadSafeModule(self.module, self.tsc);
+        LLVMOrcDisposeThreadSafeContext(self.tsc);
+        self.tsc = ptr::null_mut();
+        let err = LLVMOrcLLJITAddLLVMIRModule(jit, dylib, tsm);
+        if !err.is_null() {
+            LLVMOrcDisposeThreadSafeModule(tsm);
+        }
+        check_error(err)?;
+        Ok(jit)
+    }
+
+    // Look up the address of a function in the module, compiling it if necessary.
+    unsafe fn lookup(&mut self, name: *const c_char) -> Result<u64> {
+        let jit = self.jit()?;
+        let mut addr = 0;
+        check_error(LLVMOrcLLJITLookup(jit, &mut addr, name))?;
+        Ok(addr)
+    }
+
     unsafe fn gen_main(&mut self) -> Result<Stage<(*const libc::c_char, LLVMValueRef)>> {
         use crate::common::traverse;
-        match self.types.stage() {
+        let res = match self.types.stage() {
             Stage::Main(main) => Ok(Stage::Main(
                 self.gen_main_function(main, c_str!("__frawk_main"))?,
             )),
@@ -1037,7 +1608,12 @@ impl<'a, 'b> Generator<'a, 'b> {
                     end.map(|off| self.gen_main_function(off, c_str!("__frawk_end_loop"))),
                 )?,
             }),
+        };
+        // All functions carrying debug info have been generated at this point.
+        if let Some(debug) = &self.debug {
+            LLVMDIBuilderFinalize(debug.builder);
         }
+        res
     }
 
     unsafe fn verify(&mut self) -> Result<()> {
@@ -1078,6 +1654,9 @@ impl<'a, 'b> Generator<'a, 'b> {
             let bb = LLVMAppendBasicBlockInContext(self.ctx, self.funcs[func_id].val, c_str!(""));
             bbs.push(bb);
         }
+        // The LLVM block that each node's code ends in. This is usually the node's block in `bbs`,
+        // but some instructions (e.g. `check_divisor`) split the block they are generated in.
+        let mut ends = bbs.clone();
         LLVMPositionBuilderAtEnd(builder, bbs[0]);
         // handle arguments
         let enum_args: SmallVec<_> = self.funcs[func_id]
@@ -1096,6 +1675,7 @@ impl<'a, 'b> Generator<'a, 'b> {
                 self.funcs[func_id].locals.insert((*reg, *ty), val);
             }
         }
+        self.funcs[func_id].moved_strs = moved_strs(frame);
 
         // As of writing; we'll only ever have a single return statement for a given function, but
         // we do not lose very much by having this function support multiple returns if we decide
@@ -1103,6 +1683,8 @@ impl<'a, 'b> Generator<'a, 'b> {
         let mut exits = Vec::with_capacity(1);
         let mut phis = Vec::new();
         let mut view = view_at!(self, func_id, entry_builder);
+        // Attribute code to line 0 (i.e. no particular line) until we see the first statement.
+        view.set_debug_line(0)?;
         for (i, arg) in enum_args.into_iter() {
             let argv = LLVMGetParam(view.f.val, i as libc::c_uint);
             // We insert into `locals` directly because we know these aren't globals, and we want
@@ -1144,6 +1726,7 @@ impl<'a, 'b> Generator<'a, 'b> {
                     }
                 }
             }
+            ends[i] = LLVMGetInsertBlock(view.f.builder);
             let mut walker = frame.cfg.neighbors(NodeIx::new(i)).detach();
             let mut tcase = None;
             let mut ecase = None;
@@ -1171,7 +1754,7 @@ impl<'a, 'b> Generator<'a, 'b> {
         let node_weight = |bb, inst| &frame.cfg.node_weight(NodeIx::new(bb)).unwrap().insts[inst];
         let mut placeholder_intrinsics = IntrinsicMap::new(view.module, view.ctx);
         for (exit_bb, return_inst) in exits.into_iter() {
-            LLVMPositionBuilderAtEnd(view.f.builder, bbs[exit_bb]);
+            LLVMPositionBuilderAtEnd(view.f.builder, ends[exit_bb]);
             let var = if let Either::Right(Ret(reg, ty)) = node_weight(exit_bb, return_inst) {
                 (*reg, *ty)
             } else {
@@ -1205,7 +1788,7 @@ impl<'a, 'b> Generator<'a, 'b> {
                 let phi_node = view.get_local_raw((*reg, *ty))?;
                 for (pred_bb, pred_reg) in ps.iter() {
                     preds.push(view.get_local_raw((*pred_reg, *ty))?);
-                    blocks.push(bbs[pred_bb.index()]);
+                    blocks.push(ends[pred_bb.index()]);
                 }
                 LLVMAddIncoming(
                     phi_node,
@@ -1252,8 +1835,7 @@ impl<'a> View<'a> {
             } else {
                 Some(*v)
             }
-        } else if let Some(ix) = self.decls[self.f.id].globals.get(&local) {
-            let gv = LLVMGetParam(self.f.val, *ix as libc::c_uint);
+        } else if let Some(gv) = self.global_ptr(local) {
             Some(if let Ty::Str = local.1 {
                 // no point in loading the string directly. We manipulate them as pointers.
                 gv
@@ -1305,6 +1887,14 @@ impl<'a> View<'a> {
         self.decls[self.f.id].globals.get(&reg).is_some()
     }
 
+    // A pointer to the given global variable, if it is one.
+    unsafe fn global_ptr(&self, reg: (NumTy, Ty)) -> Option<LLVMValueRef> {
+        self.decls[self.f.id].globals.get(&reg).map(|g| match g {
+            GlobalRef::Module(gv) => *gv,
+            GlobalRef::Param(ix) => LLVMGetParam(self.f.val, *ix as libc::c_uint),
+        })
+    }
+
     unsafe fn ref_val(&mut self, mut val: LLVMValueRef, ty: Ty) {
         use Ty::*;
         let func = match ty {
@@ -1365,7 +1955,7 @@ impl<'a> View<'a> {
                 LLVMBuildStore(self.entry_builder, v, res);
                 return Ok(res);
             }
-            Ty::IterInt | Ty::Null | Ty::IterStr => {
+            Ty::IterInt | Ty::Null | Ty::IterStr | Ty::Regex => {
                 return err!("unexpected type passed to alloca: {:?}", ty);
             }
             // map
@@ -1476,24 +2066,21 @@ impl<'a> View<'a> {
                 dst_ty,
                 args,
             } => {
-                let source = &self.decls[self.f.id];
                 let target = &self.decls[*func_id as usize];
-                // Allocate room for and insert regular params, globals, and the runtime.
-                let mut argvs: SmallVec<LLVMValueRef> =
-                    smallvec![ptr::null_mut(); args.len() + target.globals.len() + 1];
+                // Allocate room for and insert regular params, globals passed as parameters, and
+                // the runtime.
+                let mut argvs: SmallVec<LLVMValueRef> = smallvec![ptr::null_mut(); target.num_args];
                 for (i, arg) in args.iter().cloned().enumerate() {
                     argvs[i] = self.get_param(arg)?;
                 }
-                for (global, ix) in target.globals.iter() {
-                    let cur_ix = source
-                        .globals
-                        .get(global)
-                        .cloned()
-                        .expect("callee must have all globals");
-                    argvs[*ix] = LLVMGetParam(self.f.val, cur_ix as libc::c_uint);
+                for (global, loc) in target.globals.iter() {
+                    if let GlobalRef::Param(ix) = loc {
+                        argvs[*ix] = self
+                            .global_ptr(*global)
+                            .expect("callee must have all globals");
+                    }
                 }
                 let rt_ix = argvs.len() - 1;
-                debug_assert_eq!(rt_ix + 1, target.num_args);
                 argvs[rt_ix] = self.runtime_val();
                 let resv = LLVMBuildCall(
                     self.f.builder,
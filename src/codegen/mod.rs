@@ -8,7 +8,7 @@ use crate::{
     common::{CancelSignal, Cleanup, FileSpec, NumTy, Result, Stage},
     compile,
     pushdown::FieldSet,
-    runtime::{self, UniqueStr},
+    runtime::{self, strict, UniqueStr},
 };
 
 use regex::bytes::Regex;
@@ -22,6 +22,10 @@ use std::sync::Arc;
 pub struct Config {
     pub opt_level: usize,
     pub num_workers: usize,
+    // Write a /tmp/perf-PID.map file naming JIT-compiled functions (LLVM only).
+    pub perf_map: bool,
+    // Emit line-table debug info mapping generated code back to source lines (LLVM only).
+    pub debug_info: bool,
 }
 
 macro_rules! external {
@@ -32,6 +36,7 @@ macro_rules! external {
 
 #[macro_use]
 pub(crate) mod intrinsics;
+#[cfg(feature = "cranelift_backend")]
 pub(crate) mod clif;
 #[cfg(feature = "llvm_backend")]
 pub(crate) mod llvm;
@@ -95,6 +100,9 @@ fn cmp(op: Cmp, is_float: bool) -> Op {
 pub enum FunctionAttr {
     ReadOnly,
     ArgmemOnly,
+    NoUnwind,
+    AlwaysInline,
+    Cold,
 }
 
 /// A handle around a generated main function, potentially allocated dynamically with the given
@@ -141,6 +149,7 @@ pub(crate) unsafe fn run_main<R, FF, J>(
     mut jit: J,
     stdin: R,
     ff: FF,
+    config: runtime::Config,
     used_fields: &FieldSet,
     named_columns: Option<Vec<&[u8]>>,
     num_workers: usize,
@@ -151,7 +160,13 @@ where
     FF: runtime::writers::FileFactory,
     J: Jit,
 {
-    let mut rt = stdin.into_runtime(ff, used_fields, named_columns, cancel_signal.clone());
+    let mut rt = stdin.into_runtime(
+        ff,
+        config,
+        used_fields,
+        named_columns,
+        cancel_signal.clone(),
+    );
     let main = jit.main_functions()?;
     match main {
         Stage::Main(m) => {
@@ -194,12 +209,14 @@ where
 
                 rt.concurrent = true;
 
+                let pinning = rt.core.config.pinning;
                 let (sender, receiver) = crossbeam_channel::bounded(reads.len());
                 let launch_data: Vec<_> = reads
                     .into_iter()
                     .enumerate()
                     .map(|(i, reader)| {
                         (
+                            i + 1,
                             reader,
                             sender.clone(),
                             rt.core.shuttle(i as runtime::Int + 2),
@@ -210,9 +227,11 @@ where
                     let old_read_files = mem::take(&mut read_files.inputs);
                     let main_loop_fn = main_loop.unwrap();
                     let scope_res = crossbeam::scope(|s| {
-                        for (reader, sender, shuttle) in launch_data.into_iter() {
+                        for (worker, reader, sender, shuttle) in launch_data.into_iter() {
                             let cancel_signal = cancel_signal.clone();
                             s.spawn(move |_| {
+                                // Pin before `reader` allocates this worker's input buffers.
+                                runtime::affinity::pin_worker(pinning, worker);
                                 if let Some(reader) = reader() {
                                     let mut runtime = Runtime {
                                         concurrent: true,
@@ -229,6 +248,7 @@ where
                         }
                         mem::drop(sender);
                         {
+                            runtime::affinity::pin_worker(pinning, 0);
                             rt.core.vars.pid = 1;
                             let r = receiver.clone();
                             rt.cleanup =
@@ -242,6 +262,8 @@ where
                             while let Ok(res) = receiver.recv() {
                                 rt.core.combine(res);
                             }
+                            // Write errors resurface when stdout is flushed on shutdown.
+                            let _ = runtime::writers::flush_ordered();
                             if let Some(rc) = cancel_signal.get_code() {
                                 mem::drop(rt);
                                 std::process::exit(rc);
@@ -311,6 +333,11 @@ pub(crate) trait CodeGenerator: Backend {
     // const ptr should not be called directly.
     fn const_ptr<T>(&mut self, r: *const T) -> Self::Val;
     fn handles(&mut self) -> &mut Handles;
+    // Whether conversions from strings to numbers call the checked intrinsics (see
+    // `Typer::check_numbers`).
+    fn check_numbers(&self) -> bool;
+    // Whether the program runs in strict mode (see `Typer::strict`).
+    fn strict(&self) -> bool;
 
     // const_{re,slice} take an `Arc` so that it can store a pointer to `c` and ensure references
     // to `c` will live as long as the generated code.
@@ -326,6 +353,12 @@ pub(crate) trait CodeGenerator: Backend {
         res
     }
 
+    /// Attribute subsequent instructions to the given (1-indexed) source line. Backends that do
+    /// not emit debug info can ignore this.
+    fn set_debug_line(&mut self, _line: u32) -> Result<()> {
+        Ok(())
+    }
+
     // NB: why &mut [..] everywhere instead of &[..] or impl Iterator<..>? The LLVM C API takes a
     // sequence of arguments by a mutable pointer to the first element along with a length. We
     // could take `args.as_ptr() as *mut _`, as most of these LLVM calls (probably?) don't actually
@@ -376,6 +409,10 @@ pub(crate) trait CodeGenerator: Backend {
     /// Advances the iterator in `iter` to the next element and stores the current element in `dst`
     fn iter_getnext(&mut self, dst: Ref, iter: Ref) -> Result<()>;
 
+    /// Checks that the divisor in `div` (a float or int register) is nonzero, calling the
+    /// `division_by_zero` intrinsic from a cold block if it is not.
+    fn check_divisor(&mut self, div: Ref, is_mod: bool) -> Result<()>;
+
     // derived functions
 
     /// Loads contents of given slot into dst.
@@ -488,6 +525,69 @@ pub(crate) trait CodeGenerator: Backend {
         Ok(())
     }
 
+    fn dump_map(&mut self, name: &impl Accum, map: Ref) -> Result<()> {
+        use compile::Ty::*;
+        let func = match map.1 {
+            MapIntInt => external!(dump_intint),
+            MapIntFloat => external!(dump_intfloat),
+            MapIntStr => external!(dump_intstr),
+            MapStrInt => external!(dump_strint),
+            MapStrFloat => external!(dump_strfloat),
+            MapStrStr => external!(dump_strstr),
+            ty => return err!("non-map type: {:?}", ty),
+        };
+        let rt = self.runtime_val();
+        let namev = self.get_val(name.reflect())?;
+        let mapv = self.get_val(map)?;
+        self.call_void(func, &mut [rt, namev, mapv])?;
+        Ok(())
+    }
+
+    fn checkpoint_map(&mut self, name: &impl Accum, map: Ref) -> Result<()> {
+        use compile::Ty::*;
+        let func = match map.1 {
+            MapIntInt => external!(checkpoint_intint),
+            MapIntFloat => external!(checkpoint_intfloat),
+            MapIntStr => external!(checkpoint_intstr),
+            MapStrInt => external!(checkpoint_strint),
+            MapStrFloat => external!(checkpoint_strfloat),
+            MapStrStr => external!(checkpoint_strstr),
+            ty => return err!("non-map type: {:?}", ty),
+        };
+        let rt = self.runtime_val();
+        let namev = self.get_val(name.reflect())?;
+        let mapv = self.get_val(map)?;
+        self.call_void(func, &mut [rt, namev, mapv])?;
+        Ok(())
+    }
+
+    /// Sets `var` to its value in the checkpoint being resumed from, or adds the saved entries to
+    /// it if it is a map.
+    fn restore_var(&mut self, name: &impl Accum, var: Ref) -> Result<()> {
+        use compile::Ty::*;
+        let rt = self.runtime_val();
+        let namev = self.get_val(name.reflect())?;
+        let func = match var.1 {
+            Int => external!(restore_int),
+            Float => external!(restore_float),
+            Str => external!(restore_str),
+            MapIntInt => external!(restore_intint),
+            MapIntFloat => external!(restore_intfloat),
+            MapIntStr => external!(restore_intstr),
+            MapStrInt => external!(restore_strint),
+            MapStrFloat => external!(restore_strfloat),
+            MapStrStr => external!(restore_strstr),
+            ty => return err!("cannot restore a value of type {:?}", ty),
+        };
+        let varv = self.get_val(var)?;
+        if var.1.is_array() {
+            self.call_void(func, &mut [rt, namev, varv])?;
+            return Ok(());
+        }
+        let resv = self.call_intrinsic(Op::Intrinsic(func), &mut [rt, namev, varv])?;
+        self.bind_val(var, resv)
+    }
+
     /// Determines if `map` contains `key` and stores the result (0 or 1) in `dst`.
     ///
     /// Assumes that map and key types match up.
@@ -577,6 +677,63 @@ pub(crate) trait CodeGenerator: Backend {
         self.bind_val(dst, resv)
     }
 
+    /// Stores the keys of `src`, sorted in the order named by `how`, in `dest`, and the number of
+    /// keys in `dst`.
+    fn sort_keys(&mut self, src: Ref, dest: Ref, how: Ref, dst: Ref) -> Result<()> {
+        use compile::Ty::*;
+        let func = match src.1 {
+            MapIntInt => intrinsic!(sort_keys_intint),
+            MapIntFloat => intrinsic!(sort_keys_intfloat),
+            MapIntStr => intrinsic!(sort_keys_intstr),
+            MapStrInt => intrinsic!(sort_keys_strint),
+            MapStrFloat => intrinsic!(sort_keys_strfloat),
+            MapStrStr => intrinsic!(sort_keys_strstr),
+            ty => return err!("non-map type: {:?}", ty),
+        };
+        let rt = self.runtime_val();
+        let srcv = self.get_val(src)?;
+        let destv = self.get_val(dest)?;
+        let howv = self.get_val(how)?;
+        let resv = self.call_intrinsic(func, &mut [rt, srcv, destv, howv])?;
+        self.bind_val(dst, resv)
+    }
+
+    /// Replaces the contents of `to` with those of `from`, storing the number of entries in `dst`.
+    fn copy_map(&mut self, to: Ref, from: Ref, dst: Ref) -> Result<()> {
+        use compile::Ty::*;
+        let func = match to.1 {
+            MapIntInt => intrinsic!(copy_intint),
+            MapIntFloat => intrinsic!(copy_intfloat),
+            MapIntStr => intrinsic!(copy_intstr),
+            MapStrInt => intrinsic!(copy_strint),
+            MapStrFloat => intrinsic!(copy_strfloat),
+            MapStrStr => intrinsic!(copy_strstr),
+            ty => return err!("non-map type: {:?}", ty),
+        };
+        let tov = self.get_val(to)?;
+        let fromv = self.get_val(from)?;
+        let resv = self.call_intrinsic(func, &mut [tov, fromv])?;
+        self.bind_val(dst, resv)
+    }
+
+    /// Offers `val` to the reservoir sample of up to `k` values kept in `map`, storing the number
+    /// of values offered to `map` so far in `dst`.
+    fn sample_map(&mut self, map: Ref, k: Ref, val: Ref, dst: Ref) -> Result<()> {
+        use compile::Ty::*;
+        let func = match map.1 {
+            MapIntInt => intrinsic!(sample_intint),
+            MapIntFloat => intrinsic!(sample_intfloat),
+            MapIntStr => intrinsic!(sample_intstr),
+            ty => return err!("invalid map type passed to sample_map: {:?}", ty),
+        };
+        let rt = self.runtime_val();
+        let mapv = self.get_val(map)?;
+        let kv = self.get_val(k)?;
+        let valv = self.get_val(val)?;
+        let resv = self.call_intrinsic(func, &mut [rt, mapv, kv, valv])?;
+        self.bind_val(dst, resv)
+    }
+
     /// Wraps `call_intrinsic` for [`Op`]s that have two arguments and return a value.
     fn binop(&mut self, op: Op, dst: &impl Accum, l: &impl Accum, r: &impl Accum) -> Result<()> {
         let lv = self.get_val(l.reflect())?;
@@ -592,6 +749,15 @@ pub(crate) trait CodeGenerator: Backend {
         self.bind_val(dst.reflect(), res)
     }
 
+    // Like `unop`, for intrinsics that also take the runtime, such as the checked conversions used
+    // with --strict.
+    fn rt_unop(&mut self, op: Op, dst: &impl Accum, x: &impl Accum) -> Result<()> {
+        let rt = self.runtime_val();
+        let xv = self.get_val(x.reflect())?;
+        let res = self.call_intrinsic(op, &mut [rt, xv])?;
+        self.bind_val(dst.reflect(), res)
+    }
+
     fn gen_ll_inst(&mut self, inst: &compile::LL) -> Result<()> {
         use crate::bytecode::Instr::*;
         match inst {
@@ -609,19 +775,45 @@ pub(crate) trait CodeGenerator: Backend {
             }
             IntToStr(sr, ir) => self.unop(intrinsic!(int_to_str), sr, ir),
             FloatToStr(sr, fr) => self.unop(intrinsic!(float_to_str), sr, fr),
+            StrToInt(ir, sr) if self.check_numbers() => {
+                self.rt_unop(intrinsic!(strict_str_to_int), ir, sr)
+            }
             StrToInt(ir, sr) => self.unop(intrinsic!(str_to_int), ir, sr),
             HexStrToInt(ir, sr) => self.unop(intrinsic!(hex_str_to_int), ir, sr),
+            StrToFloat(fr, sr) if self.check_numbers() => {
+                self.rt_unop(intrinsic!(strict_str_to_float), fr, sr)
+            }
             StrToFloat(fr, sr) => self.unop(intrinsic!(str_to_float), fr, sr),
             FloatToInt(ir, fr) => self.unop(Op::FloatToInt, ir, fr),
             IntToFloat(fr, ir) => self.unop(Op::IntToFloat, fr, ir),
             ToLowerAscii(dst, src) => self.unop(intrinsic!(to_lower_ascii), dst, src),
             ToUpperAscii(dst, src) => self.unop(intrinsic!(to_upper_ascii), dst, src),
+            GetXml(dst, doc, path) => {
+                let rt = self.runtime_val();
+                let docv = self.get_val(doc.reflect())?;
+                let pathv = self.get_val(path.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(get_xml), &mut [rt, docv, pathv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Strftime(dst, fmt, ts, tz) => {
+                let rt = self.runtime_val();
+                let fmtv = self.get_val(fmt.reflect())?;
+                let tsv = self.get_val(ts.reflect())?;
+                let tzv = self.get_val(tz.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(strftime), &mut [rt, fmtv, tsv, tzv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            ParseDate(dst, s) => self.unop(intrinsic!(parsedate), dst, s),
+            Chr(dst, n) => self.rt_unop(intrinsic!(chr), dst, n),
+            Ord(dst, s) => self.rt_unop(intrinsic!(ord), dst, s),
             AddInt(res, l, r) => self.binop(op(Arith::Add, false), res, l, r),
             AddFloat(res, l, r) => self.binop(op(Arith::Add, true), res, l, r),
             MinusInt(res, l, r) => self.binop(op(Arith::Minus, false), res, l, r),
             MinusFloat(res, l, r) => self.binop(op(Arith::Minus, true), res, l, r),
             MulInt(res, l, r) => self.binop(op(Arith::Mul, false), res, l, r),
             MulFloat(res, l, r) => self.binop(op(Arith::Mul, true), res, l, r),
+            // Division by zero is an error unless --ieee-div is set, which requires a call into
+            // the runtime.
             ModInt(res, l, r) => self.binop(op(Arith::Mod, false), res, l, r),
             ModFloat(res, l, r) => self.binop(op(Arith::Mod, true), res, l, r),
             Div(res, l, r) => self.binop(Op::Div, res, l, r),
@@ -673,6 +865,19 @@ pub(crate) trait CodeGenerator: Backend {
                 let res = self.call_intrinsic(intrinsic!(reseed_rng), &mut [rt])?;
                 self.bind_val(dst.reflect(), res)
             }
+            Sleep(dst, secs) => {
+                let secsv = self.get_val(secs.reflect())?;
+                let res = self.call_intrinsic(intrinsic!(sleep_secs), &mut [secsv])?;
+                self.bind_val(dst.reflect(), res)
+            }
+            TimeOfDay(dst) => {
+                let res = self.call_intrinsic(intrinsic!(time_of_day), &mut [])?;
+                self.bind_val(dst.reflect(), res)
+            }
+            Monotonic(dst) => {
+                let res = self.call_intrinsic(intrinsic!(monotonic_time), &mut [])?;
+                self.bind_val(dst.reflect(), res)
+            }
             Concat(dst, l, r) => self.binop(intrinsic!(concat), dst, l, r),
             StartsWithConst(dst, s, bs) => {
                 let s = self.get_val(s.reflect())?;
@@ -709,6 +914,14 @@ pub(crate) trait CodeGenerator: Backend {
                 let resv = self.call_intrinsic(intrinsic!(match_const_pat), &mut [srcv, patv])?;
                 self.bind_val(res.reflect(), resv)
             }
+            // Programs with regex values run in the bytecode interpreter; see
+            // `compile::run_cranelift`.
+            StoreConstRegex(..) | RegexToStr(..) | IsMatchRegex(..) | MatchRegex(..) => {
+                err!(
+                    "regex values are not supported in compiled code: {:?}",
+                    inst
+                )
+            }
             SubstrIndex(dst, s, t) => self.binop(intrinsic!(substr_index), dst, s, t),
             LenStr(dst, x) => self.unop(intrinsic!(str_len), dst, x),
             Sub(res, pat, s, in_s) => {
@@ -729,6 +942,21 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(subst_all), &mut [rt, patv, sv, in_sv])?;
                 self.bind_val(res.reflect(), resv)
             }
+            SubLine(res, pat, s) => {
+                let rt = self.runtime_val();
+                let patv = self.get_val(pat.reflect())?;
+                let sv = self.get_val(s.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(subst_first_line), &mut [rt, patv, sv])?;
+                self.bind_val(res.reflect(), resv)
+            }
+            GSubLine(res, pat, s) => {
+                let rt = self.runtime_val();
+                let patv = self.get_val(pat.reflect())?;
+                let sv = self.get_val(s.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(subst_all_line), &mut [rt, patv, sv])?;
+                self.bind_val(res.reflect(), resv)
+            }
             GenSubDynamic(res, pat, s, how, in_s) => {
                 let rt = self.runtime_val();
                 let patv = self.get_val(pat.reflect())?;
@@ -739,8 +967,10 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(gen_subst), &mut [rt, patv, sv, howv, in_sv])?;
                 self.bind_val(res.reflect(), resv)
             }
-            EscapeCSV(dst, s) => self.unop(intrinsic!(escape_csv), dst, s),
+            EscapeCSV(dst, s) => self.rt_unop(intrinsic!(escape_csv), dst, s),
             EscapeTSV(dst, s) => self.unop(intrinsic!(escape_tsv), dst, s),
+            EscapeJSON(dst, s) => self.unop(intrinsic!(escape_json), dst, s),
+            EscapeMsgPack(dst, s) => self.unop(intrinsic!(escape_msgpack), dst, s),
             Substr(res, base, l, r) => {
                 let basev = self.get_val(base.reflect())?;
                 let lv = self.get_val(l.reflect())?;
@@ -763,6 +993,7 @@ pub(crate) trait CodeGenerator: Backend {
             LTEStr(res, l, r) => self.binop(intrinsic!(str_lte), res, l, r),
             GTEStr(res, l, r) => self.binop(intrinsic!(str_gte), res, l, r),
             EQStr(res, l, r) => self.binop(intrinsic!(str_eq), res, l, r),
+            StrNumCmp(res, l, r) => self.binop(intrinsic!(strnum_cmp), res, l, r),
             SetColumn(dst, src) => {
                 let rt = self.runtime_val();
                 let srcv = self.get_val(src.reflect())?;
@@ -770,6 +1001,18 @@ pub(crate) trait CodeGenerator: Backend {
                 self.call_void(external!(set_col), &mut [rt, dstv, srcv])?;
                 Ok(())
             }
+            Unpack(dst, fmt) => {
+                let rt = self.runtime_val();
+                let fmtv = self.get_val(fmt.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(unpack), &mut [rt, fmtv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Resplit(dst, fs) => {
+                let rt = self.runtime_val();
+                let fsv = self.get_val(fs.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(resplit), &mut [rt, fsv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             GetColumn(dst, src) => {
                 let rt = self.runtime_val();
                 let srcv = self.get_val(src.reflect())?;
@@ -790,6 +1033,35 @@ pub(crate) trait CodeGenerator: Backend {
                 let resv = self.call_intrinsic(intrinsic!(join_tsv), &mut [rt, startv, endv])?;
                 self.bind_val(dst.reflect(), resv)
             }
+            JoinJSON(dst, start, end) => {
+                let rt = self.runtime_val();
+                let startv = self.get_val(start.reflect())?;
+                let endv = self.get_val(end.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(join_json), &mut [rt, startv, endv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            JsonKey(dst, col, pos) => {
+                let rt = self.runtime_val();
+                let colv = self.get_val(col.reflect())?;
+                let posv = self.get_val(pos.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(json_key), &mut [rt, colv, posv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            JoinMsgPack(dst, start, end) => {
+                let rt = self.runtime_val();
+                let startv = self.get_val(start.reflect())?;
+                let endv = self.get_val(end.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(join_msgpack), &mut [rt, startv, endv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MsgPackKey(dst, col, pos) => {
+                let rt = self.runtime_val();
+                let colv = self.get_val(col.reflect())?;
+                let posv = self.get_val(pos.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(msgpack_key), &mut [rt, colv, posv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             JoinColumns(dst, start, end, sep) => {
                 let rt = self.runtime_val();
                 let startv = self.get_val(start.reflect())?;
@@ -799,24 +1071,45 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(join_cols), &mut [rt, startv, endv, sepv])?;
                 self.bind_val(dst.reflect(), resv)
             }
-            SplitInt(flds, to_split, arr, pat) => {
+            SplitInt(flds, to_split, arr, pat, limit) => {
                 let rt = self.runtime_val();
                 let tsv = self.get_val(to_split.reflect())?;
                 let arrv = self.get_val(arr.reflect())?;
                 let patv = self.get_val(pat.reflect())?;
+                let limitv = self.get_val(limit.reflect())?;
                 let fldsv =
-                    self.call_intrinsic(intrinsic!(split_int), &mut [rt, tsv, arrv, patv])?;
+                    self.call_intrinsic(intrinsic!(split_int), &mut [rt, tsv, arrv, patv, limitv])?;
                 self.bind_val(flds.reflect(), fldsv)
             }
-            SplitStr(flds, to_split, arr, pat) => {
+            SplitStr(flds, to_split, arr, pat, limit) => {
                 let rt = self.runtime_val();
                 let tsv = self.get_val(to_split.reflect())?;
                 let arrv = self.get_val(arr.reflect())?;
                 let patv = self.get_val(pat.reflect())?;
+                let limitv = self.get_val(limit.reflect())?;
                 let fldsv =
-                    self.call_intrinsic(intrinsic!(split_str), &mut [rt, tsv, arrv, patv])?;
+                    self.call_intrinsic(intrinsic!(split_str), &mut [rt, tsv, arrv, patv, limitv])?;
                 self.bind_val(flds.reflect(), fldsv)
             }
+            MatchArr(dst, s, pat, arr) => {
+                let rt = self.runtime_val();
+                let sv = self.get_val(s.reflect())?;
+                let patv = self.get_val(pat.reflect())?;
+                let arrv = self.get_val(arr.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(match_pat_captures), &mut [rt, sv, patv, arrv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MatchAll(dst, s, pat, arr, group) => {
+                let rt = self.runtime_val();
+                let sv = self.get_val(s.reflect())?;
+                let patv = self.get_val(pat.reflect())?;
+                let arrv = self.get_val(arr.reflect())?;
+                let groupv = self.get_val(group.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(match_all), &mut [rt, sv, patv, arrv, groupv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Printf { output, fmt, args } => self.printf(output, fmt, &args[..]),
             Sprintf { dst, fmt, args } => self.sprintf(dst, fmt, &args[..]),
             PrintAll { output, args } => self.print_all(output, &args[..]),
@@ -827,12 +1120,79 @@ pub(crate) trait CodeGenerator: Backend {
                 Ok(())
             }
             RunCmd(dst, cmd) => self.unop(intrinsic!(run_system), dst, cmd),
+            UsePty(dst, cmd) => self.unop(intrinsic!(use_pty), dst, cmd),
+            HllAdd(dst, sketch, value) => self.binop(intrinsic!(hll_add), dst, sketch, value),
+            HllCount(dst, sketch) => self.unop(intrinsic!(hll_count), dst, sketch),
+            TopK(dst, heap, k, key, weight) => {
+                let rt = self.runtime_val();
+                let heapv = self.get_val(heap.reflect())?;
+                let kv = self.get_val(k.reflect())?;
+                let keyv = self.get_val(key.reflect())?;
+                let weightv = self.get_val(weight.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(topk), &mut [rt, heapv, kv, keyv, weightv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            CmdStatus(dst, cmd) => {
+                let rt = self.runtime_val();
+                let cmdv = self.get_val(cmd.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(cmd_status), &mut [rt, cmdv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Exit(code) => {
                 let rt = self.runtime_val();
                 let codev = self.get_val(code.reflect())?;
                 self.call_void(external!(exit), &mut [rt, codev])?;
                 Ok(())
             }
+            Assert(cond, msg, loc) => {
+                let rt = self.runtime_val();
+                let condv = self.get_val(cond.reflect())?;
+                let msgv = self.get_val(msg.reflect())?;
+                let locv = self.get_val(loc.reflect())?;
+                self.call_void(external!(assert_cond), &mut [rt, condv, msgv, locv])?;
+                Ok(())
+            }
+            CheckDivisor(fr, is_mod) => self.check_divisor(fr.reflect(), *is_mod),
+            CheckDivisorInt(ir) => self.check_divisor(ir.reflect(), true),
+            CheckIndex(fr) => {
+                let rt = self.runtime_val();
+                let fv = self.get_val(fr.reflect())?;
+                self.call_void(external!(check_index), &mut [rt, fv])?;
+                Ok(())
+            }
+            DumpScalar(name, val) => {
+                let rt = self.runtime_val();
+                let namev = self.get_val(name.reflect())?;
+                let valv = self.get_val(val.reflect())?;
+                self.call_void(external!(dump_scalar), &mut [rt, namev, valv])?;
+                Ok(())
+            }
+            DumpMap { name, map_ty, map } => self.dump_map(name, (*map, *map_ty)),
+            DumpRequested(dst) => {
+                let rt = self.runtime_val();
+                let resv = self.call_intrinsic(intrinsic!(dump_requested), &mut [rt])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            CheckpointScalar(name, val) => {
+                let rt = self.runtime_val();
+                let namev = self.get_val(name.reflect())?;
+                let valv = self.get_val(val.reflect())?;
+                self.call_void(external!(checkpoint_scalar), &mut [rt, namev, valv])?;
+                Ok(())
+            }
+            CheckpointMap { name, map_ty, map } => self.checkpoint_map(name, (*map, *map_ty)),
+            WriteCheckpoint() => {
+                let rt = self.runtime_val();
+                self.call_void(external!(write_checkpoint), &mut [rt])?;
+                Ok(())
+            }
+            CheckpointDue(dst) => {
+                let rt = self.runtime_val();
+                let resv = self.call_intrinsic(intrinsic!(checkpoint_due), &mut [rt])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            RestoreVar { name, ty, var } => self.restore_var(name, (*var, *ty)),
             ReadErr(dst, file, is_file) => {
                 let rt = self.runtime_val();
                 let filev = self.get_val(file.reflect())?;
@@ -853,6 +1213,11 @@ pub(crate) trait CodeGenerator: Backend {
                 let resv = self.call_intrinsic(intrinsic!(read_err_stdin), &mut [rt])?;
                 self.bind_val(dst.reflect(), resv)
             }
+            FileBoundary(dst) => {
+                let rt = self.runtime_val();
+                let resv = self.call_intrinsic(intrinsic!(file_boundary), &mut [rt])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             NextLineStdin(dst) => {
                 let rt = self.runtime_val();
                 let resv = self.call_intrinsic(intrinsic!(next_line_stdin), &mut [rt])?;
@@ -863,6 +1228,13 @@ pub(crate) trait CodeGenerator: Backend {
                 self.call_void(external!(next_line_stdin_fused), &mut [rt])?;
                 Ok(())
             }
+            SetLine(line) => {
+                if self.strict() {
+                    let linev = self.const_int(*line as i64);
+                    self.call_void(external!(set_line), &mut [linev])?;
+                }
+                self.set_debug_line(*line)
+            }
             NextFile() => {
                 let rt = self.runtime_val();
                 self.call_void(external!(next_file), &mut [rt])?;
@@ -940,6 +1312,36 @@ pub(crate) trait CodeGenerator: Backend {
                 by.reflect(),
                 (*dst, map_ty.val()?),
             ),
+            CopyMap {
+                map_ty,
+                dst,
+                to,
+                from,
+            } => self.copy_map((*to, *map_ty), (*from, *map_ty), dst.reflect()),
+            Sample {
+                map_ty,
+                dst,
+                map,
+                k,
+                val,
+            } => self.sample_map(
+                (*map, *map_ty),
+                k.reflect(),
+                (*val, map_ty.val()?),
+                dst.reflect(),
+            ),
+            SortKeys {
+                map_ty,
+                dst,
+                src,
+                dest,
+                how,
+            } => self.sort_keys(
+                (*src, *map_ty),
+                dest.reflect(),
+                how.reflect(),
+                dst.reflect(),
+            ),
             LoadVarStr(dst, var) => {
                 let rt = self.runtime_val();
                 let varv = self.const_int(*var as i64);
@@ -1007,7 +1409,7 @@ pub(crate) trait CodeGenerator: Backend {
             IterGetNext { iter_ty, dst, iter } => {
                 self.iter_getnext((*dst, iter_ty.iter()?), (*iter, *iter_ty))
             }
-            Push(_, _) | Pop(_, _) => err!("unexpected explicit push/pop in llvm"),
+            PushFrame(_) | PopFrame(_) => err!("unexpected explicit push/pop in llvm"),
             AllocMap(_, _) => {
                 err!("unexpected AllocMap (allocs are handled differently in LLVM)")
             }
@@ -13,6 +13,14 @@ pub(crate) type NodeIx = petgraph::graph::NodeIndex<NumTy>;
 pub(crate) type Graph<V, E> = petgraph::Graph<V, E, petgraph::Directed, NumTy>;
 pub(crate) type Result<T> = std::result::Result<T, CompileError>;
 
+// Exit statuses for errors. Scripts can exit with any status using `exit`, but the statuses below
+// are not otherwise used by frawk itself, so that tools running frawk can tell what went wrong.
+
+/// Invalid command-line arguments, or errors in the program text (e.g. syntax or type errors).
+pub const EXIT_USAGE: i32 = 2;
+/// Errors while running the program, including failures to read input or write output.
+pub const EXIT_RUNTIME: i32 = 3;
+
 #[derive(Copy, Clone)]
 pub enum ExecutionStrategy {
     /// Execute the script in a single thread. This is the default.
@@ -118,6 +126,23 @@ impl<T> Stage<T> {
     }
 }
 
+impl<T> Stage<Result<T>> {
+    pub fn transpose(self) -> Result<Stage<T>> {
+        Ok(match self {
+            Stage::Main(t) => Stage::Main(t?),
+            Stage::Par {
+                begin,
+                main_loop,
+                end,
+            } => Stage::Par {
+                begin: traverse(begin)?,
+                main_loop: traverse(main_loop)?,
+                end: traverse(end)?,
+            },
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Either<L, R> {
     Left(L),
@@ -125,6 +150,7 @@ pub enum Either<L, R> {
 }
 
 // borrowed from weld project.
+#[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
 macro_rules! c_str {
     ($s:expr) => {
         concat!($s, "\0").as_ptr() as *const libc::c_char
@@ -202,6 +228,14 @@ macro_rules! err {
     };
 }
 
+// Like `err!`, but for errors in the program being run or in its input, which users see: these
+// leave out the location in frawk's own source.
+macro_rules! user_err {
+    ($($t:tt)*) => {
+        Err($crate::common::CompileError(format!($($t)*)))
+    };
+}
+
 // We use this for when we want to print an error message, but don't want to panic if we cannot
 // write to standard error.
 macro_rules! eprintln_ignore {
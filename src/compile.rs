@@ -1,16 +1,17 @@
 use crate::builtins;
-use crate::bytecode;
+use crate::bytecode::{self, FrameRegs};
 use crate::cfg::{self, is_unused, Function, Ident, PrimExpr, PrimStmt, PrimVal, ProgramContext};
+#[cfg(any(feature = "llvm_backend", feature = "cranelift_backend"))]
 use crate::codegen;
 #[cfg(feature = "llvm_backend")]
 use crate::codegen::llvm;
-use crate::common::{
-    CancelSignal, CompileError, Either, Graph, NodeIx, NumTy, Result, Stage, WorkList,
-};
+#[cfg(any(feature = "llvm_backend", feature = "cranelift_backend"))]
+use crate::common::CancelSignal;
+use crate::common::{CompileError, Either, Graph, NodeIx, NumTy, Result, Stage, WorkList};
 use crate::cross_stage;
 use crate::input_taint::TaintedStringAnalysis;
 use crate::pushdown::{FieldSet, UsedFieldAnalysis};
-use crate::runtime::{self, Str};
+use crate::runtime::{self, strict, Str};
 use crate::string_constants::{self, StringConstantAnalysis};
 use crate::types;
 
@@ -38,7 +39,8 @@ pub(crate) enum Ty {
     MapStrStr = 8,
     IterInt = 9,
     IterStr = 10,
-    Null = 11,
+    Regex = 11,
+    Null = 12,
 }
 
 pub(crate) const NUM_TYPES: usize = Ty::Null as usize + 1;
@@ -59,7 +61,8 @@ impl std::convert::TryFrom<u32> for Ty {
             8 => MapStrStr,
             9 => IterInt,
             10 => IterStr,
-            11 => Null,
+            11 => Regex,
+            12 => Null,
             _ => return Err(()),
         })
     }
@@ -72,7 +75,7 @@ impl Default for Ty {
 }
 
 impl Ty {
-    fn is_iter(self) -> bool {
+    pub(crate) fn is_iter(self) -> bool {
         matches!(self, Ty::IterInt | Ty::IterStr)
     }
 
@@ -81,7 +84,7 @@ impl Ty {
         match self {
             MapIntInt | MapIntFloat | MapIntStr => Ok(IterInt),
             MapStrInt | MapStrFloat | MapStrStr => Ok(IterStr),
-            Null | Int | Float | Str | IterInt | IterStr => {
+            Null | Int | Float | Str | Regex | IterInt | IterStr => {
                 err!("attempt to get iterator from non-map type: {:?}", self)
             }
         }
@@ -92,7 +95,7 @@ impl Ty {
         match self {
             IterInt => Ok(Int),
             IterStr => Ok(Str),
-            Null | Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
+            Null | Int | Float | Str | Regex | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
             | MapStrFloat | MapStrStr => {
                 err!("attempt to get element of non-iterator type: {:?}", self)
             }
@@ -103,7 +106,7 @@ impl Ty {
         use Ty::*;
         match self {
             MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat | MapStrStr => true,
-            Null | Int | Float | Str | IterInt | IterStr => false,
+            Null | Int | Float | Str | Regex | IterInt | IterStr => false,
         }
     }
 
@@ -112,7 +115,7 @@ impl Ty {
         match self {
             MapIntInt | MapIntFloat | MapIntStr => Ok(Int),
             MapStrInt | MapStrFloat | MapStrStr => Ok(Str),
-            Null | Int | Float | Str | IterInt | IterStr => {
+            Null | Int | Float | Str | Regex | IterInt | IterStr => {
                 err!("attempt to get key of non-map type: {:?}", self)
             }
         }
@@ -124,7 +127,7 @@ impl Ty {
             MapStrInt | MapIntInt => Ok(Int),
             MapStrFloat | MapIntFloat => Ok(Float),
             MapStrStr | MapIntStr => Ok(Str),
-            Null | Int | Float | Str | IterInt | IterStr => {
+            Null | Int | Float | Str | Regex | IterInt | IterStr => {
                 err!("attempt to get val of non-map type: {:?}", self)
             }
         }
@@ -162,10 +165,74 @@ pub(crate) fn bytecode<'a, LR: runtime::LineReader>(
     ff: impl runtime::writers::FileFactory,
     num_workers: usize,
 ) -> Result<bytecode::Interp<'a, LR>> {
-    Typer::init_from_ctx(ctx)?.to_interp(reader, ff, num_workers)
+    let config = ctx.config.clone();
+    Typer::init_from_ctx(ctx)?.to_interp(reader, ff, config, num_workers)
 }
 
-#[cfg(test)]
+/// A program compiled to bytecode that no longer borrows from the arena it was parsed into, so
+/// that it can be kept and run any number of times (see `embed::Program`).
+#[cfg(feature = "embed")]
+pub(crate) struct Compiled {
+    instrs: Vec<Vec<LL<'static>>>,
+    main_func: Stage<usize>,
+    regs: [usize; NUM_TYPES],
+    used_fields: FieldSet,
+    named_columns: Option<Vec<Vec<u8>>>,
+}
+
+#[cfg(feature = "embed")]
+impl Compiled {
+    /// A fresh interpreter for the program, running serially over `reader`.
+    pub(crate) fn interp<LR: runtime::LineReader>(
+        &self,
+        reader: LR,
+        ff: impl runtime::writers::FileFactory,
+        config: runtime::Config,
+    ) -> bytecode::Interp<'static, LR> {
+        bytecode::Interp::new(
+            self.instrs.clone(),
+            self.main_func.clone(),
+            /*num_workers=*/ 1,
+            |ty| self.regs[ty as usize],
+            reader,
+            ff,
+            config,
+            &self.used_fields,
+            self.named_columns
+                .as_ref()
+                .map(|cols| cols.iter().map(Vec::as_slice).collect()),
+        )
+    }
+}
+
+#[cfg(feature = "embed")]
+pub(crate) fn compiled<'a>(ctx: &mut cfg::ProgramContext<'a, &'a str>) -> Result<Compiled> {
+    let mut typer = Typer::init_from_ctx(ctx)?;
+    let instrs = typer.to_bytecode()?;
+    let main_func = typer.stage();
+    bytecode::verify(&instrs, &main_func, |ty| {
+        typer.regs.stats.count(ty) as usize
+    })?;
+    let mut regs = [0; NUM_TYPES];
+    for (count, statuses) in regs.iter_mut().zip(typer.regs.stats.0.iter()) {
+        *count = statuses.len();
+    }
+    Ok(Compiled {
+        instrs: instrs
+            .into_iter()
+            .map(|func| func.into_iter().map(LL::unmoor).collect())
+            .collect(),
+        main_func,
+        regs,
+        used_fields: typer.used_fields.clone(),
+        named_columns: typer
+            .named_columns
+            .take()
+            .map(|cols| cols.into_iter().map(<[u8]>::to_vec).collect()),
+    })
+}
+
+#[cfg(any(test, feature = "fuzzing"))]
 pub(crate) fn context_compiles<'a>(ctx: &mut cfg::ProgramContext<'a, &'a str>) -> Result<()> {
     Typer::init_from_ctx(ctx)?;
     Ok(())
@@ -202,16 +269,37 @@ pub(crate) fn compile_llvm<'a>(
     }
 }
 
+/// Neither JIT backend supports regex values; programs that use them run in the bytecode
+/// interpreter instead.
+#[cfg(any(feature = "llvm_backend", feature = "cranelift_backend"))]
+fn run_interp_fallback<'a, LR: runtime::LineReader>(
+    typer: &mut Typer<'a>,
+    reader: LR,
+    ff: impl runtime::writers::FileFactory,
+    config: runtime::Config,
+    num_workers: usize,
+) -> Result<()> {
+    let rc = typer.to_interp(reader, ff, config, num_workers)?.run()?;
+    if rc != 0 {
+        std::process::exit(rc);
+    }
+    Ok(())
+}
+
 #[cfg(feature = "llvm_backend")]
 pub(crate) fn run_llvm<'a>(
     ctx: &mut cfg::ProgramContext<'a, &'a str>,
-    reader: impl codegen::intrinsics::IntoRuntime,
+    reader: impl codegen::intrinsics::IntoRuntime + runtime::LineReader,
     ff: impl runtime::writers::FileFactory,
     cfg: llvm::Config,
     cancel_signal: CancelSignal,
 ) -> Result<()> {
     use llvm::Generator;
+    let config = ctx.config.clone();
     let mut typer = Typer::init_from_ctx(ctx)?;
+    if typer.regs.stats.count(Ty::Regex) > 0 {
+        return run_interp_fallback(&mut typer, reader, ff, config, cfg.num_workers);
+    }
     let used_fields = typer.used_fields.clone();
     let named_cols = typer.named_columns.take();
     unsafe {
@@ -220,6 +308,7 @@ pub(crate) fn run_llvm<'a>(
             gen,
             reader,
             ff,
+            config,
             &used_fields,
             named_cols,
             cfg.num_workers,
@@ -228,15 +317,20 @@ pub(crate) fn run_llvm<'a>(
     }
 }
 
+#[cfg(feature = "cranelift_backend")]
 pub(crate) fn run_cranelift<'a>(
     ctx: &mut cfg::ProgramContext<'a, &'a str>,
-    reader: impl codegen::intrinsics::IntoRuntime,
+    reader: impl codegen::intrinsics::IntoRuntime + runtime::LineReader,
     ff: impl runtime::writers::FileFactory,
     cfg: codegen::Config,
     cancel_signal: CancelSignal,
 ) -> Result<()> {
     use codegen::clif::Generator;
+    let config = ctx.config.clone();
     let mut typer = Typer::init_from_ctx(ctx)?;
+    if typer.regs.stats.count(Ty::Regex) > 0 {
+        return run_interp_fallback(&mut typer, reader, ff, config, cfg.num_workers);
+    }
     let used_fields = typer.used_fields.clone();
     let named_cols = typer.named_columns.take();
     unsafe {
@@ -245,6 +339,7 @@ pub(crate) fn run_cranelift<'a>(
             gen,
             reader,
             ff,
+            config,
             &used_fields,
             named_cols,
             cfg.num_workers,
@@ -354,6 +449,11 @@ pub(crate) struct Typer<'a> {
 
     // For projection pushdown
     used_fields: FieldSet,
+    // Whether strings are converted to numbers by the runtime, to check them as they are converted
+    // (see `strict::checks_numbers`) or to parse them with a decimal comma.
+    pub check_numbers: bool,
+    // Whether lossy implicit conversions are errors; see `runtime::Config::strict`.
+    pub strict: bool,
     // The fields referenced by name via the FI builtin variable
     named_columns: Option<Vec<&'a [u8]>>,
     // For rejecting suspcicious programs with commands.
@@ -400,6 +500,8 @@ pub(crate) struct FuncInfo {
 #[derive(Default)]
 pub(crate) struct Frame<'a> {
     src_function: NumTy,
+    // The name of the source function, for debugging and profiling.
+    pub name: String,
     cur_ident: NumTy,
     entry: NodeIx,
     exit: NodeIx,
@@ -410,6 +512,16 @@ pub(crate) struct Frame<'a> {
 }
 
 impl<'a> Frame<'a> {
+    /// The number of instructions in the frame; a rough measure of its size.
+    #[cfg(feature = "llvm_backend")]
+    pub(crate) fn num_insts(&self) -> usize {
+        self.cfg
+            .raw_nodes()
+            .iter()
+            .map(|n| n.weight.insts.len())
+            .sum()
+    }
+
     fn load_slots(
         &mut self,
         regs: impl Iterator<Item = (NumTy, Ty)>,
@@ -448,35 +560,15 @@ struct View<'a, 'b> {
     local_globals: &'b HashSet<NumTy>,
     arity: &'b HashMap<NumTy, NumTy>,
     func_info: &'b Vec<FuncInfo>,
+    // Leave out the checks for division by zero; see `ProgramContext::ieee_div`.
+    ieee_div: bool,
+    // Check that floats used as array indexes are integers; see `runtime::Config::strict`.
+    strict: bool,
     // The current basic block being filled; It'll be swaped into `frame.cfg` as we translate a
     // given function cfg.
     stream: &'b mut Node<'a>,
 }
 
-fn pop_var(instrs: &mut Vec<LL>, reg: NumTy, ty: Ty) -> Result<()> {
-    use Ty::*;
-    instrs.push(match ty {
-        Null => return Ok(()),
-        IterInt | IterStr => return err!("invalid argument type: {:?}", ty),
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => LL::Pop(ty, reg),
-    });
-    Ok(())
-}
-
-fn push_var(instrs: &mut Vec<LL>, reg: NumTy, ty: Ty) -> Result<()> {
-    use Ty::*;
-    match ty {
-        Null => Ok(()),
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => {
-            instrs.push(LL::Push(ty, reg));
-            Ok(())
-        }
-        IterInt | IterStr => err!("invalid argument type: {:?}", ty),
-    }
-}
-
 fn alloc_local<'a>(dst_reg: NumTy, dst_ty: Ty) -> Option<LL<'a>> {
     use Ty::*;
     match dst_ty {
@@ -495,8 +587,8 @@ fn mov<'a>(dst_reg: u32, src_reg: u32, ty: Ty) -> Result<Option<LL<'a>>> {
     let res = match ty {
         Null => return Ok(None),
         IterInt | IterStr => return err!("attempt to move values of type {:?}", ty),
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => LL::Mov(ty, dst_reg, src_reg),
+        Int | Float | Str | Regex | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
+        | MapStrFloat | MapStrStr => LL::Mov(ty, dst_reg, src_reg),
     };
 
     Ok(Some(res))
@@ -526,14 +618,27 @@ impl<'a> Typer<'a> {
         self.main_offset.clone()
     }
 
+    /// Whether the function can call itself, directly or indirectly.
+    #[cfg(feature = "llvm_backend")]
+    pub(crate) fn is_recursive(&self, func_id: usize) -> bool {
+        let ix = NodeIx::new(func_id);
+        self.callgraph
+            .neighbors(ix)
+            .any(|callee| petgraph::algo::has_path_connecting(&self.callgraph, callee, ix, None))
+    }
+
     #[allow(clippy::wrong_self_convention)]
     fn to_interp<LR: runtime::LineReader>(
         &mut self,
         reader: LR,
         ff: impl runtime::writers::FileFactory,
+        config: runtime::Config,
         num_workers: usize,
     ) -> Result<bytecode::Interp<'a, LR>> {
         let instrs = self.to_bytecode()?;
+        bytecode::verify(&instrs, &self.stage(), |ty| {
+            self.regs.stats.count(ty) as usize
+        })?;
         let cols = self.named_columns.take();
         Ok(bytecode::Interp::new(
             instrs,
@@ -542,6 +647,7 @@ impl<'a> Typer<'a> {
             |ty| self.regs.stats.count(ty) as usize,
             reader,
             ff,
+            config,
             &self.used_fields,
             cols,
         ))
@@ -561,8 +667,6 @@ impl<'a> Typer<'a> {
         let mut jmps: Vec<usize> = Vec::new();
         // If we wanted to, we could colocate locals and args, but absent a serious performance
         // issue this seems cleaner.
-        let mut args: Vec<(NumTy, Ty)> = Vec::new();
-        let mut locals: Vec<(NumTy, Ty)> = Vec::new();
         for (i, frame) in self.frames.iter().enumerate() {
             if !frame.is_called {
                 continue;
@@ -573,14 +677,28 @@ impl<'a> Typer<'a> {
             jmps.clear();
 
             // Start by popping any args off of the stack.
-            args.extend(
-                frame
-                    .arg_regs
-                    .iter()
-                    .cloned()
-                    .zip(self.func_info[i].arg_tys.iter().cloned()),
-            );
-            args.reverse();
+            let mut args = FrameRegs::default();
+            for (reg, ty) in frame
+                .arg_regs
+                .iter()
+                .cloned()
+                .zip(self.func_info[i].arg_tys.iter().cloned())
+            {
+                args.add(reg, ty)?;
+            }
+
+            // The local variables saved and restored around each call site.
+            // NB locals does not contain all of the local registers, though the ones it does not
+            // cover are "transient" in that we have no way to get a handle on them outside of the
+            // immediate context in which they are constructed (e.g. through reg_of_ty). I believe
+            // that means we can rule them out as being needed across callsites.
+            let mut locals = FrameRegs::default();
+            for (reg, ty) in frame.locals.values().cloned() {
+                if !ty.is_iter() {
+                    locals.add(reg, ty)?;
+                }
+            }
+            let locals = Arc::new(locals);
 
             // Some local variables (maps, at time of writing) must be explicitly reallocated to
             // handle the case where no value is passed as an argument. We do this before popping
@@ -597,8 +715,8 @@ impl<'a> Typer<'a> {
             {
                 instrs.push(instr);
             }
-            for (a_reg, a_ty) in args.drain(..) {
-                pop_var(instrs, a_reg, a_ty)?;
+            if !args.is_empty() {
+                instrs.push(LL::PopFrame(Arc::new(args)));
             }
 
             for (j, n) in frame.cfg.raw_nodes().iter().enumerate() {
@@ -615,37 +733,25 @@ impl<'a> Typer<'a> {
                         }) => {
                             // args have already been normalized, and return type already matches.
                             // All we need to do is push local variables (to avoid clobbers) and
-                            // push args onto the stack.
-                            // NB locals does not contain all of the local registers, though the
-                            // ones it does not cover are "transient" in that we have no way to get
-                            // a handle on them outside of the immediate context in which they are
-                            // constructed (e.g. through reg_of_ty). I believe that means we can
-                            // rule them out as being needed across callsites.
+                            // push args onto the stack, all in a single frame.
                             //
-                            // Today, function calls are a bit slow because of all these pushes (we
-                            // have a lot of local variables because we do not reuse registers). We
-                            // may want to optimize this by looking only over variables referenced
-                            // in reachable BBs from the current one.
-                            locals.clear();
-                            locals.extend(frame.locals.values().cloned());
-                            for (reg, ty) in locals.iter().cloned() {
-                                if !ty.is_iter() {
-                                    push_var(instrs, reg, ty)?;
-                                }
-                            }
+                            // We still save a lot of local variables because we do not reuse
+                            // registers. We may want to optimize this by looking only over
+                            // variables referenced in reachable BBs from the current one.
+                            let mut pushed = (*locals).clone();
                             for (reg, ty) in args.iter().cloned() {
                                 assert!(!ty.is_iter());
-                                push_var(instrs, reg, ty)?;
+                                pushed.add(reg, ty)?;
+                            }
+                            if !pushed.is_empty() {
+                                instrs.push(LL::PushFrame(Arc::new(pushed)));
                             }
                             let callee = *func_id as usize;
                             instrs.push(LL::Call(callee));
 
                             // Restore local variables
-                            locals.reverse();
-                            for (reg, ty) in locals.iter().cloned() {
-                                if !ty.is_iter() {
-                                    pop_var(instrs, reg, ty)?;
-                                }
+                            if !locals.is_empty() {
+                                instrs.push(LL::PopFrame(locals.clone()));
                             }
                             let ret_reg = ret_regs[callee];
                             debug_assert_eq!(self.func_info[callee].ret_ty, *dst_ty);
@@ -719,7 +825,11 @@ impl<'a> Typer<'a> {
         // Type-check the code, then initialize a Typer, assigning registers to local
         // and global variables.
 
-        let mut gen = Typer::default();
+        let mut gen = Typer {
+            check_numbers: strict::checks_numbers(&pc.config) || pc.config.decimal_comma,
+            strict: pc.config.strict,
+            ..Default::default()
+        };
         if !pc.allow_arbitrary_commands {
             gen.taint_analysis = Some(Default::default());
         }
@@ -741,6 +851,7 @@ impl<'a> Typer<'a> {
                     $v.insert(res);
                     let mut f = Frame::default();
                     f.src_function = $func_id;
+                    f.name = pc.funcs[$func_id as usize].name.to_string();
                     f.cur_ident = res;
                     gen.frames.push(f);
                     gen.callgraph.add_node(Default::default());
@@ -802,6 +913,8 @@ impl<'a> Typer<'a> {
                 arity: &gen.arity,
                 local_globals: &gen.local_globals,
                 func_info: &gen.func_info,
+                ieee_div: pc.ieee_div,
+                strict: pc.config.strict,
                 stream: &mut stream,
             }
             .process_function(&pc.funcs[src_func])?;
@@ -1057,9 +1170,10 @@ impl<'a, 'b> View<'a, 'b> {
                                 self.convert(dst, Ty::Int, reg, ty)?;
                                 reg = dst;
                             }
-                            Ty::Str => {
+                            Ty::Str | Ty::Regex => {
+                                let s = self.ensure_ty(reg, ty, Ty::Str)?;
                                 let dst = self.regs.stats.reg_of_ty(Ty::Int);
-                                self.pushl(LL::LenStr(dst.into(), reg.into()));
+                                self.pushl(LL::LenStr(dst.into(), s.into()));
                                 reg = dst;
                             }
                             _ => return err!("invalid type for branch: {:?} :: {:?}", val, ty),
@@ -1156,10 +1270,23 @@ impl<'a, 'b> View<'a, 'b> {
                 self.pushl(LL::StoreConstStr(nreg.into(), Str::from(*s).into()));
                 Ok((nreg, Ty::Str, Local))
             }
+            PrimVal::RegexLit(s) => {
+                let nreg = self.regs.stats.new_reg(Ty::Regex, Local);
+                self.pushl(LL::StoreConstRegex(nreg.into(), regex_lit(s)?));
+                Ok((nreg, Ty::Regex, Local))
+            }
             PrimVal::Var(v) => Ok(self.reg_of_ident_status(v)),
         }
     }
 
+    // Arguments to printf and sprintf, which format regexes as the text of their pattern.
+    fn format_arg(&mut self, v: &PrimVal<'a>) -> Result<(u32, Ty)> {
+        match self.get_reg(v)? {
+            (reg, Ty::Regex) => Ok((self.ensure_ty(reg, Ty::Regex, Ty::Str)?, Ty::Str)),
+            res => Ok(res),
+        }
+    }
+
     fn ensure_ty(&mut self, reg: u32, from_ty: Ty, to_ty: Ty) -> Result<u32> {
         if from_ty == to_ty {
             return Ok(reg);
@@ -1169,6 +1296,26 @@ impl<'a, 'b> View<'a, 'b> {
         Ok(new_reg)
     }
 
+    // With --strict, check that a float used as an array index is an integer.
+    fn check_index(&mut self, reg: u32, ty: Ty) {
+        if ty == Ty::Float && self.strict {
+            self.pushl(LL::CheckIndex(reg.into()));
+        }
+    }
+
+    // Unless --ieee-div is set, check that the divisor of `/` (or of `%`, if `is_mod` is set) is
+    // not zero.
+    fn check_divisor(&mut self, reg: u32, ty: Ty, is_mod: bool) {
+        if self.ieee_div {
+            return;
+        }
+        match ty {
+            Ty::Float => self.pushl(LL::CheckDivisor(reg.into(), is_mod)),
+            Ty::Int => self.pushl(LL::CheckDivisorInt(reg.into())),
+            _ => {}
+        }
+    }
+
     // Move src into dst at type Ty.
     fn mov(&mut self, dst_reg: u32, src_reg: u32, ty: Ty) -> Result<()> {
         if let Some(inst) = mov(dst_reg, src_reg, ty)? {
@@ -1191,6 +1338,7 @@ impl<'a, 'b> View<'a, 'b> {
             (Float, Null) => LL::StoreConstFloat(dst_reg.into(), Default::default()),
             (Int, Null) => LL::StoreConstInt(dst_reg.into(), Default::default()),
             (Str, Null) => LL::StoreConstStr(dst_reg.into(), Default::default()),
+            (Regex, Null) => LL::StoreConstRegex(dst_reg.into(), Default::default()),
             (Float, Int) => LL::IntToFloat(dst_reg.into(), src_reg.into()),
             (Str, Int) => LL::IntToStr(dst_reg.into(), src_reg.into()),
 
@@ -1199,6 +1347,13 @@ impl<'a, 'b> View<'a, 'b> {
 
             (Int, Str) => LL::StrToInt(dst_reg.into(), src_reg.into()),
             (Float, Str) => LL::StrToFloat(dst_reg.into(), src_reg.into()),
+
+            (Str, Regex) => LL::RegexToStr(dst_reg.into(), src_reg.into()),
+            (Int, Regex) | (Float, Regex) => {
+                let str_reg = self.regs.stats.reg_of_ty(Str);
+                self.pushl(LL::RegexToStr(str_reg.into(), src_reg.into()));
+                return self.convert(dst_reg, dst_ty, str_reg, Str);
+            }
             (dst, src) => {
                 if dst == src {
                     return self.mov(dst_reg, src_reg, dst);
@@ -1216,6 +1371,10 @@ impl<'a, 'b> View<'a, 'b> {
 
     // Store values into a register at a given type, converting if necessary.
     fn store(&mut self, dst_reg: u32, dst_ty: Ty, src: &PrimVal<'a>) -> Result<()> {
+        if dst_reg == UNUSED {
+            // Nothing to do if the value is discarded (e.g. for a statement like `1;`).
+            return Ok(());
+        }
         match src {
             PrimVal::Var(id) => {
                 let (src_reg, src_ty) = self.reg_of_ident(id);
@@ -1248,6 +1407,15 @@ impl<'a, 'b> View<'a, 'b> {
                     self.convert(dst_reg, dst_ty, ir, Ty::Str)?;
                 }
             }
+            PrimVal::RegexLit(s) => {
+                if dst_ty == Ty::Regex {
+                    self.pushl(LL::StoreConstRegex(dst_reg.into(), regex_lit(s)?));
+                } else {
+                    let rr = self.regs.stats.reg_of_ty(Ty::Regex);
+                    self.pushl(LL::StoreConstRegex(rr.into(), regex_lit(s)?));
+                    self.convert(dst_reg, dst_ty, rr, Ty::Regex)?;
+                }
+            }
         };
         Ok(())
     }
@@ -1256,18 +1424,21 @@ impl<'a, 'b> View<'a, 'b> {
     fn load_map(
         &mut self,
         mut dst_reg: u32,
-        dst_ty: Ty,
+        mut dst_ty: Ty,
         arr_reg: u32,
         arr_ty: Ty,
         key: &PrimVal<'a>,
     ) -> Result<()> {
         if dst_reg == UNUSED {
-            // Lookups for a map have side-effects!
+            // Lookups for a map have side-effects! The result is discarded, so load it at the
+            // map's value type rather than converting it (which --strict could reject).
+            dst_ty = arr_ty.val()?;
             dst_reg = self.regs.stats.reg_of_ty(dst_ty);
         }
         // Convert `key` if necessary.
         let target_ty = arr_ty.key()?;
         let (mut key_reg, key_ty) = self.get_reg(key)?;
+        self.check_index(key_reg, key_ty);
         if target_ty != key_ty {
             let inter = self.regs.stats.reg_of_ty(target_ty);
             self.convert(inter, target_ty, key_reg, key_ty)?;
@@ -1292,7 +1463,7 @@ impl<'a, 'b> View<'a, 'b> {
                     map: arr_reg,
                     key: key_reg,
                 }),
-            Null | Int | Float | Str | IterInt | IterStr => {
+            Null | Int | Float | Str | Regex | IterInt | IterStr => {
                 return err!("[load_map] expected map type, found {:?}", arr_ty)
             }
         };
@@ -1310,6 +1481,11 @@ impl<'a, 'b> View<'a, 'b> {
         use crate::ast::{Binop::*, Unop::*};
         use builtins::Function::*;
 
+        if let (SetLine, [PrimVal::ILit(line)]) = (bf, &args[..]) {
+            self.pushl(LL::SetLine(*line as u32));
+            return Ok(());
+        }
+
         // Compile the argument values
         let mut args_regs = cfg::SmallVec::with_capacity(args.len());
         let mut args_tys = cfg::SmallVec::with_capacity(args.len());
@@ -1318,10 +1494,48 @@ impl<'a, 'b> View<'a, 'b> {
             args_regs.push(reg);
             args_tys.push(ty);
         }
+        if let Contains | Delete | IncMap = bf {
+            self.check_index(args_regs[1], args_tys[1]);
+        }
+
+        // Matching against a regex uses its compiled pattern directly, and a regex is never equal
+        // to a value of another type. Everywhere else, regexes are used as the text of their
+        // pattern (apart from Restore, which stores into its arguments).
+        let regex_op = match bf {
+            Binop(IsMatch) | Match => args_tys[1] == Ty::Regex,
+            Binop(EQ) => args_tys.contains(&Ty::Regex) && args_tys[0] != args_tys[1],
+            _ => false,
+        };
+        if regex_op {
+            let res_reg = if dst_ty == Ty::Int && dst_reg != UNUSED {
+                dst_reg
+            } else {
+                self.regs.stats.reg_of_ty(Ty::Int)
+            };
+            let inst = if let Binop(EQ) = bf {
+                LL::StoreConstInt(res_reg.into(), 0)
+            } else {
+                let s = self.ensure_ty(args_regs[0], args_tys[0], Ty::Str)?;
+                if let Match = bf {
+                    LL::MatchRegex(res_reg.into(), s.into(), args_regs[1].into())
+                } else {
+                    LL::IsMatchRegex(res_reg.into(), s.into(), args_regs[1].into())
+                }
+            };
+            self.pushl(inst);
+            return self.convert(dst_reg, dst_ty, res_reg, Ty::Int);
+        }
+        let sig_tys: cfg::SmallVec<_> = args_tys
+            .iter()
+            .map(|ty| match ty {
+                Ty::Regex if !matches!(bf, Restore) => Ty::Str,
+                _ => *ty,
+            })
+            .collect();
 
         // Now, perform any necessary conversions if input types do not match the argument types.
         let mut conv_regs: cfg::SmallVec<_> = smallvec![UNUSED; args.len()];
-        let (conv_tys, res_ty) = bf.type_sig(&args_tys[..])?;
+        let (conv_tys, res_ty) = bf.type_sig(&sig_tys[..], self.ieee_div)?;
 
         for (areg, (aty, (creg, cty))) in args_regs.iter().cloned().zip(
             args_tys
@@ -1388,9 +1602,15 @@ impl<'a, 'b> View<'a, 'b> {
             Binop(Plus) => gen_op!(Plus, [Float, AddFloat], [Int, AddInt]),
             Binop(Minus) => gen_op!(Minus, [Float, MinusFloat], [Int, MinusInt]),
             Binop(Mult) => gen_op!(Minus, [Float, MulFloat], [Int, MulInt]),
-            Binop(Div) => gen_op!(Div, [Float, Div]),
+            Binop(Div) => {
+                self.check_divisor(conv_regs[1], conv_tys[1], /*is_mod=*/ false);
+                gen_op!(Div, [Float, Div])
+            }
             Binop(Pow) => gen_op!(Pow, [Float, Pow]),
-            Binop(Mod) => gen_op!(Mod, [Float, ModFloat], [Int, ModInt]),
+            Binop(Mod) => {
+                self.check_divisor(conv_regs[1], conv_tys[1], /*is_mod=*/ true);
+                gen_op!(Mod, [Float, ModFloat], [Int, ModInt])
+            }
             Binop(Concat) => gen_op!(Concat, [Str, Concat]),
             Binop(IsMatch) => gen_op!(IsMatch, [Str, IsMatch]),
             Binop(LT) => gen_op!(LT, [Float, LTFloat], [Int, LTInt], [Str, LTStr]),
@@ -1398,6 +1618,7 @@ impl<'a, 'b> View<'a, 'b> {
             Binop(LTE) => gen_op!(LTE, [Float, LTEFloat], [Int, LTEInt], [Str, LTEStr]),
             Binop(GTE) => gen_op!(GTE, [Float, GTEFloat], [Int, GTEInt], [Str, GTEStr]),
             Binop(EQ) => gen_op!(EQ, [Float, EQFloat], [Int, EQInt], [Str, EQStr]),
+            StrNumCmp => gen_op!(StrNumCmp, [Str, StrNumCmp]),
             FloatFunc(ff) => {
                 if res_reg != UNUSED {
                     match ff.arity() {
@@ -1432,7 +1653,13 @@ impl<'a, 'b> View<'a, 'b> {
                             map: conv_regs[0],
                             key: conv_regs[1],
                         }),
-                        Ty::Null | Ty::Int | Ty::Float | Ty::Str | Ty::IterInt | Ty::IterStr => {
+                        Ty::Null
+                        | Ty::Int
+                        | Ty::Float
+                        | Ty::Str
+                        | Ty::Regex
+                        | Ty::IterInt
+                        | Ty::IterStr => {
                             return err!("unexpected non-map type for Contains: {:?}", conv_tys[0]);
                         }
                     }
@@ -1446,7 +1673,116 @@ impl<'a, 'b> View<'a, 'b> {
                 }
                 self.pushl(LL::RunCmd(res_reg.into(), conv_regs[0].into()))
             }
+            CmdStatus => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::CmdStatus(res_reg.into(), conv_regs[0].into()))
+            }
+            UsePty => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::UsePty(res_reg.into(), conv_regs[0].into()))
+            }
+            HllAdd => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::HllAdd(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                ))
+            }
+            HllCount => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::HllCount(res_reg.into(), conv_regs[0].into()))
+            }
+            TopK => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::TopK(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                    conv_regs[2].into(),
+                    conv_regs[3].into(),
+                ))
+            }
             Exit => self.pushl(LL::Exit(conv_regs[0].into())),
+            Assert => self.pushl(LL::Assert(
+                conv_regs[0].into(),
+                conv_regs[1].into(),
+                conv_regs[2].into(),
+            )),
+            DumpState => {
+                for (name, (val, ty)) in conv_regs
+                    .iter()
+                    .step_by(2)
+                    .zip(conv_regs.iter().zip(conv_tys.iter()).skip(1).step_by(2))
+                {
+                    if ty.is_array() {
+                        self.pushl(LL::DumpMap {
+                            name: (*name).into(),
+                            map_ty: *ty,
+                            map: *val,
+                        })
+                    } else {
+                        self.pushl(LL::DumpScalar((*name).into(), (*val).into()))
+                    }
+                }
+            }
+            DumpRequested => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::DumpRequested(res_reg.into()))
+                }
+            }
+            Checkpoint => {
+                for (name, (val, ty)) in conv_regs
+                    .iter()
+                    .step_by(2)
+                    .zip(conv_regs.iter().zip(conv_tys.iter()).skip(1).step_by(2))
+                {
+                    if ty.is_array() {
+                        self.pushl(LL::CheckpointMap {
+                            name: (*name).into(),
+                            map_ty: *ty,
+                            map: *val,
+                        })
+                    } else {
+                        self.pushl(LL::CheckpointScalar((*name).into(), (*val).into()))
+                    }
+                }
+                self.pushl(LL::WriteCheckpoint())
+            }
+            CheckpointDue => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::CheckpointDue(res_reg.into()))
+                }
+            }
+            Restore => {
+                for (name, (var, ty)) in conv_regs
+                    .iter()
+                    .step_by(2)
+                    .zip(conv_regs.iter().zip(conv_tys.iter()).skip(1).step_by(2))
+                {
+                    // Variables that are never used have no value to restore.
+                    if *ty == Ty::Null {
+                        continue;
+                    }
+                    self.pushl(LL::RestoreVar {
+                        name: (*name).into(),
+                        ty: *ty,
+                        var: *var,
+                    })
+                }
+            }
+            // Handled above; line numbers are always literals.
+            SetLine => {}
             ReadErr => {
                 if res_reg != UNUSED {
                     self.pushl(LL::ReadErr(
@@ -1480,10 +1816,24 @@ impl<'a, 'b> View<'a, 'b> {
                     self.pushl(LL::ReadErrStdin(res_reg.into()))
                 }
             }
+            FileBoundary => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::FileBoundary(res_reg.into()))
+                }
+            }
             NextlineStdin => self.pushl(LL::NextLineStdin(res_reg.into())),
             ReadLineStdinFused => self.pushl(LL::NextLineStdinFused()),
             NextFile => self.pushl(LL::NextFile()),
             Setcol => self.pushl(LL::SetColumn(conv_regs[0].into(), conv_regs[1].into())),
+            SetRecord => {
+                // set_record(s) => ($0 = s, NF)
+                let zero = self.regs.stats.reg_of_ty(Ty::Int);
+                self.pushl(LL::StoreConstInt(zero.into(), 0));
+                self.pushl(LL::SetColumn(zero.into(), conv_regs[0].into()));
+                if res_reg != UNUSED {
+                    self.pushl(LL::LoadVarInt(res_reg.into(), builtins::Variable::NF));
+                }
+            }
             Sub => {
                 if res_reg == UNUSED {
                     res_reg = self.regs.stats.reg_of_ty(res_ty);
@@ -1506,6 +1856,26 @@ impl<'a, 'b> View<'a, 'b> {
                     conv_regs[2].into(),
                 ))
             }
+            SubLine => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::SubLine(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                ))
+            }
+            GSubLine => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::GSubLine(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                ))
+            }
             GenSub => {
                 if res_reg != UNUSED {
                     // TODO: emit specialized versions of GenSub (how to inspect constants?)
@@ -1528,6 +1898,16 @@ impl<'a, 'b> View<'a, 'b> {
                     self.pushl(LL::EscapeTSV(res_reg.into(), conv_regs[0].into()))
                 }
             }
+            EscapeJSON => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::EscapeJSON(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            EscapeMsgPack => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::EscapeMsgPack(res_reg.into(), conv_regs[0].into()))
+                }
+            }
             ToUpper => {
                 if res_reg != UNUSED {
                     self.pushl(LL::ToUpperAscii(res_reg.into(), conv_regs[0].into()))
@@ -1538,6 +1918,52 @@ impl<'a, 'b> View<'a, 'b> {
                     self.pushl(LL::ToLowerAscii(res_reg.into(), conv_regs[0].into()))
                 }
             }
+            Unpack => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Unpack(res_reg.into(), conv_regs[0].into()))
+            }
+            Resplit => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Resplit(res_reg.into(), conv_regs[0].into()))
+            }
+            GetXml => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::GetXml(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            Strftime => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Strftime(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    ))
+                }
+            }
+            ParseDate => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::ParseDate(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Chr => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Chr(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Ord => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Ord(res_reg.into(), conv_regs[0].into()))
+                }
+            }
             Substr => {
                 if res_reg != UNUSED {
                     self.pushl(LL::Substr(
@@ -1572,6 +1998,22 @@ impl<'a, 'b> View<'a, 'b> {
                 }
                 self.pushl(LL::ReseedRng(res_reg.into()))
             }
+            Sleep => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Sleep(res_reg.into(), conv_regs[0].into()))
+            }
+            TimeOfDay => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::TimeOfDay(res_reg.into()))
+                }
+            }
+            Monotonic => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Monotonic(res_reg.into()))
+                }
+            }
             Split => {
                 if res_reg == UNUSED {
                     res_reg = self.regs.stats.reg_of_ty(res_ty);
@@ -1582,6 +2024,7 @@ impl<'a, 'b> View<'a, 'b> {
                         conv_regs[0].into(),
                         conv_regs[1].into(),
                         conv_regs[2].into(),
+                        conv_regs[3].into(),
                     )
                 } else if conv_tys[1] == Ty::MapStrStr {
                     LL::SplitStr(
@@ -1589,11 +2032,41 @@ impl<'a, 'b> View<'a, 'b> {
                         conv_regs[0].into(),
                         conv_regs[1].into(),
                         conv_regs[2].into(),
+                        conv_regs[3].into(),
                     )
                 } else {
                     return err!("invalid input types to split: {:?}", &conv_tys[..]);
                 })
             }
+            MatchArr => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                if conv_tys[2] != Ty::MapStrStr {
+                    return err!("invalid input types to match: {:?}", &conv_tys[..]);
+                }
+                self.pushl(LL::MatchArr(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                    conv_regs[2].into(),
+                ))
+            }
+            MatchAll => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                if conv_tys[2] != Ty::MapIntStr {
+                    return err!("invalid input types to matchall: {:?}", &conv_tys[..]);
+                }
+                self.pushl(LL::MatchAll(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                    conv_regs[2].into(),
+                    conv_regs[3].into(),
+                ))
+            }
             Length => {
                 if res_reg != UNUSED {
                     self.pushl(match conv_tys[0] {
@@ -1613,6 +2086,11 @@ impl<'a, 'b> View<'a, 'b> {
                     })
                 }
             }
+            BLength => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::LenStr(res_reg.into(), conv_regs[0].into()))
+                }
+            }
             Delete => match &conv_tys[0] {
                 Ty::MapIntInt
                 | Ty::MapIntStr
@@ -1663,6 +2141,53 @@ impl<'a, 'b> View<'a, 'b> {
                     }
                 })
             }
+            Asorti => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                if !conv_tys[0].is_array() || conv_tys[1] != Ty::MapIntStr {
+                    return err!("asorti called with malformed types: {:?}", &conv_tys[..]);
+                }
+                self.pushl(LL::SortKeys {
+                    map_ty: conv_tys[0],
+                    dst: res_reg.into(),
+                    src: conv_regs[0],
+                    dest: conv_regs[1].into(),
+                    how: conv_regs[2].into(),
+                })
+            }
+            CopyMap => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                if !conv_tys[0].is_array() || conv_tys[0] != conv_tys[1] {
+                    return err!("copy called with malformed types: {:?}", &conv_tys[..]);
+                }
+                self.pushl(LL::CopyMap {
+                    map_ty: conv_tys[0],
+                    dst: res_reg.into(),
+                    to: conv_regs[0],
+                    from: conv_regs[1],
+                })
+            }
+            Sample => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                if !conv_tys[0].is_array()
+                    || conv_tys[0].key()? != Ty::Int
+                    || conv_tys[0].val()? != conv_tys[2]
+                {
+                    return err!("sample called with malformed types: {:?}", &conv_tys[..]);
+                }
+                self.pushl(LL::Sample {
+                    map_ty: conv_tys[0],
+                    dst: res_reg.into(),
+                    map: conv_regs[0],
+                    k: conv_regs[1].into(),
+                    val: conv_regs[2],
+                })
+            }
             Clear => {
                 if conv_tys[0].is_array() {
                     self.pushl(LL::Clear {
@@ -1698,6 +2223,42 @@ impl<'a, 'b> View<'a, 'b> {
                     ))
                 }
             }
+            JoinJSON => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::JoinJSON(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            JsonKey => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::JsonKey(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            JoinMsgPack => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::JoinMsgPack(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            MsgPackKey => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::MsgPackKey(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
             JoinCols => {
                 if res_reg != UNUSED {
                     self.pushl(LL::JoinColumns(
@@ -1754,7 +2315,7 @@ impl<'a, 'b> View<'a, 'b> {
                 fmt_reg = self.ensure_ty(fmt_reg, fmt_ty, Ty::Str)?;
                 let mut arg_regs = Vec::with_capacity(args.len());
                 for a in args {
-                    arg_regs.push(self.get_reg(a)?);
+                    arg_regs.push(self.format_arg(a)?);
                 }
                 if let Ty::Str = dst_ty {
                     self.pushl(Sprintf {
@@ -1842,7 +2403,7 @@ impl<'a, 'b> View<'a, 'b> {
                         })
                     }
                     // Covered by the error check above
-                    Null | Int | Float | Str | IterInt | IterStr => unreachable!(),
+                    Null | Int | Float | Str | Regex | IterInt | IterStr => unreachable!(),
                 };
             }
             PrimExpr::HasNext(pv) => {
@@ -1904,6 +2465,7 @@ impl<'a, 'b> View<'a, 'b> {
             PrimStmt::AsgnIndex(arr, pv, pe) => {
                 let (a_reg, a_ty) = self.reg_of_ident(arr);
                 let (mut k_reg, k_ty) = self.get_reg(pv)?;
+                self.check_index(k_reg, k_ty);
                 let a_key_ty = a_ty.key()?;
                 k_reg = self.ensure_ty(k_reg, k_ty, a_key_ty)?;
                 let v_ty = a_ty.val()?;
@@ -1919,7 +2481,7 @@ impl<'a, 'b> View<'a, 'b> {
                             val: v_reg,
                         })
                     }
-                    Null | Int | Float | Str | IterInt | IterStr => {
+                    Null | Int | Float | Str | Regex | IterInt | IterStr => {
                         return err!(
                             "in stmt {:?} computed type is non-map type {:?}",
                             stmt,
@@ -1980,7 +2542,7 @@ impl<'a, 'b> View<'a, 'b> {
                 fmt_reg = self.ensure_ty(fmt_reg, fmt_ty, Ty::Str)?;
                 let mut arg_regs = Vec::with_capacity(args.len());
                 for a in args {
-                    arg_regs.push(self.get_reg(a)?);
+                    arg_regs.push(self.format_arg(a)?);
                 }
                 let out_reg = if let Some((out, append)) = out {
                     let (mut out_reg, out_ty) = self.get_reg(out)?;
@@ -2004,6 +2566,14 @@ impl<'a, 'b> View<'a, 'b> {
     }
 }
 
+// Compile the pattern of a `@/re/` constant.
+fn regex_lit(pat: &[u8]) -> Result<runtime::TypedRegex> {
+    let text = std::str::from_utf8(pat)
+        .map_err(|e| CompileError(format!("regex patterns must be valid UTF-8: {}", e)))?;
+    runtime::TypedRegex::new(text)
+        .map_err(|e| CompileError(format!("regex parse error during compilation: {}", e)))
+}
+
 /// For regex patterns of the form "^<literal>", return a copy of the literal.
 ///
 /// This works as a special case for regex constant folding, where we can compile matches into
@@ -19,8 +19,8 @@ pub(crate) struct SlotOps {
 pub(crate) fn load_slot_instr<'a>(reg: NumTy, ty: Ty, slot: usize) -> Result<Option<LL<'a>>> {
     use Ty::*;
     match ty {
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => Ok(Some(LL::LoadSlot {
+        Int | Float | Str | Regex | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
+        | MapStrFloat | MapStrStr => Ok(Some(LL::LoadSlot {
             ty,
             dst: reg,
             slot: slot as _,
@@ -33,8 +33,8 @@ pub(crate) fn load_slot_instr<'a>(reg: NumTy, ty: Ty, slot: usize) -> Result<Opt
 pub(crate) fn store_slot_instr<'a>(reg: NumTy, ty: Ty, slot: usize) -> Result<Option<LL<'a>>> {
     use Ty::*;
     match ty {
-        Int | Float | Str | MapIntInt | MapIntFloat | MapIntStr | MapStrInt | MapStrFloat
-        | MapStrStr => Ok(Some(LL::StoreSlot {
+        Int | Float | Str | Regex | MapIntInt | MapIntFloat | MapIntStr | MapStrInt
+        | MapStrFloat | MapStrStr => Ok(Some(LL::StoreSlot {
             ty,
             src: reg,
             slot: slot as _,
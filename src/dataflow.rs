@@ -191,12 +191,14 @@ pub(crate) mod boilerplate {
             StoreConstStr(dst, _) => f(dst.into(), None),
             StoreConstInt(dst, _) => f(dst.into(), None),
             StoreConstFloat(dst, _) => f(dst.into(), None),
+            StoreConstRegex(dst, _) => f(dst.into(), None),
 
             IntToStr(dst, src) => f(dst.into(), Some(src.into())),
             IntToFloat(dst, src) => f(dst.into(), Some(src.into())),
             FloatToStr(dst, src) => f(dst.into(), Some(src.into())),
             FloatToInt(dst, src) => f(dst.into(), Some(src.into())),
             StrToFloat(dst, src) => f(dst.into(), Some(src.into())),
+            RegexToStr(dst, src) => f(dst.into(), Some(src.into())),
             LenStr(dst, src) | StrToInt(dst, src) | HexStrToInt(dst, src) => f(dst.into(), Some(src.into())),
 
             Mov(ty, dst, src) => if !ty.is_array() {
@@ -234,6 +236,8 @@ pub(crate) mod boilerplate {
                 f(Key::Rng, Some(new.into()));
             }
             ReseedRng(new) => f(Key::Rng, Some(new.into())),
+            Sleep(dst, secs) => f(dst.into(), Some(secs.into())),
+            TimeOfDay(dst) | Monotonic(dst) => f(dst.into(), None),
             Concat(dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
@@ -244,7 +248,15 @@ pub(crate) mod boilerplate {
             // user-input. That is certainly true today, but any kind of dynamic simplification or
             // inlining could change that.
             MatchConst(dst, x, _) | IsMatchConst(dst, x, _) => f(dst.into(), Some(x.into())),
-            IsMatch(dst, x, y) | Match(dst, x, y) | SubstrIndex(dst, x, y) => {
+            MatchRegex(dst, x, pat) | IsMatchRegex(dst, x, pat) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(pat.into()));
+            }
+            IsMatch(dst, x, y)
+            | Match(dst, x, y)
+            | SubstrIndex(dst, x, y)
+            | GSubLine(dst, x, y)
+            | SubLine(dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
             }
@@ -260,7 +272,12 @@ pub(crate) mod boilerplate {
                 f(dst.into(), Some(how.into()));
                 f(dst.into(), Some(in_s.into()));
             }
-            EscapeTSV(dst, src) | EscapeCSV(dst, src) => f(dst.into(), Some(src.into())),
+            EscapeTSV(dst, src)
+            | EscapeCSV(dst, src)
+            | EscapeJSON(dst, src)
+            | EscapeMsgPack(dst, src) => {
+                f(dst.into(), Some(src.into()))
+            }
             Substr(dst, x, y, z) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
@@ -286,12 +303,20 @@ pub(crate) mod boilerplate {
             | GTStr(dst, x, y)
             | LTEStr(dst, x, y)
             | GTEStr(dst, x, y)
-            | EQStr(dst, x, y) => {
+            | EQStr(dst, x, y)
+            | StrNumCmp(dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
             }
             GetColumn(dst, _) => f(dst.into(), None),
-            JoinTSV(dst, start, end) | JoinCSV(dst, start, end) => {
+            Unpack(dst, fmt) => f(dst.into(), Some(fmt.into())),
+            Resplit(dst, _) => f(dst.into(), None),
+            JoinTSV(dst, start, end)
+            | JoinCSV(dst, start, end)
+            | JoinJSON(dst, start, end)
+            | JsonKey(dst, start, end)
+            | JoinMsgPack(dst, start, end)
+            | MsgPackKey(dst, start, end) => {
                 f(dst.into(), Some(start.into()));
                 f(dst.into(), Some(end.into()));
             }
@@ -303,11 +328,23 @@ pub(crate) mod boilerplate {
             ToUpperAscii(dst, src) | ToLowerAscii(dst, src) => {
                 f(dst.into(), Some(src.into()));
             }
+            GetXml(dst, doc, path) => {
+                f(dst.into(), Some(doc.into()));
+                f(dst.into(), Some(path.into()));
+            }
+            Strftime(dst, fmt, _, tz) => {
+                f(dst.into(), Some(fmt.into()));
+                f(dst.into(), Some(tz.into()));
+            }
+            ParseDate(dst, _) => f(dst.into(), None),
+            Ord(dst, _) => f(dst.into(), None),
+            Chr(dst, _) => f(dst.into(), None),
             ReadErr(dst, _cmd, _) => f(dst.into(), None),
             NextLine(dst, _cmd, _) => f(dst.into(), None),
             ReadErrStdin(dst) => f(dst.into(), None),
+            FileBoundary(dst) => f(dst.into(), None),
             NextLineStdin(dst) => f(dst.into(), None),
-            SplitInt(dst1, src1, dst2, src2) => {
+            SplitInt(dst1, src1, dst2, src2, _) => {
                 f(dst1.into(), Some(src1.into()));
                 f(dst1.into(), Some(src2.into()));
                 let (dst2_reg, dst2_ty) = dst2.reflect();
@@ -315,19 +352,49 @@ pub(crate) mod boilerplate {
                 f(Key::MapVal(dst2_reg, dst2_ty), Some(src1.into()));
                 f(Key::MapVal(dst2_reg, dst2_ty), Some(src2.into()));
             }
-            SplitStr(dst1, src1, dst2, src2) => {
+            SplitStr(dst1, src1, dst2, src2, _) => {
                 f(dst1.into(), Some(src1.into()));
                 f(dst1.into(), Some(src2.into()));
                 f(dst2.into(), Some(src1.into()));
                 f(dst2.into(), Some(src2.into()));
             }
+            MatchArr(dst, s, _, arr) => {
+                f(dst.into(), None);
+                // The match and its groups are substrings of `s`.
+                let (arr_reg, arr_ty) = arr.reflect();
+                f(Key::MapVal(arr_reg, arr_ty), Some(s.into()));
+            }
+            MatchAll(dst, s, _, arr, _) => {
+                f(dst.into(), None);
+                // The matches are substrings of `s`.
+                let (arr_reg, arr_ty) = arr.reflect();
+                f(Key::MapVal(arr_reg, arr_ty), Some(s.into()));
+            }
             Sprintf { dst, fmt, args } => {
                 f(dst.into(), Some(fmt.into()));
                 for (reg, ty) in args.iter() {
                     f(dst.into(), Some(Key::Reg(*reg, *ty)));
                 }
             }
-            RunCmd(dst, _) => f(dst.into(), None),
+            RunCmd(dst, _) | CmdStatus(dst, _) | UsePty(dst, _) | HllCount(dst, _) => {
+                f(dst.into(), None)
+            }
+            HllAdd(dst, _, _) => f(dst.into(), None),
+            TopK(dst, heap, _, key, weight) => {
+                let (heap_reg, heap_ty) = heap.reflect();
+                f(Key::MapKey(heap_reg, heap_ty), Some(key.into()));
+                f(Key::MapVal(heap_reg, heap_ty), Some(weight.into()));
+                f(dst.into(), Some(Key::MapVal(heap_reg, heap_ty)));
+            }
+            DumpRequested(dst) | CheckpointDue(dst) => f(dst.into(), None),
+            RestoreVar { ty, var, .. } => {
+                if ty.is_array() {
+                    f(Key::MapKey(*var, *ty), None);
+                    f(Key::MapVal(*var, *ty), None);
+                } else {
+                    f(Key::Reg(*var, *ty), None);
+                }
+            }
             Lookup {
                 map_ty,
                 dst,
@@ -357,6 +424,25 @@ pub(crate) mod boilerplate {
                 f(Key::MapVal(*map, *map_ty), Some(Key::Reg(reg, ty)));
                 f(Key::Reg(*dst, map_ty.val().unwrap()), Some(Key::MapVal(*map, *map_ty)));
             }
+            CopyMap {
+                map_ty,
+                dst,
+                to,
+                from,
+            } => {
+                f(Key::MapKey(*to, *map_ty), Some(Key::MapKey(*from, *map_ty)));
+                f(Key::MapVal(*to, *map_ty), Some(Key::MapVal(*from, *map_ty)));
+                f(dst.into(), None);
+            }
+            Sample { map_ty, dst, map, val, .. } => {
+                f(Key::MapVal(*map, *map_ty), Some(Key::Reg(*val, map_ty.val().unwrap())));
+                f(dst.into(), None);
+            }
+            SortKeys { map_ty, dst, src, dest, .. } => {
+                let (dest_reg, dest_ty) = dest.reflect();
+                f(Key::MapVal(dest_reg, dest_ty), Some(Key::MapKey(*src, *map_ty)));
+                f(dst.into(), None);
+            }
             IterBegin { map_ty, dst, map } => {
                 f(Key::Reg(*dst, map_ty.key_iter().unwrap()), Some(Key::MapKey(*map, *map_ty)))
             }
@@ -397,8 +483,8 @@ pub(crate) mod boilerplate {
             | IterHasNext{..}
             | JmpIf(..)
             | Jmp(_)
-            | Push(..)
-            | Pop(..)
+            | PushFrame(_)
+            | PopFrame(_)
             // We consume high-level instructions, so calls and returns are handled by visit_hl
             // above
             | Call(_)
@@ -409,7 +495,17 @@ pub(crate) mod boilerplate {
             | NextFile()
             | SetColumn(_, _)
             | AllocMap(_, _)
-            | Exit(_) => {}
+            | Exit(_)
+            | Assert(..)
+            | CheckIndex(_)
+            | CheckDivisor(..)
+            | CheckDivisorInt(_)
+            | DumpScalar(..)
+            | DumpMap { .. }
+            | CheckpointScalar(..)
+            | CheckpointMap { .. }
+            | WriteCheckpoint()
+            | SetLine(_) => {}
         }
     }
 }
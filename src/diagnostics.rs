@@ -0,0 +1,129 @@
+//! Reporting errors in the program text along with the line of source they point to, and a caret
+//! under the offending span:
+//!
+//! ```text
+//! Unrecognized token `*` found at line 1, column 18:line 1, column 19
+//! Expected one of ...
+//!   |
+//! 1 | BEGIN { print 1 +* 2 }
+//!   |                  ^
+//! ```
+//!
+//! Diagnostics are colored when standard error is a terminal, unless the `NO_COLOR` environment
+//! variable is set or frawk is run with `--no-color`.
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use lalrpop_util::ParseError;
+
+use crate::lexer::{Error, Loc, Tok};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+/// Color diagnostics if standard error is a terminal, `NO_COLOR` is unset (or empty) and
+/// `no_color` (i.e. `--no-color`) is false.
+pub fn init_color(no_color: bool) {
+    let no_color_env = std::env::var_os("NO_COLOR").map_or(false, |v| !v.is_empty());
+    let color = !no_color && !no_color_env && std::io::stderr().is_terminal();
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+fn paint(s: &str, color: &str) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        format!("{}{}{}", color, s, RESET)
+    } else {
+        s.to_string()
+    }
+}
+
+/// The line of `src` containing `start`, with a caret under the text between `start` and `end`.
+/// Spans covering more than one line are marked at their start.
+pub(crate) fn code_frame(src: &str, start: &Loc, end: &Loc) -> Option<String> {
+    let line = src.lines().nth(start.line)?;
+    let col = start.col.min(line.len());
+    let end_col = if end.line == start.line {
+        end.col.min(line.len())
+    } else {
+        col
+    };
+    let (prefix, span) = match (line.get(..col), line.get(col..end_col.max(col))) {
+        (Some(prefix), Some(span)) => (prefix, span),
+        _ => return None,
+    };
+    // Keep tabs, so that the caret lines up with the source.
+    let indent: String = prefix
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let carets = "^".repeat(span.chars().count().max(1));
+    let lineno = (start.line + 1).to_string();
+    let gutter = " ".repeat(lineno.len());
+    Some(format!(
+        "{gutter} {bar}\n{lineno} {bar} {line}\n{gutter} {bar} {indent}{carets}",
+        gutter = gutter,
+        bar = paint("|", BLUE),
+        lineno = paint(&lineno, BLUE),
+        line = line,
+        indent = indent,
+        carets = paint(&carets, RED),
+    ))
+}
+
+/// Describe the error `e` from parsing `src`, followed by a code frame showing where it happened.
+pub(crate) fn parse_error(src: &str, e: &ParseError<Loc, Tok, Error>) -> String {
+    use ParseError::*;
+    let (start, end) = match e {
+        InvalidToken { location } | UnrecognizedEOF { location, .. } => (location, location),
+        UnrecognizedToken {
+            token: (start, _, end),
+            ..
+        }
+        | ExtraToken {
+            token: (start, _, end),
+        } => (start, end),
+        User { error } => (&error.location, &error.location),
+    };
+    let msg = paint(&e.to_string(), RED);
+    match code_frame(src, start, end) {
+        Some(frame) => format!("{}\n{}", msg, frame),
+        None => msg,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Tokenizer;
+
+    fn first_error(src: &str) -> String {
+        let arena = crate::arena::Arena::default();
+        let mut buf = Vec::new();
+        let stage = crate::common::ExecutionStrategy::Serial.stage();
+        let mut prog = crate::ast::Prog::from_stage(&arena, stage);
+        let src = arena.alloc_str(src);
+        let e = crate::parsing::syntax::ProgParser::new()
+            .parse(&arena, &mut buf, &mut prog, Tokenizer::new(src))
+            .expect_err("program should not parse");
+        parse_error(src, &e)
+    }
+
+    #[test]
+    fn frames() {
+        let err = first_error("BEGIN {\n\tx = 1 +* 2\n}");
+        assert!(
+            err.ends_with("  |\n2 | \tx = 1 +* 2\n  | \t       ^"),
+            "err={}",
+            err
+        );
+        let err = first_error(r#"{ print "abc }"#);
+        assert!(
+            err.ends_with("  |\n1 | { print \"abc }\n  |          ^"),
+            "err={}",
+            err
+        );
+    }
+}
@@ -140,6 +140,7 @@ impl<'a> Display for PrimVal<'a> {
             ILit(n) => write!(f, "{}@int", *n),
             FLit(n) => write!(f, "{}@float", *n),
             StrLit(s) => write!(f, "\"{}\"", String::from_utf8_lossy(s)),
+            RegexLit(s) => write!(f, "@/{}/", String::from_utf8_lossy(s)),
         }
     }
 }
@@ -157,25 +158,35 @@ impl Display for Function {
             Nextline => write!(f, "nextline"),
             NextlineCmd => write!(f, "nextline(cmd)"),
             ReadErrStdin => write!(f, "hasline(stdin)"),
+            FileBoundary => write!(f, "file_boundary"),
             NextlineStdin => write!(f, "nextline(stdin)"),
             ReadLineStdinFused => write!(f, "stdin-fused"),
             NextFile => write!(f, "nextfile"),
             Setcol => write!(f, "$="),
             Split => write!(f, "split"),
             Length => write!(f, "length"),
+            BLength => write!(f, "blength"),
             Contains => write!(f, "contains"),
             Delete => write!(f, "delete"),
             Clear => write!(f, "clear"),
             Close => write!(f, "close"),
-            Match => write!(f, "match"),
+            Match | MatchArr => write!(f, "match"),
+            MatchAll => write!(f, "matchall"),
             SubstrIndex => write!(f, "index"),
-            Sub => write!(f, "sub"),
-            GSub => write!(f, "gsub"),
+            StrNumCmp => write!(f, "strnum_cmp"),
+            Sub | SubLine => write!(f, "sub"),
+            GSub | GSubLine => write!(f, "gsub"),
             GenSub => write!(f, "gensub"),
             EscapeCSV => write!(f, "escape_csv"),
             EscapeTSV => write!(f, "escape_tsv"),
+            EscapeJSON => write!(f, "escape_json"),
             JoinCSV => write!(f, "join_csv"),
             JoinTSV => write!(f, "join_tsv"),
+            JoinJSON => write!(f, "join_json"),
+            JsonKey => write!(f, "json_key"),
+            EscapeMsgPack => write!(f, "escape_msgpack"),
+            JoinMsgPack => write!(f, "join_msgpack"),
+            MsgPackKey => write!(f, "msgpack_key"),
             JoinCols => write!(f, "join_fields"),
             Substr => write!(f, "substr"),
             ToInt => write!(f, "int"),
@@ -183,13 +194,39 @@ impl Display for Function {
             Rand => write!(f, "rand"),
             Srand => write!(f, "srand"),
             ReseedRng => write!(f, "srand_reseed"),
+            Sleep => write!(f, "sleep"),
+            TimeOfDay => write!(f, "gettimeofday"),
+            Monotonic => write!(f, "monotonic"),
+            Strftime => write!(f, "strftime"),
+            ParseDate => write!(f, "parsedate"),
+            Chr => write!(f, "chr"),
+            Ord => write!(f, "ord"),
             System => write!(f, "system"),
+            CmdStatus => write!(f, "cmdstatus"),
+            UsePty => write!(f, "usepty"),
+            HllAdd => write!(f, "hll_add"),
+            HllCount => write!(f, "hll_count"),
+            Sample => write!(f, "sample"),
+            Asorti => write!(f, "asorti"),
+            CopyMap => write!(f, "copy"),
+            TopK => write!(f, "topk"),
             UpdateUsedFields => write!(f, "update_used_fields"),
             SetFI => write!(f, "set-FI"),
             ToLower => write!(f, "tolower"),
             ToUpper => write!(f, "toupper"),
+            GetXml => write!(f, "getxml"),
+            Unpack => write!(f, "unpack"),
+            SetRecord => write!(f, "set_record"),
+            Resplit => write!(f, "resplit"),
             IncMap => write!(f, "inc_map"),
             Exit => write!(f, "exit"),
+            Assert => write!(f, "assert"),
+            DumpState => write!(f, "dump_state"),
+            DumpRequested => write!(f, "dump_requested"),
+            Checkpoint => write!(f, "checkpoint"),
+            Restore => write!(f, "restore"),
+            CheckpointDue => write!(f, "checkpoint_due"),
+            SetLine => write!(f, "set_line"),
         }
     }
 }
@@ -215,6 +252,8 @@ impl Display for Variable {
                 RLENGTH => "RLENGTH",
                 PID => "PID",
                 FI => "FI",
+                ARGIND => "ARGIND",
+                RT => "RT",
             }
         )
     }
@@ -280,6 +319,8 @@ impl<'a> Display for lexer::Tok<'a> {
             Begin => "BEGIN",
             Prepare => "PREPARE",
             End => "END",
+            BeginFile => "BEGINFILE",
+            EndFile => "ENDFILE",
             Break => "break",
             Continue => "continue",
             Next => "next",
@@ -349,12 +390,17 @@ impl<'a> Display for lexer::Tok<'a> {
             In => "in",
             Delete => "delete",
             Return => "return",
+            TypePragma => "# @type",
 
             Ident(s) => return write!(fmt, "identifier({})", s),
             StrLit(s) => return write!(fmt, "{:?}", s),
+            RawStrLit(s) => return write!(fmt, "r\"{}\"", s),
             PatLit(s) => return write!(fmt, "/{}/", s),
+            RegexLit(s) => return write!(fmt, "@/{}/", s),
             CallStart(s) => return write!(fmt, "{}(", s),
+            IndirectCallStart(s) => return write!(fmt, "@{}(", s),
             FunDec(s) => return write!(fmt, "function {}", s),
+            ConstDec(s) => return write!(fmt, "const {}", s),
 
             ILit(s) | HexLit(s) | FLit(s) => return write!(fmt, "{}", s),
         };
@@ -0,0 +1,366 @@
+//! Running frawk programs from other programs, with the `embed` feature. This is the API that the
+//! C bindings in `capi/` and the Python bindings in `python/` are built on.
+//!
+//! ```ignore
+//! let prog = frawk::embed::Program::compile("{ n += $2; print $1, n }", &[])?;
+//! let mut out = Vec::new();
+//! prog.run(&b"a 1\nb 2\n"[..], &mut out)?;
+//! assert_eq!(out, b"a 1\nb 3\n");
+//! ```
+//!
+//! A `Program` is compiled once, and can then be run any number of times. `run` reads lines of
+//! input from a reader and writes what the program prints to standard output to a writer. For
+//! finer control, `start` returns a `Run`, which has its own variables, and executes the program's
+//! BEGIN block when it starts. Records are then fed to it one at a time, and `finish` runs the END
+//! block. After each of these steps, `output` holds what the program printed to standard output
+//! during that step, so a program can be used as a record transformer: feed it a record, and read
+//! back what it made of it. Output to files and commands is written as usual.
+//!
+//! Runs execute on the thread that drives them, with the bytecode interpreter. When the program
+//! reads a record that has not been fed to it yet, the interpreter stops, and picks up where it
+//! left off when the next record arrives, so at most one step of a run is in progress at a time.
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::rc::Rc;
+
+use crate::{
+    arena::Arena,
+    ast, bytecode,
+    cfg::{self, Escaper},
+    common::{ExecutionStrategy, Result},
+    compile, diagnostics, lexer, parsing,
+    runtime::{
+        self,
+        splitter::{DefaultLine, LineReader, ReaderState},
+        writers, Int, RegexCache, Str,
+    },
+};
+
+/// A program compiled to bytecode, ready to be run.
+pub struct Program {
+    compiled: compile::Compiled,
+}
+
+impl Program {
+    /// Compile the program `src`. `vars` are assignments of the form `name=value`, which are made
+    /// before BEGIN runs, as with `-v`. Returns a description of the problem if the program (or an
+    /// assignment) is invalid.
+    pub fn compile(src: &str, vars: &[&str]) -> std::result::Result<Program, String> {
+        let a = Arena::default();
+        let mut ctx = context(&a, src, vars)?;
+        let compiled = compile::compiled(&mut ctx).map_err(|e| e.to_string())?;
+        Ok(Program { compiled })
+    }
+
+    /// Run the program over the lines of `input`, writing what it prints to standard output to
+    /// `output`, and return its exit status. Lines are fed to the program one at a time, with
+    /// their trailing newlines removed, until the input ends or the program exits.
+    pub fn run(
+        &self,
+        mut input: impl BufRead,
+        mut output: impl Write,
+    ) -> std::result::Result<i32, String> {
+        let write_err = |e: io::Error| format!("failed to write output: {}", e);
+        let mut run = self.start();
+        output.write_all(run.output()).map_err(write_err)?;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match input.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(format!("failed to read input: {}", e)),
+            }
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
+            let read = run.feed(&line)?;
+            output.write_all(run.output()).map_err(write_err)?;
+            if !read {
+                break;
+            }
+        }
+        let status = run.finish()?;
+        output.write_all(run.output()).map_err(write_err)?;
+        output.flush().map_err(write_err)?;
+        Ok(status)
+    }
+
+    /// Start a new run of the program, executing its BEGIN block.
+    pub fn start(&self) -> Run {
+        let feed = Rc::new(RefCell::new(Feed::default()));
+        let reader = FeedReader {
+            feed: feed.clone(),
+            records: 0,
+            state: ReaderState::OK,
+        };
+        let config = runtime::Config {
+            serve: true,
+            ..Default::default()
+        };
+        let mut run = Run {
+            interp: Some(
+                self.compiled
+                    .interp(reader, writers::default_factory(), config),
+            ),
+            feed,
+            out: Default::default(),
+            output: Vec::new(),
+            status: None,
+        };
+        run.resume();
+        run
+    }
+}
+
+fn context<'a>(
+    a: &'a Arena,
+    src: &str,
+    vars: &[&str],
+) -> std::result::Result<cfg::ProgramContext<'a, &'a str>, String> {
+    let mut buf = Vec::new();
+    let mut prog = ast::Prog::from_stage(a, ExecutionStrategy::Serial.stage());
+    for var in vars.iter() {
+        let mut split = var.splitn(2, '=');
+        let (name, value) = match (split.next().map(str::trim), split.next()) {
+            (Some(name), Some(value)) if lexer::is_ident(name) => (name, value),
+            _ => return Err(format!("invalid variable assignment: {}", var)),
+        };
+        buf.clear();
+        let value = lexer::parse_string_literal(value, a, &mut buf);
+        prog.prelude_vardecs
+            .push((a.alloc_str(name), a.alloc(ast::Expr::StrLit(value))));
+    }
+    let src = a.alloc_str(src);
+    parsing::syntax::ProgParser::new()
+        .parse(a, &mut buf, &mut prog, lexer::Tokenizer::new(src))
+        .map_err(|e| diagnostics::parse_error(src, &e))?;
+    parsing::add_helpers(a, &mut buf, &mut prog).map_err(|e| e.to_string())?;
+    cfg::ProgramContext::from_prog(a, a.alloc(prog), Escaper::Identity).map_err(|e| e.to_string())
+}
+
+// Collects a run's output to standard output, via `writers::set_client`.
+struct Sink(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A run of a `Program`. Dropping a run that has not finished ends its input, running its END
+/// block.
+pub struct Run {
+    // None once the program has finished.
+    interp: Option<bytecode::Interp<'static, FeedReader>>,
+    feed: Rc<RefCell<Feed>>,
+    out: Rc<RefCell<Vec<u8>>>,
+    output: Vec<u8>,
+    status: Option<std::result::Result<i32, String>>,
+}
+
+impl Run {
+    // Execute the program until it waits for the next record or finishes, collecting its output.
+    fn resume(&mut self) {
+        let interp = match self.interp.as_mut() {
+            Some(interp) => interp,
+            None => return,
+        };
+        writers::set_client(Some(Box::new(Sink(self.out.clone()))));
+        let res = interp.resume();
+        if !matches!(res, Ok(None)) {
+            // Close the program's files and wait for its commands before reporting that it is
+            // done.
+            self.interp = None;
+        }
+        writers::set_client(None);
+        self.output = mem::take(&mut *self.out.borrow_mut());
+        match res {
+            Ok(None) => {}
+            Ok(Some(status)) => self.status = Some(Ok(status)),
+            Err(e) => self.status = Some(Err(e.to_string())),
+        }
+    }
+
+    /// Process `record`, as the next record of input. Returns whether the program read the record:
+    /// it does not if it has already finished, for example by calling `exit`. Returns an error if
+    /// the program fails while processing the record.
+    pub fn feed(&mut self, record: &[u8]) -> std::result::Result<bool, String> {
+        if self.status.is_some() {
+            self.output.clear();
+            return Ok(false);
+        }
+        self.feed.borrow_mut().next = Some(record.to_vec());
+        self.resume();
+        match &self.status {
+            Some(Err(e)) => Err(e.clone()),
+            _ => Ok(true),
+        }
+    }
+
+    /// Signal the end of the input, running the END block, and return the program's exit status.
+    /// Calling this again returns the same result.
+    pub fn finish(&mut self) -> std::result::Result<i32, String> {
+        if self.status.is_none() {
+            self.feed.borrow_mut().done = true;
+            self.resume();
+        }
+        self.status.clone().unwrap()
+    }
+
+    /// What the program printed to standard output during the last step: starting the run, feeding
+    /// it a record, or finishing it.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// The input of a run, shared between the `Run` and its reader.
+#[derive(Default)]
+struct Feed {
+    // The record fed to the program that it has not read yet.
+    next: Option<Vec<u8>>,
+    // Whether the input has ended.
+    done: bool,
+}
+
+// Reads the records fed to a `Run`, reporting that it is waiting for one when there is none.
+struct FeedReader {
+    feed: Rc<RefCell<Feed>>,
+    records: Int,
+    state: ReaderState,
+}
+
+impl LineReader for FeedReader {
+    type Line = DefaultLine;
+    fn filename(&self) -> Str<'static> {
+        Str::default()
+    }
+    fn check_utf8(&self) -> bool {
+        false
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<(bool, Self::Line)> {
+        let mut line = DefaultLine::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        _pat: &Str,
+        _rc: &mut RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        let first = self.records == 0 && self.state == ReaderState::OK;
+        match self.feed.borrow_mut().next.take() {
+            Some(record) => {
+                old.set(Str::from(&record[..]).unmoor());
+                self.records += 1;
+            }
+            None => {
+                old.set(Str::default());
+                self.state = ReaderState::Eof;
+            }
+        }
+        Ok(first)
+    }
+    fn read_state(&self) -> i64 {
+        self.state as i64
+    }
+    fn pending(&self) -> bool {
+        let feed = self.feed.borrow();
+        feed.next.is_none() && !feed.done && self.state == ReaderState::OK
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        // There is just one input; skipping the rest of it ends the run's input.
+        self.state = ReaderState::Eof;
+        Ok(false)
+    }
+    fn file_index(&self) -> Int {
+        0
+    }
+    fn set_used_fields(&mut self, _used_fields: &crate::pushdown::FieldSet) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feed_records() {
+        let prog = Program::compile(
+            r#"BEGIN { print "start" } { n += $2; print $1, n } END { print "total", n }"#,
+            &[],
+        )
+        .unwrap();
+        let mut run = prog.start();
+        assert_eq!(run.output(), b"start\n");
+        assert_eq!(run.feed(b"a 1"), Ok(true));
+        assert_eq!(run.output(), b"a 1\n");
+        assert_eq!(run.feed(b"b 2"), Ok(true));
+        assert_eq!(run.output(), b"b 3\n");
+        assert_eq!(run.finish(), Ok(0));
+        assert_eq!(run.output(), b"total 3\n");
+
+        // Each run starts afresh.
+        let mut run = prog.start();
+        assert_eq!(run.feed(b"c 5"), Ok(true));
+        assert_eq!(run.output(), b"c 5\n");
+        assert_eq!(run.finish(), Ok(0));
+    }
+
+    #[test]
+    fn interleaved_runs() {
+        // Runs of the same program proceed independently, on the same thread.
+        let prog = Program::compile(r#"{ getline x; print $0 "+" x }"#, &[]).unwrap();
+        let mut r1 = prog.start();
+        let mut r2 = prog.start();
+        assert_eq!(r1.feed(b"a"), Ok(true));
+        assert_eq!(r1.output(), b"");
+        assert_eq!(r2.feed(b"c"), Ok(true));
+        assert_eq!(r1.feed(b"b"), Ok(true));
+        assert_eq!(r1.output(), b"a+b\n");
+        assert_eq!(r2.feed(b"d"), Ok(true));
+        assert_eq!(r2.output(), b"c+d\n");
+        assert_eq!(r1.finish(), Ok(0));
+        assert_eq!(r2.feed(b"e"), Ok(true));
+        assert_eq!(r2.finish(), Ok(0));
+        assert_eq!(r2.output(), b"e+\n");
+    }
+
+    #[test]
+    fn vars_and_exit() {
+        let prog = Program::compile(r#"{ print x $0 } $0 == "stop" { exit 3 }"#, &["x=>"]).unwrap();
+        let mut run = prog.start();
+        assert_eq!(run.feed(b"go"), Ok(true));
+        assert_eq!(run.output(), b">go\n");
+        assert_eq!(run.feed(b"stop"), Ok(true));
+        assert_eq!(run.feed(b"more"), Ok(false));
+        assert_eq!(run.finish(), Ok(3));
+    }
+
+    #[test]
+    fn run_io() {
+        let prog = Program::compile(r#"{ n += $2; print $1, n } END { exit n }"#, &[]).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(prog.run(&b"a 1\nb 2\n"[..], &mut out), Ok(3));
+        assert_eq!(out, b"a 1\nb 3\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Program::compile("{ print ", &[]).is_err());
+        assert!(Program::compile("{ print }", &["1x=2"]).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Entry points for the cargo-fuzz targets in `fuzz/`, which call them with arbitrary bytes.
+//!
+//! These only look for crashes: errors such as a program that does not parse, or a pattern that
+//! is not a valid regex, are expected and ignored. Inputs are read in small chunks so that records
+//! and fields straddle buffer boundaries, which is where most of the unsafe code in the splitters
+//! lives.
+use std::io::Cursor;
+use std::iter;
+
+use crate::{
+    arena::Arena,
+    ast,
+    cfg::{self, Escaper},
+    common::{ExecutionStrategy, Result},
+    compile, lexer,
+    parsing::{self, syntax},
+    runtime::{
+        printf::{printf, FormatArg},
+        splitter::{
+            batch::{CSVReader, CsvDialect, InputFormat},
+            regex::RegexSplitter,
+        },
+        Config, Int, Line, LineReader, RegexCache, Str,
+    },
+};
+
+const CHUNK_SIZE: usize = 64;
+
+/// Parse `data` as a program and, if it parses, type-check it and compile it to bytecode.
+pub fn parse(data: &[u8]) {
+    let src = match std::str::from_utf8(data) {
+        Ok(src) => src,
+        Err(_) => return,
+    };
+    let a = Arena::default();
+    let src = a.alloc_str(src);
+    let mut buf = Vec::new();
+    let mut prog = ast::Prog::from_stage(&a, ExecutionStrategy::Serial.stage());
+    let parsed =
+        syntax::ProgParser::new().parse(&a, &mut buf, &mut prog, lexer::Tokenizer::new(src));
+    if parsed.is_err() || parsing::add_helpers(&a, &mut buf, &mut prog).is_err() {
+        return;
+    }
+    let prog = a.alloc(prog);
+    if let Ok(mut ctx) = cfg::ProgramContext::from_prog(&a, prog, Escaper::Identity) {
+        let _ = compile::context_compiles(&mut ctx);
+    };
+}
+
+// Read every record from `reader`, splitting each into fields with `fs`.
+fn read_all(mut reader: impl LineReader, rs: &Str, fs: &Str) -> Result<()> {
+    let mut cache = RegexCache::default();
+    loop {
+        let (_, mut line) = reader.read_line(rs, &mut cache)?;
+        if reader.read_state() != 1 {
+            return Ok(());
+        }
+        let nf = line.nf(fs, &mut cache)?;
+        line.get_col(nf as Int, fs, &Str::from(" "), &mut cache)?;
+    }
+}
+
+/// Split `data` as CSV, using its first two bytes as the separator and quote character, or as TSV
+/// if those do not make a valid CSV dialect.
+pub fn split_csv(data: &[u8]) {
+    let (ifmt, input) = match data {
+        [sep, quote, input @ ..] => match CsvDialect::new(*sep, *quote) {
+            Ok(dialect) => (InputFormat::CSV(dialect), input),
+            Err(_) => (InputFormat::TSV, input),
+        },
+        _ => return,
+    };
+    let reader = CSVReader::new(
+        iter::once((Cursor::new(input.to_vec()), String::from("fuzz"))),
+        ifmt,
+        CHUNK_SIZE,
+        /*check_utf8=*/ false,
+        Default::default(),
+        ExecutionStrategy::Serial,
+        Default::default(),
+    );
+    let _ = read_all(reader, &Str::default(), &Str::default());
+}
+
+/// Split `data` into records and fields with a regex: the pattern is the first line of `data`,
+/// and the input is the rest.
+pub fn split_regex(data: &[u8]) {
+    let (pat, input) = match data.iter().position(|b| *b == b'\n') {
+        Some(ix) => (&data[..ix], &data[ix + 1..]),
+        None => return,
+    };
+    let pat = Str::from(pat);
+    let reader = RegexSplitter::new(
+        Cursor::new(input.to_vec()),
+        CHUNK_SIZE,
+        "fuzz",
+        /*check_utf8=*/ false,
+        Default::default(),
+    );
+    let _ = read_all(reader, &pat, &pat);
+}
+
+/// Format a fixed set of arguments with `data` as the format string.
+pub fn format(data: &[u8]) {
+    let args = [
+        FormatArg::I(-42),
+        FormatArg::F(3.25),
+        FormatArg::S(Str::from("fuzz")),
+        FormatArg::F(f64::NAN),
+        FormatArg::I(i64::MIN),
+        FormatArg::F(-1e300),
+    ];
+    let _ = printf(Vec::new(), data, &args, &Config::default());
+}
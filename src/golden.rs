@@ -0,0 +1,271 @@
+//! `frawk test DIR`: run the golden tests in a directory.
+//!
+//! A test is a script `NAME.awk` and the output it should print, `NAME.out`. If there is a
+//! `NAME.in` the script reads it as input (otherwise its standard input is empty), and if there is
+//! a `NAME.args` each of its lines is passed to frawk as an extra argument before the script, e.g.
+//! `-icsv`. Scripts run in `DIR`, once for each backend, and pass if they exit successfully having
+//! printed exactly the contents of `NAME.out`. Failures are reported with the difference between
+//! the expected and actual output, or with the script's error output if it failed.
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const BACKENDS: &[&str] = &[
+    "interp",
+    #[cfg(feature = "cranelift_backend")]
+    "cranelift",
+    #[cfg(feature = "llvm_backend")]
+    "llvm",
+];
+
+// The number of unchanged lines shown around each change in a diff.
+const CONTEXT: usize = 2;
+
+// Outputs longer than this (in lines) are not diffed line-by-line, as that takes quadratic time;
+// only the first line that differs is reported.
+const MAX_DIFF_LINES: usize = 5000;
+
+/// The directory to test if `args` (including the program name) are `test DIR` for a directory
+/// `DIR`. Anything else, including `test FILE`, is an ordinary program and its operands.
+pub(crate) fn test_dir(args: &[OsString]) -> Option<PathBuf> {
+    match args {
+        [_, cmd, dir] if cmd == "test" && Path::new(dir).is_dir() => Some(PathBuf::from(dir)),
+        _ => None,
+    }
+}
+
+struct Test {
+    name: String,
+    script: PathBuf,
+}
+
+fn discover(dir: &Path) -> io::Result<Vec<Test>> {
+    let mut tests = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let script = PathBuf::from(entry?.file_name());
+        if script.extension().map_or(true, |ext| ext != "awk") {
+            continue;
+        }
+        let name = match script.file_stem().and_then(|s| s.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        tests.push(Test { name, script });
+    }
+    tests.sort_by(|t1, t2| t1.name.cmp(&t2.name));
+    Ok(tests)
+}
+
+fn run(dir: &Path, test: &Test, backend: &str) -> io::Result<Output> {
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.current_dir(dir).arg(format!("-B{}", backend));
+    if let Ok(args) = fs::read_to_string(dir.join(test.script.with_extension("args"))) {
+        cmd.args(args.lines().filter(|arg| !arg.is_empty()));
+    }
+    cmd.arg("-f").arg(&test.script);
+    let input = test.script.with_extension("in");
+    if dir.join(&input).is_file() {
+        cmd.arg(input);
+    } else {
+        cmd.stdin(Stdio::null());
+    }
+    cmd.output()
+}
+
+// Why a test failed under one backend.
+enum Failure {
+    Error(String),
+    Diff(String),
+}
+
+fn check(dir: &Path, test: &Test, expected: &[u8], backend: &str) -> Option<Failure> {
+    let out = match run(dir, test, backend) {
+        Ok(out) => out,
+        Err(e) => return Some(Failure::Error(format!("failed to run frawk: {}", e))),
+    };
+    if !out.status.success() {
+        let status = match out.status.code() {
+            Some(code) => format!("exited with status {}", code),
+            None => String::from("was killed by a signal"),
+        };
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        return Some(Failure::Error(format!(
+            "{}:\n{}",
+            status,
+            stderr.trim_end()
+        )));
+    }
+    if out.stdout == expected {
+        return None;
+    }
+    Some(Failure::Diff(diff(expected, &out.stdout)))
+}
+
+fn lines(bs: &[u8]) -> Vec<&[u8]> {
+    let bs = bs.strip_suffix(b"\n").unwrap_or(bs);
+    if bs.is_empty() {
+        return Vec::new();
+    }
+    bs.split(|b| *b == b'\n').collect()
+}
+
+/// A line diff from `expected` to `got`, with removed lines prefixed by `-`, added lines by `+`,
+/// and a few unchanged lines of context prefixed by a space.
+fn diff(expected: &[u8], got: &[u8]) -> String {
+    let (old, new) = (lines(expected), lines(got));
+    let mut out = String::new();
+    let mut push = |prefix: char, line: &[u8]| {
+        out.push(prefix);
+        out.push_str(&String::from_utf8_lossy(line));
+        out.push('\n');
+    };
+    if old == new {
+        // The lines are the same, so the outputs differ in whether they end with a newline.
+        push(' ', b"(the output differs only in its trailing newline)");
+        return out;
+    }
+    if old.len().max(new.len()) > MAX_DIFF_LINES {
+        let ix = old
+            .iter()
+            .zip(new.iter())
+            .take_while(|(o, n)| o == n)
+            .count();
+        push(
+            ' ',
+            format!("(first difference at line {})", ix + 1).as_bytes(),
+        );
+        if let Some(line) = old.get(ix) {
+            push('-', line);
+        }
+        if let Some(line) = new.get(ix) {
+            push('+', line);
+        }
+        return out;
+    }
+
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', old[i]));
+            i += 1;
+        } else {
+            ops.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    // Only show unchanged lines near a change.
+    let near_change = |ix: usize| {
+        let lo = ix.saturating_sub(CONTEXT);
+        let hi = (ix + CONTEXT + 1).min(ops.len());
+        ops[lo..hi].iter().any(|(op, _)| *op != ' ')
+    };
+    let mut skipped = false;
+    for (ix, (op, line)) in ops.iter().enumerate() {
+        if *op == ' ' && !near_change(ix) {
+            skipped = true;
+            continue;
+        }
+        if skipped {
+            push(' ', b"...");
+            skipped = false;
+        }
+        push(*op, line);
+    }
+    out
+}
+
+/// Run the tests in `dir`, printing a report to standard output, and return the exit status:
+/// 0 if every test passed, 1 if any failed.
+pub(crate) fn run_tests(dir: &Path) -> io::Result<i32> {
+    let tests = discover(dir)?;
+    let stdout = io::stdout();
+    let mut w = stdout.lock();
+    let mut failures = Vec::new();
+    for test in tests.iter() {
+        let expected = match fs::read(dir.join(test.script.with_extension("out"))) {
+            Ok(expected) => expected,
+            Err(e) => {
+                writeln!(w, "test {} ... FAILED", test.name)?;
+                let msg = format!("could not read {}.out: {}", test.name, e);
+                failures.push((test.name.clone(), None, Failure::Error(msg)));
+                continue;
+            }
+        };
+        let mut failed = false;
+        for backend in BACKENDS.iter().cloned() {
+            if let Some(failure) = check(dir, test, &expected, backend) {
+                failures.push((test.name.clone(), Some(backend), failure));
+                failed = true;
+            }
+        }
+        writeln!(
+            w,
+            "test {} ... {}",
+            test.name,
+            if failed { "FAILED" } else { "ok" }
+        )?;
+    }
+
+    let failed_tests = {
+        let mut names: Vec<&str> = failures.iter().map(|(name, _, _)| name.as_str()).collect();
+        names.dedup();
+        names.len()
+    };
+    if !failures.is_empty() {
+        writeln!(w, "\nfailures:")?;
+        for (name, backend, failure) in failures.iter() {
+            match backend {
+                Some(backend) => writeln!(w, "\n---- {} ({}) ----", name, backend)?,
+                None => writeln!(w, "\n---- {} ----", name)?,
+            }
+            match failure {
+                Failure::Error(msg) => writeln!(w, "{}", msg)?,
+                Failure::Diff(diff) => write!(w, "--- {0}.out\n+++ output\n{1}", name, diff)?,
+            }
+        }
+    }
+    writeln!(
+        w,
+        "\ntest result: {}. {} passed; {} failed",
+        if failed_tests == 0 { "ok" } else { "FAILED" },
+        tests.len() - failed_tests,
+        failed_tests
+    )?;
+    Ok(if failed_tests == 0 { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs() {
+        assert_eq!(diff(b"a\nb\nc\n", b"a\nx\nc\n"), " a\n-b\n+x\n c\n");
+        let old = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        assert_eq!(diff(old, new), " ...\n 8\n 9\n+10\n");
+        assert_eq!(
+            diff(b"a\n", b"a"),
+            " (the output differs only in its trailing newline)\n"
+        );
+    }
+}
@@ -8,7 +8,6 @@ use crate::{
     arena::Arena,
     ast,
     cfg::{self, Escaper},
-    codegen::{self, intrinsics::IntoRuntime},
     common::{ExecutionStrategy, Result},
     compile, lexer,
     parsing::syntax,
@@ -16,14 +15,20 @@ use crate::{
     runtime::{
         self,
         splitter::{
-            batch::{ByteReader, CSVReader, InputFormat},
+            batch::{CSVReader, InputFormat},
+            chunk::{ChunkProducer, OffsetChunk},
             regex::RegexSplitter,
         },
         writers::testing::FakeFs,
-        ChainedReader,
+        ArgvReader, ChainedReader,
     },
     types::{self, get_types},
 };
+#[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
+use crate::{
+    codegen::{self, intrinsics::IntoRuntime},
+    runtime::splitter::batch::ByteReader,
+};
 
 use cfg_if::cfg_if;
 use hashbrown::HashMap;
@@ -47,6 +52,7 @@ fn split_stdin(
     )
     .into_iter()
 }
+#[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
 macro_rules! with_reader {
     ($report:expr, $inp:expr, |$id:ident| $body:expr) => {
         match $report {
@@ -78,39 +84,42 @@ macro_rules! with_reader {
     };
 }
 
+#[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
 fn simulate_stdin_singlechar(
     field_sep: u8,
     record_sep: u8,
     inp: impl Into<String>,
 ) -> impl IntoRuntime + runtime::LineReader {
-    ByteReader::new(
+    ArgvReader::fixed(ByteReader::new(
         split_stdin(inp.into()),
         field_sep,
         record_sep,
         runtime::CHUNK_SIZE,
         /*check_utf8=*/ true,
+        Default::default(),
+        /*stream=*/ false,
         ExecutionStrategy::Serial,
         Default::default(),
-    )
+    ))
 }
 
+#[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
 fn simulate_stdin_whitespace(inp: impl Into<String>) -> impl IntoRuntime + runtime::LineReader {
-    ByteReader::new_whitespace(
+    ArgvReader::fixed(ByteReader::new_whitespace(
         split_stdin(inp.into()),
         runtime::CHUNK_SIZE,
         /*check_utf8=*/ true,
+        Default::default(),
+        /*stream=*/ false,
         ExecutionStrategy::Serial,
         Default::default(),
-    )
+    ))
 }
 
 fn simulate_stdin<LR: runtime::LineReader>(
     inp: impl Into<String>,
     mut f: impl FnMut(Box<dyn io::Read + Send>, String) -> LR,
-) -> ChainedReader<LR>
-where
-    ChainedReader<LR>: IntoRuntime,
-{
+) -> ChainedReader<LR> {
     ChainedReader::new(split_stdin(inp.into()).map(|(r, name)| f(r, name)))
 }
 
@@ -118,26 +127,30 @@ fn simulate_stdin_csv(
     ifmt: InputFormat,
     inp: impl Into<String>,
     strat: ExecutionStrategy,
-) -> impl IntoRuntime + runtime::LineReader {
-    CSVReader::new(
+) -> ArgvReader<CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>> {
+    ArgvReader::fixed(CSVReader::new(
         split_stdin(inp.into()),
         ifmt,
         runtime::CHUNK_SIZE,
         /*check_utf8=*/ true,
+        Default::default(),
         strat,
         Default::default(),
-    )
+    ))
 }
 
-fn simulate_stdin_regex(inp: impl Into<String>) -> impl IntoRuntime + runtime::LineReader {
-    simulate_stdin(inp, |reader, name| {
+pub(crate) fn simulate_stdin_regex(
+    inp: impl Into<String>,
+) -> ArgvReader<ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>> {
+    ArgvReader::fixed(simulate_stdin(inp, |reader, name| {
         RegexSplitter::new(
             reader,
             runtime::CHUNK_SIZE,
             name,
             /*check_utf8=*/ false,
+            Default::default(),
         )
-    })
+    }))
 }
 
 const _PRINT_DEBUG_INFO: bool = false;
@@ -150,9 +163,12 @@ type ProgResult<'a> = Result<(
     HashMap<&'a str, compile::Ty>, /* type info */
 )>;
 
+#[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
 const CODEGEN_CONFIG: codegen::Config = codegen::Config {
     opt_level: 0,
     num_workers: 1,
+    perf_map: false,
+    debug_info: false,
 };
 
 pub(crate) fn run_program<'a>(
@@ -217,6 +233,8 @@ cfg_if! {
                     llvm::Config {
                         opt_level: CODEGEN_CONFIG.opt_level,
                         num_workers: strat.num_workers(),
+                        perf_map: false,
+                        debug_info: false,
                     },
                     Default::default(),
                 )?;
@@ -234,6 +252,7 @@ cfg_if! {
     }
 }
 
+#[cfg(feature = "cranelift_backend")]
 pub(crate) fn run_cranelift(
     prog: &str,
     stdin: impl Into<String>,
@@ -260,6 +279,8 @@ pub(crate) fn run_cranelift(
             codegen::Config {
                 opt_level: CODEGEN_CONFIG.opt_level,
                 num_workers: strat.num_workers(),
+                perf_map: false,
+                debug_info: false,
             },
             Default::default(),
         )?;
@@ -324,10 +345,11 @@ pub(crate) fn parse_program<'a, 'inp>(
     let parser = syntax::ProgParser::new();
     match parser.parse(a, &mut buf, &mut program, lexer) {
         Ok(()) => {
+            crate::parsing::add_helpers(a, &mut buf, &mut program)?;
             match esc {
                 Escaper::CSV => program.output_sep = Some(b","),
                 Escaper::TSV => program.output_sep = Some(b"\t"),
-                Escaper::Identity => {}
+                Escaper::JSON | Escaper::MsgPack | Escaper::Identity => {}
             };
             Ok(a.alloc(program))
         }
@@ -469,7 +491,7 @@ mod tests {
                         $e,
                         $in,
                         Escaper::Identity,
-                        Some(InputFormat::CSV),
+                        Some(InputFormat::CSV(Default::default())),
                         ExecutionStrategy::$strat,
                     );
                     match out {
@@ -487,7 +509,7 @@ mod tests {
                         $e,
                         $in,
                         Escaper::Identity,
-                        Some(InputFormat::CSV),
+                        Some(InputFormat::CSV(Default::default())),
                         ExecutionStrategy::$strat,
                     ) {
                         Ok(out) => assert_eq!(
@@ -553,6 +575,7 @@ mod tests {
                     }
                 }
 
+                #[cfg(feature = "cranelift_backend")]
                 #[test]
                 fn cranelift() {
                     match run_cranelift($e, $inp, $esc, $csv, ExecutionStrategy::Serial) {
@@ -579,7 +602,7 @@ mod tests {
         ($desc:ident, $e:expr, $out:expr, @input $inp:expr) => {
             test_program!(
                 $desc, $e, $out, @input $inp,
-                @types [], @out_fmt Escaper::Identity, @csv Some(InputFormat::CSV)
+                @types [], @out_fmt Escaper::Identity, @csv Some(InputFormat::CSV(Default::default()))
             );
         };
     }
@@ -621,6 +644,21 @@ mod tests {
         assert_eq!(s1, used_fields(p1).unwrap());
     }
 
+    #[test]
+    fn used_fields_assignments() {
+        let p1 = r#"{ $2 = "x"; print $2; }"#;
+        assert_eq!(FieldSet::singleton(2), used_fields(p1).unwrap());
+
+        // $0 is rebuilt from all of the fields after one is assigned to.
+        let p2 = r#"{ $2 = "x"; print $0; }"#;
+        assert_eq!(FieldSet::all(), used_fields(p2).unwrap());
+
+        let p3 = r#"{ $0 = "a b"; print $0, $1; }"#;
+        let mut s3 = FieldSet::singleton(0);
+        s3.set(1);
+        assert_eq!(s3, used_fields(p3).unwrap());
+    }
+
     #[test]
     fn used_fields_maps() {
         let p1 = r#"{ m[1]=1; m[2]=3; y=m[8]; for (i in m) print $i; }"#;
@@ -1320,6 +1358,76 @@ this as well"#
         @input "snow ball"
     );
 
+    test_program!(
+        string_escapes,
+        r#"BEGIN { print "\u{e9}\x41\102\u{1F600}", length("\u{1F600}"); }"#,
+        "éAB😀 4\n"
+    );
+
+    test_program!(
+        raw_string_literals,
+        r#"BEGIN {
+        re = r"^\d+\.\d+$";
+        print re, length(r"\t"), ("3.25" ~ re), ("3x25" ~ re);
+        s = "a.b.c"; gsub(r"\.", "-", s); print s;
+        }"#,
+        "^\\d+\\.\\d+$ 2 1 0\na-b-c\n"
+    );
+
+    test_program!(
+        typed_regex_constants,
+        r#"function matches(s, re) { return s ~ re; }
+        BEGIN {
+        re = @/^a+$/;
+        print matches("aaa", re), matches("ab", re), matches("a", @/b/);
+        s = "a.b"; gsub(@/\./, "-", s); print s;
+        print (@/a/ == "a"), (@/a/ == @/a/), (re "");
+        }"#,
+        "1 0 0\na-b\n0 1 ^a+$\n"
+    );
+
+    test_program!(
+        substitution_escapes,
+        r#"BEGIN {
+        s = "abc"; sub(/b/, "[&]", s); print s;
+        s = "abc"; sub(/b/, "[\\&]", s); print s;
+        s = "abc"; sub(/b/, "[\\\\&]", s); print s;
+        s = "abc"; gsub(/b/, "\\\\", s); print s;
+        s = "abc"; gsub(/b/, "\\q", s); print s;
+        }"#,
+        "a[b]c\na[&]c\na[\\b]c\na\\c\na\\qc\n"
+    );
+
+    test_program!(
+        column_substitutions_no_match,
+        r#"{
+        n = NF;
+        nsubs = gsub(/z/, "y");
+        print n, nsubs, $2;
+        $2 = "b";
+        nsubs = gsub(/q/, "y");
+        print NF, nsubs, $0;
+        gsub(/ /, ",");
+        print NF, $1;
+        }"#,
+        "3 0 b\n3 0 a b c\n1 a,b,c\n",
+        @input "a b c"
+    );
+
+    test_program!(
+        column_reassign_after_fs,
+        r#"{
+        print $1;
+        FS = ",";
+        $0 = $0;
+        print NF, $1;
+        sub(/b/, "x", $0);
+        print NF, $2;
+        }"#,
+        "a,b\n2 a\n2 x c\n",
+        @input "a,b c"
+    );
+
     test_program!(
         substrings,
         r#"BEGIN {
@@ -1332,13 +1440,23 @@ this as well"#
             substr(s, 11),
         );
     }"#,
-        "[t] is a this string string\n"
+        "[t] is a  string string\n"
     );
     test_program!(
         out_of_range_substr,
         r#"BEGIN { print substr("hi", 5, 10); }"#,
         "\n"
     );
+    test_program!(
+        substr_edge_cases,
+        r#"BEGIN {
+        s = "hello";
+        print substr(s, 0, 2), substr(s, -1, 3), substr(s, 1.5), substr(s, 2.3, 2);
+        print "[" substr(s, 1, 0) "]", "[" substr(s, 1, -3) "]", substr(s, 5, 5), "[" substr(s, 7, 2) "]";
+        print substr(s, 1.5, 1.5), substr(s, 0.4, 1.6);
+    }"#,
+        "h h ello el\n[] [] o []\nel h\n"
+    );
 
     test_program!(
         arithmetic,
@@ -1369,6 +1487,50 @@ this as well"#
         "1 2 3\n"
     );
 
+    test_program!(
+        length_polymorphic,
+        r#"BEGIN {
+            x = 12345; y = 1.5; m["a"] = 1; m["b"] = 2;
+            print length(x), length(y), length(-7), length(x y), length("abc"), length(m)
+        }"#,
+        "5 3 2 8 3 2\n"
+    );
+
+    test_program!(
+        subsep_membership,
+        r#"BEGIN { SUBSEP = ":" }
+        { m[$1, $2] = $3 }
+        END {
+            print ((1, 2) in m), ((2, 1) in m), ("3:4" in m);
+            delete m[1, 2];
+            print ((1, 2) in m);
+            for (k in m) { split(k, ix, SUBSEP); print ix[1], ix[2], m[k]; }
+        }"#,
+        "1 0 1\n0\n3 4 b\n",
+        @input "1 2 a\n3 4 b\n"
+    );
+
+    test_program!(
+        strnum_comparisons,
+        r#"{ x = $1; y = $2; print ($1 < $2), ($1 == $2), (x < y), ($1 < "9") }
+        END { a = "10"; b = "9"; print (a < b) }"#,
+        "0 0 0 1\n0 1 0 1\n1 0 1 0\n1 0 1 1\n1\n",
+        @input "10 9\n1.0 1\nabc abd\n10 abc\n"
+    );
+
+    test_program!(
+        indirect_calls,
+        r#"function add(a, b) { return a + b }
+        function cat(a, b) { return a b }
+        function neg(a) { return -a }
+        BEGIN {
+            f = "add"; print @f(1, 2);
+            f = "cat"; print @f(1, 2);
+            f = "neg"; print @f(5)
+        }"#,
+        "3\n12\n-5\n"
+    );
+
     test_program!(
         function_locals,
         r#"function p(n,  i,res) {
@@ -1431,6 +1593,17 @@ this as well"#
         @input "aboba\n"
     );
 
+    test_program!(
+        type_annotations,
+        r#"# @type n: float
+        # @type m: map[str]int
+        function half(x: int): float { return x / 2 }
+        BEGIN { n = 1; m["a"] = 2; print n, half(3), m["a"] }"#,
+        "1 1.5 2\n",
+        @input "",
+        @types [n :: Float, m :: MapStrInt]
+    );
+
     // TODO test more operators, consider more edge cases around functions
 }
 
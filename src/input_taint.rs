@@ -115,6 +115,7 @@ impl TaintedStringAnalysis {
             }
             GetColumn(dst, _) => self.dfa.add_src(dst, Taint::Tainted),
             ReadErrStdin(dst) => self.dfa.add_src(dst, Taint::Tainted),
+            FileBoundary(dst) => self.dfa.add_src(dst, Taint::Okay),
             NextLineStdin(dst) => self.dfa.add_src(dst, Taint::Tainted),
             StoreConstStr(dst, _) => self.dfa.add_src(dst, Taint::Okay),
             StoreConstInt(dst, _) => self.dfa.add_src(dst, Taint::Okay),
@@ -131,6 +132,10 @@ impl TaintedStringAnalysis {
                 self.dfa.add_query(cmd);
                 self.dfa.add_src(dst, Taint::Tainted);
             }
+            // Restored variables come from the --resume file.
+            RestoreVar { .. } => dataflow::boilerplate::visit_ll(inst, |dst, _| {
+                self.dfa.add_src(dst, Taint::Tainted)
+            }),
             _ => dataflow::boilerplate::visit_ll(inst, |dst, src| {
                 if let Some(src) = src {
                     self.dfa.add_dep(dst, src, ())
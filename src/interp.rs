@@ -1,9 +1,11 @@
 use crate::builtins::Variable;
-use crate::bytecode::{Get, Instr, Label, Reg};
+use crate::bytecode::{FrameRegs, Get, Instr, Label, Reg};
 use crate::common::{NumTy, Result, Stage};
 use crate::compile::{self, Ty};
 use crate::pushdown::FieldSet;
-use crate::runtime::{self, Float, Int, Line, LineReader, Str, UniqueStr};
+use crate::runtime::{
+    self, arith, strict, Float, Int, Line, LineReader, Str, TypedRegex, UniqueStr,
+};
 
 use crossbeam::scope;
 use crossbeam_channel::bounded;
@@ -11,7 +13,6 @@ use hashbrown::HashMap;
 use rand::{self, rngs::StdRng, Rng, SeedableRng};
 use regex::bytes::Regex;
 
-use std::cmp;
 use std::mem;
 
 type ClassicReader = runtime::splitter::regex::RegexSplitter<Box<dyn std::io::Read>>;
@@ -30,14 +31,30 @@ pub(crate) struct Core<'a> {
     pub write_files: runtime::FileWrite,
     pub rng: StdRng,
     pub current_seed: u64,
+    pub samples: runtime::Samples<'a>,
+    pub top_k: runtime::TopK<'a>,
     pub slots: Slots,
+    pub config: runtime::Config,
 }
 
 impl<'a> Drop for Core<'a> {
     fn drop(&mut self) {
+        self.flush_warnings();
         if let Err(e) = self.write_files.shutdown() {
             eprintln_ignore!("{}", e);
         }
+        // Worker threads exit before the main thread does, so it is left to the main thread to
+        // wait for any commands that were run.
+        if self.vars.pid <= 1 {
+            // Output written before the program ended comes ahead of the commands' own output.
+            // Errors writing to stdout are ignored, as they are when stdout is flushed on drop.
+            let _ = self.write_files.flush_stdout();
+            if let Err(e) = self.write_files.close_all_commands() {
+                eprintln_ignore!("{}", e);
+            }
+            runtime::wait_all_commands(self.config.fail_on_command_error);
+            self.config.warnings.report_stats();
+        }
     }
 }
 
@@ -57,6 +74,7 @@ pub(crate) struct Slots {
     pub strint: Vec<HashMap<UniqueStr<'static>, Int>>,
     pub strfloat: Vec<HashMap<UniqueStr<'static>, Float>>,
     pub strstr: Vec<HashMap<UniqueStr<'static>, UniqueStr<'static>>>,
+    pub regexes: Vec<TypedRegex>,
 }
 
 /// A Simple helper trait for implement aggregations for slot values and variables.
@@ -83,6 +101,16 @@ impl<'a> Agg for UniqueStr<'a> {
         }
     }
 }
+impl Agg for TypedRegex {
+    fn agg(self, other: TypedRegex) -> TypedRegex {
+        // Like strings, keep the last regex that was set.
+        if other.regex().as_str().is_empty() {
+            self
+        } else {
+            other
+        }
+    }
+}
 impl<K: std::hash::Hash + Eq, V: Agg + Default> Agg for HashMap<K, V> {
     fn agg(mut self, other: HashMap<K, V>) -> HashMap<K, V> {
         for (k, v) in other {
@@ -110,7 +138,7 @@ impl Slots {
             ($s1:ident, $s2:ident, $body:expr) => {
                 for_each_slot_pair!(
                     $s1, $s2, $body, int, float, strs, intint, intfloat, intstr, strint, strfloat,
-                    strstr
+                    strstr, regexes
                 );
             };
             ($s1:ident, $s2:ident, $body:expr, $($fld:tt),*) => {$({
@@ -160,23 +188,29 @@ impl<'a> Core<'a> {
         let rs: UniqueStr<'a> = self.vars.rs.clone().into();
         let ors: UniqueStr<'a> = self.vars.ors.clone().into();
         let filename: UniqueStr<'a> = self.vars.filename.clone().into();
+        let rt: UniqueStr<'a> = self.vars.rt.clone().into();
+        let argc = self.vars.argc;
         let argv = self.vars.argv.shuttle();
         let fi = self.vars.fi.shuttle();
         let slots = self.slots.clone();
+        let config = self.config.clone();
         move || {
+            fw.attach_thread();
             let vars = Variables {
                 fs: fs.into_str(),
                 ofs: ofs.into_str(),
                 ors: ors.into_str(),
                 rs: rs.into_str(),
                 filename: filename.into_str(),
+                rt: rt.into_str(),
                 pid,
                 nf: 0,
                 nr: 0,
                 fnr: 0,
                 rstart: 0,
                 rlength: 0,
-                argc: 0,
+                argc,
+                argind: 0,
                 argv: argv.into(),
                 fi: fi.into(),
             };
@@ -186,19 +220,25 @@ impl<'a> Core<'a> {
                 write_files: fw,
                 rng: rand::rngs::StdRng::seed_from_u64(seed),
                 current_seed: seed,
+                samples: Default::default(),
+                top_k: Default::default(),
                 slots,
+                config,
             }
         }
     }
-    pub fn new(ff: impl runtime::writers::FileFactory) -> Core<'a> {
+    pub fn new(ff: impl runtime::writers::FileFactory, config: runtime::Config) -> Core<'a> {
         let seed: u64 = rand::thread_rng().gen();
         Core {
             vars: Default::default(),
             regexes: Default::default(),
-            write_files: runtime::FileWrite::new(ff),
+            write_files: runtime::FileWrite::new(ff, &config),
             rng: rand::rngs::StdRng::seed_from_u64(seed),
             current_seed: seed,
+            samples: Default::default(),
+            top_k: Default::default(),
             slots: Default::default(),
+            config,
         }
     }
 
@@ -230,6 +270,15 @@ impl<'a> Core<'a> {
         self.regexes.regex_match_loc(&mut self.vars, pat, s)
     }
 
+    pub fn match_regex_captures(
+        &mut self,
+        s: &Str<'a>,
+        pat: &Str<'a>,
+        m: &runtime::StrMap<'a, Str<'a>>,
+    ) -> Result<Int> {
+        self.regexes.regex_match_captures(&mut self.vars, pat, s, m)
+    }
+
     pub fn match_const_regex(&mut self, s: &Str<'a>, pat: &Regex) -> Result<Int> {
         runtime::RegexCache::regex_const_match_loc(&mut self.vars, pat, s)
     }
@@ -238,6 +287,14 @@ impl<'a> Core<'a> {
         self.regexes.is_regex_match(pat, s)
     }
 
+    /// Print warnings reported while processing the current record. This is called before the next
+    /// record is read, so that NR and FILENAME still refer to the record they were reported for.
+    pub fn flush_warnings(&self) {
+        self.config
+            .warnings
+            .flush(self.vars.nr, &self.vars.filename)
+    }
+
     pub fn load_int(&mut self, slot: usize) -> Int {
         self.slots.int[slot]
     }
@@ -247,6 +304,9 @@ impl<'a> Core<'a> {
     pub fn load_str(&mut self, slot: usize) -> Str<'a> {
         mem::take(&mut self.slots.strs[slot]).into_str().upcast()
     }
+    pub fn load_regex(&mut self, slot: usize) -> TypedRegex {
+        mem::take(&mut self.slots.regexes[slot])
+    }
     pub fn load_intint(&mut self, slot: usize) -> runtime::IntMap<Int> {
         mem::take(&mut self.slots.intint[slot]).into()
     }
@@ -287,6 +347,9 @@ impl<'a> Core<'a> {
     pub fn store_str(&mut self, slot: usize, s: Str<'a>) {
         set_slot(&mut self.slots.strs, slot, s.unmoor().into())
     }
+    pub fn store_regex(&mut self, slot: usize, re: TypedRegex) {
+        set_slot(&mut self.slots.regexes, slot, re)
+    }
     pub fn store_intint(&mut self, slot: usize, s: runtime::IntMap<Int>) {
         set_slot(
             &mut self.slots.intint,
@@ -396,22 +459,68 @@ macro_rules! map_regs {
                 let $iter_reg: Reg<runtime::Iter<Str<'a>>> = $iter_reg.into();
                 $body
             }
-            Ty::Null | Ty::Int | Ty::Float | Ty::Str | Ty::IterInt | Ty::IterStr => panic!(
-                "attempting to perform map operations on non-map type: {:?}",
-                map_ty
-            ),
+            Ty::Null | Ty::Int | Ty::Float | Ty::Str | Ty::Regex | Ty::IterInt | Ty::IterStr => {
+                panic!(
+                    "attempting to perform map operations on non-map type: {:?}",
+                    map_ty
+                )
+            }
         }
     }};
 }
 
+// What to do after executing an instruction.
+enum Step {
+    // Continue at the given instruction in the (possibly new) current function.
+    Next(usize),
+    // Stop executing with the given exit code.
+    Exit(i32),
+    // Stop executing until the reader has more input, then execute the current instruction again.
+    Suspend,
+}
+
+// A function executing a single instruction; see `Interp::step` for the meaning of the arguments.
+type Handler<'a, LR> = fn(&mut Interp<'a, LR>, &mut usize, usize) -> Result<Step>;
+
+// Generates dedicated handlers (see `Interp::handler`) for instructions that compute a scalar
+// from the values of one or more registers.
+macro_rules! handlers {
+    ($($name:ident: $variant:ident($($arg:ident),+) => $e:expr;)*) => {
+        $(
+            fn $name(
+                &mut self,
+                cur_fn: &mut usize,
+                cur: usize,
+            ) -> Result<Step> {
+                if let Instr::$variant(res, $($arg),+) = *self.instr(*cur_fn, cur) {
+                    $(let $arg = *self.get($arg);)+
+                    *self.get_mut(res) = $e;
+                    Ok(Step::Next(cur + 1))
+                } else {
+                    unreachable!()
+                }
+            }
+        )*
+    };
+}
+
 pub(crate) struct Interp<'a, LR: LineReader = ClassicReader> {
     // index of `instrs` that contains "main"
     main_func: Stage<usize>,
     num_workers: usize,
     instrs: Vec<Vec<Instr<'a>>>,
+    // The handler for each instruction in `instrs`. Release builds dispatch through these rather
+    // than matching on every instruction.
+    handlers: Vec<Vec<Handler<'a, LR>>>,
     stack: Vec<(usize /*function*/, Label /*instr*/)>,
+    // Where execution stopped to wait for input, if it did (see `resume`).
+    suspended: Option<(usize /*function*/, usize /*instr*/)>,
+    // Scratch space for formatting arguments to printf and sprintf.
+    scratch: Vec<runtime::FormatArg<'a>>,
 
     line: LR::Line,
+    // NB: read_files is dropped before core, so that pipes from commands are closed before Core
+    // waits for those commands to exit.
     read_files: runtime::FileRead<LR>,
 
     core: Core<'a>,
@@ -422,6 +531,7 @@ pub(crate) struct Interp<'a, LR: LineReader = ClassicReader> {
     pub(crate) floats: Storage<Float>,
     pub(crate) ints: Storage<Int>,
     pub(crate) strs: Storage<Str<'a>>,
+    pub(crate) regexes: Storage<TypedRegex>,
     pub(crate) maps_int_float: Storage<runtime::IntMap<Float>>,
     pub(crate) maps_int_int: Storage<runtime::IntMap<Int>>,
     pub(crate) maps_int_str: Storage<runtime::IntMap<Str<'a>>>,
@@ -451,22 +561,28 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         regs: impl Fn(compile::Ty) -> usize,
         stdin: LR,
         ff: impl runtime::writers::FileFactory,
+        config: runtime::Config,
         used_fields: &FieldSet,
         named_columns: Option<Vec<&[u8]>>,
     ) -> Self {
         use compile::Ty::*;
+        let read_files = runtime::FileRead::new(stdin, used_fields.clone(), named_columns, &config);
         Interp {
             main_func,
             num_workers,
+            handlers: Self::threaded(&instrs),
             instrs,
             stack: Default::default(),
+            suspended: None,
+            scratch: Default::default(),
             floats: default_of(regs(Float)),
             ints: default_of(regs(Int)),
             strs: default_of(regs(Str)),
-            core: Core::new(ff),
+            regexes: default_of(regs(Regex)),
+            core: Core::new(ff, config),
 
-            line: Default::default(),
-            read_files: runtime::FileRead::new(stdin, used_fields.clone(), named_columns),
+            line: read_files.new_line(),
+            read_files,
 
             maps_int_float: default_of(regs(MapIntFloat)),
             maps_int_int: default_of(regs(MapIntInt)),
@@ -495,8 +611,12 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
     }
 
     fn reset_file_vars(&mut self) {
-        self.core.vars.fnr = 0;
+        self.core.vars.fnr = self.read_files.stdin_file_records();
         self.core.vars.filename = self.read_files.stdin_filename().upcast();
+        // ARGIND stays at 0 when there are no input files and we are reading standard input.
+        if self.core.vars.argc > 1 {
+            self.core.vars.argind = self.read_files.stdin_file_index();
+        }
     }
 
     pub(crate) fn run_parallel(&mut self) -> Result<i32> {
@@ -547,6 +667,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             let float_size = self.floats.regs.len();
             let ints_size = self.ints.regs.len();
             let strs_size = self.strs.regs.len();
+            let regexes_size = self.regexes.regs.len();
             let maps_int_int_size = self.maps_int_int.regs.len();
             let maps_int_float_size = self.maps_int_float.regs.len();
             let maps_int_str_size = self.maps_int_str.regs.len();
@@ -555,24 +676,32 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             let maps_str_str_size = self.maps_str_str.regs.len();
             let iters_int_size = self.iters_int.regs.len();
             let iters_str_size = self.iters_str.regs.len();
+            let pinning = self.core.config.pinning;
             for (i, handle) in handles.into_iter().enumerate() {
                 let sender = sender.clone();
                 let core_shuttle = self.core.shuttle(i as Int + 2);
                 let instrs = self.instrs.clone();
+                let handlers = self.handlers.clone();
                 s.spawn(move |_| {
+                    // Pin before `handle` allocates this worker's input buffers.
+                    runtime::affinity::pin_worker(pinning, i + 1);
                     if let Some(read_files) = handle() {
                         let mut interp = Interp {
                             main_func: Stage::Main(main_loop),
                             num_workers: 1,
                             instrs,
+                            handlers,
                             stack: Default::default(),
+                            suspended: None,
+                            scratch: Default::default(),
                             core: core_shuttle(),
-                            line: Default::default(),
+                            line: read_files.new_line(),
                             read_files,
 
                             floats: default_of(float_size),
                             ints: default_of(ints_size),
                             strs: default_of(strs_size),
+                            regexes: default_of(regexes_size),
                             maps_int_int: default_of(maps_int_int_size),
                             maps_int_float: default_of(maps_int_float_size),
                             maps_int_str: default_of(maps_int_str_size),
@@ -594,6 +723,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                 });
             }
             mem::drop(sender);
+            runtime::affinity::pin_worker(pinning, 0);
             self.core.vars.pid = 1;
             let mut rc = self.run_at(main_loop)?;
             self.core.vars.pid = 0;
@@ -608,6 +738,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             Ok(rc)
         });
         let rc = wrap_error(scope_res)?;
+        runtime::writers::flush_ordered()?;
         if rc != 0 {
             return Ok(rc);
         }
@@ -637,710 +768,1149 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         }
     }
 
-    #[allow(clippy::never_loop)]
-    pub(crate) fn run_at(&mut self, mut cur_fn: usize) -> Result<i32> {
+    /// Run a serial program until it finishes, returning its exit status, or until it reads from
+    /// a reader that is waiting for more input, returning `None`. Calling this again continues
+    /// from where execution stopped.
+    #[cfg(feature = "embed")]
+    pub(crate) fn resume(&mut self) -> Result<Option<i32>> {
+        let (cur_fn, cur) = match (self.suspended.take(), &self.main_func) {
+            (Some(at), _) => at,
+            (None, Stage::Main(main)) => (*main, 0),
+            (None, Stage::Par { .. }) => {
+                return err!("unexpected parallel configuration for resumable execution")
+            }
+        };
+        self.run_from(cur_fn, cur)
+    }
+
+    pub(crate) fn run_at(&mut self, cur_fn: usize) -> Result<i32> {
+        // Only `resume` runs programs over readers that wait for input.
+        Ok(self.run_from(cur_fn, 0)?.unwrap_or(0))
+    }
+
+    fn run_from(&mut self, mut cur_fn: usize, mut cur: usize) -> Result<Option<i32>> {
+        loop {
+            let next = if cfg!(debug_assertions) {
+                // Debug builds always go through `step`, which is easier to follow in a debugger.
+                self.step(&mut cur_fn, cur)?
+            } else {
+                let handler = unsafe { *self.handlers.get_unchecked(cur_fn).get_unchecked(cur) };
+                handler(self, &mut cur_fn, cur)?
+            };
+            match next {
+                Step::Next(next) => cur = next,
+                Step::Exit(rc) => return Ok(Some(rc)),
+                Step::Suspend => {
+                    self.suspended = Some((cur_fn, cur));
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn instr(&self, func: usize, cur: usize) -> &Instr<'a> {
+        debug_assert!(cur < self.instrs[func].len());
+        unsafe { self.instrs.get_unchecked(func).get_unchecked(cur) }
+    }
+
+    // Pick a handler for each instruction ahead of time. Common arithmetic, comparison and
+    // control-flow instructions get a dedicated handler; everything else goes through `step`.
+    fn threaded(instrs: &[Vec<Instr<'a>>]) -> Vec<Vec<Handler<'a, LR>>> {
+        instrs
+            .iter()
+            .map(|func| func.iter().map(Self::handler).collect())
+            .collect()
+    }
+
+    fn handler(inst: &Instr<'a>) -> Handler<'a, LR> {
         use Instr::*;
-        let mut scratch: Vec<runtime::FormatArg> = Vec::new();
+        match inst {
+            StoreConstInt(..) => Self::store_const_int,
+            StoreConstFloat(..) => Self::store_const_float,
+            AddInt(..) => Self::add_int,
+            AddFloat(..) => Self::add_float,
+            MulInt(..) => Self::mul_int,
+            MulFloat(..) => Self::mul_float,
+            MinusInt(..) => Self::minus_int,
+            MinusFloat(..) => Self::minus_float,
+            Div(..) => Self::div,
+            LTInt(..) => Self::lt_int,
+            LTFloat(..) => Self::lt_float,
+            GTInt(..) => Self::gt_int,
+            GTFloat(..) => Self::gt_float,
+            LTEInt(..) => Self::lte_int,
+            LTEFloat(..) => Self::lte_float,
+            GTEInt(..) => Self::gte_int,
+            GTEFloat(..) => Self::gte_float,
+            EQInt(..) => Self::eq_int,
+            EQFloat(..) => Self::eq_float,
+            Not(..) => Self::not,
+            JmpIf(..) => Self::jmp_if,
+            Jmp(..) => Self::jmp,
+            _ => Self::step,
+        }
+    }
+
+    handlers! {
+        add_int: AddInt(l, r) => l + r;
+        add_float: AddFloat(l, r) => l + r;
+        mul_int: MulInt(l, r) => l * r;
+        mul_float: MulFloat(l, r) => l * r;
+        minus_int: MinusInt(l, r) => l - r;
+        minus_float: MinusFloat(l, r) => l - r;
+        div: Div(l, r) => l / r;
+        lt_int: LTInt(l, r) => (l < r) as Int;
+        lt_float: LTFloat(l, r) => (l < r) as Int;
+        gt_int: GTInt(l, r) => (l > r) as Int;
+        gt_float: GTFloat(l, r) => (l > r) as Int;
+        lte_int: LTEInt(l, r) => (l <= r) as Int;
+        lte_float: LTEFloat(l, r) => (l <= r) as Int;
+        gte_int: GTEInt(l, r) => (l >= r) as Int;
+        gte_float: GTEFloat(l, r) => (l >= r) as Int;
+        eq_int: EQInt(l, r) => (l == r) as Int;
+        eq_float: EQFloat(l, r) => (l == r) as Int;
+        not: Not(i) => (i == 0) as Int;
+    }
+
+    fn store_const_int(&mut self, cur_fn: &mut usize, cur: usize) -> Result<Step> {
+        if let Instr::StoreConstInt(ir, i) = *self.instr(*cur_fn, cur) {
+            *self.get_mut(ir) = i;
+            Ok(Step::Next(cur + 1))
+        } else {
+            unreachable!()
+        }
+    }
+
+    fn store_const_float(&mut self, cur_fn: &mut usize, cur: usize) -> Result<Step> {
+        if let Instr::StoreConstFloat(fr, f) = *self.instr(*cur_fn, cur) {
+            *self.get_mut(fr) = f;
+            Ok(Step::Next(cur + 1))
+        } else {
+            unreachable!()
+        }
+    }
+
+    fn jmp_if(&mut self, cur_fn: &mut usize, cur: usize) -> Result<Step> {
+        if let Instr::JmpIf(cond, lbl) = *self.instr(*cur_fn, cur) {
+            Ok(Step::Next(if *self.get(cond) != 0 {
+                lbl.0
+            } else {
+                cur + 1
+            }))
+        } else {
+            unreachable!()
+        }
+    }
+
+    fn jmp(&mut self, cur_fn: &mut usize, cur: usize) -> Result<Step> {
+        if let Instr::Jmp(lbl) = *self.instr(*cur_fn, cur) {
+            Ok(Step::Next(lbl.0))
+        } else {
+            unreachable!()
+        }
+    }
+
+    // Execute the instruction at index `cur` of function `cur_fn`, updating `cur_fn` on calls and
+    // returns. This handles every instruction, and is the handler for those without a dedicated
+    // one.
+    fn step(&mut self, cur_fn: &mut usize, cur: usize) -> Result<Step> {
+        use Instr::*;
+        use Variable::*;
         // We are only accessing one vector at a time here, but it's hard to convince the borrow
-        // checker of this fact, so we access the vectors through raw pointers.
-        let mut instrs = (&mut self.instrs[cur_fn]) as *mut Vec<Instr<'a>>;
-        let mut cur = 0;
-
-        'outer: loop {
-            // This somewhat ersatz structure is to allow 'cur' to be reassigned
-            // in most but not all branches in the big match below.
-            cur = loop {
-                debug_assert!(cur < unsafe { (*instrs).len() });
-                use Variable::*;
-                match unsafe { (*instrs).get_unchecked(cur) } {
-                    StoreConstStr(sr, s) => {
-                        let sr = *sr;
-                        *self.get_mut(sr) = s.clone_str()
-                    }
-                    StoreConstInt(ir, i) => {
-                        let ir = *ir;
-                        *self.get_mut(ir) = *i
-                    }
-                    StoreConstFloat(fr, f) => {
-                        let fr = *fr;
-                        *self.get_mut(fr) = *f
-                    }
-                    IntToStr(sr, ir) => {
-                        let s = runtime::convert::<_, Str>(*self.get(*ir));
-                        let sr = *sr;
-                        *self.get_mut(sr) = s;
-                    }
-                    FloatToStr(sr, fr) => {
-                        let s = runtime::convert::<_, Str>(*self.get(*fr));
-                        let sr = *sr;
-                        *self.get_mut(sr) = s;
-                    }
-                    StrToInt(ir, sr) => {
-                        let i = runtime::convert::<_, Int>(self.get(*sr));
-                        let ir = *ir;
-                        *self.get_mut(ir) = i;
-                    }
-                    HexStrToInt(ir, sr) => {
-                        let i = self.get(*sr).with_bytes(runtime::hextoi);
-                        let ir = *ir;
-                        *self.get_mut(ir) = i;
-                    }
-                    StrToFloat(fr, sr) => {
-                        let f = runtime::convert::<_, Float>(self.get(*sr));
-                        let fr = *fr;
-                        *self.get_mut(fr) = f;
-                    }
-                    FloatToInt(ir, fr) => {
-                        let i = runtime::convert::<_, Int>(*self.get(*fr));
-                        let ir = *ir;
-                        *self.get_mut(ir) = i;
-                    }
-                    IntToFloat(fr, ir) => {
-                        let f = runtime::convert::<_, Float>(*self.get(*ir));
-                        let fr = *fr;
-                        *self.get_mut(fr) = f;
-                    }
-                    AddInt(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l + r;
-                    }
-                    AddFloat(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l + r;
-                    }
-                    MulInt(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l * r;
-                    }
-                    MulFloat(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l * r;
-                    }
-                    MinusInt(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l - r;
-                    }
-                    MinusFloat(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l - r;
-                    }
-                    ModInt(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l % r;
-                    }
-                    ModFloat(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l % r;
-                    }
-                    Div(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l / r;
-                    }
-                    Pow(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = l.powf(r);
-                    }
-                    Not(res, ir) => {
-                        let res = *res;
-                        let i = *self.get(*ir);
-                        *self.get_mut(res) = (i == 0) as Int;
-                    }
-                    NotStr(res, sr) => {
-                        let res = *res;
-                        let sr = *sr;
-                        let is_empty = self.get(sr).with_bytes(|bs| bs.is_empty());
-                        *self.get_mut(res) = is_empty as Int;
-                    }
-                    NegInt(res, ir) => {
-                        let res = *res;
-                        let i = *self.get(*ir);
-                        *self.get_mut(res) = -i;
-                    }
-                    NegFloat(res, fr) => {
-                        let res = *res;
-                        let f = *self.get(*fr);
-                        *self.get_mut(res) = -f;
-                    }
-                    Float1(ff, dst, src) => {
-                        let f = *index(&self.floats, src);
-                        let dst = *dst;
-                        *self.get_mut(dst) = ff.eval1(f);
-                    }
-                    Float2(ff, dst, x, y) => {
-                        let fx = *index(&self.floats, x);
-                        let fy = *index(&self.floats, y);
-                        let dst = *dst;
-                        *self.get_mut(dst) = ff.eval2(fx, fy);
-                    }
-                    Int1(bw, dst, src) => {
-                        let i = *index(&self.ints, src);
-                        let dst = *dst;
-                        *self.get_mut(dst) = bw.eval1(i);
-                    }
-                    Int2(bw, dst, x, y) => {
-                        let ix = *index(&self.ints, x);
-                        let iy = *index(&self.ints, y);
-                        let dst = *dst;
-                        *self.get_mut(dst) = bw.eval2(ix, iy);
-                    }
-                    Rand(dst) => {
-                        let res: f64 = self.core.rng.gen_range(0.0..=1.0);
-                        *index_mut(&mut self.floats, dst) = res;
-                    }
-                    Srand(res, seed) => {
-                        let old_seed = self.core.reseed(*index(&self.ints, seed) as u64);
-                        *index_mut(&mut self.ints, res) = old_seed as Int;
-                    }
-                    ReseedRng(res) => {
-                        *index_mut(&mut self.ints, res) = self.core.reseed_random() as Int;
-                    }
-                    StartsWithConst(res, s, bs) => {
-                        let s_bytes = unsafe { &*index(&self.strs, s).get_bytes() };
-                        *index_mut(&mut self.ints, res) =
-                            (bs.len() <= s_bytes.len() && s_bytes[..bs.len()] == **bs) as Int;
-                    }
-                    Concat(res, l, r) => {
-                        let res = *res;
-                        let l = self.get(*l).clone();
-                        let r = self.get(*r).clone();
-                        *self.get_mut(res) = Str::concat(l, r);
-                    }
-                    Match(res, l, r) => {
-                        *index_mut(&mut self.ints, res) = self
-                            .core
-                            .match_regex(index(&self.strs, l), index(&self.strs, r))?;
-                    }
-                    IsMatch(res, l, r) => {
-                        *index_mut(&mut self.ints, res) = self
-                            .core
-                            .is_match_regex(index(&self.strs, l), index(&self.strs, r))?
-                            as Int;
-                    }
-                    MatchConst(res, x, pat) => {
-                        *index_mut(&mut self.ints, res) =
-                            runtime::RegexCache::regex_const_match(&*pat, index(&self.strs, x))
-                                as Int;
-                    }
-                    IsMatchConst(res, x, pat) => {
-                        *index_mut(&mut self.ints, res) =
-                            self.core.match_const_regex(index(&self.strs, x), &*pat)?;
-                    }
-                    SubstrIndex(res, s, t) => {
-                        let res = *res;
-                        let s = index(&self.strs, s);
-                        let t = index(&self.strs, t);
-                        *self.get_mut(res) = runtime::string_search::index_substr(t, s);
-                    }
-                    LenStr(res, s) => {
-                        let res = *res;
-                        let s = *s;
-                        // TODO consider doing a with_str here or enforce elsewhere that strings
-                        // cannot exceed u32::max.
-                        let len = self.get(s).len();
-                        *self.get_mut(res) = len as Int;
-                    }
-                    Sub(res, pat, s, in_s) => {
-                        let (subbed, new) = {
-                            let pat = index(&self.strs, pat);
-                            let s = index(&self.strs, s);
-                            let in_s = index(&self.strs, in_s);
-                            self.core
-                                .regexes
-                                .with_regex(pat, |re| in_s.subst_first(re, s))?
-                        };
-                        *index_mut(&mut self.strs, in_s) = subbed;
-                        *index_mut(&mut self.ints, res) = new as Int;
-                    }
-                    GSub(res, pat, s, in_s) => {
-                        let (subbed, subs_made) = {
-                            let pat = index(&self.strs, pat);
-                            let s = index(&self.strs, s);
-                            let in_s = index(&self.strs, in_s);
-                            self.core
-                                .regexes
-                                .with_regex(pat, |re| in_s.subst_all(re, s))?
-                        };
-                        *index_mut(&mut self.strs, in_s) = subbed;
-                        *index_mut(&mut self.ints, res) = subs_made;
-                    }
-                    GenSubDynamic(res, pat, s, how, in_s) => {
-                        let subbed = {
-                            let pat = index(&self.strs, pat);
-                            let s = index(&self.strs, s);
-                            let how = index(&self.strs, how);
-                            let in_s = index(&self.strs, in_s);
-                            self.core
-                                .regexes
-                                .with_regex(pat, |re| in_s.gen_subst_dynamic(re, s, how))?
-                        };
-                        *index_mut(&mut self.strs, res) = subbed;
-                    }
-                    EscapeCSV(res, s) => {
-                        *index_mut(&mut self.strs, res) = {
-                            let s = index(&self.strs, s);
-                            runtime::escape_csv(s)
-                        };
-                    }
-                    EscapeTSV(res, s) => {
-                        *index_mut(&mut self.strs, res) = {
-                            let s = index(&self.strs, s);
-                            runtime::escape_tsv(s)
-                        };
-                    }
-                    Substr(res, base, l, r) => {
-                        let base = index(&self.strs, base);
-                        let len = base.len();
-                        let l = cmp::max(0, -1 + *index(&self.ints, l));
-                        *index_mut(&mut self.strs, res) = if l as usize >= len {
-                            Str::default()
-                        } else {
-                            let r = cmp::min(len as Int, l.saturating_add(*index(&self.ints, r)))
-                                as usize;
-                            base.slice(l as usize, r)
-                        };
-                    }
-                    LTFloat(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l < r) as Int;
-                    }
-                    LTInt(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l < r) as Int;
-                    }
-                    LTStr(res, l, r) => {
-                        let res = *res;
-                        let l = self.get(*l);
-                        let r = self.get(*r);
-                        *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l < r)) as Int;
-                    }
-                    GTFloat(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l > r) as Int;
-                    }
-                    GTInt(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l > r) as Int;
-                    }
-                    GTStr(res, l, r) => {
-                        let res = *res;
-                        let l = self.get(*l);
-                        let r = self.get(*r);
-                        *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l > r)) as Int;
-                    }
-                    LTEFloat(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l <= r) as Int;
-                    }
-                    LTEInt(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l <= r) as Int;
-                    }
-                    LTEStr(res, l, r) => {
-                        let res = *res;
-                        let l = self.get(*l);
-                        let r = self.get(*r);
-                        *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l <= r)) as Int;
-                    }
-                    GTEFloat(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l >= r) as Int;
-                    }
-                    GTEInt(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l >= r) as Int;
-                    }
-                    GTEStr(res, l, r) => {
-                        let res = *res;
-                        let l = self.get(*l);
-                        let r = self.get(*r);
-                        *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l >= r)) as Int;
-                    }
-                    EQFloat(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l == r) as Int;
-                    }
-                    EQInt(res, l, r) => {
-                        let res = *res;
-                        let l = *self.get(*l);
-                        let r = *self.get(*r);
-                        *self.get_mut(res) = (l == r) as Int;
-                    }
-                    EQStr(res, l, r) => {
-                        let res = *res;
-                        let l = self.get(*l);
-                        let r = self.get(*r);
-                        *self.get_mut(res) = (l == r) as Int;
-                    }
-                    SetColumn(dst, src) => {
-                        let col = *self.get(*dst);
-                        let v = index(&self.strs, src);
-                        self.line
-                            .set_col(col, v, &self.core.vars.ofs, &mut self.core.regexes)?;
-                    }
-                    GetColumn(dst, src) => {
-                        let col = *self.get(*src);
-                        let dst = *dst;
-                        let res = self.line.get_col(
-                            col,
-                            &self.core.vars.fs,
-                            &self.core.vars.ofs,
-                            &mut self.core.regexes,
-                        )?;
-                        *self.get_mut(dst) = res;
-                    }
-                    JoinCSV(dst, start, end) => {
-                        let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
-                        *index_mut(&mut self.strs, dst) = {
-                            let start = *index(&self.ints, start);
-                            let end = *index(&self.ints, end);
-                            self.line.join_cols(start, end, &",".into(), nf, |s| {
-                                runtime::escape_csv(&s)
-                            })?
-                        };
-                    }
-                    JoinTSV(dst, start, end) => {
-                        let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
-                        *index_mut(&mut self.strs, dst) = {
-                            let start = *index(&self.ints, start);
-                            let end = *index(&self.ints, end);
-                            self.line.join_cols(start, end, &"\t".into(), nf, |s| {
-                                runtime::escape_tsv(&s)
-                            })?
-                        };
-                    }
-                    JoinColumns(dst, start, end, sep) => {
-                        let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
-                        *index_mut(&mut self.strs, dst) = {
-                            let sep = index(&self.strs, sep);
-                            let start = *index(&self.ints, start);
-                            let end = *index(&self.ints, end);
-                            self.line.join_cols(start, end, sep, nf, |s| s)?
-                        };
-                    }
-                    ToUpperAscii(dst, src) => {
-                        let res = index(&self.strs, src).to_upper_ascii();
-                        *index_mut(&mut self.strs, dst) = res;
-                    }
-                    ToLowerAscii(dst, src) => {
-                        let res = index(&self.strs, src).to_lower_ascii();
-                        *index_mut(&mut self.strs, dst) = res;
-                    }
-                    SplitInt(flds, to_split, arr, pat) => {
-                        // Index manually here to defeat the borrow checker.
-                        let to_split = index(&self.strs, to_split);
-                        let arr = index(&self.maps_int_str, arr);
-                        let pat = index(&self.strs, pat);
-                        self.core.regexes.split_regex_intmap(pat, to_split, arr)?;
-                        let res = arr.len() as Int;
-                        let flds = *flds;
-                        *self.get_mut(flds) = res;
-                    }
-                    SplitStr(flds, to_split, arr, pat) => {
-                        // Very similar to above
-                        let to_split = index(&self.strs, to_split);
-                        let arr = index(&self.maps_str_str, arr);
-                        let pat = index(&self.strs, pat);
-                        self.core.regexes.split_regex_strmap(pat, to_split, arr)?;
-                        let res = arr.len() as Int;
-                        let flds = *flds;
-                        *self.get_mut(flds) = res;
-                    }
-                    Sprintf { dst, fmt, args } => {
-                        debug_assert_eq!(scratch.len(), 0);
-                        for a in args.iter() {
-                            scratch.push(self.format_arg(*a)?);
-                        }
-                        use runtime::str_impl::DynamicBuf;
-                        let fmt_str = index(&self.strs, fmt);
-                        let mut buf = DynamicBuf::new(0);
-                        fmt_str
-                            .with_bytes(|bs| runtime::printf::printf(&mut buf, bs, &scratch[..]))?;
-                        scratch.clear();
-                        let res = buf.into_str();
-                        let dst = *dst;
-                        *self.get_mut(dst) = res;
-                    }
-                    PrintAll { output, args } => {
-                        let mut scratch_strs =
-                            smallvec::SmallVec::<[&Str; 4]>::with_capacity(args.len());
-                        for a in args {
-                            scratch_strs.push(index(&self.strs, a));
-                        }
-                        let res = if let Some((out_path_reg, fspec)) = output {
-                            let out_path = index(&self.strs, out_path_reg);
-                            self.core
-                                .write_files
-                                .write_all(&scratch_strs[..], Some((out_path, *fspec)))
-                        } else {
-                            self.core.write_files.write_all(&scratch_strs[..], None)
-                        };
-                        if res.is_err() {
-                            return Ok(0);
-                        }
-                    }
-                    Printf { output, fmt, args } => {
-                        debug_assert_eq!(scratch.len(), 0);
-                        for a in args.iter() {
-                            scratch.push(self.format_arg(*a)?);
-                        }
-                        let fmt_str = index(&self.strs, fmt);
-                        let res = if let Some((out_path_reg, fspec)) = output {
-                            let out_path = index(&self.strs, out_path_reg);
-                            self.core.write_files.printf(
-                                Some((out_path, *fspec)),
-                                fmt_str,
-                                &scratch[..],
-                            )
-                        } else {
-                            // print to stdout.
-                            self.core.write_files.printf(None, fmt_str, &scratch[..])
-                        };
-                        if res.is_err() {
-                            return Ok(0);
-                        }
-                        scratch.clear();
-                    }
-                    Close(file) => {
-                        let file = index(&self.strs, file);
-                        // NB this may create an unused entry in write_files. It would not be
-                        // terribly difficult to optimize the close path to include an existence
-                        // check first.
-                        self.core.write_files.close(file)?;
-                        self.read_files.close(file);
-                    }
-                    RunCmd(dst, cmd) => {
-                        *index_mut(&mut self.ints, dst) =
-                            index(&self.strs, cmd).with_bytes(runtime::run_command);
-                    }
-                    Exit(code) => return Ok(*index(&self.ints, code) as i32),
-                    Lookup {
-                        map_ty,
-                        dst,
-                        map,
-                        key,
-                    } => self.lookup(*map_ty, *dst, *map, *key),
-                    Contains {
-                        map_ty,
-                        dst,
-                        map,
-                        key,
-                    } => self.contains(*map_ty, *dst, *map, *key),
-                    Delete { map_ty, map, key } => self.delete(*map_ty, *map, *key),
-                    Clear { map_ty, map } => self.clear(*map_ty, *map),
-                    Len { map_ty, map, dst } => self.len(*map_ty, *map, *dst),
-                    Store {
-                        map_ty,
-                        map,
-                        key,
-                        val,
-                    } => self.store_map(*map_ty, *map, *key, *val),
-                    IncInt {
-                        map_ty,
-                        map,
-                        key,
-                        by,
-                        dst,
-                    } => self.inc_map_int(*map_ty, *map, *key, *by, *dst),
-                    IncFloat {
-                        map_ty,
-                        map,
-                        key,
-                        by,
-                        dst,
-                    } => self.inc_map_float(*map_ty, *map, *key, *by, *dst),
-                    LoadVarStr(dst, var) => {
-                        let s = self.core.vars.load_str(*var)?;
-                        let dst = *dst;
-                        *self.get_mut(dst) = s;
-                    }
-                    StoreVarStr(var, src) => {
-                        let src = *src;
-                        let s = self.get(src).clone();
-                        self.core.vars.store_str(*var, s)?;
-                    }
-                    LoadVarInt(dst, var) => {
-                        // If someone explicitly sets NF to a different value, this means we will
-                        // ignore it. I think that is fine.
-                        if let NF = *var {
-                            self.core.vars.nf =
-                                self.line.nf(&self.core.vars.fs, &mut self.core.regexes)? as Int;
-                        }
-                        let i = self.core.vars.load_int(*var)?;
-                        let dst = *dst;
-                        *self.get_mut(dst) = i;
-                    }
-                    StoreVarInt(var, src) => {
-                        let src = *src;
-                        let s = *self.get(src);
-                        self.core.vars.store_int(*var, s)?;
-                    }
-                    LoadVarIntMap(dst, var) => {
-                        let arr = self.core.vars.load_intmap(*var)?;
-                        let dst = *dst;
-                        *self.get_mut(dst) = arr;
-                    }
-                    StoreVarIntMap(var, src) => {
-                        let src = *src;
-                        let s = self.get(src).clone();
-                        self.core.vars.store_intmap(*var, s)?;
-                    }
-                    LoadVarStrMap(dst, var) => {
-                        let arr = self.core.vars.load_strmap(*var)?;
-                        let dst = *dst;
-                        *self.get_mut(dst) = arr;
-                    }
-                    StoreVarStrMap(var, src) => {
-                        let src = *src;
-                        let s = self.get(src).clone();
-                        self.core.vars.store_strmap(*var, s)?;
-                    }
+        // checker of this fact, so we access the instruction through a raw pointer.
+        let instr = self.instr(*cur_fn, cur) as *const Instr<'a>;
+        match unsafe { &*instr } {
+            StoreConstStr(sr, s) => {
+                let sr = *sr;
+                *self.get_mut(sr) = s.clone_str()
+            }
+            StoreConstInt(ir, i) => {
+                let ir = *ir;
+                *self.get_mut(ir) = *i
+            }
+            StoreConstFloat(fr, f) => {
+                let fr = *fr;
+                *self.get_mut(fr) = *f
+            }
+            StoreConstRegex(rr, re) => {
+                let rr = *rr;
+                *self.get_mut(rr) = re.clone()
+            }
+            RegexToStr(sr, rr) => {
+                let s = self.get(*rr).to_str();
+                let sr = *sr;
+                *self.get_mut(sr) = s;
+            }
+            IntToStr(sr, ir) => {
+                let s = runtime::convert::<_, Str>(*self.get(*ir));
+                let sr = *sr;
+                *self.get_mut(sr) = s;
+            }
+            FloatToStr(sr, fr) => {
+                let s = runtime::convert::<_, Str>(*self.get(*fr));
+                let sr = *sr;
+                *self.get_mut(sr) = s;
+            }
+            StrToInt(ir, sr) => {
+                self.check_number(*sr)?;
+                let i = runtime::convert::<_, Int>(self.get(*sr));
+                let ir = *ir;
+                *self.get_mut(ir) = i;
+            }
+            HexStrToInt(ir, sr) => {
+                let i = self.get(*sr).with_bytes(runtime::hextoi);
+                let ir = *ir;
+                *self.get_mut(ir) = i;
+            }
+            StrToFloat(fr, sr) => {
+                self.check_number(*sr)?;
+                let decimal_comma = self.core.config.decimal_comma;
+                let f = self
+                    .get(*sr)
+                    .with_bytes(|bs| runtime::strtod_locale(bs, decimal_comma));
+                let fr = *fr;
+                *self.get_mut(fr) = f;
+            }
+            FloatToInt(ir, fr) => {
+                let i = runtime::convert::<_, Int>(*self.get(*fr));
+                let ir = *ir;
+                *self.get_mut(ir) = i;
+            }
+            IntToFloat(fr, ir) => {
+                let f = runtime::convert::<_, Float>(*self.get(*ir));
+                let fr = *fr;
+                *self.get_mut(fr) = f;
+            }
+            AddInt(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l + r;
+            }
+            AddFloat(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l + r;
+            }
+            MulInt(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l * r;
+            }
+            MulFloat(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l * r;
+            }
+            MinusInt(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l - r;
+            }
+            MinusFloat(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l - r;
+            }
+            ModInt(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l.wrapping_rem(r);
+            }
+            ModFloat(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l % r;
+            }
+            Div(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l / r;
+            }
+            Pow(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = l.powf(r);
+            }
+            Not(res, ir) => {
+                let res = *res;
+                let i = *self.get(*ir);
+                *self.get_mut(res) = (i == 0) as Int;
+            }
+            NotStr(res, sr) => {
+                let res = *res;
+                let sr = *sr;
+                let is_empty = self.get(sr).with_bytes(|bs| bs.is_empty());
+                *self.get_mut(res) = is_empty as Int;
+            }
+            NegInt(res, ir) => {
+                let res = *res;
+                let i = *self.get(*ir);
+                *self.get_mut(res) = -i;
+            }
+            NegFloat(res, fr) => {
+                let res = *res;
+                let f = *self.get(*fr);
+                *self.get_mut(res) = -f;
+            }
+            Float1(ff, dst, src) => {
+                let f = *index(&self.floats, src);
+                let dst = *dst;
+                *self.get_mut(dst) = ff.eval1(f);
+            }
+            Float2(ff, dst, x, y) => {
+                let fx = *index(&self.floats, x);
+                let fy = *index(&self.floats, y);
+                let dst = *dst;
+                *self.get_mut(dst) = ff.eval2(fx, fy);
+            }
+            Int1(bw, dst, src) => {
+                let i = *index(&self.ints, src);
+                let dst = *dst;
+                *self.get_mut(dst) = bw.eval1(i);
+            }
+            Int2(bw, dst, x, y) => {
+                let ix = *index(&self.ints, x);
+                let iy = *index(&self.ints, y);
+                let dst = *dst;
+                *self.get_mut(dst) = bw.eval2(ix, iy);
+            }
+            Rand(dst) => {
+                let res: f64 = self.core.rng.gen_range(0.0..=1.0);
+                *index_mut(&mut self.floats, dst) = res;
+            }
+            Srand(res, seed) => {
+                let old_seed = self.core.reseed(*index(&self.ints, seed) as u64);
+                *index_mut(&mut self.ints, res) = old_seed as Int;
+            }
+            ReseedRng(res) => {
+                *index_mut(&mut self.ints, res) = self.core.reseed_random() as Int;
+            }
+            Sleep(res, secs) => {
+                runtime::sleep(*index(&self.floats, secs));
+                *index_mut(&mut self.ints, res) = 0;
+            }
+            TimeOfDay(res) => *index_mut(&mut self.floats, res) = runtime::time_of_day(),
+            Monotonic(res) => *index_mut(&mut self.floats, res) = runtime::monotonic(),
+            StartsWithConst(res, s, bs) => {
+                let s_bytes = unsafe { &*index(&self.strs, s).get_bytes() };
+                *index_mut(&mut self.ints, res) =
+                    (bs.len() <= s_bytes.len() && s_bytes[..bs.len()] == **bs) as Int;
+            }
+            Concat(res, l, r) => {
+                let res = *res;
+                let l = self.get(*l).clone();
+                let r = self.get(*r).clone();
+                *self.get_mut(res) = Str::concat(l, r);
+            }
+            Match(res, l, r) => {
+                *index_mut(&mut self.ints, res) = self
+                    .core
+                    .match_regex(index(&self.strs, l), index(&self.strs, r))?;
+            }
+            IsMatch(res, l, r) => {
+                *index_mut(&mut self.ints, res) = self
+                    .core
+                    .is_match_regex(index(&self.strs, l), index(&self.strs, r))?
+                    as Int;
+            }
+            MatchConst(res, x, pat) => {
+                *index_mut(&mut self.ints, res) =
+                    runtime::RegexCache::regex_const_match(&*pat, index(&self.strs, x)) as Int;
+            }
+            IsMatchConst(res, x, pat) => {
+                *index_mut(&mut self.ints, res) =
+                    self.core.match_const_regex(index(&self.strs, x), &*pat)?;
+            }
+            IsMatchRegex(res, x, pat) => {
+                *index_mut(&mut self.ints, res) = runtime::RegexCache::regex_const_match(
+                    index(&self.regexes, pat).regex(),
+                    index(&self.strs, x),
+                ) as Int;
+            }
+            MatchRegex(res, x, pat) => {
+                *index_mut(&mut self.ints, res) = self
+                    .core
+                    .match_const_regex(index(&self.strs, x), index(&self.regexes, pat).regex())?;
+            }
+            SubstrIndex(res, s, t) => {
+                let res = *res;
+                let s = index(&self.strs, s);
+                let t = index(&self.strs, t);
+                *self.get_mut(res) = runtime::string_search::index_substr(t, s);
+            }
+            LenStr(res, s) => {
+                let res = *res;
+                let s = *s;
+                // TODO consider doing a with_str here or enforce elsewhere that strings
+                // cannot exceed u32::max.
+                let len = self.get(s).len();
+                *self.get_mut(res) = len as Int;
+            }
+            Sub(res, pat, s, in_s) => {
+                let (subbed, new) = {
+                    let pat = index(&self.strs, pat);
+                    let s = index(&self.strs, s);
+                    let in_s = index(&self.strs, in_s);
+                    self.core
+                        .regexes
+                        .with_regex_subst(pat, s, |re, s| in_s.subst_first(re, s))?
+                };
+                *index_mut(&mut self.strs, in_s) = subbed;
+                *index_mut(&mut self.ints, res) = new as Int;
+            }
+            GSub(res, pat, s, in_s) => {
+                let (subbed, subs_made) = {
+                    let pat = index(&self.strs, pat);
+                    let s = index(&self.strs, s);
+                    let in_s = index(&self.strs, in_s);
+                    self.core
+                        .regexes
+                        .with_regex_subst(pat, s, |re, s| in_s.subst_all(re, s))?
+                };
+                *index_mut(&mut self.strs, in_s) = subbed;
+                *index_mut(&mut self.ints, res) = subs_made;
+            }
+            SubLine(res, pat, s) => {
+                let pat = index(&self.strs, pat);
+                let s = index(&self.strs, s);
+                *index_mut(&mut self.ints, res) = self.line.subst_line(
+                    pat,
+                    s,
+                    /*global=*/ false,
+                    &self.core.vars.fs,
+                    &self.core.vars.ofs,
+                    &mut self.core.regexes,
+                )?;
+            }
+            GSubLine(res, pat, s) => {
+                let pat = index(&self.strs, pat);
+                let s = index(&self.strs, s);
+                *index_mut(&mut self.ints, res) = self.line.subst_line(
+                    pat,
+                    s,
+                    /*global=*/ true,
+                    &self.core.vars.fs,
+                    &self.core.vars.ofs,
+                    &mut self.core.regexes,
+                )?;
+            }
+            GenSubDynamic(res, pat, s, how, in_s) => {
+                let subbed = {
+                    let pat = index(&self.strs, pat);
+                    let s = index(&self.strs, s);
+                    let how = index(&self.strs, how);
+                    let in_s = index(&self.strs, in_s);
+                    let warnings = &self.core.config.warnings;
+                    self.core.regexes.with_regex_fallible(pat, |re| {
+                        in_s.gen_subst_dynamic(re, s, how, warnings)
+                    })?
+                };
+                *index_mut(&mut self.strs, res) = subbed;
+            }
+            EscapeCSV(res, s) => {
+                *index_mut(&mut self.strs, res) = {
+                    let s = index(&self.strs, s);
+                    runtime::escape_csv(s, self.core.config.csv_output)
+                };
+            }
+            EscapeTSV(res, s) => {
+                *index_mut(&mut self.strs, res) = {
+                    let s = index(&self.strs, s);
+                    runtime::escape_tsv(s)
+                };
+            }
+            EscapeJSON(res, s) => {
+                *index_mut(&mut self.strs, res) = {
+                    let s = index(&self.strs, s);
+                    runtime::escape_json(s)
+                };
+            }
+            EscapeMsgPack(res, s) => {
+                *index_mut(&mut self.strs, res) = {
+                    let s = index(&self.strs, s);
+                    runtime::msgpack::escape_msgpack(s).upcast()
+                };
+            }
+            Substr(res, base, l, r) => {
+                let base = index(&self.strs, base);
+                let (l, r) = (*index(&self.floats, l), *index(&self.floats, r));
+                *index_mut(&mut self.strs, res) = runtime::substr(base, l, r);
+            }
+            LTFloat(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l < r) as Int;
+            }
+            LTInt(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l < r) as Int;
+            }
+            LTStr(res, l, r) => {
+                let res = *res;
+                let l = self.get(*l);
+                let r = self.get(*r);
+                *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l < r)) as Int;
+            }
+            GTFloat(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l > r) as Int;
+            }
+            GTInt(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l > r) as Int;
+            }
+            GTStr(res, l, r) => {
+                let res = *res;
+                let l = self.get(*l);
+                let r = self.get(*r);
+                *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l > r)) as Int;
+            }
+            LTEFloat(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l <= r) as Int;
+            }
+            LTEInt(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l <= r) as Int;
+            }
+            LTEStr(res, l, r) => {
+                let res = *res;
+                let l = self.get(*l);
+                let r = self.get(*r);
+                *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l <= r)) as Int;
+            }
+            GTEFloat(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l >= r) as Int;
+            }
+            GTEInt(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l >= r) as Int;
+            }
+            GTEStr(res, l, r) => {
+                let res = *res;
+                let l = self.get(*l);
+                let r = self.get(*r);
+                *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l >= r)) as Int;
+            }
+            EQFloat(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l == r) as Int;
+            }
+            EQInt(res, l, r) => {
+                let res = *res;
+                let l = *self.get(*l);
+                let r = *self.get(*r);
+                *self.get_mut(res) = (l == r) as Int;
+            }
+            EQStr(res, l, r) => {
+                let res = *res;
+                let l = self.get(*l);
+                let r = self.get(*r);
+                *self.get_mut(res) = (l == r) as Int;
+            }
+            StrNumCmp(res, l, r) => {
+                let res = *res;
+                let l = self.get(*l);
+                let r = self.get(*r);
+                *self.get_mut(res) = runtime::strnum_cmp(l, r);
+            }
+            SetColumn(dst, src) => {
+                let col = *self.get(*dst);
+                let v = index(&self.strs, src);
+                self.line
+                    .set_col(col, v, &self.core.vars.ofs, &mut self.core.regexes)?;
+            }
+            Unpack(dst, fmt) => {
+                let fmt = index(&self.strs, fmt);
+                *index_mut(&mut self.ints, dst) = runtime::unpack::unpack_line(
+                    &mut self.line,
+                    fmt,
+                    &self.core.vars.fs,
+                    &self.core.vars.ofs,
+                    &mut self.core.regexes,
+                )?;
+            }
+            Resplit(dst, fs) => {
+                let fs = index(&self.strs, fs);
+                *index_mut(&mut self.ints, dst) = self.line.resplit(
+                    fs,
+                    &self.core.vars.fs,
+                    &self.core.vars.ofs,
+                    &mut self.core.regexes,
+                )? as Int;
+            }
+            GetColumn(dst, src) => {
+                let col = *self.get(*src);
+                let dst = *dst;
+                let res = self.line.get_col(
+                    col,
+                    &self.core.vars.fs,
+                    &self.core.vars.ofs,
+                    &mut self.core.regexes,
+                )?;
+                *self.get_mut(dst) = res;
+            }
+            JoinCSV(dst, start, end) => {
+                let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
+                *index_mut(&mut self.strs, dst) = {
+                    let start = *index(&self.ints, start);
+                    let end = *index(&self.ints, end);
+                    let dialect = self.core.config.csv_output;
+                    let sep = dialect.sep_str().upcast();
+                    self.line
+                        .join_cols(start, end, &sep, nf, |s| runtime::escape_csv(&s, dialect))?
+                };
+            }
+            JoinTSV(dst, start, end) => {
+                let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
+                *index_mut(&mut self.strs, dst) = {
+                    let start = *index(&self.ints, start);
+                    let end = *index(&self.ints, end);
+                    self.line
+                        .join_cols(start, end, &"\t".into(), nf, |s| runtime::escape_tsv(&s))?
+                };
+            }
+            JoinJSON(dst, start, end) => {
+                let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
+                *index_mut(&mut self.strs, dst) = {
+                    let start = *index(&self.ints, start);
+                    let end = *index(&self.ints, end);
+                    let mut pairs = runtime::json::ColumnPairs::new(&self.core.vars.fi, start);
+                    let fields = self
+                        .line
+                        .join_cols(start, end, &",".into(), nf, |s| pairs.pair(s))?;
+                    runtime::json::object(fields)
+                };
+            }
+            JsonKey(dst, col, pos) => {
+                *index_mut(&mut self.strs, dst) = {
+                    let col = *index(&self.ints, col);
+                    let pos = *index(&self.ints, pos);
+                    runtime::json::json_key(&self.core.vars.fi, col, pos).upcast()
+                };
+            }
+            JoinMsgPack(dst, start, end) => {
+                let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
+                *index_mut(&mut self.strs, dst) = {
+                    let start = *index(&self.ints, start);
+                    let end = *index(&self.ints, end);
+                    let mut pairs = runtime::msgpack::ColumnPairs::new(&self.core.vars.fi, start);
+                    let fields = self
+                        .line
+                        .join_cols(start, end, &"".into(), nf, |s| pairs.pair(s))?;
+                    pairs.map(fields)
+                };
+            }
+            MsgPackKey(dst, col, pos) => {
+                *index_mut(&mut self.strs, dst) = {
+                    let col = *index(&self.ints, col);
+                    let pos = *index(&self.ints, pos);
+                    runtime::msgpack::msgpack_key(&self.core.vars.fi, col, pos).upcast()
+                };
+            }
+            JoinColumns(dst, start, end, sep) => {
+                let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes)?;
+                *index_mut(&mut self.strs, dst) = {
+                    let sep = index(&self.strs, sep);
+                    let start = *index(&self.ints, start);
+                    let end = *index(&self.ints, end);
+                    self.line.join_cols(start, end, sep, nf, |s| s)?
+                };
+            }
+            ToUpperAscii(dst, src) => {
+                let res = index(&self.strs, src).to_upper_ascii();
+                *index_mut(&mut self.strs, dst) = res;
+            }
+            ToLowerAscii(dst, src) => {
+                let res = index(&self.strs, src).to_lower_ascii();
+                *index_mut(&mut self.strs, dst) = res;
+            }
+            GetXml(dst, doc, path) => {
+                let res = runtime::xml::get(index(&self.strs, doc), index(&self.strs, path))?;
+                *index_mut(&mut self.strs, dst) = res.upcast();
+            }
+            Strftime(dst, fmt, ts, tz) => {
+                let ts = *index(&self.floats, ts);
+                let res = index(&self.strs, fmt).with_bytes(|fmt| {
+                    index(&self.strs, tz).with_bytes(|tz| runtime::strftime(fmt, ts, tz))
+                })?;
+                *index_mut(&mut self.strs, dst) = res.upcast();
+            }
+            ParseDate(dst, s) => {
+                *index_mut(&mut self.floats, dst) =
+                    index(&self.strs, s).with_bytes(runtime::parsedate);
+            }
+            Chr(dst, n) => {
+                let n = *index(&self.ints, n);
+                let utf8 = self.core.config.utf8_chars;
+                *index_mut(&mut self.strs, dst) = runtime::chr(n, utf8).upcast();
+            }
+            Ord(dst, s) => {
+                let utf8 = self.core.config.utf8_chars;
+                *index_mut(&mut self.ints, dst) =
+                    index(&self.strs, s).with_bytes(|bs| runtime::ord(bs, utf8));
+            }
+            SplitInt(flds, to_split, arr, pat, limit) => {
+                // Index manually here to defeat the borrow checker.
+                let to_split = index(&self.strs, to_split);
+                let arr = index(&self.maps_int_str, arr);
+                let pat = index(&self.strs, pat);
+                let limit = *index(&self.ints, limit);
+                self.core
+                    .regexes
+                    .split_regex_intmap(pat, to_split, arr, limit)?;
+                let res = arr.len() as Int;
+                let flds = *flds;
+                *self.get_mut(flds) = res;
+            }
+            SplitStr(flds, to_split, arr, pat, limit) => {
+                // Very similar to above
+                let to_split = index(&self.strs, to_split);
+                let arr = index(&self.maps_str_str, arr);
+                let pat = index(&self.strs, pat);
+                let limit = *index(&self.ints, limit);
+                self.core
+                    .regexes
+                    .split_regex_strmap(pat, to_split, arr, limit)?;
+                let res = arr.len() as Int;
+                let flds = *flds;
+                *self.get_mut(flds) = res;
+            }
+            MatchArr(dst, s, pat, arr) => {
+                let s = index(&self.strs, s);
+                let pat = index(&self.strs, pat);
+                let arr = index(&self.maps_str_str, arr);
+                let res = self.core.match_regex_captures(s, pat, arr)?;
+                *index_mut(&mut self.ints, dst) = res;
+            }
+            MatchAll(dst, s, pat, arr, group) => {
+                let s = index(&self.strs, s);
+                let pat = index(&self.strs, pat);
+                let arr = index(&self.maps_int_str, arr);
+                let group = *index(&self.ints, group);
+                self.core.regexes.match_all_intmap(pat, s, arr, group)?;
+                let res = arr.len() as Int;
+                let dst = *dst;
+                *self.get_mut(dst) = res;
+            }
+            Sprintf { dst, fmt, args } => {
+                debug_assert_eq!(self.scratch.len(), 0);
+                for a in args.iter() {
+                    let arg = self.format_arg(*a)?;
+                    self.scratch.push(arg);
+                }
+                use runtime::str_impl::DynamicBuf;
+                let fmt_str = index(&self.strs, fmt);
+                let mut buf = DynamicBuf::new(0);
+                let config = &self.core.config;
+                fmt_str.with_bytes(|bs| {
+                    runtime::printf::printf(&mut buf, bs, &self.scratch[..], config)
+                })?;
+                self.scratch.clear();
+                let res = buf.into_str();
+                let dst = *dst;
+                *self.get_mut(dst) = res;
+            }
+            PrintAll { output, args } => {
+                let mut scratch_strs = smallvec::SmallVec::<[&Str; 4]>::with_capacity(args.len());
+                for a in args {
+                    scratch_strs.push(index(&self.strs, a));
+                }
+                let res = if let Some((out_path_reg, fspec)) = output {
+                    let out_path = index(&self.strs, out_path_reg);
+                    self.core
+                        .write_files
+                        .write_all(&scratch_strs[..], Some((out_path, *fspec)))
+                } else {
+                    self.core.write_files.write_all(&scratch_strs[..], None)
+                };
+                if res.is_err() {
+                    return Ok(Step::Exit(0));
+                }
+            }
+            Printf { output, fmt, args } => {
+                debug_assert_eq!(self.scratch.len(), 0);
+                for a in args.iter() {
+                    let arg = self.format_arg(*a)?;
+                    self.scratch.push(arg);
+                }
+                let fmt_str = index(&self.strs, fmt);
+                let res = if let Some((out_path_reg, fspec)) = output {
+                    let out_path = index(&self.strs, out_path_reg);
+                    self.core.write_files.printf(
+                        Some((out_path, *fspec)),
+                        fmt_str,
+                        &self.scratch[..],
+                        &self.core.config,
+                    )
+                } else {
+                    // print to stdout.
+                    self.core.write_files.printf(
+                        None,
+                        fmt_str,
+                        &self.scratch[..],
+                        &self.core.config,
+                    )
+                };
+                if res.is_err() {
+                    return Ok(Step::Exit(0));
+                }
+                self.scratch.clear();
+            }
+            Close(file) => {
+                let file = index(&self.strs, file);
+                // NB this may create an unused entry in write_files. It would not be
+                // terribly difficult to optimize the close path to include an existence
+                // check first.
+                self.core.write_files.close(file)?;
+                self.read_files.close(file);
+            }
+            CmdStatus(dst, cmd) => {
+                let cmd = index(&self.strs, cmd);
+                self.core.write_files.close_command(cmd)?;
+                self.read_files.close(cmd);
+                *index_mut(&mut self.ints, dst) = cmd.with_bytes(runtime::command_status);
+            }
+            UsePty(dst, cmd) => {
+                *index_mut(&mut self.ints, dst) =
+                    index(&self.strs, cmd).with_bytes(runtime::use_pty);
+            }
+            HllAdd(dst, sketch, value) => {
+                let res = index(&self.strs, sketch).with_bytes(|sketch| {
+                    index(&self.strs, value).with_bytes(|value| runtime::hll_add(sketch, value))
+                });
+                *index_mut(&mut self.ints, dst) = res;
+            }
+            HllCount(dst, sketch) => {
+                *index_mut(&mut self.ints, dst) =
+                    index(&self.strs, sketch).with_bytes(runtime::hll_count);
+            }
+            TopK(dst, heap, k, key, weight) => {
+                let res = self.core.top_k.add(
+                    index(&self.maps_str_float, heap),
+                    *index(&self.ints, k),
+                    index(&self.strs, key),
+                    *index(&self.floats, weight),
+                );
+                *index_mut(&mut self.floats, dst) = res;
+            }
+            RunCmd(dst, cmd) => {
+                *index_mut(&mut self.ints, dst) =
+                    index(&self.strs, cmd).with_bytes(runtime::run_command);
+            }
+            Exit(code) => return Ok(Step::Exit(*index(&self.ints, code) as i32)),
+            Assert(cond, msg, loc) => {
+                if *index(&self.ints, cond) == 0 {
+                    let msg = index(&self.strs, msg);
+                    let loc = index(&self.strs, loc);
+                    return user_err!("{}", runtime::assertion_failure(msg, loc, &self.core.vars));
+                }
+            }
+            CheckDivisor(fr, is_mod) => {
+                if *index(&self.floats, fr) == 0.0 {
+                    return user_err!("{}", arith::division_by_zero(*is_mod, &self.core.vars));
+                }
+            }
+            CheckDivisorInt(ir) => {
+                if *index(&self.ints, ir) == 0 {
+                    return user_err!("{}", arith::division_by_zero(true, &self.core.vars));
+                }
+            }
+            CheckIndex(fr) => {
+                let f = *index(&self.floats, fr);
+                if let Err(e) = strict::check_index(f, &self.core.vars) {
+                    return user_err!("{}", e);
+                }
+            }
+            DumpScalar(name, val) => {
+                let mem_profile = self.core.config.mem_profile;
+                runtime::dump_scalar(index(&self.strs, name), index(&self.strs, val), mem_profile)
+            }
+            DumpMap { name, map_ty, map } => self.dump_map(*name, *map_ty, *map),
+            DumpRequested(dst) => {
+                let requested = runtime::take_dump_request(self.core.config.mem_profile);
+                *index_mut(&mut self.ints, dst) = requested as Int;
+            }
+            CheckpointScalar(name, val) => {
+                let checkpoint = &self.core.config.checkpoint;
+                checkpoint.save_scalar(index(&self.strs, name), index(&self.strs, val))
+            }
+            CheckpointMap { name, map_ty, map } => self.checkpoint_map(*name, *map_ty, *map),
+            WriteCheckpoint() => {
+                if let Err(e) = self.core.config.checkpoint.write(self.core.vars.nr) {
+                    return err!("{}", e);
+                }
+            }
+            CheckpointDue(dst) => {
+                let due = self.core.config.checkpoint.take_checkpoint_request();
+                *index_mut(&mut self.ints, dst) = due as Int;
+            }
+            RestoreVar { name, ty, var } => self.restore_var(*name, *ty, *var),
+            Lookup {
+                map_ty,
+                dst,
+                map,
+                key,
+            } => self.lookup(*map_ty, *dst, *map, *key),
+            Contains {
+                map_ty,
+                dst,
+                map,
+                key,
+            } => self.contains(*map_ty, *dst, *map, *key),
+            Delete { map_ty, map, key } => self.delete(*map_ty, *map, *key),
+            Clear { map_ty, map } => self.clear(*map_ty, *map),
+            Len { map_ty, map, dst } => self.len(*map_ty, *map, *dst),
+            Store {
+                map_ty,
+                map,
+                key,
+                val,
+            } => self.store_map(*map_ty, *map, *key, *val),
+            IncInt {
+                map_ty,
+                map,
+                key,
+                by,
+                dst,
+            } => self.inc_map_int(*map_ty, *map, *key, *by, *dst),
+            IncFloat {
+                map_ty,
+                map,
+                key,
+                by,
+                dst,
+            } => self.inc_map_float(*map_ty, *map, *key, *by, *dst),
+            CopyMap {
+                map_ty,
+                dst,
+                to,
+                from,
+            } => self.copy_map(*map_ty, *dst, *to, *from),
+            Sample {
+                map_ty,
+                dst,
+                map,
+                k,
+                val,
+            } => self.sample(*map_ty, *dst, *map, *k, *val),
+            SortKeys {
+                map_ty,
+                dst,
+                src,
+                dest,
+                how,
+            } => {
+                let dest = index(&self.maps_int_str, dest);
+                let how = index(&self.strs, how);
+                let src = *src;
+                let res = map_regs!(
+                    *map_ty,
+                    src,
+                    runtime::sort_keys(self.get(src), dest, how, self.core.config.decimal_comma)
+                )?;
+                *index_mut(&mut self.ints, dst) = res;
+            }
+            LoadVarStr(dst, var) => {
+                let s = self.core.vars.load_str(*var)?;
+                let dst = *dst;
+                *self.get_mut(dst) = s;
+            }
+            StoreVarStr(var, src) => {
+                let src = *src;
+                let s = self.get(src).clone();
+                self.core.vars.store_str(*var, s)?;
+            }
+            LoadVarInt(dst, var) => {
+                // If someone explicitly sets NF to a different value, this means we will
+                // ignore it. I think that is fine.
+                if let NF = *var {
+                    self.core.vars.nf =
+                        self.line.nf(&self.core.vars.fs, &mut self.core.regexes)? as Int;
+                }
+                let i = self.core.vars.load_int(*var)?;
+                let dst = *dst;
+                *self.get_mut(dst) = i;
+            }
+            StoreVarInt(var, src) => {
+                let src = *src;
+                let s = *self.get(src);
+                self.core.vars.store_int(*var, s)?;
+            }
+            LoadVarIntMap(dst, var) => {
+                let arr = self.core.vars.load_intmap(*var)?;
+                let dst = *dst;
+                *self.get_mut(dst) = arr;
+            }
+            StoreVarIntMap(var, src) => {
+                let src = *src;
+                let s = self.get(src).clone();
+                self.core.vars.store_intmap(*var, s)?;
+            }
+            LoadVarStrMap(dst, var) => {
+                let arr = self.core.vars.load_strmap(*var)?;
+                let dst = *dst;
+                *self.get_mut(dst) = arr;
+            }
+            StoreVarStrMap(var, src) => {
+                let src = *src;
+                let s = self.get(src).clone();
+                self.core.vars.store_strmap(*var, s)?;
+            }
 
-                    IterBegin { map_ty, map, dst } => self.iter_begin(*map_ty, *map, *dst),
-                    IterHasNext { iter_ty, dst, iter } => self.iter_has_next(*iter_ty, *dst, *iter),
-                    IterGetNext { iter_ty, dst, iter } => self.iter_get_next(*iter_ty, *dst, *iter),
-
-                    LoadSlot { ty, dst, slot } => self.load_slot(*ty, *dst, *slot),
-                    StoreSlot { ty, src, slot } => self.store_slot(*ty, *src, *slot),
-                    Mov(ty, dst, src) => self.mov(*ty, *dst, *src),
-                    AllocMap(ty, reg) => self.alloc_map(*ty, *reg),
-
-                    // TODO add error logging for these errors perhaps?
-                    ReadErr(dst, file, is_file) => {
-                        let dst = *dst;
-                        let file = index(&self.strs, file);
-                        let res = if *is_file {
-                            self.read_files.read_err(file)?
-                        } else {
-                            self.read_files.read_err_cmd(file)?
-                        };
-                        *self.get_mut(dst) = res;
-                    }
-                    NextLine(dst, file, is_file) => {
-                        let dst = *dst;
-                        let file = index(&self.strs, file);
-                        match self.core.regexes.get_line(
-                            file,
-                            &self.core.vars.rs,
-                            &mut self.read_files,
-                            *is_file,
-                        ) {
-                            Ok(l) => *self.get_mut(dst) = l,
-                            Err(_) => *self.get_mut(dst) = "".into(),
-                        };
-                    }
-                    ReadErrStdin(dst) => {
-                        let dst = *dst;
-                        let res = self.read_files.read_err_stdin();
-                        *self.get_mut(dst) = res;
-                    }
-                    NextLineStdin(dst) => {
-                        let dst = *dst;
-                        let (changed, res) = self
-                            .core
-                            .regexes
-                            .get_line_stdin(&self.core.vars.rs, &mut self.read_files)?;
-                        if changed {
-                            self.reset_file_vars();
-                        }
-                        *self.get_mut(dst) = res;
-                    }
-                    NextLineStdinFused() => {
-                        let changed = self.core.regexes.get_line_stdin_reuse(
-                            &self.core.vars.rs,
-                            &mut self.read_files,
-                            &mut self.line,
-                        )?;
-                        if changed {
-                            self.reset_file_vars()
-                        }
-                    }
-                    NextFile() => {
-                        self.read_files.next_file()?;
-                        self.reset_file_vars();
-                    }
-                    UpdateUsedFields() => {
-                        let fi = &self.core.vars.fi;
-                        self.read_files.update_named_columns(fi);
-                    }
-                    SetFI(key, val) => {
-                        let key = *index(&self.ints, key);
-                        let val = *index(&self.ints, val);
-                        let col = self.line.get_col(
-                            key,
-                            &self.core.vars.fs,
-                            &self.core.vars.ofs,
-                            &mut self.core.regexes,
-                        )?;
-                        self.core.vars.fi.insert(col, val);
-                    }
-                    JmpIf(cond, lbl) => {
-                        let cond = *cond;
-                        if *self.get(cond) != 0 {
-                            break lbl.0 as usize;
-                        }
-                    }
-                    Jmp(lbl) => {
-                        break lbl.0 as usize;
-                    }
-                    Push(ty, reg) => self.push_reg(*ty, *reg),
-                    Pop(ty, reg) => self.pop_reg(*ty, *reg),
-                    Call(func) => {
-                        self.stack.push((cur_fn, Label(cur + 1)));
-                        cur_fn = *func;
-                        instrs = &mut self.instrs[*func];
-                        break 0;
-                    }
-                    Ret => {
-                        if let Some((func, Label(inst))) = self.stack.pop() {
-                            cur_fn = func;
-                            instrs = &mut self.instrs[func];
-                            break inst as usize;
-                        } else {
-                            break 'outer Ok(0);
-                        }
-                    }
+            IterBegin { map_ty, map, dst } => self.iter_begin(*map_ty, *map, *dst),
+            IterHasNext { iter_ty, dst, iter } => self.iter_has_next(*iter_ty, *dst, *iter),
+            IterGetNext { iter_ty, dst, iter } => self.iter_get_next(*iter_ty, *dst, *iter),
+
+            LoadSlot { ty, dst, slot } => self.load_slot(*ty, *dst, *slot),
+            StoreSlot { ty, src, slot } => self.store_slot(*ty, *src, *slot),
+            Mov(ty, dst, src) => self.mov(*ty, *dst, *src),
+            AllocMap(ty, reg) => self.alloc_map(*ty, *reg),
+
+            // TODO add error logging for these errors perhaps?
+            ReadErr(dst, file, is_file) => {
+                let dst = *dst;
+                let file = index(&self.strs, file);
+                let res = if *is_file {
+                    self.read_files.read_err(file)?
+                } else {
+                    self.read_files.read_err_cmd(file)?
                 };
-                break cur + 1;
-            };
-        }
+                *self.get_mut(dst) = res;
+            }
+            NextLine(dst, file, is_file) => {
+                let dst = *dst;
+                let file = index(&self.strs, file);
+                match self.core.regexes.get_line(
+                    file,
+                    &self.core.vars.rs,
+                    &mut self.read_files,
+                    *is_file,
+                ) {
+                    Ok(l) => *self.get_mut(dst) = l,
+                    Err(_) => *self.get_mut(dst) = "".into(),
+                };
+            }
+            ReadErrStdin(dst) => {
+                let dst = *dst;
+                let res = self.read_files.read_err_stdin();
+                *self.get_mut(dst) = res;
+            }
+            FileBoundary(dst) => {
+                let dst = *dst;
+                let res = self.read_files.stdin_file_boundary();
+                *self.get_mut(dst) = res;
+            }
+            NextLineStdin(dst) => {
+                if self.read_files.stdin_pending() {
+                    return Ok(Step::Suspend);
+                }
+                let dst = *dst;
+                self.core.flush_warnings();
+                let (changed, res) = self.core.regexes.get_line_stdin(
+                    &self.core.vars.rs,
+                    self.core.vars.argc,
+                    &self.core.vars.argv,
+                    &mut self.read_files,
+                )?;
+                if changed {
+                    self.reset_file_vars();
+                }
+                self.core.vars.rt = self.read_files.stdin_terminator().upcast();
+                *self.get_mut(dst) = res;
+            }
+            NextLineStdinFused() => {
+                if self.read_files.stdin_pending() {
+                    return Ok(Step::Suspend);
+                }
+                self.core.flush_warnings();
+                let changed = self.core.regexes.get_line_stdin_reuse(
+                    &self.core.vars.rs,
+                    self.core.vars.argc,
+                    &self.core.vars.argv,
+                    &mut self.read_files,
+                    &mut self.line,
+                )?;
+                if changed {
+                    self.reset_file_vars()
+                }
+                self.core.vars.rt = self.read_files.stdin_terminator().upcast();
+            }
+            SetLine(line) => strict::set_line(*line),
+            NextFile() => {
+                if self.read_files.next_file()? {
+                    self.reset_file_vars();
+                }
+            }
+            UpdateUsedFields() => {
+                let fi = &self.core.vars.fi;
+                self.read_files.update_named_columns(fi);
+            }
+            SetFI(key, val) => {
+                let key = *index(&self.ints, key);
+                let val = *index(&self.ints, val);
+                let col = self.line.get_col(
+                    key,
+                    &self.core.vars.fs,
+                    &self.core.vars.ofs,
+                    &mut self.core.regexes,
+                )?;
+                self.core.vars.fi.insert(col, val);
+            }
+            JmpIf(cond, lbl) => {
+                let cond = *cond;
+                if *self.get(cond) != 0 {
+                    return Ok(Step::Next(lbl.0 as usize));
+                }
+            }
+            Jmp(lbl) => {
+                return Ok(Step::Next(lbl.0 as usize));
+            }
+            PushFrame(regs) => self.push_frame(regs),
+            PopFrame(regs) => self.pop_frame(regs),
+            Call(func) => {
+                self.stack.push((*cur_fn, Label(cur + 1)));
+                *cur_fn = *func;
+                return Ok(Step::Next(0));
+            }
+            Ret => {
+                if let Some((func, Label(inst))) = self.stack.pop() {
+                    *cur_fn = func;
+                    return Ok(Step::Next(inst as usize));
+                } else {
+                    return Ok(Step::Exit(0));
+                }
+            }
+        };
+        Ok(Step::Next(cur + 1))
     }
     fn mov(&mut self, ty: Ty, dst: NumTy, src: NumTy) {
         match ty {
@@ -1356,6 +1926,10 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                 let src = index(&self.strs, &src.into()).clone();
                 *index_mut(&mut self.strs, &dst.into()) = src;
             }
+            Ty::Regex => {
+                let src = index(&self.regexes, &src.into()).clone();
+                *index_mut(&mut self.regexes, &dst.into()) = src;
+            }
             Ty::MapIntInt => {
                 let src = index(&self.maps_int_int, &src.into()).clone();
                 *index_mut(&mut self.maps_int_int, &dst.into()) = src;
@@ -1385,6 +1959,17 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             }
         }
     }
+    // With --strict, fail if the string in `sr` is about to be converted to a number that does not
+    // represent it. Otherwise, report a `numeric` warning.
+    fn check_number(&self, sr: Reg<Str<'a>>) -> Result<()> {
+        if !strict::checks_numbers(&self.core.config) {
+            return Ok(());
+        }
+        match strict::check_number(index(&self.strs, &sr), &self.core.config, &self.core.vars) {
+            Ok(()) => Ok(()),
+            Err(e) => user_err!("{}", e),
+        }
+    }
     fn alloc_map(&mut self, ty: Ty, reg: NumTy) {
         map_regs!(ty, reg, *self.get_mut(reg) = Default::default())
     }
@@ -1411,6 +1996,53 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
     fn clear(&mut self, map_ty: Ty, map: NumTy) {
         map_regs!(map_ty, map, self.get(map).clear());
     }
+    fn dump_map(&mut self, name: Reg<Str<'a>>, map_ty: Ty, map: NumTy) {
+        let name = index(&self.strs, &name);
+        let mem_profile = self.core.config.mem_profile;
+        map_regs!(
+            map_ty,
+            map,
+            runtime::dump_map(name, self.get(map), mem_profile)
+        );
+    }
+
+    fn checkpoint_map(&mut self, name: Reg<Str<'a>>, map_ty: Ty, map: NumTy) {
+        let name = index(&self.strs, &name);
+        let checkpoint = &self.core.config.checkpoint;
+        map_regs!(map_ty, map, checkpoint.save_map(name, self.get(map)));
+    }
+
+    fn restore_var(&mut self, name: Reg<Str<'a>>, ty: Ty, var: NumTy) {
+        let name = index(&self.strs, &name).clone();
+        let checkpoint = self.core.config.checkpoint.clone();
+        match ty {
+            Ty::Int => {
+                let var: Reg<Int> = var.into();
+                let cur = *self.get(var);
+                *self.get_mut(var) = checkpoint.restore_scalar(&name, cur);
+            }
+            Ty::Float => {
+                let var: Reg<Float> = var.into();
+                let cur = *self.get(var);
+                *self.get_mut(var) = checkpoint.restore_scalar(&name, cur);
+            }
+            Ty::Str => {
+                let var: Reg<Str<'a>> = var.into();
+                let cur = self.get(var).clone();
+                *self.get_mut(var) = checkpoint.restore_scalar(&name, cur);
+            }
+            Ty::Regex => {
+                // Regexes are saved as the text of their pattern.
+                let var: Reg<TypedRegex> = var.into();
+                let text = checkpoint.restore_scalar(&name, self.get(var).to_str());
+                if let Ok(re) = TypedRegex::new(&text.to_string()) {
+                    *self.get_mut(var) = re;
+                }
+            }
+            Ty::Null | Ty::IterInt | Ty::IterStr => {}
+            _ => map_regs!(ty, var, checkpoint.restore_map(&name, self.get(var))),
+        }
+    }
 
     // Allowing this because it allows for easier use of the map_regs macro.
     #[allow(clippy::clone_on_copy)]
@@ -1439,6 +2071,51 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             *self.get_mut(dst) = res;
         })
     }
+    fn copy_map(&mut self, map_ty: Ty, dst: Reg<Int>, to: NumTy, from: NumTy) {
+        macro_rules! copy_map {
+            ($maps:ident) => {{
+                let to = index(&self.$maps, &to.into());
+                to.copy_from(index(&self.$maps, &from.into()));
+                to.len() as Int
+            }};
+        }
+        let res = match map_ty {
+            Ty::MapIntInt => copy_map!(maps_int_int),
+            Ty::MapIntFloat => copy_map!(maps_int_float),
+            Ty::MapIntStr => copy_map!(maps_int_str),
+            Ty::MapStrInt => copy_map!(maps_str_int),
+            Ty::MapStrFloat => copy_map!(maps_str_float),
+            Ty::MapStrStr => copy_map!(maps_str_str),
+            _ => panic!("copying a non-map type: {:?}", map_ty),
+        };
+        *index_mut(&mut self.ints, &dst) = res;
+    }
+    fn sample(&mut self, map_ty: Ty, dst: Reg<Int>, map: NumTy, k: Reg<Int>, val: NumTy) {
+        let k = *index(&self.ints, &k);
+        let core = &mut self.core;
+        let res = match map_ty {
+            Ty::MapIntInt => core.samples.sample(
+                &mut core.rng,
+                index(&self.maps_int_int, &map.into()),
+                k,
+                index(&self.ints, &val.into()),
+            ),
+            Ty::MapIntFloat => core.samples.sample(
+                &mut core.rng,
+                index(&self.maps_int_float, &map.into()),
+                k,
+                index(&self.floats, &val.into()),
+            ),
+            Ty::MapIntStr => core.samples.sample(
+                &mut core.rng,
+                index(&self.maps_int_str, &map.into()),
+                k,
+                index(&self.strs, &val.into()),
+            ),
+            _ => panic!("sampling into a map without integer keys: {:?}", map_ty),
+        };
+        *index_mut(&mut self.ints, &dst) = res;
+    }
     fn len(&mut self, map_ty: Ty, map: NumTy, dst: NumTy) {
         let len = map_regs!(map_ty, map, self.get(map).len() as Int);
         *index_mut(&mut self.ints, &dst.into()) = len;
@@ -1488,6 +2165,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             Ty::Int => do_load!(load_int, ints),
             Ty::Float => do_load!(load_float, floats),
             Ty::Str => do_load!(load_str, strs),
+            Ty::Regex => do_load!(load_regex, regexes),
             Ty::MapIntInt => do_load!(load_intint, maps_int_int),
             Ty::MapIntFloat => do_load!(load_intfloat, maps_int_float),
             Ty::MapIntStr => do_load!(load_intstr, maps_int_str),
@@ -1511,6 +2189,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             Ty::Int => do_store!(store_int, ints),
             Ty::Float => do_store!(store_float, floats),
             Ty::Str => do_store!(store_str, strs),
+            Ty::Regex => do_store!(store_regex, regexes),
             Ty::MapIntInt => do_store!(store_intint, maps_int_int),
             Ty::MapIntFloat => do_store!(store_intfloat, maps_int_float),
             Ty::MapIntStr => do_store!(store_intstr, maps_int_str),
@@ -1520,54 +2199,37 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             Ty::Null | Ty::IterInt | Ty::IterStr => panic!("unsupported slot type: {:?}", ty),
         }
     }
-    fn push_reg(&mut self, ty: Ty, src: NumTy) {
-        match ty {
-            Ty::Int => push(&mut self.ints, &src.into()),
-            Ty::Float => push(&mut self.floats, &src.into()),
-            Ty::Str => push(&mut self.strs, &src.into()),
-            Ty::MapIntInt => push(&mut self.maps_int_int, &src.into()),
-            Ty::MapIntFloat => push(&mut self.maps_int_float, &src.into()),
-            Ty::MapIntStr => push(&mut self.maps_int_str, &src.into()),
-            Ty::MapStrInt => push(&mut self.maps_str_int, &src.into()),
-            Ty::MapStrFloat => push(&mut self.maps_str_float, &src.into()),
-            Ty::MapStrStr => push(&mut self.maps_str_str, &src.into()),
-            Ty::Null | Ty::IterInt | Ty::IterStr => {
-                panic!("unsupported register type for push operation: {:?}", ty)
-            }
-        }
-    }
-    fn pop_reg(&mut self, ty: Ty, dst: NumTy) {
-        match ty {
-            Ty::Int => *index_mut(&mut self.ints, &dst.into()) = pop(&mut self.ints),
-            Ty::Float => *index_mut(&mut self.floats, &dst.into()) = pop(&mut self.floats),
-            Ty::Str => *index_mut(&mut self.strs, &dst.into()) = pop(&mut self.strs),
-            Ty::MapIntInt => {
-                *index_mut(&mut self.maps_int_int, &dst.into()) = pop(&mut self.maps_int_int)
-            }
-            Ty::MapIntFloat => {
-                *index_mut(&mut self.maps_int_float, &dst.into()) = pop(&mut self.maps_int_float)
-            }
-            Ty::MapIntStr => {
-                *index_mut(&mut self.maps_int_str, &dst.into()) = pop(&mut self.maps_int_str)
-            }
-            Ty::MapStrInt => {
-                *index_mut(&mut self.maps_str_int, &dst.into()) = pop(&mut self.maps_str_int)
-            }
-            Ty::MapStrFloat => {
-                *index_mut(&mut self.maps_str_float, &dst.into()) = pop(&mut self.maps_str_float)
-            }
-            Ty::MapStrStr => {
-                *index_mut(&mut self.maps_str_float, &dst.into()) = pop(&mut self.maps_str_float)
-            }
-            Ty::Null | Ty::IterInt | Ty::IterStr => {
-                panic!("unsupported register type for pop operation: {:?}", ty)
-            }
-        }
+    fn push_frame(&mut self, frame: &FrameRegs) {
+        let regs = |ty: Ty| &frame.0[ty as usize][..];
+        push_all(&mut self.ints, regs(Ty::Int));
+        push_all(&mut self.floats, regs(Ty::Float));
+        push_all(&mut self.strs, regs(Ty::Str));
+        push_all(&mut self.regexes, regs(Ty::Regex));
+        push_all(&mut self.maps_int_int, regs(Ty::MapIntInt));
+        push_all(&mut self.maps_int_float, regs(Ty::MapIntFloat));
+        push_all(&mut self.maps_int_str, regs(Ty::MapIntStr));
+        push_all(&mut self.maps_str_int, regs(Ty::MapStrInt));
+        push_all(&mut self.maps_str_float, regs(Ty::MapStrFloat));
+        push_all(&mut self.maps_str_str, regs(Ty::MapStrStr));
+    }
+    fn pop_frame(&mut self, frame: &FrameRegs) {
+        let regs = |ty: Ty| &frame.0[ty as usize][..];
+        pop_all(&mut self.ints, regs(Ty::Int));
+        pop_all(&mut self.floats, regs(Ty::Float));
+        pop_all(&mut self.strs, regs(Ty::Str));
+        pop_all(&mut self.regexes, regs(Ty::Regex));
+        pop_all(&mut self.maps_int_int, regs(Ty::MapIntInt));
+        pop_all(&mut self.maps_int_float, regs(Ty::MapIntFloat));
+        pop_all(&mut self.maps_int_str, regs(Ty::MapIntStr));
+        pop_all(&mut self.maps_str_int, regs(Ty::MapStrInt));
+        pop_all(&mut self.maps_str_float, regs(Ty::MapStrFloat));
+        pop_all(&mut self.maps_str_str, regs(Ty::MapStrStr));
     }
 }
 
-// TODO: Add a pass that does checking of indexes once.
-// That could justify no checking during interpretation.
+// Release builds skip bounds checks when accessing registers. This relies on `bytecode::verify`,
+// which checks every register index against the sizes of the corresponding `Storage` before we
+// start interpreting.
 #[cfg(debug_assertions)]
 const CHECKED: bool = true;
 #[cfg(not(debug_assertions))]
@@ -1596,13 +2258,14 @@ pub(crate) fn index_mut<'a, T>(
     }
 }
 
-pub(crate) fn push<T: Clone>(s: &mut Storage<T>, reg: &Reg<T>) {
-    let v = index(s, reg).clone();
-    s.stack.push(v);
+pub(crate) fn push_all<T: Clone>(Storage { regs, stack }: &mut Storage<T>, frame: &[NumTy]) {
+    stack.extend(frame.iter().map(|reg| regs[*reg as usize].clone()));
 }
 
-pub(crate) fn pop<T: Clone>(s: &mut Storage<T>) -> T {
-    s.stack.pop().expect("pop must be called on nonempty stack")
+pub(crate) fn pop_all<T>(Storage { regs, stack }: &mut Storage<T>, frame: &[NumTy]) {
+    for reg in frame.iter().rev() {
+        regs[*reg as usize] = stack.pop().expect("pop must be called on nonempty stack");
+    }
 }
 
 // Used in benchmarking code.
@@ -1639,3 +2302,75 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         self.iters_str.reset();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unique(s: &str) -> UniqueStr<'static> {
+        Str::from(s).unmoor().into()
+    }
+
+    #[test]
+    fn combine_slots() {
+        let mut main = Slots::default();
+        set_slot(&mut main.int, 1, 2);
+        set_slot(&mut main.strs, 0, unique("kept"));
+        set_slot(&mut main.strs, 1, unique("replaced"));
+        let mut counts = HashMap::new();
+        counts.insert(unique("a"), 1);
+        set_slot(&mut main.strint, 0, counts);
+
+        let mut worker = Slots::default();
+        set_slot(&mut worker.int, 1, 3);
+        set_slot(&mut worker.int, 2, 5);
+        set_slot(&mut worker.strs, 1, unique("new"));
+        let mut counts = HashMap::new();
+        counts.insert(unique("a"), 4);
+        counts.insert(unique("b"), 1);
+        set_slot(&mut worker.strint, 0, counts);
+
+        // Numbers are summed, and strings are replaced unless the new one is empty.
+        main.combine(worker);
+        assert_eq!(main.int, vec![0, 5, 5]);
+        let strs: Vec<String> = main
+            .strs
+            .iter()
+            .map(|s| s.clone().into_str().to_string())
+            .collect();
+        assert_eq!(strs, vec!["kept", "new"]);
+        assert_eq!(main.strint[0].get(&unique("a")), Some(&5));
+        assert_eq!(main.strint[0].get(&unique("b")), Some(&1));
+    }
+
+    #[test]
+    fn typed_regex_match_skips_cache() {
+        use crate::runtime::writers::testing::FakeFs;
+        use crate::{arena::Arena, cfg, common::ExecutionStrategy, harness};
+        let a = Arena::default();
+        let prog = harness::parse_program(
+            r#"BEGIN { re = @/a+/; print ("aaa" ~ re), match("baa", re); }"#,
+            &a,
+            cfg::Escaper::Identity,
+            ExecutionStrategy::Serial,
+        )
+        .unwrap();
+        let mut ctx = cfg::ProgramContext::from_prog(&a, prog, cfg::Escaper::Identity).unwrap();
+        let fake_fs = FakeFs::default();
+        let mut interp = compile::bytecode(
+            &mut ctx,
+            harness::simulate_stdin_regex(""),
+            fake_fs.clone(),
+            /*num_workers=*/ 1,
+        )
+        .unwrap();
+        assert_eq!(interp.run().unwrap(), 0);
+        assert_eq!(interp.core.regexes.len(), 0);
+        // Output is flushed when the interpreter is dropped.
+        drop(interp);
+        assert_eq!(
+            String::from_utf8(fake_fs.stdout.read_data()).unwrap(),
+            "1 2\n"
+        );
+    }
+}
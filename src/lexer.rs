@@ -23,6 +23,8 @@ pub enum Tok<'a> {
     Begin,
     Prepare,
     End,
+    BeginFile,
+    EndFile,
     Break,
     Continue,
     Next,
@@ -95,9 +97,18 @@ pub enum Tok<'a> {
 
     Ident(&'a str),
     StrLit(&'a str),
+    // r"..." literals; not subject to escape processing.
+    RawStrLit(&'a str),
     PatLit(&'a str),
+    // `@/re/`, a regex constant that is a value in its own right.
+    RegexLit(&'a str),
     CallStart(&'a str),
+    // `@f(`, an indirect call through the variable `f`.
+    IndirectCallStart(&'a str),
     FunDec(&'a str),
+    ConstDec(&'a str),
+    // The start of a `# @type NAME: TYPE` comment. The rest of the line is lexed as usual.
+    TypePragma,
 
     ILit(&'a str),
     HexLit(&'a str),
@@ -142,6 +153,8 @@ keyword_map!(
     [b"PREPARE", Tok::Prepare],
     [b"BEGIN", Tok::Begin, WS_BRACE.clone()],
     [b"END", Tok::End, WS_BRACE.clone()],
+    [b"BEGINFILE", Tok::BeginFile, WS_BRACE.clone()],
+    [b"ENDFILE", Tok::EndFile, WS_BRACE.clone()],
     [b"break", Tok::Break, WS_SEMI.clone()],
     [b"continue", Tok::Continue, WS_SEMI.clone()],
     [b"next", Tok::Next],
@@ -224,6 +237,8 @@ pub struct Tokenizer<'a> {
     cur: usize,
     prev_tok: Option<Tok<'a>>,
     lines: Vec<usize>,
+    // Do not recognize syntax added by gawk or frawk.
+    traditional: bool,
 }
 
 pub fn is_ident(s: &str) -> bool {
@@ -253,6 +268,24 @@ fn push_char(buf: &mut Vec<u8>, c: char) {
 }
 
 pub(crate) fn parse_string_literal<'a>(lit: &str, arena: &'a Arena, buf: &mut Vec<u8>) -> &'a [u8] {
+    parse_string_literal_inner(lit, arena, buf, /*unicode_escapes=*/ true)
+}
+
+// one-true-awk does not support \u{...} escapes, so we leave them as-is in traditional mode.
+pub(crate) fn parse_traditional_string_literal<'a>(
+    lit: &str,
+    arena: &'a Arena,
+    buf: &mut Vec<u8>,
+) -> &'a [u8] {
+    parse_string_literal_inner(lit, arena, buf, /*unicode_escapes=*/ false)
+}
+
+fn parse_string_literal_inner<'a>(
+    lit: &str,
+    arena: &'a Arena,
+    buf: &mut Vec<u8>,
+    unicode_escapes: bool,
+) -> &'a [u8] {
     fn hex_digit(c: char) -> Option<u8> {
         match c {
             '0'..='9' => Some((c as u8) - b'0'),
@@ -316,12 +349,45 @@ pub(crate) fn parse_string_literal<'a>(lit: &str, arena: &'a Arena, buf: &mut Ve
                         }
                         buf.push(n)
                     }
+                    // \u{1 to 6 hex digits}, pushed as UTF-8. Unlike \x and octal escapes, which
+                    // push a single (possibly non-UTF-8) byte, these must be valid Unicode scalar
+                    // values.
+                    'u' if unicode_escapes => {
+                        let mut lookahead = iter.clone();
+                        let mut parsed = None;
+                        if let Some('{') = lookahead.next() {
+                            let mut n = 0u32;
+                            let mut digits = 0;
+                            for x in lookahead.by_ref() {
+                                match hex_digit(x) {
+                                    Some(d) if digits < 6 => {
+                                        n = n * 16 + d as u32;
+                                        digits += 1;
+                                    }
+                                    _ => {
+                                        if x == '}' && digits > 0 {
+                                            parsed = char::from_u32(n);
+                                        }
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        if let Some(x) = parsed {
+                            push_char(buf, x);
+                            iter = lookahead;
+                        } else {
+                            // Not a valid escape; push \u and continue as normal.
+                            buf.push(b'\\');
+                            buf.push(b'u');
+                        }
+                    }
                     // 1 to 3 octal digits
                     '0'..='7' => {
                         let mut n = octal_digit(c).unwrap();
                         for _ in 0..2 {
                             if let Some(x) = iter.next() {
-                                if let Some(d) = hex_digit(x) {
+                                if let Some(d) = octal_digit(x) {
                                     // saturate on overflow
                                     n = n.saturating_mul(8);
                                     n = n.saturating_add(d);
@@ -400,6 +466,9 @@ impl<'a> Tokenizer<'a> {
             }
             let text = &self.text.as_bytes()[start..start + len];
             if let Some(tok) = ks.get(text) {
+                if self.traditional && matches!(tok, Tok::BeginFile | Tok::EndFile) {
+                    continue;
+                }
                 if start + len == self.text.len()
                     || KEYWORDS
                         .get(text)
@@ -452,6 +521,25 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
+    // `const NAME =`, but not `const NAME ==`. Constants are a frawk extension.
+    fn constdec(&self) -> Option<(Tok<'a>, usize)> {
+        lazy_static! {
+            static ref CONST_PATTERN: Regex =
+                Regex::new(r"^(const\s+([a-zA-Z_][a-zA-Z_0-9]*))\s*=([^=~]|$)").unwrap();
+        }
+        if self.traditional {
+            return None;
+        }
+        let captures = CONST_PATTERN.captures(&self.text[self.cur..])?;
+        let full = captures.get(1)?.as_str();
+        let name = captures.get(2)?.as_str();
+        if KEYWORDS.get(name.as_bytes()).is_none() {
+            Some((Tok::ConstDec(name), full.len()))
+        } else {
+            None
+        }
+    }
+
     fn ident(&mut self, id_start: usize) -> (&'a str, usize) {
         debug_assert!(is_id_start(self.text[id_start..].chars().next().unwrap()));
         let ix = self.text[self.cur..]
@@ -463,6 +551,23 @@ impl<'a> Tokenizer<'a> {
         (&self.text[id_start..ix], ix)
     }
 
+    // If the text at `at` is `@f(`, an indirect call, return `f` and the index just past the `(`.
+    fn indirect_call(&self, at: usize) -> Option<(&'a str, usize)> {
+        let rest = &self.text[at + 1..];
+        if !rest.chars().next().map_or(false, is_id_start) {
+            return None;
+        }
+        let len = rest
+            .char_indices()
+            .find(|(_, c)| !is_id_body(*c))
+            .map_or(rest.len(), |(ix, _)| ix);
+        if rest[len..].starts_with('(') {
+            Some((&rest[..len], at + len + 2))
+        } else {
+            None
+        }
+    }
+
     fn literal(&mut self, delim: char, error_msg: &'static str) -> Result<(&'a str, usize), Error> {
         // assumes we just saw a delimiter.
         let mut bound = None;
@@ -497,7 +602,39 @@ impl<'a> Tokenizer<'a> {
         self.literal('"', "incomplete string literal")
     }
 
+    fn raw_string_lit(&mut self) -> Result<(&'a str, usize /* new start */), Error> {
+        // assumes we just saw r"
+        match self.text[self.cur..].find('"') {
+            Some(end) => Ok((&self.text[self.cur..self.cur + end], self.cur + end + 1)),
+            None => Err(Error {
+                location: self.index_to_loc(self.cur),
+                desc: "incomplete raw string literal",
+            }),
+        }
+    }
+
+    // `# @type`, followed by whitespace, at the start of a line. Type annotations are a frawk
+    // extension; anywhere else this is an ordinary comment.
+    fn type_pragma(&self) -> Option<usize> {
+        lazy_static! {
+            static ref PRAGMA_PATTERN: Regex = Regex::new(r"^#[ \t]*@type[ \t]").unwrap();
+        }
+        if self.traditional {
+            return None;
+        }
+        let len = PRAGMA_PATTERN.find(&self.text[self.cur..])?.end();
+        let line_start = self.text[..self.cur].rfind('\n').map_or(0, |ix| ix + 1);
+        if self.text[line_start..self.cur].trim().is_empty() {
+            Some(len)
+        } else {
+            None
+        }
+    }
+
     fn consume_comment(&mut self) {
+        if self.type_pragma().is_some() {
+            return;
+        }
         let mut iter = self.text[self.cur..].char_indices();
         if let Some((_, '#')) = iter.next() {
             if let Some((ix, _)) = iter.find(|x| x.1 == '\n') {
@@ -550,10 +687,15 @@ impl<'a> Tokenizer<'a> {
 
     fn potential_re(&self) -> bool {
         match &self.prev_tok {
-            Some(Tok::Ident(_)) | Some(Tok::StrLit(_)) | Some(Tok::PatLit(_))
-            | Some(Tok::ILit(_)) | Some(Tok::FLit(_)) | Some(Tok::RParen) | Some(Tok::RBrack) => {
-                false
-            }
+            Some(Tok::Ident(_))
+            | Some(Tok::StrLit(_))
+            | Some(Tok::RawStrLit(_))
+            | Some(Tok::PatLit(_))
+            | Some(Tok::RegexLit(_))
+            | Some(Tok::ILit(_))
+            | Some(Tok::FLit(_))
+            | Some(Tok::RParen)
+            | Some(Tok::RBrack) => false,
             _ => true,
         }
     }
@@ -587,8 +729,15 @@ impl<'a> Tokenizer<'a> {
                 .enumerate()
                 .flat_map(|(i, b)| if *b == b'\n' { Some(i) } else { None }.into_iter())
                 .collect(),
+            traditional: false,
         }
     }
+    // Lex the program as one-true-awk would: raw strings, @/re/ constants, and the BEGINFILE and
+    // ENDFILE keywords are not recognized.
+    pub fn traditional(mut self, traditional: bool) -> Tokenizer<'a> {
+        self.traditional = traditional;
+        self
+    }
     fn index_to_loc(&self, ix: usize) -> Loc {
         let offset = ix;
         match self.lines.binary_search(&ix) {
@@ -635,6 +784,30 @@ impl<'a> Iterator for Tokenizer<'a> {
                     self.cur = new_start;
                     self.spanned(ix, new_start, Tok::StrLit(s))
                 }
+                'r' if !self.traditional && self.text[ix..].starts_with("r\"") => {
+                    self.cur += 2;
+                    let (s, new_start) = try_tok!(self.raw_string_lit());
+                    self.cur = new_start;
+                    self.spanned(ix, new_start, Tok::RawStrLit(s))
+                }
+                // gawk-style "strongly typed" regex constants. These keep their own type (rather
+                // than being strings) when stored in variables or passed to functions.
+                '@' if !self.traditional && self.text[ix..].starts_with("@/") => {
+                    self.cur += 2;
+                    let (re, new_start) = try_tok!(self.regex_lit());
+                    self.cur = new_start;
+                    self.spanned(ix, new_start, Tok::RegexLit(re))
+                }
+                '@' if !self.traditional && self.indirect_call(ix).is_some() => {
+                    let (f, new_start) = self.indirect_call(ix).unwrap();
+                    self.cur = new_start;
+                    self.spanned(ix, new_start, Tok::IndirectCallStart(f))
+                }
+                '#' => {
+                    // Other comments are skipped by `advance`.
+                    self.cur += self.type_pragma().unwrap();
+                    self.spanned(ix, self.cur, Tok::TypePragma)
+                }
                 '/' if self.potential_re() => {
                     self.cur += 1;
                     let (re, new_start) = try_tok!(self.regex_lit());
@@ -645,6 +818,9 @@ impl<'a> Iterator for Tokenizer<'a> {
                     if let Some((tok, len)) = self.fundec() {
                         self.cur += len;
                         self.spanned(ix, self.cur, tok)
+                    } else if let Some((tok, len)) = self.constdec() {
+                        self.cur += len;
+                        self.spanned(ix, self.cur, tok)
                     } else if let Some((tok, len)) = self.keyword() {
                         self.cur += len;
                         self.spanned(ix, self.cur, tok)
@@ -807,6 +983,33 @@ and the third"#;
                 FLit("3.5"),
             ],
         );
+        let toks = lex_str(r#"x = @/a\/b/ / 2"#);
+        assert_eq!(
+            toks.into_iter().map(|x| x.1).collect::<Vec<_>>(),
+            vec![
+                Ident("x"),
+                Assign,
+                RegexLit("a\\/b"),
+                Div,
+                ILit("2"),
+                Newline
+            ],
+        );
+        let toks = lex_str(r#"x = r"\d+\.\d*" r; rx=r"""#);
+        assert_eq!(
+            toks.into_iter().map(|x| x.1).collect::<Vec<_>>(),
+            vec![
+                Ident("x"),
+                Assign,
+                RawStrLit(r#"\d+\.\d*"#),
+                Ident("r"),
+                Semi,
+                Ident("rx"),
+                Assign,
+                RawStrLit(""),
+                Newline,
+            ],
+        );
         let mut buf = Vec::new();
         let a = Arena::default();
         assert_eq!(parse_string_literal(s1, &a, &mut buf), b"\"hi\tthere\n");
@@ -823,5 +1026,49 @@ and the third"#;
             parse_string_literal(r#"are you there \77\xh"#, &a, &mut buf),
             b"are you there ?\\xh"
         );
+        assert_eq!(parse_string_literal(r#"\101\1018"#, &a, &mut buf), b"AA8");
+        assert_eq!(parse_string_literal(r#"\xff\0"#, &a, &mut buf), b"\xff\x00");
+        assert_eq!(
+            parse_string_literal(r#"\u{1F600}\u{e9}!"#, &a, &mut buf),
+            "\u{1F600}\u{e9}!".as_bytes()
+        );
+        assert_eq!(
+            parse_string_literal(r#"\u{}\u{D800}\u{1234567}\u41"#, &a, &mut buf),
+            br#"\u{}\u{D800}\u{1234567}\u41"#
+        );
+    }
+
+    #[test]
+    fn type_pragmas() {
+        use Tok::*;
+        let toks = lex_str("# @type m: map[str]int\nx = 1 # @type x: int\n  #@type y: str");
+        assert_eq!(
+            toks.into_iter().map(|x| x.1).collect::<Vec<_>>(),
+            vec![
+                TypePragma,
+                Ident("m"),
+                COLON,
+                Ident("map"),
+                LBrack,
+                Ident("str"),
+                RBrack,
+                Ident("int"),
+                Newline,
+                Ident("x"),
+                Assign,
+                ILit("1"),
+                Newline,
+                TypePragma,
+                Ident("y"),
+                COLON,
+                Ident("str"),
+                Newline,
+            ],
+        );
+        let toks: Vec<_> = Tokenizer::new("# @type x: int")
+            .traditional(true)
+            .map(|x| x.ok().unwrap().1)
+            .collect();
+        assert_eq!(toks, vec![Newline]);
     }
 }
@@ -0,0 +1,49 @@
+//! frawk as a library, for embedding frawk programs in other Rust programs (see `embed`), the
+//! cargo-fuzz targets in `fuzz/`, the C bindings in `capi/` and the Python bindings in `python/`.
+//! It has the same modules as the binary (see `main.rs`), along with the embedding API in `embed`,
+//! the entry points in `fuzz` and the Python module in `python`, and is empty unless the `embed`,
+//! `fuzzing` or `pyo3` feature is enabled.
+#![cfg(any(feature = "fuzzing", feature = "embed"))]
+#![recursion_limit = "512"]
+#![cfg_attr(feature = "unstable", feature(core_intrinsics))]
+#![cfg_attr(feature = "unstable", feature(test))]
+#![cfg_attr(feature = "unstable", feature(write_all_vectored))]
+// Much of the crate is only reachable from `main`.
+#![allow(dead_code, unused_macros)]
+#[macro_use]
+pub mod common;
+
+pub mod arena;
+pub mod ast;
+pub mod builtins;
+pub mod bytecode;
+pub mod cfg;
+#[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
+#[macro_use]
+pub mod codegen;
+pub mod compile;
+pub mod cross_stage;
+pub mod dataflow;
+mod diagnostics;
+mod display;
+pub mod dom;
+#[cfg(feature = "embed")]
+pub mod embed;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+#[cfg(test)]
+pub mod harness;
+mod input_taint;
+pub mod interp;
+pub mod lexer;
+#[allow(unused_parens)] // Warnings appear in generated code
+#[allow(clippy::all)]
+pub mod parsing;
+pub mod pushdown;
+#[cfg(feature = "pyo3")]
+pub mod python;
+pub mod runtime;
+mod string_constants;
+#[cfg(test)]
+mod test_string_constants;
+pub mod types;
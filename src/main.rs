@@ -10,13 +10,16 @@ pub mod ast;
 pub mod builtins;
 pub mod bytecode;
 pub mod cfg;
+#[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
 #[macro_use]
 pub mod codegen;
 pub mod compile;
 pub mod cross_stage;
 pub mod dataflow;
+mod diagnostics;
 mod display;
 pub mod dom;
+mod golden;
 #[cfg(test)]
 pub mod harness;
 mod input_taint;
@@ -31,41 +34,73 @@ mod string_constants;
 #[cfg(test)]
 mod test_string_constants;
 pub mod types;
+mod watch;
 
 use clap::{Arg, Command};
 
 use arena::Arena;
 use cfg::Escaper;
+#[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
 use codegen::intrinsics::IntoRuntime;
-use common::{CancelSignal, ExecutionStrategy, Stage};
+use common::{CancelSignal, ExecutionStrategy, Stage, EXIT_RUNTIME, EXIT_USAGE};
+#[cfg(feature = "arrow")]
+use runtime::splitter::arrow::ArrowReader;
+use runtime::splitter::auto::AutoReader;
+#[cfg(feature = "avro")]
+use runtime::splitter::avro::AvroReader;
+use runtime::splitter::binary::BinaryReader;
+#[cfg(feature = "json")]
+use runtime::splitter::json::JsonReader;
+use runtime::splitter::socket::SocketReader;
+#[cfg(feature = "sqlite")]
+use runtime::splitter::sqlite::SqliteReader;
+use runtime::splitter::xml::XmlReader;
 use runtime::{
     splitter::{
-        batch::{ByteReader, CSVReader, InputFormat},
+        batch::{ByteReader, CSVReader, CsvDialect, InputFormat},
         regex::RegexSplitter,
     },
-    ChainedReader, LineReader, CHUNK_SIZE,
+    warnings::Warning,
+    ArgvReader, ChainedReader, LineReader, CHUNK_SIZE,
 };
+use std::ffi::OsString;
 use std::fs::File;
 use std::io::{self, BufReader, Write};
 use std::iter::once;
 use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[cfg(feature = "use_jemalloc")]
 #[global_allocator]
 static ALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+// Print an error and exit. The exit status is EXIT_USAGE unless another is given with
+// `fail!(status = ...; ...)`.
 macro_rules! fail {
-    ($($t:tt)*) => {{
+    (status = $status:expr; $($t:tt)*) => {{
         eprintln_ignore!($($t)*);
-        std::process::exit(1)
-    }}
+        std::process::exit($status)
+    }};
+    ($($t:tt)*) => {
+        fail!(status = EXIT_USAGE; $($t)*)
+    };
 }
 
 #[derive(Clone)]
 struct PreludeScalars {
     arbitrary_shell: bool,
     fold_regexes: bool,
+    ieee_div: bool,
     parse_header: bool,
+    traditional: bool,
+    asserts: bool,
+    mem_profile: bool,
+    dump_on_signal: bool,
+    debug_info: bool,
+    strict: bool,
+    checkpoint: bool,
+    resume_nr: Option<runtime::Int>,
     escaper: Escaper,
     stage: Stage<()>,
 }
@@ -73,14 +108,19 @@ struct PreludeScalars {
 struct RawPrelude {
     argv: Vec<String>,
     var_decs: Vec<String>,
+    // NAME=VALUE operands, with the ARGIND of the input file each one precedes.
+    file_assigns: Vec<(runtime::Int, String)>,
+    const_decs: Vec<String>,
     field_sep: Option<String>,
-    output_sep: Option<&'static str>,
+    output_sep: Option<String>,
     output_record_sep: Option<&'static str>,
     scalars: PreludeScalars,
 }
 
 struct Prelude<'a> {
     var_decs: Vec<(&'a str, &'a ast::Expr<'a, 'a, &'a str>)>,
+    file_assigns: Vec<(runtime::Int, &'a str, &'a ast::Expr<'a, 'a, &'a str>)>,
+    const_decs: Vec<(&'a str, &'a ast::Expr<'a, 'a, &'a str>)>,
     field_sep: Option<&'a [u8]>,
     output_sep: Option<&'a [u8]>,
     output_record_sep: Option<&'a [u8]>,
@@ -89,6 +129,29 @@ struct Prelude<'a> {
 }
 
 // TODO: make file reading lazy
+/// A single-character argument to `--csv-delimiter` or `--csv-quote`; `\t` is accepted for a tab.
+fn csv_char(flag: &str, s: &str) -> u8 {
+    match s {
+        "\\t" => b'\t',
+        s if s.len() == 1 => s.as_bytes()[0],
+        s => fail!("--{} must be a single character, got {:?}", flag, s),
+    }
+}
+
+// Input operands of the form NAME=VALUE, where NAME is an identifier, assign VALUE to the variable
+// NAME once the files before them have been read, as in POSIX awk. A file with such a name can be
+// read by prefixing it with "./".
+fn is_assignment_operand(f: &str) -> bool {
+    f.find('=').map_or(false, |ix| lexer::is_ident(&f[..ix]))
+}
+
+// Input operands of the form sqlite://<file>?query=<sql> read the results of a query rather than a
+// file; see runtime::splitter::sqlite.
+fn is_sqlite_input(f: &str) -> bool {
+    f.starts_with("sqlite://")
+}
+
+// Open the input file `f` when it is first read.
 fn open_file_read(f: &str) -> impl io::BufRead {
     enum LazyReader<F, R> {
         Uninit(F),
@@ -118,6 +181,24 @@ fn open_file_read(f: &str) -> impl io::BufRead {
     BufReader::new(LazyReader::Uninit(move || File::open(filename.as_str())))
 }
 
+// Open the input named `name`, or standard input, along with its name.
+fn open_input(name: Option<&str>) -> (Box<dyn io::Read + Send>, String) {
+    match name {
+        Some(f) => (Box::new(open_file_read(f)), String::from(f)),
+        None => (Box::new(io::stdin()), String::from("-")),
+    }
+}
+
+// The inputs given to the batch readers, along with their names.
+type Inputs = Box<dyn Iterator<Item = (Box<dyn io::Read + Send>, String)> + Send>;
+
+// Open each of the input files `fs`, or standard input if there are none.
+fn open_inputs(fs: &[String]) -> impl Iterator<Item = (Box<dyn io::Read + Send>, String)> {
+    let names: Vec<_> = fs.iter().cloned().map(Some).collect();
+    let names = if names.is_empty() { vec![None] } else { names };
+    names.into_iter().map(|f| open_input(f.as_deref()))
+}
+
 fn chained<LR: LineReader>(lr: LR) -> ChainedReader<LR> {
     ChainedReader::new(std::iter::once(lr))
 }
@@ -153,11 +234,46 @@ fn get_vars<'a, 'b>(
     res
 }
 
+// -D flags define constants. Values that look like numbers are numeric constants; anything else is
+// a string.
+fn get_consts<'a, 'b>(
+    consts: impl Iterator<Item = &'b str>,
+    a: &'a Arena,
+    buf: &mut Vec<u8>,
+) -> Vec<(&'a str, &'a ast::Expr<'a, 'a, &'a str>)> {
+    let mut res = Vec::new();
+    for c in consts {
+        buf.clear();
+        let mut split = c.splitn(2, '=');
+        let (name, value) = match (split.next(), split.next()) {
+            (Some(name), Some(value)) => (name, value),
+            _ => fail!("received -D flag without an '=' sign: {}", c),
+        };
+        let ident = a.alloc_str(name.trim());
+        if !lexer::is_ident(ident) {
+            fail!(
+                "invalid identifier for left-hand side of -D flag: {}",
+                ident
+            );
+        }
+        let lit = if let Ok(n) = value.parse::<i64>() {
+            ast::Expr::ILit(n)
+        } else if let Some(n) = value.parse::<f64>().ok().filter(|n| n.is_finite()) {
+            ast::Expr::FLit(n)
+        } else {
+            ast::Expr::StrLit(lexer::parse_string_literal(value, a, buf))
+        };
+        res.push((ident, a.alloc(lit)))
+    }
+    res
+}
+
 fn get_prelude<'a>(a: &'a Arena, raw: &RawPrelude) -> Prelude<'a> {
     let mut buf = Vec::new();
     let output_sep = raw
         .output_sep
-        .map(|s| lexer::parse_string_literal(s, a, &mut buf));
+        .as_ref()
+        .map(|s| lexer::parse_string_literal(s.as_str(), a, &mut buf));
     let output_record_sep = raw
         .output_record_sep
         .map(|s| lexer::parse_string_literal(s, a, &mut buf));
@@ -165,9 +281,17 @@ fn get_prelude<'a>(a: &'a Arena, raw: &RawPrelude) -> Prelude<'a> {
         .field_sep
         .as_ref()
         .map(|s| lexer::parse_string_literal(s.as_str(), a, &mut buf));
+    let assigns = raw.file_assigns.iter().map(|(_, s)| s.as_str());
+    let file_assigns = get_vars(assigns, a, &mut buf)
+        .into_iter()
+        .zip(raw.file_assigns.iter())
+        .map(|((name, value), (ix, _))| (*ix, name, value))
+        .collect();
     Prelude {
         field_sep,
         var_decs: get_vars(raw.var_decs.iter().map(|s| s.as_str()), a, &mut buf),
+        file_assigns,
+        const_decs: get_consts(raw.const_decs.iter().map(|s| s.as_str()), a, &mut buf),
         scalars: raw.scalars.clone(),
         output_sep,
         output_record_sep,
@@ -180,16 +304,45 @@ fn get_context<'a>(
     a: &'a Arena,
     mut prelude: Prelude<'a>,
 ) -> cfg::ProgramContext<'a, &'a str> {
-    let prog = a.alloc_str(prog);
-    let lexer = lexer::Tokenizer::new(prog);
+    let src = if prelude.scalars.traditional {
+        a.alloc_str(prog)
+    } else {
+        match parsing::expand_includes(prog) {
+            Ok(src) => a.alloc_str(&src),
+            Err(e) => fail!("{}", e),
+        }
+    };
+    let lexer = lexer::Tokenizer::new(src).traditional(prelude.scalars.traditional);
     let mut buf = Vec::new();
     let parser = parsing::syntax::ProgParser::new();
     let mut prog = ast::Prog::from_stage(a, prelude.scalars.stage.clone());
     prog.argv = mem::take(&mut prelude.argv);
-    let stmt = match parser.parse(a, &mut buf, &mut prog, lexer) {
+    prog.traditional = prelude.scalars.traditional;
+    prog.asserts = prelude.scalars.asserts;
+    prog.mem_profile = prelude.scalars.mem_profile;
+    prog.dump_on_signal = prelude.scalars.dump_on_signal;
+    // Strict mode needs the line markers used for debug info.
+    prog.debug_lines = prelude.scalars.debug_info || prelude.scalars.strict;
+    prog.checkpoint = prelude.scalars.checkpoint;
+    prog.resume_nr = prelude.scalars.resume_nr;
+    prog.consts = mem::take(&mut prelude.const_decs);
+    let parsed = match parser.parse(a, &mut buf, &mut prog, lexer) {
+        Ok(()) => parsing::add_helpers(a, &mut buf, &mut prog),
+        Err(e) => fail!("{}", diagnostics::parse_error(src, &e)),
+    };
+    let stmt = match parsed {
         Ok(()) => {
+            // As in gawk, records are not read from BEGINFILE and ENDFILE, and the file being read
+            // cannot be skipped once it has ended.
+            if prog.beginfile.iter().any(|s| s.contains_next(false)) {
+                fail!("`next` cannot be used in BEGINFILE");
+            }
+            if prog.endfile.iter().any(|s| s.contains_next(true)) {
+                fail!("`next` and `nextfile` cannot be used in ENDFILE");
+            }
             prog.field_sep = prelude.field_sep;
             prog.prelude_vardecs = prelude.var_decs;
+            prog.file_assignments = prelude.file_assigns;
             prog.output_sep = prelude.output_sep;
             prog.output_record_sep = prelude.output_record_sep;
             prog.parse_header = prelude.scalars.parse_header;
@@ -203,6 +356,7 @@ fn get_context<'a>(
         Ok(mut ctx) => {
             ctx.allow_arbitrary_commands = prelude.scalars.arbitrary_shell;
             ctx.fold_regex_constants = prelude.scalars.fold_regexes;
+            ctx.ieee_div = prelude.scalars.ieee_div;
             ctx
         }
         Err(e) => fail!("failed to create program context: {}", e),
@@ -221,7 +375,7 @@ fn run_interp_with_context<'a>(
             Err(e) => fail!("bytecode compilation failure: {}", e),
         };
         match interp.run() {
-            Err(e) => fail!("fatal error during execution: {}", e),
+            Err(e) => fail!(status = EXIT_RUNTIME; "fatal error during execution: {}", e),
             Ok(0) => return,
             Ok(n) => n,
         }
@@ -229,9 +383,10 @@ fn run_interp_with_context<'a>(
     std::process::exit(rc);
 }
 
+#[cfg(feature = "cranelift_backend")]
 fn run_cranelift_with_context<'a>(
     mut ctx: cfg::ProgramContext<'a, &'a str>,
-    stdin: impl IntoRuntime,
+    stdin: impl IntoRuntime + LineReader,
     ff: impl runtime::writers::FileFactory,
     cfg: codegen::Config,
     signal: CancelSignal,
@@ -245,7 +400,7 @@ cfg_if::cfg_if! {
     if #[cfg(feature = "llvm_backend")] {
         fn run_llvm_with_context<'a>(
             mut ctx: cfg::ProgramContext<'a, &'a str>,
-            stdin: impl IntoRuntime,
+            stdin: impl IntoRuntime + LineReader,
             ff: impl runtime::writers::FileFactory,
             cfg: codegen::Config,
             signal: CancelSignal,
@@ -278,9 +433,10 @@ fn dump_bytecode(prog: &str, raw: &RawPrelude) -> String {
         &mut ctx,
         chained(CSVReader::new(
             once((fake_inp, String::from("unused"))),
-            InputFormat::CSV,
+            InputFormat::CSV(Default::default()),
             CHUNK_SIZE,
             /*check_utf8=*/ false,
+            Default::default(),
             ExecutionStrategy::Serial,
             Default::default(),
         )),
@@ -301,25 +457,80 @@ fn dump_bytecode(prog: &str, raw: &RawPrelude) -> String {
     String::from_utf8(v).unwrap()
 }
 
+/// Split up the options from a `#!` line. Most kernels pass everything after the interpreter in a
+/// `#!` line to it as a single argument, so a script starting with `#!/usr/bin/frawk -F, -f` runs
+/// frawk with the arguments "-F, -f" and the path of the script. We split such an argument on
+/// whitespace when it ends in `-f`, which a `#!` line needs in order to read the script as the
+/// program; other arguments (such as "-F " for a separator of a single space) are left alone.
+fn split_shebang_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let opts = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some(arg) if arg.starts_with('-') => arg,
+        _ => return args,
+    };
+    let split: Vec<OsString> = opts.split_whitespace().map(OsString::from).collect();
+    match split.last().and_then(|last| last.to_str()) {
+        Some("-f" | "--program-file") if split.len() > 1 => {
+            args.splice(1..2, split);
+            args
+        }
+        _ => args,
+    }
+}
+
 fn main() {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    if let Some(dir) = golden::test_dir(&args) {
+        match golden::run_tests(&dir) {
+            Ok(status) => std::process::exit(status),
+            Err(e) => fail!("failed to run the tests in {}: {}", dir.display(), e),
+        }
+    }
     #[allow(unused_mut)]
     let mut app = Command::new("frawk")
         .version("0.4.6")
         .author("Eli R.")
         .about("frawk is a pattern scanning and (semi-structured) text processing language")
+        .after_help("Run `frawk test DIR` to run the golden tests in DIR: each script NAME.awk runs on NAME.in (if present) under every backend, and must print the contents of NAME.out")
         .arg(Arg::new("program-file")
              .long("program-file")
              .short('f')
              .takes_value(true)
              .multiple_occurrences(true)
              .help("Read the program source from the file program-file, instead of from the command line. Multiple '-f' options may be used"))
+        .arg(Arg::new("source")
+             .long("source")
+             .short('e')
+             .takes_value(true)
+             .multiple_occurrences(true)
+             .value_name("CODE")
+             .help("Use CODE as program source, instead of the program on the command line. Multiple '-e' options may be used, and may be mixed with '-f': all of them are concatenated in the order they are given"))
+        .arg(Arg::new("begin")
+             .long("begin")
+             .takes_value(true)
+             .multiple_occurrences(true)
+             .value_name("CODE")
+             .help("Add a BEGIN block containing CODE to the end of the program, e.g. --begin 'OFS=\",\"'. It runs after any BEGIN blocks in the program. Multiple '--begin' options may be used"))
+        .arg(Arg::new("end")
+             .long("end")
+             .takes_value(true)
+             .multiple_occurrences(true)
+             .value_name("CODE")
+             .help("Add an END block containing CODE to the end of the program, e.g. --end 'print total'. It runs after any END blocks in the program. Multiple '--end' options may be used"))
         .arg(Arg::new("opt-level")
              .long("opt-level")
              .short('O')
              .takes_value(true)
              .allow_hyphen_values(true)
-             .help("The optimization level for the program. Positive levels determine the optimization level for LLVM. Level `-1` forces bytecode interpretation")
+             .help("The optimization level for the program. Level `-1` forces bytecode interpretation. For LLVM, `-O0` skips optimization entirely, `-O1` only runs per-function passes, and `-O2` and `-O3` add module-level passes like inlining; lower levels start faster, higher levels process records faster. Level 3 also folds constant regular expressions")
              .possible_values(&["-1", "0", "1", "2", "3"]))
+        .arg(Arg::new("perf-map")
+             .long("perf-map")
+             .takes_value(false)
+             .help("Write the addresses of JIT-compiled functions to /tmp/perf-<pid>.map, so that profilers like `perf` can attribute samples to them. Only supported by the LLVM backend"))
+        .arg(Arg::new("debug-info")
+             .long("debug-info")
+             .takes_value(false)
+             .help("Emit line-table debug info for JIT-compiled code, so that debuggers and profilers can map it back to lines in the awk program. Only supported by the LLVM backend"))
         .arg(Arg::new("out-file")
              .long("out-file")
              .takes_value(true)
@@ -328,7 +539,7 @@ fn main() {
         .arg(Arg::new("utf8")
              .long("utf8")
              .takes_value(false)
-             .help("Validate all input as UTF-8, returning an error if it is invalid"))
+             .help("Validate all input as UTF-8, returning an error if it is invalid. chr and ord work on code points rather than bytes"))
         .arg(Arg::new("dump-cfg")
              .long("dump-cfg")
              .takes_value(false)
@@ -342,19 +553,70 @@ fn main() {
              .short('H')
              .takes_value(false)
              .help("Consume the first line of input and populate the `FI` variable with column names mapping to column indexes"))
+        .arg(Arg::new("traditional")
+             .long("traditional")
+             .short('c')
+             .takes_value(false)
+             .conflicts_with("parse-header")
+             .help("Compatibility mode for one-true-awk: disable gawk and frawk extensions to the language, such as BEGINFILE, raw strings and the bitwise builtins. Names of extension builtins are treated as ordinary identifiers"))
+        .arg(Arg::new("disable-asserts")
+             .long("disable-asserts")
+             .takes_value(false)
+             .help("Compile away calls to `assert`, without evaluating their arguments"))
+        .arg(Arg::new("mem-profile")
+             .long("mem-profile")
+             .takes_value(false)
+             .help("When the program finishes (or, with --dump-on-signal, receives SIGUSR1), print the number of entries and estimated memory usage of each array, along with allocator statistics, to standard error"))
+        .arg(Arg::new("dump-on-signal")
+             .long("dump-on-signal")
+             .takes_value(false)
+             .help("Call dump_state() after the current record when the process receives SIGUSR1. Not supported in parallel mode"))
         .arg(Arg::new("input-format")
              .long("input-format")
              .short('i')
-             .value_name("csv|tsv")
+             .value_name("csv|tsv|arrow|avro|json")
              .conflicts_with("field-separator")
-             .help("Input is split according to the rules of (csv|tsv). $0 contains the unescaped line. Assigning to columns does nothing")
-             .possible_values(&["csv", "tsv"]))
+             .help("Input is split according to the rules of (csv|tsv). $0 contains the unescaped line. Assigning to a column rebuilds $0 from the fields joined by OFS, and assigning to $0 splits it again. With 'arrow', inputs are Arrow IPC files or streams, with one field per column. With 'avro', inputs are Avro container files, with one field per field of the record schema. With 'json', each line of input is a JSON object, with one field per key; with -H, the keys of the first object populate FI")
+             .possible_values(&["csv", "tsv", "arrow", "avro", "json"]))
+        .arg(Arg::new("csv-delimiter")
+             .long("csv-delimiter")
+             .takes_value(true)
+             .value_name("CHAR")
+             .help("Separate fields with CHAR rather than ',' when reading input with '-i csv' and writing output with '-o csv', e.g. ';' or '|'. Use '\\t' for a tab"))
+        .arg(Arg::new("csv-quote")
+             .long("csv-quote")
+             .takes_value(true)
+             .value_name("CHAR")
+             .help("Quote fields with CHAR rather than '\"' when reading input with '-i csv' and writing output with '-o csv' or escape_csv. A quote character inside a quoted field is written twice"))
+        .arg(Arg::new("xml-record")
+             .long("xml-record")
+             .takes_value(true)
+             .value_name("SELECTOR")
+             .conflicts_with_all(&["input-format", "field-separator"])
+             .help("Read inputs as XML or HTML documents, with one record per element matching SELECTOR: a tag name, or a CSS-style selector such as 'div.result > a[href]'. $0 contains the element's markup; use getxml to extract its text and attributes"))
+        .arg(Arg::new("record-bytes")
+             .long("record-bytes")
+             .takes_value(true)
+             .value_name("N")
+             .conflicts_with_all(&["input-format", "xml-record"])
+             .help("Read inputs as fixed-length binary records of N bytes each. $0 contains the record's raw bytes; use unpack to split it into fields"))
+        .arg(Arg::new("auto")
+             .long("auto")
+             .takes_value(false)
+             .conflicts_with_all(&["input-format", "field-separator", "xml-record", "record-bytes", "serve"])
+             .help("Detect the format of each input from its first few lines: TSV if they all have the same number of tabs, CSV (see --csv-delimiter) if they all have the same number of separators, and whitespace-separated fields otherwise. Gzip-compressed inputs are decompressed first. With --stats, the format chosen for each input is reported"))
         .arg(Arg::new("var")
              .short('v')
              .takes_value(true)
              .multiple_occurrences(true)
              .value_name("var=val")
              .help("Assign the value <val> to the variable <var>, before execution of the frawk program begins. Multiple '-v' options may be used"))
+        .arg(Arg::new("define")
+             .short('D')
+             .takes_value(true)
+             .multiple_occurrences(true)
+             .value_name("name=val")
+             .help("Define the constant <name> with value <val>, as if by a `const` declaration. <val> is a number if it parses as one, and a string otherwise. Multiple '-D' options may be used"))
         .arg(Arg::new("field-separator")
              .long("field-separator")
              .short('F')
@@ -365,7 +627,7 @@ fn main() {
         .arg(Arg::new("backend")
              .long("backend")
              .short('B')
-             .help("The backend used to run the frawk program, ranging from fastest to compile and slowest to execute, and slowest to compile and fastest to execute. Cranelift is the default")
+             .help("The backend used to run the frawk program, ranging from fastest to compile and slowest to execute, and slowest to compile and fastest to execute. Cranelift is the default, or the interpreter if frawk was built without Cranelift support")
              .possible_values(&["interp", "cranelift", "llvm"]))
         .arg(Arg::new("output-format")
              .long("output-format")
@@ -373,17 +635,27 @@ fn main() {
              .value_name("csv|tsv")
              .help("If set, records output via print are escaped according to the rules of the corresponding format")
              .possible_values(&["csv", "tsv"]))
+        .arg(Arg::new("ojson")
+             .long("ojson")
+             .takes_value(false)
+             .conflicts_with("output-format")
+             .help("Write each record output via print as a JSON object on its own line. Fields are keyed by their column names from the header (see -H), or by $N for columns without a name; other values are keyed by their position in the print statement. Print with no arguments writes the whole record"))
+        .arg(Arg::new("omsgpack")
+             .long("omsgpack")
+             .takes_value(false)
+             .conflicts_with_all(&["output-format", "ojson"])
+             .help("Write each record output via print as a MessagePack map, keyed as with --ojson. Records are written back to back, without ORS. Requires frawk to be built with the msgpack feature"))
         .arg(Arg::new("program")
              .index(1)
              .help("The frawk program to execute"))
         .arg(Arg::new("input-files")
              .index(2)
              .multiple_values(true)
-             .help("Input files to be read by frawk program"))
+             .help("Input files to be read by frawk program. An operand of the form NAME=VALUE instead assigns VALUE to the variable NAME before the files after it are read"))
         .arg(Arg::new("parallel-strategy")
              .short('p')
-             .help("Attempt to execute the script in parallel. Strategy r[ecord] parallelizes within the current input file. Strategy f[ile] parallelizes between input files")
-             .possible_values(&["r", "record", "f", "file"]))
+             .value_name("r[ecord]|f[ile]|N")
+             .help("Attempt to execute the script in parallel. Strategy r[ecord] parallelizes within the current input file. Strategy f[ile] parallelizes between input files. A number N is short for '-pr -jN'"))
         .arg(Arg::new("chunk-size")
              .long("chunk-size")
              .takes_value(true)
@@ -397,7 +669,124 @@ fn main() {
              .short('j')
              .requires("parallel-strategy")
              .takes_value(true)
-             .help("Number or worker threads to launch when executing in parallel, requires '-p' flag to be set. When using record-level parallelism, this value is an upper bound on the number of worker threads that will be spawned; the number of active worker threads is chosen dynamically"));
+             .help("Number or worker threads to launch when executing in parallel, requires '-p' flag to be set. When using record-level parallelism, this value is an upper bound on the number of worker threads that will be spawned; the number of active worker threads is chosen dynamically"))
+        .arg(Arg::new("pin-workers")
+             .long("pin-workers")
+             .requires("parallel-strategy")
+             .takes_value(true)
+             .value_name("cores|nodes")
+             .possible_values(&["cores", "nodes"])
+             .help("Pin worker threads to CPUs when executing in parallel, requires '-p' flag to be set. 'cores' pins each worker to its own core; 'nodes' pins each worker to the cores of a NUMA node, spreading workers across nodes. Workers are pinned before allocating their input buffers, so those buffers are local to the node they run on. Only supported on Linux"))
+        .arg(Arg::new("keep-order")
+             .long("keep-order")
+             .requires("parallel-strategy")
+             .takes_value(false)
+             .help("Write output to standard output in input order when executing in parallel, requires '-p' flag to be set. Each worker buffers the output for a chunk of input (or a whole file, with '-pf') until the output for all earlier chunks has been written"))
+        .arg(Arg::new("fail-on-command-error")
+             .long("fail-on-command-error")
+             .takes_value(false)
+             .help("Exit with status 3 if a command run through a pipe (e.g. `print | cmd` or `cmd | getline`) exits with a nonzero status. Commands still running when the program ends are waited on before frawk exits"))
+        .arg(Arg::new("max-record-bytes")
+             .long("max-record-bytes")
+             .takes_value(true)
+             .value_name("N")
+             .help("Limit input records to N bytes, so that a malformed input (e.g. a large file with no newlines) cannot exhaust memory. Longer records are an error, unless --on-limit=truncate is set"))
+        .arg(Arg::new("max-fields")
+             .long("max-fields")
+             .takes_value(true)
+             .value_name("N")
+             .help("Limit input records to N fields. Records with more fields are an error, unless --on-limit=truncate is set"))
+        .arg(Arg::new("on-limit")
+             .long("on-limit")
+             .takes_value(true)
+             .value_name("error|truncate")
+             .possible_values(&["error", "truncate"])
+             .help("What to do with records exceeding --max-record-bytes or --max-fields: stop with an error giving the record's location (the default), or truncate the record and print a warning the first time this happens"))
+        .arg(Arg::new("skip")
+             .long("skip")
+             .takes_value(true)
+             .value_name("N")
+             .conflicts_with("parallel-strategy")
+             .help("Skip the first N records of the input. Skipped records are never seen by the program, and do not count towards NR or FNR. With -H, the header is read after skipping"))
+        .arg(Arg::new("skip-file")
+             .long("skip-file")
+             .takes_value(true)
+             .value_name("N")
+             .conflicts_with("parallel-strategy")
+             .help("Skip the first N records of each input file, e.g. to drop the header line of every file"))
+        .arg(Arg::new("head")
+             .long("head")
+             .takes_value(true)
+             .value_name("N")
+             .conflicts_with("parallel-strategy")
+             .help("Stop reading input after N records, not counting skipped records or a header read with -H. The END block still runs"))
+        .arg(Arg::new("zip")
+             .long("zip")
+             .takes_value(false)
+             .conflicts_with_all(&["parallel-strategy", "skip-file"])
+             .help("Read the input files in lockstep, like paste(1): the first record of each file in turn, then the second record of each, and so on. Files that run out of records drop out. FILENAME and ARGIND identify the file the current record came from, and FNR is its record number within that file. BEGINFILE and ENDFILE cannot be used with this option"))
+        .arg(Arg::new("checkpoint")
+             .long("checkpoint")
+             .takes_value(true)
+             .value_name("FILE")
+             .conflicts_with("parallel-strategy")
+             .help("Save every global variable, along with NR, to FILE every --checkpoint-interval seconds (between records) and when the program finishes, so that an interrupted run can be continued with --resume. A program that stops with `exit` writes no final checkpoint"))
+        .arg(Arg::new("checkpoint-interval")
+             .long("checkpoint-interval")
+             .takes_value(true)
+             .value_name("SECS")
+             .requires("checkpoint")
+             .help("How often --checkpoint saves the program's state, in seconds [default: 60]"))
+        .arg(Arg::new("resume")
+             .long("resume")
+             .takes_value(true)
+             .value_name("FILE")
+             .conflicts_with("parallel-strategy")
+             .help("Restore the global variables saved in a --checkpoint FILE before BEGIN runs (-v assignments still take precedence), and skip the NR records of the input that had already been processed. The same input should be given as in the original run. FNR counts from the first record read after resuming"))
+        .arg(Arg::new("watch")
+             .long("watch")
+             .takes_value(false)
+             .help("Run the program again whenever one of its -f files changes, until interrupted. A run that is still going when the program changes is stopped first: input files are read again from the start, while input from standard input carries on where the previous run left off"))
+        .arg(Arg::new("serve")
+             .long("serve")
+             .takes_value(true)
+             .value_name("ADDR")
+             .conflicts_with_all(&["parallel-strategy", "zip", "input-format", "xml-record", "record-bytes", "resume", "watch"])
+             .help("Run as a server listening on ADDR (HOST:PORT for TCP, or unix:PATH for a Unix domain socket), rather than reading input files. Clients are served one at a time: each connection is read like an input file, named by FILENAME, and what the program prints to standard output is sent back to the client. Variables keep their values between clients, and the server runs until it is killed, so END never runs"))
+        .arg(Arg::new("warning")
+             .short('W')
+             .takes_value(true)
+             .multiple_occurrences(true)
+             .value_name("[no-]CATEGORY")
+             .help("Enable (CATEGORY) or disable (no-CATEGORY) a category of warnings. The categories are 'truncation', for records truncated with --on-limit=truncate; 'substitution', for gensub replacements that refer to a missing group; 'numeric', for strings converted to numbers that are not numbers; and 'encoding', for invalid UTF-8 replaced by printf. Warnings are printed with the NR and FILENAME of the record being processed. All categories but 'numeric' are enabled by default"))
+        .arg(Arg::new("werror")
+             .long("werror")
+             .takes_value(false)
+             .help("Stop with an error instead of printing an enabled warning"))
+        .arg(Arg::new("stats")
+             .long("stats")
+             .takes_value(false)
+             .help("Print the number of warnings in each category (see -W) to standard error when the program finishes, including categories that are disabled, along with the format chosen for each input by --auto"))
+        .arg(Arg::new("no-color")
+             .long("no-color")
+             .takes_value(false)
+             .help("Do not color error messages. By default they are colored when standard error is a terminal, unless the NO_COLOR environment variable is set"))
+        .arg(Arg::new("decimal-comma")
+             .long("decimal-comma")
+             .takes_value(false)
+             .help("Use ',' as the decimal separator, as in many European locales: strings such as \"3,14\" convert to the number 3.14, and printf formats floating-point numbers as 3,14 (grouping digits with '.' for the ' flag). Numbers printed with print, and numeric literals in the program, still use '.'"))
+        .arg(Arg::new("strict")
+             .long("strict")
+             .takes_value(false)
+             .help("Stop with an error, reporting the source line, when a conversion would silently lose information: a string that is not a number (such as \"abc\" or \"12abc\") used as a number, or a float with a fractional part used as an array index. Blank strings still convert to 0"))
+        .arg(Arg::new("ieee-div")
+             .long("ieee-div")
+             .takes_value(false)
+             .help("Follow IEEE 754 floating point when dividing by zero: x/0 is inf or -inf, and 0/0 and x%0 are NaN. By default, division or modulo by zero is a fatal error"))
+        .arg(Arg::new("stream-records")
+             .long("stream-records")
+             .takes_value(false)
+             .help("Stream records that are too long to fit in the input buffer, rather than growing the buffer to hold them. Only the fields that fit in the buffer are kept: reading $0 or a later field of such a record is an error, though NF is still correct. Supported for inputs split on whitespace or on a single-character field separator"));
     cfg_if::cfg_if! {
         if #[cfg(feature = "llvm_backend")] {
             app = app.arg(Arg::new("dump-llvm")
@@ -406,21 +795,85 @@ fn main() {
              .help("Print LLVM-IR for the input program"));
         }
     }
-    let matches = app.get_matches();
+    let args = split_shebang_args(args);
+    let matches = app.get_matches_from(&args);
+    diagnostics::init_color(matches.is_present("no-color"));
+    if matches.is_present("watch") {
+        let files: Vec<PathBuf> = matches
+            .values_of("program-file")
+            .into_iter()
+            .flatten()
+            .map(PathBuf::from)
+            .collect();
+        if files.is_empty() {
+            fail!("--watch requires the program to be read from a file with -f");
+        }
+        let run_args: Vec<OsString> = args[1..]
+            .iter()
+            .filter(|arg| *arg != "--watch")
+            .cloned()
+            .collect();
+        match watch::run(&run_args, &files) {
+            Ok(()) => return,
+            Err(e) => fail!("--watch: {}", e),
+        }
+    }
+    let csv_dialect = {
+        let default = CsvDialect::default();
+        let sep = matches
+            .value_of("csv-delimiter")
+            .map_or(default.sep, |s| csv_char("csv-delimiter", s));
+        let quote = matches
+            .value_of("csv-quote")
+            .map_or(default.quote, |s| csv_char("csv-quote", s));
+        CsvDialect::new(sep, quote).unwrap_or_else(|e| fail!("{}", e))
+    };
     let ifmt = match matches.value_of("input-format") {
-        Some("csv") => Some(InputFormat::CSV),
+        Some("csv") => Some(InputFormat::CSV(csv_dialect)),
         Some("tsv") => Some(InputFormat::TSV),
+        Some("arrow") | Some("avro") | Some("json") => None,
         Some(x) => fail!("invalid input format: {}", x),
         None => None,
     };
-    let exec_strategy = match matches.value_of("parallel-strategy") {
-        Some("r") | Some("record") => ExecutionStrategy::ShardPerRecord,
-        Some("f") | Some("file") => ExecutionStrategy::ShardPerFile,
-        None => ExecutionStrategy::Serial,
-        Some(x) => fail!(
-            "invalid execution strategy (clap arg parsing should handle this): {}",
-            x
-        ),
+    let arrow_input = matches.value_of("input-format") == Some("arrow");
+    let avro_input = matches.value_of("input-format") == Some("avro");
+    let json_input = matches.value_of("input-format") == Some("json");
+    let xml_selector = matches.value_of("xml-record").map(|sel| {
+        runtime::xml::Selector::parse(sel).unwrap_or_else(|e| fail!("--xml-record: {}", e))
+    });
+    let auto_input = matches.is_present("auto");
+    let record_bytes = matches
+        .value_of("record-bytes")
+        .map(|n| match n.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => fail!("--record-bytes must be a positive integer, got {:?}", n),
+        });
+    let limit = |arg: &str| {
+        matches.value_of(arg).map(|n| match n.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => fail!("--{} must be a positive integer, got {:?}", arg, n),
+        })
+    };
+    let (max_record_bytes, max_fields) = (limit("max-record-bytes"), limit("max-fields"));
+    let count = |arg: &str| {
+        matches.value_of(arg).map(|n| match n.parse::<usize>() {
+            Ok(n) => n,
+            _ => fail!("--{} must be a non-negative integer, got {:?}", arg, n),
+        })
+    };
+    let (skip, skip_file, head) = (count("skip"), count("skip-file"), count("head"));
+    // `-p N` parallelizes by record, with N workers.
+    let (exec_strategy, parallel_jobs) = match matches.value_of("parallel-strategy") {
+        Some("r") | Some("record") => (ExecutionStrategy::ShardPerRecord, None),
+        Some("f") | Some("file") => (ExecutionStrategy::ShardPerFile, None),
+        None => (ExecutionStrategy::Serial, None),
+        Some(x) => match x.parse::<usize>() {
+            Ok(n) if n > 0 => (ExecutionStrategy::ShardPerRecord, Some(n)),
+            _ => fail!(
+                "invalid execution strategy {:?}: expected r[ecord], f[ile] or a number of workers",
+                x
+            ),
+        },
     };
 
     // NB: do we want this to be a command-line param?
@@ -433,53 +886,107 @@ fn main() {
         CHUNK_SIZE
     };
     let num_workers = match matches.value_of("jobs") {
+        Some(_) if parallel_jobs.is_some() => {
+            fail!("-j cannot be combined with -p N, which already sets the number of workers")
+        }
         Some(s) => match s.parse::<usize>() {
             Ok(u) => u,
             Err(e) => fail!("value of 'jobs' flag must be numeric: {}", e),
         },
-        None => exec_strategy.num_workers(),
+        None => parallel_jobs.unwrap_or_else(|| exec_strategy.num_workers()),
     };
-    let argv: Vec<String> = std::env::args()
-        .next()
-        .into_iter()
-        .chain(
-            matches
-                .values_of("input-files")
-                .into_iter()
-                .flat_map(|x| x.map(String::from)),
-        )
-        .collect();
     let mut input_files: Vec<String> = matches
         .values_of("input-files")
         .map(|x| x.map(String::from).collect())
         .unwrap_or_else(Vec::new);
-    let program_string = {
-        if let Some(pfiles) = matches.values_of("program-file") {
-            // We specified a file on the command line, so the "program" will be
+    let mut program_string = {
+        // Program source from -f and -e, in the order the options were given.
+        let mut fragments: Vec<(usize, String)> = Vec::new();
+        if let (Some(ixs), Some(pfiles)) = (
+            matches.indices_of("program-file"),
+            matches.values_of("program-file"),
+        ) {
+            for (ix, pfile) in ixs.zip(pfiles) {
+                match std::fs::read_to_string(pfile) {
+                    Ok(p) => fragments.push((ix, p)),
+                    Err(e) => fail!("failed to read program from {}: {}", pfile, e),
+                }
+            }
+        }
+        if let (Some(ixs), Some(sources)) =
+            (matches.indices_of("source"), matches.values_of("source"))
+        {
+            fragments.extend(ixs.zip(sources.map(String::from)));
+        }
+        if !fragments.is_empty() {
+            // We specified the program with -f or -e, so the "program" will be
             // interpreted as another input file.
             if let Some(p) = matches.value_of("program") {
                 input_files.insert(0, p.into());
             }
+            fragments.sort_by_key(|(ix, _)| *ix);
             let mut prog = String::new();
-            for pfile in pfiles {
-                match std::fs::read_to_string(pfile) {
-                    Ok(p) => {
-                        prog.push_str(p.as_str());
-                        prog.push('\n');
-                    }
-                    Err(e) => fail!("failed to read program from {}: {}", pfile, e),
-                }
+            for (_, p) in fragments {
+                prog.push_str(p.as_str());
+                prog.push('\n');
             }
             prog
         } else if let Some(p) = matches.value_of("program") {
             String::from(p)
         } else {
-            fail!("must specify program at command line, or in a file via -f");
+            fail!("must specify program at command line, or via -f or -e");
         }
     };
+    // Snippets from --begin and --end go at the end of the program, so that line numbers in error
+    // messages for the rest of the program are unchanged.
+    for (flag, block) in [("begin", "BEGIN"), ("end", "END")] {
+        for code in matches.values_of(flag).into_iter().flatten() {
+            program_string.push_str(&format!("\n{} {{\n{}\n}}\n", block, code));
+        }
+    }
+    // Separate assignment operands from input files. Each assignment is made when reading reaches
+    // the file that follows it, identified by its ARGIND (or right away when reading standard
+    // input, where ARGIND stays 0).
+    let mut file_assigns = Vec::new();
+    let mut files = Vec::with_capacity(input_files.len());
+    for f in input_files.drain(..) {
+        if is_assignment_operand(&f) {
+            file_assigns.push((files.len() as runtime::Int + 1, f));
+        } else {
+            files.push(f);
+        }
+    }
+    if files.is_empty() {
+        for (ix, _) in file_assigns.iter_mut() {
+            *ix = 0;
+        }
+    }
+    input_files = files;
+    // Build ARGV from the final list of input files, so that ARGV[ARGIND] is always the file
+    // currently being read.
+    let argv: Vec<String> = std::env::args()
+        .next()
+        .into_iter()
+        .chain(input_files.iter().cloned())
+        .collect();
+    let serve_addr = matches.value_of("serve");
+    if serve_addr.is_some() && !input_files.is_empty() {
+        fail!("--serve reads its input from clients, and cannot be given input files");
+    }
     let (escaper, output_sep, output_record_sep) = match matches.value_of("output-format") {
-        Some("csv") => (Escaper::CSV, Some(","), Some("\r\n")),
-        Some("tsv") => (Escaper::TSV, Some("\t"), Some("\n")),
+        None if matches.is_present("ojson") => (Escaper::JSON, None, None),
+        None if matches.is_present("omsgpack") => {
+            if !cfg!(feature = "msgpack") {
+                fail!("--omsgpack requires frawk to be built with the msgpack feature");
+            }
+            (Escaper::MsgPack, None, None)
+        }
+        Some("csv") => (
+            Escaper::CSV,
+            Some((csv_dialect.sep as char).to_string()),
+            Some("\r\n"),
+        ),
+        Some("tsv") => (Escaper::TSV, Some("\t".into()), Some("\n")),
         Some(s) => fail!(
             "invalid output format {:?}; expected csv or tsv (or the empty string)",
             s
@@ -488,6 +995,32 @@ fn main() {
     };
     let arbitrary_shell = matches.is_present("arbitrary-shell");
     let parse_header = matches.is_present("parse-header");
+    let traditional = matches.is_present("traditional");
+    let asserts = !matches.is_present("disable-asserts");
+    let mem_profile = matches.is_present("mem-profile");
+    let dump_on_signal = matches.is_present("dump-on-signal");
+    #[cfg(any(feature = "llvm_backend", feature = "cranelift_backend"))]
+    let perf_map = matches.is_present("perf-map");
+    let debug_info = matches.is_present("debug-info");
+    let strict = matches.is_present("strict");
+    let zip = matches.is_present("zip");
+    let checkpoint_interval = match matches.value_of("checkpoint-interval") {
+        Some(n) => match n.parse::<f64>() {
+            Ok(n) if n > 0.0 && n.is_finite() => Duration::from_secs_f64(n),
+            _ => fail!(
+                "--checkpoint-interval must be a positive number of seconds, got {:?}",
+                n
+            ),
+        },
+        None => Duration::from_secs(60),
+    };
+    let checkpoint = matches.value_of("checkpoint").map(PathBuf::from);
+    let checkpoints = runtime::checkpoint::Checkpoint::default();
+    let resume_nr = matches.value_of("resume").map(|path| {
+        checkpoints
+            .load(Path::new(path))
+            .unwrap_or_else(|e| fail!("failed to resume from {}: {}", path, e))
+    });
 
     let opt_level: i32 = match matches.value_of("opt-level") {
         Some("3") => 3,
@@ -504,13 +1037,27 @@ fn main() {
             .values_of("var")
             .map(|x| x.map(String::from).collect())
             .unwrap_or_else(Vec::new),
+        file_assigns,
+        const_decs: matches
+            .values_of("define")
+            .map(|x| x.map(String::from).collect())
+            .unwrap_or_else(Vec::new),
         output_sep,
         scalars: PreludeScalars {
             escaper,
             arbitrary_shell,
             fold_regexes: opt_level >= 3,
+            ieee_div: matches.is_present("ieee-div"),
             stage: exec_strategy.stage(),
             parse_header,
+            traditional,
+            asserts,
+            mem_profile,
+            dump_on_signal,
+            debug_info,
+            strict,
+            checkpoint: checkpoint.is_some(),
+            resume_nr,
         },
         output_record_sep,
         argv,
@@ -524,6 +1071,8 @@ fn main() {
                 let config = codegen::Config {
                     opt_level: if opt_level < 0 { 3 } else { opt_level as usize },
                     num_workers,
+                    perf_map: false,
+                    debug_info,
                 };
                 let _ = write!(
                     std::io::stdout(),
@@ -552,138 +1101,353 @@ fn main() {
     if skip_output {
         return;
     }
+    let mut config = runtime::Config::default();
+    if let Some(path) = checkpoint {
+        checkpoints.enable(path, checkpoint_interval);
+    }
+    config.checkpoint = checkpoints;
+    config.mem_profile = mem_profile;
+    config.pinning = match matches.value_of("pin-workers") {
+        Some("cores") => runtime::affinity::Pinning::Cores,
+        Some("nodes") => runtime::affinity::Pinning::Nodes,
+        None => runtime::affinity::Pinning::None,
+        Some(x) => fail!("invalid value for pin-workers: {}", x),
+    };
+    config.keep_order = matches.is_present("keep-order");
+    config.serve = serve_addr.is_some();
+    config.fail_on_command_error = matches.is_present("fail-on-command-error");
+    config.strict = strict;
+    let on_limit = match matches.value_of("on-limit") {
+        Some("truncate") => runtime::splitter::limits::OnLimit::Truncate,
+        Some("error") | None => runtime::splitter::limits::OnLimit::Error,
+        Some(x) => fail!("invalid value for on-limit: {}", x),
+    };
+    config.limits = runtime::splitter::limits::Limits::new(
+        max_record_bytes,
+        max_fields,
+        on_limit,
+        &config.warnings,
+    );
+    for w in matches.values_of("warning").into_iter().flatten() {
+        let (name, enabled) = match w.strip_prefix("no-") {
+            Some(name) => (name, false),
+            None => (w, true),
+        };
+        match Warning::from_name(name) {
+            Some(w) => config.warnings.set_enabled(w, enabled),
+            None => fail!(
+                "unknown warning category {:?}; expected one of: {}",
+                name,
+                Warning::ALL
+                    .iter()
+                    .map(|w| w.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+    if matches.is_present("werror") {
+        config.warnings.enable_werror();
+    }
+    if matches.is_present("stats") {
+        config.warnings.enable_stats();
+    }
+    // Records processed before the checkpoint we are resuming from are skipped.
+    let skip = skip.unwrap_or(0) + resume_nr.unwrap_or(0) as usize;
+    config.record_range =
+        runtime::splitter::limits::RecordRange::new(skip, skip_file.unwrap_or(0), head);
+    config.stream_records = matches.is_present("stream-records");
+    config.decimal_comma = matches.is_present("decimal-comma");
+    config.csv_output = csv_dialect;
+    if dump_on_signal && matches!(exec_strategy.stage(), Stage::Main(_)) {
+        // The main loop calls dump_state() after receiving SIGUSR1; see Prog::desugar_stage.
+        runtime::install_dump_handler();
+    }
     let check_utf8 = matches.is_present("utf8");
+    config.utf8_chars = check_utf8;
     let signal = CancelSignal::default();
+    let a = Arena::default();
+    let mut ctx = get_context(program_string.as_str(), &a, get_prelude(&a, &raw));
+    let limits = config.limits.clone();
+    let warnings = config.warnings.clone();
+    let stream_records = config.stream_records;
+    ctx.config = config;
+    let analysis_result = ctx.analyze_sep_assignments();
+    let file_hooks = ctx.file_hooks;
+    if file_hooks && zip {
+        // File hooks rely on each file's records being consecutive.
+        fail!("BEGINFILE and ENDFILE cannot be used with --zip");
+    }
 
     // This horrid macro is here because all of the different ways of reading input are different
     // types, making functions hard to write. Still, there must be something to be done to clean
     // this up here.
     macro_rules! with_inp {
         ($analysis:expr, $inp:ident, $body:expr) => {{
-            if input_files.len() == 0 {
-                let _reader: Box<dyn io::Read + Send> = Box::new(io::stdin());
+            // Whether ChainedReader needs a separate reader for each file: it interleaves them
+            // with --zip, and stops between them for BEGINFILE and ENDFILE.
+            let per_file = zip || file_hooks;
+            if let Some(addr) = serve_addr {
+                let $inp = chained(
+                    SocketReader::bind(addr)
+                        .unwrap_or_else(|e| fail!("failed to listen on {}: {}", addr, e)),
+                )
+                .zip(zip)
+                .file_hooks(file_hooks);
+                $body
+            } else if input_files.iter().any(|f| is_sqlite_input(f)) {
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "sqlite")] {
+                        if !input_files.iter().all(|f| is_sqlite_input(f)) {
+                            fail!("sqlite inputs cannot be combined with other input files");
+                        }
+                        let readers: Vec<_> = input_files
+                            .iter()
+                            .map(|f| {
+                                SqliteReader::new(f.as_str(), check_utf8)
+                                    .unwrap_or_else(|e| fail!(status = EXIT_RUNTIME; "{}", e))
+                            })
+                            .collect();
+                        let $inp = ChainedReader::new(readers.into_iter())
+                            .zip(zip)
+                            .file_hooks(file_hooks);
+                        $body
+                    } else {
+                        fail!("sqlite inputs require frawk to be built with the sqlite feature");
+                    }
+                }
+            } else if let Some(selector) = &xml_selector {
+                let $inp = if input_files.len() == 0 {
+                    chained(XmlReader::new("-", selector.clone(), check_utf8))
+                        .zip(zip)
+                        .file_hooks(file_hooks)
+                } else {
+                    ChainedReader::new(
+                        input_files
+                            .iter()
+                            .map(|f| XmlReader::new(f.as_str(), selector.clone(), check_utf8)),
+                    )
+                    .zip(zip)
+                    .file_hooks(file_hooks)
+                };
+                $body
+            } else if let Some(n) = record_bytes {
+                let $inp = if input_files.len() == 0 {
+                    let reader: Box<dyn io::Read + Send> = Box::new(io::stdin());
+                    chained(BinaryReader::new(reader, n, "-"))
+                        .zip(zip)
+                        .file_hooks(file_hooks)
+                } else {
+                    ChainedReader::new(input_files.iter().cloned().map(|file| {
+                        let reader: Box<dyn io::Read + Send> = Box::new(open_file_read(&file));
+                        BinaryReader::new(reader, n, file)
+                    }))
+                    .zip(zip)
+                    .file_hooks(file_hooks)
+                };
+                $body
+            } else if arrow_input {
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "arrow")] {
+                        let $inp = if input_files.len() == 0 {
+                            ChainedReader::new(once(ArrowReader::new("-", check_utf8)))
+                                .zip(zip)
+                                .file_hooks(file_hooks)
+                        } else {
+                            ChainedReader::new(
+                                input_files
+                                    .iter()
+                                    .map(|f| ArrowReader::new(f.as_str(), check_utf8)),
+                            )
+                            .zip(zip)
+                            .file_hooks(file_hooks)
+                        };
+                        $body
+                    } else {
+                        fail!("-i arrow requires frawk to be built with the arrow feature");
+                    }
+                }
+            } else if avro_input {
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "avro")] {
+                        // With -H, field names are read from the schema of the first input.
+                        let $inp = if input_files.len() == 0 {
+                            let stdin = AvroReader::new("-", parse_header, check_utf8);
+                            ChainedReader::new(once(stdin)).zip(zip).file_hooks(file_hooks)
+                        } else {
+                            ChainedReader::new(input_files.iter().enumerate().map(|(i, f)| {
+                                AvroReader::new(f.as_str(), parse_header && i == 0, check_utf8)
+                            }))
+                            .zip(zip)
+                            .file_hooks(file_hooks)
+                        };
+                        $body
+                    } else {
+                        fail!("-i avro requires frawk to be built with the avro feature");
+                    }
+                }
+            } else if json_input {
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "json")] {
+                        // With -H, field names are read from the first object of the first input.
+                        let $inp = if input_files.len() == 0 {
+                            let stdin = JsonReader::new("-", parse_header, check_utf8);
+                            ChainedReader::new(once(stdin)).zip(zip).file_hooks(file_hooks)
+                        } else {
+                            ChainedReader::new(input_files.iter().enumerate().map(|(i, f)| {
+                                JsonReader::new(f.as_str(), parse_header && i == 0, check_utf8)
+                            }))
+                            .zip(zip)
+                            .file_hooks(file_hooks)
+                        };
+                        $body
+                    } else {
+                        fail!("-i json requires frawk to be built with the json feature");
+                    }
+                }
+            } else if auto_input {
+                let $inp =
+                    if input_files.len() == 0 {
+                        chained(AutoReader::new("-", csv_dialect, check_utf8, &warnings))
+                            .zip(zip)
+                            .file_hooks(file_hooks)
+                    } else {
+                        ChainedReader::new(input_files.iter().map(|f| {
+                            AutoReader::new(f.as_str(), csv_dialect, check_utf8, &warnings)
+                        }))
+                        .zip(zip)
+                        .file_hooks(file_hooks)
+                    };
+                $body
+            } else {
+                // Outside of parallel mode, and without --zip, each input is opened from ARGV as
+                // it is reached, so that the program can skip inputs or add new ones.
+                let argv_inputs = matches!(exec_strategy, ExecutionStrategy::Serial) && !zip;
+                let limits = limits.clone();
+                let signal = signal.clone();
                 match (ifmt, $analysis) {
+                    // File hooks are handled by ChainedReader.
+                    (Some(ifmt), _) if per_file => {
+                        let csv = move |(reader, file): (Box<dyn io::Read + Send>, String)| {
+                            let csv = CSVReader::new(
+                                once((reader, file.clone())),
+                                ifmt,
+                                chunk_size,
+                                check_utf8,
+                                limits.clone(),
+                                exec_strategy,
+                                signal.clone(),
+                            );
+                            (csv, file)
+                        };
+                        let $inp = if argv_inputs {
+                            ArgvReader::new(check_utf8, file_hooks, move |f| {
+                                ChainedReader::with_names(once(csv(open_input(f))))
+                                    .file_hooks(file_hooks)
+                            })
+                        } else {
+                            ArgvReader::fixed(
+                                ChainedReader::with_names(open_inputs(&input_files).map(csv))
+                                    .zip(zip)
+                                    .file_hooks(file_hooks),
+                            )
+                        };
+                        $body
+                    }
                     (Some(ifmt), _) => {
-                        let $inp = CSVReader::new(
-                            once((_reader, String::from("-"))),
-                            ifmt,
-                            chunk_size,
-                            check_utf8,
-                            exec_strategy,
-                            signal.clone(),
-                        );
+                        let csv = move |inputs: Inputs| {
+                            CSVReader::new(
+                                inputs,
+                                ifmt,
+                                chunk_size,
+                                check_utf8,
+                                limits.clone(),
+                                exec_strategy,
+                                signal.clone(),
+                            )
+                        };
+                        let $inp = if argv_inputs {
+                            ArgvReader::new(check_utf8, file_hooks, move |f| {
+                                csv(Box::new(once(open_input(f))))
+                            })
+                        } else {
+                            ArgvReader::fixed(csv(Box::new(open_inputs(&input_files))))
+                        };
                         $body
                     }
+                    // Zipped inputs, and inputs with file hooks, are read through ChainedReader,
+                    // below.
                     (
                         None,
                         cfg::SepAssign::Potential {
                             field_sep,
                             record_sep,
                         },
-                    ) => {
-                        let field_sep = field_sep.unwrap_or(b" ");
-                        let record_sep = record_sep.unwrap_or(b"\n");
-                        if field_sep.len() == 1 && record_sep.len() == 1 {
-                            if field_sep == b" " && record_sep == b"\n" {
-                                let $inp = ByteReader::new_whitespace(
-                                    once((_reader, String::from("-"))),
+                    ) if field_sep.map_or(1, <[u8]>::len) == 1
+                        && record_sep.map_or(1, <[u8]>::len) == 1
+                        && !per_file =>
+                    {
+                        let field_sep = field_sep.map_or(b' ', |s| s[0]);
+                        let record_sep = record_sep.map_or(b'\n', |s| s[0]);
+                        if field_sep == b' ' && record_sep == b'\n' {
+                            let bytes = move |inputs: Inputs| {
+                                ByteReader::new_whitespace(
+                                    inputs,
                                     chunk_size,
                                     check_utf8,
+                                    limits.clone(),
+                                    stream_records,
                                     exec_strategy,
                                     signal.clone(),
-                                );
-                                $body
+                                )
+                            };
+                            let $inp = if argv_inputs {
+                                ArgvReader::new(check_utf8, file_hooks, move |f| {
+                                    bytes(Box::new(once(open_input(f))))
+                                })
                             } else {
-                                let $inp = ByteReader::new(
-                                    once((io::stdin(), String::from("-"))),
-                                    field_sep[0],
-                                    record_sep[0],
-                                    chunk_size,
-                                    check_utf8,
-                                    exec_strategy,
-                                    signal.clone(),
-                                );
-                                $body
-                            }
-                        } else {
-                            let $inp =
-                                chained(RegexSplitter::new(_reader, chunk_size, "-", check_utf8));
+                                ArgvReader::fixed(bytes(Box::new(open_inputs(&input_files))))
+                            };
                             $body
-                        }
-                    }
-                    (None, cfg::SepAssign::Unsure) => {
-                        let $inp =
-                            chained(RegexSplitter::new(_reader, chunk_size, "-", check_utf8));
-                        $body
-                    }
-                }
-            } else if let Some(ifmt) = ifmt {
-                let file_handles: Vec<_> = input_files
-                    .iter()
-                    .cloned()
-                    .map(|file| (open_file_read(file.as_str()), file))
-                    .collect();
-                let $inp = CSVReader::new(
-                    file_handles.into_iter(),
-                    ifmt,
-                    chunk_size,
-                    check_utf8,
-                    exec_strategy,
-                    signal.clone(),
-                );
-                $body
-            } else {
-                match $analysis {
-                    cfg::SepAssign::Potential {
-                        field_sep,
-                        record_sep,
-                    } => {
-                        let field_sep = field_sep.unwrap_or(b" ");
-                        let record_sep = record_sep.unwrap_or(b"\n");
-                        if field_sep.len() == 1 && record_sep.len() == 1 {
-                            let file_handles: Vec<_> = input_files
-                                .iter()
-                                .cloned()
-                                .map(move |file| (open_file_read(file.as_str()), file))
-                                .collect();
-                            if field_sep == b" " && record_sep == b"\n" {
-                                let $inp = ByteReader::new_whitespace(
-                                    file_handles.into_iter(),
+                        } else {
+                            let bytes = move |inputs: Inputs| {
+                                ByteReader::new(
+                                    inputs,
+                                    field_sep,
+                                    record_sep,
                                     chunk_size,
                                     check_utf8,
+                                    limits.clone(),
+                                    stream_records,
                                     exec_strategy,
                                     signal.clone(),
-                                );
-                                $body
+                                )
+                            };
+                            let $inp = if argv_inputs {
+                                ArgvReader::new(check_utf8, file_hooks, move |f| {
+                                    bytes(Box::new(once(open_input(f))))
+                                })
                             } else {
-                                let $inp = ByteReader::new(
-                                    file_handles.into_iter(),
-                                    field_sep[0],
-                                    record_sep[0],
-                                    chunk_size,
-                                    check_utf8,
-                                    exec_strategy,
-                                    signal.clone(),
-                                );
-                                $body
-                            }
-                        } else {
-                            let iter = input_files.iter().cloned().map(|file| {
-                                let reader: Box<dyn io::Read + Send> =
-                                    Box::new(open_file_read(file.as_str()));
-                                RegexSplitter::new(reader, chunk_size, file, check_utf8)
-                            });
-                            let $inp = ChainedReader::new(iter);
+                                ArgvReader::fixed(bytes(Box::new(open_inputs(&input_files))))
+                            };
                             $body
                         }
                     }
-                    cfg::SepAssign::Unsure => {
-                        let iter = input_files.iter().cloned().map(|file| {
-                            let reader: Box<dyn io::Read + Send> =
-                                Box::new(open_file_read(file.as_str()));
-                            RegexSplitter::new(reader, chunk_size, file, check_utf8)
-                        });
-                        let $inp = ChainedReader::new(iter);
+                    (None, _) => {
+                        let regex = move |(reader, file): (Box<dyn io::Read + Send>, String)| {
+                            RegexSplitter::new(reader, chunk_size, file, check_utf8, limits.clone())
+                        };
+                        let $inp = if argv_inputs {
+                            ArgvReader::new(check_utf8, file_hooks, move |f| {
+                                chained(regex(open_input(f))).file_hooks(file_hooks)
+                            })
+                        } else {
+                            ArgvReader::fixed(
+                                ChainedReader::new(open_inputs(&input_files).map(regex))
+                                    .zip(zip)
+                                    .file_hooks(file_hooks),
+                            )
+                        };
                         $body
                     }
                 }
@@ -691,16 +1455,15 @@ fn main() {
         }};
     }
 
-    let a = Arena::default();
-    let ctx = get_context(program_string.as_str(), &a, get_prelude(&a, &raw));
-    let analysis_result = ctx.analyze_sep_assignments();
     let out_file = matches.value_of("out-file");
     macro_rules! with_io {
         (|$inp:ident, $out:ident| $body:expr) => {
             match out_file {
                 Some(oup) => {
                     let $out = runtime::writers::factory_from_file(oup)
-                        .unwrap_or_else(|e| fail!("failed to open {}: {}", oup, e));
+                        .unwrap_or_else(|e| {
+                            fail!(status = EXIT_RUNTIME; "failed to open {}: {}", oup, e)
+                        });
                     with_inp!(analysis_result, $inp, $body);
                 }
                 None => {
@@ -721,6 +1484,8 @@ fn main() {
                             codegen::Config {
                                 opt_level: opt_level as usize,
                                 num_workers,
+                                perf_map,
+                                debug_info,
                             },
                             signal,
                     ));
@@ -732,6 +1497,13 @@ fn main() {
         Some("interp") => {
             with_io!(|inp, oup| run_interp_with_context(ctx, inp, oup, num_workers))
         }
+        #[cfg(not(feature = "cranelift_backend"))]
+        None => with_io!(|inp, oup| run_interp_with_context(ctx, inp, oup, num_workers)),
+        #[cfg(not(feature = "cranelift_backend"))]
+        Some("cranelift") => {
+            fail!("backend specified as cranelift, but compiled without cranelift support");
+        }
+        #[cfg(feature = "cranelift_backend")]
         None | Some("cranelift") => {
             with_io!(|inp, oup| run_cranelift_with_context(
                 ctx,
@@ -740,6 +1512,8 @@ fn main() {
                 codegen::Config {
                     opt_level: opt_level as usize,
                     num_workers,
+                    perf_map,
+                    debug_info,
                 },
                 signal,
             ));
@@ -748,4 +1522,7 @@ fn main() {
             fail!("invalid backend: {:?}", b);
         }
     }
+    if mem_profile {
+        runtime::report_alloc_stats();
+    }
 }
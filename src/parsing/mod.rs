@@ -1,3 +1,223 @@
-use lalrpop_util::lalrpop_mod;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use hashbrown::HashSet;
+use lalrpop_util::{lalrpop_mod, ParseError};
+
+use crate::arena::Arena;
+use crate::ast::Prog;
+use crate::common::Result;
+use crate::lexer::{self, Loc, Tok, Tokenizer};
 
 lalrpop_mod!(pub syntax);
+
+// `asorti(src, dest, "cmp")`, where `cmp` is a user-defined function, is lowered to a call to a
+// copy of this function (with `CMP` replaced by `cmp`), which merge sorts the keys of `src` by
+// calling `cmp(k1, v1, k2, v2)`. As in gawk, a negative return value means that `k1` comes first.
+// The sort is stable, so keys that compare equal stay in the order of their string values. A copy
+// is added for each function of at least four parameters whose name appears as a string constant;
+// copies that are not called are never instantiated.
+const SORT_HELPER: &str = r#"
+function sort_helper(src, dest,    n, width, lo, mid, hi, i, j, o, tmp) {
+    n = asorti(src, dest)
+    for (width = 1; width < n; width *= 2) {
+        for (lo = 1; lo <= n; lo += 2 * width) {
+            mid = lo + width
+            if (mid > n + 1) mid = n + 1
+            hi = lo + 2 * width
+            if (hi > n + 1) hi = n + 1
+            i = lo; j = mid; o = lo
+            while (i < mid && j < hi) {
+                if (CMP(dest[j], src[dest[j]], dest[i], src[dest[i]]) < 0) {
+                    tmp[o++] = dest[j++]
+                } else {
+                    tmp[o++] = dest[i++]
+                }
+            }
+            while (i < mid) tmp[o++] = dest[i++]
+            while (j < hi) tmp[o++] = dest[j++]
+        }
+        for (i = 1; i <= n; i++) dest[i] = tmp[i]
+    }
+    return n
+}
+"#;
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn sort_helper_name<'a>(arena: &'a Arena, cmp: &str) -> &'a str {
+    // Helpers are named with an illegal frawk identifier, so they cannot clash with user functions.
+    arena.alloc_str(&format!("--asorti-{}", cmp))
+}
+
+// `@f(args)` calls the user-defined function named by the value of `f`. Functions are resolved
+// ahead of time, so each such call with n arguments is rewritten to a call to a dispatcher taking
+// the name and n arguments, which calls whichever function of at least n parameters has that
+// name. To keep the arguments of unrelated functions from sharing types, dispatchers only consider
+// functions whose names appear as string constants in the program.
+fn call_dispatcher_name<'a>(arena: &'a Arena, nargs: usize) -> &'a str {
+    arena.alloc_str(&format!("--call-{}", nargs))
+}
+
+/// Record that `prog` makes an indirect call with `nargs` arguments, and return the name of the
+/// dispatcher for it.
+pub(crate) fn indirect_call<'a>(
+    arena: &'a Arena,
+    prog: &mut Prog<'a, 'a, &'a str>,
+    nargs: usize,
+) -> &'a str {
+    if !prog.indirect_calls.contains(&nargs) {
+        prog.indirect_calls.push(nargs);
+    }
+    call_dispatcher_name(arena, nargs)
+}
+
+/// Record the string constant `s` if it could name a function called through `@f(args)`.
+pub(crate) fn string_constant<'a>(prog: &mut Prog<'a, 'a, &'a str>, s: &'a str) {
+    if is_ident(s) && !prog.function_names.contains(&s) {
+        prog.function_names.push(s);
+    }
+}
+
+// Directories searched for `@include`d files when AWKPATH is not set, as in gawk.
+const DEFAULT_AWKPATH: &str = ".:/usr/local/share/awk";
+
+// Find the file named by `@include "name"`. As in gawk, names containing a `/` are used as-is,
+// other names are looked up in each directory of AWKPATH in turn, and a name that is not found is
+// tried again with a `.awk` suffix.
+fn include_path(name: &str) -> Option<PathBuf> {
+    let search = |name: &str| {
+        if name.contains('/') {
+            let path = PathBuf::from(name);
+            return if path.is_file() { Some(path) } else { None };
+        }
+        let awkpath = env::var("AWKPATH").unwrap_or_else(|_| DEFAULT_AWKPATH.into());
+        awkpath
+            .split(':')
+            .map(|dir| Path::new(if dir.is_empty() { "." } else { dir }).join(name))
+            .find(|path| path.is_file())
+    };
+    search(name).or_else(|| search(&format!("{}.awk", name)))
+}
+
+// Parse the file name out of an `@include` directive, given the text following `@include`.
+fn include_name(rest: &str) -> Option<&str> {
+    let rest = rest.trim_start().strip_prefix('"')?;
+    let end = rest.find('"')?;
+    let tail = rest[end + 1..].trim_start();
+    let tail = tail.strip_prefix(';').unwrap_or(tail).trim_start();
+    if tail.is_empty() || tail.starts_with('#') {
+        Some(&rest[..end])
+    } else {
+        None
+    }
+}
+
+fn expand_includes_into(src: &str, seen: &mut HashSet<PathBuf>, out: &mut String) -> Result<()> {
+    for line in src.split_inclusive('\n') {
+        let rest = match line.trim_start().strip_prefix("@include") {
+            Some(rest) if !rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') => {
+                rest
+            }
+            _ => {
+                out.push_str(line);
+                continue;
+            }
+        };
+        let name = match include_name(rest) {
+            Some(name) => name,
+            None => return user_err!("malformed @include directive: {}", line.trim()),
+        };
+        let path = match include_path(name) {
+            Some(path) => path,
+            None => return user_err!("cannot find @include file {}", name),
+        };
+        // Each file is included once, so that libraries can include one another freely.
+        if seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) => {
+                    return user_err!("failed to read @include file {}: {}", path.display(), e)
+                }
+            };
+            expand_includes_into(&text, seen, out)?;
+        }
+        out.push('\n');
+    }
+    Ok(())
+}
+
+/// Replace each `@include "file"` line in `src` with the contents of that file, as in gawk, so
+/// that function libraries can be shared between programs. Included files may themselves use
+/// `@include`; each file is only included once.
+pub(crate) fn expand_includes(src: &str) -> Result<String> {
+    let mut out = String::with_capacity(src.len());
+    expand_includes_into(src, &mut HashSet::new(), &mut out)?;
+    Ok(out)
+}
+
+/// An error in a type annotation, at `location`.
+pub(crate) fn type_error<'a>(
+    location: Loc,
+    desc: &'static str,
+) -> ParseError<Loc, Tok<'a>, lexer::Error> {
+    ParseError::User {
+        error: lexer::Error { location, desc },
+    }
+}
+
+/// Add the sort helpers for functions named in string constants, and the call dispatchers recorded
+/// by `indirect_call`, to `prog`. This is called once the rest of the program has been parsed.
+pub(crate) fn add_helpers<'a>(
+    arena: &'a Arena,
+    buf: &mut Vec<u8>,
+    prog: &mut Prog<'a, 'a, &'a str>,
+) -> Result<()> {
+    let parser = syntax::ProgParser::new();
+    let comparators: Vec<_> = if prog.traditional {
+        Vec::new()
+    } else {
+        prog.decs
+            .iter()
+            .filter(|dec| dec.args.len() >= 4 && prog.function_names.contains(&dec.name))
+            .map(|dec| dec.name)
+            .collect()
+    };
+    for cmp in comparators {
+        let helper = arena.alloc_str(&SORT_HELPER.replace("CMP(", &format!("{}(", cmp)));
+        if let Err(e) = parser.parse(arena, buf, prog, Tokenizer::new(helper)) {
+            return err!("failed to parse the sort helper for {}: {}", cmp, e);
+        }
+        let name = sort_helper_name(arena, cmp);
+        prog.decs.last_mut().unwrap().name = name;
+        prog.sort_helpers.push((cmp, name));
+    }
+    for nargs in std::mem::take(&mut prog.indirect_calls) {
+        let args: Vec<_> = (1..=nargs).map(|i| format!(", arg{}", i)).collect();
+        let args = args.concat();
+        let mut dispatcher = format!("function dispatcher(name{}) {{\n", args);
+        for dec in prog.decs.iter() {
+            if dec.args.len() >= nargs && prog.function_names.contains(&dec.name) {
+                dispatcher.push_str(&format!(
+                    "    if (name == \"{0}\") return {0}({1})\n",
+                    dec.name,
+                    args.trim_start_matches(", ")
+                ));
+            }
+        }
+        dispatcher.push_str(
+            "    print \"frawk: call to undefined function \" name > \"/dev/stderr\"\n    exit 2\n}\n",
+        );
+        let dispatcher = arena.alloc_str(&dispatcher);
+        if let Err(e) = parser.parse(arena, buf, prog, Tokenizer::new(dispatcher)) {
+            return err!("failed to parse the dispatcher for indirect calls: {}", e);
+        }
+        prog.decs.last_mut().unwrap().name = call_dispatcher_name(arena, nargs);
+    }
+    Ok(())
+}
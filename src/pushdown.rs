@@ -246,6 +246,12 @@ pub struct UsedFieldAnalysis {
     // the variables in question.  We can always add it in the future, but since join nodes are
     // always "leaves" we will just add the missing columns as a postprocessing step.
     joins: Vec<(Key /*lhs*/, Key /*rhs*/)>,
+    // Whether the program re-splits $0 (via `resplit`) or substitutes into it, which requires all
+    // of $0.
+    resplits: bool,
+    // The registers holding the columns assigned to. Assigning to a field and then reading $0
+    // rebuilds $0 from all of the fields.
+    set_cols: Vec<Key>,
 }
 
 impl Default for UsedFieldAnalysis {
@@ -253,6 +259,8 @@ impl Default for UsedFieldAnalysis {
         let mut res = UsedFieldAnalysis {
             dfa: Default::default(),
             joins: Default::default(),
+            resplits: false,
+            set_cols: Default::default(),
         };
         res.dfa.add_src(Key::Rng, FieldSet::all());
         res.dfa.add_src(Key::VarVal(Variable::FI), FieldSet::fi());
@@ -293,12 +301,26 @@ impl UsedFieldAnalysis {
             }
             JoinCSV(dst, start, end)
             | JoinTSV(dst, start, end)
+            | JoinJSON(dst, start, end)
+            | JoinMsgPack(dst, start, end)
             | JoinColumns(dst, start, end, _) => {
                 self.dfa.add_query(start);
                 self.dfa.add_query(end);
                 self.dfa.add_src(dst, FieldSet::all());
                 self.joins.push((start.into(), end.into()));
             }
+            SetColumn(col, _) => {
+                self.dfa.add_query(col);
+                self.set_cols.push(col.into());
+            }
+            Resplit(dst, _) => {
+                self.dfa.add_src(dst, FieldSet::all());
+                self.resplits = true;
+            }
+            SubLine(dst, _, _) | GSubLine(dst, _, _) => {
+                self.dfa.add_src(dst, FieldSet::all());
+                self.resplits = true;
+            }
             _ => dataflow::boilerplate::visit_ll(inst, |dst, _| {
                 self.dfa.add_src(dst, FieldSet::all())
             }),
@@ -314,6 +336,18 @@ impl UsedFieldAnalysis {
             l_flds.fill(r_flds);
             res.union(&l_flds);
         }
+        if self.resplits {
+            res.union(&FieldSet::singleton(0));
+        }
+        if res.get(0) {
+            let zero = FieldSet::singleton(0);
+            for col in self.set_cols.iter().cloned() {
+                let cols = self.dfa.query(col);
+                if !cols.is_empty() && cols != &zero {
+                    return FieldSet::all();
+                }
+            }
+        }
         res
     }
 }
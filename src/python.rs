@@ -0,0 +1,186 @@
+//! The `frawk` Python module, built on `embed`. The extension module itself is built from
+//! `python/`, with the `pyo3` feature.
+//!
+//! ```python
+//! import frawk
+//! prog = frawk.compile('{ n += $2; print $1, n }')
+//! for line in prog.run(["a 1", "b 2"]):
+//!     print(line)  # "a 1", then "b 3"
+//! ```
+//!
+//! `run` and `run_file` return an iterator over the lines the program prints to standard output,
+//! without their trailing newlines. Input is read lazily: each record is fed to the program as
+//! the output for the previous ones is consumed.
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyIterator;
+
+use crate::embed;
+
+/// Add the contents of the `frawk` module to `m`.
+pub fn init(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    m.add_class::<Program>()?;
+    m.add_class::<Run>()?;
+    Ok(())
+}
+
+/// Compile a frawk program. `vars` maps variable names to the (string) values they are assigned
+/// before BEGIN runs, as with frawk's -v. Raises ValueError if the program does not compile.
+#[pyfunction]
+#[pyo3(text_signature = "(program, vars=None)")]
+fn compile(program: &str, vars: Option<HashMap<String, String>>) -> PyResult<Program> {
+    let vars: Vec<String> = vars
+        .into_iter()
+        .flatten()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    let vars: Vec<&str> = vars.iter().map(String::as_str).collect();
+    embed::Program::compile(program, &vars)
+        .map(|prog| Program { prog })
+        .map_err(PyValueError::new_err)
+}
+
+/// A compiled frawk program.
+#[pyclass(module = "frawk")]
+struct Program {
+    prog: embed::Program,
+}
+
+#[pymethods]
+impl Program {
+    /// Run the program over `lines`, an iterable of strings, returning an iterator over the lines
+    /// it prints.
+    #[pyo3(text_signature = "($self, lines)")]
+    fn run(&self, lines: &PyAny) -> PyResult<Run> {
+        Ok(Run::new(&self.prog, Input::Lines(lines.iter()?.into())))
+    }
+
+    /// Run the program over the lines of the file at `path`, returning an iterator over the lines
+    /// it prints.
+    #[pyo3(text_signature = "($self, path)")]
+    fn run_file(&self, path: &str) -> PyResult<Run> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(Run::new(&self.prog, Input::File(file)))
+    }
+}
+
+enum Input {
+    Lines(Py<PyIterator>),
+    File(BufReader<File>),
+}
+
+impl Input {
+    // The next line of input, without its trailing newline.
+    fn next(&mut self, py: Python<'_>) -> PyResult<Option<Vec<u8>>> {
+        let mut line = match self {
+            Input::Lines(iter) => match Iterator::next(&mut iter.as_ref(py)) {
+                Some(line) => line?.extract::<&str>()?.as_bytes().to_vec(),
+                None => return Ok(None),
+            },
+            Input::File(file) => {
+                let mut line = Vec::new();
+                if file.read_until(b'\n', &mut line)? == 0 {
+                    return Ok(None);
+                }
+                line
+            }
+        };
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+}
+
+/// An iterator over the lines printed by a run of a program. A run executes on the thread that
+/// drives it, so it cannot be shared with other threads.
+#[pyclass(module = "frawk", unsendable)]
+struct Run {
+    run: embed::Run,
+    // None once the input has ended.
+    input: Option<Input>,
+    lines: VecDeque<String>,
+    // Output after the last newline.
+    partial: Vec<u8>,
+}
+
+impl Run {
+    fn new(prog: &embed::Program, input: Input) -> Run {
+        let mut run = Run {
+            run: prog.start(),
+            input: Some(input),
+            lines: VecDeque::new(),
+            partial: Vec::new(),
+        };
+        run.collect_output();
+        run
+    }
+
+    fn collect_output(&mut self) {
+        self.partial.extend_from_slice(self.run.output());
+        let mut start = 0;
+        while let Some(ix) = memchr::memchr(b'\n', &self.partial[start..]) {
+            let line = &self.partial[start..start + ix];
+            self.lines
+                .push_back(String::from_utf8_lossy(line).into_owned());
+            start += ix + 1;
+        }
+        self.partial.drain(..start);
+    }
+
+    fn finish(&mut self) -> PyResult<()> {
+        self.input = None;
+        let res = self.run.finish();
+        self.collect_output();
+        if !self.partial.is_empty() {
+            let line = String::from_utf8_lossy(&self.partial).into_owned();
+            self.lines.push_back(line);
+            self.partial.clear();
+        }
+        res.map(|_| ()).map_err(PyRuntimeError::new_err)
+    }
+}
+
+#[pymethods]
+impl Run {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Option<String>> {
+        let this = &mut *slf;
+        loop {
+            if let Some(line) = this.lines.pop_front() {
+                return Ok(Some(line));
+            }
+            let record = match this.input.as_mut() {
+                Some(input) => input.next(py)?,
+                None => return Ok(None),
+            };
+            let record = match record {
+                Some(record) => record,
+                None => {
+                    this.finish()?;
+                    continue;
+                }
+            };
+            let read = match this.run.feed(&record) {
+                Ok(read) => read,
+                Err(e) => {
+                    this.input = None;
+                    return Err(PyRuntimeError::new_err(e));
+                }
+            };
+            this.collect_output();
+            if !read {
+                // The program has exited.
+                this.finish()?;
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Placement of parallel worker threads on CPUs.
+//!
+//! When executing in parallel, frawk can pin each worker to a single core, or to the cores of a
+//! single NUMA node. Workers are pinned before they allocate their input buffers, so that with the
+//! usual first-touch allocation policy those buffers end up in memory local to the worker.
+//!
+//! Pinning is only supported on Linux; elsewhere it is a no-op.
+/// How worker threads are pinned to CPUs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Pinning {
+    /// Leave placement to the operating system. This is the default.
+    #[default]
+    None,
+    /// Pin each worker to a single core, in order.
+    Cores,
+    /// Pin each worker to all of the cores in a NUMA node, spreading workers across nodes.
+    Nodes,
+}
+
+lazy_static::lazy_static! {
+    // The CPUs available to the process, grouped by NUMA node. This is computed by the first
+    // thread to be pinned, before it pins itself, as a pinned thread reports fewer CPUs as
+    // available.
+    static ref NODES: Vec<Vec<usize>> = topology::nodes();
+}
+
+/// Pin the calling thread according to `pinning`. The thread running the main loop alongside the
+/// workers is worker 0, and the remaining workers are numbered from 1.
+pub(crate) fn pin_worker(pinning: Pinning, worker: usize) {
+    let cpus: Vec<usize> = match pinning {
+        Pinning::None => return,
+        Pinning::Cores => {
+            let all: Vec<usize> = NODES.iter().flatten().cloned().collect();
+            if all.is_empty() {
+                return;
+            }
+            vec![all[worker % all.len()]]
+        }
+        Pinning::Nodes => {
+            if NODES.is_empty() {
+                return;
+            }
+            NODES[worker % NODES.len()].clone()
+        }
+    };
+    // Pinning is best-effort: if it fails, the worker simply runs wherever the OS places it.
+    let _ = topology::pin_current_thread(&cpus);
+}
+
+/// Parse a Linux cpulist (e.g. "0-3,8,10-11") into the CPUs it contains.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_cpulist(s: &str) -> Option<Vec<usize>> {
+    let mut res = Vec::new();
+    for range in s.trim().split(',').filter(|r| !r.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let lo = bounds.next()?.parse::<usize>().ok()?;
+        let hi = match bounds.next() {
+            Some(hi) => hi.parse::<usize>().ok()?,
+            None => lo,
+        };
+        res.extend(lo..=hi);
+    }
+    Some(res)
+}
+
+#[cfg(target_os = "linux")]
+mod topology {
+    use std::fs;
+    use std::mem;
+
+    pub(super) fn nodes() -> Vec<Vec<usize>> {
+        let allowed = allowed_cpus();
+        let mut nodes = Vec::new();
+        if let Ok(dir) = fs::read_dir("/sys/devices/system/node") {
+            let mut entries: Vec<(usize, std::path::PathBuf)> = dir
+                .filter_map(|e| e.ok())
+                .filter_map(|e| {
+                    let name = e.file_name().into_string().ok()?;
+                    let id = name.strip_prefix("node")?.parse::<usize>().ok()?;
+                    Some((id, e.path()))
+                })
+                .collect();
+            entries.sort();
+            for (_, path) in entries {
+                let cpus = fs::read_to_string(path.join("cpulist"))
+                    .ok()
+                    .and_then(|s| super::parse_cpulist(&s))
+                    .unwrap_or_default();
+                let cpus: Vec<usize> = cpus.into_iter().filter(|c| allowed.contains(c)).collect();
+                if !cpus.is_empty() {
+                    nodes.push(cpus);
+                }
+            }
+        }
+        if nodes.is_empty() && !allowed.is_empty() {
+            // No NUMA information; treat the machine as a single node.
+            nodes.push(allowed);
+        }
+        nodes
+    }
+
+    fn allowed_cpus() -> Vec<usize> {
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            if libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+                return Vec::new();
+            }
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|cpu| libc::CPU_ISSET(*cpu, &set))
+                .collect()
+        }
+    }
+
+    pub(super) fn pin_current_thread(cpus: &[usize]) -> std::io::Result<()> {
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            libc::CPU_ZERO(&mut set);
+            for cpu in cpus {
+                libc::CPU_SET(*cpu, &mut set);
+            }
+            if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod topology {
+    pub(super) fn nodes() -> Vec<Vec<usize>> {
+        Vec::new()
+    }
+
+    pub(super) fn pin_current_thread(_cpus: &[usize]) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpulist() {
+        assert_eq!(
+            parse_cpulist("0-3,8,10-11\n"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpulist(""), Some(vec![]));
+        assert_eq!(parse_cpulist("0-x"), None);
+    }
+}
@@ -0,0 +1,19 @@
+//! Division and modulo by zero.
+//!
+//! By default, as in POSIX AWK, dividing by zero with `/` or `%` is a fatal error: the divisor is
+//! checked before each division (see `Instr::CheckDivisor`). With `--ieee-div` (see
+//! `ProgramContext::ieee_div`), those checks are left out and division follows IEEE 754 instead:
+//! `1/0` is `inf`, `-1/0` is `-inf`, and `0/0` and `x%0` are NaN. `%` always works on floats in
+//! that mode (see `builtins`), so that a zero divisor gives NaN even when both operands are
+//! integers.
+use crate::runtime::Variables;
+
+/// The error to report when dividing by zero with `/`, or with `%` if `is_mod` is set.
+pub(crate) fn division_by_zero(is_mod: bool, vars: &Variables) -> String {
+    format!(
+        "division by zero attempted in `{}` (NR={}, FILENAME={:?})",
+        if is_mod { "%" } else { "/" },
+        vars.nr,
+        vars.filename.to_string()
+    )
+}
@@ -0,0 +1,75 @@
+//! Conversions between characters and their numeric codes, for the `chr` and `ord` builtins.
+//!
+//! By default these work on bytes. With `--utf8` (`Config::utf8_chars`), they work on Unicode code
+//! points instead.
+use std::convert::TryFrom;
+
+use crate::runtime::{Int, Str};
+
+/// The character with code `n`, or the empty string if there is no such character: `n` must be a
+/// byte value, or a Unicode scalar value if `utf8` is set.
+pub(crate) fn chr(n: Int, utf8: bool) -> Str<'static> {
+    let mut buf = [0u8; 4];
+    let bs: &[u8] = if utf8 {
+        match u32::try_from(n).ok().and_then(char::from_u32) {
+            Some(c) => c.encode_utf8(&mut buf).as_bytes(),
+            None => &[],
+        }
+    } else {
+        match u8::try_from(n) {
+            Ok(b) => {
+                buf[0] = b;
+                &buf[..1]
+            }
+            Err(_) => &[],
+        }
+    };
+    // Strings this short are stored inline, so the result does not borrow from `buf`.
+    Str::from(bs).unmoor()
+}
+
+/// The code of the first character in `s`, or 0 if `s` is empty. If `utf8` is set, a first byte
+/// that does not begin a valid UTF-8 sequence is returned as-is.
+pub(crate) fn ord(s: &[u8], utf8: bool) -> Int {
+    let first = match s.first() {
+        Some(b) => *b,
+        None => return 0,
+    };
+    if first < 0x80 || !utf8 {
+        return first as Int;
+    }
+    let prefix = &s[..s.len().min(4)];
+    let valid = match std::str::from_utf8(prefix) {
+        Ok(s) => s,
+        Err(e) => std::str::from_utf8(&prefix[..e.valid_up_to()]).unwrap(),
+    };
+    match valid.chars().next() {
+        Some(c) => c as Int,
+        None => first as Int,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_and_code_points() {
+        assert_eq!(chr(65, false), Str::from("A"));
+        assert_eq!(ord(b"ABC", false), 65);
+        assert_eq!(ord(b"", false), 0);
+        assert_eq!(chr(-1, false), Str::default());
+        assert_eq!(ord("é".as_bytes(), false), 0xc3);
+        assert_eq!(chr(0xe9, false).with_bytes(|bs| bs.to_vec()), vec![0xe9]);
+        assert_eq!(chr(256, false), Str::default());
+
+        assert_eq!(chr(0xe9, true), Str::from("é"));
+        assert_eq!(chr(0x1f600, true), Str::from("😀"));
+        assert_eq!(chr(0xd800, true), Str::default());
+        assert_eq!(chr(0x110000, true), Str::default());
+        assert_eq!(ord("é!".as_bytes(), true), 0xe9);
+        assert_eq!(ord("😀".as_bytes(), true), 0x1f600);
+        assert_eq!(ord(b"\xe9x", true), 0xe9);
+        assert_eq!(ord(b"A", true), 65);
+    }
+}
@@ -0,0 +1,279 @@
+//! `--checkpoint FILE` and `--resume FILE`: saving the program's global variables while it runs,
+//! so that a long-running aggregation can pick up where it left off after a restart.
+//!
+//! A checkpoint is written between records, once every `--checkpoint-interval` seconds, and once
+//! more when the program finishes. It holds every global scalar and map, along with NR. Resuming
+//! from it restores those variables before BEGIN runs (so that `-v` assignments still take
+//! precedence) and skips the first NR records of the input, which had already been processed.
+//!
+//! Checkpoints are text, one entry per line, with tabs, newlines, carriage returns and
+//! backslashes in names and values escaped:
+//!
+//! ```text
+//! frawk-checkpoint 1
+//! NR	1000
+//! s	NAME	VALUE
+//! m	NAME	KEY	VALUE
+//! ```
+//!
+//! They are written to a temporary file that is then renamed, so a crash while writing one leaves
+//! the previous checkpoint intact.
+//!
+//! The checkpoint settings and the checkpoint being resumed from belong to a single run of a
+//! program; see `Checkpoint`, which is part of its `runtime::Config`.
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use hashbrown::HashMap;
+
+use crate::runtime::{convert, Float, Int, SharedMap, Str};
+
+const HEADER: &[u8] = b"frawk-checkpoint 1\n";
+
+#[derive(Debug, Default)]
+struct State {
+    // Where to write checkpoints, if checkpointing is enabled.
+    path: Option<PathBuf>,
+    // The checkpoint currently being written.
+    pending: Vec<u8>,
+    // The contents of the checkpoint given to --resume.
+    scalars: HashMap<Vec<u8>, Vec<u8>>,
+    maps: HashMap<Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>>,
+}
+
+/// Where a run of a program writes its checkpoints, and the checkpoint it resumed from. Clones
+/// share their state.
+#[derive(Clone, Debug, Default)]
+pub struct Checkpoint(Arc<Shared>);
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    // Set every --checkpoint-interval seconds by a background thread; the main loop polls this
+    // once per record and writes a checkpoint if it is set.
+    due: AtomicBool,
+}
+
+fn escape(bs: &[u8], out: &mut Vec<u8>) {
+    for b in bs {
+        match b {
+            b'\\' => out.extend_from_slice(b"\\\\"),
+            b'\t' => out.extend_from_slice(b"\\t"),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\r' => out.extend_from_slice(b"\\r"),
+            b => out.push(*b),
+        }
+    }
+}
+
+fn unescape(bs: &[u8]) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(bs.len());
+    let mut iter = bs.iter();
+    while let Some(b) = iter.next() {
+        if *b != b'\\' {
+            res.push(*b);
+            continue;
+        }
+        res.push(match iter.next()? {
+            b'\\' => b'\\',
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'r' => b'\r',
+            _ => return None,
+        });
+    }
+    Some(res)
+}
+
+/// A map key or value that can be saved in a checkpoint, and restored from one.
+pub(crate) trait Saved: Sized {
+    fn save(&self, out: &mut Vec<u8>);
+    fn restore(bs: &[u8]) -> Self;
+}
+
+impl Saved for Int {
+    fn save(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(itoa::Buffer::new().format(*self).as_bytes());
+    }
+    fn restore(bs: &[u8]) -> Int {
+        convert::<&Str, Int>(&Str::from(bs))
+    }
+}
+
+impl Saved for Float {
+    fn save(&self, out: &mut Vec<u8>) {
+        Str::from(*self).with_bytes(|bs| out.extend_from_slice(bs));
+    }
+    fn restore(bs: &[u8]) -> Float {
+        // Checkpoints always use '.' as the decimal separator, even with --decimal-comma.
+        fast_float::parse(bs).unwrap_or_else(|_| convert::<&Str, Float>(&Str::from(bs)))
+    }
+}
+
+impl<'a> Saved for Str<'a> {
+    fn save(&self, out: &mut Vec<u8>) {
+        self.with_bytes(|bs| escape(bs, out));
+    }
+    fn restore(bs: &[u8]) -> Str<'a> {
+        Str::from(bs).unmoor().upcast()
+    }
+}
+
+impl Checkpoint {
+    /// Write checkpoints to `path` every `interval`, and when the program finishes.
+    pub fn enable(&self, path: PathBuf, interval: Duration) {
+        self.0.state.lock().unwrap().path = Some(path);
+        // The timer stops once the program is done with its checkpoints.
+        let shared = Arc::downgrade(&self.0);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match shared.upgrade() {
+                Some(shared) => shared.due.store(true, Ordering::Relaxed),
+                None => break,
+            }
+        });
+    }
+
+    pub(crate) fn take_checkpoint_request(&self) -> bool {
+        // Avoid a read-modify-write in the common case, as this is called for every record.
+        let due = &self.0.due;
+        due.load(Ordering::Relaxed) && due.swap(false, Ordering::Relaxed)
+    }
+
+    pub(crate) fn save_scalar(&self, name: &Str, val: &Str) {
+        let pending = &mut self.0.state.lock().unwrap().pending;
+        pending.extend_from_slice(b"s\t");
+        name.save(pending);
+        pending.push(b'\t');
+        val.save(pending);
+        pending.push(b'\n');
+    }
+
+    pub(crate) fn save_map<K: Saved + Hash + Eq, V: Saved>(
+        &self,
+        name: &Str,
+        map: &SharedMap<K, V>,
+    ) {
+        let pending = &mut self.0.state.lock().unwrap().pending;
+        map.iter(|entries| {
+            for (k, v) in entries {
+                pending.extend_from_slice(b"m\t");
+                name.save(pending);
+                pending.push(b'\t');
+                k.save(pending);
+                pending.push(b'\t');
+                v.save(pending);
+                pending.push(b'\n');
+            }
+        });
+    }
+
+    /// Write the variables saved since the last checkpoint to the checkpoint file, returning the
+    /// error to report if that fails.
+    pub(crate) fn write(&self, nr: Int) -> Result<(), String> {
+        let mut state = self.0.state.lock().unwrap();
+        let pending = std::mem::take(&mut state.pending);
+        let path = match &state.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut contents = Vec::with_capacity(pending.len() + HEADER.len() + 32);
+        contents.extend_from_slice(HEADER);
+        contents.extend_from_slice(b"NR\t");
+        nr.save(&mut contents);
+        contents.push(b'\n');
+        contents.extend_from_slice(&pending);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, &contents)
+            .and_then(|()| fs::rename(&tmp, path))
+            .map_err(|e| format!("failed to write checkpoint {}: {}", path.display(), e))
+    }
+
+    /// The saved value of the scalar `name`, or `cur` if it was not saved.
+    pub(crate) fn restore_scalar<T: Saved>(&self, name: &Str, cur: T) -> T {
+        let state = self.0.state.lock().unwrap();
+        name.with_bytes(|name| match state.scalars.get(name) {
+            Some(val) => T::restore(val),
+            None => cur,
+        })
+    }
+
+    /// Add the saved entries of the map `name` to `map`.
+    pub(crate) fn restore_map<K, V>(&self, name: &Str, map: &SharedMap<K, V>)
+    where
+        K: Saved + std::hash::Hash + Eq,
+        V: Saved,
+    {
+        let state = self.0.state.lock().unwrap();
+        name.with_bytes(|name| {
+            for (k, v) in state.maps.get(name).into_iter().flatten() {
+                map.insert(K::restore(k), V::restore(v));
+            }
+        })
+    }
+
+    /// Load the checkpoint in `path`, to restore its variables before BEGIN. Returns the number of
+    /// records that had been processed when it was written.
+    pub fn load(&self, path: &Path) -> io::Result<Int> {
+        let contents = fs::read(path)?;
+        let (nr, loaded) = parse(&contents)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a valid checkpoint"))?;
+        let mut state = self.0.state.lock().unwrap();
+        state.scalars = loaded.scalars;
+        state.maps = loaded.maps;
+        Ok(nr)
+    }
+}
+
+fn parse(contents: &[u8]) -> Option<(Int, State)> {
+    let body = contents.strip_prefix(HEADER)?;
+    let mut nr = 0;
+    let mut state = State::default();
+    for line in body.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+        let fields: Vec<&[u8]> = line.split(|b| *b == b'\t').collect();
+        match fields[..] {
+            [b"NR", n] => nr = std::str::from_utf8(n).ok()?.parse().ok()?,
+            [b"s", name, val] => {
+                state.scalars.insert(unescape(name)?, unescape(val)?);
+            }
+            [b"m", name, key, val] => state
+                .maps
+                .entry(unescape(name)?)
+                .or_insert_with(Vec::new)
+                .push((unescape(key)?, unescape(val)?)),
+            _ => return None,
+        }
+    }
+    Some((nr, state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut contents = HEADER.to_vec();
+        contents.extend_from_slice(b"NR\t42\ns\t");
+        escape(b"x", &mut contents);
+        contents.push(b'\t');
+        escape(b"a\tb\\c\nd", &mut contents);
+        contents.extend_from_slice(b"\nm\tm\t1\t2.5\n");
+        let (nr, state) = parse(&contents).unwrap();
+        assert_eq!(nr, 42);
+        assert_eq!(state.scalars[&b"x"[..]], b"a\tb\\c\nd");
+        assert_eq!(
+            state.maps[&b"m"[..]],
+            vec![(b"1".to_vec(), b"2.5".to_vec())]
+        );
+        assert!(parse(b"NR\t1\n").is_none());
+        assert!(parse(b"frawk-checkpoint 1\nq\t1\n").is_none());
+    }
+}
@@ -0,0 +1,50 @@
+//! Support for the `sleep`, `gettimeofday` and `monotonic` builtins.
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::runtime::Float;
+
+// Durations longer than this are truncated, rather than overflowing a `Duration`.
+const MAX_SLEEP_SECS: Float = u32::MAX as Float;
+
+lazy_static::lazy_static! {
+    static ref START: Instant = Instant::now();
+}
+
+/// Suspend the current thread for `secs` seconds. Negative and NaN durations return immediately.
+pub(crate) fn sleep(secs: Float) {
+    if secs > 0.0 {
+        thread::sleep(Duration::from_secs_f64(secs.min(MAX_SLEEP_SECS)));
+    }
+}
+
+/// The current wall-clock time, in (fractional) seconds since the Unix epoch.
+pub(crate) fn time_of_day() -> Float {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+/// Seconds elapsed since an unspecified starting point. Unlike `time_of_day`, this never goes
+/// backwards, so differences between calls measure durations reliably.
+pub(crate) fn monotonic() -> Float {
+    START.elapsed().as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_and_timers() {
+        let (wall, mono) = (time_of_day(), monotonic());
+        sleep(0.02);
+        sleep(-1.0);
+        sleep(Float::NAN);
+        assert!(monotonic() - mono >= 0.02);
+        assert!(time_of_day() > wall);
+        // Some time after 2020.
+        assert!(wall > 1.6e9);
+    }
+}
@@ -1,8 +1,106 @@
-use std::io;
-use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+use std::io::{self, Read};
+use std::mem;
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+
+use hashbrown::{HashMap, HashSet};
 
 use crate::runtime::Int;
 
+/// Commands started for reading or writing through a pipe that have yet to be waited on, along
+/// with the exit status of the last instance of each command that has been.
+#[derive(Default)]
+struct Children {
+    running: Vec<(Box<[u8]>, Child)>,
+    statuses: HashMap<Box<[u8]>, Int>,
+}
+
+lazy_static::lazy_static! {
+    static ref CHILDREN: Mutex<Children> = Default::default();
+    // Commands whose output is read through a pseudo-terminal; see `use_pty`.
+    static ref PTY_COMMANDS: Mutex<HashSet<Box<[u8]>>> = Default::default();
+}
+
+fn track(cmd: &[u8], child: Child) {
+    CHILDREN.lock().unwrap().running.push((cmd.into(), child));
+}
+
+fn exit_code(status: ExitStatus) -> Int {
+    if let Some(code) = status.code() {
+        return Int::from(code);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(sig) = status.signal() {
+            // Follow the shell's convention for commands killed by a signal.
+            return 128 + Int::from(sig);
+        }
+    }
+    1
+}
+
+// Wait for all running instances of `cmd` (or of every command, if `cmd` is None), returning
+// their exit statuses.
+fn wait_for(cmd: Option<&[u8]>) -> Vec<(Box<[u8]>, Int)> {
+    let children: Vec<_> = {
+        let mut children = CHILDREN.lock().unwrap();
+        let (matching, rest) = mem::take(&mut children.running)
+            .into_iter()
+            .partition(|(c, _)| cmd.map_or(true, |cmd| &**c == cmd));
+        children.running = rest;
+        matching
+    };
+    // Other threads may start commands in the meantime, so we wait without holding the lock.
+    let res: Vec<_> = children
+        .into_iter()
+        .map(|(c, mut child)| {
+            let code = child.wait().map_or(1, exit_code);
+            (c, code)
+        })
+        .collect();
+    let mut children = CHILDREN.lock().unwrap();
+    for (c, code) in res.iter() {
+        children.statuses.insert(c.clone(), *code);
+    }
+    res
+}
+
+/// Wait for every instance of `cmd` started so far, returning the exit status of the last one to
+/// have been started, or -1 if `cmd` has never been run. Callers must close any pipes to or from
+/// `cmd` first.
+pub(crate) fn command_status(cmd: &[u8]) -> Int {
+    wait_for(Some(cmd));
+    CHILDREN
+        .lock()
+        .unwrap()
+        .statuses
+        .get(cmd)
+        .cloned()
+        .unwrap_or(-1)
+}
+
+/// Wait for every outstanding command, once all pipes have been closed at the end of the
+/// program. With `fail_on_error` (`--fail-on-command-error`), a command exiting with a nonzero
+/// status is reported and the process exits with status `EXIT_RUNTIME`.
+pub(crate) fn wait_all_commands(fail_on_error: bool) {
+    let failed: Vec<_> = wait_for(None)
+        .into_iter()
+        .filter(|(_, code)| *code != 0)
+        .collect();
+    if failed.is_empty() || !fail_on_error {
+        return;
+    }
+    for (cmd, code) in failed.iter() {
+        eprintln_ignore!(
+            "command '{}' exited with status {}",
+            String::from_utf8_lossy(cmd),
+            code
+        );
+    }
+    std::process::exit(crate::common::EXIT_RUNTIME)
+}
+
 fn prepare_command(bs: &[u8]) -> io::Result<Command> {
     let prog = match std::str::from_utf8(bs) {
         Ok(s) => s,
@@ -36,11 +134,103 @@ pub fn run_command(bs: &[u8]) -> Int {
 pub fn command_for_write(bs: &[u8]) -> io::Result<ChildStdin> {
     let mut cmd = prepare_command(bs)?;
     let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::inherit()).spawn()?;
-    Ok(child.stdin.take().unwrap())
+    let stdin = child.stdin.take().unwrap();
+    track(bs, child);
+    Ok(stdin)
+}
+
+/// Run `cmd` with a pseudo-terminal as its standard output whenever it is started to be read
+/// from, for programs that only flush their output a line at a time when writing to a terminal.
+/// Returns 1 if pseudo-terminals are supported on this platform, and 0 otherwise.
+pub(crate) fn use_pty(cmd: &[u8]) -> Int {
+    if cfg!(unix) {
+        PTY_COMMANDS.lock().unwrap().insert(cmd.into());
+        1
+    } else {
+        0
+    }
+}
+
+/// The output of a command started with `command_for_read`.
+pub enum CommandOutput {
+    Pipe(ChildStdout),
+    #[cfg(unix)]
+    Pty(std::fs::File),
 }
 
-pub fn command_for_read(bs: &[u8]) -> io::Result<ChildStdout> {
+impl Read for CommandOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            CommandOutput::Pipe(p) => p.read(buf),
+            #[cfg(unix)]
+            CommandOutput::Pty(master) => match master.read(buf) {
+                // Reads from the master side of a pseudo-terminal fail with EIO, rather than
+                // returning 0, once every process has closed the other side.
+                Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+                res => res,
+            },
+        }
+    }
+}
+
+// Open a pseudo-terminal, returning its master and slave sides. The terminal is put in raw mode, so
+// output is not altered (e.g. by translating "\n" to "\r\n").
+#[cfg(unix)]
+fn open_pty() -> io::Result<(std::fs::File, std::fs::File)> {
+    use std::ffi::CStr;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    lazy_static::lazy_static! {
+        // ptsname is not thread-safe.
+        static ref PTSNAME: Mutex<()> = Mutex::new(());
+    }
+    fn check(rc: libc::c_int) -> io::Result<libc::c_int> {
+        if rc < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(rc)
+        }
+    }
+    unsafe {
+        let master = check(libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY))?;
+        let master = std::fs::File::from_raw_fd(master);
+        check(libc::grantpt(master.as_raw_fd()))?;
+        check(libc::unlockpt(master.as_raw_fd()))?;
+        let slave = {
+            let _guard = PTSNAME.lock().unwrap();
+            let name = libc::ptsname(master.as_raw_fd());
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(CStr::from_ptr(name).to_string_lossy().as_ref())?
+        };
+        let mut attrs: libc::termios = mem::zeroed();
+        check(libc::tcgetattr(slave.as_raw_fd(), &mut attrs))?;
+        libc::cfmakeraw(&mut attrs);
+        check(libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &attrs))?;
+        Ok((master, slave))
+    }
+}
+
+pub fn command_for_read(bs: &[u8]) -> io::Result<CommandOutput> {
     let mut cmd = prepare_command(bs)?;
-    let mut child = cmd.stdin(Stdio::inherit()).stdout(Stdio::piped()).spawn()?;
-    Ok(child.stdout.take().unwrap())
+    cmd.stdin(Stdio::inherit());
+    #[cfg(unix)]
+    {
+        if PTY_COMMANDS.lock().unwrap().contains(bs) {
+            let (master, slave) = open_pty()?;
+            let child = cmd.stdout(slave).spawn()?;
+            // Drop our copy of the slave side along with `cmd`, so that reads see the end of the
+            // output once the command exits.
+            mem::drop(cmd);
+            track(bs, child);
+            return Ok(CommandOutput::Pty(master));
+        }
+    }
+    let mut child = cmd.stdout(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take().unwrap();
+    track(bs, child);
+    Ok(CommandOutput::Pipe(stdout))
 }
@@ -0,0 +1,57 @@
+//! Settings for a single run of a program, chosen on the command line (or by a program using the
+//! `embed` API). They are stored in the program's `cfg::ProgramContext`, and handed to the
+//! runtime when the program starts; nothing here is kept in process-wide state, so programs run
+//! with different settings do not interfere with one another.
+use crate::runtime::{
+    affinity,
+    checkpoint::Checkpoint,
+    splitter::{
+        batch::CsvDialect,
+        limits::{Limits, RecordRange},
+    },
+    warnings::Warnings,
+};
+
+/// Settings for a single run of a program.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Write output to standard output in input order when executing in parallel
+    /// (`--keep-order`).
+    pub keep_order: bool,
+    /// Send output to standard output back to the client being served (`--serve`, and programs
+    /// run through the `embed` API); see `writers::set_client`.
+    pub serve: bool,
+    /// How parallel worker threads are placed on CPUs (`--pin-workers`).
+    pub pinning: affinity::Pinning,
+    /// Exit with an error if a command run through a pipe fails, once the program ends
+    /// (`--fail-on-command-error`).
+    pub fail_on_command_error: bool,
+    /// The separator and quote characters used for CSV output: with `-o csv`, and by `escape_csv`
+    /// and `join_csv`.
+    pub csv_output: CsvDialect,
+    /// The limits on the length of input records and their number of fields
+    /// (`--max-record-bytes`, `--max-fields` and `--on-limit`).
+    pub limits: Limits,
+    /// The records of the main input that are read at all (`--skip`, `--skip-file` and
+    /// `--head`).
+    pub record_range: RecordRange,
+    /// Stream records that do not fit in the input buffer (`--stream-records`); see
+    /// `splitter::stream`.
+    pub stream_records: bool,
+    /// Use ',' rather than '.' as the decimal separator when converting strings to numbers and
+    /// when formatting floating-point numbers with printf (`--decimal-comma`).
+    pub decimal_comma: bool,
+    /// Report lossy implicit conversions as errors (`--strict`); see `strict`.
+    pub strict: bool,
+    /// Treat characters as UTF-8-encoded code points rather than bytes in `chr` and `ord`
+    /// (`--utf8`).
+    pub utf8_chars: bool,
+    /// Where checkpoints are written (`--checkpoint`), and the checkpoint the program resumes
+    /// from (`--resume`).
+    pub checkpoint: Checkpoint,
+    /// Report memory usage rather than values when dumping the program's state
+    /// (`--mem-profile`).
+    pub mem_profile: bool,
+    /// Which warnings are printed, and whether they are errors (`-W` and `--werror`).
+    pub warnings: Warnings,
+}
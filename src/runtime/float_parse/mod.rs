@@ -1,12 +1,36 @@
 //! Fast float parser based on github.com/lemire/fast_double_parser, but adopted to support AWK
 //! semantics (no failures, just 0s and stopping early). Mistakes are surely my own.
-
 fn is_integer(c: u8) -> bool {
     matches!(c, b'0'..=b'9')
 }
 
-/// The simdjson repo has more optimizations to add for int parsing, but this is a big win over libc
-/// for the time being, if only because we do not have to copy `s` into a NUL-terminated
+/// Check if the 8 bytes in `val` (loaded little-endian) are all ASCII digits.
+///
+/// Taken from the simdjson/fast_float number parsers.
+#[inline(always)]
+fn is_made_of_eight_digits(val: u64) -> bool {
+    ((val & 0xF0F0_F0F0_F0F0_F0F0)
+        | (((val.wrapping_add(0x0606_0606_0606_0606)) & 0xF0F0_F0F0_F0F0_F0F0) >> 4))
+        == 0x3333_3333_3333_3333
+}
+
+/// Parse 8 ASCII digits (loaded little-endian into `val`) as a decimal integer, using a handful of
+/// multiplies rather than one per digit.
+#[inline(always)]
+fn parse_eight_digits(mut val: u64) -> u64 {
+    const MASK: u64 = 0x0000_00FF_0000_00FF;
+    const MUL1: u64 = 100 + (1_000_000 << 32);
+    const MUL2: u64 = 1 + (10_000 << 32);
+    val = val.wrapping_sub(0x3030_3030_3030_3030);
+    val = val.wrapping_mul(10).wrapping_add(val >> 8);
+    (((val & MASK).wrapping_mul(MUL1)).wrapping_add(((val >> 16) & MASK).wrapping_mul(MUL2))) >> 32
+}
+
+/// Integer parser for AWK-style conversions: we parse the longest prefix of `bs` that looks like
+/// an integer, returning 0 on overflow or if there is no such prefix.
+///
+/// Long runs of digits are consumed 8 bytes at a time using the SWAR tricks from simdjson, which
+/// is a big win over libc, if only because we do not have to copy `s` into a NUL-terminated
 /// representation.
 pub fn strtoi(bs: &[u8]) -> i64 {
     if bs.is_empty() {
@@ -14,8 +38,28 @@ pub fn strtoi(bs: &[u8]) -> i64 {
     }
     let neg = bs[0] == b'-';
     let off = if neg || bs[0] == b'+' { 1 } else { 0 };
+    let mut digits = &bs[off..];
     let mut i = 0i64;
-    for b in bs[off..].iter().cloned().take_while(|b| is_integer(*b)) {
+    while digits.len() >= 8 {
+        let mut chunk = [0u8; 8];
+        chunk.copy_from_slice(&digits[..8]);
+        let val = u64::from_le_bytes(chunk);
+        if !is_made_of_eight_digits(val) {
+            break;
+        }
+        let eight = parse_eight_digits(val) as i64;
+        i = if let Some(i) = i
+            .checked_mul(100_000_000)
+            .and_then(|i| i.checked_add(eight))
+        {
+            i
+        } else {
+            // overflow
+            return 0;
+        };
+        digits = &digits[8..];
+    }
+    for b in digits.iter().cloned().take_while(|b| is_integer(*b)) {
         let digit = (b - b'0') as i64;
         i = if let Some(i) = i.checked_mul(10).and_then(|i| i.checked_add(digit)) {
             i
@@ -75,6 +119,56 @@ pub fn strtod(bs: &[u8]) -> f64 {
     }
 }
 
+/// Parse `bs` as a number if all of it, apart from leading and trailing blanks, looks like a
+/// decimal number. These are POSIX awk's "numeric strings".
+pub fn strnum(bs: &[u8]) -> Option<f64> {
+    let start = bs.iter().position(|b| !b.is_ascii_whitespace())?;
+    let end = bs.iter().rposition(|b| !b.is_ascii_whitespace())? + 1;
+    let bs = &bs[start..end];
+    // Rule out "inf" and "nan", which fast_float accepts.
+    let unsigned = match bs {
+        [b'+' | b'-', rest @ ..] => rest,
+        _ => bs,
+    };
+    if !matches!(unsigned, [b'0'..=b'9', ..] | [b'.', b'0'..=b'9', ..]) {
+        return None;
+    }
+    match fast_float::parse_partial::<f64, _>(bs) {
+        Ok((f, len)) if len == bs.len() => Some(f),
+        _ => None,
+    }
+}
+
+/// Like `strtod`, but with ',' as the decimal separator if `decimal_comma` is set (with
+/// `--decimal-comma`).
+pub(crate) fn strtod_locale(bs: &[u8], decimal_comma: bool) -> f64 {
+    if decimal_comma {
+        strtod_comma(bs)
+    } else {
+        strtod(bs)
+    }
+}
+
+/// Like `strtod`, but with ',' as the decimal separator. A '.' ends the number.
+fn strtod_comma(bs: &[u8]) -> f64 {
+    let sep = match bs.iter().position(|b| matches!(b, b'.' | b',')) {
+        Some(ix) => ix,
+        None => return fast_float::parse_partial(bs).map_or(0.0, |(f, _)| f),
+    };
+    if bs[sep] == b'.' {
+        return fast_float::parse_partial(&bs[..sep]).map_or(0.0, |(f, _)| f);
+    }
+    // Copy out the number, up to anything that cannot be part of its fraction or exponent.
+    let frac = &bs[sep + 1..];
+    let len = frac
+        .iter()
+        .position(|b| !matches!(b, b'0'..=b'9' | b'e' | b'E' | b'+' | b'-'))
+        .unwrap_or(frac.len());
+    let mut buf = smallvec::SmallVec::<[u8; 64]>::from_slice(&bs[..sep + 1 + len]);
+    buf[sep] = b'.';
+    fast_float::parse_partial(&buf[..]).map_or(0.0, |(f, _)| f)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,4 +185,34 @@ mod tests {
         assert_eq!(strtod(imax.as_bytes()), i64::max_value() as f64);
         assert_eq!(strtod(imin.as_bytes()), i64::min_value() as f64);
     }
+
+    #[test]
+    fn decimal_comma_parsing() {
+        assert_eq!(strtod_comma(b"1,234"), 1.234);
+        assert_eq!(strtod_comma(b"-1,5e3 EUR"), -1500.0);
+        assert_eq!(strtod_comma(b"1.234,5"), 1.0);
+        assert_eq!(strtod_comma(b"2,5.1"), 2.5);
+        assert_eq!(strtod_comma(b"42"), 42.0);
+        assert_eq!(strtod_comma(b",5"), 0.5);
+        assert_eq!(strtod_comma(b"hello"), 0.0);
+    }
+
+    #[test]
+    fn int_parsing() {
+        assert_eq!(strtoi(b""), 0);
+        assert_eq!(strtoi(b"-"), 0);
+        assert_eq!(strtoi(b"12"), 12);
+        assert_eq!(strtoi(b"+12hello"), 12);
+        assert_eq!(strtoi(b"-12345678"), -12345678);
+        assert_eq!(strtoi(b"1234567a90"), 1234567);
+        assert_eq!(strtoi(b"123456789012"), 123456789012);
+        assert_eq!(strtoi(b"1234567890123456 7"), 1234567890123456);
+        assert_eq!(strtoi(b"00000000000000042"), 42);
+        let imax = format!("{}", i64::MAX);
+        let imin = format!("{}", i64::MIN + 1);
+        assert_eq!(strtoi(imax.as_bytes()), i64::MAX);
+        assert_eq!(strtoi(imin.as_bytes()), i64::MIN + 1);
+        // overflow
+        assert_eq!(strtoi(b"92233720368547758070"), 0);
+    }
 }
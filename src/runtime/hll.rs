@@ -0,0 +1,127 @@
+//! HyperLogLog sketches, for the `hll_add` and `hll_count` builtins.
+//!
+//! A sketch estimates the number of distinct values added to it using a fixed amount of memory:
+//! 2^14 one-byte registers, for a standard error of about 0.8%. Sketches are named by strings and
+//! shared between threads, so that in parallel mode every worker adds to the same sketch.
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::sync::Mutex;
+
+use hashbrown::HashMap;
+
+use crate::runtime::Int;
+
+const PRECISION: u32 = 14;
+const REGISTERS: usize = 1 << PRECISION;
+
+lazy_static::lazy_static! {
+    static ref SKETCHES: Mutex<HashMap<Box<[u8]>, Sketch>> = Default::default();
+}
+
+struct Sketch {
+    registers: Box<[u8]>,
+}
+
+impl Sketch {
+    fn new() -> Sketch {
+        Sketch {
+            registers: vec![0; REGISTERS].into_boxed_slice(),
+        }
+    }
+
+    /// Add `value` to the sketch, returning whether the sketch changed.
+    fn add(&mut self, value: &[u8]) -> bool {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(value);
+        let hash = hasher.finish();
+        // The first PRECISION bits pick a register, which records the longest run of leading
+        // zeros (plus one) seen in the remaining bits.
+        let ix = (hash >> (64 - PRECISION)) as usize;
+        let rank = ((hash << PRECISION).leading_zeros().min(64 - PRECISION) + 1) as u8;
+        if rank > self.registers[ix] {
+            self.registers[ix] = rank;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn count(&self) -> Int {
+        let m = REGISTERS as f64;
+        let mut sum = 0.0;
+        let mut zeros = 0;
+        for r in self.registers.iter() {
+            sum += 2f64.powi(-(*r as i32));
+            if *r == 0 {
+                zeros += 1;
+            }
+        }
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let estimate = alpha * m * m / sum;
+        // The raw estimate is biased for small cardinalities, where linear counting of the empty
+        // registers does better.
+        let estimate = if estimate <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        };
+        estimate.round() as Int
+    }
+}
+
+/// Add `value` to the sketch named `sketch`, creating it if need be. Returns 1 if this changed
+/// the sketch (in which case `value` has not been added before), and 0 otherwise.
+pub(crate) fn hll_add(sketch: &[u8], value: &[u8]) -> Int {
+    let mut sketches = SKETCHES.lock().unwrap();
+    let changed = match sketches.get_mut(sketch) {
+        Some(s) => s.add(value),
+        None => {
+            let mut s = Sketch::new();
+            s.add(value);
+            sketches.insert(sketch.into(), s);
+            true
+        }
+    };
+    changed as Int
+}
+
+/// The estimated number of distinct values added to the sketch named `sketch`; 0 if there is no
+/// such sketch.
+pub(crate) fn hll_count(sketch: &[u8]) -> Int {
+    SKETCHES
+        .lock()
+        .unwrap()
+        .get(sketch)
+        .map_or(0, Sketch::count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates() {
+        let mut sketch = Sketch::new();
+        assert_eq!(sketch.count(), 0);
+        for i in 0..10 {
+            sketch.add(format!("{}", i).as_bytes());
+        }
+        assert_eq!(sketch.count(), 10);
+        for i in 0..200_000 {
+            sketch.add(format!("{}", i % 100_000).as_bytes());
+        }
+        let count = sketch.count();
+        assert!((97_000..=103_000).contains(&count), "count={}", count);
+    }
+
+    #[test]
+    fn named_sketches() {
+        assert_eq!(hll_count(b"hll-test-missing"), 0);
+        assert_eq!(hll_add(b"hll-test-a", b"x"), 1);
+        assert_eq!(hll_add(b"hll-test-a", b"x"), 0);
+        hll_add(b"hll-test-a", b"y");
+        hll_add(b"hll-test-b", b"x");
+        assert_eq!(hll_count(b"hll-test-a"), 2);
+        assert_eq!(hll_count(b"hll-test-b"), 1);
+    }
+}
@@ -0,0 +1,213 @@
+//! JSON output, for `--ojson` and the `escape_json` and `join_json` builtins.
+//!
+//! In JSON output mode each `print` writes one object per line. Values that are already valid
+//! JSON numbers are written as numbers, and everything else as a string. Columns are keyed by
+//! their name in the header read with `-H` (i.e. by looking them up in `FI`), or by `$N` if they
+//! have no name; other values are keyed by their position in the `print` statement.
+use std::borrow::Cow;
+
+use crate::runtime::{Int, Str, StrMap};
+
+/// Whether `bs` is a number in the syntax accepted by JSON: no leading `+` or zeros, and digits on
+/// both sides of any decimal point.
+pub(crate) fn is_json_number(bs: &[u8]) -> bool {
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while *i < bs.len() && bs[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        *i - start
+    };
+    let mut i = 0;
+    if bs.first() == Some(&b'-') {
+        i += 1;
+    }
+    let int_start = i;
+    match digits(&mut i) {
+        0 => return false,
+        1 => {}
+        _ if bs[int_start] == b'0' => return false,
+        _ => {}
+    }
+    if bs.get(i) == Some(&b'.') {
+        i += 1;
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+    if let Some(b'e' | b'E') = bs.get(i) {
+        i += 1;
+        if let Some(b'+' | b'-') = bs.get(i) {
+            i += 1;
+        }
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+    i == bs.len()
+}
+
+/// Append `bs` to `out` as a quoted JSON string. Bytes that are not valid UTF-8 are copied as-is.
+fn push_json_string(bs: &[u8], out: &mut Vec<u8>) {
+    out.push(b'"');
+    for &b in bs {
+        match b {
+            b'"' => out.extend_from_slice(b"\\\""),
+            b'\\' => out.extend_from_slice(b"\\\\"),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\r' => out.extend_from_slice(b"\\r"),
+            b'\t' => out.extend_from_slice(b"\\t"),
+            0..=0x1f => {
+                const HEX: &[u8; 16] = b"0123456789abcdef";
+                out.extend_from_slice(b"\\u00");
+                out.push(HEX[(b >> 4) as usize]);
+                out.push(HEX[(b & 0xf) as usize]);
+            }
+            b => out.push(b),
+        }
+    }
+    out.push(b'"');
+}
+
+fn push_json_value(bs: &[u8], out: &mut Vec<u8>) {
+    if is_json_number(bs) {
+        out.extend_from_slice(bs);
+    } else {
+        push_json_string(bs, out)
+    }
+}
+
+/// `s` as a JSON value: unchanged if it is a number, and a quoted string otherwise.
+pub fn escape_json<'a>(s: &Str<'a>) -> Str<'a> {
+    s.with_bytes(|bs| {
+        if is_json_number(bs) {
+            return s.clone();
+        }
+        let mut out = Vec::with_capacity(bs.len() + 2);
+        push_json_string(bs, &mut out);
+        Str::from(&out[..]).unmoor().upcast()
+    })
+}
+
+/// The names of columns in the header, taken from `FI`.
+pub(crate) struct ColumnNames(Vec<Option<Vec<u8>>>);
+
+impl ColumnNames {
+    pub(crate) fn new(fi: &StrMap<Int>) -> ColumnNames {
+        let mut names = Vec::new();
+        fi.iter(|iter| {
+            for (name, col) in iter {
+                if *col <= 0 {
+                    continue;
+                }
+                let col = *col as usize;
+                if names.len() <= col {
+                    names.resize(col + 1, None);
+                }
+                names[col] = Some(name.with_bytes(|bs| bs.to_vec()));
+            }
+        });
+        ColumnNames(names)
+    }
+
+    /// The key for column `col`: its name, or `$col` if it has none.
+    pub(crate) fn key(&self, col: usize) -> Cow<[u8]> {
+        match self.0.get(col) {
+            Some(Some(name)) => Cow::Borrowed(&name[..]),
+            _ => Cow::Owned(format!("${}", col).into_bytes()),
+        }
+    }
+}
+
+/// The key for a value printed in a structured output mode: the name of column `col` if it is
+/// positive, and the 1-based position `pos` of the value in the `print` statement otherwise.
+pub(crate) fn value_key(fi: &StrMap<Int>, col: Int, pos: Int) -> Vec<u8> {
+    if col > 0 {
+        ColumnNames::new(fi).key(col as usize).into_owned()
+    } else {
+        pos.to_string().into_bytes()
+    }
+}
+
+/// The key (with its trailing `:`) for a value printed in JSON output mode; see `value_key`.
+pub(crate) fn json_key(fi: &StrMap<Int>, col: Int, pos: Int) -> Str<'static> {
+    let mut out = Vec::new();
+    push_json_string(&value_key(fi, col, pos), &mut out);
+    out.push(b':');
+    Str::from(&out[..]).unmoor()
+}
+
+/// Converts consecutive columns, starting at `start`, to `"key":value` pairs, for `join_json`.
+pub(crate) struct ColumnPairs {
+    names: ColumnNames,
+    col: usize,
+}
+
+impl ColumnPairs {
+    pub(crate) fn new(fi: &StrMap<Int>, start: Int) -> ColumnPairs {
+        ColumnPairs {
+            names: ColumnNames::new(fi),
+            col: start.max(1) as usize,
+        }
+    }
+
+    pub(crate) fn pair(&mut self, s: Str<'static>) -> Str<'static> {
+        let mut out = Vec::new();
+        push_json_string(&self.names.key(self.col), &mut out);
+        out.push(b':');
+        s.with_bytes(|bs| push_json_value(bs, &mut out));
+        self.col += 1;
+        Str::from(&out[..]).unmoor()
+    }
+}
+
+/// Wrap the comma-separated pairs in `fields` in braces.
+pub(crate) fn object(fields: Str) -> Str {
+    Str::concat(Str::concat("{".into(), fields), "}".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        for s in ["0", "-1", "3.25", "1e5", "-0.5E-3", "10"] {
+            assert!(is_json_number(s.as_bytes()), "s={:?}", s);
+        }
+        for s in [
+            "", "-", "007", "+1", "1.", ".5", "1e", "0x10", "1 ", "NaN", "inf",
+        ] {
+            assert!(!is_json_number(s.as_bytes()), "s={:?}", s);
+        }
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape_json(&Str::from("12.5")), Str::from("12.5"));
+        assert_eq!(escape_json(&Str::from("02139")), Str::from(r#""02139""#));
+        assert_eq!(
+            escape_json(&Str::from("say \"hi\"\\\n\x01")),
+            Str::from(r#""say \"hi\"\\\n\u0001""#)
+        );
+    }
+
+    #[test]
+    fn objects() {
+        let fi: StrMap<Int> = Default::default();
+        fi.insert(Str::from("name"), 1);
+        fi.insert(Str::from("age"), 2);
+        let mut pairs = ColumnPairs::new(&fi, 1);
+        let fields: Vec<_> = ["bob", "42", "x"]
+            .iter()
+            .map(|s| pairs.pair(Str::from(*s)))
+            .collect();
+        let fields = Str::from(",").join(fields.into_iter());
+        assert_eq!(
+            object(fields),
+            Str::from(r#"{"name":"bob","age":42,"$3":"x"}"#)
+        );
+        assert_eq!(json_key(&fi, 2, 1), Str::from(r#""age":"#));
+        assert_eq!(json_key(&fi, 0, 3), Str::from(r#""3":"#));
+    }
+}
@@ -0,0 +1,67 @@
+//! State kept on the side for individual maps, for builtins like `sample` and `topk` that need
+//! more than the contents of the map to do their job.
+//!
+//! State is keyed by the map's address. Each entry holds a weak reference to its map, so the
+//! address cannot be reused by another map until the entry is removed.
+use std::rc::Rc;
+
+use hashbrown::HashMap;
+
+use crate::runtime::SharedMap;
+
+struct Entry<'a, T> {
+    // Whether the map this entry belongs to is still alive.
+    live: Box<dyn Fn() -> bool + 'a>,
+    state: T,
+}
+
+pub(crate) struct MapState<'a, T>(HashMap<usize, Entry<'a, T>>);
+
+impl<'a, T> Default for MapState<'a, T> {
+    fn default() -> Self {
+        MapState(Default::default())
+    }
+}
+
+impl<'a, T: Default> MapState<'a, T> {
+    /// The state for `map`, which starts out as `T::default()`.
+    pub(crate) fn get<K: 'a, V: 'a>(&mut self, map: &SharedMap<K, V>) -> &mut T {
+        let addr = Rc::as_ptr(&map.0) as usize;
+        if !self.0.contains_key(&addr) {
+            // Forget about any maps that have been dropped since we last added one.
+            self.0.retain(|_, e| (e.live)());
+            let weak = Rc::downgrade(&map.0);
+            let live = Box::new(move || weak.strong_count() > 0);
+            self.0.insert(
+                addr,
+                Entry {
+                    live,
+                    state: T::default(),
+                },
+            );
+        }
+        &mut self.0.get_mut(&addr).unwrap().state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{Int, IntMap};
+
+    #[test]
+    fn separate_maps() {
+        let mut states = MapState::<Int>::default();
+        let m1: IntMap<Int> = Default::default();
+        let m2: IntMap<Int> = Default::default();
+        *states.get(&m1) += 1;
+        *states.get(&m1) += 1;
+        *states.get(&m2) += 5;
+        assert_eq!(*states.get(&m1), 2);
+        assert_eq!(*states.get(&m2.clone()), 5);
+        drop(m2);
+        let m3: IntMap<Int> = Default::default();
+        assert_eq!(*states.get(&m3), 0);
+        assert_eq!(states.0.len(), 2);
+    }
+}
@@ -7,37 +7,109 @@ use std::hash::Hash;
 use std::io;
 use std::iter::FromIterator;
 use std::mem;
-use std::process::ChildStdout;
 use std::rc::Rc;
 use std::str;
+use std::sync::Arc;
 
+pub mod affinity;
+pub mod arith;
+mod chars;
+pub mod checkpoint;
+mod clock;
 mod command;
+mod config;
 pub mod float_parse;
+mod hll;
+pub(crate) mod json;
+mod map_state;
+pub(crate) mod msgpack;
+mod parsedate;
 pub mod printf;
+mod sample;
+mod sort;
 pub mod splitter;
 pub mod str_impl;
+mod strftime;
+pub mod strict;
 pub mod string_search;
+mod topk;
+pub(crate) mod unpack;
 pub mod utf8;
+pub mod warnings;
 pub mod writers;
+pub mod xml;
 
 use crate::pushdown::FieldSet;
-use splitter::regex::RegexSplitter;
+use splitter::{
+    limits::{Limits, RecordRange},
+    regex::RegexSplitter,
+    FileBoundary, ReaderState,
+};
 
 // TODO: remove the pub use for Variables here.
 pub(crate) use crate::builtins::Variables;
+pub(crate) use chars::{chr, ord};
+pub(crate) use clock::{monotonic, sleep, time_of_day};
 pub use command::run_command;
-pub(crate) use float_parse::{hextoi, strtod, strtoi};
+pub(crate) use command::{command_status, use_pty, wait_all_commands};
+pub use config::Config;
+pub(crate) use float_parse::{hextoi, strtod, strtod_locale, strtoi};
+pub(crate) use hll::{hll_add, hll_count};
+pub use json::escape_json;
+pub(crate) use parsedate::parsedate;
 pub(crate) use printf::FormatArg;
+pub(crate) use sample::Samples;
+pub(crate) use sort::sort_keys;
 pub use splitter::{
     batch::{escape_csv, escape_tsv},
-    ChainedReader, Line, LineReader,
+    ArgvReader, ChainedReader, Line, LineReader,
 };
+pub(crate) use str_impl::SubstTemplate;
 pub use str_impl::{Str, UniqueStr};
+pub(crate) use strftime::strftime;
+pub(crate) use topk::TopK;
+
+#[derive(Default)]
+pub struct RegexCache(Registry<Regex>, SubstCache);
 
+/// Compiled replacement strings for sub and gsub.
+///
+/// Unlike regexes, replacement strings are frequently computed per-record (e.g. `gsub(/x/, $2)`),
+/// so we bound the size of the cache and start over when it fills up.
 #[derive(Default)]
-pub struct RegexCache(Registry<Regex>);
+struct SubstCache(HashMap<Str<'static>, SubstTemplate>);
+
+const MAX_CACHED_SUBSTS: usize = 64;
+
+impl SubstCache {
+    fn get(&mut self, subst: &Str) -> &SubstTemplate {
+        let key = subst.clone().unmoor();
+        if !self.0.contains_key(&key) && self.0.len() >= MAX_CACHED_SUBSTS {
+            self.0.clear();
+        }
+        self.0
+            .entry(key)
+            .or_insert_with_key(|k| k.with_bytes(SubstTemplate::new))
+    }
+}
 
 impl RegexCache {
+    pub(crate) fn with_regex_subst<T>(
+        &mut self,
+        pat: &Str,
+        subst: &Str,
+        mut f: impl FnMut(&Regex, &SubstTemplate) -> T,
+    ) -> Result<T> {
+        let subst = self.1.get(subst);
+        self.0.get(
+            pat,
+            |s| match Regex::new(s) {
+                Ok(r) => Ok(r),
+                Err(e) => err!("{}", e),
+            },
+            |x| f(x, subst),
+        )
+    }
     pub(crate) fn with_regex<T>(&mut self, pat: &Str, mut f: impl FnMut(&Regex) -> T) -> Result<T> {
         self.0.get(
             pat,
@@ -89,9 +161,20 @@ impl RegexCache {
     pub(crate) fn get_line_stdin<'a, LR: LineReader>(
         &mut self,
         pat: &Str<'a>,
+        argc: Int,
+        argv: &IntMap<Str<'a>>,
         reg: &mut FileRead<LR>,
     ) -> Result<(/* file changed */ bool, Str<'a>)> {
-        let (changed, mut line) = reg.stdin.read_line(pat, self)?;
+        let read = reg.read_stdin(
+            /*boundaries=*/ false,
+            argc,
+            argv,
+            |stdin| stdin.read_line(pat, self),
+        )?;
+        let (changed, mut line) = match read {
+            Some(res) => res,
+            None => return Ok((false, Str::default())),
+        };
         // NB both of these `pat`s are "wrong" but we are fine because they are only used
         // when the column is nonzero, or someone has overwritten a nonzero column.
         Ok((changed, line.get_col(0, pat, pat, self)?.clone().upcast()))
@@ -99,37 +182,35 @@ impl RegexCache {
     pub(crate) fn get_line_stdin_reuse<'a, LR: LineReader>(
         &mut self,
         pat: &Str<'a>,
+        argc: Int,
+        argv: &IntMap<Str<'a>>,
         reg: &mut FileRead<LR>,
         old_line: &mut LR::Line,
     ) -> Result</*file changed */ bool> {
-        reg.stdin.read_line_reuse(pat, self, old_line)
+        let changed = reg
+            .read_stdin(/*boundaries=*/ true, argc, argv, |stdin| {
+                Ok((stdin.read_line_reuse(pat, self, old_line)?, ()))
+            })?
+            .map_or(false, |(changed, ())| changed);
+        // Readers may replace the line with a new one.
+        old_line.set_limits(&reg.limits);
+        Ok(changed)
     }
     fn split_internal<'a>(
         &mut self,
         pat: &Str,
         s: &Str<'a>,
         used_fields: &FieldSet,
+        limit: usize,
         mut push: impl FnMut(Str<'a>),
     ) -> Result<()> {
         if pat == &Str::from(" ") {
-            self.with_regex(&Str::from(r#"[ \t]+"#), |re| {
-                s.split(
-                    re,
-                    |s, is_empty| {
-                        if !is_empty {
-                            push(s);
-                            1
-                        } else {
-                            0
-                        }
-                    },
-                    used_fields,
-                )
-            })
+            self.split_whitespace(r#"[ \t]+"#, s, used_fields, limit, push)
         } else {
             self.with_regex(pat, |re| {
-                s.split(
+                s.split_n(
                     re,
+                    limit,
                     |s, _| {
                         push(s);
                         1
@@ -139,6 +220,31 @@ impl RegexCache {
             })
         }
     }
+    // Split `s` on matches of `ws`, ignoring leading and trailing matches, as for the default FS.
+    fn split_whitespace<'a>(
+        &mut self,
+        ws: &'static str,
+        s: &Str<'a>,
+        used_fields: &FieldSet,
+        limit: usize,
+        mut push: impl FnMut(Str<'a>),
+    ) -> Result<()> {
+        self.with_regex(&Str::from(ws), |re| {
+            s.split_n(
+                re,
+                limit,
+                |s, is_empty| {
+                    if !is_empty {
+                        push(s);
+                        1
+                    } else {
+                        0
+                    }
+                },
+                used_fields,
+            )
+        })
+    }
     pub(crate) fn split_regex<'a>(
         &mut self,
         pat: &Str,
@@ -146,19 +252,51 @@ impl RegexCache {
         used_fields: &FieldSet,
         v: &mut Vec<Str<'a>>,
     ) -> Result<()> {
-        self.split_internal(pat, s, used_fields, |s| v.push(s))
+        self.split_internal(pat, s, used_fields, usize::max_value(), |s| v.push(s))
+    }
+    /// Split a record read in paragraph mode (with an empty RS), where newlines separate fields
+    /// as well as `pat`.
+    pub(crate) fn split_regex_paragraph<'a>(
+        &mut self,
+        pat: &Str,
+        s: &Str<'a>,
+        used_fields: &FieldSet,
+        v: &mut Vec<Str<'a>>,
+    ) -> Result<()> {
+        let limit = usize::max_value();
+        if pat == &Str::from(" ") {
+            self.split_whitespace(r#"[ \t\n]+"#, s, used_fields, limit, |s| v.push(s))
+        } else {
+            let pat = Str::from(format!("(?:{})|\n", pat));
+            self.split_internal(&pat, s, used_fields, limit, |s| v.push(s))
+        }
     }
 
+    // The maximum number of fields passed to split(); the last field holds the rest of the string,
+    // unsplit. Limits that are not positive mean there is no limit.
+    fn field_limit(limit: Int) -> usize {
+        if limit > 0 {
+            limit as usize
+        } else {
+            usize::max_value()
+        }
+    }
+
+    // split() is frequently called on the same array for every record; both of the following
+    // clear `m` in place rather than allocating a new map, so the table allocated for the first
+    // record is reused for all subsequent ones.
     pub(crate) fn split_regex_intmap<'a>(
         &mut self,
         pat: &Str<'a>,
         s: &Str<'a>,
         m: &IntMap<Str<'a>>,
+        limit: Int,
     ) -> Result<()> {
         let mut i = 0i64;
         let mut m_b = m.0.borrow_mut();
         m_b.clear();
-        self.split_internal(pat, s, &FieldSet::all(), |s| {
+        let limit = Self::field_limit(limit);
+        self.split_internal(pat, s, &FieldSet::all(), limit, |s| {
             i += 1;
             m_b.insert(i, s);
         })
@@ -169,16 +307,56 @@ impl RegexCache {
         pat: &Str<'a>,
         s: &Str<'a>,
         m: &StrMap<'a, Str<'a>>,
+        limit: Int,
     ) -> Result<()> {
         let mut i = 0i64;
         let mut m_b = m.0.borrow_mut();
         m_b.clear();
-        self.split_internal(pat, s, &FieldSet::all(), |s| {
+        let limit = Self::field_limit(limit);
+        self.split_internal(pat, s, &FieldSet::all(), limit, |s| {
             i += 1;
             m_b.insert(convert::<i64, Str<'_>>(i), s);
         })
     }
 
+    /// Store every non-overlapping match of `pat` in `s` in `m` under the keys 1, 2, ..., or just
+    /// the part of each match captured by group number `group` if it is nonzero. Groups that do
+    /// not participate in a match are stored as the empty string.
+    pub(crate) fn match_all_intmap<'a>(
+        &mut self,
+        pat: &Str<'a>,
+        s: &Str<'a>,
+        m: &IntMap<Str<'a>>,
+        group: Int,
+    ) -> Result<()> {
+        self.with_regex_fallible(pat, |re| {
+            if group < 0 || group as usize >= re.captures_len() {
+                return err!("regex {} has no capture group {}", re, group);
+            }
+            let group = group as usize;
+            let mut i = 0i64;
+            let mut m_b = m.0.borrow_mut();
+            m_b.clear();
+            s.with_bytes(|bs| {
+                if group == 0 {
+                    for mat in re.find_iter(bs) {
+                        i += 1;
+                        m_b.insert(i, s.slice(mat.start(), mat.end()));
+                    }
+                } else {
+                    for caps in re.captures_iter(bs) {
+                        i += 1;
+                        let sub = caps
+                            .get(group)
+                            .map_or_else(Str::default, |c| s.slice(c.start(), c.end()));
+                        m_b.insert(i, sub);
+                    }
+                }
+            });
+            Ok(())
+        })
+    }
+
     pub(crate) fn regex_const_match_loc(vars: &mut Variables, re: &Regex, s: &Str) -> Result<Int> {
         use crate::builtins::Variable;
         let (start, len) = s.with_bytes(|bs| match re.find(bs) {
@@ -193,6 +371,43 @@ impl RegexCache {
         vars.store_int(Variable::RLENGTH, len)?;
         Ok(start)
     }
+    /// Like `regex_match_loc`, but also store the text of the match in `m`: the whole match under
+    /// "0", and each capture group that participates in it under its number and, if the group is
+    /// named, under its name as well. `m` is cleared first.
+    pub(crate) fn regex_match_captures<'a>(
+        &mut self,
+        vars: &mut Variables,
+        pat: &Str<'a>,
+        s: &Str<'a>,
+        m: &StrMap<'a, Str<'a>>,
+    ) -> Result<Int> {
+        use crate::builtins::Variable;
+        let (start, len) = self.with_regex(pat, |re| {
+            let mut m_b = m.0.borrow_mut();
+            m_b.clear();
+            s.with_bytes(|bs| match re.captures(bs) {
+                Some(caps) => {
+                    for (i, name) in re.capture_names().enumerate() {
+                        if let Some(c) = caps.get(i) {
+                            let sub = s.slice(c.start(), c.end());
+                            if let Some(name) = name {
+                                m_b.insert(Str::from(name.to_string()), sub.clone());
+                            }
+                            m_b.insert(convert::<i64, Str<'_>>(i as i64), sub);
+                        }
+                    }
+                    let mat = caps.get(0).unwrap();
+                    let start = mat.start() as Int;
+                    let end = mat.end() as Int;
+                    (start + 1, end - start)
+                }
+                None => (0, -1),
+            })
+        })?;
+        vars.store_int(Variable::RSTART, start)?;
+        vars.store_int(Variable::RLENGTH, len)?;
+        Ok(start)
+    }
     pub(crate) fn regex_match_loc(
         &mut self,
         vars: &mut Variables,
@@ -209,6 +424,40 @@ impl RegexCache {
     pub(crate) fn is_regex_match(&mut self, pat: &Str, s: &Str) -> Result<bool> {
         self.with_regex(pat, |re| Self::regex_const_match(re, s))
     }
+
+    /// The number of patterns compiled so far.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.0.cached.len()
+    }
+}
+
+/// The value of a `@/re/` constant. It holds the compiled pattern, so matching against it does not
+/// go through the `RegexCache`. Regexes are converted to strings using the text of the pattern.
+#[derive(Clone, Debug)]
+pub(crate) struct TypedRegex(Arc<Regex>);
+
+impl Default for TypedRegex {
+    fn default() -> TypedRegex {
+        lazy_static::lazy_static! {
+            static ref EMPTY: Arc<Regex> = Arc::new(Regex::new("").unwrap());
+        }
+        TypedRegex(EMPTY.clone())
+    }
+}
+
+impl TypedRegex {
+    pub(crate) fn new(pat: &str) -> std::result::Result<TypedRegex, regex::Error> {
+        Ok(TypedRegex(Arc::new(Regex::new(pat)?)))
+    }
+
+    pub(crate) fn regex(&self) -> &Regex {
+        &self.0
+    }
+
+    pub(crate) fn to_str<'a>(&self) -> Str<'a> {
+        Str::from(self.0.as_str().to_string())
+    }
 }
 
 #[derive(Clone)]
@@ -216,7 +465,7 @@ pub(crate) struct FileWrite(writers::Registry);
 
 impl Default for FileWrite {
     fn default() -> FileWrite {
-        FileWrite::new(writers::default_factory())
+        FileWrite::new(writers::default_factory(), &Config::default())
     }
 }
 
@@ -227,19 +476,32 @@ impl FileWrite {
     pub(crate) fn close(&mut self, path: &Str) -> Result<()> {
         self.0.close(path)
     }
-    pub(crate) fn new(ff: impl writers::FileFactory) -> FileWrite {
-        FileWrite(writers::Registry::from_factory(ff))
+    pub(crate) fn close_command(&mut self, cmd: &Str) -> Result<()> {
+        self.0.close_command(cmd)
+    }
+    pub(crate) fn new(ff: impl writers::FileFactory, config: &Config) -> FileWrite {
+        FileWrite(writers::Registry::from_factory(ff, config))
+    }
+
+    /// Prepare to write from a new worker thread; see `Registry::attach_thread`.
+    pub(crate) fn attach_thread(&self) {
+        self.0.attach_thread()
     }
 
     pub(crate) fn shutdown(&mut self) -> Result<()> {
         self.0.destroy_and_flush_all_files()
     }
 
+    pub(crate) fn close_all_commands(&mut self) -> Result<()> {
+        self.0.close_all_commands()
+    }
+
     pub(crate) fn printf(
         &mut self,
         path: Option<(&Str, FileSpec)>,
         spec: &Str,
         pa: &[printf::FormatArg],
+        config: &Config,
     ) -> Result<()> {
         let (handle, fspec) = if let Some((out_file, fspec)) = path {
             (self.0.get_handle(Some(out_file), fspec)?, fspec)
@@ -250,7 +512,7 @@ impl FileWrite {
             )
         };
         let mut text = str_impl::DynamicBuf::default();
-        spec.with_bytes(|spec| printf::printf(&mut text, spec, pa))?;
+        spec.with_bytes(|spec| printf::printf(&mut text, spec, pa, config))?;
         let s = text.into_str();
         handle.write(&s, fspec)
     }
@@ -274,7 +536,7 @@ pub const CHUNK_SIZE: usize = 8 << 10;
 #[derive(Default)]
 pub(crate) struct Inputs {
     files: Registry<RegexSplitter<File>>,
-    commands: Registry<RegexSplitter<ChildStdout>>,
+    commands: Registry<RegexSplitter<command::CommandOutput>>,
 }
 
 pub(crate) struct FileRead<LR = RegexSplitter<Box<dyn io::Read + Send>>> {
@@ -283,6 +545,8 @@ pub(crate) struct FileRead<LR = RegexSplitter<Box<dyn io::Read + Send>>> {
     named_columns: Option<Vec<Str<'static>>>,
     used_fields: FieldSet,
     backup_used_fields: FieldSet,
+    range: RecordRange,
+    limits: Limits,
 }
 
 impl<LR: LineReader> FileRead<LR> {
@@ -292,6 +556,8 @@ impl<LR: LineReader> FileRead<LR> {
             .into_iter()
             .map(|x| {
                 let fields = self.used_fields.clone();
+                let range = self.range.clone();
+                let limits = self.limits.clone();
                 move || {
                     let stdin = x();
                     if stdin.wait() {
@@ -301,6 +567,8 @@ impl<LR: LineReader> FileRead<LR> {
                             used_fields: fields.clone(),
                             backup_used_fields: fields,
                             stdin,
+                            range,
+                            limits,
                         })
                     } else {
                         None
@@ -310,14 +578,18 @@ impl<LR: LineReader> FileRead<LR> {
             .collect()
     }
 
+    // Close the file or command named `path`, so that reading from it again starts over (rerunning
+    // the command).
     pub(crate) fn close(&mut self, path: &Str) {
         self.inputs.files.remove(path);
+        self.inputs.commands.remove(path);
     }
 
     pub(crate) fn new(
         stdin: LR,
         used_fields: FieldSet,
         named_columns: Option<Vec<&[u8]>>,
+        config: &Config,
     ) -> FileRead<LR> {
         let backup_used_fields = used_fields;
         let used_fields = if named_columns.is_some() {
@@ -333,6 +605,8 @@ impl<LR: LineReader> FileRead<LR> {
             stdin,
             used_fields,
             backup_used_fields,
+            range: config.record_range.clone(),
+            limits: config.limits.clone(),
             named_columns: named_columns
                 .map(|cs| cs.into_iter().map(|s| Str::from(s).unmoor()).collect()),
         };
@@ -340,7 +614,16 @@ impl<LR: LineReader> FileRead<LR> {
         res
     }
 
+    /// A line to read records from the main input into, which enforces the same limits.
+    pub(crate) fn new_line(&self) -> LR::Line {
+        let mut line = LR::Line::default();
+        line.set_limits(&self.limits);
+        line
+    }
+
     pub(crate) fn update_named_columns<'a>(&mut self, fi: &StrMap<'a, Int>) {
+        // The header is not one of the records counted by --head.
+        self.range.untake();
         let referenced_fi = self.backup_used_fields.has_fi();
         let have_columns = self.named_columns.is_some();
 
@@ -376,10 +659,89 @@ impl<LR: LineReader> FileRead<LR> {
         self.stdin.filename()
     }
 
+    pub(crate) fn stdin_file_index(&self) -> Int {
+        self.stdin.file_index()
+    }
+
+    pub(crate) fn stdin_file_records(&self) -> Int {
+        self.stdin.file_records()
+    }
+
+    pub(crate) fn stdin_terminator(&self) -> Str<'static> {
+        self.stdin.terminator()
+    }
+
+    pub(crate) fn stdin_file_boundary(&self) -> Int {
+        self.stdin.file_boundary()
+    }
+
+    pub(crate) fn stdin_pending(&self) -> bool {
+        self.stdin.pending()
+    }
+
     pub(crate) fn read_err_stdin(&mut self) -> Int {
+        if self.range.stopped() {
+            return ReaderState::Eof as Int;
+        }
         self.stdin.read_state()
     }
 
+    /// Read a record from the main input with `read`, dropping the records skipped with `--skip`
+    /// and `--skip-file`. Returns `None` once `--head` records have been read.
+    ///
+    /// The starts and ends of files that the reader stops at for BEGINFILE and ENDFILE are only
+    /// returned if `boundaries` is set; they are not records, and so are never skipped.
+    /// Otherwise, as for an explicit `getline`, the read ends there as if at the end of the input,
+    /// and the boundary is left for the main loop to run the hooks.
+    ///
+    /// When the current input runs out, reading moves on to the next one named in ARGV, which has
+    /// `argc` entries (see `LineReader::next_input`).
+    fn read_stdin<'a, T>(
+        &mut self,
+        boundaries: bool,
+        argc: Int,
+        argv: &IntMap<Str<'a>>,
+        mut read: impl FnMut(&mut LR) -> Result<(/* file changed */ bool, T)>,
+    ) -> Result<Option<(bool, T)>> {
+        if self.range.stop() {
+            return Ok(None);
+        }
+        let mut changed = false;
+        loop {
+            let (file_changed, res) = read(&mut self.stdin)?;
+            if self.stdin.file_boundary() != FileBoundary::Record as Int {
+                if boundaries {
+                    return Ok(Some((changed | file_changed, res)));
+                }
+                self.stdin.defer_boundary();
+                return Ok(Some((changed, res)));
+            }
+            changed |= file_changed;
+            match self.stdin.read_state() {
+                x if x == ReaderState::OK as Int => {}
+                x if x == ReaderState::Eof as Int
+                    && self.stdin.next_input(argc, &|ix| {
+                        // Deleted entries are skipped, without being recreated.
+                        if argv.contains(&ix) {
+                            argv.get(&ix)
+                        } else {
+                            Str::default()
+                        }
+                    })? =>
+                {
+                    changed = true;
+                    continue;
+                }
+                _ => return Ok(Some((changed, res))),
+            }
+            let stdin = &self.stdin;
+            if !self.range.skip(|| stdin.file_index()) {
+                self.range.take();
+                return Ok(Some((changed, res)));
+            }
+        }
+    }
+
     pub(crate) fn read_err<'a>(&mut self, path: &Str<'a>) -> Result<Int> {
         self.with_file(path, |reader| Ok(reader.read_state()))
     }
@@ -387,17 +749,21 @@ impl<LR: LineReader> FileRead<LR> {
         self.with_cmd(cmd, |reader| Ok(reader.read_state()))
     }
 
-    pub(crate) fn next_file(&mut self) -> Result<()> {
+    /// Stop reading the current input file, returning whether the main input has moved on to the
+    /// next one. With BEGINFILE and ENDFILE, that only happens at the next read, once the end of
+    /// the current file has been reported.
+    pub(crate) fn next_file(&mut self) -> Result<bool> {
         let _ = self.stdin.next_file()?;
-        Ok(())
+        Ok(!self.stdin.file_hooks())
     }
 
     fn with_cmd<'a, R>(
         &mut self,
         cmd: &Str<'a>,
-        f: impl FnMut(&mut RegexSplitter<ChildStdout>) -> Result<R>,
+        f: impl FnMut(&mut RegexSplitter<command::CommandOutput>) -> Result<R>,
     ) -> Result<R> {
         let check_utf8 = self.stdin.check_utf8();
+        let limits = &self.limits;
         self.inputs.commands.get_fallible(
             cmd,
             |s| match command::command_for_read(s.as_bytes()) {
@@ -406,6 +772,7 @@ impl<LR: LineReader> FileRead<LR> {
                     CHUNK_SIZE,
                     cmd.clone().unmoor(),
                     check_utf8,
+                    limits.clone(),
                 )),
                 Err(e) => err!("failed to crate command for reading: {}", e),
             },
@@ -419,6 +786,7 @@ impl<LR: LineReader> FileRead<LR> {
         f: impl FnMut(&mut RegexSplitter<File>) -> Result<R>,
     ) -> Result<R> {
         let check_utf8 = self.stdin.check_utf8();
+        let limits = &self.limits;
         self.inputs.files.get_fallible(
             path,
             |s| match File::open(s) {
@@ -427,6 +795,7 @@ impl<LR: LineReader> FileRead<LR> {
                     CHUNK_SIZE,
                     path.clone().unmoor(),
                     check_utf8,
+                    limits.clone(),
                 )),
                 Err(e) => err!("failed to open file '{}': {}", s, e),
             },
@@ -579,6 +948,193 @@ where
     _Carrier::convert(s)
 }
 
+// Compare two strings as numbers if both look like numbers (see `float_parse::strnum`), and as
+// strings otherwise, returning -1, 0 or 1.
+pub(crate) fn strnum_cmp(l: &Str, r: &Str) -> Int {
+    l.with_bytes(|l| {
+        r.with_bytes(|r| {
+            let ord = match (float_parse::strnum(l), float_parse::strnum(r)) {
+                (Some(l), Some(r)) => l.partial_cmp(&r).unwrap_or(std::cmp::Ordering::Equal),
+                _ => l.cmp(r),
+            };
+            ord as Int
+        })
+    })
+}
+
+// substr(s, m, n): the characters of `s` at positions i (counting from 1) with m <= i < m + n,
+// where m and n are first rounded to the nearest integer. Positions outside of `s` are ignored,
+// so m may be zero or negative. As in POSIX awk, a NaN position or length yields "".
+pub(crate) fn substr<'a>(base: &Str<'a>, m: Float, n: Float) -> Str<'a> {
+    let round = |f: Float| (f + 0.5).floor();
+    let start = round(m);
+    let end = start + round(n);
+    let lo = start.max(1.0);
+    let hi = end.min(base.len() as Float + 1.0);
+    // This comparison is false if either bound is NaN.
+    if lo < hi {
+        base.slice(lo as usize - 1, hi as usize - 1)
+    } else {
+        Str::default()
+    }
+}
+
+// The error reported when a call to `assert` fails.
+pub(crate) fn assertion_failure(msg: &Str, loc: &Str, vars: &Variables) -> String {
+    let sep = if msg.is_empty() { "" } else { ": " };
+    format!(
+        "assertion failed at {}{}{} (NR={}, FILENAME={:?})",
+        loc,
+        sep,
+        msg,
+        vars.nr,
+        vars.filename.to_string()
+    )
+}
+
+// The number of entries of each map printed by dump_state().
+const DUMP_SAMPLES: usize = 3;
+
+// The heap memory owned by a map key or value, for --mem-profile.
+pub(crate) trait HeapSize {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for Int {}
+impl HeapSize for Float {}
+impl<'a> HeapSize for Str<'a> {
+    fn heap_size(&self) -> usize {
+        Str::heap_size(self)
+    }
+}
+
+/// Print the scalar `name` for dump_state(). With `mem_profile` set (by --mem-profile),
+/// dump_state() reports memory usage rather than values.
+pub(crate) fn dump_scalar(name: &Str, val: &Str, mem_profile: bool) {
+    // Scalars are negligible next to arrays; leave them out of memory profiles.
+    if !mem_profile {
+        eprintln!("{} = {}", name, val);
+    }
+}
+
+/// Print a sample of the entries of the map `name` for dump_state(), or an estimate of its memory
+/// usage if `mem_profile` is set.
+pub(crate) fn dump_map<K, V>(name: &Str, map: &SharedMap<K, V>, mem_profile: bool)
+where
+    K: Hash + Eq + std::fmt::Display + HeapSize,
+    V: std::fmt::Display + HeapSize,
+{
+    use std::fmt::Write;
+    if mem_profile {
+        let (table_bytes, str_bytes) = map.iter(|entries| {
+            let str_bytes: usize = entries.map(|(k, v)| k.heap_size() + v.heap_size()).sum();
+            // One control byte per bucket, in addition to the entry itself.
+            let table_bytes = map.capacity() * (mem::size_of::<(K, V)>() + 1);
+            (table_bytes, str_bytes)
+        });
+        eprintln!(
+            "{}: {} entries, ~{} bytes ({} in the table, {} in strings)",
+            name,
+            map.len(),
+            table_bytes + str_bytes,
+            table_bytes,
+            str_bytes,
+        );
+        return;
+    }
+    let mut out = format!("{}: map with {} entries\n", name, map.len());
+    map.iter(|entries| {
+        for (k, v) in entries.take(DUMP_SAMPLES) {
+            let _ = writeln!(&mut out, "  {}[{}] = {}", name, k, v);
+        }
+    });
+    if map.len() > DUMP_SAMPLES {
+        out.push_str("  ...\n");
+    }
+    eprint!("{}", out);
+}
+
+/// Print the allocator's statistics to stderr, for --mem-profile.
+pub fn report_alloc_stats() {
+    #[cfg(feature = "use_jemalloc")]
+    {
+        use tikv_jemalloc_sys::mallctl;
+        // Statistics are only refreshed when the epoch is advanced.
+        unsafe fn read_stat(name: &[u8]) -> Option<usize> {
+            let mut val: usize = 0;
+            let mut len = mem::size_of::<usize>();
+            let rc = mallctl(
+                name.as_ptr() as *const _,
+                &mut val as *mut usize as *mut _,
+                &mut len,
+                std::ptr::null_mut(),
+                0,
+            );
+            if rc == 0 {
+                Some(val)
+            } else {
+                None
+            }
+        }
+        unsafe {
+            let mut epoch: u64 = 1;
+            let mut len = mem::size_of::<u64>();
+            mallctl(
+                b"epoch\0".as_ptr() as *const _,
+                &mut epoch as *mut u64 as *mut _,
+                &mut len,
+                &mut epoch as *mut u64 as *mut _,
+                len,
+            );
+            match (
+                read_stat(b"stats.allocated\0"),
+                read_stat(b"stats.resident\0"),
+            ) {
+                (Some(allocated), Some(resident)) => eprintln!(
+                    "allocator: {} bytes allocated, {} bytes resident",
+                    allocated, resident
+                ),
+                _ => eprintln!("allocator: statistics unavailable"),
+            }
+        }
+    }
+    #[cfg(not(feature = "use_jemalloc"))]
+    eprintln!("allocator: statistics require frawk to be built with jemalloc");
+}
+
+// Set by the SIGUSR1 handler; the main loop polls this once per record and calls dump_state() if
+// it is set.
+static DUMP_REQUESTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Whether a state dump was requested since the last call, reporting the allocator's statistics
+/// first if `mem_profile` is set.
+pub(crate) fn take_dump_request(mem_profile: bool) -> bool {
+    use std::sync::atomic::Ordering;
+    // Avoid a read-modify-write in the common case, as this is called for every record.
+    let requested =
+        DUMP_REQUESTED.load(Ordering::Relaxed) && DUMP_REQUESTED.swap(false, Ordering::Relaxed);
+    if requested && mem_profile {
+        report_alloc_stats();
+    }
+    requested
+}
+
+/// Request a state dump on SIGUSR1, rather than terminating the process.
+#[cfg(unix)]
+pub fn install_dump_handler() {
+    extern "C" fn on_sigusr1(_: libc::c_int) {
+        DUMP_REQUESTED.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+    unsafe {
+        libc::signal(libc::SIGUSR1, on_sigusr1 as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install_dump_handler() {}
+
 // AWK arrays are inherently shared and mutable, so we have to do this, even if it is a code smell.
 // NB These are repr(transparent) because we pass them around as void* when compiling with LLVM.
 #[repr(transparent)]
@@ -601,6 +1157,9 @@ impl<K: Hash + Eq, V> SharedMap<K, V> {
     pub(crate) fn len(&self) -> usize {
         self.0.borrow().len()
     }
+    pub(crate) fn capacity(&self) -> usize {
+        self.0.borrow().capacity()
+    }
     pub(crate) fn insert(&self, k: K, v: V) {
         self.borrow_mut().insert(k, v);
     }
@@ -730,10 +1289,22 @@ impl<'a> StrMap<'a, Int> {
     }
 }
 
+impl<K: Hash + Eq + Clone, V: Clone> SharedMap<K, V> {
+    /// Replace the contents of this map with a copy of the contents of `other`.
+    pub(crate) fn copy_from(&self, other: &SharedMap<K, V>) {
+        if Rc::ptr_eq(&self.0, &other.0) {
+            return;
+        }
+        let other = other.0.borrow();
+        self.borrow_mut().clone_from(&other);
+    }
+}
+
 impl<K: Hash + Eq + Clone, V> SharedMap<K, V> {
     pub(crate) fn to_iter(&self) -> Iter<K> {
         self.0.borrow().keys().cloned().collect()
     }
+    #[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
     pub(crate) fn to_vec(&self) -> Vec<K> {
         self.0.borrow().keys().cloned().collect()
     }
@@ -796,3 +1367,60 @@ impl<S> Iter<S> {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_reuses_map() {
+        // split() clears the destination in place, so every reference to the array sees the new
+        // contents, with no entries left over from a longer split.
+        let mut rc = RegexCache::default();
+        let pat = Str::from(",");
+        let long = Str::from("a,b,c,d,e,f,g,h,i,j,k,l,m,n,o,p");
+        let m = IntMap::<Str>::default();
+        let alias = m.clone();
+        rc.split_regex_intmap(&pat, &long, &m, 0).unwrap();
+        assert_eq!(alias.len(), 16);
+        rc.split_regex_intmap(&pat, &Str::from("x,y"), &m, 0)
+            .unwrap();
+        assert_eq!(alias.len(), 2);
+        assert_eq!(alias.get(&2), Str::from("y"));
+        assert!(!alias.contains(&3));
+
+        let sm = StrMap::<Str>::default();
+        let alias = sm.clone();
+        rc.split_regex_strmap(&pat, &long, &sm, 0).unwrap();
+        rc.split_regex_strmap(&pat, &Str::from("x,y"), &sm, 0)
+            .unwrap();
+        assert_eq!(alias.len(), 2);
+        assert_eq!(alias.get(&Str::from("1")), Str::from("x"));
+        assert!(!alias.contains(&Str::from("3")));
+    }
+
+    #[test]
+    fn split_limit() {
+        let mut rc = RegexCache::default();
+        let m = IntMap::<Str>::default();
+        let s = Str::from("key: value: with: colons");
+        rc.split_regex_intmap(&Str::from(": "), &s, &m, 2).unwrap();
+        assert_eq!(m.len(), 2);
+        assert_eq!(m.get(&1), Str::from("key"));
+        assert_eq!(m.get(&2), Str::from("value: with: colons"));
+        rc.split_regex_intmap(&Str::from(": "), &s, &m, 1).unwrap();
+        assert_eq!(m.len(), 1);
+        assert_eq!(m.get(&1), s);
+        rc.split_regex_intmap(&Str::from(": "), &s, &m, -1).unwrap();
+        assert_eq!(m.len(), 4);
+
+        // Leading whitespace is skipped when splitting on " ", but the remainder is left as-is.
+        let s = Str::from("  a  b\tc d ");
+        rc.split_regex_intmap(&Str::from(" "), &s, &m, 2).unwrap();
+        assert_eq!(m.len(), 2);
+        assert_eq!(m.get(&1), Str::from("a"));
+        assert_eq!(m.get(&2), Str::from("b\tc d "));
+        rc.split_regex_intmap(&Str::from(" "), &s, &m, 10).unwrap();
+        assert_eq!(m.len(), 4);
+    }
+}
@@ -0,0 +1,161 @@
+//! MessagePack output, for `--omsgpack`.
+//!
+//! Each `print` writes one map, with the same keys as in JSON output mode (see `runtime::json`),
+//! and no separator between records. Values that are valid JSON numbers are written as MessagePack
+//! integers (if they are integers that fit in 64 bits) or floats; everything else is written as a
+//! string.
+use std::convert::TryFrom;
+
+use crate::runtime::{
+    json::{is_json_number, value_key, ColumnNames},
+    Int, Str, StrMap,
+};
+
+fn push_str(bs: &[u8], out: &mut Vec<u8>) {
+    let len = bs.len();
+    if len < 32 {
+        out.push(0xa0 | len as u8);
+    } else if len <= u8::MAX as usize {
+        out.push(0xd9);
+        out.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(0xda);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(0xdb);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    out.extend_from_slice(bs);
+}
+
+fn push_int(i: i64, out: &mut Vec<u8>) {
+    if (0..=0x7f).contains(&i) || (-32..0).contains(&i) {
+        // Positive and negative "fixint"s are stored in a single byte.
+        out.push(i as u8);
+    } else if i8::try_from(i).is_ok() {
+        out.push(0xd0);
+        out.push(i as u8);
+    } else if i16::try_from(i).is_ok() {
+        out.push(0xd1);
+        out.extend_from_slice(&(i as i16).to_be_bytes());
+    } else if i32::try_from(i).is_ok() {
+        out.push(0xd2);
+        out.extend_from_slice(&(i as i32).to_be_bytes());
+    } else {
+        out.push(0xd3);
+        out.extend_from_slice(&i.to_be_bytes());
+    }
+}
+
+fn push_value(bs: &[u8], out: &mut Vec<u8>) {
+    if !is_json_number(bs) {
+        return push_str(bs, out);
+    }
+    // JSON numbers are ASCII.
+    let s = std::str::from_utf8(bs).unwrap();
+    if let Ok(i) = s.parse::<i64>() {
+        return push_int(i, out);
+    }
+    out.push(0xcb);
+    out.extend_from_slice(&s.parse::<f64>().unwrap().to_be_bytes());
+}
+
+/// The header of a map with `n` entries.
+pub(crate) fn map_header(n: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(5);
+    if n < 16 {
+        out.push(0x80 | n as u8);
+    } else if n <= u16::MAX as usize {
+        out.push(0xde);
+        out.extend_from_slice(&(n as u16).to_be_bytes());
+    } else {
+        out.push(0xdf);
+        out.extend_from_slice(&(n as u32).to_be_bytes());
+    }
+    out
+}
+
+/// `s` as a MessagePack value: a number if it is a valid JSON number, and a string otherwise.
+pub(crate) fn escape_msgpack(s: &Str) -> Str<'static> {
+    let mut out = Vec::new();
+    s.with_bytes(|bs| push_value(bs, &mut out));
+    Str::from(&out[..]).unmoor()
+}
+
+/// The key for a value printed in MessagePack output mode; see `json::value_key`.
+pub(crate) fn msgpack_key(fi: &StrMap<Int>, col: Int, pos: Int) -> Str<'static> {
+    let mut out = Vec::new();
+    push_str(&value_key(fi, col, pos), &mut out);
+    Str::from(&out[..]).unmoor()
+}
+
+/// Converts consecutive columns, starting at `start`, to key-value pairs, for printing a whole
+/// record.
+pub(crate) struct ColumnPairs {
+    names: ColumnNames,
+    start: usize,
+    col: usize,
+}
+
+impl ColumnPairs {
+    pub(crate) fn new(fi: &StrMap<Int>, start: Int) -> ColumnPairs {
+        let start = start.max(1) as usize;
+        ColumnPairs {
+            names: ColumnNames::new(fi),
+            start,
+            col: start,
+        }
+    }
+
+    pub(crate) fn pair(&mut self, s: Str<'static>) -> Str<'static> {
+        let mut out = Vec::new();
+        push_str(&self.names.key(self.col), &mut out);
+        s.with_bytes(|bs| push_value(bs, &mut out));
+        self.col += 1;
+        Str::from(&out[..]).unmoor()
+    }
+
+    /// Prefix the concatenated pairs in `fields` with a map header.
+    pub(crate) fn map<'a>(&self, fields: Str<'a>) -> Str<'a> {
+        let header = map_header(self.col - self.start);
+        Str::concat(Str::from(&header[..]).unmoor().upcast(), fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(s: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        push_value(s.as_bytes(), &mut out);
+        out
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(encode("7"), [0x07]);
+        assert_eq!(encode("-3"), [0xfd]);
+        assert_eq!(encode("200"), [0xd1, 0x00, 0xc8]);
+        assert_eq!(encode("-100000"), [0xd2, 0xff, 0xfe, 0x79, 0x60]);
+        assert_eq!(encode("1.5"), [0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(encode("007"), [0xa3, b'0', b'0', b'7']);
+        let long = "x".repeat(40);
+        let mut expected = vec![0xd9, 40];
+        expected.extend_from_slice(long.as_bytes());
+        assert_eq!(encode(&long), expected);
+    }
+
+    #[test]
+    fn records() {
+        assert_eq!(map_header(2), [0x82]);
+        assert_eq!(map_header(20), [0xde, 0, 20]);
+        let fi: StrMap<Int> = Default::default();
+        fi.insert(Str::from("id"), 1);
+        let mut pairs = ColumnPairs::new(&fi, 1);
+        let fields = Str::concat(pairs.pair(Str::from("5")), pairs.pair(Str::from("a")));
+        let expected: &[u8] = b"\x82\xa2id\x05\xa2$2\xa1a";
+        assert_eq!(pairs.map(fields), Str::from(expected));
+        assert_eq!(msgpack_key(&fi, 0, 3), Str::from(&b"\xa13"[..]));
+    }
+}
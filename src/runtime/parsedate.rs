@@ -0,0 +1,336 @@
+//! Parsing timestamps, for the `parsedate` builtin.
+//!
+//! We accept the formats that turn up most often in logs: ISO 8601 and RFC 3339
+//! (`2023-11-14T22:13:20.5Z`, `2023-11-14 22:13:20 +05:30`, `20231114T221320Z`), RFC 2822
+//! (`Tue, 14 Nov 2023 22:13:20 +0000`) and the common log format used by web servers
+//! (`[14/Nov/2023:22:13:20 +0000]`). Timestamps without a UTC offset are in local time.
+use crate::runtime::{
+    strftime::{days_from_civil, local_zone, MONTHS},
+    Float,
+};
+
+struct Cursor<'a> {
+    bs: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bs: &'a [u8]) -> Cursor<'a> {
+        Cursor { bs, pos: 0 }
+    }
+
+    fn done(&self) -> bool {
+        self.pos == self.bs.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bs.get(self.pos).cloned()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Option<()> {
+        if self.eat(b) {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.eat(b' ') {}
+    }
+
+    /// Between `min` and `max` digits, as a number.
+    fn number(&mut self, min: usize, max: usize) -> Option<i64> {
+        let start = self.pos;
+        let mut res = 0;
+        while self.pos - start < max {
+            match self.peek() {
+                Some(d @ b'0'..=b'9') => res = res * 10 + (d - b'0') as i64,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if self.pos - start < min {
+            None
+        } else {
+            Some(res)
+        }
+    }
+
+    fn digits(&mut self, n: usize) -> Option<i64> {
+        self.number(n, n)
+    }
+
+    fn word(&mut self) -> &'a [u8] {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b) if b.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        &self.bs[start..self.pos]
+    }
+
+    /// A month name, in full or abbreviated to three letters.
+    fn month(&mut self) -> Option<i64> {
+        let word = self.word();
+        MONTHS
+            .iter()
+            .position(|m| {
+                word.eq_ignore_ascii_case(m.as_bytes())
+                    || word.eq_ignore_ascii_case(&m.as_bytes()[..3])
+            })
+            .map(|i| i as i64 + 1)
+    }
+
+    /// Fractional seconds, following a '.' or ','.
+    fn fraction(&mut self) -> Float {
+        if !(self.eat(b'.') || self.eat(b',')) {
+            return 0.0;
+        }
+        let (mut res, mut scale) = (0.0, 0.1);
+        while let Some(d @ b'0'..=b'9') = self.peek() {
+            res += (d - b'0') as Float * scale;
+            scale /= 10.0;
+            self.pos += 1;
+        }
+        res
+    }
+
+    /// An optional time zone at the end of the input: "Z", "UTC", "GMT", or an offset like
+    /// "+05:30", "-0800" or "+01". Returns the offset in seconds east of UTC, or `Some(None)` if
+    /// there is no time zone.
+    fn zone(&mut self) -> Option<Option<i64>> {
+        self.skip_spaces();
+        if self.done() {
+            return Some(None);
+        }
+        let word = self.word();
+        if !word.is_empty() {
+            return match word.to_ascii_uppercase().as_slice() {
+                b"Z" | b"UT" | b"UTC" | b"GMT" => Some(Some(0)),
+                _ => None,
+            };
+        }
+        let sign = if self.eat(b'+') {
+            1
+        } else if self.eat(b'-') {
+            -1
+        } else {
+            return None;
+        };
+        let hours = self.digits(2)?;
+        let mins = if self.eat(b':') || matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.digits(2)?
+        } else {
+            0
+        };
+        if hours > 23 || mins > 59 {
+            return None;
+        }
+        Some(Some(sign * (hours * 3600 + mins * 60)))
+    }
+}
+
+#[derive(Default)]
+struct Stamp {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    frac: Float,
+    // Seconds east of UTC; local time if missing.
+    offset: Option<i64>,
+}
+
+impl Stamp {
+    fn to_epoch(&self) -> Option<Float> {
+        if !(1..=12).contains(&self.month) {
+            return None;
+        }
+        let first = days_from_civil(self.year, self.month, 1);
+        let next = days_from_civil(self.year + self.month / 12, self.month % 12 + 1, 1);
+        if self.day < 1 || self.day > next - first || self.hour > 23 || self.min > 59 {
+            return None;
+        }
+        // Allow for leap seconds.
+        if self.sec > 60 {
+            return None;
+        }
+        let local = (first + self.day - 1) * 86400 + self.hour * 3600 + self.min * 60 + self.sec;
+        let offset = match self.offset {
+            Some(offset) => offset,
+            None => {
+                // The local offset depends on the time we are looking for; start from a guess.
+                let guess = local_zone(local).0;
+                local_zone(local - guess).0
+            }
+        };
+        Some((local - offset) as Float + self.frac)
+    }
+}
+
+/// `2023-11-14T22:13:20.5+05:30`, and variations on it.
+fn iso(c: &mut Cursor) -> Option<Stamp> {
+    let mut st = Stamp {
+        year: c.digits(4)?,
+        ..Default::default()
+    };
+    let extended = c.eat(b'-');
+    st.month = c.digits(2)?;
+    if extended && !c.eat(b'-') {
+        return None;
+    }
+    st.day = c.digits(2)?;
+    if !c.done() {
+        if !(c.eat(b'T') || c.eat(b't') || c.eat(b' ')) {
+            return None;
+        }
+        st.hour = c.digits(2)?;
+        c.eat(b':');
+        st.min = c.digits(2)?;
+        if c.eat(b':') || matches!(c.peek(), Some(b'0'..=b'9')) {
+            st.sec = c.digits(2)?;
+            st.frac = c.fraction();
+        }
+    }
+    st.offset = c.zone()?;
+    Some(st)
+}
+
+/// `Tue, 14 Nov 2023 22:13:20 +0000`; the day of the week and the seconds are optional.
+fn rfc2822(c: &mut Cursor) -> Option<Stamp> {
+    if !c.word().is_empty() && !c.eat(b',') {
+        return None;
+    }
+    c.skip_spaces();
+    let mut st = Stamp {
+        day: c.number(1, 2)?,
+        ..Default::default()
+    };
+    c.skip_spaces();
+    st.month = c.month()?;
+    c.skip_spaces();
+    st.year = c.digits(4)?;
+    c.skip_spaces();
+    st.hour = c.digits(2)?;
+    c.expect(b':')?;
+    st.min = c.digits(2)?;
+    if c.eat(b':') {
+        st.sec = c.digits(2)?;
+    }
+    st.offset = c.zone()?;
+    Some(st)
+}
+
+/// `[14/Nov/2023:22:13:20 +0000]`, with or without the brackets.
+fn common_log(c: &mut Cursor) -> Option<Stamp> {
+    let bracketed = c.eat(b'[');
+    let mut st = Stamp {
+        day: c.digits(2)?,
+        ..Default::default()
+    };
+    c.expect(b'/')?;
+    st.month = c.month()?;
+    c.expect(b'/')?;
+    st.year = c.digits(4)?;
+    c.expect(b':')?;
+    st.hour = c.digits(2)?;
+    c.expect(b':')?;
+    st.min = c.digits(2)?;
+    c.expect(b':')?;
+    st.sec = c.digits(2)?;
+    st.frac = c.fraction();
+    if bracketed {
+        let end = c.bs.len() - 1;
+        if c.bs[end] != b']' {
+            return None;
+        }
+        c.bs = &c.bs[..end];
+    }
+    st.offset = c.zone()?;
+    Some(st)
+}
+
+/// Parse the timestamp in `s`, returning the number of seconds since the Unix epoch, or -1 if `s`
+/// is not a timestamp in a format we recognize.
+pub(crate) fn parsedate(s: &[u8]) -> Float {
+    let start = s.iter().position(|b| !b.is_ascii_whitespace());
+    let end = s.iter().rposition(|b| !b.is_ascii_whitespace());
+    let s = match (start, end) {
+        (Some(start), Some(end)) => &s[start..=end],
+        _ => return -1.0,
+    };
+    for parse in [iso, rfc2822, common_log] {
+        let mut c = Cursor::new(s);
+        if let Some(res) = parse(&mut c)
+            .filter(|_| c.done())
+            .and_then(|st| st.to_epoch())
+        {
+            return res;
+        }
+    }
+    -1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iso_8601() {
+        assert_eq!(parsedate(b"2023-11-14T22:13:20Z"), 1700000000.0);
+        assert_eq!(parsedate(b" 2023-11-14t22:13:20.25z\n"), 1700000000.25);
+        assert_eq!(parsedate(b"2023-11-15 03:43:20+05:30"), 1700000000.0);
+        assert_eq!(parsedate(b"2023-11-14T17:13:20,5 -0500"), 1700000000.5);
+        assert_eq!(parsedate(b"2023-11-14T23:13:20+01"), 1700000000.0);
+        assert_eq!(parsedate(b"2023-11-14 22:13:20 UTC"), 1700000000.0);
+        assert_eq!(parsedate(b"20231114T221320Z"), 1700000000.0);
+        assert_eq!(parsedate(b"2023-11-14T22:13Z"), 1699999980.0);
+        assert_eq!(parsedate(b"1970-01-01T00:00:00Z"), 0.0);
+        assert_eq!(parsedate(b"1969-12-31T00:00:00Z"), -86400.0);
+        assert_eq!(parsedate(b"2000-02-29T00:00:00Z"), 951782400.0);
+    }
+
+    #[test]
+    fn other_formats() {
+        assert_eq!(parsedate(b"Tue, 14 Nov 2023 22:13:20 +0000"), 1700000000.0);
+        assert_eq!(parsedate(b"14 Nov 2023 22:13:20 GMT"), 1700000000.0);
+        assert_eq!(
+            parsedate(b"Wed, 15 November 2023 03:43 +0530"),
+            1699999980.0
+        );
+        assert_eq!(parsedate(b"[14/Nov/2023:22:13:20 +0000]"), 1700000000.0);
+        assert_eq!(parsedate(b"14/Nov/2023:23:13:20 +0100"), 1700000000.0);
+    }
+
+    #[test]
+    fn invalid() {
+        for s in [
+            "",
+            "  ",
+            "yesterday",
+            "2023-11-14T22:13:20Q",
+            "2023-13-01",
+            "2023-02-29T00:00:00Z",
+            "2023-11-14T24:00:00Z",
+            "2023-11-14T22:13:20+2400",
+            "2023-11-14T22:13:20+",
+            "2023-1114",
+            "Tue 14 Nov 2023 22:13:20 +0000",
+            "[14/Nov/2023:22:13:20 +0000",
+            "14/Foo/2023:22:13:20 +0000",
+        ] {
+            assert_eq!(parsedate(s.as_bytes()), -1.0, "s={:?}", s);
+        }
+    }
+}
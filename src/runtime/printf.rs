@@ -8,9 +8,11 @@
 //! byte sequences with REPLACEMENT CHARACTER using String's from_utf8_lossy function. This means
 //! that users hoping to output raw bytes using `printf` (as may be necessary, given that print
 //! appends a newline) may find some bytes replaced inadvertently. We could solve this by adding a
-//! new print function that does not append a newline.
+//! new print function that does not append a newline. Replacements in `%s` arguments are reported
+//! as `encoding` warnings.
 use crate::common::Result;
-use crate::runtime::{convert, strtoi, Float, Int, Str};
+use crate::runtime::warnings::{Warning, Warnings};
+use crate::runtime::{convert, float_parse, strtod_locale, strtoi, Config, Float, Int, Str};
 
 use std::convert::TryFrom;
 use std::fmt;
@@ -38,13 +40,6 @@ impl Write for StackWriter {
     }
 }
 
-struct DisplayBytes<'a>(&'a [u8]);
-impl<'a> fmt::Display for DisplayBytes<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&*std::string::String::from_utf8_lossy(self.0), fmt)
-    }
-}
-
 #[derive(Clone, Debug)]
 pub(crate) enum FormatArg<'a> {
     S(Str<'a>),
@@ -83,10 +78,11 @@ impl<'a> From<Float> for FormatArg<'a> {
 }
 
 impl<'a> FormatArg<'a> {
-    fn to_float(&self) -> f64 {
+    // Strings are converted with ',' as the decimal separator if `decimal_comma` is set.
+    fn to_float(&self, decimal_comma: bool) -> f64 {
         use FormatArg::*;
         match self {
-            S(s) => convert::<_, f64>(s),
+            S(s) => s.with_bytes(|bs| strtod_locale(bs, decimal_comma)),
             F(f) => *f,
             I(i) => convert::<_, f64>(*i),
         }
@@ -114,123 +110,339 @@ impl<'a> FormatArg<'a> {
 struct FormatSpec {
     // leading '-' ? -- left justification.
     minus: bool,
-    // number to the left of '.', if any
+    // leading '+' ? -- always print the sign of a signed conversion.
+    plus: bool,
+    // leading ' ' ? -- print a space in place of a '+' sign.
+    space: bool,
+    // leading '#' ? -- the "alternate form": a leading 0 for %o, 0x for %x, and a decimal point
+    // (and trailing zeros, for %g) for floating point numbers.
+    alt: bool,
+    // leading '0' ? -- pad numbers with zeros rather than spaces.
     leading_zeros: bool,
+    // leading '\'' ? -- group the digits of integers and floats in threes.
+    group: bool,
     // padding
     lnum: usize,
-    // maximum string width, or floating point precision.
+    // maximum string width, floating point precision, or minimum number of integer digits.
     rnum: usize,
     // format specifier: e.g. c, d, s, x.
     spec: u8,
+    // n in `%n$d`: the position of the argument to format, counting from 1.
+    position: Option<usize>,
+    // Whether the width and precision were given as `*`, to be read from the arguments.
+    star_width: bool,
+    star_prec: bool,
 }
 
 impl Default for FormatSpec {
     fn default() -> FormatSpec {
         FormatSpec {
             minus: false,
+            plus: false,
+            space: false,
+            alt: false,
             leading_zeros: false,
+            group: false,
             lnum: 0,
             rnum: usize::max_value(),
             spec: b'z', /* invalid */
+            position: None,
+            star_width: false,
+            star_prec: false,
+        }
+    }
+}
+
+impl FormatSpec {
+    fn precision(&self) -> Option<usize> {
+        if self.rnum == usize::max_value() {
+            None
+        } else {
+            Some(self.rnum)
         }
     }
 }
 
 fn is_spec(c: u8) -> bool {
-    matches!(c, b'f' | b'c' | b'd' | b'e' | b'g' | b'o' | b's' | b'x')
+    matches!(
+        c,
+        b'%' | b'c'
+            | b'd'
+            | b'i'
+            | b'o'
+            | b'u'
+            | b'x'
+            | b'X'
+            | b'e'
+            | b'E'
+            | b'f'
+            | b'F'
+            | b'g'
+            | b'G'
+            | b's'
+    )
 }
 
-fn process_spec(mut w: impl Write, fspec: &mut FormatSpec, arg: &FormatArg) -> Result<()> {
-    macro_rules! match_for_spec {
-        ($s:expr, $arg:expr) => {
-            match (
-                fspec.minus,
-                fspec.leading_zeros,
-                fspec.lnum,
-                fspec.rnum == usize::max_value(),
-            ) {
-                (true, true, lnum, true) => write!(w, concat!("{:0<l$", $s, "}"), $arg, l = lnum),
-                (true, false, lnum, true) => write!(w, concat!("{:<l$", $s, "}"), $arg, l = lnum),
-                (true, true, lnum, false) => write!(
-                    w,
-                    concat!("{:0<l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-                (true, false, lnum, false) => write!(
-                    w,
-                    concat!("{:<l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-                (false, true, lnum, true) => write!(w, concat!("{:0>l$", $s, "}"), $arg, l = lnum),
-                (false, false, lnum, true) => write!(w, concat!("{:>l$", $s, "}"), $arg, l = lnum),
-                (false, true, lnum, false) => write!(
-                    w,
-                    concat!("{:0>l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-                (false, false, lnum, false) => write!(
-                    w,
-                    concat!("{:>l$.r$", $s, "}"),
-                    $arg,
-                    l = lnum,
-                    r = fspec.rnum
-                ),
-            }
-        };
+/// Insert `sep` between each group of three digits in the integer part of a formatted number.
+fn group_digits(bs: &[u8], sep: u8) -> SmallVec<u8> {
+    let start = if matches!(bs.first(), Some(b'-' | b'+')) {
+        1
+    } else {
+        0
+    };
+    let end = bs[start..]
+        .iter()
+        .position(|b| !b.is_ascii_digit())
+        .map_or(bs.len(), |ix| start + ix);
+    let mut res = SmallVec::new();
+    res.extend_from_slice(&bs[..start]);
+    for (i, b) in bs[start..end].iter().enumerate() {
+        if i > 0 && (end - start - i) % 3 == 0 {
+            res.push(sep);
+        }
+        res.push(*b);
+    }
+    res.extend_from_slice(&bs[end..]);
+    res
+}
+
+/// Write `f` (which must be finite and non-negative) in scientific notation with `prec` digits
+/// after the decimal point and an exponent of at least two digits, as C does: `1.500000e+03`.
+fn write_exp(w: &mut StackWriter, f: Float, prec: usize, upper: bool) -> Result<()> {
+    let mut buf = StackWriter::default();
+    wrap_result(write!(buf, "{:.p$e}", f, p = prec))?;
+    let e = buf.0.iter().position(|b| *b == b'e').unwrap();
+    let exp = strtoi(&buf.0[e + 1..]);
+    w.0.extend_from_slice(&buf.0[..e]);
+    let sign = if exp < 0 { '-' } else { '+' };
+    let e = if upper { 'E' } else { 'e' };
+    wrap_result(write!(w, "{}{}{:02}", e, sign, exp.abs()))
+}
+
+/// Remove the trailing zeros after the decimal point of a number in `bs` (before any exponent),
+/// and the decimal point itself if nothing is left after it.
+fn trim_zeros(bs: &mut StackWriter) {
+    let end = bs.0.iter().position(|b| matches!(b, b'e' | b'E'));
+    let mantissa = end.unwrap_or(bs.len());
+    if !bs.0[..mantissa].contains(&b'.') {
+        return;
+    }
+    let mut keep = mantissa;
+    while bs.0[keep - 1] == b'0' {
+        keep -= 1;
     }
-    let res = match fspec.spec {
-        b'f' => {
-            if !fspec.leading_zeros && fspec.lnum == 0 && fspec.rnum == usize::max_value() {
-                // Fast path: use Ryu, which today is more efficient than the standard library.
-                // NB Ryu prints some things a bit differently than most awk implementations.
-                // `write!(w, "{}", arg.to_float())` is a bit closer.
-                let mut buf = ryu::Buffer::new();
-                write!(w, "{}", buf.format(arg.to_float()))
+    if bs.0[keep - 1] == b'.' {
+        keep -= 1;
+    }
+    bs.0.drain(keep..mantissa);
+}
+
+/// Write the digits of a finite, non-negative float for %e, %f or %g (in either case).
+fn write_float(w: &mut StackWriter, fspec: &FormatSpec, f: Float) -> Result<()> {
+    let prec = fspec.precision().unwrap_or(6);
+    match fspec.spec.to_ascii_lowercase() {
+        b'f' => wrap_result(write!(w, "{:.p$}", f, p = prec))?,
+        b'e' => write_exp(w, f, prec, fspec.spec == b'E')?,
+        _ => {
+            // %g uses %e if the exponent is less than -4 or at least the precision, and %f
+            // otherwise, with `prec` significant digits in both cases.
+            let prec = std::cmp::max(prec, 1);
+            let mut sci = StackWriter::default();
+            write_exp(&mut sci, f, prec - 1, fspec.spec == b'G')?;
+            let e = sci.0.iter().position(|b| matches!(b, b'e' | b'E')).unwrap();
+            let exp = strtoi(&sci.0[e + 1..]);
+            let start = w.len();
+            if exp < -4 || exp >= prec as Int {
+                w.0.extend_from_slice(&sci.0);
             } else {
-                match_for_spec!("", arg.to_float())
+                let p = (prec as Int - 1 - exp) as usize;
+                wrap_result(write!(w, "{:.p$}", f, p = p))?;
+            }
+            if !fspec.alt {
+                let mut digits = StackWriter(w.0.drain(start..).collect());
+                trim_zeros(&mut digits);
+                w.0.extend_from_slice(&digits.0);
             }
         }
-        b'e' => match_for_spec!("e", arg.to_float()),
-        b'g' => {
-            let mut buf = StackWriter::default();
-            // %g means "pick the shorter of standard and scientific notation". We do the obvious
-            // thing of computing both and writing out the smaller one.
-            fspec.spec = b'f';
-            process_spec(&mut buf, fspec, arg)?;
-            let l1 = buf.len();
-            fspec.spec = b'e';
-            process_spec(&mut buf, fspec, arg)?;
-            let l2 = buf.len() - l1;
-            let bytes = if l1 < l2 {
-                &buf.0[0..l1]
-            } else {
-                &buf.0[l1..(l1 + l2)]
+    }
+    if fspec.alt && !w.0.contains(&b'.') {
+        // The alternate form always includes a decimal point.
+        let at =
+            w.0.iter()
+                .position(|b| matches!(b, b'e' | b'E'))
+                .unwrap_or(w.len());
+        w.0.insert(at, b'.');
+    }
+    Ok(())
+}
+
+/// The length in bytes of the first character of `bs`, or 1 if `bs` does not start with valid
+/// UTF-8.
+fn first_char_len(bs: &[u8]) -> usize {
+    let valid = match str::from_utf8(bs) {
+        Ok(s) => s,
+        Err(e) => str::from_utf8(&bs[..e.valid_up_to()]).unwrap(),
+    };
+    valid
+        .chars()
+        .next()
+        .map_or(std::cmp::min(bs.len(), 1), char::len_utf8)
+}
+
+/// Write `prefix` (a sign, or `0x`) followed by `body`, padded to the width in `fspec`. If `zeros`
+/// is set and the `0` flag was passed, the padding is zeros placed between the prefix and the
+/// body.
+fn pad(
+    mut w: impl Write,
+    fspec: &FormatSpec,
+    prefix: &[u8],
+    body: &[u8],
+    zeros: bool,
+) -> Result<()> {
+    // Strings are padded by character, rather than by byte.
+    let len = str::from_utf8(body).map_or(body.len(), |s| s.chars().count());
+    let padding = fspec.lnum.saturating_sub(prefix.len() + len);
+    if fspec.minus {
+        write_bytes(&mut w, prefix)?;
+        write_bytes(&mut w, body)?;
+        return wrap_result(write!(w, "{:l$}", "", l = padding));
+    }
+    if zeros && fspec.leading_zeros {
+        write_bytes(&mut w, prefix)?;
+        wrap_result(write!(w, "{:0>l$}", "", l = padding))?;
+        return write_bytes(&mut w, body);
+    }
+    wrap_result(write!(w, "{:l$}", "", l = padding))?;
+    write_bytes(&mut w, prefix)?;
+    write_bytes(&mut w, body)
+}
+
+fn process_spec(w: impl Write, fspec: &FormatSpec, arg: &FormatArg, config: &Config) -> Result<()> {
+    format_spec(w, fspec, arg, config.decimal_comma, &config.warnings)
+}
+
+/// Format `arg` as C's printf would, using ',' as the decimal separator if `comma` is set.
+fn format_spec(
+    w: impl Write,
+    fspec: &FormatSpec,
+    arg: &FormatArg,
+    comma: bool,
+    warnings: &Warnings,
+) -> Result<()> {
+    let mut body = StackWriter::default();
+    let sign = |neg: bool| -> &'static [u8] {
+        if neg {
+            b"-"
+        } else if fspec.plus {
+            b"+"
+        } else if fspec.space {
+            b" "
+        } else {
+            b""
+        }
+    };
+    let (prefix, zeros) = match fspec.spec {
+        b'd' | b'i' | b'o' | b'u' | b'x' | b'X' => {
+            let i = arg.to_int();
+            // Only %d and %i are signed; the others print negative numbers as unsigned.
+            let signed = matches!(fspec.spec, b'd' | b'i');
+            let u = if signed { i.unsigned_abs() } else { i as u64 };
+            let res = match fspec.spec {
+                b'o' => write!(body, "{:o}", u),
+                b'x' => write!(body, "{:x}", u),
+                b'X' => write!(body, "{:X}", u),
+                _ => write!(body, "{}", u),
             };
-            return write_bytes(&mut w, bytes);
+            wrap_result(res)?;
+            // The precision is the minimum number of digits to print. As in C, a precision of 0
+            // prints nothing at all for 0.
+            if let Some(prec) = fspec.precision() {
+                if prec == 0 && u == 0 {
+                    body.0.clear();
+                }
+                let zeros = prec.saturating_sub(body.len());
+                body.0.insert_many(0, std::iter::repeat(b'0').take(zeros));
+            }
+            if fspec.spec == b'o' && fspec.alt && body.0.first() != Some(&b'0') {
+                body.0.insert(0, b'0');
+            }
+            if fspec.group && matches!(fspec.spec, b'd' | b'i' | b'u') {
+                body.0 = group_digits(&body.0, if comma { b'.' } else { b',' });
+            }
+            let prefix: &[u8] = match fspec.spec {
+                b'x' if fspec.alt && u != 0 => b"0x",
+                b'X' if fspec.alt && u != 0 => b"0X",
+                _ if signed => sign(i < 0),
+                _ => b"",
+            };
+            (prefix, fspec.precision().is_none())
+        }
+        b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => {
+            let f = arg.to_float(comma);
+            let upper = fspec.spec.is_ascii_uppercase();
+            if !f.is_finite() {
+                let text: &[u8] = match (f.is_nan(), upper) {
+                    (true, false) => b"nan",
+                    (true, true) => b"NAN",
+                    (false, false) => b"inf",
+                    (false, true) => b"INF",
+                };
+                body.0.extend_from_slice(text);
+                (sign(f.is_sign_negative() && !f.is_nan()), false)
+            } else {
+                write_float(&mut body, fspec, f.abs())?;
+                if comma {
+                    body.0
+                        .iter_mut()
+                        .filter(|b| **b == b'.')
+                        .for_each(|b| *b = b',');
+                }
+                if fspec.group && !matches!(fspec.spec, b'e' | b'E') {
+                    body.0 = group_digits(&body.0, if comma { b'.' } else { b',' });
+                }
+                (sign(f.is_sign_negative()), true)
+            }
         }
-        b'd' => match_for_spec!("", arg.to_int()),
-        b'o' => match_for_spec!("o", arg.to_int()),
-        b'x' => match_for_spec!("x", arg.to_int()),
         b'c' => {
-            // First, see if we have something ascii/UTF8 here
-            match char::try_from(arg.to_int() as u32) {
-                Ok(ch) => match_for_spec!("", ch),
-                // TODO: Unclear what we should do here, write out the raw bytes? write out the
-                // character code? Awk may just write the raw bytes out, but it's hard to say
-                // (different behavior across implementations)
-                _ => match_for_spec!("", "?"),
+            match arg {
+                // Strings that do not look like numbers print their first character.
+                FormatArg::S(s) if s.with_bytes(|bs| float_parse::strnum(bs).is_none()) => {
+                    s.with_bytes(|bs| body.0.extend_from_slice(&bs[..first_char_len(bs)]))
+                }
+                arg => {
+                    // TODO: Unclear what we should do with numbers that are not characters: write
+                    // out the raw bytes? Different awks behave differently here.
+                    let ch = char::try_from(arg.to_int() as u32).unwrap_or('?');
+                    wrap_result(write!(body, "{}", ch))?;
+                }
             }
+            (&b""[..], false)
+        }
+        b's' => {
+            arg.with_bytes(|bs| {
+                let s = String::from_utf8_lossy(bs);
+                if matches!(s, std::borrow::Cow::Owned(_)) {
+                    warnings.warn(Warning::Encoding, || {
+                        String::from("printf replaced invalid UTF-8 in a %s argument with U+FFFD")
+                    })?;
+                }
+                // The precision is the maximum number of characters to print.
+                let len = match fspec.precision().and_then(|p| s.char_indices().nth(p)) {
+                    Some((ix, _)) => ix,
+                    None => s.len(),
+                };
+                body.0.extend_from_slice(&s.as_bytes()[..len]);
+                Ok(())
+            })?;
+            (&b""[..], false)
+        }
+        b'%' => {
+            body.0.push(b'%');
+            (&b""[..], false)
         }
-        b's' => arg.with_bytes(|bs| match_for_spec!("", DisplayBytes(bs))),
         x => return err!("unsupported format specifier: {}", x),
     };
-    wrap_result(res)
+    pad(w, fspec, prefix, &body.0, zeros)
 }
 
 fn wrap_result<T>(r: std::result::Result<T, impl fmt::Display>) -> Result<()> {
@@ -241,155 +453,131 @@ fn wrap_result<T>(r: std::result::Result<T, impl fmt::Display>) -> Result<()> {
 }
 
 fn write_bytes(mut w: impl Write, bs: &[u8]) -> Result<()> {
-    wrap_result(w.write(bs))
+    wrap_result(w.write_all(bs))
 }
 
-pub(crate) fn printf(mut w: impl Write, spec: &[u8], mut args: &[FormatArg]) -> Result<()> {
-    #[derive(Copy, Clone)]
-    enum State {
-        // Byte index of start of string
-        Raw(usize),
-        // Byte index of percent sign
-        Format(usize),
-    }
-
-    use State::*;
-    let mut iter = spec.iter().cloned().enumerate();
-    macro_rules! next_state {
-        ($e:expr) => {
-            match $e {
-                Some((_, b'%')) => Format(0),
-                Some(_) => Raw(0),
-                None => return Ok(()),
-            }
-        };
+/// Parse the format directive following a '%' at the start of `bs`, returning it along with its
+/// length. Returns None if `bs` does not start with a valid directive.
+fn parse_directive(bs: &[u8]) -> Option<(FormatSpec, usize)> {
+    let mut fs = FormatSpec::default();
+    let digits = |ix: usize| bs[ix..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut ix = 0;
+    // Positional arguments, as in gawk: `%2$s` formats the second argument.
+    let position = digits(0);
+    if position > 0 && bs.get(position) == Some(&b'$') {
+        match strtoi(&bs[..position]) {
+            n if n > 0 => fs.position = Some(n as usize),
+            _ => return None,
+        }
+        ix = position + 1;
     }
-    let mut state = next_state!(iter.next());
-    let default = FormatArg::S(Default::default());
-    let mut next_arg = || {
-        if args.is_empty() {
-            &default
+    while let Some(ch) = bs.get(ix) {
+        match ch {
+            b'-' => fs.minus = true,
+            b'+' => fs.plus = true,
+            b' ' => fs.space = true,
+            b'#' => fs.alt = true,
+            b'0' => fs.leading_zeros = true,
+            b'\'' => fs.group = true,
+            _ => break,
+        }
+        ix += 1;
+    }
+    if bs.get(ix) == Some(&b'*') {
+        fs.star_width = true;
+        ix += 1;
+    } else {
+        let width = digits(ix);
+        fs.lnum = strtoi(&bs[ix..ix + width]) as usize;
+        ix += width;
+    }
+    if bs.get(ix) == Some(&b'.') {
+        ix += 1;
+        if bs.get(ix) == Some(&b'*') {
+            fs.star_prec = true;
+            ix += 1;
         } else {
-            let res = &args[0];
-            args = &args[1..];
-            res
+            let prec = digits(ix);
+            fs.rnum = strtoi(&bs[ix..ix + prec]) as usize;
+            ix += prec;
         }
-    };
-    let mut buf = SmallVec::new();
-    'outer: loop {
-        match state {
-            Raw(start) => {
-                for (ix, ch) in iter.by_ref() {
-                    if ch == b'%' {
-                        write_bytes(&mut w, &spec[start..ix])?;
-                        state = Format(ix);
-                        continue 'outer;
-                    }
-                }
-                write_bytes(&mut w, &spec[start..])?;
-                break 'outer;
+    }
+    match bs.get(ix) {
+        Some(ch) if is_spec(*ch) => {
+            fs.spec = *ch;
+            Some((fs, ix + 1))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn printf(
+    mut w: impl Write,
+    spec: &[u8],
+    all_args: &[FormatArg],
+    config: &Config,
+) -> Result<()> {
+    let default = FormatArg::S(Default::default());
+    let mut args = all_args.iter();
+    // Whether directives pick their arguments by position, once we have seen one that does or
+    // does not. As in gawk, a format string cannot mix the two.
+    let mut positional = None;
+    // Start of the text not yet written, and of the text left to search for directives.
+    let mut start = 0;
+    let mut search = 0;
+    while let Some(off) = memchr::memchr(b'%', &spec[search..]) {
+        let pct = search + off;
+        // AWK is, as usual, rather permissive when it comes to invalid format specifiers: if
+        // something is formatted incorrectly, it is simply treated like a normal string.
+        let (mut fs, len) = match parse_directive(&spec[pct + 1..]) {
+            Some(directive) => directive,
+            None => {
+                search = pct + 1;
+                continue;
             }
-            Format(start) => {
-                let mut fs = FormatSpec::default();
-                #[derive(Copy, Clone)]
-                enum Stage {
-                    Begin,
-                    Lnum,
-                    Rnum,
-                }
-                use Stage::*;
-                let mut stage = Begin;
-                let mut next = iter.next();
-                // AWK is, as usual, rather permissive when it comes to invalid format specifiers:
-                // If something is formatted incorrectly, it is simply treated like a normal
-                // string. We implement by checking for error conditions and `break`ing out of the
-                // inner loop, which will change state to Raw(start).
-                while let Some((ix, ch)) = next {
-                    if !ch.is_ascii() {
-                        // We cast characters to bytes in what follows.
-                        break;
-                    }
-                    match (ch, stage) {
-                        (b'%', Begin) => {
-                            fs.spec = b'%';
-                            process_spec(&mut w, &mut fs, next_arg())?;
-                            state = Raw(ix + 1);
-                            continue 'outer;
-                        }
-                        (ch, _) if is_spec(ch) => {
-                            fs.spec = ch as u8;
-                            process_spec(&mut w, &mut fs, next_arg())?;
-                            state = Raw(ix + 1);
-                            continue 'outer;
-                        }
-                        (b'-', Begin) => {
-                            stage = Lnum;
-                            fs.minus = true;
-                        }
-                        (b'-', _) | (b'%', _) => break,
-                        (ch, Lnum) | (ch, Begin) => {
-                            if fs.lnum != 0 {
-                                break;
-                            }
-                            buf.clear();
-                            if ch == b'0' {
-                                fs.leading_zeros = true;
-                            } else if ch == b'.' {
-                                stage = Rnum;
-                                continue;
-                            } else {
-                                buf.push(ch);
-                            };
-                            next = None;
-                            for (ix, ch) in iter.by_ref() {
-                                if !matches!(ch, b'0'..=b'9') {
-                                    next = Some((ix, ch));
-                                    break;
-                                }
-                                buf.push(ch);
-                            }
-                            let num = strtoi(&buf[..]);
-                            if num < 0 {
-                                break;
-                            }
-                            fs.lnum = num as usize;
-                            stage = Rnum;
-                            continue;
-                        }
-                        (ch, Rnum) => {
-                            if fs.rnum != usize::max_value() {
-                                break;
-                            }
-                            if ch != b'.' {
-                                break;
-                            }
-                            buf.clear();
-                            next = None;
-                            for (ix, ch) in iter.by_ref() {
-                                if !matches!(ch, b'0'..=b'9') {
-                                    next = Some((ix, ch));
-                                    break;
-                                }
-                                buf.push(ch);
-                            }
-                            let num = strtoi(&buf[..]);
-                            if num < 0 {
-                                break;
-                            }
-                            fs.rnum = num as usize;
-                            continue;
-                        }
-                    };
-                    next = iter.next();
-                }
-                // We do not have a complete format specifier, and we have exhausted the string.
-                // Just print it out.
-                state = Raw(start);
-                continue 'outer;
+        };
+        write_bytes(&mut w, &spec[start..pct])?;
+        // A `*` width or precision is read from the next argument, before the value to format.
+        // As in C, a negative width means left justification, and a negative precision is
+        // ignored.
+        if fs.star_width || fs.star_prec {
+            if fs.position.is_some() {
+                return user_err!(
+                    "printf: format string {:?} uses `*` in a positional (%n$) directive",
+                    String::from_utf8_lossy(spec)
+                );
+            }
+        }
+        if fs.star_width {
+            let width = args.next().unwrap_or(&default).to_int();
+            fs.minus |= width < 0;
+            fs.lnum = width.unsigned_abs() as usize;
+        }
+        if fs.star_prec {
+            let prec = args.next().unwrap_or(&default).to_int();
+            if prec >= 0 {
+                fs.rnum = prec as usize;
             }
         }
+        let arg = if fs.spec == b'%' {
+            &default
+        } else {
+            if *positional.get_or_insert(fs.position.is_some()) != fs.position.is_some() {
+                return user_err!(
+                    "printf: format string {:?} mixes positional (%n$) and ordinary directives",
+                    String::from_utf8_lossy(spec)
+                );
+            }
+            match fs.position {
+                Some(n) => all_args.get(n - 1).unwrap_or(&default),
+                None => args.next().unwrap_or(&default),
+            }
+        };
+        process_spec(&mut w, &fs, arg, config)?;
+        start = pct + 1 + len;
+        search = start;
     }
-    Ok(())
+    write_bytes(&mut w, &spec[start..])
 }
 
 #[cfg(test)]
@@ -402,7 +590,7 @@ mod tests {
         ($fmt:expr $(, $e:expr)*) => {{
             let mut v = Vec::<u8>::new();
             let w = Cursor::new(&mut v);
-            printf(w, $fmt, &[$( $e.into() ),*]).expect("printf failure");
+            printf(w, $fmt, &[$( $e.into() ),*], &Config::default()).expect("printf failure");
             String::from_utf8(v).expect("printf should produce valid utf8")
         }}
     }
@@ -417,16 +605,17 @@ mod tests {
             w,
             b"Hi %s, to my %d friends %f percent of the time: %g!",
             &[S("there".into()), F(2.5), I(1), F(1.25369E23)],
+            &Config::default(),
         )
         .expect("printf failed");
         let s = str::from_utf8(&v[..]).unwrap();
         assert_eq!(
             s,
-            "Hi there, to my 2 friends 1.0 percent of the time: 1.25369e23!"
+            "Hi there, to my 2 friends 1.000000 percent of the time: 1.25369e+23!"
         );
 
         let s2 = sprintf!(b"%e %d ~~ %s", 12535, 3, "hi");
-        assert_eq!(s2.as_str(), "1.2535e4 3 ~~ hi");
+        assert_eq!(s2.as_str(), "1.253500e+04 3 ~~ hi");
     }
 
     #[test]
@@ -437,6 +626,45 @@ mod tests {
         assert_eq!(s2.as_str(), "|%-10.");
     }
 
+    #[test]
+    fn digit_grouping() {
+        let s1 = sprintf!(b"%'d|%'d|%'d|%'d", 1234567, -1234, 999, 0);
+        assert_eq!(s1.as_str(), "1,234,567|-1,234|999|0");
+        let s2 = sprintf!(
+            b"%'.2f|%'10d|%-'10d|%'010d|",
+            1234567.891,
+            12345,
+            12345,
+            -12345
+        );
+        assert_eq!(
+            s2.as_str(),
+            "1,234,567.89|    12,345|12,345    |-00012,345|"
+        );
+        let s3 = sprintf!(b"%'x %'s", 1234567, "1234");
+        assert_eq!(s3.as_str(), "12d687 1234");
+    }
+
+    #[test]
+    fn decimal_comma_output() {
+        let mut v = Vec::<u8>::new();
+        let fs = FormatSpec {
+            lnum: 9,
+            rnum: 2,
+            spec: b'f',
+            ..Default::default()
+        };
+        format_spec(
+            &mut v,
+            &fs,
+            &FormatArg::F(1234.5),
+            true,
+            &Warnings::default(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(v).unwrap(), "  1234,50");
+    }
+
     #[test]
     fn float_rounding() {
         let s1 = sprintf!(b"%02.2f", 2.375);
@@ -444,4 +672,108 @@ mod tests {
         let s2 = sprintf!(b"%.2f", 2.375);
         assert_eq!(s2.as_str(), "2.38");
     }
+
+    #[test]
+    fn positional_args() {
+        let s1 = sprintf!(b"%2$s %1$s, %2$s!", "world", "hello");
+        assert_eq!(s1.as_str(), "hello world, hello!");
+        let s2 = sprintf!(b"[%1$-6.2f|%1$'+d|%3$s]", 1234.5, "unused");
+        assert_eq!(s2.as_str(), "[1234.50|+1,234|]");
+        // %0$ is not a valid position, so it is printed as-is.
+        let s3 = sprintf!(b"%0$s %s", "x");
+        assert_eq!(s3.as_str(), "%0$s x");
+        let mut v = Vec::<u8>::new();
+        assert!(printf(
+            &mut v,
+            b"%1$s %s",
+            &["a".into(), "b".into()],
+            &Config::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn star_width_precision() {
+        let s1 = sprintf!(b"[%*.*f]", 10, 3, 1.23456);
+        assert_eq!(s1.as_str(), "[     1.235]");
+        let s2 = sprintf!(b"[%*d|%-*s|%.*s]", -5, 42, 4, "ab", 2, "xyz");
+        assert_eq!(s2.as_str(), "[42   |ab  |xy]");
+        let s3 = sprintf!(b"[%.*f|%0*d]", -1, 2.5, "6", 7);
+        assert_eq!(s3.as_str(), "[2.500000|000007]");
+        let mut v = Vec::<u8>::new();
+        assert!(printf(&mut v, b"%1$*d", &[5.into(), 1.into()], &Config::default()).is_err());
+    }
+
+    #[test]
+    fn c_conformance() {
+        use FormatArg::*;
+        // Expected outputs are those of glibc's printf.
+        let cases: &[(&str, FormatArg, &str)] = &[
+            ("%e", F(0.000123456), "1.234560e-04"),
+            ("%E", F(123456.0), "1.234560E+05"),
+            ("%.3e", F(-1234.5678), "-1.235e+03"),
+            ("%.2e", F(9.999), "1.00e+01"),
+            ("%e", F(0.0), "0.000000e+00"),
+            ("%.0e", F(5.0), "5e+00"),
+            ("%#.0e", F(5.0), "5.e+00"),
+            ("%g", F(0.0001), "0.0001"),
+            ("%g", F(0.00001), "1e-05"),
+            ("%g", F(100.0), "100"),
+            ("%g", F(123456.0), "123456"),
+            ("%g", F(1234567.0), "1.23457e+06"),
+            ("%G", F(0.000001234), "1.234E-06"),
+            ("%.3g", F(1.23456), "1.23"),
+            ("%#.3g", F(1.0), "1.00"),
+            ("%.0f", F(2.7), "3"),
+            ("%#.0f", F(2.7), "3."),
+            ("%+.2f", F(2.5), "+2.50"),
+            ("% .1f", F(2.26), " 2.3"),
+            ("%010.3f", F(-1.23456), "-00001.235"),
+            ("%F", F(1.5), "1.500000"),
+            ("%5.1f", F(-Float::INFINITY), " -inf"),
+            ("%+E", F(Float::INFINITY), "+INF"),
+            ("%05f", F(Float::NAN), "  nan"),
+            ("%+d", I(5), "+5"),
+            ("% d", I(5), " 5"),
+            ("%+ d", I(5), "+5"),
+            ("%05d", I(-42), "-0042"),
+            ("%-5d|", I(42), "42   |"),
+            ("%.3d", I(7), "007"),
+            ("%08.3d", I(7), "     007"),
+            ("%.0d", I(0), ""),
+            ("%i", F(3.9), "3"),
+            ("%#o", I(8), "010"),
+            ("%#x", I(255), "0xff"),
+            ("%#X", I(255), "0XFF"),
+            ("%#x", I(0), "0"),
+            ("%#06x", I(255), "0x00ff"),
+            ("%x", I(-1), "ffffffffffffffff"),
+            ("%u", I(-1), "18446744073709551615"),
+            ("%8.3s|", S("abcdef".into()), "     abc|"),
+            ("%-4s|", S("é".into()), "é   |"),
+            ("%c", I(65), "A"),
+            ("%c", S("hello".into()), "h"),
+            ("%c", S("65".into()), "A"),
+            ("%3c|", S("é".into()), "  é|"),
+            ("%c", S("".into()), ""),
+            ("100%%", I(1), "100%"),
+        ];
+        for (fmt, arg, expected) in cases {
+            let mut v = Vec::<u8>::new();
+            printf(
+                &mut v,
+                fmt.as_bytes(),
+                std::slice::from_ref(arg),
+                &Config::default(),
+            )
+            .expect("printf failure");
+            assert_eq!(
+                String::from_utf8(v).unwrap().as_str(),
+                *expected,
+                "format={:?} arg={:?}",
+                fmt,
+                arg
+            );
+        }
+    }
 }
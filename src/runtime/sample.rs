@@ -0,0 +1,84 @@
+//! Reservoir sampling, for the `sample` builtin.
+//!
+//! `sample(arr, k, value)` keeps a uniform random sample of up to `k` of the values passed to it
+//! in `arr`, under the keys 1 through `k`. Doing this in one pass requires knowing how many values
+//! have been offered to `arr` so far, which we track here on the side rather than in the map
+//! itself.
+use rand::Rng;
+
+use crate::runtime::{map_state::MapState, Int, IntMap};
+
+/// The number of values offered to each map passed to `sample`.
+#[derive(Default)]
+pub(crate) struct Samples<'a>(MapState<'a, Int>);
+
+impl<'a> Samples<'a> {
+    /// Offer `value` to the sample of up to `k` values kept in `map`, returning the number of
+    /// values offered to `map` so far. Clearing `map` starts a new sample.
+    pub(crate) fn sample<V: Clone + 'a>(
+        &mut self,
+        rng: &mut impl Rng,
+        map: &IntMap<V>,
+        k: Int,
+        value: &V,
+    ) -> Int {
+        let seen = self.0.get(map);
+        if k > 0 && map.len() == 0 {
+            *seen = 0;
+        }
+        *seen += 1;
+        let seen = *seen;
+        // The first k values fill the sample; after that, the nth value replaces a random member
+        // of the sample with probability k/n.
+        let slot = if seen <= k {
+            seen
+        } else {
+            rng.gen_range(1..=seen)
+        };
+        if slot <= k {
+            map.insert(slot, value.clone());
+        }
+        seen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn uniform_sample() {
+        let mut samples = Samples::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        let map: IntMap<Int> = Default::default();
+        let mut hits = vec![0; 10];
+        for _ in 0..2000 {
+            map.clear();
+            for i in 0..10 {
+                assert_eq!(samples.sample(&mut rng, &map, 3, &i), i + 1);
+            }
+            assert_eq!(map.len(), 3);
+            for slot in 1..=3 {
+                hits[map.get(&slot) as usize] += 1;
+            }
+        }
+        // Each value should be kept 600 times on average.
+        for h in hits {
+            assert!((500..700).contains(&h), "hits={}", h);
+        }
+    }
+
+    #[test]
+    fn separate_maps() {
+        let mut samples = Samples::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        let m1: IntMap<Int> = Default::default();
+        let m2: IntMap<Int> = Default::default();
+        samples.sample(&mut rng, &m1, 5, &1);
+        samples.sample(&mut rng, &m1, 5, &2);
+        assert_eq!(samples.sample(&mut rng, &m2, 5, &3), 1);
+        assert_eq!(samples.sample(&mut rng, &m1, 0, &4), 3);
+        assert_eq!(m1.len(), 2);
+    }
+}
@@ -0,0 +1,196 @@
+//! Sorting the contents of a map, for the `asorti` builtin.
+//!
+//! Orderings are named as in gawk's `PROCINFO["sorted_in"]`: `@ind_str_asc` sorts by key as a
+//! string in ascending order, `@val_num_desc` sorts by value as a number in descending order, and
+//! so on. Entries that compare equal are ordered by their keys (as strings), so the result does
+//! not depend on the map's iteration order. Sorting with a user-defined comparison function is
+//! lowered to a call to a helper function written in AWK; see `parsing::add_helpers`.
+use std::cmp::Ordering;
+use std::hash::Hash;
+
+use crate::common::Result;
+use crate::runtime::{convert, strtod_locale, Float, Int, IntMap, SharedMap, Str};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Order {
+    by_value: bool,
+    numeric: bool,
+    descending: bool,
+}
+
+impl Order {
+    fn parse(how: &[u8]) -> Result<Order> {
+        let name = match how.strip_prefix(b"@") {
+            Some(name) => name,
+            None => return Order::invalid(how),
+        };
+        let mut parts = name.split(|b| *b == b'_');
+        let (what, kind, dir) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(what), Some(kind), Some(dir), None) => (what, kind, dir),
+            _ => return Order::invalid(how),
+        };
+        let by_value = match what {
+            b"ind" => false,
+            b"val" => true,
+            _ => return Order::invalid(how),
+        };
+        let numeric = match kind {
+            b"str" => false,
+            b"num" => true,
+            _ => return Order::invalid(how),
+        };
+        let descending = match dir {
+            b"asc" => false,
+            b"desc" => true,
+            _ => return Order::invalid(how),
+        };
+        Ok(Order {
+            by_value,
+            numeric,
+            descending,
+        })
+    }
+
+    fn invalid(how: &[u8]) -> Result<Order> {
+        err!(
+            "invalid sort order {:?}; expected \"@ind_str_asc\", \"@val_num_desc\" or similar, \
+             or the name of a function as a string constant",
+            String::from_utf8_lossy(how)
+        )
+    }
+}
+
+/// A key or value in the form used to compare it.
+pub(crate) enum SortKey {
+    Num(Float),
+    Bytes(Vec<u8>),
+}
+
+impl SortKey {
+    fn compare(&self, other: &SortKey) -> Ordering {
+        match (self, other) {
+            (SortKey::Num(x), SortKey::Num(y)) => x
+                .partial_cmp(y)
+                .unwrap_or_else(|| x.is_nan().cmp(&y.is_nan())),
+            (SortKey::Bytes(x), SortKey::Bytes(y)) => x.cmp(y),
+            // Every key in a sort is converted the same way.
+            (SortKey::Num(_), SortKey::Bytes(_)) => Ordering::Less,
+            (SortKey::Bytes(_), SortKey::Num(_)) => Ordering::Greater,
+        }
+    }
+}
+
+pub(crate) trait Sortable {
+    /// The key to sort `self` by, as a number if `numeric` is set. Strings are converted to
+    /// numbers with ',' as the decimal separator if `decimal_comma` is set.
+    fn sort_key(&self, numeric: bool, decimal_comma: bool) -> SortKey;
+}
+
+impl Sortable for Int {
+    fn sort_key(&self, numeric: bool, _decimal_comma: bool) -> SortKey {
+        if numeric {
+            SortKey::Num(*self as Float)
+        } else {
+            SortKey::Bytes(self.to_string().into_bytes())
+        }
+    }
+}
+
+impl Sortable for Float {
+    fn sort_key(&self, numeric: bool, _decimal_comma: bool) -> SortKey {
+        if numeric {
+            SortKey::Num(*self)
+        } else {
+            convert::<Float, Str>(*self).with_bytes(|bs| SortKey::Bytes(bs.to_vec()))
+        }
+    }
+}
+
+impl<'a> Sortable for Str<'a> {
+    fn sort_key(&self, numeric: bool, decimal_comma: bool) -> SortKey {
+        self.with_bytes(|bs| {
+            if numeric {
+                SortKey::Num(strtod_locale(bs, decimal_comma))
+            } else {
+                SortKey::Bytes(bs.to_vec())
+            }
+        })
+    }
+}
+
+/// Replace the contents of `dest` with the keys of `src`, sorted according to `how` and stored
+/// under the keys 1 through n. Returns n. Numeric sorts convert strings with ',' as the decimal
+/// separator if `decimal_comma` is set.
+pub(crate) fn sort_keys<'a, K, V>(
+    src: &SharedMap<K, V>,
+    dest: &IntMap<Str<'a>>,
+    how: &Str,
+    decimal_comma: bool,
+) -> Result<Int>
+where
+    K: Hash + Eq + Clone + Sortable + Into<Str<'a>>,
+    V: Sortable,
+{
+    let order = how.with_bytes(Order::parse)?;
+    let mut entries: Vec<(SortKey, SortKey, K)> = src.iter(|entries| {
+        entries
+            .map(|(k, v)| {
+                let primary = if order.by_value {
+                    v.sort_key(order.numeric, decimal_comma)
+                } else {
+                    k.sort_key(order.numeric, decimal_comma)
+                };
+                (primary, k.sort_key(false, decimal_comma), k.clone())
+            })
+            .collect()
+    });
+    entries.sort_by(|(x1, y1, _), (x2, y2, _)| {
+        let ord = x1.compare(x2);
+        let ord = if order.descending { ord.reverse() } else { ord };
+        ord.then_with(|| y1.compare(y2))
+    });
+    dest.clear();
+    let n = entries.len() as Int;
+    for (i, (_, _, k)) in entries.into_iter().enumerate() {
+        dest.insert(i as Int + 1, k.into());
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(src: &SharedMap<Str<'static>, Int>, how: &str) -> Vec<String> {
+        let dest: IntMap<Str> = Default::default();
+        let n = sort_keys(src, &dest, &Str::from(how), false).unwrap();
+        (1..=n).map(|i| format!("{}", dest.get(&i))).collect()
+    }
+
+    #[test]
+    fn orders() {
+        let src: SharedMap<Str, Int> = Default::default();
+        for (k, v) in [("b", 2), ("10", 1), ("a", 2), ("9", 3)] {
+            src.insert(Str::from(k), v);
+        }
+        assert_eq!(sorted(&src, "@ind_str_asc"), ["10", "9", "a", "b"]);
+        assert_eq!(sorted(&src, "@ind_num_asc"), ["a", "b", "9", "10"]);
+        assert_eq!(sorted(&src, "@ind_str_desc"), ["b", "a", "9", "10"]);
+        assert_eq!(sorted(&src, "@val_num_desc"), ["9", "a", "b", "10"]);
+        assert_eq!(sorted(&src, "@val_num_asc"), ["10", "a", "b", "9"]);
+    }
+
+    #[test]
+    fn invalid_orders() {
+        for how in [
+            "",
+            "ind_str_asc",
+            "@ind_str",
+            "@key_str_asc",
+            "@val_num_up",
+            "@ind_str_asc_x",
+        ] {
+            assert!(Order::parse(how.as_bytes()).is_err(), "how={}", how);
+        }
+    }
+}
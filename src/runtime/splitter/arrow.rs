@@ -0,0 +1,265 @@
+//! Reading Arrow IPC files (also known as Feather v2) and streams as input records.
+//!
+//! With `-i arrow`, each row of each record batch becomes a record with one field per column of
+//! the input's schema; `$0` holds the fields joined by tabs. Inputs that start with the IPC file
+//! magic are read with the file reader, which only decodes the columns the program uses; all other
+//! inputs (including standard input) are read as IPC streams, in which case unused columns are
+//! decoded but not converted to strings.
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::mem;
+
+use arrow::array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array,
+    Int64Array, Int8Array, LargeBinaryArray, LargeStringArray, StringArray, UInt16Array,
+    UInt32Array, UInt64Array, UInt8Array,
+};
+use arrow::datatypes::{DataType, SchemaRef};
+use arrow::error::{ArrowError, Result as ArrowResult};
+use arrow::ipc::reader::{FileReader, StreamReader};
+use arrow::record_batch::RecordBatch;
+
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::{convert, Float, Int, Str};
+
+use super::{DefaultLine, LineReader, ReaderState};
+
+const FILE_MAGIC: &[u8] = b"ARROW1";
+
+type Batches = Box<dyn Iterator<Item = ArrowResult<RecordBatch>>>;
+
+fn column_str(col: &ArrayRef, row: usize) -> Result<Str<'static>> {
+    macro_rules! value {
+        ($ty:ty) => {
+            col.as_any().downcast_ref::<$ty>().unwrap().value(row)
+        };
+    }
+    if col.is_null(row) {
+        return Ok(Str::default());
+    }
+    Ok(match col.data_type() {
+        DataType::Boolean => convert::<Int, Str>(value!(BooleanArray) as Int),
+        DataType::Int8 => convert::<Int, Str>(value!(Int8Array) as Int),
+        DataType::Int16 => convert::<Int, Str>(value!(Int16Array) as Int),
+        DataType::Int32 => convert::<Int, Str>(value!(Int32Array) as Int),
+        DataType::Int64 => convert::<Int, Str>(value!(Int64Array)),
+        DataType::UInt8 => convert::<Int, Str>(value!(UInt8Array) as Int),
+        DataType::UInt16 => convert::<Int, Str>(value!(UInt16Array) as Int),
+        DataType::UInt32 => convert::<Int, Str>(value!(UInt32Array) as Int),
+        // Values above i64::MAX don't fit in an awk integer; keep their digits intact.
+        DataType::UInt64 => Str::from(value!(UInt64Array).to_string()),
+        DataType::Float32 => convert::<Float, Str>(value!(Float32Array) as Float),
+        DataType::Float64 => convert::<Float, Str>(value!(Float64Array)),
+        DataType::Utf8 => Str::from(value!(StringArray)).unmoor(),
+        DataType::LargeUtf8 => Str::from(value!(LargeStringArray)).unmoor(),
+        DataType::Binary => Str::from(value!(BinaryArray)).unmoor(),
+        DataType::LargeBinary => Str::from(value!(LargeBinaryArray)).unmoor(),
+        ty => return err!("unsupported arrow column type {:?}", ty),
+    })
+}
+
+enum Source {
+    Stdin,
+    File(String),
+}
+
+pub struct ArrowReader {
+    name: Str<'static>,
+    // Inputs are opened on the first read, once the used fields are known.
+    source: Option<Source>,
+    batches: Option<Batches>,
+    batch: Option<RecordBatch>,
+    row: usize,
+    // The schema index of each column in `batch`, and the number of columns in the schema.
+    columns: Vec<usize>,
+    ncols: usize,
+    used_fields: FieldSet,
+    state: ReaderState,
+    check_utf8: bool,
+    // Used to trigger updating FILENAME on the first read.
+    start: bool,
+}
+
+impl ArrowReader {
+    /// Read the IPC file or stream at `path`, or standard input if `path` is "-".
+    pub fn new(path: &str, check_utf8: bool) -> ArrowReader {
+        let source = if path == "-" {
+            Source::Stdin
+        } else {
+            Source::File(path.into())
+        };
+        ArrowReader {
+            name: Str::from(path).unmoor(),
+            source: Some(source),
+            batches: None,
+            batch: None,
+            row: 0,
+            columns: Vec::new(),
+            ncols: 0,
+            used_fields: FieldSet::all(),
+            state: ReaderState::OK,
+            check_utf8,
+            start: true,
+        }
+    }
+
+    fn used(&self, col: usize) -> bool {
+        // $0 needs every column.
+        self.used_fields.get(0) || self.used_fields.get(col + 1)
+    }
+
+    fn set_schema(&mut self, schema: &SchemaRef, projection: Option<&[usize]>) {
+        self.ncols = schema.fields().len();
+        self.columns = match projection {
+            Some(cols) => cols.to_vec(),
+            None => (0..self.ncols).collect(),
+        };
+    }
+
+    fn open_stream(&mut self, r: impl Read + 'static) -> ArrowResult<Batches> {
+        let reader = StreamReader::try_new(r, None)?;
+        self.set_schema(&reader.schema(), None);
+        Ok(Box::new(reader))
+    }
+
+    fn open_file(&mut self, path: &str) -> Result<Batches> {
+        let open = || File::open(path).map_err(|e| err_raw!("failed to open {}: {}", path, e));
+        let mut file = open()?;
+        let mut magic = [0u8; 6];
+        let is_file = match file.read_exact(&mut magic) {
+            Ok(()) => magic == FILE_MAGIC,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => false,
+            Err(e) => return err!("failed to read {}: {}", path, e),
+        };
+        if let Err(e) = file.seek(SeekFrom::Start(0)) {
+            return err!("failed to read {}: {}", path, e);
+        }
+        let wrap = |e: ArrowError| err_raw!("failed to read arrow input {}: {}", path, e);
+        if !is_file {
+            return self.open_stream(BufReader::new(file)).map_err(wrap);
+        }
+        // The file footer holds the schema; read it before deciding which columns to decode.
+        let reader = FileReader::try_new(BufReader::new(file), None).map_err(wrap)?;
+        let schema = reader.schema();
+        let mut projection: Vec<usize> = (0..schema.fields().len())
+            .filter(|col| self.used(*col))
+            .collect();
+        if projection.is_empty() && !schema.fields().is_empty() {
+            // Batches need at least one column to record how many rows they have.
+            projection.push(0);
+        }
+        if projection.len() == schema.fields().len() {
+            self.set_schema(&schema, None);
+            return Ok(Box::new(reader));
+        }
+        let reader =
+            FileReader::try_new(BufReader::new(open()?), Some(projection.clone())).map_err(wrap)?;
+        self.set_schema(&schema, Some(&projection[..]));
+        Ok(Box::new(reader))
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let batches = match self.source.take() {
+            Some(Source::Stdin) => self
+                .open_stream(io::stdin())
+                .map_err(|e| err_raw!("failed to read arrow input from stdin: {}", e))?,
+            Some(Source::File(path)) => self.open_file(path.as_str())?,
+            None => return Ok(()),
+        };
+        self.batches = Some(batches);
+        Ok(())
+    }
+
+    // Advance to the next row, loading a new batch if needed. Returns false at the end of input.
+    fn advance(&mut self) -> Result<bool> {
+        self.open()?;
+        loop {
+            if let Some(batch) = &self.batch {
+                if self.row < batch.num_rows() {
+                    return Ok(true);
+                }
+            }
+            self.row = 0;
+            self.batch = match self.batches.as_mut().and_then(|b| b.next()) {
+                Some(Ok(batch)) => Some(batch),
+                Some(Err(e)) => return err!("failed to read arrow input {}: {}", self.name, e),
+                None => return Ok(false),
+            };
+        }
+    }
+
+    fn read_row(&mut self, fields: &mut Vec<Str<'static>>) -> Result<()> {
+        let batch = self.batch.as_ref().unwrap();
+        fields.clear();
+        fields.resize_with(self.ncols, Str::default);
+        for (col, schema_ix) in batch.columns().iter().zip(self.columns.iter().cloned()) {
+            if !self.used(schema_ix) {
+                continue;
+            }
+            let s = column_str(col, self.row)?;
+            if self.check_utf8 && s.with_bytes(|bs| std::str::from_utf8(bs).is_err()) {
+                return err!("arrow input {} contains invalid UTF-8", self.name);
+            }
+            fields[schema_ix] = s;
+        }
+        self.row += 1;
+        Ok(())
+    }
+}
+
+impl LineReader for ArrowReader {
+    type Line = DefaultLine;
+    fn filename(&self) -> Str<'static> {
+        self.name.clone()
+    }
+    fn check_utf8(&self) -> bool {
+        self.check_utf8
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
+        let mut line = DefaultLine::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        _pat: &Str,
+        _rc: &mut super::RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        let start = mem::replace(&mut self.start, false);
+        old.used_fields = self.used_fields.clone();
+        old.diverged = false;
+        if self.advance()? {
+            self.read_row(&mut old.fields)?;
+            old.line = if self.used_fields.get(0) {
+                Str::from("\t").join_slice(&old.fields[..])
+            } else {
+                Str::default()
+            };
+            self.state = ReaderState::OK;
+        } else {
+            old.line = Str::default();
+            old.fields.clear();
+            self.state = ReaderState::Eof;
+        }
+        Ok(/* file changed */ start)
+    }
+    fn read_state(&self) -> i64 {
+        self.state as i64
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        // There is just one file. Set EOF.
+        self.source = None;
+        self.batches = None;
+        self.batch = None;
+        self.state = ReaderState::Eof;
+        Ok(false)
+    }
+    fn file_index(&self) -> Int {
+        1
+    }
+    fn set_used_fields(&mut self, used_fields: &FieldSet) {
+        self.used_fields = used_fields.clone();
+    }
+}
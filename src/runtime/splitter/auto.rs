@@ -0,0 +1,320 @@
+//! Reading inputs whose format is detected from their contents, for `--auto`.
+//!
+//! The start of each input is examined before any of it is split. Inputs beginning with the gzip
+//! magic number are decompressed. The first few lines are then used to pick a format: TSV if
+//! they all contain the same (nonzero) number of tabs, CSV if they all contain the same number of
+//! separators outside of quotes, and whitespace-separated fields otherwise. With `--stats`, the
+//! format chosen for each input is included in the summary.
+//!
+//! Records are split one line at a time, so this is slower than reading the same input with `-i`
+//! or `-F`. CSV and TSV fields follow the same quoting and escaping rules as `-i csv` and `-i tsv`;
+//! whitespace-separated records are split according to `FS` as usual.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use std::mem;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::{warnings::Warnings, Int, Str};
+
+use super::batch::{split_csv, split_tsv, CsvDialect};
+use super::{DefaultLine, LineReader, ReaderState};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+// Detection stops reading the start of an input once it has this many lines, or bytes.
+const SNIFF_LINES: usize = 16;
+const SNIFF_BYTES: usize = 64 << 10;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Whitespace,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Tsv => "tsv",
+            Format::Whitespace => "whitespace-separated",
+        }
+    }
+}
+
+// The number of occurrences of `sep` outside of quotes in `line`. Quotes are assumed to be
+// balanced within a line.
+fn count_sep(line: &[u8], sep: u8, quote: u8) -> usize {
+    let mut quoted = false;
+    let mut n = 0;
+    for &b in line {
+        if b == quote {
+            quoted = !quoted;
+        } else if b == sep && !quoted {
+            n += 1;
+        }
+    }
+    n
+}
+
+// Whether every one of `lines` has the same, nonzero, number of `sep`s.
+fn consistent(lines: &[&[u8]], sep: u8, quote: u8) -> bool {
+    let mut counts = lines.iter().map(|l| count_sep(l, sep, quote));
+    match counts.next() {
+        Some(n) if n > 0 => counts.all(|m| m == n),
+        _ => false,
+    }
+}
+
+// Pick a format for an input beginning with `head`. `eof` is whether `head` is the whole input.
+fn sniff(head: &[u8], eof: bool, dialect: CsvDialect) -> Format {
+    // A trailing partial line could be cut off anywhere, so it is left out unless it is all there
+    // is.
+    let complete = match memchr::memrchr(b'\n', head) {
+        Some(ix) if !eof => &head[..ix],
+        _ => head,
+    };
+    let lines: Vec<&[u8]> = complete
+        .split(|&b| b == b'\n')
+        .filter(|l| !l.iter().all(|b| b.is_ascii_whitespace()))
+        .take(SNIFF_LINES)
+        .collect();
+    // Tabs are rare in prose, so they are considered first.
+    if consistent(&lines, b'\t', 0) {
+        Format::Tsv
+    } else if consistent(&lines, dialect.sep, dialect.quote) {
+        Format::Csv
+    } else {
+        Format::Whitespace
+    }
+}
+
+// Read the start of `r`, returning it along with whether it is all there is.
+fn read_head(r: &mut impl Read) -> io::Result<(Vec<u8>, bool)> {
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = match r.read(&mut chunk) {
+            Ok(0) => return Ok((head, true)),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        head.extend_from_slice(&chunk[..n]);
+        if head.len() >= SNIFF_BYTES || memchr::memchr_iter(b'\n', &head).count() > SNIFF_LINES {
+            return Ok((head, false));
+        }
+    }
+}
+
+type Input = Box<dyn BufRead + Send>;
+
+// Open `path` (standard input if it is "-"), decompressing it if needed, and detect its format.
+fn open(path: &str, dialect: CsvDialect) -> io::Result<(Input, Format, bool)> {
+    let mut file: Box<dyn Read + Send> = if path == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+    let (mut head, mut eof) = read_head(&mut file)?;
+    let gzip = head.starts_with(GZIP_MAGIC);
+    if gzip {
+        let mut decoder: Box<dyn Read + Send> =
+            Box::new(MultiGzDecoder::new(Cursor::new(head).chain(file)));
+        let (h, e) = read_head(&mut decoder)?;
+        head = h;
+        eof = e;
+        file = decoder;
+    }
+    let format = sniff(&head, eof, dialect);
+    let input = BufReader::new(Cursor::new(head).chain(file));
+    Ok((Box::new(input), format, gzip))
+}
+
+pub struct AutoReader {
+    name: Str<'static>,
+    dialect: CsvDialect,
+    // The input and its format, once it has been opened.
+    inner: Option<(Input, Format)>,
+    buf: Vec<u8>,
+    used_fields: FieldSet,
+    state: ReaderState,
+    check_utf8: bool,
+    // Used to trigger updating FILENAME on the first read.
+    start: bool,
+    // Where the detected format is noted for `--stats`.
+    warnings: Warnings,
+}
+
+impl AutoReader {
+    /// Read the input at `path`, or standard input if `path` is "-". `dialect` is used for inputs
+    /// detected as CSV, and the format detected is noted in the `--stats` summary of `warnings`.
+    pub fn new(
+        path: &str,
+        dialect: CsvDialect,
+        check_utf8: bool,
+        warnings: &Warnings,
+    ) -> AutoReader {
+        AutoReader {
+            name: Str::from(path).unmoor(),
+            dialect,
+            inner: None,
+            buf: Vec::new(),
+            used_fields: FieldSet::all(),
+            state: ReaderState::OK,
+            check_utf8,
+            start: true,
+            warnings: warnings.clone(),
+        }
+    }
+
+    // Read the next record into `buf`, returning false at the end of the input.
+    fn read_record(&mut self) -> Result<bool> {
+        if self.inner.is_none() {
+            let path = self.name.to_string();
+            let (input, format, gzip) = match open(path.as_str(), self.dialect) {
+                Ok(opened) => opened,
+                Err(e) => return err!("failed to read {}: {}", path, e),
+            };
+            self.warnings.note(format!(
+                "input {}: {}{}",
+                path,
+                if gzip { "gzip, " } else { "" },
+                format.name()
+            ));
+            self.inner = Some((input, format));
+        }
+        let quote = self.dialect.quote;
+        let (input, format) = self.inner.as_mut().unwrap();
+        self.buf.clear();
+        loop {
+            match input.read_until(b'\n', &mut self.buf) {
+                Ok(0) => break,
+                // A newline within quotes is part of the field.
+                Ok(_) if *format == Format::Csv => {
+                    let quotes = self.buf.iter().filter(|&&b| b == quote);
+                    if quotes.count() % 2 == 0 {
+                        break;
+                    }
+                }
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.state = ReaderState::Error;
+                    return err!("failed to read {}: {}", self.name, e);
+                }
+            }
+        }
+        if self.buf.is_empty() {
+            return Ok(false);
+        }
+        if self.buf.last() == Some(&b'\n') {
+            self.buf.pop();
+        }
+        if self.check_utf8 && std::str::from_utf8(&self.buf[..]).is_err() {
+            return err!("{} contains invalid UTF-8", self.name);
+        }
+        Ok(true)
+    }
+}
+
+impl LineReader for AutoReader {
+    type Line = DefaultLine;
+    fn filename(&self) -> Str<'static> {
+        self.name.clone()
+    }
+    fn check_utf8(&self) -> bool {
+        self.check_utf8
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
+        let mut line = DefaultLine::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        _pat: &Str,
+        _rc: &mut super::RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        let start = mem::replace(&mut self.start, false);
+        if start {
+            old.used_fields = self.used_fields.clone();
+        }
+        if !self.read_record()? {
+            old.set(Str::default());
+            self.state = ReaderState::Eof;
+            return Ok(start);
+        }
+        old.set(Str::from(&self.buf[..]).unmoor());
+        // Whitespace-separated records are split lazily, according to FS.
+        match self.inner.as_ref().map(|(_, format)| *format) {
+            Some(Format::Csv) => split_csv(&self.buf, self.dialect, &mut old.fields),
+            Some(Format::Tsv) => split_tsv(&self.buf, &mut old.fields),
+            _ => {}
+        }
+        self.state = ReaderState::OK;
+        Ok(/* file changed */ start)
+    }
+    fn read_state(&self) -> i64 {
+        self.state as i64
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        // There is just one file. Set EOF.
+        self.start = false;
+        self.inner = None;
+        self.state = ReaderState::Eof;
+        Ok(false)
+    }
+    fn file_index(&self) -> Int {
+        1
+    }
+    fn set_used_fields(&mut self, used_fields: &FieldSet) {
+        self.used_fields = used_fields.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_formats() {
+        let csv = CsvDialect::default();
+        let cases: &[(&str, Format)] = &[
+            ("a,b,c\n1,2,3\n4,5,6\n", Format::Csv),
+            ("name,quote\nx,\"a, b\"\n", Format::Csv),
+            ("a\tb\n1\t2\n", Format::Tsv),
+            ("a\tb,c\n1\t2,3\n", Format::Tsv),
+            ("a b c\n1 2 3\n", Format::Whitespace),
+            ("a,b\n1,2,3\n", Format::Whitespace),
+            ("", Format::Whitespace),
+        ];
+        for (head, want) in cases {
+            assert_eq!(sniff(head.as_bytes(), true, csv), *want, "head={:?}", head);
+        }
+        // A partial last line is ignored, unless the input has ended.
+        assert_eq!(sniff(b"a,b\n1,2\n3", false, csv), Format::Csv);
+        assert_eq!(sniff(b"a,b\n1,2\n3", true, csv), Format::Whitespace);
+    }
+
+    #[test]
+    fn split_fields() {
+        let fields = |bs: &[u8], format| {
+            let mut fields = Vec::new();
+            match format {
+                Format::Csv => split_csv(bs, CsvDialect::default(), &mut fields),
+                _ => split_tsv(bs, &mut fields),
+            }
+            fields.iter().map(|f| f.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            fields(b"a,\"b,\"\"c\"\"\",\r", Format::Csv),
+            vec!["a", "b,\"c\"", ""]
+        );
+        assert_eq!(fields(b"a\\tb\tc\\\\", Format::Tsv), vec!["a\tb", "c\\"]);
+    }
+}
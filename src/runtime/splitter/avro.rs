@@ -0,0 +1,197 @@
+//! Reading Avro object container files as input records.
+//!
+//! With `-i avro`, each record in each input becomes a record with one field per field of the
+//! writer schema stored in the file's header; `$0` holds the fields joined by tabs. The top-level
+//! schema must be a record. With `-H`, the names of the schema's fields are read as the first
+//! record of the first input, so that columns can be referenced by name via `FI`.
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::mem;
+
+use apache_avro::{types::Value, Reader, Schema};
+
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::{convert, Float, Int, Str};
+
+use super::{DefaultLine, LineReader, ReaderState};
+
+type Records = Reader<'static, Box<dyn Read>>;
+
+fn value_str(v: Value) -> Result<Str<'static>> {
+    Ok(match v {
+        Value::Null => Str::default(),
+        Value::Boolean(b) => convert::<Int, Str>(b as Int),
+        Value::Int(i) | Value::Date(i) | Value::TimeMillis(i) => convert::<Int, Str>(i as Int),
+        Value::Long(i)
+        | Value::TimeMicros(i)
+        | Value::TimestampMillis(i)
+        | Value::TimestampMicros(i) => convert::<Int, Str>(i),
+        Value::Float(f) => convert::<Float, Str>(f as Float),
+        Value::Double(f) => convert::<Float, Str>(f),
+        Value::String(s) | Value::Enum(_, s) => Str::from(s),
+        Value::Bytes(bs) | Value::Fixed(_, bs) => Str::from(&bs[..]).unmoor(),
+        Value::Uuid(u) => Str::from(u.to_string()),
+        Value::Union(_, v) => return value_str(*v),
+        v => return err!("unsupported avro value {:?}", v),
+    })
+}
+
+enum Source {
+    Stdin,
+    File(String),
+}
+
+pub struct AvroReader {
+    name: Str<'static>,
+    // Inputs are opened on the first read.
+    source: Option<Source>,
+    records: Option<Records>,
+    // The names of the schema's fields, if they have yet to be read as a header.
+    header: Option<Vec<Str<'static>>>,
+    read_header: bool,
+    used_fields: FieldSet,
+    state: ReaderState,
+    check_utf8: bool,
+    // Used to trigger updating FILENAME on the first read.
+    start: bool,
+}
+
+impl AvroReader {
+    /// Read the container file at `path`, or standard input if `path` is "-". If `read_header` is
+    /// set, the first record read holds the names of the schema's fields.
+    pub fn new(path: &str, read_header: bool, check_utf8: bool) -> AvroReader {
+        let source = if path == "-" {
+            Source::Stdin
+        } else {
+            Source::File(path.into())
+        };
+        AvroReader {
+            name: Str::from(path).unmoor(),
+            source: Some(source),
+            records: None,
+            header: None,
+            read_header,
+            used_fields: FieldSet::all(),
+            state: ReaderState::OK,
+            check_utf8,
+            start: true,
+        }
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let r: Box<dyn Read> = match self.source.take() {
+            Some(Source::Stdin) => Box::new(io::stdin()),
+            Some(Source::File(path)) => match File::open(path.as_str()) {
+                Ok(f) => Box::new(BufReader::new(f)),
+                Err(e) => return err!("failed to open {}: {}", path, e),
+            },
+            None => return Ok(()),
+        };
+        let records = match Reader::new(r) {
+            Ok(records) => records,
+            Err(e) => return err!("failed to read avro input {}: {}", self.name, e),
+        };
+        let names = match records.writer_schema() {
+            Schema::Record { fields, .. } => fields
+                .iter()
+                .map(|f| Str::from(f.name.clone()))
+                .collect::<Vec<_>>(),
+            s => {
+                return err!(
+                    "avro input {} must have a record schema, found {:?}",
+                    self.name,
+                    s
+                )
+            }
+        };
+        if self.read_header {
+            self.header = Some(names);
+        }
+        self.records = Some(records);
+        Ok(())
+    }
+
+    // Read the next record into `fields`. Returns false at the end of input.
+    fn read_record(&mut self, fields: &mut Vec<Str<'static>>) -> Result<bool> {
+        self.open()?;
+        fields.clear();
+        if let Some(names) = self.header.take() {
+            *fields = names;
+            return Ok(true);
+        }
+        let record = match self.records.as_mut().and_then(|r| r.next()) {
+            Some(Ok(Value::Record(record))) => record,
+            Some(Ok(v)) => return err!("unexpected avro value in {}: {:?}", self.name, v),
+            Some(Err(e)) => return err!("failed to read avro input {}: {}", self.name, e),
+            None => return Ok(false),
+        };
+        for (i, (_, v)) in record.into_iter().enumerate() {
+            // $0 needs every field.
+            if !self.used_fields.get(0) && !self.used_fields.get(i + 1) {
+                fields.push(Str::default());
+                continue;
+            }
+            let s = value_str(v)?;
+            if self.check_utf8 && s.with_bytes(|bs| std::str::from_utf8(bs).is_err()) {
+                return err!("avro input {} contains invalid UTF-8", self.name);
+            }
+            fields.push(s);
+        }
+        Ok(true)
+    }
+}
+
+impl LineReader for AvroReader {
+    type Line = DefaultLine;
+    fn filename(&self) -> Str<'static> {
+        self.name.clone()
+    }
+    fn check_utf8(&self) -> bool {
+        self.check_utf8
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
+        let mut line = DefaultLine::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        _pat: &Str,
+        _rc: &mut super::RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        let start = mem::replace(&mut self.start, false);
+        old.used_fields = self.used_fields.clone();
+        old.diverged = false;
+        if self.read_record(&mut old.fields)? {
+            old.line = if self.used_fields.get(0) {
+                Str::from("\t").join_slice(&old.fields[..])
+            } else {
+                Str::default()
+            };
+            self.state = ReaderState::OK;
+        } else {
+            old.line = Str::default();
+            self.state = ReaderState::Eof;
+        }
+        Ok(/* file changed */ start)
+    }
+    fn read_state(&self) -> i64 {
+        self.state as i64
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        // There is just one file. Set EOF.
+        self.source = None;
+        self.records = None;
+        self.header = None;
+        self.state = ReaderState::Eof;
+        Ok(false)
+    }
+    fn file_index(&self) -> Int {
+        1
+    }
+    fn set_used_fields(&mut self, used_fields: &FieldSet) {
+        self.used_fields = used_fields.clone();
+    }
+}
@@ -30,7 +30,7 @@ use regex::{bytes, bytes::Regex};
 use crate::common::{CancelSignal, ExecutionStrategy, Result};
 use crate::pushdown::FieldSet;
 use crate::runtime::{
-    str_impl::{Buf, Str, UniqueBuf},
+    str_impl::{Buf, Str, SubstTemplate, UniqueBuf},
     Int, RegexCache,
 };
 
@@ -39,6 +39,7 @@ use super::{
         self, CancellableChunkProducer, Chunk, ChunkProducer, OffsetChunk, ParallelChunkProducer,
         ShardedChunkProducer,
     },
+    limits::Limits,
     normalize_join_indexes, DefaultLine, LineReader, ReaderState,
 };
 
@@ -55,6 +56,7 @@ pub struct CSVReader<P> {
 
     empty_buf: Buf,
     check_utf8: bool,
+    limits: Limits,
 }
 
 impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
@@ -75,6 +77,7 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
         for p_factory in producers.into_iter() {
             let field_set = self.field_set.clone();
             let check_utf8 = self.check_utf8;
+            let limits = self.limits.clone();
             res.push(Box::new(move || {
                 let empty_buf = UniqueBuf::new(0).into_buf();
                 let cur_buf = empty_buf.clone();
@@ -89,6 +92,7 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
                     ifmt,
                     field_set,
                     check_utf8,
+                    limits,
                 }
             }) as _)
         }
@@ -121,6 +125,10 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
         self.prev_ix = 0;
         self.prod.next_file()
     }
+    fn file_index(&self) -> Int {
+        // Chunk versions are assigned starting at 1 for the first input file.
+        self.cur_chunk.version as Int
+    }
     fn set_used_fields(&mut self, field_set: &FieldSet) {
         self.field_set = field_set.clone();
     }
@@ -132,6 +140,7 @@ impl CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
         ifmt: InputFormat,
         chunk_size: usize,
         check_utf8: bool,
+        limits: Limits,
         exec_strategy: ExecutionStrategy,
         cancel_signal: CancelSignal,
     ) -> Self
@@ -141,15 +150,16 @@ impl CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
     {
         let prod: Box<dyn ChunkProducer<Chunk = OffsetChunk>> = match exec_strategy {
             ExecutionStrategy::Serial => Box::new(chunk::new_chained_offset_chunk_producer_csv(
-                rs, chunk_size, ifmt, check_utf8,
+                rs, chunk_size, ifmt, check_utf8, &limits,
             )),
             x @ ExecutionStrategy::ShardPerRecord => {
+                let limits = limits.clone();
                 Box::new(CancellableChunkProducer::new(
                     cancel_signal,
                     ParallelChunkProducer::new(
                         move || {
                             chunk::new_chained_offset_chunk_producer_csv(
-                                rs, chunk_size, ifmt, check_utf8,
+                                rs, chunk_size, ifmt, check_utf8, &limits,
                             )
                         },
                         /*channel_size*/ x.num_workers() * 2,
@@ -157,7 +167,9 @@ impl CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
                 ))
             }
             ExecutionStrategy::ShardPerFile => {
+                let limits = limits.clone();
                 let iter = rs.enumerate().map(move |(i, (r, name))| {
+                    let limits = limits.clone();
                     move || {
                         chunk::new_offset_chunk_producer_csv(
                             r,
@@ -166,6 +178,7 @@ impl CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
                             ifmt,
                             i as u32 + 1,
                             check_utf8,
+                            &limits,
                         )
                     }
                 });
@@ -188,6 +201,7 @@ impl CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
             ifmt,
             empty_buf,
             check_utf8,
+            limits,
         }
     }
 }
@@ -200,7 +214,8 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> CSVReader<P> {
         let old_buf = mem::replace(&mut self.cur_buf, placeholder);
         self.buf_len = 0;
 
-        // Send the chunks back, if possible, so they get freed in the same thread.
+        // Send the buffer back, if no record still points into it, so that the producer can reuse
+        // it.
         self.cur_chunk.buf = old_buf.try_unique().ok();
         if self.prod.get_chunk(&mut self.cur_chunk)? {
             // We may have received an EOF without getting any data. Increment the version so this
@@ -231,6 +246,7 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> CSVReader<P> {
         line: &'a mut Line,
     ) -> Result</*file changed*/ bool> {
         line.clear();
+        line.ifmt = Some(self.ifmt);
         let mut changed = false;
         if self.cur_chunk.off.rel.start == self.cur_chunk.off.rel.fields.len() {
             // NB: see comment on corresponding condition in ByteReader.
@@ -255,6 +271,7 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> CSVReader<P> {
         if st != State::Done {
             line.promote();
         }
+        self.limits.check_fields(&line.raw, &mut line.fields)?;
         Ok(changed)
     }
 }
@@ -302,6 +319,12 @@ pub struct Line {
     len: usize,
     fields: Vec<Str<'static>>,
     partial: Str<'static>,
+    // The format the line was read in, used to split $0 again when it is assigned to.
+    ifmt: Option<InputFormat>,
+    // Has a field been assigned without regenerating `raw`? See the same field on `DefaultLine`.
+    diverged: bool,
+    // The limit on the number of fields enforced when $0 is assigned to.
+    limits: Limits,
 }
 
 impl Line {
@@ -340,10 +363,14 @@ impl<'a> super::Line<'a> for Line {
         &mut self,
         col: super::Int,
         _pat: &Str,
-        _ofs: &Str,
+        ofs: &Str,
         _rc: &mut super::RegexCache,
     ) -> Result<Str<'a>> {
         if col == 0 {
+            if self.diverged {
+                self.raw = ofs.join_slice(&self.fields[..]);
+                self.diverged = false;
+            }
             return Ok(self.raw.clone().upcast());
         }
         if col < 0 {
@@ -357,16 +384,57 @@ impl<'a> super::Line<'a> for Line {
             .upcast())
     }
 
-    // Setting columns for CSV doesn't work. We refuse it outright.
+    // Assigning to $0 splits it again according to the input format; assigning to a field
+    // rebuilds $0 from the fields, joined by OFS, the next time it is read.
     fn set_col(
         &mut self,
-        _col: super::Int,
-        _s: &Str<'a>,
+        col: super::Int,
+        s: &Str<'a>,
         _pat: &Str,
         _rc: &mut super::RegexCache,
     ) -> Result<()> {
+        if col < 0 {
+            return err!("attempt to access negative index {}", col);
+        }
+        if col == 0 {
+            self.raw = s.clone().unmoor();
+            self.fields.clear();
+            self.diverged = false;
+            let ifmt = self.ifmt.unwrap_or(InputFormat::CSV(CsvDialect::default()));
+            let fields = &mut self.fields;
+            self.raw.with_bytes(|bs| ifmt.split(bs, fields));
+            return self.limits.check_fields(&self.raw, &mut self.fields);
+        }
+        let col = col as usize - 1;
+        if col >= self.fields.len() {
+            self.fields.resize_with(col + 1, Str::default);
+        }
+        self.fields[col] = s.clone().unmoor();
+        self.diverged = true;
         Ok(())
     }
+
+    fn resplit(
+        &mut self,
+        new_pat: &Str,
+        _pat: &Str,
+        ofs: &Str,
+        rc: &mut super::RegexCache,
+    ) -> Result<usize> {
+        if self.diverged {
+            self.raw = ofs.join_slice(&self.fields[..]);
+            self.diverged = false;
+        }
+        self.fields.clear();
+        rc.split_regex(new_pat, &self.raw, &FieldSet::all(), &mut self.fields)?;
+        Ok(self.fields.len())
+    }
+
+    fn set_limits(&mut self, limits: &Limits) {
+        if !self.limits.same(limits) {
+            self.limits = limits.clone();
+        }
+    }
 }
 
 impl Line {
@@ -383,6 +451,7 @@ impl Line {
         self.partial = Str::default();
         self.raw = Str::default();
         self.len = 0;
+        self.diverged = false;
     }
 }
 
@@ -442,12 +511,13 @@ impl<'a> Stepper<'a> {
 
     pub(crate) unsafe fn step(&mut self) -> usize {
         let sep = self.ifmt.sep();
+        let quote = self.ifmt.quote();
         let line_start = self.prev_ix;
         let bs = &self.buf.as_bytes()[0..self.buf_len];
         let mut cur = self.off.rel.start;
         let bs_transition = match self.ifmt {
             // Escape sequences only occur within quotes for CSV-formatted data.
-            InputFormat::CSV => State::Quote,
+            InputFormat::CSV(_) => State::Quote,
             // There are no "quoted fields" in TSV, and escape sequences simply occur at any point
             // in a field.
             InputFormat::TSV => State::Init,
@@ -478,7 +548,8 @@ impl<'a> Stepper<'a> {
                             let ix = *self.off.rel.fields.get_unchecked(cur) as usize;
                             cur += 1;
                             match *bs.get_unchecked(ix) {
-                                b'\r' | b'"' | b'\\' => {}
+                                b'\r' | b'\\' => {}
+                                x if x == quote => {}
                                 b'\n' => {
                                     self.prev_ix = ix + 1;
                                     self.promote_null();
@@ -507,7 +578,7 @@ impl<'a> Stepper<'a> {
                             self.st = State::Done;
                             return self.get(line_start, ix, cur);
                         }
-                        b'"' => {
+                        x if x == quote => {
                             self.push_past(ix);
                             self.st = State::Quote;
                             continue 'outer;
@@ -530,7 +601,7 @@ impl<'a> Stepper<'a> {
                     // Parse a quoted field; this will only happen in CSV mode.
                     let ix = get_next!();
                     match *bs.get_unchecked(ix) {
-                        b'"' => {
+                        x if x == quote => {
                             // We have found a quote, time to figure out if the next character is a
                             // quote, or if it is the end of the quoted portion of the field.
                             //
@@ -559,8 +630,8 @@ impl<'a> Stepper<'a> {
                         debug_assert_eq!(self.off.rel.fields.len(), cur);
                         return self.get(line_start, bs.len(), cur);
                     }
-                    if *bs.get_unchecked(self.prev_ix) == b'"' {
-                        self.append("\"".into());
+                    if *bs.get_unchecked(self.prev_ix) == quote {
+                        self.append(Str::from(&[quote][..]).unmoor());
                         self.st = State::Quote;
                         // burn the next entry. It should be a quote. Using get_next here is a
                         // convenience: if we hit the branch that returns early within the macro,
@@ -568,7 +639,7 @@ impl<'a> Stepper<'a> {
                         // should appear in the offsets vector, and we know that there is more
                         // space in `bs`.
                         let _q = get_next!();
-                        debug_assert_eq!(bs[_q], b'"');
+                        debug_assert_eq!(bs[_q], quote);
                         self.prev_ix += 1;
                     } else {
                         self.st = State::Init;
@@ -601,19 +672,144 @@ impl<'a> Stepper<'a> {
     }
 }
 
-#[derive(Copy, Clone)]
+/// The field separator and quote characters used to read and write CSV.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct CsvDialect {
+    pub sep: u8,
+    pub quote: u8,
+}
+
+impl Default for CsvDialect {
+    fn default() -> CsvDialect {
+        CsvDialect {
+            sep: b',',
+            quote: b'"',
+        }
+    }
+}
+
+impl CsvDialect {
+    pub fn new(sep: u8, quote: u8) -> Result<CsvDialect> {
+        for (name, c) in [("separator", sep), ("quote", quote)] {
+            if !c.is_ascii() || matches!(c, b'\n' | b'\r' | b'\\') {
+                return err!(
+                    "invalid CSV {} {:?}: must be an ASCII character other than a newline, \
+                     carriage return or backslash",
+                    name,
+                    c as char
+                );
+            }
+        }
+        if sep == quote {
+            return err!(
+                "CSV separator and quote must be different characters, got {:?} for both",
+                sep as char
+            );
+        }
+        Ok(CsvDialect { sep, quote })
+    }
+
+    /// The separator placed between columns by `join_csv`, when writing this dialect.
+    pub fn sep_str(self) -> Str<'static> {
+        Str::from(&[self.sep][..]).unmoor()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
 pub enum InputFormat {
-    CSV,
+    CSV(CsvDialect),
     TSV,
 }
 
 impl InputFormat {
-    fn sep(self) -> u8 {
+    pub(crate) fn sep(self) -> u8 {
         match self {
-            InputFormat::CSV => b',',
+            InputFormat::CSV(dialect) => dialect.sep,
             InputFormat::TSV => b'\t',
         }
     }
+    pub(crate) fn quote(self) -> u8 {
+        match self {
+            InputFormat::CSV(dialect) => dialect.quote,
+            // TSV has no quoted fields.
+            InputFormat::TSV => b'"',
+        }
+    }
+    // Split a single record, as when $0 is assigned to.
+    pub(crate) fn split(self, bs: &[u8], fields: &mut Vec<Str<'static>>) {
+        match self {
+            InputFormat::CSV(dialect) => split_csv(bs, dialect, fields),
+            InputFormat::TSV => split_tsv(bs, fields),
+        }
+    }
+}
+
+// Split a CSV record into fields, following the rules of `-i csv`: quotes may appear anywhere in
+// a field, doubled quotes within quotes stand for a quote, and backslash escapes are recognized
+// within quotes.
+pub(crate) fn split_csv(bs: &[u8], dialect: CsvDialect, fields: &mut Vec<Str<'static>>) {
+    let mut field = Vec::new();
+    let mut quoted = false;
+    let mut i = 0;
+    while i < bs.len() {
+        let b = bs[i];
+        i += 1;
+        if quoted {
+            if b == dialect.quote {
+                if bs.get(i) == Some(&dialect.quote) {
+                    field.push(b);
+                    i += 1;
+                } else {
+                    quoted = false;
+                }
+            } else if b == b'\\' && i < bs.len() {
+                unescape(bs[i], &mut field);
+                i += 1;
+            } else {
+                field.push(b);
+            }
+        } else if b == dialect.quote {
+            quoted = true;
+        } else if b == dialect.sep {
+            fields.push(Str::from(&field[..]).unmoor());
+            field.clear();
+        } else if b != b'\r' {
+            field.push(b);
+        }
+    }
+    fields.push(Str::from(&field[..]).unmoor());
+}
+
+// Split a TSV record into fields, following the rules of `-i tsv`.
+pub(crate) fn split_tsv(bs: &[u8], fields: &mut Vec<Str<'static>>) {
+    let mut field = Vec::new();
+    let mut i = 0;
+    while i < bs.len() {
+        let b = bs[i];
+        i += 1;
+        match b {
+            b'\t' => {
+                fields.push(Str::from(&field[..]).unmoor());
+                field.clear();
+            }
+            b'\\' if i < bs.len() => {
+                unescape(bs[i], &mut field);
+                i += 1;
+            }
+            b'\r' => {}
+            b => field.push(b),
+        }
+    }
+    fields.push(Str::from(&field[..]).unmoor());
+}
+
+fn unescape(b: u8, field: &mut Vec<u8>) {
+    match b {
+        b'n' => field.push(b'\n'),
+        b't' => field.push(b'\t'),
+        b'\\' => field.push(b'\\'),
+        b => field.extend_from_slice(&[b'\\', b]),
+    }
 }
 
 // get_find_indexes{_bytes,_ascii_whitespace}, what's that all about?
@@ -629,10 +825,19 @@ impl InputFormat {
 unsafe fn find_indexes_csv_avx2(
     buf: &[u8],
     offsets: &mut Offsets,
+    sep: u8,
+    quote: u8,
     prev_iter_inside_quote: u64,
     prev_iter_cr_end: u64,
 ) -> (u64, u64) {
-    generic::find_indexes_csv::<avx2::Impl>(buf, offsets, prev_iter_inside_quote, prev_iter_cr_end)
+    generic::find_indexes_csv::<avx2::Impl>(
+        buf,
+        offsets,
+        sep,
+        quote,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -640,10 +845,19 @@ unsafe fn find_indexes_csv_avx2(
 unsafe fn find_indexes_tsv_avx2(
     buf: &[u8],
     offsets: &mut Offsets,
+    sep: u8,
+    quote: u8,
     prev_iter_inside_quote: u64,
     prev_iter_cr_end: u64,
 ) -> (u64, u64) {
-    generic::find_indexes_tsv::<avx2::Impl>(buf, offsets, prev_iter_inside_quote, prev_iter_cr_end)
+    generic::find_indexes_tsv::<avx2::Impl>(
+        buf,
+        offsets,
+        sep,
+        quote,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -651,10 +865,19 @@ unsafe fn find_indexes_tsv_avx2(
 unsafe fn find_indexes_csv_sse2(
     buf: &[u8],
     offsets: &mut Offsets,
+    sep: u8,
+    quote: u8,
     prev_iter_inside_quote: u64,
     prev_iter_cr_end: u64,
 ) -> (u64, u64) {
-    generic::find_indexes_csv::<sse2::Impl>(buf, offsets, prev_iter_inside_quote, prev_iter_cr_end)
+    generic::find_indexes_csv::<sse2::Impl>(
+        buf,
+        offsets,
+        sep,
+        quote,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -662,15 +885,24 @@ unsafe fn find_indexes_csv_sse2(
 unsafe fn find_indexes_tsv_sse2(
     buf: &[u8],
     offsets: &mut Offsets,
+    sep: u8,
+    quote: u8,
     prev_iter_inside_quote: u64,
     prev_iter_cr_end: u64,
 ) -> (u64, u64) {
-    generic::find_indexes_tsv::<sse2::Impl>(buf, offsets, prev_iter_inside_quote, prev_iter_cr_end)
+    generic::find_indexes_tsv::<sse2::Impl>(
+        buf,
+        offsets,
+        sep,
+        quote,
+        prev_iter_inside_quote,
+        prev_iter_cr_end,
+    )
 }
 
-pub fn get_find_indexes(
-    ifmt: InputFormat,
-) -> unsafe fn(&[u8], &mut Offsets, u64, u64) -> (u64, u64) {
+pub type CsvIndexKernel = unsafe fn(&[u8], &mut Offsets, u8, u8, u64, u64) -> (u64, u64);
+
+pub fn get_find_indexes(ifmt: InputFormat) -> CsvIndexKernel {
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "x86_64")] {
             #[cfg(feature = "allow_avx2")]
@@ -679,23 +911,23 @@ pub fn get_find_indexes(
             const ALLOW_AVX2: bool = false;
             if ALLOW_AVX2 && is_x86_feature_detected!("avx2") && is_x86_feature_detected!("pclmulqdq") {
                  match ifmt {
-                     InputFormat::CSV => find_indexes_csv_avx2,
+                     InputFormat::CSV(_) => find_indexes_csv_avx2,
                      InputFormat::TSV => find_indexes_tsv_avx2,
                  }
              } else if is_x86_feature_detected!("sse2") && is_x86_feature_detected!("pclmulqdq") {
                  match ifmt {
-                     InputFormat::CSV => find_indexes_csv_sse2,
+                     InputFormat::CSV(_) => find_indexes_csv_sse2,
                      InputFormat::TSV => find_indexes_tsv_sse2,
                  }
              } else {
                  match ifmt {
-                     InputFormat::CSV => generic::find_indexes_csv::<generic::Impl>,
+                     InputFormat::CSV(_) => generic::find_indexes_csv::<generic::Impl>,
                      InputFormat::TSV => generic::find_indexes_tsv::<generic::Impl>,
                  }
              }
         } else {
             match ifmt {
-                InputFormat::CSV => generic::find_indexes_csv::<generic::Impl>,
+                InputFormat::CSV(_) => generic::find_indexes_csv::<generic::Impl>,
                 InputFormat::TSV => generic::find_indexes_tsv::<generic::Impl>,
             }
         }
@@ -780,35 +1012,37 @@ pub fn get_find_indexes_ascii_whitespace() -> WhitespaceIndexKernel {
 
 // TODO: consider putting these into the runtime struct to avoid the extra indirection.
 lazy_static! {
-    static ref QUOTE: Regex = Regex::new(r#"""#).unwrap();
     static ref TAB: Regex = Regex::new(r#"\t"#).unwrap();
     static ref NEWLINE: Regex = Regex::new(r#"\n"#).unwrap();
+    static ref TAB_SUBST: SubstTemplate = SubstTemplate::new(br#"\t"#);
+    static ref NEWLINE_SUBST: SubstTemplate = SubstTemplate::new(br#"\n"#);
     static ref NEEDS_ESCAPE_TSV: bytes::RegexSet =
         bytes::RegexSet::new(&[r#"\t"#, r#"\n"#]).unwrap();
-    static ref NEEDS_ESCAPE_CSV: bytes::RegexSet =
-        bytes::RegexSet::new(&[r#"""#, r#"\t"#, r#"\n"#, ","]).unwrap();
 }
 
-pub fn escape_csv<'a>(s: &Str<'a>) -> Str<'a> {
+/// Escape `s` for use as a column of CSV output in `dialect`, for `escape_csv`, `join_csv` and
+/// `-o csv`.
+pub fn escape_csv<'a>(s: &Str<'a>, dialect: CsvDialect) -> Str<'a> {
+    let CsvDialect { sep, quote } = dialect;
     let bs = unsafe { &*s.get_bytes() };
-    let matches = NEEDS_ESCAPE_CSV.matches(bs);
-    if !matches.matched_any() {
+    if !bs
+        .iter()
+        .any(|&b| b == sep || b == quote || b == b'\t' || b == b'\n')
+    {
         return s.clone();
     }
-    let mut cur = s.clone();
-    for m in matches.into_iter() {
-        let (pat, subst_for) = match m {
-            0 => (&*QUOTE, r#""""#),
-            1 => (&*TAB, r#"\t"#),
-            2 => (&*NEWLINE, r#"\n"#),
-            // This just necessitates the ""s
-            3 => continue,
-            _ => unreachable!(),
-        };
-        cur = cur.subst_all(pat, &Str::from(subst_for).upcast()).0;
+    let mut res = Vec::with_capacity(bs.len() + 2);
+    res.push(quote);
+    for &b in bs {
+        match b {
+            b'\t' => res.extend_from_slice(b"\\t"),
+            b'\n' => res.extend_from_slice(b"\\n"),
+            b if b == quote => res.extend_from_slice(&[quote, quote]),
+            b => res.push(b),
+        }
     }
-    let quote = Str::from("\"");
-    Str::concat(Str::concat(quote.clone(), cur), quote)
+    res.push(quote);
+    Buf::read_from_bytes(&res).into_str()
 }
 
 pub fn escape_tsv<'a>(s: &Str<'a>) -> Str<'a> {
@@ -820,11 +1054,11 @@ pub fn escape_tsv<'a>(s: &Str<'a>) -> Str<'a> {
     let mut cur = s.clone();
     for m in matches.into_iter() {
         let (pat, subst_for) = match m {
-            0 => (&*TAB, r#"\t"#),
-            1 => (&*NEWLINE, r#"\n"#),
+            0 => (&*TAB, &*TAB_SUBST),
+            1 => (&*NEWLINE, &*NEWLINE_SUBST),
             _ => unreachable!(),
         };
-        cur = cur.subst_all(pat, &Str::from(subst_for).upcast()).0;
+        cur = cur.subst_all(pat, subst_for).0;
     }
     cur
 }
@@ -837,9 +1071,9 @@ mod escape_tests {
     fn csv_escaping() {
         let s1 = Str::from("no escaping");
         let s2 = Str::from("This ought to be escaped, for two\treasons");
-        assert_eq!(escape_csv(&s1), s1);
+        assert_eq!(escape_csv(&s1, CsvDialect::default()), s1);
         assert_eq!(
-            escape_csv(&s2),
+            escape_csv(&s2, CsvDialect::default()),
             Str::from(r#""This ought to be escaped, for two\treasons""#)
         );
     }
@@ -880,6 +1114,7 @@ mod generic {
 
         unsafe fn find_quote_mask(
             self,
+            quote: u8,
             prev_iter_inside_quote: &mut u64,
         ) -> (/*inside quotes*/ u64, /*quote locations*/ u64);
 
@@ -996,9 +1231,9 @@ mod generic {
             foreach_impl!(ix, if self.0[ix] == m { 1u8 } else { 0u8 })
         }
 
-        unsafe fn find_quote_mask(self, prev_iter_inside_quote: &mut u64) -> (u64, u64) {
+        unsafe fn find_quote_mask(self, quote: u8, prev_iter_inside_quote: &mut u64) -> (u64, u64) {
             // NB: this implementation is pretty naive. We could definitely speed this up.
-            let quote_mask = self.cmp_against_input(b'"').mask();
+            let quote_mask = self.cmp_against_input(quote).mask();
             let mut running_xor = 0;
             let mut res = 0u64;
             for ix in 0..64 {
@@ -1014,6 +1249,7 @@ mod generic {
     #[cfg(target_arch = "x86_64")]
     pub unsafe fn default_x86_find_quote_mask<V: Vector>(
         inp: V,
+        quote: u8,
         prev_iter_inside_quote: &mut u64,
     ) -> (/*inside quotes*/ u64, /*quote locations*/ u64) {
         use std::arch::x86_64::*;
@@ -1024,7 +1260,7 @@ mod generic {
         // [000000000000001111111111110]
         // We will use this mask to avoid splitting on commas that are inside a quoted field. We
         // start by generating a mask for all the quote characters appearing in the string.
-        let quote_bits = inp.cmp_mask_against_input(b'"');
+        let quote_bits = inp.cmp_mask_against_input(quote);
         // Then we pull this trick from the simdjson paper. Lets use the example from the comments
         // above:
         // [unquoted text "quoted text"]
@@ -1195,13 +1431,15 @@ mod generic {
     pub unsafe fn find_indexes_csv<V: Vector>(
         buf: &[u8],
         offsets: &mut Offsets,
+        sep: u8,
+        quote: u8,
         prev_iter_inside_quote: u64, /*start at 0*/
         prev_iter_cr_end: u64,       /*start at 0*/
     ) -> (u64, u64) {
         let f = |(mut prev_iter_inside_quote, mut prev_iter_cr_end), buf| {
             let inp = V::fill_input(buf);
-            let (quote_mask, quote_locs) = inp.find_quote_mask(&mut prev_iter_inside_quote);
-            let sep = inp.cmp_mask_against_input(b',');
+            let (quote_mask, quote_locs) = inp.find_quote_mask(quote, &mut prev_iter_inside_quote);
+            let sep = inp.cmp_mask_against_input(sep);
             let esc = inp.cmp_mask_against_input(b'\\');
 
             let cr = inp.cmp_mask_against_input(0x0d);
@@ -1222,7 +1460,9 @@ mod generic {
     pub unsafe fn find_indexes_tsv<V: Vector>(
         buf: &[u8],
         offsets: &mut Offsets,
-        // These two are ignored for TSV
+        // These four are ignored for TSV
+        _sep: u8,
+        _quote: u8,
         _prev_iter_inside_quote: u64,
         _prev_iter_cr_end: u64,
     ) -> (u64, u64) {
@@ -1335,9 +1575,10 @@ mod sse2 {
         #[inline(always)]
         unsafe fn find_quote_mask(
             self,
+            quote: u8,
             prev_iter_inside_quote: &mut u64,
         ) -> (/*inside quotes*/ u64, /*quote locations*/ u64) {
-            default_x86_find_quote_mask::<Self>(self, prev_iter_inside_quote)
+            default_x86_find_quote_mask::<Self>(self, quote, prev_iter_inside_quote)
         }
     }
 }
@@ -1397,9 +1638,10 @@ mod avx2 {
         #[inline(always)]
         unsafe fn find_quote_mask(
             self,
+            quote: u8,
             prev_iter_inside_quote: &mut u64,
         ) -> (/*inside quotes*/ u64, /*quote locations*/ u64) {
-            default_x86_find_quote_mask::<Self>(self, prev_iter_inside_quote)
+            default_x86_find_quote_mask::<Self>(self, quote, prev_iter_inside_quote)
         }
     }
 }
@@ -1413,18 +1655,25 @@ pub struct ByteReader<P: ChunkProducer> {
     // Progress in the current buffer.
     progress: usize,
     record_sep: u8,
+    // If the last record read was streamed, the number of complete fields it kept.
+    streamed: Option<usize>,
 
     last_len: usize,
+    empty_buf: Buf,
     check_utf8: bool,
+    limits: Limits,
 }
 
 impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<I, S>(
         rs: I,
         field_sep: u8,
         record_sep: u8,
         chunk_size: usize,
         check_utf8: bool,
+        limits: Limits,
+        stream: bool,
         exec_strategy: ExecutionStrategy,
         cancel_signal: CancelSignal,
     ) -> Self
@@ -1438,6 +1687,8 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
             record_sep,
             chunk_size,
             check_utf8,
+            limits,
+            stream,
             exec_strategy,
             get_find_indexes_bytes(),
             cancel_signal,
@@ -1455,6 +1706,8 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
         record_sep: u8,
         chunk_size: usize,
         check_utf8: bool,
+        limits: Limits,
+        stream: bool,
         exec_strategy: ExecutionStrategy,
         kernel: BytesIndexKernel,
         cancel_signal: CancelSignal,
@@ -1465,15 +1718,17 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
     {
         let prod: Box<dyn ChunkProducer<Chunk = OffsetChunk>> = match exec_strategy {
             ExecutionStrategy::Serial => Box::new(chunk::new_chained_offset_chunk_producer_bytes(
-                rs, chunk_size, field_sep, record_sep, check_utf8, kernel,
+                rs, chunk_size, field_sep, record_sep, check_utf8, &limits, stream, kernel,
             )),
             x @ ExecutionStrategy::ShardPerRecord => {
+                let limits = limits.clone();
                 Box::new(CancellableChunkProducer::new(
                     cancel_signal,
                     ParallelChunkProducer::new(
                         move || {
                             chunk::new_chained_offset_chunk_producer_bytes(
-                                rs, chunk_size, field_sep, record_sep, check_utf8, kernel,
+                                rs, chunk_size, field_sep, record_sep, check_utf8, &limits, stream,
+                                kernel,
                             )
                         },
                         /*channel_size*/ x.num_workers() * 2,
@@ -1481,7 +1736,9 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
                 ))
             }
             ExecutionStrategy::ShardPerFile => {
+                let limits = limits.clone();
                 let iter = rs.enumerate().map(move |(i, (r, name))| {
+                    let limits = limits.clone();
                     move || {
                         chunk::new_offset_chunk_producer_bytes(
                             r,
@@ -1491,6 +1748,8 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
                             record_sep,
                             i as u32 + 1,
                             check_utf8,
+                            &limits,
+                            stream,
                             kernel,
                         )
                     }
@@ -1501,16 +1760,20 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
                 ))
             }
         };
+        let empty_buf = UniqueBuf::new(0).into_buf();
         ByteReader {
             prod,
             cur_chunk: Default::default(),
-            cur_buf: UniqueBuf::new(0).into_buf(),
+            cur_buf: empty_buf.clone(),
             buf_len: 0,
             progress: 0,
             record_sep,
             used_fields: FieldSet::all(),
+            streamed: None,
             last_len: usize::max_value(),
+            empty_buf,
             check_utf8,
+            limits,
         }
     }
 }
@@ -1520,6 +1783,8 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
         rs: I,
         chunk_size: usize,
         check_utf8: bool,
+        limits: Limits,
+        stream: bool,
         exec_strategy: ExecutionStrategy,
         cancel_signal: CancelSignal,
     ) -> Self
@@ -1531,15 +1796,20 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
             rs,
             chunk_size,
             check_utf8,
+            limits,
+            stream,
             exec_strategy,
             get_find_indexes_ascii_whitespace(),
             cancel_signal,
         )
     }
+    #[allow(clippy::too_many_arguments)]
     pub fn new_whitespace_internal<I, S>(
         rs: I,
         chunk_size: usize,
         check_utf8: bool,
+        limits: Limits,
+        stream: bool,
         exec_strategy: ExecutionStrategy,
         find_indexes: unsafe fn(&[u8], &mut WhitespaceOffsets, u64) -> u64,
         cancel_signal: CancelSignal,
@@ -1555,10 +1825,13 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                         rs,
                         chunk_size,
                         check_utf8,
+                        &limits,
+                        stream,
                         find_indexes,
                     ))
                 }
                 x @ ExecutionStrategy::ShardPerRecord => {
+                    let limits = limits.clone();
                     Box::new(CancellableChunkProducer::new(
                         cancel_signal,
                         ParallelChunkProducer::new(
@@ -1567,6 +1840,8 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                                     rs,
                                     chunk_size,
                                     check_utf8,
+                                    &limits,
+                                    stream,
                                     find_indexes,
                                 )
                             },
@@ -1575,7 +1850,9 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                     ))
                 }
                 ExecutionStrategy::ShardPerFile => {
+                    let limits = limits.clone();
                     let iter = rs.enumerate().map(move |(i, (r, name))| {
+                        let limits = limits.clone();
                         move || {
                             chunk::new_offset_chunk_producer_ascii_whitespace(
                                 r,
@@ -1583,6 +1860,8 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                                 name.as_str(),
                                 i as u32 + 1,
                                 check_utf8,
+                                &limits,
+                                stream,
                                 find_indexes,
                             )
                         }
@@ -1593,16 +1872,20 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                     ))
                 }
             };
+        let empty_buf = UniqueBuf::new(0).into_buf();
         ByteReader {
             prod,
             cur_chunk: Default::default(),
-            cur_buf: UniqueBuf::new(0).into_buf(),
+            cur_buf: empty_buf.clone(),
             buf_len: 0,
             progress: 0,
             record_sep: 0, // unused
             used_fields: FieldSet::all(),
+            streamed: None,
             last_len: usize::max_value(),
+            empty_buf,
             check_utf8,
+            limits,
         }
     }
 }
@@ -1628,16 +1911,23 @@ where
             let used_fields = self.used_fields.clone();
             let record_sep = self.record_sep;
             let check_utf8 = self.check_utf8;
-            res.push(Box::new(move || ByteReader {
-                prod: p_factory(),
-                cur_chunk: Default::default(),
-                cur_buf: UniqueBuf::new(0).into_buf(),
-                buf_len: 0,
-                progress: 0,
-                record_sep,
-                last_len: usize::max_value(),
-                used_fields,
-                check_utf8,
+            let limits = self.limits.clone();
+            res.push(Box::new(move || {
+                let empty_buf = UniqueBuf::new(0).into_buf();
+                ByteReader {
+                    prod: p_factory(),
+                    cur_chunk: Default::default(),
+                    cur_buf: empty_buf.clone(),
+                    buf_len: 0,
+                    progress: 0,
+                    record_sep,
+                    streamed: None,
+                    last_len: usize::max_value(),
+                    used_fields,
+                    empty_buf,
+                    check_utf8,
+                    limits,
+                }
             }) as _)
         }
         res
@@ -1663,6 +1953,7 @@ where
         }
         old.fields.clear();
         let changed = self.read_line_inner(&mut old.line, &mut old.fields)?;
+        old.streamed = self.streamed;
         Ok(changed)
     }
     fn read_state(&self) -> i64 {
@@ -1681,6 +1972,10 @@ where
         self.prod.next_file()
     }
 
+    fn file_index(&self) -> Int {
+        self.cur_chunk_version() as Int
+    }
+
     fn set_used_fields(&mut self, field_set: &FieldSet) {
         self.used_fields = field_set.clone();
     }
@@ -1714,6 +2009,14 @@ where
     OffsetChunk<T>: Chunk,
 {
     let prev_version = br.cur_chunk.version;
+    if br.progress == br.buf_len {
+        // Hand the old buffer back so it can be reused; see the equivalent line in CSVReader. If
+        // there is data left over, we are at the end of the input and still need the buffer to
+        // read the final record.
+        let placeholder = br.empty_buf.clone();
+        let old_buf = mem::replace(&mut br.cur_buf, placeholder);
+        br.cur_chunk.buf = old_buf.try_unique().ok();
+    }
     if br.prod.get_chunk(&mut br.cur_chunk)? {
         // See comment in the equivalent line in CSVReader.
         br.cur_chunk.version = std::cmp::max(prev_version, 1);
@@ -1735,6 +2038,7 @@ where
     ByteReader<P>: ByteReaderBase,
 {
     let mut changed = false;
+    br.streamed = None;
     if br.maybe_done() {
         // What's going on with this second test? br.refresh_buf() returns Ok(true) if we
         // were unable to fetch more data due to an EOF. The last execution consumed buffer up
@@ -1742,6 +2046,9 @@ where
         // up the rest of the buffer with no more record or field separators. In that case, we
         // want to return the rest of the input as a single-field record, which one more
         // `consume_line` will indeed accomplish.
+        //
+        // The previous record is dropped first, so that the buffer it points into can be reused.
+        *line = Str::default();
         let (is_eof, has_changed) = br.refresh_buf()?;
         changed = has_changed;
         if is_eof && br.progress == br.buf_len {
@@ -1751,7 +2058,12 @@ where
             return Ok(false);
         }
     }
+    // Only the first record in a chunk can have been streamed.
+    if br.progress == 0 {
+        br.streamed = br.cur_chunk.streamed;
+    }
     let (next_line, consumed) = unsafe { br.consume_line(fields) };
+    br.limits.check_fields(&next_line, fields)?;
     *line = next_line;
     br.last_len = consumed;
     Ok(changed)
@@ -1983,7 +2295,7 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
         mem.reserve(32);
         let mut offsets: Offsets = Default::default();
         let (in_quote, in_cr) =
-            unsafe { generic::find_indexes_csv::<V>(&mem[..], &mut offsets, 0, 0) };
+            unsafe { generic::find_indexes_csv::<V>(&mem[..], &mut offsets, b',', b'"', 0, 0) };
         assert_ne!(in_quote, 0);
         assert_eq!(in_cr, 0);
         assert_eq!(
@@ -2043,6 +2355,7 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
             InputFormat::TSV,
             /*chunk_size=*/ 512,
             /*check_utf8=*/ true,
+            Limits::default(),
             ExecutionStrategy::Serial,
             Default::default(),
         );
@@ -2075,6 +2388,70 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
         }
     }
 
+    #[test]
+    fn recycled_buffers() {
+        // Unlike the tests above, keep no references to the input between records, so that
+        // buffers are handed back to the chunk producers and reused.
+        fn read_all<R: LineReader>(mut reader: R, fields: impl Fn(&mut R::Line) -> Vec<String>)
+        where
+            R::Line: Default,
+        {
+            let corpus = crate::test_string_constants::PRIDE_PREJUDICE_CH2;
+            let expected: Vec<Vec<String>> = corpus
+                .lines()
+                .map(|line| line.split_whitespace().map(String::from).collect())
+                .collect();
+            let mut _cache = RegexCache::default();
+            let _pat = Str::default();
+            let mut line = R::Line::default();
+            let mut got = Vec::new();
+            loop {
+                reader
+                    .read_line_reuse(&_pat, &mut _cache, &mut line)
+                    .expect("failed to read line");
+                if reader.read_state() != 1 {
+                    break;
+                }
+                got.push(fields(&mut line));
+            }
+            assert_eq!(got, expected);
+        }
+        let corpus = crate::test_string_constants::PRIDE_PREJUDICE_CH2;
+        read_all(
+            CSVReader::new(
+                iter::once((
+                    std::io::Cursor::new(corpus.replace(' ', "\t")),
+                    String::from("fake-stdin"),
+                )),
+                InputFormat::TSV,
+                /*chunk_size=*/ 512,
+                /*check_utf8=*/ true,
+                Limits::default(),
+                ExecutionStrategy::Serial,
+                Default::default(),
+            ),
+            |line| {
+                line.fields
+                    .iter()
+                    .filter(|f| !f.is_empty())
+                    .map(|f| f.to_string())
+                    .collect()
+            },
+        );
+        read_all(
+            ByteReader::new_whitespace(
+                iter::once((std::io::Cursor::new(corpus), String::from("fake-stdin"))),
+                /*chunk_size=*/ 512,
+                /*check_utf8=*/ true,
+                Limits::default(),
+                /*stream=*/ false,
+                ExecutionStrategy::Serial,
+                Default::default(),
+            ),
+            |line| line.fields.iter().map(|f| f.to_string()).collect(),
+        );
+    }
+
     #[test]
     fn tsv_splitter_basic() {
         tsv_split(crate::test_string_constants::VIRGIL);
@@ -2113,6 +2490,8 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
             rs,
             1024,
             /*check_utf8=*/ true,
+            Limits::default(),
+            /*stream=*/ false,
             ExecutionStrategy::Serial,
             kernel,
             Default::default(),
@@ -2270,6 +2649,8 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
                 iter::once((reader, String::from("fake-stdin"))),
                 /*chunk_size=*/ 1024,
                 /*check_utf8=*/ false,
+                Limits::default(),
+                /*stream=*/ false,
                 ExecutionStrategy::ShardPerRecord,
                 Default::default(),
             )
@@ -2281,6 +2662,8 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
                 /*record_sep=*/ b'\n',
                 /*chunk_size=*/ 1024,
                 /*check_utf8=*/ false,
+                Limits::default(),
+                /*stream=*/ false,
                 ExecutionStrategy::ShardPerRecord,
                 Default::default(),
             )
@@ -2331,6 +2714,8 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
             std::iter::once((reader, String::from("fake-stdin"))),
             1024,
             /*check_utf8=*/ false,
+            Limits::default(),
+            /*stream=*/ false,
             ExecutionStrategy::Serial,
             kernel,
             Default::default(),
@@ -0,0 +1,136 @@
+//! Reading fixed-length binary records.
+//!
+//! With `--record-bytes N`, every N bytes of input form a record, with `$0` holding the raw bytes.
+//! A final record shorter than N bytes is read as-is. Records are typically decoded into fields
+//! with the `unpack` builtin.
+use std::io::{self, Read};
+
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::{Int, Str};
+
+use super::{DefaultLine, LineReader, ReaderState};
+
+pub struct BinaryReader<R> {
+    inner: R,
+    name: Str<'static>,
+    record_bytes: usize,
+    buf: Vec<u8>,
+    used_fields: FieldSet,
+    state: ReaderState,
+    // Used to trigger updating FILENAME on the first read.
+    start: bool,
+}
+
+impl<R: Read> BinaryReader<R> {
+    pub fn new(r: R, record_bytes: usize, name: impl Into<Str<'static>>) -> Self {
+        BinaryReader {
+            inner: r,
+            name: name.into(),
+            record_bytes,
+            buf: Vec::with_capacity(record_bytes),
+            used_fields: FieldSet::all(),
+            state: ReaderState::OK,
+            start: true,
+        }
+    }
+
+    // Read the next record into `buf`, returning false at the end of the input.
+    fn read_record(&mut self) -> Result<bool> {
+        if let ReaderState::Eof = self.state {
+            return Ok(false);
+        }
+        self.buf.clear();
+        self.buf.resize(self.record_bytes, 0);
+        let mut filled = 0;
+        while filled < self.record_bytes {
+            match self.inner.read(&mut self.buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.state = ReaderState::Error;
+                    return err!("failed to read {}: {}", self.name, e);
+                }
+            }
+        }
+        self.buf.truncate(filled);
+        Ok(filled > 0)
+    }
+}
+
+impl<R: Read> LineReader for BinaryReader<R> {
+    type Line = DefaultLine;
+    fn filename(&self) -> Str<'static> {
+        self.name.clone()
+    }
+    fn check_utf8(&self) -> bool {
+        // Records are binary data, so there is nothing to check.
+        false
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
+        let mut line = DefaultLine::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        _pat: &Str,
+        _rc: &mut super::RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        let start = std::mem::replace(&mut self.start, false);
+        if start {
+            old.used_fields = self.used_fields.clone();
+        }
+        old.diverged = false;
+        old.fields.clear();
+        if self.read_record()? {
+            old.line = Str::from(&self.buf[..]).unmoor();
+            self.state = ReaderState::OK;
+        } else {
+            old.line = Str::default();
+            self.state = ReaderState::Eof;
+        }
+        Ok(/* file changed */ start)
+    }
+    fn read_state(&self) -> i64 {
+        self.state as i64
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        // There is just one file. Set EOF.
+        self.state = ReaderState::Eof;
+        Ok(false)
+    }
+    fn file_index(&self) -> Int {
+        1
+    }
+    fn set_used_fields(&mut self, used_fields: &FieldSet) {
+        self.used_fields = used_fields.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RegexCache;
+
+    #[test]
+    fn fixed_records() {
+        let data: &[u8] = b"\x00\x01\x02\x03\x04\x05\x06";
+        let mut reader = BinaryReader::new(data, 3, "data");
+        let mut rc = RegexCache::default();
+        let mut line = DefaultLine::default();
+        let mut records = Vec::new();
+        loop {
+            reader
+                .read_line_reuse(&" ".into(), &mut rc, &mut line)
+                .unwrap();
+            if reader.read_state() != ReaderState::OK as i64 {
+                break;
+            }
+            records.push(line.line.with_bytes(|bs| bs.to_vec()));
+        }
+        assert_eq!(records, vec![vec![0u8, 1, 2], vec![3, 4, 5], vec![6]]);
+    }
+}
@@ -12,9 +12,12 @@ use crate::runtime::{
             get_find_indexes, BytesIndexKernel, InputFormat, Offsets, WhitespaceIndexKernel,
             WhitespaceOffsets,
         },
+        limits::Limits,
+        stream::FieldSep,
         Reader,
     },
     str_impl::UniqueBuf,
+    writers,
 };
 
 /// A vector of dynamically typed factory functions producing a chunk producer
@@ -73,13 +76,19 @@ pub fn new_offset_chunk_producer_csv<R: Read>(
     ifmt: InputFormat,
     start_version: u32,
     check_utf8: bool,
+    limits: &Limits,
 ) -> OffsetChunkProducer<R, impl FnMut(&[u8], &mut Offsets)> {
     let find_indexes = get_find_indexes(ifmt);
+    let (sep, quote) = (ifmt.sep(), ifmt.quote());
     OffsetChunkProducer {
         name: name.into(),
-        inner: Reader::new(r, chunk_size, /*padding=*/ 128, check_utf8),
+        inner: Reader::new(r, chunk_size, /*padding=*/ 128, check_utf8).limit_records(
+            limits.clone(),
+            name,
+            Some(b'\n'),
+        ),
         find_indexes: move |bs: &[u8], offs: &mut Offsets| {
-            unsafe { find_indexes(bs, offs, 0, 0) };
+            unsafe { find_indexes(bs, offs, sep, quote, 0, 0) };
         },
         record_sep: b'\n',
         cur_file_version: start_version,
@@ -95,11 +104,15 @@ pub fn new_offset_chunk_producer_bytes<R: Read>(
     record_sep: u8,
     start_version: u32,
     check_utf8: bool,
+    limits: &Limits,
+    stream: bool,
     find_indexes: BytesIndexKernel,
 ) -> OffsetChunkProducer<R, impl FnMut(&[u8], &mut Offsets)> {
     OffsetChunkProducer {
         name: name.into(),
-        inner: Reader::new(r, chunk_size, /*padding=*/ 128, check_utf8),
+        inner: Reader::new(r, chunk_size, /*padding=*/ 128, check_utf8)
+            .limit_records(limits.clone(), name, Some(record_sep))
+            .stream_records(stream, FieldSep::Byte(field_sep), record_sep),
         find_indexes: move |bs: &[u8], offs: &mut Offsets| unsafe {
             find_indexes(bs, offs, field_sep, record_sep)
         },
@@ -109,18 +122,23 @@ pub fn new_offset_chunk_producer_bytes<R: Read>(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn new_offset_chunk_producer_ascii_whitespace<R: Read>(
     r: R,
     chunk_size: usize,
     name: &str,
     start_version: u32,
     check_utf8: bool,
+    limits: &Limits,
+    stream: bool,
     find_indexes: WhitespaceIndexKernel,
 ) -> WhitespaceChunkProducer<R, impl FnMut(&[u8], &mut WhitespaceOffsets, u64) -> u64> {
     WhitespaceChunkProducer(
         OffsetChunkProducer {
             name: name.into(),
-            inner: Reader::new(r, chunk_size, /*padding=*/ 128, check_utf8),
+            inner: Reader::new(r, chunk_size, /*padding=*/ 128, check_utf8)
+                .limit_records(limits.clone(), name, Some(b'\n'))
+                .stream_records(stream, FieldSep::Whitespace, b'\n'),
             find_indexes: move |bs: &[u8], offs: &mut WhitespaceOffsets, start: u64| unsafe {
                 find_indexes(bs, offs, start)
             },
@@ -141,6 +159,7 @@ pub fn new_chained_offset_chunk_producer_csv<
     chunk_size: usize,
     ifmt: InputFormat,
     check_utf8: bool,
+    limits: &Limits,
 ) -> ChainedChunkProducer<OffsetChunkProducer<R, impl FnMut(&[u8], &mut Offsets)>> {
     ChainedChunkProducer::new(
         r.enumerate()
@@ -152,12 +171,14 @@ pub fn new_chained_offset_chunk_producer_csv<
                     ifmt,
                     /*start_version=*/ (i as u32).wrapping_add(1),
                     check_utf8,
+                    limits,
                 )
             })
             .collect(),
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn new_chained_offset_chunk_producer_bytes<
     R: Read,
     N: Borrow<str>,
@@ -168,6 +189,8 @@ pub fn new_chained_offset_chunk_producer_bytes<
     field_sep: u8,
     record_sep: u8,
     check_utf8: bool,
+    limits: &Limits,
+    stream: bool,
     kernel: BytesIndexKernel,
 ) -> ChainedChunkProducer<OffsetChunkProducer<R, impl FnMut(&[u8], &mut Offsets)>> {
     ChainedChunkProducer::new(
@@ -181,6 +204,8 @@ pub fn new_chained_offset_chunk_producer_bytes<
                     record_sep,
                     /*start_version=*/ (i as u32).wrapping_add(1),
                     check_utf8,
+                    limits,
+                    stream,
                     kernel,
                 )
             })
@@ -196,6 +221,8 @@ pub fn new_chained_offset_chunk_producer_ascii_whitespace<
     r: I,
     chunk_size: usize,
     check_utf8: bool,
+    limits: &Limits,
+    stream: bool,
     find_indexes: WhitespaceIndexKernel,
 ) -> ChainedChunkProducer<
     WhitespaceChunkProducer<R, impl FnMut(&[u8], &mut WhitespaceOffsets, u64) -> u64>,
@@ -209,6 +236,8 @@ pub fn new_chained_offset_chunk_producer_ascii_whitespace<
                     name.borrow(),
                     /*start_version=*/ (i as u32).wrapping_add(1),
                     check_utf8,
+                    limits,
+                    stream,
                     find_indexes,
                 )
             })
@@ -238,6 +267,9 @@ pub struct OffsetChunk<Off = Offsets> {
     pub buf: Option<UniqueBuf>,
     pub len: usize,
     pub off: Off,
+    // If the first record in buf was streamed (see the `stream` module), the number of complete
+    // fields it kept.
+    pub streamed: Option<usize>,
 }
 
 impl<Off: Default> Default for OffsetChunk<Off> {
@@ -248,6 +280,7 @@ impl<Off: Default> Default for OffsetChunk<Off> {
             buf: None,
             len: 0,
             off: Default::default(),
+            streamed: None,
         }
     }
 }
@@ -266,6 +299,10 @@ impl<R: Read, F: FnMut(&[u8], &mut Offsets)> ChunkProducer for OffsetChunkProduc
         Ok(false)
     }
     fn get_chunk(&mut self, chunk: &mut OffsetChunk) -> Result<bool> {
+        if let Some(buf) = chunk.buf.take() {
+            // The consumer is done with the buffer from the last chunk.
+            self.inner.recycle(buf);
+        }
         loop {
             match self.state {
                 ChunkState::Init => {
@@ -279,6 +316,7 @@ impl<R: Read, F: FnMut(&[u8], &mut Offsets)> ChunkProducer for OffsetChunkProduc
                     chunk.version = self.cur_file_version;
                     chunk.name = self.name.clone();
                     let buf = self.inner.buf.clone();
+                    chunk.streamed = self.inner.streamed;
                     let bs = buf.as_bytes();
                     (self.find_indexes)(bs, &mut chunk.off);
                     let mut target = None;
@@ -324,6 +362,14 @@ impl<R: Read, F: FnMut(&[u8], &mut Offsets)> ChunkProducer for OffsetChunkProduc
                     chunk.len = self.inner.end;
                     let is_eof = self.inner.reset()?;
                     return match (is_partial, is_eof) {
+                        (true, true) if chunk.len == 0 => {
+                            // There is no data left (the input may have been empty to begin
+                            // with). Move on to the next input rather than yielding an empty
+                            // chunk.
+                            self.inner.clear_buf();
+                            self.state = ChunkState::Done;
+                            continue;
+                        }
                         (false, false) => {
                             // Yield buffer, stay in main.
                             chunk.buf = Some(buf.try_unique().unwrap());
@@ -363,6 +409,9 @@ impl<R: Read, F: FnMut(&[u8], &mut WhitespaceOffsets, u64) -> u64> ChunkProducer
         Ok(false)
     }
     fn get_chunk(&mut self, chunk: &mut Self::Chunk) -> Result<bool> {
+        if let Some(buf) = chunk.buf.take() {
+            self.0.inner.recycle(buf);
+        }
         loop {
             match self.0.state {
                 ChunkState::Init => {
@@ -376,6 +425,7 @@ impl<R: Read, F: FnMut(&[u8], &mut WhitespaceOffsets, u64) -> u64> ChunkProducer
                     chunk.version = self.0.cur_file_version;
                     chunk.name = self.0.name.clone();
                     let buf = self.0.inner.buf.clone();
+                    chunk.streamed = self.0.inner.streamed;
                     let bs = buf.as_bytes();
                     self.1 = (self.0.find_indexes)(bs, &mut chunk.off, self.1);
                     // Find the last newline in the buffer, if there is one.
@@ -403,6 +453,11 @@ impl<R: Read, F: FnMut(&[u8], &mut WhitespaceOffsets, u64) -> u64> ChunkProducer
                     chunk.len = self.0.inner.end;
                     let is_eof = self.0.inner.reset()?;
                     return match (is_partial, is_eof) {
+                        (true, true) if chunk.len == 0 => {
+                            self.0.inner.clear_buf();
+                            self.0.state = ChunkState::Done;
+                            continue;
+                        }
                         (false, false) => {
                             // Yield buffer, stay in main.
                             chunk.buf = Some(buf.try_unique().unwrap());
@@ -475,10 +530,10 @@ impl<P: ChunkProducer> ChunkProducer for ChainedChunkProducer<P> {
 }
 
 /// ParallelChunkProducer allows for consumption of individual chunks from a ChunkProducer in
-/// parallel.
+/// parallel. Chunks are numbered in the order they are read, for --keep-order.
 pub struct ParallelChunkProducer<P: ChunkProducer> {
     start: Receiver<()>,
-    incoming: Receiver<P::Chunk>,
+    incoming: Receiver<(u64, P::Chunk)>,
     spent: Sender<P::Chunk>,
 }
 
@@ -504,12 +559,15 @@ impl<P: ChunkProducer + 'static> ParallelChunkProducer<P> {
             let mut n_workers = 0;
             let mut p = p_factory();
             let mut n_failures = 0;
+            let mut seq = 0u64;
             loop {
                 let mut chunk = spent_receiver.try_recv().ok().unwrap_or_default();
                 let chunk_res = p.get_chunk(&mut chunk);
                 if chunk_res.is_err() || matches!(chunk_res, Ok(true)) {
                     return;
                 }
+                let mut chunk = (seq, chunk);
+                seq += 1;
                 match in_sender.try_send(chunk) {
                     Ok(()) => {
                         n_failures = 0;
@@ -578,11 +636,13 @@ impl<P: ChunkProducer + 'static> ChunkProducer for ParallelChunkProducer<P> {
             .is_ok()
     }
     fn get_chunk(&mut self, chunk: &mut P::Chunk) -> Result<bool> {
-        if let Ok(mut new_chunk) = self.incoming.recv() {
+        if let Ok((seq, mut new_chunk)) = self.incoming.recv() {
             mem::swap(chunk, &mut new_chunk);
             let _ = self.spent.try_send(new_chunk);
+            writers::start_chunk(seq)?;
             Ok(false)
         } else {
+            writers::finish_chunk()?;
             Ok(true)
         }
     }
@@ -594,9 +654,10 @@ enum ProducerState<T> {
     Done,
 }
 
-/// ShardedChunkProducer allows consuption of entire chunk producers in parallel
+/// ShardedChunkProducer allows consuption of entire chunk producers in parallel. For the purposes
+/// of --keep-order, each producer counts as a single chunk.
 pub struct ShardedChunkProducer<P> {
-    incoming: Receiver<Box<dyn FnOnce() -> P + Send>>,
+    incoming: Receiver<(u64, Box<dyn FnOnce() -> P + Send>)>,
     state: ProducerState<P>,
 }
 
@@ -610,9 +671,9 @@ impl<P: ChunkProducer + 'static> ShardedChunkProducer<P> {
         // small buffer.
         let (sender, receiver) = bounded(1);
         std::thread::spawn(move || {
-            for p_factory in ps {
+            for (seq, p_factory) in ps.enumerate() {
                 let to_send: Box<dyn FnOnce() -> P + Send> = Box::new(p_factory);
-                if sender.send(to_send).is_err() {
+                if sender.send((seq as u64, to_send)).is_err() {
                     return;
                 }
             }
@@ -623,15 +684,17 @@ impl<P: ChunkProducer + 'static> ShardedChunkProducer<P> {
         }
     }
 
-    fn refresh_producer(&mut self) -> bool {
-        let next = if let Ok(p) = self.incoming.recv() {
+    fn refresh_producer(&mut self) -> Result<bool> {
+        let (seq, next) = if let Ok(p) = self.incoming.recv() {
             p
         } else {
             self.state = ProducerState::Done;
-            return false;
+            writers::finish_chunk()?;
+            return Ok(false);
         };
+        writers::start_chunk(seq)?;
         self.state = ProducerState::Main(next());
-        true
+        Ok(true)
     }
 }
 
@@ -652,9 +715,9 @@ impl<P: ChunkProducer + 'static> ChunkProducer for ShardedChunkProducer<P> {
     }
     fn next_file(&mut self) -> Result<bool> {
         match &mut self.state {
-            ProducerState::Init => Ok(self.refresh_producer()),
+            ProducerState::Init => self.refresh_producer(),
             ProducerState::Done => Ok(false),
-            ProducerState::Main(p) => Ok(p.next_file()? || self.refresh_producer()),
+            ProducerState::Main(p) => Ok(p.next_file()? || self.refresh_producer()?),
         }
     }
     fn get_chunk(&mut self, chunk: &mut Self::Chunk) -> Result<bool> {
@@ -664,9 +727,9 @@ impl<P: ChunkProducer + 'static> ChunkProducer for ShardedChunkProducer<P> {
                     if !p.get_chunk(chunk)? {
                         return Ok(false);
                     }
-                    self.refresh_producer()
+                    self.refresh_producer()?
                 }
-                ProducerState::Init => self.refresh_producer(),
+                ProducerState::Init => self.refresh_producer()?,
                 ProducerState::Done => return Ok(true),
             };
         }
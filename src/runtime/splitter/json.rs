@@ -0,0 +1,223 @@
+//! Reading JSON Lines as input records.
+//!
+//! With `-i json`, each non-blank line of each input is a JSON object, which becomes a record with
+//! one field per key. Keys are numbered in the order they first appear in an input, so a key has
+//! the same column in every record of that input, even in records that omit it or list their keys
+//! in a different order. `$0` holds the line itself. With `-H`, the keys of the first object of
+//! the first input are read as the first record, so that values can be referenced by key via `FI`.
+//!
+//! Strings are read unescaped, `true` and `false` as 1 and 0, and `null` as the empty string.
+//! Arrays and nested objects are read as JSON text.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::mem;
+
+use hashbrown::HashMap;
+use simd_json::{prelude::*, BorrowedValue, StaticNode};
+
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::{convert, Float, Int, Str};
+
+use super::{DefaultLine, LineReader, ReaderState};
+
+fn value_str(v: &BorrowedValue) -> Str<'static> {
+    match v {
+        BorrowedValue::Static(StaticNode::Null) => Str::default(),
+        BorrowedValue::Static(StaticNode::Bool(b)) => convert::<Int, Str>(*b as Int),
+        BorrowedValue::Static(StaticNode::I64(i)) => convert::<Int, Str>(*i),
+        BorrowedValue::Static(StaticNode::F64(f)) => convert::<Float, Str>(*f),
+        BorrowedValue::String(s) => Str::from(s.as_bytes()).unmoor(),
+        v => Str::from(v.encode()),
+    }
+}
+
+enum Source {
+    Stdin,
+    File(String),
+}
+
+pub struct JsonReader {
+    name: Str<'static>,
+    // Inputs are opened on the first read.
+    source: Option<Source>,
+    input: Option<Box<dyn BufRead>>,
+    buf: Vec<u8>,
+    lineno: usize,
+    // The column of each key seen so far, numbered from 0.
+    columns: HashMap<Vec<u8>, usize>,
+    // Whether the keys of the first object have yet to be read as a header.
+    read_header: bool,
+    // Whether `buf` holds a line that has yet to be read as a record.
+    pending: bool,
+    used_fields: FieldSet,
+    state: ReaderState,
+    check_utf8: bool,
+    // Used to trigger updating FILENAME on the first read.
+    start: bool,
+}
+
+impl JsonReader {
+    /// Read JSON Lines from the file at `path`, or standard input if `path` is "-". If
+    /// `read_header` is set, the first record read holds the keys of the first object.
+    pub fn new(path: &str, read_header: bool, check_utf8: bool) -> JsonReader {
+        let source = if path == "-" {
+            Source::Stdin
+        } else {
+            Source::File(path.into())
+        };
+        JsonReader {
+            name: Str::from(path).unmoor(),
+            source: Some(source),
+            input: None,
+            buf: Vec::new(),
+            lineno: 0,
+            columns: HashMap::new(),
+            read_header,
+            pending: false,
+            used_fields: FieldSet::all(),
+            state: ReaderState::OK,
+            check_utf8,
+            start: true,
+        }
+    }
+
+    fn open(&mut self) -> Result<()> {
+        self.input = match self.source.take() {
+            Some(Source::Stdin) => Some(Box::new(BufReader::new(io::stdin()))),
+            Some(Source::File(path)) => match File::open(path.as_str()) {
+                Ok(f) => Some(Box::new(BufReader::new(f))),
+                Err(e) => return user_err!("failed to open {}: {}", path, e),
+            },
+            None => return Ok(()),
+        };
+        Ok(())
+    }
+
+    // Read the next non-blank line into `buf`, without its line ending. Returns false at the end
+    // of input.
+    fn next_line(&mut self) -> Result<bool> {
+        let input = match self.input.as_mut() {
+            Some(input) => input,
+            None => return Ok(false),
+        };
+        loop {
+            self.buf.clear();
+            match input.read_until(b'\n', &mut self.buf) {
+                Ok(0) => return Ok(false),
+                Ok(_) => {}
+                Err(e) => return user_err!("failed to read {}: {}", self.name, e),
+            }
+            self.lineno += 1;
+            while let Some(b'\n' | b'\r') = self.buf.last() {
+                self.buf.pop();
+            }
+            if !self.buf.iter().all(u8::is_ascii_whitespace) {
+                return Ok(true);
+            }
+        }
+    }
+
+    // Parse `buf`, placing the value of each key in its column of `fields`.
+    fn parse(&mut self, fields: &mut Vec<Str<'static>>) -> Result<()> {
+        let (name, lineno) = (&self.name, self.lineno);
+        let value = match simd_json::to_borrowed_value(&mut self.buf) {
+            Ok(value) => value,
+            Err(e) => return user_err!("invalid JSON in {} on line {}: {}", name, lineno, e),
+        };
+        let obj = match value {
+            BorrowedValue::Object(obj) => obj,
+            _ => return user_err!("expected a JSON object in {} on line {}", name, lineno),
+        };
+        for (key, v) in obj.iter() {
+            let next = self.columns.len();
+            let col = *self.columns.entry(key.as_bytes().to_vec()).or_insert(next);
+            if col >= fields.len() {
+                fields.resize_with(col + 1, Str::default);
+            }
+            if self.used_fields.get(col + 1) {
+                fields[col] = value_str(v);
+            }
+        }
+        // Columns for keys this object omits are empty.
+        fields.resize_with(self.columns.len(), Str::default);
+        Ok(())
+    }
+
+    // Read the next record into `fields`, returning its text. Returns None at the end of input.
+    fn read_record(&mut self, fields: &mut Vec<Str<'static>>) -> Result<Option<Str<'static>>> {
+        self.open()?;
+        fields.clear();
+        if !mem::replace(&mut self.pending, false) && !self.next_line()? {
+            return Ok(None);
+        }
+        if mem::replace(&mut self.read_header, false) {
+            // Parse a copy, so that the line can be read again as the first record.
+            let line = self.buf.clone();
+            self.parse(&mut Vec::new())?;
+            self.buf = line;
+            self.pending = true;
+            fields.resize_with(self.columns.len(), Str::default);
+            for (key, col) in self.columns.iter() {
+                fields[*col] = Str::from(&key[..]).unmoor();
+            }
+            return Ok(Some(Str::from("\t").join_slice(&fields[..])));
+        }
+        let line = Str::from(&self.buf[..]).unmoor();
+        self.parse(fields)?;
+        Ok(Some(line))
+    }
+}
+
+impl LineReader for JsonReader {
+    type Line = DefaultLine;
+    fn filename(&self) -> Str<'static> {
+        self.name.clone()
+    }
+    fn check_utf8(&self) -> bool {
+        self.check_utf8
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
+        let mut line = DefaultLine::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        _pat: &Str,
+        _rc: &mut super::RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        let start = mem::replace(&mut self.start, false);
+        old.used_fields = self.used_fields.clone();
+        old.diverged = false;
+        match self.read_record(&mut old.fields)? {
+            Some(line) => {
+                old.line = line;
+                self.state = ReaderState::OK;
+            }
+            None => {
+                old.line = Str::default();
+                self.state = ReaderState::Eof;
+            }
+        }
+        Ok(/* file changed */ start)
+    }
+    fn read_state(&self) -> i64 {
+        self.state as i64
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        // There is just one file. Set EOF.
+        self.source = None;
+        self.input = None;
+        self.pending = false;
+        self.state = ReaderState::Eof;
+        Ok(false)
+    }
+    fn file_index(&self) -> Int {
+        1
+    }
+    fn set_used_fields(&mut self, used_fields: &FieldSet) {
+        self.used_fields = used_fields.clone();
+    }
+}
@@ -0,0 +1,259 @@
+//! Limits on the size of input records, set with `--max-record-bytes` and `--max-fields`.
+//!
+//! A record exceeding a limit is either an error, or it is truncated with a warning (printed the
+//! first time a record is truncated; see `runtime::warnings`). The limit on record length is
+//! enforced by `Reader` as it fills its buffer, so that a single huge record (say, a large file
+//! that is missing its newlines) never has to be held in memory. The limit on the number of fields
+//! is enforced once a record has been split.
+//!
+//! The limits for a run of a program are part of its `runtime::Config`, and are handed to each
+//! reader and line that enforces them.
+//!
+//! This module also holds the limits on which records of the main input are read at all, set with
+//! `--skip`, `--skip-file` and `--head`; see `RecordRange`.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::common::Result;
+use crate::runtime::{
+    warnings::{Warning, Warnings},
+    Int, Str,
+};
+
+/// What to do with a record that exceeds one of the limits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OnLimit {
+    Error,
+    Truncate,
+}
+
+/// The limits on input records for a run of a program. The default is no limit at all. Clones
+/// share their record of which truncations have been reported.
+#[derive(Clone, Debug, Default)]
+pub struct Limits(Option<Arc<Settings>>);
+
+#[derive(Debug)]
+struct Settings {
+    max_record_bytes: usize,
+    max_fields: usize,
+    truncate: bool,
+    warned_record_bytes: AtomicBool,
+    warned_fields: AtomicBool,
+    warnings: Warnings,
+}
+
+impl Limits {
+    pub fn new(
+        max_record_bytes: Option<usize>,
+        max_fields: Option<usize>,
+        on_limit: OnLimit,
+        warnings: &Warnings,
+    ) -> Limits {
+        if max_record_bytes.is_none() && max_fields.is_none() {
+            return Limits(None);
+        }
+        Limits(Some(Arc::new(Settings {
+            max_record_bytes: max_record_bytes.unwrap_or(usize::MAX),
+            max_fields: max_fields.unwrap_or(usize::MAX),
+            truncate: on_limit == OnLimit::Truncate,
+            warned_record_bytes: AtomicBool::new(false),
+            warned_fields: AtomicBool::new(false),
+            warnings: warnings.clone(),
+        })))
+    }
+
+    pub(crate) fn max_record_bytes(&self) -> usize {
+        self.0.as_ref().map_or(usize::MAX, |s| s.max_record_bytes)
+    }
+
+    /// Whether `self` and `other` are (clones of) the same limits.
+    pub(crate) fn same(&self, other: &Limits) -> bool {
+        match (&self.0, &other.0) {
+            (Some(x), Some(y)) => Arc::ptr_eq(x, y),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Report that the record starting at byte `offset` of input `name` is longer than
+    /// `max_record_bytes()`. Returns an error unless such records are truncated.
+    pub(crate) fn record_too_long(&self, name: &str, offset: u64) -> Result<()> {
+        let settings = match &self.0 {
+            Some(settings) => settings,
+            None => return Ok(()),
+        };
+        let max = settings.max_record_bytes;
+        if !settings.truncate {
+            return user_err!(
+                "the record starting at byte {} of {} is longer than {} bytes (see --max-record-bytes)",
+                offset,
+                name,
+                max
+            );
+        }
+        settings.warn_once(&settings.warned_record_bytes, || {
+            format!(
+                "truncated the record starting at byte {} of {} to {} bytes",
+                offset, name, max
+            )
+        })
+    }
+
+    /// Enforce the limit on the number of fields in `record`, which has been split into `fields`.
+    pub(crate) fn check_fields<T>(&self, record: &Str, fields: &mut Vec<T>) -> Result<()> {
+        let settings = match &self.0 {
+            Some(settings) if fields.len() > settings.max_fields => settings,
+            _ => return Ok(()),
+        };
+        let max = settings.max_fields;
+        if !settings.truncate {
+            return user_err!(
+                "{} has {} fields, more than {} (see --max-fields)",
+                describe(record),
+                fields.len(),
+                max
+            );
+        }
+        settings.warn_once(&settings.warned_fields, || {
+            format!(
+                "truncated {} from {} fields to {}",
+                describe(record),
+                fields.len(),
+                max
+            )
+        })?;
+        fields.truncate(max);
+        Ok(())
+    }
+}
+
+impl Settings {
+    fn warn_once(&self, warned: &AtomicBool, msg: impl FnOnce() -> String) -> Result<()> {
+        if warned.swap(true, Ordering::Relaxed) {
+            self.warnings.count(Warning::Truncation);
+            return Ok(());
+        }
+        self.warnings.warn(Warning::Truncation, || {
+            format!("{} (further truncations are not reported)", msg())
+        })
+    }
+}
+
+// A short description of `record` for error messages. Batch readers leave `$0` empty when the
+// script does not use it, in which case there is nothing to show.
+fn describe(record: &Str) -> String {
+    const PREFIX_LEN: usize = 32;
+    record.with_bytes(|bs| {
+        if bs.is_empty() {
+            String::from("a record")
+        } else if bs.len() <= PREFIX_LEN {
+            format!("the record {:?}", String::from_utf8_lossy(bs))
+        } else {
+            format!(
+                "the record beginning {:?}",
+                String::from_utf8_lossy(&bs[..PREFIX_LEN])
+            )
+        }
+    })
+}
+
+/// Tracks the records of the main input that have been skipped or read so far. Skipped records
+/// are never seen by the program (they do not count towards `NR` or `FNR`), and once `--head`
+/// records have been read the input behaves as if it had reached its end.
+#[derive(Clone, Debug)]
+pub struct RecordRange {
+    skip: usize,
+    skip_file: usize,
+    // Records still to skip in the current file.
+    file_left: usize,
+    // The index of the current file, used to notice when we move on to the next one.
+    file: Int,
+    // Records still to read.
+    left: usize,
+    stopped: bool,
+}
+
+impl Default for RecordRange {
+    fn default() -> RecordRange {
+        RecordRange::new(0, 0, None)
+    }
+}
+
+impl RecordRange {
+    /// Skip the first `skip` records of the main input, and the first `skip_file` records of each
+    /// input file, and stop reading after `head` records.
+    pub fn new(skip: usize, skip_file: usize, head: Option<usize>) -> RecordRange {
+        RecordRange {
+            skip,
+            skip_file,
+            file_left: 0,
+            file: 0,
+            left: head.unwrap_or(usize::MAX),
+            stopped: false,
+        }
+    }
+
+    /// Whether the main input should be treated as finished, because `--head` records have
+    /// already been read.
+    pub(crate) fn stop(&mut self) -> bool {
+        self.stopped |= self.left == 0;
+        self.stopped
+    }
+
+    pub(crate) fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Whether the record just read from file number `file()` should be skipped.
+    pub(crate) fn skip(&mut self, file: impl FnOnce() -> Int) -> bool {
+        if self.skip == 0 && self.skip_file == 0 {
+            return false;
+        }
+        let file = file();
+        if file != self.file {
+            self.file = file;
+            self.file_left = self.skip_file;
+        }
+        let skip = self.skip > 0 || self.file_left > 0;
+        self.skip = self.skip.saturating_sub(1);
+        self.file_left = self.file_left.saturating_sub(1);
+        skip
+    }
+
+    /// Count a record that was read.
+    pub(crate) fn take(&mut self) {
+        self.left = self.left.saturating_sub(1);
+    }
+
+    /// Stop counting a record that was read, such as a header read with `-H`.
+    pub(crate) fn untake(&mut self) {
+        self.left = self.left.saturating_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_range() {
+        let mut range = RecordRange {
+            skip: 1,
+            skip_file: 1,
+            file_left: 0,
+            file: 0,
+            left: 2,
+            stopped: false,
+        };
+        // The first record of the first file is skipped once, not twice.
+        assert!(range.skip(|| 1));
+        assert!(!range.skip(|| 1));
+        range.take();
+        assert!(range.skip(|| 2));
+        assert!(!range.skip(|| 2));
+        assert!(!range.stop());
+        range.take();
+        assert!(range.stop());
+        assert!(range.stopped());
+    }
+}
@@ -5,16 +5,33 @@
 //! characters that cross chunk boundaries, or multi-chunk "lines".
 
 // TODO: add padding to the linereader trait
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod auto;
+#[cfg(feature = "avro")]
+pub mod avro;
 pub mod batch;
+pub mod binary;
 pub mod chunk;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod limits;
 pub mod regex;
+pub mod socket;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stream;
+pub mod xml;
 
 use super::str_impl::{Buf, Str, UniqueBuf};
 use super::utf8::{is_utf8, validate_utf8_clipped};
 use super::{Int, RegexCache};
-use crate::common::Result;
+use crate::common::{CompileError, Result};
 use crate::pushdown::FieldSet;
+use limits::Limits;
+use stream::{FieldSep, Streamer};
 
+use std::collections::VecDeque;
 use std::io::{ErrorKind, Read};
 
 // We have several implementations of "read and split a line"; they are governed by the LineReader
@@ -34,6 +51,42 @@ pub trait Line<'a>: Default {
     fn nf(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<usize>;
     fn get_col(&mut self, col: Int, pat: &Str, ofs: &Str, rc: &mut RegexCache) -> Result<Str<'a>>;
     fn set_col(&mut self, col: Int, s: &Str<'a>, pat: &Str, rc: &mut RegexCache) -> Result<()>;
+    // Split $0 again using `new_pat` rather than the field separator `pat`, returning the number
+    // of fields. The new fields remain until $0 changes.
+    fn resplit(
+        &mut self,
+        new_pat: &Str,
+        pat: &Str,
+        ofs: &Str,
+        rc: &mut RegexCache,
+    ) -> Result<usize>;
+    // Substitute `subst` for the first match of `pat` in $0 (or for every match, if `global` is
+    // set), returning the number of substitutions. $0 is only replaced if something was
+    // substituted; its fields are split again when they are next read.
+    fn subst_line(
+        &mut self,
+        pat: &Str,
+        subst: &Str,
+        global: bool,
+        fs: &Str,
+        ofs: &Str,
+        rc: &mut RegexCache,
+    ) -> Result<Int> {
+        let line = self.get_col(0, fs, ofs, rc)?;
+        let (subbed, n) = if global {
+            rc.with_regex_subst(pat, subst, |re, s| line.subst_all(re, s))?
+        } else {
+            let (subbed, new) = rc.with_regex_subst(pat, subst, |re, s| line.subst_first(re, s))?;
+            (subbed, new as Int)
+        };
+        if n > 0 {
+            self.set_col(0, &subbed, fs, rc)?;
+        }
+        Ok(n)
+    }
+    // Enforce `limits` on the number of fields when splitting this line. Lines are created
+    // without limits, so the runtime sets them on the lines it reads into.
+    fn set_limits(&mut self, _limits: &Limits) {}
 }
 
 pub trait LineReader: Sized {
@@ -63,6 +116,44 @@ pub trait LineReader: Sized {
     }
     fn read_state(&self) -> i64;
     fn next_file(&mut self) -> Result<bool>;
+    // The 1-based position of the current file among the inputs this reader was constructed
+    // with. This is used to populate ARGIND.
+    fn file_index(&self) -> Int;
+    // The number of records read from the current file before the current record, which FNR
+    // starts from when the file changes. This is only nonzero for readers that interleave records
+    // from several files (see --zip); otherwise a new file starts from its first record.
+    fn file_records(&self) -> Int {
+        0
+    }
+    // The text that ended the last record read, which populates RT. This is empty at the end of
+    // the input, and for readers that do not split records with RS.
+    fn terminator(&self) -> Str<'static> {
+        Str::default()
+    }
+    // Whether the last read stopped at the start or the end of a file (see `FileBoundary`)
+    // rather than returning a record. Only readers with file hooks enabled stop there.
+    fn file_boundary(&self) -> Int {
+        FileBoundary::Record as Int
+    }
+    // Leave the file boundary that the last read stopped at to be returned again by the next
+    // read, reporting the end of the input until then.
+    fn defer_boundary(&mut self) {}
+    // Whether reads stop at the start and end of each file, for BEGINFILE and ENDFILE.
+    fn file_hooks(&self) -> bool {
+        false
+    }
+    // Move on to the next input named in ARGV, which has `argc` entries given by `argv`, once the
+    // current input has run out, returning whether there is one. Only `ArgvReader` consults ARGV
+    // like this; other readers are given all of their inputs when they are created.
+    fn next_input<'a>(&mut self, _argc: Int, _argv: &dyn Fn(Int) -> Str<'a>) -> Result<bool> {
+        Ok(false)
+    }
+    // Whether the next record has not arrived yet, though the input has not ended. Reading from
+    // such a reader suspends the interpreter until it has (see `Interp::resume`); only the reader
+    // fed by `embed::Run` ever waits like this.
+    fn pending(&self) -> bool {
+        false
+    }
     fn set_used_fields(&mut self, used_fields: &FieldSet);
     // Whether or not this LineReader is configured to check for valid UTF-8. This is used to
     // propagate consistent options across multiple LineReader instances.
@@ -98,6 +189,14 @@ pub struct DefaultLine {
     // After that first line, we set diverged to true, so we know to regenerate $0 when $0 is asked
     // for. This speeds up cases where multiple fields are assigned in a row.
     diverged: bool,
+    // If this record was streamed (see the `stream` module), the number of leading fields that
+    // are available. Only these fields may be read until $0 is assigned to.
+    streamed: Option<usize>,
+    // Was this record read in paragraph mode (with an empty RS)? If so, newlines separate fields
+    // as well as FS.
+    paragraph: bool,
+    // The limit on the number of fields enforced when splitting; see `Line::set_limits`.
+    limits: Limits,
 }
 
 impl Default for DefaultLine {
@@ -107,17 +206,56 @@ impl Default for DefaultLine {
             used_fields: FieldSet::all(),
             fields: Vec::new(),
             diverged: false,
+            streamed: None,
+            paragraph: false,
+            limits: Limits::default(),
         }
     }
 }
 
 impl DefaultLine {
+    /// Replace the contents of this line with `line`, which has not been split into fields yet.
+    pub(crate) fn set(&mut self, line: Str<'static>) {
+        self.line = line;
+        self.fields.clear();
+        self.diverged = false;
+    }
+
+    // Split $0 into `fields`, which must be empty, using the field separator `pat`.
+    fn split_line(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<()> {
+        if self.paragraph {
+            rc.split_regex_paragraph(pat, &self.line, &self.used_fields, &mut self.fields)
+        } else {
+            rc.split_regex(pat, &self.line, &self.used_fields, &mut self.fields)
+        }
+    }
+
     fn split_if_needed(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<()> {
         if self.fields.is_empty() {
-            rc.split_regex(pat, &self.line, &self.used_fields, &mut self.fields)?;
+            self.split_line(pat, rc)?;
+            self.limits.check_fields(&self.line, &mut self.fields)?;
         }
         Ok(())
     }
+
+    fn check_streamed(&self, col: usize) -> Result<()> {
+        match self.streamed {
+            Some(kept) if col == 0 || col > kept => {
+                let field = if col == 0 {
+                    String::from("$0")
+                } else {
+                    format!("field {}", col)
+                };
+                err!(
+                    "{} is not available: the record was too long to buffer, so only its first {} \
+                     fields were kept (see --stream-records)",
+                    field,
+                    kept
+                )
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl<'a> Line<'a> for DefaultLine {
@@ -135,6 +273,7 @@ impl<'a> Line<'a> for DefaultLine {
         // Should have split before calling this function.
         debug_assert!(!self.fields.is_empty());
         let (start, end) = normalize_join_indexes(start, end, nf)?;
+        self.check_streamed(end)?;
         Ok(sep
             .clone()
             .unmoor()
@@ -150,6 +289,7 @@ impl<'a> Line<'a> for DefaultLine {
         if col < 0 {
             return err!("attempt to access field {}; field must be nonnegative", col);
         }
+        self.check_streamed(col as usize)?;
         let res = if col == 0 && !self.diverged {
             self.line.clone()
         } else if col == 0 && self.diverged {
@@ -162,18 +302,17 @@ impl<'a> Line<'a> for DefaultLine {
                 // place; so once we hit this condition we overwrite the used fields with all() so
                 // this doesn't happen again for a while.
                 let old_set = std::mem::replace(&mut self.used_fields, FieldSet::all());
-                let mut new_vec = Vec::with_capacity(self.fields.len());
-                rc.split_regex(pat, &self.line, &self.used_fields, &mut new_vec)?;
+                let old_fields = std::mem::take(&mut self.fields);
+                self.split_line(pat, rc)?;
 
-                for (i, field) in self.fields.iter().enumerate().rev() {
-                    if i >= new_vec.len() {
-                        new_vec.resize_with(i + 1, Str::default);
+                for (i, field) in old_fields.into_iter().enumerate().rev() {
+                    if i >= self.fields.len() {
+                        self.fields.resize_with(i + 1, Str::default);
                     }
                     if old_set.get(i + 1) {
-                        new_vec[i] = field.clone()
+                        self.fields[i] = field
                     }
                 }
-                self.fields = new_vec;
             }
             let res = ofs.join_slice(&self.fields[..]);
             self.line = res.clone();
@@ -192,6 +331,7 @@ impl<'a> Line<'a> for DefaultLine {
         if col == 0 {
             self.line = s.clone().unmoor();
             self.fields.clear();
+            self.streamed = None;
             return Ok(());
         }
         if col < 0 {
@@ -206,20 +346,410 @@ impl<'a> Line<'a> for DefaultLine {
         self.diverged = true;
         Ok(())
     }
+    fn resplit(
+        &mut self,
+        new_pat: &Str,
+        pat: &Str,
+        ofs: &Str,
+        rc: &mut RegexCache,
+    ) -> Result<usize> {
+        self.check_streamed(0)?;
+        if self.diverged {
+            // Regenerate $0 from the fields that were assigned to.
+            self.get_col(0, pat, ofs, rc)?;
+        }
+        // Later field assignments rebuild $0 from these fields, so they must all be present.
+        self.used_fields = FieldSet::all();
+        self.fields.clear();
+        self.split_line(new_pat, rc)?;
+        self.limits.check_fields(&self.line, &mut self.fields)?;
+        Ok(self.fields.len())
+    }
+    fn set_limits(&mut self, limits: &Limits) {
+        if !self.limits.same(limits) {
+            self.limits = limits.clone();
+        }
+    }
 }
 
-pub struct ChainedReader<R>(Vec<R>, /*check_utf8=*/ bool);
+// Creates a reader for the named input, or for standard input.
+type OpenInput<R> = Box<dyn FnMut(Option<&str>) -> R>;
+
+/// Reads the inputs named in ARGV one at a time, consulting ARGV as each one is opened, so that a
+/// program can skip inputs or add new ones by editing ARGV as it runs. As in POSIX awk, entries
+/// that are deleted or set to the empty string, or that ARGC leaves out, are skipped; if ARGV
+/// names no inputs, standard input is read instead.
+///
+/// Readers that are given all of their inputs when they are created (see `ArgvReader::fixed`)
+/// ignore ARGV. That is how parallel and zipped inputs are read.
+pub struct ArgvReader<R> {
+    cur: Option<R>,
+    open: Option<OpenInput<R>>,
+    // The index in ARGV of the current input, or 0 for standard input.
+    index: Int,
+    // Whether an input has been opened.
+    started: bool,
+    hooks: bool,
+    check_utf8: bool,
+    used_fields: FieldSet,
+}
+
+impl<R: LineReader> ArgvReader<R> {
+    /// Read the inputs named in ARGV, opening each one with `open`. `hooks` is passed on to
+    /// `LineReader::file_hooks`, and `check_utf8` to `LineReader::check_utf8`, for the readers
+    /// that `open` creates.
+    pub fn new(
+        check_utf8: bool,
+        hooks: bool,
+        open: impl FnMut(Option<&str>) -> R + 'static,
+    ) -> ArgvReader<R> {
+        ArgvReader {
+            cur: None,
+            open: Some(Box::new(open)),
+            index: 0,
+            started: false,
+            hooks,
+            check_utf8,
+            used_fields: FieldSet::all(),
+        }
+    }
+
+    /// Read the inputs of `reader`, regardless of ARGV.
+    pub fn fixed(reader: R) -> ArgvReader<R> {
+        ArgvReader {
+            hooks: reader.file_hooks(),
+            check_utf8: reader.check_utf8(),
+            cur: Some(reader),
+            open: None,
+            index: 0,
+            started: true,
+            used_fields: FieldSet::all(),
+        }
+    }
+}
+
+impl<R: LineReader + 'static> LineReader for ArgvReader<R>
+where
+    R::Line: Default,
+{
+    type Line = R::Line;
+    fn filename(&self) -> Str<'static> {
+        self.cur.as_ref().map_or_else(Str::default, R::filename)
+    }
+    fn request_handles(&self, size: usize) -> Vec<Box<dyn FnOnce() -> Self + Send>> {
+        match &self.cur {
+            Some(cur) if self.open.is_none() => cur
+                .request_handles(size)
+                .into_iter()
+                .map(|handle| {
+                    let handle: Box<dyn FnOnce() -> Self + Send> =
+                        Box::new(move || ArgvReader::fixed(handle()));
+                    handle
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+    fn wait(&self) -> bool {
+        match &self.cur {
+            Some(cur) => cur.wait(),
+            None => true,
+        }
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<(bool, Self::Line)> {
+        let mut line = R::Line::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        pat: &Str,
+        rc: &mut RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        match &mut self.cur {
+            Some(cur) => cur.read_line_reuse(pat, rc, old),
+            // No input has been opened yet; `next_input` opens the first one.
+            None => {
+                *old = Default::default();
+                Ok(false)
+            }
+        }
+    }
+    fn read_state(&self) -> i64 {
+        self.cur
+            .as_ref()
+            .map_or(ReaderState::Eof as i64, R::read_state)
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        match &mut self.cur {
+            Some(cur) => cur.next_file(),
+            None => Ok(false),
+        }
+    }
+    fn next_input<'a>(&mut self, argc: Int, argv: &dyn Fn(Int) -> Str<'a>) -> Result<bool> {
+        let open = match &mut self.open {
+            Some(open) => open,
+            None => return Ok(false),
+        };
+        if self.started && self.index == 0 {
+            // Standard input was read in place of the inputs in ARGV.
+            return Ok(false);
+        }
+        let next = (self.index + 1..argc)
+            .map(|ix| (ix, argv(ix)))
+            .find(|(_, name)| !name.is_empty());
+        let mut reader = match next {
+            Some((ix, name)) => {
+                self.index = ix;
+                name.with_bytes(|bs| open(Some(&String::from_utf8_lossy(bs))))
+            }
+            None if !self.started => open(None),
+            None => return Ok(false),
+        };
+        self.started = true;
+        reader.set_used_fields(&self.used_fields);
+        self.cur = Some(reader);
+        Ok(true)
+    }
+    fn file_index(&self) -> Int {
+        match &self.cur {
+            Some(cur) if self.open.is_none() => cur.file_index(),
+            _ => self.index,
+        }
+    }
+    fn file_records(&self) -> Int {
+        self.cur.as_ref().map_or(0, R::file_records)
+    }
+    fn terminator(&self) -> Str<'static> {
+        self.cur.as_ref().map_or_else(Str::default, R::terminator)
+    }
+    fn file_boundary(&self) -> Int {
+        self.cur
+            .as_ref()
+            .map_or(FileBoundary::Record as Int, R::file_boundary)
+    }
+    fn defer_boundary(&mut self) {
+        if let Some(cur) = &mut self.cur {
+            cur.defer_boundary();
+        }
+    }
+    fn file_hooks(&self) -> bool {
+        self.hooks
+    }
+    fn set_used_fields(&mut self, used_fields: &FieldSet) {
+        self.used_fields = used_fields.clone();
+        if let Some(cur) = &mut self.cur {
+            cur.set_used_fields(used_fields);
+        }
+    }
+    fn check_utf8(&self) -> bool {
+        self.check_utf8
+    }
+}
+
+struct Input<R> {
+    reader: R,
+    // The 1-based position of this input among all of the inputs.
+    index: Int,
+    // The number of records read from this input so far; only tracked with --zip.
+    records: Int,
+    // The name of this input, for readers that only learn it once they start reading.
+    name: Str<'static>,
+}
+
+/// Reads records from a sequence of inputs: each input in its entirety before the next, or, with
+/// --zip, one record from each input in turn. In the latter case, inputs that run out of records
+/// drop out of the rotation, and a change of input is reported whenever consecutive records come
+/// from different inputs.
+///
+/// With file hooks enabled, reads also stop at the start and end of each input, so that BEGINFILE
+/// and ENDFILE can run before its first record and after its last one.
+pub struct ChainedReader<R> {
+    // The inputs that have not been exhausted, in order.
+    inputs: VecDeque<Input<R>>,
+    zip: bool,
+    // The position in `inputs` of the input that the current record came from. This is always 0
+    // unless zipping.
+    cur: usize,
+    // Whether the next read should move on to the next input (when zipping).
+    advance: bool,
+    // The input that the current record came from.
+    rec_index: Int,
+    // The number of records read from that input before the current one (when zipping).
+    rec_base: Int,
+    // The index of the last input to be exhausted.
+    last_index: Int,
+    // The text that ended the current record.
+    terminator: Str<'static>,
+    hooks: bool,
+    // Where the last read stopped (when running file hooks).
+    boundary: FileBoundary,
+    // Whether the start of the first input has been reported (when running file hooks).
+    started: bool,
+    // Whether the first input has run out of records, or was skipped with nextfile; its end is
+    // reported at the next read (when running file hooks).
+    exhausted: bool,
+    // Whether the first input was skipped from BEGINFILE, in which case it has no end to report.
+    skipped: bool,
+    // Whether the next read should return `boundary` again; see `LineReader::defer_boundary`.
+    deferred: bool,
+    check_utf8: bool,
+}
 
 impl<R: LineReader> ChainedReader<R> {
     pub fn new(rs: impl Iterator<Item = R>) -> ChainedReader<R> {
-        let mut v: Vec<_> = rs.collect();
-        v.reverse();
-        let check_utf8 = if let Some(r) = v.last() {
-            r.check_utf8()
+        ChainedReader::with_names(rs.map(|reader| {
+            let name = reader.filename();
+            (reader, name)
+        }))
+    }
+
+    /// Like `new`, but with the name of each input given explicitly, for readers that do not
+    /// report one before their first read.
+    pub fn with_names<S: Into<Str<'static>>>(rs: impl Iterator<Item = (R, S)>) -> ChainedReader<R> {
+        let inputs: VecDeque<_> = rs
+            .enumerate()
+            .map(|(i, (reader, name))| Input {
+                reader,
+                index: i as Int + 1,
+                records: 0,
+                name: name.into(),
+            })
+            .collect();
+        let check_utf8 = if let Some(i) = inputs.front() {
+            i.reader.check_utf8()
         } else {
             false
         };
-        ChainedReader(v, check_utf8)
+        ChainedReader {
+            inputs,
+            zip: false,
+            cur: 0,
+            advance: false,
+            rec_index: 0,
+            rec_base: 0,
+            last_index: 0,
+            terminator: Str::default(),
+            hooks: false,
+            boundary: FileBoundary::Record,
+            started: false,
+            exhausted: false,
+            skipped: false,
+            deferred: false,
+            check_utf8,
+        }
+    }
+
+    /// Read the inputs in lockstep (--zip): one record from each input in turn, rather than each
+    /// input in its entirety before the next.
+    pub fn zip(mut self, zip: bool) -> ChainedReader<R> {
+        self.zip = zip;
+        self
+    }
+
+    /// Stop at the start and end of each input, so that the program can run its BEGINFILE and
+    /// ENDFILE actions; see `LineReader::file_boundary`.
+    pub fn file_hooks(mut self, hooks: bool) -> ChainedReader<R> {
+        self.hooks = hooks;
+        self
+    }
+}
+
+impl<R: LineReader + 'static> ChainedReader<R>
+where
+    R::Line: Default,
+{
+    fn read_line_zipped(
+        &mut self,
+        pat: &Str,
+        rc: &mut RegexCache,
+        old: &mut R::Line,
+    ) -> Result<bool> {
+        if std::mem::take(&mut self.advance) {
+            self.cur += 1;
+        }
+        loop {
+            if self.cur >= self.inputs.len() {
+                self.cur = 0;
+            }
+            let cur = match self.inputs.get_mut(self.cur) {
+                Some(cur) => cur,
+                None => {
+                    *old = Default::default();
+                    self.terminator = Str::default();
+                    return Ok(false);
+                }
+            };
+            cur.reader.read_line_reuse(pat, rc, old)?;
+            if cur.reader.read_state() == ReaderState::Eof as i64 {
+                self.next_file()?;
+                continue;
+            }
+            self.terminator = cur.reader.terminator();
+            self.advance = true;
+            self.rec_base = cur.records;
+            cur.records += 1;
+            let changed = cur.index != self.rec_index;
+            self.rec_index = cur.index;
+            return Ok(changed);
+        }
+    }
+
+    fn read_line_hooks(
+        &mut self,
+        pat: &Str,
+        rc: &mut RegexCache,
+        old: &mut R::Line,
+    ) -> Result<bool> {
+        if std::mem::take(&mut self.deferred) {
+            *old = Default::default();
+            self.terminator = Str::default();
+            return Ok(self.boundary == FileBoundary::Start);
+        }
+        loop {
+            let cur = match self.inputs.front_mut() {
+                Some(cur) => cur,
+                None => {
+                    *old = Default::default();
+                    self.terminator = Str::default();
+                    self.boundary = FileBoundary::Record;
+                    return Ok(false);
+                }
+            };
+            if !self.started {
+                // Report the new file, but leave it unopened until its first record is read: it
+                // may yet be skipped from BEGINFILE.
+                self.started = true;
+                self.boundary = FileBoundary::Start;
+                *old = Default::default();
+                self.terminator = Str::default();
+                return Ok(true);
+            }
+            if self.exhausted {
+                self.last_index = cur.index;
+                self.inputs.pop_front();
+                self.started = false;
+                self.exhausted = false;
+                if std::mem::take(&mut self.skipped) {
+                    continue;
+                }
+                // The file variables still describe the input that just ended, so this does not
+                // count as a change of file.
+                self.boundary = FileBoundary::End;
+                *old = Default::default();
+                self.terminator = Str::default();
+                return Ok(false);
+            }
+            cur.reader.read_line_reuse(pat, rc, old)?;
+            if cur.reader.read_state() == ReaderState::Eof as i64 {
+                self.exhausted = true;
+                continue;
+            }
+            self.terminator = cur.reader.terminator();
+            self.boundary = FileBoundary::Record;
+            return Ok(false);
+        }
     }
 }
 
@@ -229,12 +759,19 @@ where
 {
     type Line = R::Line;
     fn check_utf8(&self) -> bool {
-        self.1
+        self.check_utf8
     }
     fn filename(&self) -> Str<'static> {
-        self.0
-            .last()
-            .map(LineReader::filename)
+        self.inputs
+            .get(self.cur)
+            .map(|i| {
+                let name = i.reader.filename();
+                if name.is_empty() {
+                    i.name.clone()
+                } else {
+                    name
+                }
+            })
             .unwrap_or_else(Str::default)
     }
     fn read_line(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<(bool, R::Line)> {
@@ -248,14 +785,22 @@ where
         rc: &mut RegexCache,
         old: &'a mut Self::Line,
     ) -> Result<bool> {
-        let cur = match self.0.last_mut() {
-            Some(cur) => cur,
+        if self.zip {
+            return self.read_line_zipped(pat, rc, old);
+        }
+        if self.hooks {
+            return self.read_line_hooks(pat, rc, old);
+        }
+        let cur = match self.inputs.front_mut() {
+            Some(cur) => &mut cur.reader,
             None => {
                 *old = Default::default();
+                self.terminator = Str::default();
                 return Ok(false);
             }
         };
         let changed = cur.read_line_reuse(pat, rc, old)?;
+        self.terminator = cur.terminator();
         if cur.read_state() == 0 /* EOF */ && self.next_file()? {
             self.read_line_reuse(pat, rc, old)?;
             Ok(true)
@@ -264,25 +809,65 @@ where
         }
     }
     fn read_state(&self) -> i64 {
-        match self.0.last() {
-            Some(cur) => cur.read_state(),
+        if self.deferred {
+            return ReaderState::Eof as i64;
+        }
+        if self.boundary != FileBoundary::Record {
+            return ReaderState::OK as i64;
+        }
+        match self.inputs.get(self.cur) {
+            Some(cur) => cur.reader.read_state(),
             None => 0, /* EOF */
         }
     }
     fn next_file(&mut self) -> Result<bool> {
-        Ok(match self.0.last_mut() {
+        if self.hooks {
+            // The input is dropped at the next read, once its end has been reported. An input
+            // skipped before its first record is never opened.
+            if self.inputs.is_empty() {
+                return Ok(false);
+            }
+            self.skipped |= self.boundary == FileBoundary::Start;
+            self.exhausted = true;
+            return Ok(true);
+        }
+        Ok(match self.inputs.get_mut(self.cur) {
             Some(e) => {
-                if !e.next_file()? {
-                    self.0.pop();
+                if !e.reader.next_file()? {
+                    self.last_index = e.index;
+                    self.inputs.remove(self.cur);
+                    // The next input has taken this one's place.
+                    self.advance = false;
                 }
                 true
             }
             None => false,
         })
     }
+    fn file_index(&self) -> Int {
+        // Once every input has been consumed, keep reporting the last one.
+        self.inputs
+            .get(self.cur)
+            .map_or(self.last_index, |i| i.index)
+    }
+    fn file_records(&self) -> Int {
+        self.rec_base
+    }
+    fn terminator(&self) -> Str<'static> {
+        self.terminator.clone()
+    }
+    fn file_boundary(&self) -> Int {
+        self.boundary as Int
+    }
+    fn defer_boundary(&mut self) {
+        self.deferred = true;
+    }
+    fn file_hooks(&self) -> bool {
+        self.hooks
+    }
     fn set_used_fields(&mut self, used_fields: &FieldSet) {
-        for i in self.0.iter_mut() {
-            i.set_used_fields(used_fields);
+        for i in self.inputs.iter_mut() {
+            i.reader.set_used_fields(used_fields);
         }
     }
 }
@@ -297,6 +882,17 @@ pub(crate) enum ReaderState {
     OK = 1,
 }
 
+/// Where a read of the main input stopped; see `LineReader::file_boundary`.
+#[repr(i64)]
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub(crate) enum FileBoundary {
+    Record = 0,
+    // Before the first record of a file, where BEGINFILE runs.
+    Start = 1,
+    // After the last record of a file (or once it has been skipped), where ENDFILE runs.
+    End = 2,
+}
+
 /// frawk inputs read chunks of data into large contiguous buffers, and then advance progress
 /// within those buffers. The logic for reading, and conserving unused portions of previous buffers
 /// when reading a new one, is handled by the Reader type.
@@ -323,6 +919,76 @@ struct Reader<R> {
 
     // Validate input as UTF-8
     check_utf8: bool,
+
+    // Buffers that may be reused when reading more input: either ones replaced by previous calls
+    // to `reset` (if `recycle` is set) or ones handed back with `Reader::recycle`.
+    pool: BufferPool,
+    recycle: bool,
+
+    // State for enforcing --max-record-bytes; see the `limits` module.
+    limits: Limits,
+    // The name of the input, for error messages.
+    name: String,
+    // The byte separating records, if there is one. Without it, only records that span a refill
+    // of the buffer are checked, and callers must check the records they return.
+    record_sep: Option<u8>,
+    // The position in the input of buf[0].
+    base: u64,
+    // Bytes removed from buf when truncating records, as (index of the first byte following the
+    // removed bytes, number of bytes removed) pairs in increasing order of index.
+    drops: Vec<(usize, u64)>,
+    // An error from exceeding a limit, for callers that do not otherwise report read errors.
+    limit_error: Option<CompileError>,
+
+    // State for streaming records too long to buffer; see the `stream` module. `streamer` is only
+    // set when streaming is enabled and supported by this reader's input format.
+    streamer: Option<Streamer>,
+    // Are we partway through streaming a record, i.e. have we yet to read its end?
+    streaming: bool,
+    // If the record at the start of buf was streamed, the number of complete fields it kept.
+    streamed: Option<usize>,
+}
+
+/// The maximum number of buffers a BufferPool will hold on to.
+const MAX_POOLED_BUFS: usize = 4;
+
+/// A small pool of input buffers, recycled between refills of a Reader.
+///
+/// Readers allocate a fresh `chunk_size` buffer every time they read more input. Strings for
+/// records and fields point into these buffers, so a buffer can only be reused once every string
+/// referencing it has been dropped; in the common case of a script that does not retain `$0` or
+/// its fields across records, that is true by the time the next buffer is requested.
+///
+/// Readers that hand their buffers off to other threads (as the chunk producers used by the batch
+/// splitters do) cannot keep them in the pool, because buffer reference counts are not atomic.
+/// Instead, consumers hand buffers back in the next chunk they request once they are done with
+/// them, and the producer passes them to `Reader::recycle`. The offset vectors in those chunks
+/// are reused the same way.
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    bufs: Vec<Buf>,
+}
+
+impl BufferPool {
+    pub(crate) fn put(&mut self, buf: Buf) {
+        if buf.len() == 0 {
+            return;
+        }
+        if self.bufs.len() == MAX_POOLED_BUFS {
+            self.bufs.remove(0);
+        }
+        self.bufs.push(buf);
+    }
+
+    /// Get an unshared buffer of `size` bytes from the pool, if there is one. The contents of the
+    /// buffer are unspecified.
+    pub(crate) fn get(&mut self, size: usize) -> Option<UniqueBuf> {
+        let ix = self
+            .bufs
+            .iter()
+            .position(|b| b.len() == size && b.refcount() == 1)?;
+        self.bufs.swap_remove(ix).try_unique().ok()
+    }
 }
 
 fn read_to_slice(r: &mut impl Read, mut buf: &mut [u8]) -> Result<usize> {
@@ -361,9 +1027,138 @@ impl<R: Read> Reader<R> {
             state: ReaderState::OK,
             last_len: 0,
             check_utf8,
+            pool: Default::default(),
+            recycle: false,
+            limits: Limits::default(),
+            name: String::new(),
+            record_sep: None,
+            base: 0,
+            drops: Vec::new(),
+            limit_error: None,
+            streamer: None,
+            streaming: false,
+            streamed: None,
+        }
+    }
+
+    /// Stream records that do not fit in the buffer, if `stream` is set (with `--stream-records`).
+    pub(crate) fn stream_records(
+        mut self,
+        stream: bool,
+        field_sep: FieldSep,
+        record_sep: u8,
+    ) -> Self {
+        if stream {
+            self.streamer = Some(Streamer::new(field_sep, record_sep));
+        }
+        self
+    }
+
+    /// Enforce `limits` on the length of records, naming the input and the byte that separates
+    /// its records.
+    pub(crate) fn limit_records(
+        mut self,
+        limits: Limits,
+        name: &str,
+        record_sep: Option<u8>,
+    ) -> Self {
+        self.limits = limits;
+        self.name = name.into();
+        self.record_sep = record_sep;
+        self
+    }
+
+    /// The position in the input of buf[ix].
+    fn position(&self, ix: usize) -> u64 {
+        let dropped: u64 = self
+            .drops
+            .iter()
+            .take_while(|(at, _)| *at <= ix)
+            .map(|(_, n)| n)
+            .sum();
+        self.base + ix as u64 + dropped
+    }
+
+    /// Report a record starting at buf[ix] that is longer than the limit, recording the error if
+    /// records are not truncated.
+    fn record_too_long(&mut self, ix: usize) -> Result<()> {
+        let res = self
+            .limits
+            .record_too_long(self.name.as_str(), self.position(ix));
+        if let Err(e) = &res {
+            self.state = ReaderState::Error;
+            self.limit_error = Some(e.clone());
+        }
+        res
+    }
+
+    // The index at which to truncate a record starting at data[start] to at most `max` bytes,
+    // avoiding splitting a UTF-8 character when checking UTF-8.
+    fn truncation_point(&self, data: &[u8], start: usize, max: usize) -> usize {
+        let mut cut = start + max;
+        if self.check_utf8 {
+            while cut > start && data[cut] & 0xC0 == 0x80 {
+                cut -= 1;
+            }
+        }
+        cut
+    }
+
+    /// Enforce the limit on record length for the records in data[..len], filling the rest of
+    /// `data` from the input as records are truncated. Returns the new length of the data.
+    ///
+    /// Note that this splits records on every occurrence of the separator, including newlines in
+    /// quoted CSV fields; such records are only ever measured as shorter than they are.
+    fn enforce_record_limit(
+        &mut self,
+        data: &mut [u8],
+        mut len: usize,
+        sep: u8,
+        max: usize,
+    ) -> Result<usize> {
+        let mut rec_start = 0;
+        let mut i = 0;
+        loop {
+            let mut rec_end = memchr::memchr(sep, &data[i..len]).map_or(len, |ix| i + ix);
+            if rec_end - rec_start > max {
+                self.record_too_long(rec_start)?;
+                let cut = self.truncation_point(data, rec_start, max);
+                data.copy_within(rec_end..len, cut);
+                self.drops.push((cut, (rec_end - cut) as u64));
+                len -= rec_end - cut;
+                rec_end = cut;
+            }
+            if rec_end < len {
+                rec_start = rec_end + 1;
+                i = rec_start;
+                continue;
+            }
+            // We have truncated the last record in the buffer (or there was nothing left to
+            // scan): fill the space we freed up, if there is more input.
+            if len == data.len() {
+                return Ok(len);
+            }
+            let n = read_to_slice(&mut self.inner, &mut data[len..])?;
+            if n == 0 {
+                return Ok(len);
+            }
+            i = len;
+            len += n;
         }
     }
 
+    /// Reuse old buffers once they are no longer referenced. Callers must not retain unique
+    /// references to `buf` across calls to `reset`.
+    pub(crate) fn recycle_buffers(mut self) -> Self {
+        self.recycle = true;
+        self
+    }
+
+    /// Make `buf`, a buffer that this Reader returned earlier, available for future reads.
+    pub(crate) fn recycle(&mut self, buf: UniqueBuf) {
+        self.pool.put(buf.into_buf())
+    }
+
     pub(crate) fn check_utf8(&self) -> bool {
         self.check_utf8
     }
@@ -394,6 +1189,43 @@ impl<R: Read> Reader<R> {
         }
     }
 
+    /// Replace the rest of the record being streamed, starting at data[start..len], with
+    /// placeholders, reading more input until we find the end of the record or fill `data`.
+    /// Returns the new length of the data.
+    fn stream_record(
+        &mut self,
+        data: &mut [u8],
+        mut start: usize,
+        mut len: usize,
+    ) -> Result<usize> {
+        let streamer = self.streamer.as_mut().unwrap();
+        loop {
+            let (squashed, end) = streamer.squash(&mut data[start..len]);
+            let rest = end.map_or(len, |ix| start + ix);
+            let removed = rest - start - squashed;
+            if removed > 0 {
+                data.copy_within(rest..len, start + squashed);
+                self.drops.push((start + squashed, removed as u64));
+                len -= removed;
+            }
+            if end.is_some() {
+                self.streaming = false;
+                return Ok(len);
+            }
+            start = len;
+            if len == data.len() {
+                // The placeholders fill the buffer; keep going once it has grown.
+                return Ok(len);
+            }
+            let n = read_to_slice(&mut self.inner, &mut data[len..])?;
+            if n == 0 {
+                self.streaming = false;
+                return Ok(len);
+            }
+            len += n;
+        }
+    }
+
     fn clear_buf(&mut self) {
         self.start = 0;
         self.end = 0;
@@ -406,7 +1238,10 @@ impl<R: Read> Reader<R> {
             return Ok(true);
         }
         let (next_buf, next_len, input_len) = self.get_next_buf(self.start)?;
-        self.buf = next_buf.into_buf();
+        let old_buf = std::mem::replace(&mut self.buf, next_buf.into_buf());
+        if self.recycle {
+            self.pool.put(old_buf);
+        }
         self.end = next_len;
         self.input_end = input_len;
         self.start = 0;
@@ -418,21 +1253,81 @@ impl<R: Read> Reader<R> {
         consume: usize,
     ) -> Result<(UniqueBuf, /*end*/ usize, /*input_end*/ usize)> {
         let mut done = false;
-        let plen = self.input_end.saturating_sub(consume);
+        let mut plen = self.input_end.saturating_sub(consume);
+        let max_record = self.limits.max_record_bytes();
+        let mut dropped = 0;
+        self.base = self.position(consume);
+        self.drops.clear();
+        if consume > 0 {
+            self.streamed = None;
+        }
+        let mut streamed_prefix = None;
+        if let Some(streamer) = &mut self.streamer {
+            if !self.streaming && plen > self.chunk_size / 2 {
+                // This record does not fit in the buffer. Stream it, rather than growing the
+                // buffer to hold all of it.
+                let (prefix, kept) = streamer.start(&self.buf.as_bytes()[consume..consume + plen]);
+                self.streaming = true;
+                self.streamed = Some(kept);
+                dropped = plen - prefix.len();
+                plen = prefix.len();
+                streamed_prefix = Some(prefix);
+            }
+        }
+        if streamed_prefix.is_none() && plen > max_record {
+            // The bytes carried over are all part of one record. When we know the record separator
+            // this is usually caught by enforce_record_limit, but not for records containing
+            // quoted separators.
+            let cut = self.truncation_point(self.buf.as_bytes(), consume, max_record) - consume;
+            self.record_too_long(0)?;
+            dropped = plen - cut;
+            plen = cut;
+        }
         // Double the chunk size if it is too small to read a sufficient batch given the prefix
         // size.
         if plen > self.chunk_size / 2 {
             self.chunk_size = std::cmp::max(self.chunk_size * 2, 1024);
         }
-        // NB: UniqueBuf fills the allocation with zeros.
-        let mut data = UniqueBuf::new(self.chunk_size + self.padding);
+        // NB: UniqueBuf fills the allocation with zeros. Pooled buffers do not, so we zero out
+        // whatever we do not read into below. The same goes for bytes left over from truncating
+        // records.
+        let size = self.chunk_size + self.padding;
+        let (mut data, reused) = match self.pool.get(size) {
+            Some(buf) => (buf, true),
+            None => (UniqueBuf::new(size), false),
+        };
 
         // First, append the remaining bytes.
-        unsafe {
-            std::ptr::copy_nonoverlapping(self.buf.as_ptr().add(consume), data.as_mut_ptr(), plen);
+        match &streamed_prefix {
+            Some(prefix) => data.as_mut_bytes()[..plen].copy_from_slice(prefix),
+            None => unsafe {
+                std::ptr::copy_nonoverlapping(
+                    self.buf.as_ptr().add(consume),
+                    data.as_mut_ptr(),
+                    plen,
+                );
+            },
+        }
+        if dropped > 0 {
+            self.drops.push((plen, dropped as u64));
+        }
+        let mut bytes_read = plen
+            + read_to_slice(
+                &mut self.inner,
+                &mut data.as_mut_bytes()[plen..self.chunk_size],
+            )?;
+        if self.streaming {
+            let chunk = &mut data.as_mut_bytes()[..self.chunk_size];
+            bytes_read = self.stream_record(chunk, plen, bytes_read)?;
+        }
+        if let (Some(sep), true) = (self.record_sep, max_record != usize::MAX) {
+            let chunk = &mut data.as_mut_bytes()[..self.chunk_size];
+            bytes_read = self.enforce_record_limit(chunk, bytes_read, sep, max_record)?;
+        }
+        if reused || !self.drops.is_empty() {
+            data.as_mut_bytes()[bytes_read..].fill(0);
         }
         let mut bytes = &mut data.as_mut_bytes()[..self.chunk_size];
-        let bytes_read = plen + read_to_slice(&mut self.inner, &mut bytes[plen..])?;
         if bytes_read != self.chunk_size {
             done = true;
             bytes = &mut bytes[..bytes_read];
@@ -3,16 +3,22 @@ use std::io::Read;
 
 use crate::common::Result;
 use crate::pushdown::FieldSet;
-use crate::runtime::Str;
+use crate::runtime::{Int, Str};
 use regex::bytes::Regex;
 
-use super::{DefaultLine, LineReader, Reader, ReaderState};
+use super::{limits::Limits, DefaultLine, LineReader, Reader, ReaderState};
+
+// With an empty RS, records are separated by one or more blank lines, and newlines around them are
+// not part of any record.
+const PARAGRAPH_SEP: &str = "\n\n+";
 
 // TODO: this can probably just be "Splitter"
 pub struct RegexSplitter<R> {
     reader: Reader<R>,
     name: Str<'static>,
     used_fields: FieldSet,
+    // The separator that ended the last record read, if any.
+    terminator: Str<'static>,
     // Used to trigger updating FILENAME on the first read.
     start: bool,
 }
@@ -42,21 +48,23 @@ impl<R: Read> LineReader for RegexSplitter<R> {
         self.start = false;
         old.diverged = false;
         old.fields.clear();
-        rc.with_regex(pat, |re| {
-            old.line = self.read_line_regex(re);
-        })?;
+        old.line = self.read_record(pat, rc)?;
+        old.paragraph = pat.is_empty();
         Ok(/* file changed */ start)
     }
 
     fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
         let start = self.start;
         self.start = false;
-        let line = rc.with_regex(pat, |re| DefaultLine {
-            line: self.read_line_regex(re),
+        let line = DefaultLine {
+            line: self.read_record(pat, rc)?,
             fields: Default::default(),
             used_fields: self.used_fields.clone(),
             diverged: false,
-        })?;
+            streamed: None,
+            paragraph: pat.is_empty(),
+            limits: self.reader.limits.clone(),
+        };
         Ok((/* file changed */ start, line))
     }
     fn read_state(&self) -> i64 {
@@ -67,29 +75,103 @@ impl<R: Read> LineReader for RegexSplitter<R> {
         self.reader.force_eof();
         Ok(false)
     }
+    fn file_index(&self) -> Int {
+        1
+    }
+    fn terminator(&self) -> Str<'static> {
+        self.terminator.clone()
+    }
     fn set_used_fields(&mut self, used_fields: &FieldSet) {
         self.used_fields = used_fields.clone();
     }
 }
 
 impl<R: Read> RegexSplitter<R> {
-    pub fn new(r: R, chunk_size: usize, name: impl Into<Str<'static>>, check_utf8: bool) -> Self {
+    pub fn new(
+        r: R,
+        chunk_size: usize,
+        name: impl Into<Str<'static>>,
+        check_utf8: bool,
+        limits: Limits,
+    ) -> Self {
+        let name = name.into();
         RegexSplitter {
-            reader: Reader::new(r, chunk_size, /*padding=*/ 0, check_utf8),
-            name: name.into(),
+            reader: Reader::new(r, chunk_size, /*padding=*/ 0, check_utf8)
+                .recycle_buffers()
+                .limit_records(limits, name.to_string().as_str(), None),
+            name,
             used_fields: FieldSet::all(),
+            terminator: Str::default(),
             start: true,
         }
     }
 
+    // Read the next record, separated by matches of `pat`, or by blank lines if it is empty.
+    fn read_record(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<Str<'static>> {
+        let line = if pat.is_empty() {
+            rc.with_regex(&Str::from(PARAGRAPH_SEP), |re| self.read_paragraph(re))?
+        } else {
+            rc.with_regex(pat, |re| self.read_line_regex(re))?
+        };
+        if let Some(e) = self.reader.limit_error.take() {
+            return Err(e);
+        }
+        Ok(line)
+    }
+
+    // Read a record in paragraph mode, skipping any newlines before and after it.
+    fn read_paragraph(&mut self, sep: &Regex) -> Str<'static> {
+        let mut consumed = 0;
+        loop {
+            let (res, n) = self.read_line_inner(sep);
+            consumed += n;
+            let (start, end, len) = res.with_bytes(|bs| {
+                let start = bs.iter().take_while(|b| **b == b'\n').count();
+                let trailing = bs[start..].iter().rev().take_while(|b| **b == b'\n');
+                (start, bs.len() - trailing.count(), bs.len())
+            });
+            if start < end {
+                if end < len {
+                    // Newlines at the end of the input still terminate the record.
+                    self.terminator = res.slice(end, len);
+                }
+                self.reader.last_len = consumed;
+                return self.limit_record(res.slice(start, end), consumed);
+            }
+            if n == 0 || self.reader.is_eof() {
+                // Only newlines remained in the input, so there is no record.
+                self.reader.last_len = 0;
+                return Str::default();
+            }
+        }
+    }
+
     pub fn read_line_regex(&mut self, pat: &Regex) -> Str<'static> {
         // We keep this as a separate method because it helps in writing tests.
         let (res, consumed) = self.read_line_inner(pat);
         self.reader.last_len = consumed;
-        res
+        self.limit_record(res, consumed)
+    }
+
+    // The reader only catches records longer than the limit if they span a refill of its buffer,
+    // as it does not know where our records end; check the rest here.
+    fn limit_record(&mut self, res: Str<'static>, consumed: usize) -> Str<'static> {
+        let max = self.reader.limits.max_record_bytes();
+        if res.len() <= max {
+            return res;
+        }
+        // This position is not exact if the reader has already removed bytes from the record, but
+        // in that case it has reported the record itself.
+        let start = self.reader.start.saturating_sub(consumed);
+        if self.reader.record_too_long(start).is_err() {
+            return Str::default();
+        }
+        let cut = res.with_bytes(|bs| self.reader.truncation_point(bs, 0, max));
+        res.slice(0, cut)
     }
 
     fn read_line_inner(&mut self, pat: &Regex) -> (Str<'static>, usize) {
+        self.terminator = Str::default();
         if self.reader.is_eof() {
             return (Str::default(), 0);
         }
@@ -106,6 +188,10 @@ impl<R: Read> RegexSplitter<R> {
                         .reader
                         .buf
                         .slice_to_str(self.reader.start, self.reader.start + start);
+                    self.terminator = self
+                        .reader
+                        .buf
+                        .slice_to_str(self.reader.start + start, self.reader.start + end);
                     self.reader.start += end;
                     return (res, end);
                 }
@@ -155,6 +241,10 @@ impl<R: Read> RegexSplitter<R> {
                                 .reader
                                 .buf
                                 .slice_to_str(self.reader.start, self.reader.start + start);
+                            self.terminator = self
+                                .reader
+                                .buf
+                                .slice_to_str(self.reader.start + start, self.reader.start + end);
                             self.reader.start += end;
                             (res, end)
                         }
@@ -204,8 +294,13 @@ mod tests {
         }
         let bs = String::from_utf8(buf).unwrap();
         let c = Cursor::new(bs.clone());
-        let mut rdr =
-            RegexSplitter::new(c, /*chunk_size=*/ 512, "", /*check_utf8=*/ false);
+        let mut rdr = RegexSplitter::new(
+            c,
+            /*chunk_size=*/ 512,
+            "",
+            /*check_utf8=*/ false,
+            Limits::default(),
+        );
         let mut lines = Vec::new();
         while !rdr.reader.is_eof() {
             let line = rdr.read_line_regex(&*BS).upcast();
@@ -226,12 +321,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_paragraph_split() {
+        let sep = Regex::new(PARAGRAPH_SEP).unwrap();
+        let mut bs = String::from("\n\n");
+        let mut expected = Vec::new();
+        for i in 0..200 {
+            let para = format!("paragraph {}\nline two\nline three", i);
+            let term = "\n".repeat(2 + i % 3);
+            bs.push_str(&para);
+            bs.push_str(&term);
+            expected.push((para, term));
+        }
+        let mut rdr = RegexSplitter::new(
+            Cursor::new(bs),
+            512,
+            "",
+            /*check_utf8=*/ false,
+            Limits::default(),
+        );
+        let mut paras = Vec::new();
+        loop {
+            let para = rdr.read_paragraph(&sep);
+            if rdr.read_state() == ReaderState::Eof as i64 {
+                break;
+            }
+            paras.push((para.to_string(), rdr.terminator().to_string()));
+        }
+        assert_eq!(paras, expected);
+
+        let mut rdr = RegexSplitter::new(Cursor::new("\n\n\n"), 512, "", false, Limits::default());
+        assert_eq!(rdr.read_paragraph(&sep), Str::default());
+        assert_eq!(rdr.read_state(), ReaderState::Eof as i64);
+    }
+
     #[test]
     fn test_line_split() {
         let chunk_size = 1 << 9;
         let bs: String = crate::test_string_constants::PRIDE_PREJUDICE_CH2.into();
         let c = Cursor::new(bs.clone());
-        let mut rdr = RegexSplitter::new(c, chunk_size, "", /*check_utf8=*/ true);
+        let mut rdr = RegexSplitter::new(
+            c,
+            chunk_size,
+            "",
+            /*check_utf8=*/ true,
+            Limits::default(),
+        );
         let mut lines = Vec::new();
         while !rdr.reader.is_eof() {
             let line = rdr.read_line_regex(&*LINE).upcast();
@@ -251,6 +386,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_line_split_recycled_buffers() {
+        // Unlike the tests above, drop each line after we read it so that buffers get reused.
+        let chunk_size = 1 << 9;
+        let bs: String = crate::test_string_constants::PRIDE_PREJUDICE_CH2.into();
+        let c = Cursor::new(bs.clone());
+        let mut rdr = RegexSplitter::new(
+            c,
+            chunk_size,
+            "",
+            /*check_utf8=*/ true,
+            Limits::default(),
+        );
+        let mut expected = LINE.split(bs.as_bytes()).map(ref_str);
+        let mut i = 0;
+        while !rdr.reader.is_eof() {
+            let line = rdr.read_line_regex(&LINE).upcast();
+            assert!(rdr.read_state() != -1);
+            assert_eq!(Some(line), expected.next(), "mismatch at index {}", i);
+            i += 1;
+        }
+        assert_eq!(expected.next(), None);
+    }
+
     #[test]
     fn test_clipped_chunk_split_pp() {
         // _random is more thorough, but this works as a sort of smoke test.
@@ -268,7 +427,13 @@ mod tests {
 
         let s = String::from_utf8(bs).unwrap();
         let c = Cursor::new(s.clone());
-        let mut rdr = RegexSplitter::new(c, chunk_size, "", /*check_utf8=*/ true);
+        let mut rdr = RegexSplitter::new(
+            c,
+            chunk_size,
+            "",
+            /*check_utf8=*/ true,
+            Limits::default(),
+        );
         let mut lines = Vec::new();
         while !rdr.reader.is_eof() {
             let line = rdr.read_line_regex(&*LINE).upcast();
@@ -304,7 +469,13 @@ mod tests {
 
             let s = String::from_utf8(bs).unwrap();
             let c = Cursor::new(s.clone());
-            let mut rdr = RegexSplitter::new(c, chunk_size, "", /*check_utf8=*/ true);
+            let mut rdr = RegexSplitter::new(
+                c,
+                chunk_size,
+                "",
+                /*check_utf8=*/ true,
+                Limits::default(),
+            );
             let mut lines = Vec::new();
             while !rdr.reader.is_eof() {
                 let line = rdr.read_line_regex(&*LINE).upcast();
@@ -0,0 +1,192 @@
+//! Reading records from clients connecting over a socket, for `--serve`.
+//!
+//! Clients are served one at a time, in the order they connect. Each client's connection is read
+//! as an input file of its own, named after the client's address, with records separated by the
+//! first byte of RS (a newline by default). Output to standard output while processing a client's
+//! records is sent back to that client (see `writers::set_client`), and is flushed whenever the
+//! server waits for more input, so a client can send one record at a time and read back the
+//! results for each. The connection is closed once the client shuts down its side of it and the
+//! last of its output has been sent. The program keeps running, with its variables intact, between
+//! clients: the server only stops when it is killed, so END does not run.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::{writers, Int, Str};
+
+use super::{DefaultLine, LineReader, ReaderState};
+
+enum Listener {
+    Tcp(TcpListener),
+    // Unix domain sockets are named after the path they listen on.
+    #[cfg(unix)]
+    Unix(UnixListener, String),
+}
+
+type Connection = (Box<dyn Read + Send>, Box<dyn Write>, String);
+
+impl Listener {
+    fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Listener::Tcp(l) => {
+                let (stream, addr) = l.accept()?;
+                // Records are usually small, and are answered one at a time.
+                stream.set_nodelay(true)?;
+                Ok((
+                    Box::new(stream.try_clone()?),
+                    Box::new(stream),
+                    addr.to_string(),
+                ))
+            }
+            #[cfg(unix)]
+            Listener::Unix(l, path) => {
+                let (stream, _) = l.accept()?;
+                Ok((
+                    Box::new(stream.try_clone()?),
+                    Box::new(stream),
+                    path.clone(),
+                ))
+            }
+        }
+    }
+}
+
+pub struct SocketReader {
+    listener: Listener,
+    // The client currently being served.
+    conn: Option<BufReader<Box<dyn Read + Send>>>,
+    name: Str<'static>,
+    // The number of clients accepted so far.
+    clients: Int,
+    buf: Vec<u8>,
+    used_fields: FieldSet,
+}
+
+impl SocketReader {
+    /// Listen on `addr`: either `unix:PATH` for a Unix domain socket, or `HOST:PORT` for TCP.
+    pub fn bind(addr: &str) -> io::Result<SocketReader> {
+        let listener = match addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => Listener::Unix(UnixListener::bind(path)?, String::from(path)),
+            #[cfg(not(unix))]
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "Unix domain sockets are not supported on this platform",
+                ))
+            }
+            None => Listener::Tcp(TcpListener::bind(addr)?),
+        };
+        Ok(SocketReader {
+            listener,
+            conn: None,
+            name: Str::default(),
+            clients: 0,
+            buf: Vec::new(),
+            used_fields: FieldSet::all(),
+        })
+    }
+
+    // Wait for the next client, returning an error only if the listener itself fails.
+    fn accept(&mut self) -> Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((r, w, name)) => {
+                    writers::set_client(Some(w));
+                    self.conn = Some(BufReader::new(r));
+                    self.name = name.into();
+                    self.clients += 1;
+                    return Ok(());
+                }
+                // The client may have given up before we got to it.
+                Err(e) if e.kind() == io::ErrorKind::ConnectionAborted => continue,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return err!("failed to accept a connection: {}", e),
+            }
+        }
+    }
+
+    // Finish with the current client: send it the rest of its output and close the connection.
+    fn disconnect(&mut self) {
+        writers::set_client(None);
+        self.conn = None;
+    }
+
+    // Read the next record into `buf`, returning whether a new client was accepted to do so.
+    fn read_record(&mut self, sep: u8) -> Result<bool> {
+        let mut changed = false;
+        loop {
+            if self.conn.is_none() {
+                self.accept()?;
+                changed = true;
+            }
+            let conn = self.conn.as_mut().unwrap();
+            if memchr::memchr(sep, conn.buffer()).is_none() {
+                // We may have to wait for the client to send more; first send back the output
+                // for the records it has sent so far.
+                writers::flush_client();
+            }
+            self.buf.clear();
+            match conn.read_until(sep, &mut self.buf) {
+                Ok(0) => self.disconnect(),
+                Ok(_) => {
+                    if self.buf.last() == Some(&sep) {
+                        self.buf.pop();
+                    }
+                    return Ok(changed);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // A client that fails (e.g. by resetting the connection) is dropped; the server
+                // carries on with the next one.
+                Err(_) => self.disconnect(),
+            }
+        }
+    }
+}
+
+impl LineReader for SocketReader {
+    type Line = DefaultLine;
+    fn filename(&self) -> Str<'static> {
+        self.name.clone()
+    }
+    fn check_utf8(&self) -> bool {
+        false
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
+        let mut line = DefaultLine::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        pat: &Str,
+        _rc: &mut super::RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        let sep = pat.with_bytes(|bs| bs.first().cloned().unwrap_or(b'\n'));
+        let changed = self.read_record(sep)?;
+        if changed {
+            old.used_fields = self.used_fields.clone();
+        }
+        old.set(Str::from(&self.buf[..]).unmoor());
+        Ok(changed)
+    }
+    fn read_state(&self) -> i64 {
+        // There is always another client to wait for.
+        ReaderState::OK as i64
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        // Move on to the next client.
+        self.disconnect();
+        Ok(true)
+    }
+    fn file_index(&self) -> Int {
+        self.clients
+    }
+    fn set_used_fields(&mut self, used_fields: &FieldSet) {
+        self.used_fields = used_fields.clone();
+    }
+}
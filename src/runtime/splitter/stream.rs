@@ -0,0 +1,169 @@
+//! Streaming records that do not fit in the input buffer, enabled with `--stream-records`.
+//!
+//! Normally a `Reader` grows its buffer until it holds an entire record. When streaming is enabled
+//! and a record outgrows half of the buffer, the reader instead keeps the complete fields that it
+//! has read so far, and replaces the rest of the record with a placeholder for each remaining
+//! field as it reads them. The placeholders take up a byte or two per field, no matter how long the
+//! fields are, so `NF` is still correct and the record's memory use no longer depends on the
+//! length of its fields. Reading `$0` or any of the replaced fields of such a record is an error.
+//!
+//! This is only supported for inputs split by a single byte or by whitespace, where the reader
+//! can find field boundaries without the help of a regex.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum FieldSep {
+    Byte(u8),
+    // Runs of ASCII whitespace, as split by the whitespace reader in the `batch` module.
+    Whitespace,
+}
+
+fn is_space(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+/// The state needed to replace the fields of a streamed record with placeholders.
+pub(crate) struct Streamer {
+    field_sep: FieldSep,
+    record_sep: u8,
+    // The byte standing in for a field's contents.
+    placeholder: u8,
+    // Whether the last byte we replaced was part of a field (rather than whitespace); this is only
+    // used when splitting by whitespace.
+    in_field: bool,
+}
+
+impl Streamer {
+    pub(crate) fn new(field_sep: FieldSep, record_sep: u8) -> Streamer {
+        let placeholder = [b'.', b'_', b'x']
+            .iter()
+            .cloned()
+            .find(|b| field_sep != FieldSep::Byte(*b) && *b != record_sep)
+            .unwrap();
+        Streamer {
+            field_sep,
+            record_sep,
+            placeholder,
+            in_field: false,
+        }
+    }
+
+    /// Begin streaming the record in `prefix`, which does not contain the end of the record.
+    /// Returns the bytes to keep in its place, along with the number of (complete) fields they
+    /// contain.
+    pub(crate) fn start(&mut self, prefix: &[u8]) -> (Vec<u8>, usize) {
+        let (cut, kept) = match self.field_sep {
+            FieldSep::Byte(sep) => match memchr::memrchr(sep, prefix) {
+                Some(ix) => (ix, memchr::memchr_iter(sep, &prefix[..ix]).count() + 1),
+                None => (0, 0),
+            },
+            FieldSep::Whitespace => {
+                let cut = prefix
+                    .iter()
+                    .rposition(|b| is_space(*b))
+                    .map_or(0, |ix| ix + 1);
+                let mut kept = 0;
+                let mut in_field = false;
+                for b in &prefix[..cut] {
+                    if !is_space(*b) && !in_field {
+                        kept += 1;
+                    }
+                    in_field = !is_space(*b);
+                }
+                (cut, kept)
+            }
+        };
+        let mut res = prefix[..cut].to_vec();
+        let mut rest = prefix[cut..].to_vec();
+        match self.field_sep {
+            // The first field is incomplete; keep a placeholder for it.
+            FieldSep::Byte(_) if kept == 0 => res.push(self.placeholder),
+            FieldSep::Byte(_) => {}
+            // `cut` is either 0 or follows whitespace, so any bytes after it begin a new field.
+            FieldSep::Whitespace => self.in_field = false,
+        }
+        let (len, _) = self.squash(&mut rest);
+        res.extend_from_slice(&rest[..len]);
+        (res, kept)
+    }
+
+    /// Replace the fields at the start of `data` with placeholders, in place, up to the end of
+    /// the record. Returns the length of the replacement and, if the record ends in `data`, the
+    /// index of its record separator.
+    pub(crate) fn squash(&mut self, data: &mut [u8]) -> (usize, Option<usize>) {
+        let end = memchr::memchr(self.record_sep, data);
+        let len = end.unwrap_or(data.len());
+        // Every byte we write replaces at least one byte that we have already read.
+        let mut w = 0;
+        for r in 0..len {
+            let b = data[r];
+            let out = match self.field_sep {
+                FieldSep::Byte(sep) if b == sep => Some(sep),
+                FieldSep::Byte(_) => None,
+                FieldSep::Whitespace => {
+                    let in_field = !is_space(b);
+                    let out = if in_field == self.in_field {
+                        None
+                    } else if in_field {
+                        Some(self.placeholder)
+                    } else {
+                        Some(b' ')
+                    };
+                    self.in_field = in_field;
+                    out
+                }
+            };
+            if let Some(out) = out {
+                data[w] = out;
+                w += 1;
+            }
+        }
+        (w, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(field_sep: FieldSep, parts: &[&str]) -> (String, usize) {
+        let mut streamer = Streamer::new(field_sep, b'\n');
+        let (mut res, kept) = streamer.start(parts[0].as_bytes());
+        for part in &parts[1..] {
+            let mut part = part.as_bytes().to_vec();
+            let (len, end) = streamer.squash(&mut part);
+            res.extend_from_slice(&part[..len]);
+            if let Some(end) = end {
+                res.extend_from_slice(&part[end..]);
+            }
+        }
+        (String::from_utf8(res).unwrap(), kept)
+    }
+
+    #[test]
+    fn byte_separated() {
+        assert_eq!(
+            stream(FieldSep::Byte(b','), &["a,bc,def", "gh,,i", "j,k\nl,m"]),
+            ("a,bc,,,,\nl,m".into(), 2)
+        );
+        assert_eq!(
+            stream(FieldSep::Byte(b','), &["abc", "def\n"]),
+            (".\n".into(), 0)
+        );
+        assert_eq!(
+            stream(FieldSep::Byte(b'.'), &["a.b", "c\n"]),
+            ("a.\n".into(), 1)
+        );
+    }
+
+    #[test]
+    fn whitespace_separated() {
+        assert_eq!(
+            stream(FieldSep::Whitespace, &["  a bc de", "f  g", "\th \nx y"]),
+            ("  a bc . . . \nx y".into(), 2)
+        );
+        assert_eq!(
+            stream(FieldSep::Whitespace, &["abc", "def ", "g"]),
+            (". .".into(), 0)
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Reading the elements of XML and HTML documents as input records.
+//!
+//! With `--xml-record`, each element matching the given selector becomes a record, with `$0`
+//! holding the element's markup. Fields are split from `$0` according to `FS` as usual, though
+//! most scripts will extract values with `getxml` instead. Each input is read into memory in its
+//! entirety before it is split; see `runtime::xml` for how documents are parsed.
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::xml::Selector;
+use crate::runtime::{Int, Str};
+
+use super::{DefaultLine, LineReader, ReaderState};
+
+pub struct XmlReader {
+    name: Str<'static>,
+    selector: Selector,
+    // The document and the offsets of its records, once it has been read.
+    doc: Option<Vec<u8>>,
+    records: std::vec::IntoIter<(usize, usize)>,
+    used_fields: FieldSet,
+    state: ReaderState,
+    check_utf8: bool,
+    // Used to trigger updating FILENAME on the first read.
+    start: bool,
+}
+
+impl XmlReader {
+    /// Read the document at `path`, or standard input if `path` is "-".
+    pub fn new(path: &str, selector: Selector, check_utf8: bool) -> XmlReader {
+        XmlReader {
+            name: Str::from(path).unmoor(),
+            selector,
+            doc: None,
+            records: Vec::new().into_iter(),
+            used_fields: FieldSet::all(),
+            state: ReaderState::OK,
+            check_utf8,
+            start: true,
+        }
+    }
+
+    fn load(&mut self) -> Result<()> {
+        if !self.start {
+            return Ok(());
+        }
+        let mut doc = Vec::new();
+        let path = self.name.to_string();
+        let res = if path == "-" {
+            io::stdin().read_to_end(&mut doc)
+        } else {
+            File::open(path.as_str()).and_then(|mut f| f.read_to_end(&mut doc))
+        };
+        if let Err(e) = res {
+            return err!("failed to read {}: {}", path, e);
+        }
+        if self.check_utf8 && std::str::from_utf8(&doc[..]).is_err() {
+            return err!("{} contains invalid UTF-8", path);
+        }
+        self.records = self.selector.records(&doc[..]).into_iter();
+        self.doc = Some(doc);
+        Ok(())
+    }
+}
+
+impl LineReader for XmlReader {
+    type Line = DefaultLine;
+    fn filename(&self) -> Str<'static> {
+        self.name.clone()
+    }
+    fn check_utf8(&self) -> bool {
+        self.check_utf8
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut super::RegexCache) -> Result<(bool, Self::Line)> {
+        let mut line = DefaultLine::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        _pat: &Str,
+        _rc: &mut super::RegexCache,
+        old: &'a mut Self::Line,
+    ) -> Result<bool> {
+        self.load()?;
+        let start = mem::replace(&mut self.start, false);
+        if start {
+            old.used_fields = self.used_fields.clone();
+        }
+        old.diverged = false;
+        old.fields.clear();
+        match (self.records.next(), &self.doc) {
+            (Some((lo, hi)), Some(doc)) => {
+                old.line = Str::from(&doc[lo..hi]).unmoor();
+                self.state = ReaderState::OK;
+            }
+            _ => {
+                old.line = Str::default();
+                self.doc = None;
+                self.state = ReaderState::Eof;
+            }
+        }
+        Ok(/* file changed */ start)
+    }
+    fn read_state(&self) -> i64 {
+        self.state as i64
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        // There is just one file. Set EOF.
+        self.start = false;
+        self.doc = None;
+        self.records = Vec::new().into_iter();
+        self.state = ReaderState::Eof;
+        Ok(false)
+    }
+    fn file_index(&self) -> Int {
+        1
+    }
+    fn set_used_fields(&mut self, used_fields: &FieldSet) {
+        self.used_fields = used_fields.clone();
+    }
+}
@@ -6,8 +6,13 @@
 /// space, and it also makes for more ergonomic interop with LLVM.
 ///
 /// TODO explain more about what is going on here.
+use crate::common;
 use crate::pushdown::FieldSet;
-use crate::runtime::{strtoi, Float, Int};
+use crate::runtime::{
+    strtoi,
+    warnings::{Warning, Warnings},
+    Float, Int,
+};
 
 use regex::bytes::{Captures, Regex};
 use smallvec::SmallVec;
@@ -257,6 +262,7 @@ impl<'a> StrRep<'a> {
     // function in llvm/builtin_functions.rs for more context.
     //
     // drop_with_tag must not be called with an Inline or Literal tag.
+    #[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
     unsafe fn drop_with_tag(&mut self, tag: u64) {
         // Debug-asserts are here to ensure that we catch any perturbing of the tag values getting
         // out of sync with this function.
@@ -317,6 +323,12 @@ impl<'a> UniqueStr<'a> {
         self.0.is_empty()
     }
 
+    /// Copy a literal string out of the memory it points into (see `Str::unmoor`), so that it no
+    /// longer borrows from it.
+    pub fn unmoor(self) -> UniqueStr<'static> {
+        UniqueStr(self.0.unmoor())
+    }
+
     // TODO: is this safe for INLINE values?
     // Seems like we aren't guaranteed that inlines are valid for all of <'a>
     // We probably just want to use Strs
@@ -403,6 +415,7 @@ impl<'a> Str<'a> {
         &mut *self.0.get()
     }
 
+    #[cfg(any(feature = "cranelift_backend", feature = "llvm_backend"))]
     pub(crate) unsafe fn drop_with_tag(&self, tag: u64) {
         self.rep_mut().drop_with_tag(tag)
     }
@@ -415,6 +428,16 @@ impl<'a> Str<'a> {
         }
     }
 
+    // An estimate of the heap memory referenced by this string, used by --mem-profile. Buffers
+    // shared between several strings are counted once per string.
+    pub(crate) fn heap_size(&self) -> usize {
+        if self.drop_is_trivial() {
+            0
+        } else {
+            self.len()
+        }
+    }
+
     // leaks `self` unless you transmute it back. This is used in LLVM codegen
     pub fn into_bits(self) -> u128 {
         unsafe { mem::transmute::<Str<'a>, u128>(self) }
@@ -427,6 +450,18 @@ impl<'a> Str<'a> {
         // splitting. As a result, we pass down the field, and whether or not it was empty (emptiness
         // checks for the string itself are insufficient if used_fields projects some fields away),
         // the pattern returns the number of fields added to the output.
+        push: impl FnMut(Str<'a>, bool /*is_empty*/) -> usize,
+        used_fields: &FieldSet,
+    ) {
+        self.split_n(pat, usize::max_value(), push, used_fields)
+    }
+
+    /// Like `split`, but stops splitting once `limit` fields have been added to the output. The
+    /// last field holds the remainder of the string.
+    pub fn split_n(
+        &self,
+        pat: &Regex,
+        limit: usize,
         mut push: impl FnMut(Str<'a>, bool /*is_empty*/) -> usize,
         used_fields: &FieldSet,
     ) {
@@ -437,6 +472,9 @@ impl<'a> Str<'a> {
             let mut prev = 0;
             let mut cur_field = 1;
             for m in pat.find_iter(s) {
+                if cur_field >= limit {
+                    break;
+                }
                 let is_empty = prev == m.start();
                 cur_field += if used_fields.get(cur_field) {
                     push(self.slice(prev, m.start()), is_empty)
@@ -526,58 +564,65 @@ impl<'a> Str<'a> {
         })
     }
 
-    pub fn subst_first(&self, pat: &Regex, subst: &Str<'a>) -> (Str<'a>, bool) {
+    pub(crate) fn subst_first(&self, pat: &Regex, subst: &SubstTemplate) -> (Str<'a>, bool) {
         self.with_bytes(|s| {
-            subst.with_bytes(|subst| {
-                if let Some(m) = pat.find(s) {
-                    let mut buf = DynamicBuf::new(s.len());
-                    buf.write_all(&s[0..m.start()]).unwrap();
-                    process_match(&s[m.start()..m.end()], subst, &mut buf).unwrap();
-                    buf.write_all(&s[m.end()..s.len()]).unwrap();
-                    (buf.into_str(), true)
-                } else {
-                    (self.clone(), false)
-                }
-            })
+            if let Some(m) = pat.find(s) {
+                let mut buf = DynamicBuf::new(s.len());
+                buf.write_all(&s[0..m.start()]).unwrap();
+                subst.expand(&s[m.start()..m.end()], &mut buf).unwrap();
+                buf.write_all(&s[m.end()..s.len()]).unwrap();
+                (buf.into_str(), true)
+            } else {
+                (self.clone(), false)
+            }
         })
     }
 
-    pub fn subst_all(&self, pat: &Regex, subst: &Str<'a>) -> (Str<'a>, Int) {
+    pub(crate) fn subst_all(&self, pat: &Regex, subst: &SubstTemplate) -> (Str<'a>, Int) {
         self.with_bytes(|s| {
-            subst.with_bytes(|subst| {
-                let mut buf = DynamicBuf::new(0);
-                let mut prev = 0;
-                let mut count = 0;
-                for m in pat.find_iter(s) {
-                    buf.write_all(&s[prev..m.start()]).unwrap();
-                    process_match(&s[m.start()..m.end()], subst, &mut buf).unwrap();
-                    prev = m.end();
-                    count += 1;
-                }
-                if count == 0 {
-                    (self.clone(), count)
-                } else {
-                    buf.write_all(&s[prev..s.len()]).unwrap();
-                    (buf.into_str(), count)
-                }
-            })
+            let mut buf = DynamicBuf::new(0);
+            let mut prev = 0;
+            let mut count = 0;
+            for m in pat.find_iter(s) {
+                buf.write_all(&s[prev..m.start()]).unwrap();
+                subst.expand(&s[m.start()..m.end()], &mut buf).unwrap();
+                prev = m.end();
+                count += 1;
+            }
+            if count == 0 {
+                (self.clone(), count)
+            } else {
+                buf.write_all(&s[prev..s.len()]).unwrap();
+                (buf.into_str(), count)
+            }
         })
     }
 
-    pub fn gen_subst_dynamic(&self, pat: &Regex, subst: &Str<'a>, how: &Str<'a>) -> Str<'a> {
+    pub fn gen_subst_dynamic(
+        &self,
+        pat: &Regex,
+        subst: &Str<'a>,
+        how: &Str<'a>,
+        warnings: &Warnings,
+    ) -> common::Result<Str<'a>> {
         how.with_bytes(|how| {
             if !how.is_empty() && matches!(how[0], b'g' | b'G') {
-                self.gen_subst_all(pat, subst)
+                self.gen_subst_all(pat, subst, warnings)
             } else {
                 // this silently ignores strings that cannot be parsed and treats them as "1"
                 let which = strtoi(how);
                 let which = std::cmp::max(1, which);
-                self.gen_subst_n(pat, subst, which)
+                self.gen_subst_n(pat, subst, which, warnings)
             }
         })
     }
 
-    pub fn gen_subst_all(&self, pat: &Regex, subst: &Str<'a>) -> Str<'a> {
+    pub fn gen_subst_all(
+        &self,
+        pat: &Regex,
+        subst: &Str<'a>,
+        warnings: &Warnings,
+    ) -> common::Result<Str<'a>> {
         self.with_bytes(|s| {
             subst.with_bytes(|subst| {
                 let mut buf = DynamicBuf::new(0);
@@ -586,15 +631,15 @@ impl<'a> Str<'a> {
                 for c in pat.captures_iter(s) {
                     let m = c.get(0).unwrap();
                     buf.write_all(&s[prev..m.start()]).unwrap();
-                    process_match_gen(c, subst, &mut buf).unwrap();
+                    process_match_gen(c, subst, &mut buf, warnings)?;
                     prev = m.end();
                     count += 1;
                 }
                 if count == 0 {
-                    self.clone()
+                    Ok(self.clone())
                 } else {
                     buf.write_all(&s[prev..s.len()]).unwrap();
-                    buf.into_str()
+                    Ok(buf.into_str())
                 }
             })
         })
@@ -602,7 +647,13 @@ impl<'a> Str<'a> {
 
     /// Handle the general substitution for a case of integer value in "how"
     /// Will replace match number `which` (indexed from 1)
-    pub fn gen_subst_n(&self, pat: &Regex, subst: &Str<'a>, which: Int) -> Str<'a> {
+    pub fn gen_subst_n(
+        &self,
+        pat: &Regex,
+        subst: &Str<'a>,
+        which: Int,
+        warnings: &Warnings,
+    ) -> common::Result<Str<'a>> {
         self.with_bytes(|s| {
             subst.with_bytes(|subst| {
                 // skip first
@@ -618,7 +669,7 @@ impl<'a> Str<'a> {
                         start.end()
                     } else {
                         // not enough matches, so return the string verbatim
-                        return self.clone();
+                        return Ok(self.clone());
                     }
                 } else {
                     // no need to skip anything
@@ -632,11 +683,11 @@ impl<'a> Str<'a> {
 
                     let mut buf = DynamicBuf::new(s.len());
                     buf.write_all(&s[0..start]).unwrap();
-                    process_match_gen(c, subst, &mut buf).unwrap();
+                    process_match_gen(c, subst, &mut buf, warnings)?;
                     buf.write_all(&s[end..]).unwrap();
-                    buf.into_str()
+                    Ok(buf.into_str())
                 } else {
-                    self.clone()
+                    Ok(self.clone())
                 }
             })
         })
@@ -1000,11 +1051,23 @@ impl<'a> From<String> for Str<'a> {
 // represented. This allows us to trigger the "Inline" variant and avoid a heap allocation,
 // sometimes at the expenseof a small copy.
 
+impl<'a> Str<'a> {
+    /// Copy the (short, formatted) number in `bs` into a new string, avoiding the heap if it fits
+    /// inline.
+    fn from_formatted_number(bs: &[u8]) -> Str<'a> {
+        if bs.len() <= MAX_INLINE_SIZE {
+            // Safety: we just checked that `bs` fits inline.
+            unsafe { Str::from_rep(Inline::from_unchecked(bs).into()) }
+        } else {
+            Buf::read_from_bytes(bs).into_str()
+        }
+    }
+}
+
 impl<'a> From<Int> for Str<'a> {
     fn from(i: Int) -> Str<'a> {
         let mut itoabuf = itoa::Buffer::new();
-        let s = itoabuf.format(i);
-        Buf::read_from_bytes(s.as_bytes()).into_str()
+        Str::from_formatted_number(itoabuf.format(i).as_bytes())
     }
 }
 
@@ -1019,7 +1082,7 @@ impl<'a> From<Float> for Str<'a> {
         } else {
             slen
         };
-        Buf::read_from_bytes(&s.as_bytes()[..slen]).into_str()
+        Str::from_formatted_number(&s.as_bytes()[..slen])
     }
 }
 
@@ -1300,7 +1363,7 @@ impl Buf {
         unsafe { self.0.offset(1) as *const u8 }
     }
 
-    fn refcount(&self) -> usize {
+    pub(crate) fn refcount(&self) -> usize {
         let header: &BufHeader = unsafe { &(*self.0) };
         header.count.get()
     }
@@ -1365,74 +1428,107 @@ impl Buf {
     }
 }
 
-/// Helper function for `subst_first` and `subst_all`: handles '&' syntax.
-fn process_match(matched: &[u8], subst: &[u8], w: &mut impl Write) -> io::Result<()> {
-    if memchr::memchr(b'&', subst).is_none() {
-        w.write_all(subst).unwrap();
-        return Ok(());
-    }
-    let mut start = 0;
-    let mut escaped = false;
-    for (i, b) in subst.iter().cloned().enumerate() {
-        match b {
-            b'&' => {
-                if escaped {
-                    w.write_all(&subst[start..i - 1])?;
-                    w.write_all(&[b'&'])?;
-                } else {
-                    w.write_all(&subst[start..i])?;
-                    w.write_all(matched)?;
+/// A compiled replacement string for `sub` and `gsub`.
+///
+/// Following POSIX, `&` in the replacement stands for the matched text, `\&` for a literal `&`
+/// and `\\` for a literal backslash. A backslash before any other character is left alone.
+#[derive(Debug, Default)]
+pub(crate) struct SubstTemplate {
+    // The literal portions of the replacement, with escapes removed.
+    lits: Vec<u8>,
+    pieces: SmallVec<[SubstPiece; 4]>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SubstPiece {
+    // A range of `lits`.
+    Lit(usize, usize),
+    Matched,
+}
+
+impl SubstTemplate {
+    pub(crate) fn new(subst: &[u8]) -> SubstTemplate {
+        let mut res = SubstTemplate {
+            lits: Vec::with_capacity(subst.len()),
+            pieces: Default::default(),
+        };
+        let mut lit_start = 0;
+        let mut i = 0;
+        while i < subst.len() {
+            match subst[i] {
+                b'&' => {
+                    res.push_lit(lit_start);
+                    res.pieces.push(SubstPiece::Matched);
+                    lit_start = res.lits.len();
                 }
-                start = i + 1;
-            }
-            b'\\' => {
-                if !escaped {
-                    escaped = true;
-                    continue;
+                b'\\' if matches!(subst.get(i + 1), Some(b'&') | Some(b'\\')) => {
+                    res.lits.push(subst[i + 1]);
+                    i += 1;
                 }
-                w.write_all(&subst[start..i])?;
-                start = i + 1;
+                b => res.lits.push(b),
             }
-            _ => {}
+            i += 1;
         }
-        escaped = false;
+        res.push_lit(lit_start);
+        res
+    }
+
+    fn push_lit(&mut self, start: usize) {
+        if start < self.lits.len() {
+            self.pieces.push(SubstPiece::Lit(start, self.lits.len()));
+        }
+    }
+
+    fn expand(&self, matched: &[u8], w: &mut impl Write) -> io::Result<()> {
+        for piece in self.pieces.iter() {
+            match *piece {
+                SubstPiece::Lit(start, end) => w.write_all(&self.lits[start..end])?,
+                SubstPiece::Matched => w.write_all(matched)?,
+            }
+        }
+        Ok(())
     }
-    w.write_all(&subst[start..])?;
-    Ok(())
 }
 
 /// Helper function for `subst_gen` function; handles the syntax for &, \0, \1, etc...
-fn process_match_gen(matched: Captures, subst: &[u8], w: &mut impl Write) -> io::Result<()> {
+fn process_match_gen(
+    matched: Captures,
+    subst: &[u8],
+    w: &mut DynamicBuf,
+    warnings: &Warnings,
+) -> common::Result<()> {
     let mut start = 0;
     let mut escaped = false;
     for (i, b) in subst.iter().cloned().enumerate() {
         match b {
             b'0'..=b'9' => {
                 if escaped {
-                    w.write_all(&subst[start..i - 1])?;
+                    w.write_all(&subst[start..i - 1]).unwrap();
                     let n = b - b'0';
                     match matched.get(n as usize) {
-                        Some(match_) => w.write_all(match_.as_bytes())?,
-                        None => eprintln_ignore!(
-                            // no match - no substitution (same as gawk); warning is nice though
-                            "Couldn't substitute match {}, we have only {}",
-                            n,
-                            matched.len()
-                        ),
+                        Some(match_) => w.write_all(match_.as_bytes()).unwrap(),
+                        // no match - no substitution (same as gawk); warning is nice though
+                        None => warnings.warn(Warning::Substitution, || {
+                            format!(
+                                "couldn't substitute match {}, we have only {}",
+                                n,
+                                matched.len()
+                            )
+                        })?,
                     }
                 } else {
-                    w.write_all(&subst[start..i])?;
-                    w.write_all(&[b])?;
+                    w.write_all(&subst[start..i]).unwrap();
+                    w.write_all(&[b]).unwrap();
                 }
                 start = i + 1;
             }
             b'&' => {
                 if escaped {
-                    w.write_all(&subst[start..i - 1])?;
-                    w.write_all(&[b'&'])?;
+                    w.write_all(&subst[start..i - 1]).unwrap();
+                    w.write_all(&[b'&']).unwrap();
                 } else {
-                    w.write_all(&subst[start..i])?;
-                    w.write_all(matched.get(0).unwrap().as_bytes())?;
+                    w.write_all(&subst[start..i]).unwrap();
+                    w.write_all(matched.get(0).unwrap().as_bytes()).unwrap();
                 }
                 start = i + 1;
             }
@@ -1441,14 +1537,14 @@ fn process_match_gen(matched: Captures, subst: &[u8], w: &mut impl Write) -> io:
                     escaped = true;
                     continue;
                 }
-                w.write_all(&subst[start..i])?;
+                w.write_all(&subst[start..i]).unwrap();
                 start = i + 1;
             }
             _ => {}
         }
         escaped = false;
     }
-    w.write_all(&subst[start..])?;
+    w.write_all(&subst[start..]).unwrap();
     Ok(())
 }
 
@@ -1471,6 +1567,21 @@ mod tests {
         s1.with_bytes(|bs1| assert_eq!(bs1, b"h"));
     }
 
+    #[test]
+    fn number_formatting() {
+        let small: Str = (-12345 as Int).into();
+        assert_eq!(unsafe { small.rep().get_tag() }, StrTag::Inline);
+        small.with_bytes(|bs| assert_eq!(bs, b"-12345"));
+        let large: Str = Int::MIN.into();
+        assert_eq!(unsafe { large.rep().get_tag() }, StrTag::Boxed);
+        large.with_bytes(|bs| assert_eq!(bs, Int::MIN.to_string().as_bytes()));
+        let f: Str = (2.5 as Float).into();
+        assert_eq!(unsafe { f.rep().get_tag() }, StrTag::Inline);
+        f.with_bytes(|bs| assert_eq!(bs, b"2.5"));
+        let whole: Str = (3.0 as Float).into();
+        whole.with_bytes(|bs| assert_eq!(bs, b"3"));
+    }
+
     #[test]
     fn basic_behavior() {
         let base_1 = b"hi there fellow";
@@ -1569,7 +1680,7 @@ And this is the second part"#
     #[test]
     fn subst() {
         let s1: Str = "String number one".into();
-        let s2: Str = "m".into();
+        let s2 = SubstTemplate::new(b"m");
         let re1 = Regex::new("n").unwrap();
         let (s3, n1) = s1.subst_all(&re1, &s2);
         assert_eq!(n1, 3);
@@ -1586,7 +1697,7 @@ And this is the second part"#
         assert_eq!(empty, s5);
 
         let s6: Str = "xxyz substituted into another xxyz".into();
-        let (s7, subbed) = s6.subst_first(&re2, &s1);
+        let (s7, subbed) = s6.subst_first(&re2, &SubstTemplate::new(b"String number one"));
         s7.with_bytes(|bs| assert_eq!(bs, b"String number one substituted into another xxyz"));
         assert!(subbed);
     }
@@ -1594,7 +1705,7 @@ And this is the second part"#
     #[test]
     fn subst_ampersand() {
         let s1: Str = "hahbhc".into();
-        let s2: Str = "ha&".into();
+        let s2 = SubstTemplate::new(b"ha&");
         let re1 = Regex::new("h.").unwrap();
         let (s3, subbed) = s1.subst_first(&re1, &s2);
         assert!(subbed);
@@ -1602,30 +1713,58 @@ And this is the second part"#
         let (s4, count) = s1.subst_all(&re1, &s2);
         s4.with_bytes(|bs| assert_eq!(bs, b"hahahahbhahc"));
         assert_eq!(count, 3);
-        let s5: Str = "hz\\&".into();
+        let s5 = SubstTemplate::new(b"hz\\&");
         let (s6, subbed) = s1.subst_first(&re1, &s5);
         s6.with_bytes(|bs| assert_eq!(bs, b"hz&hbhc"));
         assert!(subbed);
     }
 
+    #[test]
+    fn subst_escapes() {
+        let s: Str = "abc".into();
+        let re = Regex::new("b").unwrap();
+        let sub = |t: &[u8]| {
+            let (res, subbed) = s.subst_first(&re, &SubstTemplate::new(t));
+            assert!(subbed);
+            res.with_bytes(|bs| bs.to_vec())
+        };
+        assert_eq!(sub(b"[&]"), b"a[b]c");
+        assert_eq!(sub(b"\\&"), b"a&c");
+        assert_eq!(sub(b"\\\\&"), b"a\\bc");
+        assert_eq!(sub(b"\\\\\\&"), b"a\\&c");
+        assert_eq!(sub(b"\\\\"), b"a\\c");
+        assert_eq!(sub(b"\\q"), b"a\\qc");
+        assert_eq!(sub(b"x\\"), b"ax\\c");
+        assert_eq!(sub(b"&&"), b"abbc");
+        assert_eq!(sub(b""), b"ac");
+    }
+
     #[test]
     fn gen_subst_basic() {
         let s1: Str = "String number one".into();
         let s2: Str = "m".into();
         let re1 = Regex::new("n").unwrap();
-        let s3 = s1.gen_subst_dynamic(&re1, &s2, &"g".into());
+        let s3 = s1
+            .gen_subst_dynamic(&re1, &s2, &"g".into(), &Warnings::default())
+            .unwrap();
         s3.with_bytes(|bs| assert_eq!(bs, b"Strimg mumber ome"));
 
         let re2 = Regex::new("xxyz").unwrap();
-        let s4 = s3.gen_subst_dynamic(&re2, &s2, &"g".into());
+        let s4 = s3
+            .gen_subst_dynamic(&re2, &s2, &"g".into(), &Warnings::default())
+            .unwrap();
         assert_eq!(s3, s4);
 
         let empty = Str::default();
-        let s5 = empty.gen_subst_dynamic(&re1, &s2, &"g".into());
+        let s5 = empty
+            .gen_subst_dynamic(&re1, &s2, &"g".into(), &Warnings::default())
+            .unwrap();
         assert_eq!(empty, s5);
 
         let s6: Str = "xxyz substituted into another xxyz".into();
-        let s7 = s6.gen_subst_dynamic(&re2, &s1, &"1".into());
+        let s7 = s6
+            .gen_subst_dynamic(&re2, &s1, &"1".into(), &Warnings::default())
+            .unwrap();
         s7.with_bytes(|bs| assert_eq!(bs, b"String number one substituted into another xxyz"));
     }
 
@@ -1634,7 +1773,9 @@ And this is the second part"#
         let s1: Str = "abc def".into();
         let s2: Str = "\\2 \\1 \\0".into();
         let re1 = Regex::new("(.+) (.+)").unwrap();
-        let s3 = s1.gen_subst_dynamic(&re1, &s2, &"g".into());
+        let s3 = s1
+            .gen_subst_dynamic(&re1, &s2, &"g".into(), &Warnings::default())
+            .unwrap();
         s3.with_bytes(|bs| assert_eq!(bs, b"def abc abc def"));
     }
 }
@@ -0,0 +1,375 @@
+//! Formatting timestamps, for the `strftime` builtin.
+//!
+//! We do the calendar arithmetic here rather than calling the C library's `strftime`, so that
+//! times can be formatted in UTC or at a fixed offset from it without setting `TZ`, which is not
+//! safe to do once worker threads are running. The C library is only asked for the offset and name
+//! of the local time zone, which it takes from `TZ` as usual.
+use std::io::Write;
+
+use crate::common::Result;
+use crate::runtime::{Float, Int, Str};
+
+const DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+pub(super) const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+#[derive(Debug, PartialEq)]
+enum Zone {
+    Local,
+    // Seconds east of UTC, and the name printed by %Z.
+    Fixed(i64, String),
+}
+
+impl Zone {
+    /// Parse the time zone argument to `strftime`: "" for local time, "UTC" (or "GMT" or "Z"), or
+    /// a fixed offset from UTC like "+05:30", "-0800" or "+01". As in gawk, a nonzero number also
+    /// means UTC.
+    fn parse(tz: &[u8]) -> Result<Zone> {
+        let name = String::from_utf8_lossy(tz).into_owned();
+        if tz.is_empty() || tz.iter().all(|b| *b == b'0') {
+            return Ok(Zone::Local);
+        }
+        if tz.iter().all(u8::is_ascii_digit) {
+            return Ok(Zone::Fixed(0, "UTC".into()));
+        }
+        match name.to_ascii_uppercase().as_str() {
+            "UTC" | "Z" => return Ok(Zone::Fixed(0, "UTC".into())),
+            "GMT" => return Ok(Zone::Fixed(0, "GMT".into())),
+            _ => {}
+        }
+        let sign = match tz[0] {
+            b'+' => 1,
+            b'-' => -1,
+            _ => return Zone::invalid(&name),
+        };
+        let digits = match &tz[1..] {
+            [h1, h2, b':', m1, m2] => vec![*h1, *h2, *m1, *m2],
+            rest => rest.to_vec(),
+        };
+        if !matches!(digits.len(), 2 | 4) || !digits.iter().all(u8::is_ascii_digit) {
+            return Zone::invalid(&name);
+        }
+        let num = |bs: &[u8]| bs.iter().fold(0, |n, b| n * 10 + (b - b'0') as i64);
+        let (hours, mins) = (num(&digits[..2]), num(&digits[2..]));
+        if hours > 23 || mins > 59 {
+            return Zone::invalid(&name);
+        }
+        Ok(Zone::Fixed(sign * (hours * 3600 + mins * 60), name))
+    }
+
+    fn invalid(name: &str) -> Result<Zone> {
+        err!(
+            "unsupported time zone {:?}; expected \"UTC\", an offset from UTC such as \"+05:30\", \
+             or \"\" for local time",
+            name
+        )
+    }
+
+    /// The offset from UTC (in seconds) and the name of this zone at time `ts`.
+    fn at(&self, ts: i64) -> (i64, String) {
+        match self {
+            Zone::Local => local_zone(ts),
+            Zone::Fixed(offset, name) => (*offset, name.clone()),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub(super) fn local_zone(ts: i64) -> (i64, String) {
+    use std::ffi::CStr;
+    use std::sync::Once;
+    // The libc crate does not declare tzset for all unix targets.
+    extern "C" {
+        fn tzset();
+    }
+    static TZSET: Once = Once::new();
+    // localtime_r, unlike localtime, need not read TZ itself.
+    TZSET.call_once(|| unsafe { tzset() });
+    unsafe {
+        let t = ts as libc::time_t;
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&t, &mut tm).is_null() {
+            return (0, "UTC".into());
+        }
+        let name = if tm.tm_zone.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(tm.tm_zone).to_string_lossy().into_owned()
+        };
+        (tm.tm_gmtoff as i64, name)
+    }
+}
+
+#[cfg(not(unix))]
+pub(super) fn local_zone(_ts: i64) -> (i64, String) {
+    (0, "UTC".into())
+}
+
+/// The number of days between 1970-01-01 and the given date.
+pub(super) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // See http://howardhinnant.github.io/date_algorithms.html.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// The (year, month, day) that is `days` days after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// The number of ISO 8601 weeks in `year`.
+fn iso_weeks(year: i64) -> i64 {
+    let p = |y: i64| (y + y.div_euclid(4) - y.div_euclid(100) + y.div_euclid(400)).rem_euclid(7);
+    if p(year) == 4 || p(year - 1) == 3 {
+        53
+    } else {
+        52
+    }
+}
+
+struct Tm {
+    ts: i64,
+    year: i64,
+    // 1 through 12
+    month: i64,
+    // 1 through 31
+    day: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    // 0 (Sunday) through 6
+    wday: i64,
+    // 0 through 365
+    yday: i64,
+    offset: i64,
+    zone: String,
+}
+
+impl Tm {
+    fn new(ts: i64, zone: &Zone) -> Tm {
+        let (offset, zone) = zone.at(ts);
+        let local = ts + offset;
+        let days = local.div_euclid(86400);
+        let secs = local.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        Tm {
+            ts,
+            year,
+            month,
+            day,
+            hour: secs / 3600,
+            min: secs / 60 % 60,
+            sec: secs % 60,
+            wday: (days + 4).rem_euclid(7),
+            yday: days - days_from_civil(year, 1, 1),
+            offset,
+            zone,
+        }
+    }
+
+    /// The ISO 8601 year and week number.
+    fn iso_week(&self) -> (i64, i64) {
+        let weekday = (self.wday + 6) % 7;
+        let week = (self.yday - weekday + 10) / 7;
+        if week < 1 {
+            (self.year - 1, iso_weeks(self.year - 1))
+        } else if week > iso_weeks(self.year) {
+            (self.year + 1, 1)
+        } else {
+            (self.year, week)
+        }
+    }
+
+    fn format(&self, w: &mut Vec<u8>, fmt: &[u8]) {
+        let mut iter = fmt.iter();
+        while let Some(b) = iter.next() {
+            if *b != b'%' {
+                w.push(*b);
+                continue;
+            }
+            let spec = match iter.next() {
+                Some(spec) => *spec,
+                None => {
+                    w.push(b'%');
+                    break;
+                }
+            };
+            let hour12 = (self.hour + 11) % 12 + 1;
+            // Writes to a Vec cannot fail.
+            let _ = match spec {
+                b'a' => write!(w, "{}", &DAYS[self.wday as usize][..3]),
+                b'A' => write!(w, "{}", DAYS[self.wday as usize]),
+                b'b' | b'h' => write!(w, "{}", &MONTHS[self.month as usize - 1][..3]),
+                b'B' => write!(w, "{}", MONTHS[self.month as usize - 1]),
+                b'c' => {
+                    self.format(w, b"%a %b %e %H:%M:%S %Y");
+                    Ok(())
+                }
+                b'C' => write!(w, "{:02}", self.year.div_euclid(100)),
+                b'd' => write!(w, "{:02}", self.day),
+                b'D' | b'x' => {
+                    self.format(w, b"%m/%d/%y");
+                    Ok(())
+                }
+                b'e' => write!(w, "{:2}", self.day),
+                b'F' => {
+                    self.format(w, b"%Y-%m-%d");
+                    Ok(())
+                }
+                b'G' => write!(w, "{}", self.iso_week().0),
+                b'g' => write!(w, "{:02}", self.iso_week().0.rem_euclid(100)),
+                b'H' => write!(w, "{:02}", self.hour),
+                b'I' => write!(w, "{:02}", hour12),
+                b'j' => write!(w, "{:03}", self.yday + 1),
+                b'k' => write!(w, "{:2}", self.hour),
+                b'l' => write!(w, "{:2}", hour12),
+                b'm' => write!(w, "{:02}", self.month),
+                b'M' => write!(w, "{:02}", self.min),
+                b'n' => writeln!(w),
+                b'p' => write!(w, "{}", if self.hour < 12 { "AM" } else { "PM" }),
+                b'r' => {
+                    self.format(w, b"%I:%M:%S %p");
+                    Ok(())
+                }
+                b'R' => {
+                    self.format(w, b"%H:%M");
+                    Ok(())
+                }
+                b's' => write!(w, "{}", self.ts),
+                b'S' => write!(w, "{:02}", self.sec),
+                b't' => write!(w, "\t"),
+                b'T' | b'X' => {
+                    self.format(w, b"%H:%M:%S");
+                    Ok(())
+                }
+                b'u' => write!(w, "{}", (self.wday + 6) % 7 + 1),
+                b'U' => write!(w, "{:02}", (self.yday + 7 - self.wday) / 7),
+                b'V' => write!(w, "{:02}", self.iso_week().1),
+                b'w' => write!(w, "{}", self.wday),
+                b'W' => write!(w, "{:02}", (self.yday + 7 - (self.wday + 6) % 7) / 7),
+                b'y' => write!(w, "{:02}", self.year.rem_euclid(100)),
+                b'Y' => write!(w, "{}", self.year),
+                b'z' => {
+                    let sign = if self.offset < 0 { '-' } else { '+' };
+                    let mins = self.offset.abs() / 60;
+                    write!(w, "{}{:02}{:02}", sign, mins / 60, mins % 60)
+                }
+                b'Z' => write!(w, "{}", self.zone),
+                b'%' => write!(w, "%"),
+                // Like the C library, leave unknown conversions alone.
+                _ => write!(w, "%{}", spec as char),
+            };
+        }
+    }
+}
+
+/// Format the time `ts` (in seconds since the Unix epoch) according to `fmt`, in the time zone
+/// named by `tz`; see `Zone::parse`.
+pub(crate) fn strftime(fmt: &[u8], ts: Float, tz: &[u8]) -> Result<Str<'static>> {
+    let zone = Zone::parse(tz)?;
+    // Clamp timestamps to within about 30 million years of 1970, to avoid overflow.
+    let ts = if ts.is_nan() {
+        0
+    } else {
+        ts.floor().clamp(-1e15, 1e15) as Int
+    };
+    let mut out = Vec::new();
+    Tm::new(ts, &zone).format(&mut out, fmt);
+    Ok(Str::from(&out[..]).unmoor())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(fmt: &str, ts: Float, tz: &str) -> String {
+        let s = strftime(fmt.as_bytes(), ts, tz.as_bytes()).unwrap();
+        format!("{}", s)
+    }
+
+    #[test]
+    fn utc() {
+        assert_eq!(
+            format("%Y-%m-%dT%H:%M:%S%z %Z", 0.0, "UTC"),
+            "1970-01-01T00:00:00+0000 UTC"
+        );
+        assert_eq!(
+            format("%a %A %b %B %e %j %u %w %I %p %s", 1700000000.5, "1"),
+            "Tue Tuesday Nov November 14 318 2 2 10 PM 1700000000"
+        );
+        assert_eq!(format("%F %T", -1.0, "Z"), "1969-12-31 23:59:59");
+        assert_eq!(format("%F %%q %q", 951782400.0, "UTC"), "2000-02-29 %q %q");
+    }
+
+    #[test]
+    fn fixed_offsets() {
+        assert_eq!(
+            format("%F %H:%M %z %Z", 0.0, "+05:30"),
+            "1970-01-01 05:30 +0530 +05:30"
+        );
+        assert_eq!(
+            format("%F %H:%M %z", 0.0, "-0800"),
+            "1969-12-31 16:00 -0800"
+        );
+        assert_eq!(format("%H %z", 0.0, "+01"), "01 +0100");
+        for tz in ["EST", "+5", "+24:00", "+05:3", "05:30", "+05-30"] {
+            assert!(strftime(b"%F", 0.0, tz.as_bytes()).is_err(), "tz={}", tz);
+        }
+        assert_eq!(Zone::parse(b"").unwrap(), Zone::Local);
+        assert_eq!(Zone::parse(b"0").unwrap(), Zone::Local);
+    }
+
+    #[test]
+    fn weeks() {
+        // 2021-01-03 was a Sunday, in the last ISO week of 2020.
+        let ts = days_from_civil(2021, 1, 3) as Float * 86400.0;
+        assert_eq!(format("%G %g %V %U %W", ts, "UTC"), "2020 20 53 01 00");
+        // 2024-12-30 was a Monday, in the first ISO week of 2025.
+        let ts = days_from_civil(2024, 12, 30) as Float * 86400.0;
+        assert_eq!(format("%G %V %U %W %j", ts, "UTC"), "2025 01 52 53 365");
+    }
+
+    #[test]
+    fn calendar() {
+        for days in -800_000..800_000 {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+    }
+}
@@ -0,0 +1,127 @@
+//! Checks for `--strict`, under which implicit conversions that would lose information are
+//! runtime errors rather than being silently coerced:
+//!
+//! * A string used as a number must be a number (optionally surrounded by whitespace), or blank.
+//!   In AWK, `"abc" + 1` is 1 and `"12abc" + 1` is 13.
+//! * A floating-point number used as an array index must be an integer. In AWK, `a[1.5]` and
+//!   `a["1.5"]` are the same element, which is rarely what was intended.
+//!
+//! Errors point at the source line of the statement that failed, along with the current record.
+//!
+//! Without `--strict`, strings that are not numbers are reported as `numeric` warnings instead
+//! (see `warnings`), when those are enabled or counted.
+use std::cell::Cell;
+
+use crate::runtime::warnings::Warning;
+use crate::runtime::{Config, Float, Str, Variables};
+
+thread_local! {
+    // The source line of the statement being executed, as set by `SetLine` instructions. These
+    // are only emitted in strict mode (or with debug info).
+    static LINE: Cell<u32> = Cell::new(0);
+}
+
+/// Whether strings converted to numbers need to go through `check_number`.
+pub(crate) fn checks_numbers(config: &Config) -> bool {
+    config.strict || config.warnings.tracked(Warning::Numeric)
+}
+
+pub(crate) fn set_line(line: u32) {
+    LINE.with(|l| l.set(line))
+}
+
+fn location(vars: &Variables) -> String {
+    let line = LINE.with(Cell::get);
+    let at = if line == 0 {
+        String::new()
+    } else {
+        format!(" at line {}", line)
+    };
+    format!(
+        "{} (NR={}, FILENAME={:?})",
+        at,
+        vars.nr,
+        vars.filename.to_string()
+    )
+}
+
+/// Whether `bs` converts to a number without ignoring any of it.
+fn is_number(bs: &[u8], decimal_comma: bool) -> bool {
+    let start = bs
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bs.len());
+    let end = bs
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |ix| ix + 1);
+    let bs = &bs[start..end];
+    if bs.is_empty() {
+        return true;
+    }
+    let parsed = if decimal_comma {
+        if bs.contains(&b'.') {
+            return false;
+        }
+        let swapped: Vec<u8> = bs
+            .iter()
+            .map(|&b| if b == b',' { b'.' } else { b })
+            .collect();
+        fast_float::parse_partial::<f64, _>(&swapped)
+    } else {
+        fast_float::parse_partial::<f64, _>(bs)
+    };
+    matches!(parsed, Ok((_, len)) if len == bs.len())
+}
+
+/// Check that `s` can be used as a number, returning the error to report if it cannot. Outside
+/// of strict mode, this reports a warning instead.
+pub(crate) fn check_number(s: &Str, config: &Config, vars: &Variables) -> Result<(), String> {
+    if s.with_bytes(|bs| is_number(bs, config.decimal_comma)) {
+        return Ok(());
+    }
+    if !config.strict {
+        return config
+            .warnings
+            .warn_at(
+                Warning::Numeric,
+                || format!("{:?} is not a number", s.to_string()),
+                || location(vars),
+            )
+            .map_err(|e| e.to_string());
+    }
+    Err(format!(
+        "strict mode: {:?} is not a number{}",
+        s.to_string(),
+        location(vars)
+    ))
+}
+
+/// Check that `f` can be used as an array index, returning the error to report if it cannot.
+pub(crate) fn check_index(f: Float, vars: &Variables) -> Result<(), String> {
+    if f.is_finite() && f.fract() == 0.0 {
+        return Ok(());
+    }
+    Err(format!(
+        "strict mode: array index {} is not an integer{}",
+        f,
+        location(vars)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        for s in ["1", " -2.5 ", "1e3", "", "  ", "+7", "0.5\n"] {
+            assert!(is_number(s.as_bytes(), false), "s={:?}", s);
+        }
+        for s in ["abc", "12abc", "1 2", "0x10", "1,5", "."] {
+            assert!(!is_number(s.as_bytes(), false), "s={:?}", s);
+        }
+        assert!(is_number(b"1,5", true));
+        assert!(!is_number(b"1.5", true));
+    }
+}
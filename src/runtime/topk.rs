@@ -0,0 +1,170 @@
+//! Bounded heavy-hitter tracking, for the `topk` builtin.
+//!
+//! `topk(heap, k, key, weight)` adds `weight` to `heap[key]` while keeping at most `k` keys in
+//! `heap`. This is the "Space-Saving" algorithm: when a new key arrives at a full heap, the key with
+//! the smallest weight is evicted and the new key takes over its weight. Any key whose true total
+//! exceeds the smallest weight in the heap is guaranteed to be present, and each weight in the heap
+//! is an upper bound on that key's true total.
+//!
+//! Finding the smallest weight quickly requires keeping the heap's entries ordered, which we do
+//! here on the side. Ties are broken by key, so results do not depend on hash iteration order.
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+use crate::runtime::{map_state::MapState, Float, Int, Str, StrMap};
+
+#[derive(Clone)]
+struct Entry<'a> {
+    weight: Float,
+    key: Str<'a>,
+}
+
+impl<'a> Ord for Entry<'a> {
+    fn cmp(&self, other: &Entry<'a>) -> Ordering {
+        let (x, y) = (self.weight, other.weight);
+        x.partial_cmp(&y)
+            .unwrap_or_else(|| x.is_nan().cmp(&y.is_nan()))
+            .then_with(|| {
+                self.key
+                    .with_bytes(|k1| other.key.with_bytes(|k2| k1.cmp(k2)))
+            })
+    }
+}
+
+impl<'a> PartialOrd for Entry<'a> {
+    fn partial_cmp(&self, other: &Entry<'a>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> PartialEq for Entry<'a> {
+    fn eq(&self, other: &Entry<'a>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a> Eq for Entry<'a> {}
+
+/// The entries of each map passed to `topk`, ordered by weight.
+#[derive(Default)]
+pub(crate) struct TopK<'a>(MapState<'a, BTreeSet<Entry<'a>>>);
+
+fn rebuild<'a>(entries: &mut BTreeSet<Entry<'a>>, map: &StrMap<'a, Float>) {
+    entries.clear();
+    map.iter(|iter| {
+        entries.extend(iter.map(|(key, weight)| Entry {
+            weight: *weight,
+            key: key.clone(),
+        }))
+    });
+}
+
+impl<'a> TopK<'a> {
+    /// Add `weight` to `key` in `map`, evicting the lightest keys so that `map` holds at most `k`
+    /// keys. Returns the new weight of `key`; if `key` is not already present and `k` is not
+    /// positive, nothing is added and the result is 0.
+    ///
+    /// Entries are rebuilt from `map` if it has been modified other than by `topk`, e.g. by
+    /// clearing it or deleting a key.
+    pub(crate) fn add(
+        &mut self,
+        map: &StrMap<'a, Float>,
+        k: Int,
+        key: &Str<'a>,
+        weight: Float,
+    ) -> Float {
+        let entries = self.0.get(map);
+        if entries.len() != map.len() {
+            rebuild(entries, map);
+        }
+        if map.contains(key) {
+            let old = Entry {
+                weight: map.get(key),
+                key: key.clone(),
+            };
+            if !entries.remove(&old) {
+                rebuild(entries, map);
+                entries.remove(&old);
+            }
+            let weight = old.weight + weight;
+            map.insert(key.clone(), weight);
+            entries.insert(Entry {
+                weight,
+                key: key.clone(),
+            });
+            return weight;
+        }
+        if k <= 0 {
+            return 0.0;
+        }
+        let mut floor = 0.0;
+        while map.len() as Int >= k {
+            let min = entries.iter().next().cloned().unwrap();
+            let current = Entry {
+                weight: map.get(&min.key),
+                key: min.key.clone(),
+            };
+            if !map.contains(&min.key) || current != min {
+                // `map` was modified directly; start over from its contents.
+                rebuild(entries, map);
+                continue;
+            }
+            entries.remove(&min);
+            map.delete(&min.key);
+            floor = min.weight;
+        }
+        let weight = floor + weight;
+        map.insert(key.clone(), weight);
+        entries.insert(Entry {
+            weight,
+            key: key.clone(),
+        });
+        weight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(map: &StrMap<Float>) -> Vec<(String, Float)> {
+        let mut res: Vec<_> = map.iter(|iter| {
+            iter.map(|(k, v)| (format!("{}", k), *v))
+                .collect::<Vec<_>>()
+        });
+        res.sort_by(|x, y| x.0.cmp(&y.0));
+        res
+    }
+
+    #[test]
+    fn heavy_hitters() {
+        let mut topk = TopK::default();
+        let map: StrMap<Float> = Default::default();
+        assert_eq!(topk.add(&map, 2, &Str::from("a"), 5.0), 5.0);
+        assert_eq!(topk.add(&map, 2, &Str::from("b"), 1.0), 1.0);
+        // "b" is the lightest, so "c" replaces it and inherits its weight.
+        assert_eq!(topk.add(&map, 2, &Str::from("c"), 1.0), 2.0);
+        assert_eq!(topk.add(&map, 2, &Str::from("a"), 1.0), 6.0);
+        assert_eq!(contents(&map), [("a".into(), 6.0), ("c".into(), 2.0)]);
+        // Ties are broken by key.
+        assert_eq!(topk.add(&map, 2, &Str::from("d"), 4.0), 6.0);
+        assert_eq!(topk.add(&map, 2, &Str::from("e"), 0.0), 6.0);
+        assert_eq!(contents(&map), [("d".into(), 6.0), ("e".into(), 6.0)]);
+    }
+
+    #[test]
+    fn outside_changes() {
+        let mut topk = TopK::default();
+        let map: StrMap<Float> = Default::default();
+        topk.add(&map, 2, &Str::from("a"), 1.0);
+        topk.add(&map, 2, &Str::from("b"), 2.0);
+        map.delete(&Str::from("a"));
+        assert_eq!(topk.add(&map, 2, &Str::from("c"), 1.0), 1.0);
+        assert_eq!(topk.add(&map, 2, &Str::from("d"), 1.0), 2.0);
+        assert_eq!(contents(&map), [("b".into(), 2.0), ("d".into(), 2.0)]);
+        map.clear();
+        assert_eq!(topk.add(&map, 2, &Str::from("a"), 1.0), 1.0);
+        assert_eq!(topk.add(&map, 0, &Str::from("b"), 1.0), 0.0);
+        assert_eq!(map.len(), 1);
+    }
+}
@@ -0,0 +1,209 @@
+//! Decoding binary records into fields, for the `unpack` builtin.
+//!
+//! Formats follow Python's `struct` module. An optional byte order comes first: `<` for
+//! little-endian (the default), `>` or `!` for big-endian, and `=` or `@` for the native byte order.
+//! It is followed by a sequence of codes, each optionally preceded by a decimal repeat count:
+//!
+//! * `b`/`B`, `h`/`H`, `i`/`I`, `q`/`Q`: signed/unsigned integers of 1, 2, 4 and 8 bytes.
+//! * `f`, `d`: 4- and 8-byte IEEE floating point numbers.
+//! * `s`: a string; the count is its length in bytes rather than a repetition. Trailing NUL
+//!   bytes are removed.
+//! * `x`: a padding byte, which is skipped; the count is the number of bytes to skip.
+//!
+//! Whitespace between codes is ignored. Every other code yields one field.
+use crate::common::Result;
+use crate::runtime::{convert, Float, Int, Line, RegexCache, Str};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Code {
+    Int { bytes: usize, signed: bool },
+    Float(usize),
+    Str(usize),
+    Pad(usize),
+}
+
+impl Code {
+    fn size(&self) -> usize {
+        match *self {
+            Code::Int { bytes, .. } => bytes,
+            Code::Float(bytes) | Code::Str(bytes) | Code::Pad(bytes) => bytes,
+        }
+    }
+}
+
+fn parse(fmt: &[u8]) -> Result<(bool /* big endian */, Vec<Code>)> {
+    let (big_endian, mut rest) = match fmt.first() {
+        Some(b'<') => (false, &fmt[1..]),
+        Some(b'>') | Some(b'!') => (true, &fmt[1..]),
+        Some(b'=') | Some(b'@') => (cfg!(target_endian = "big"), &fmt[1..]),
+        _ => (false, fmt),
+    };
+    let mut codes = Vec::new();
+    loop {
+        while let Some(b) = rest.first() {
+            if !b.is_ascii_whitespace() {
+                break;
+            }
+            rest = &rest[1..];
+        }
+        let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+        let count = if digits == 0 {
+            None
+        } else {
+            match std::str::from_utf8(&rest[..digits])
+                .unwrap()
+                .parse::<usize>()
+            {
+                Ok(count) => Some(count),
+                Err(_) => return err!("invalid repeat count in unpack format"),
+            }
+        };
+        rest = &rest[digits..];
+        let c = match rest.first() {
+            Some(c) => *c,
+            None if count.is_some() => return err!("unpack format ends with a repeat count"),
+            None => break,
+        };
+        rest = &rest[1..];
+        let int = |bytes, signed| Code::Int { bytes, signed };
+        let code = match c {
+            b'b' => int(1, true),
+            b'B' => int(1, false),
+            b'h' => int(2, true),
+            b'H' => int(2, false),
+            b'i' => int(4, true),
+            b'I' => int(4, false),
+            b'q' => int(8, true),
+            b'Q' => int(8, false),
+            b'f' => Code::Float(4),
+            b'd' => Code::Float(8),
+            b's' => {
+                codes.push(Code::Str(count.unwrap_or(1)));
+                continue;
+            }
+            b'x' => {
+                codes.push(Code::Pad(count.unwrap_or(1)));
+                continue;
+            }
+            c => return err!("invalid code {:?} in unpack format", c as char),
+        };
+        codes.extend(std::iter::repeat(code).take(count.unwrap_or(1)));
+    }
+    Ok((big_endian, codes))
+}
+
+/// Decode `data` according to `fmt`, returning one field per non-padding code.
+pub(crate) fn unpack(data: &[u8], fmt: &[u8]) -> Result<Vec<Str<'static>>> {
+    let (big_endian, codes) = parse(fmt)?;
+    let needed: usize = codes.iter().map(Code::size).sum();
+    if needed > data.len() {
+        return err!(
+            "unpack format needs {} bytes, but the record has {}",
+            needed,
+            data.len()
+        );
+    }
+    let mut res = Vec::with_capacity(codes.len());
+    let mut data = data;
+    for code in codes {
+        let (bs, rest) = data.split_at(code.size());
+        data = rest;
+        if let Code::Pad(_) = code {
+            continue;
+        }
+        // Copy the value into the low-order bytes of a little-endian u64.
+        let mut buf = [0u8; 8];
+        if let Code::Int { .. } | Code::Float(_) = code {
+            buf[..bs.len()].copy_from_slice(bs);
+            if big_endian {
+                buf[..bs.len()].reverse();
+            }
+        }
+        let raw = u64::from_le_bytes(buf);
+        res.push(match code {
+            Code::Int { bytes, signed } => {
+                let shift = 64 - 8 * bytes as u32;
+                if signed {
+                    // Sign-extend from the value's width.
+                    convert::<Int, Str>(((raw << shift) as i64) >> shift)
+                } else if raw > Int::MAX as u64 {
+                    // This doesn't fit in an awk integer; keep its digits intact.
+                    Str::from(raw.to_string())
+                } else {
+                    convert::<Int, Str>(raw as Int)
+                }
+            }
+            Code::Float(4) => convert::<Float, Str>(f32::from_bits(raw as u32) as Float),
+            Code::Float(_) => convert::<Float, Str>(f64::from_bits(raw)),
+            Code::Str(_) => {
+                let len = bs.iter().rposition(|b| *b != 0).map_or(0, |ix| ix + 1);
+                Str::from(&bs[..len]).unmoor()
+            }
+            Code::Pad(_) => unreachable!(),
+        });
+    }
+    Ok(res)
+}
+
+/// Decode the current record according to `fmt`, replacing its fields with the result. Returns
+/// the new number of fields.
+pub(crate) fn unpack_line<'a, L: Line<'a>>(
+    line: &mut L,
+    fmt: &Str<'a>,
+    fs: &Str<'a>,
+    ofs: &Str<'a>,
+    rc: &mut RegexCache,
+) -> Result<Int> {
+    let record = line.get_col(0, fs, ofs, rc)?;
+    let fields = record.with_bytes(|bs| fmt.with_bytes(|fmt| unpack(bs, fmt)))?;
+    // Clear the record, so that $0 is rebuilt from the new fields with OFS.
+    line.set_col(0, &Str::default(), fs, rc)?;
+    for (i, field) in fields.iter().enumerate() {
+        line.set_col(i as Int + 1, field.upcast_ref(), fs, rc)?;
+    }
+    Ok(fields.len() as Int)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpack_strs(data: &[u8], fmt: &str) -> Vec<String> {
+        unpack(data, fmt.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn ints_and_floats() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(-2i16).to_le_bytes());
+        data.extend_from_slice(&40000u16.to_le_bytes());
+        data.extend_from_slice(&(-7i32).to_be_bytes());
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        data.extend_from_slice(&1.5f32.to_le_bytes());
+        data.extend_from_slice(&(-0.25f64).to_le_bytes());
+        assert_eq!(unpack_strs(&data[..4], "<hH"), vec!["-2", "40000"]);
+        assert_eq!(unpack_strs(&data[4..8], ">i"), vec!["-7"]);
+        assert_eq!(
+            unpack_strs(&data[8..], "Q f d"),
+            vec!["18446744073709551615", "1.5", "-0.25"]
+        );
+        assert_eq!(unpack_strs(&[1, 2, 255], "2b x"), vec!["1", "2"]);
+        assert_eq!(unpack_strs(&[255, 255], "bB"), vec!["-1", "255"]);
+    }
+
+    #[test]
+    fn strings_and_errors() {
+        assert_eq!(
+            unpack_strs(b"abc\0\0\x01\0xyz", "5s2x3s"),
+            vec!["abc", "xyz"]
+        );
+        assert_eq!(unpack_strs(b"", ""), Vec::<String>::new());
+        assert!(unpack(b"ab", b"i").is_err());
+        assert!(unpack(b"abcd", b"2").is_err());
+        assert!(unpack(b"abcd", b"z").is_err());
+    }
+}
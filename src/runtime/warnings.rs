@@ -0,0 +1,221 @@
+//! Warnings printed while a program runs, grouped into categories that can be turned on or off
+//! individually with `-W <category>` and `-W no-<category>`, or turned into errors with
+//! `--werror`.
+//!
+//! Warnings are counted whether or not they are printed, and `--stats` reports the counts when
+//! the program finishes. Code reporting a warning rarely knows which record it is processing, so
+//! printed warnings are queued and written out (see `Warnings::flush`) along with NR and FILENAME
+//! once the thread that reported them is done with its current record.
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+
+use crate::common::Result;
+use crate::runtime::{Int, Str};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// A record was truncated to fit `--max-record-bytes` or `--max-fields`.
+    Truncation,
+    /// The replacement string passed to `gensub` refers to a group that the pattern does not
+    /// have.
+    Substitution,
+    /// A string that is not a number was converted to one, ignoring some or all of it. This is
+    /// common in AWK programs, so these warnings are disabled by default.
+    Numeric,
+    /// `printf` replaced bytes that are not valid UTF-8 in a `%s` argument.
+    Encoding,
+}
+
+impl Warning {
+    pub const ALL: &'static [Warning] = &[
+        Warning::Truncation,
+        Warning::Substitution,
+        Warning::Numeric,
+        Warning::Encoding,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Warning::Truncation => "truncation",
+            Warning::Substitution => "substitution",
+            Warning::Numeric => "numeric",
+            Warning::Encoding => "encoding",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Warning> {
+        Warning::ALL.iter().cloned().find(|w| w.name() == name)
+    }
+
+    fn bit(self) -> u32 {
+        1 << (self as u32)
+    }
+}
+
+/// Which warnings are reported for a run of a program, and how, along with the warnings reported
+/// so far. A `Warnings` is part of the program's `runtime::Config`; clones share their settings
+/// and the warnings reported through any of them.
+#[derive(Clone, Debug, Default)]
+pub struct Warnings(Arc<Settings>);
+
+#[derive(Debug)]
+struct Settings {
+    // A bit for each disabled category.
+    disabled: AtomicU32,
+    werror: AtomicBool,
+    stats: AtomicBool,
+    // The number of warnings reported in each category, indexed by `Warning as usize`.
+    counts: [AtomicU64; 4],
+    // Warnings that have not been printed yet, along with the thread that reported them.
+    pending: Mutex<Vec<(ThreadId, Warning, String)>>,
+    // Set once a warning has been queued, so that `flush` costs next to nothing for programs that
+    // never report one.
+    queued: AtomicBool,
+    // Other lines for the `--stats` summary, in the order they were noted.
+    notes: Mutex<Vec<String>>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            disabled: AtomicU32::new(Warning::Numeric.bit()),
+            werror: AtomicBool::new(false),
+            stats: AtomicBool::new(false),
+            counts: Default::default(),
+            pending: Default::default(),
+            queued: AtomicBool::new(false),
+            notes: Default::default(),
+        }
+    }
+}
+
+impl Warnings {
+    /// Enable or disable warnings in the category `w`. All categories other than `numeric` are
+    /// enabled by default.
+    pub fn set_enabled(&self, w: Warning, enabled: bool) {
+        if enabled {
+            self.0.disabled.fetch_and(!w.bit(), Ordering::Relaxed);
+        } else {
+            self.0.disabled.fetch_or(w.bit(), Ordering::Relaxed);
+        }
+    }
+
+    /// Treat enabled warnings as errors.
+    pub fn enable_werror(&self) {
+        self.0.werror.store(true, Ordering::Relaxed);
+    }
+
+    /// Report the number of warnings in each category when the program finishes.
+    pub fn enable_stats(&self) {
+        self.0.stats.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn enabled(&self, w: Warning) -> bool {
+        self.0.disabled.load(Ordering::Relaxed) & w.bit() == 0
+    }
+
+    /// Whether warnings in the category `w` are printed or counted. Checks that are costly to
+    /// make can be skipped when this is false.
+    pub(crate) fn tracked(&self, w: Warning) -> bool {
+        self.enabled(w) || self.0.stats.load(Ordering::Relaxed)
+    }
+
+    /// Count a warning in the category `w` without printing it, e.g. for a repeat of a warning
+    /// that is only printed once.
+    pub(crate) fn count(&self, w: Warning) {
+        self.0.counts[w as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Report a warning in the category `w`, returning an error instead with `--werror`. `msg` is
+    /// only called if the warning is enabled.
+    pub(crate) fn warn(&self, w: Warning, msg: impl FnOnce() -> String) -> Result<()> {
+        self.warn_at(w, msg, String::new)
+    }
+
+    /// Like `warn`, for callers that know where the program is: with `--werror`, the error
+    /// includes `at()` (such as the line, NR and FILENAME), which printed warnings get from
+    /// `flush` instead.
+    pub(crate) fn warn_at(
+        &self,
+        w: Warning,
+        msg: impl FnOnce() -> String,
+        at: impl FnOnce() -> String,
+    ) -> Result<()> {
+        self.count(w);
+        if !self.enabled(w) {
+            return Ok(());
+        }
+        if self.0.werror.load(Ordering::Relaxed) {
+            return user_err!("{}{} [-W{}, with --werror]", msg(), at(), w.name());
+        }
+        let msg = msg();
+        let mut pending = self.0.pending.lock().unwrap();
+        pending.push((thread::current().id(), w, msg));
+        self.0.queued.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Print the warnings reported on this thread since the last call, at the record given by
+    /// `nr` and `filename`. This is called before each record is read, and when the program
+    /// finishes.
+    pub(crate) fn flush(&self, nr: Int, filename: &Str) {
+        if !self.0.queued.load(Ordering::Relaxed) {
+            return;
+        }
+        let this_thread = thread::current().id();
+        self.0.pending.lock().unwrap().retain(|(thread, w, msg)| {
+            if *thread != this_thread {
+                return true;
+            }
+            eprintln_ignore!(
+                "frawk: warning: {} (NR={}, FILENAME={:?}) [-W{}]",
+                msg,
+                nr,
+                filename.to_string(),
+                w.name()
+            );
+            false
+        })
+    }
+
+    /// Add `line` to the `--stats` summary, e.g. to describe a decision made while reading input.
+    pub(crate) fn note(&self, line: String) {
+        if self.0.stats.load(Ordering::Relaxed) {
+            self.0.notes.lock().unwrap().push(line);
+        }
+    }
+
+    /// Print the number of warnings reported in each category, along with anything passed to
+    /// `note`, with `--stats`.
+    pub(crate) fn report_stats(&self) {
+        if !self.0.stats.load(Ordering::Relaxed) {
+            return;
+        }
+        let counts: Vec<String> = Warning::ALL
+            .iter()
+            .map(|w| {
+                let n = self.0.counts[*w as usize].load(Ordering::Relaxed);
+                format!("{}={}", w.name(), n)
+            })
+            .collect();
+        eprintln_ignore!("frawk: stats: warnings: {}", counts.join(", "));
+        for line in self.0.notes.lock().unwrap().iter() {
+            eprintln_ignore!("frawk: stats: {}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for w in Warning::ALL {
+            assert_eq!(Warning::from_name(w.name()), Some(*w));
+        }
+        assert_eq!(Warning::from_name("uninitialized"), None);
+        assert_eq!(Warning::ALL.len(), Settings::default().counts.len());
+    }
+}
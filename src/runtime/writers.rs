@@ -31,10 +31,20 @@
 //! mitigates a "producer-consumer" allocation and freeing pattern, which can put a lot of strain
 //! on some allocators.
 //!
+//! In parallel mode, `--keep-order` makes output to standard output appear in input order. Each
+//! worker buffers what it writes to stdout while processing a chunk of input (see `start_chunk`);
+//! once it moves on to the next chunk, the buffer is handed to the program's sequencer, which
+//! writes chunks out in the order in which they were read.
+//!
+//! With `--serve`, output to standard output is sent back to the client whose input is being
+//! processed (see `set_client`). These writes happen synchronously on the thread running the
+//! program, so that all of a client's output has been sent by the time its connection is closed.
+//!
 //! To facilitate easier testing, the functionality of the file system that we use is abstracted in
 //! the `FileFactory` trait. The `testing` module contains an implementation of this trait that
 //! writes all data in memory.
 
+use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::io::{self, Write};
 use std::process::ChildStdin;
@@ -57,7 +67,7 @@ use crossbeam_channel::{bounded, Receiver, Sender};
 use hashbrown::HashMap;
 
 use crate::common::{CompileError, FileSpec, Notification, Result};
-use crate::runtime::{command::command_for_write, Str};
+use crate::runtime::{command::command_for_write, Config, Str};
 
 /// The maximum number of pending requests in the per-file channels.
 const IO_CHAN_SIZE: usize = 8;
@@ -142,6 +152,8 @@ fn build_handle<W: io::Write, F: Fn(FileSpec) -> io::Result<W> + Send + 'static>
         error,
         sender,
         line_buffer: is_stdout && grep_cli::is_tty_stdout(),
+        ordered: false,
+        serve: false,
     }
 }
 
@@ -157,15 +169,33 @@ pub struct Registry {
 }
 
 impl Registry {
-    pub fn from_factory(f: impl FileFactory) -> Registry {
-        let root_impl = RootImpl::from_factory(f);
+    pub fn from_factory(f: impl FileFactory, config: &Config) -> Registry {
+        let root_impl = RootImpl::from_factory(f, config);
         let stdout = root_impl.get_stdout().into_handle();
-        Registry {
+        let reg = Registry {
             global: Arc::new(root_impl),
             files: Default::default(),
             cmds: Default::default(),
             stdout,
-        }
+        };
+        reg.attach_thread();
+        reg
+    }
+
+    /// Buffer the output to standard output of the chunks of input read on the current thread,
+    /// so that it is written in input order, with --keep-order; see `start_chunk`. This is called
+    /// on the thread that creates the registry, and on each worker thread before it starts
+    /// reading.
+    pub fn attach_thread(&self) {
+        let sequencer = self.global.sequencer();
+        // Output left over from a previous program run on this thread is submitted as the old
+        // value is dropped.
+        CUR_CHUNK.with(|c| {
+            *c.borrow_mut() = ChunkOutput {
+                sequencer,
+                cur: None,
+            }
+        });
     }
 
     pub fn get_handle<'a>(
@@ -199,6 +229,15 @@ impl Registry {
         path_or_cmd.with_bytes(|bs| self.global.close(bs))
     }
 
+    /// Like `close`, but first waits for pending writes to `cmd` to be issued, so that the command
+    /// has been started (if anything was written to it) and can be waited on once this returns.
+    pub fn close_command<'a>(&mut self, cmd: &Str<'a>) -> Result<()> {
+        if let Some(ch) = self.cmds.get_mut(&cmd.clone().unmoor()) {
+            ch.flush()?;
+        }
+        self.close(cmd)
+    }
+
     pub fn get_cmd<'a>(&mut self, cmd: &Str<'a>) -> Result<&mut FileHandle> {
         use hashbrown::hash_map::Entry;
         // borrowed by with_bytes closure.
@@ -242,6 +281,12 @@ impl Registry {
         }
         last_error
     }
+
+    /// Close the pipes to every command written to by any thread, so that the commands see the end
+    /// of their input.
+    pub fn close_all_commands(&mut self) -> Result<()> {
+        self.global.close_commands()
+    }
 }
 
 impl Clone for Registry {
@@ -263,6 +308,8 @@ trait Root: 'static + Send + Sync {
     fn get_stdout(&self) -> RawHandle;
     // closes a file or command with name `fname`.
     fn close(&self, fname: &[u8]) -> Result<()>;
+    fn close_commands(&self) -> Result<()>;
+    fn sequencer(&self) -> Option<Arc<Mutex<Sequencer>>>;
 }
 
 struct RootImpl<F> {
@@ -270,20 +317,35 @@ struct RootImpl<F> {
     commands: Mutex<HashMap<Box<[u8]>, RawHandle>>,
     stdout_raw: RawHandle,
     file_factory: F,
+    // Writes the output of each chunk of input in order, with --keep-order.
+    sequencer: Option<Arc<Mutex<Sequencer>>>,
 }
 
 impl<F: FileFactory> RootImpl<F> {
-    fn from_factory(file_factory: F) -> RootImpl<F> {
+    fn from_factory(file_factory: F, config: &Config) -> RootImpl<F> {
         let local_factory = file_factory.clone();
-        let stdout_raw = build_handle(
+        let mut stdout_raw = build_handle(
             move |_append| Ok(local_factory.stdout()),
             /*is_stdout*/ true,
         );
+        let mut sequencer = None;
+        if config.keep_order {
+            sequencer = Some(Arc::new(Mutex::new(Sequencer {
+                out: Some(stdout_raw.clone().into_handle()),
+                ..Default::default()
+            })));
+            stdout_raw.ordered = true;
+        }
+        if config.serve {
+            stdout_raw.ordered = true;
+            stdout_raw.serve = true;
+        }
         RootImpl {
             handles: Default::default(),
             commands: Default::default(),
             stdout_raw,
             file_factory,
+            sequencer,
         }
     }
 }
@@ -321,6 +383,17 @@ impl<F: FileFactory> Root for RootImpl<F> {
         }
         Ok(())
     }
+    fn close_commands(&self) -> Result<()> {
+        // As in `close`, we avoid closing handles with the lock held.
+        let handles: Vec<_> = self.commands.lock().unwrap().values().cloned().collect();
+        for h in handles {
+            h.into_handle().close()?;
+        }
+        Ok(())
+    }
+    fn sequencer(&self) -> Option<Arc<Mutex<Sequencer>>> {
+        self.sequencer.clone()
+    }
     fn get_command(&self, cmd: &[u8]) -> RawHandle {
         let mut cmds = self.commands.lock().unwrap();
         if let Some(h) = cmds.get(cmd) {
@@ -446,6 +519,9 @@ impl FileHandle {
     }
 
     pub fn write_all<'a>(&mut self, ss: &[&Str<'a>], spec: FileSpec) -> Result<()> {
+        if self.raw.ordered && self.capture(ss)? {
+            return Ok(());
+        }
         let cur_len = self.cur_batch.data.len();
         let mut added_bytes = 0;
         let mut last_line = None;
@@ -469,6 +545,40 @@ impl FileHandle {
         self.write_all(&[s], spec)
     }
 
+    // Append `ss` to the output of the chunk of input this thread is processing, if there is one.
+    fn capture<'a>(&mut self, ss: &[&Str<'a>]) -> Result<bool> {
+        if self.raw.serve {
+            return Ok(send_to_client(ss));
+        }
+        if !CUR_CHUNK.with(|c| c.borrow().cur.is_some()) {
+            return Ok(false);
+        }
+        // Anything written before the chunk started has to go out ahead of it.
+        self.clear_batch(None)?;
+        CUR_CHUNK.with(|c| {
+            if let Some((_, buf)) = &mut c.borrow_mut().cur {
+                for s in ss.iter() {
+                    buf.extend_from_slice(unsafe { &*s.get_bytes() });
+                }
+            }
+        });
+        Ok(true)
+    }
+
+    // Write the output of a chunk of input, sending it to the writer thread right away.
+    fn write_chunk(&mut self, bs: &[u8]) -> Result<()> {
+        if bs.is_empty() {
+            return Ok(());
+        }
+        self.cur_batch.extend(bs, FileSpec::Append);
+        let upto = if self.raw.line_buffer {
+            Some(self.cur_batch.data.len())
+        } else {
+            None
+        };
+        self.clear_batch(upto)
+    }
+
     pub fn flush(&mut self) -> Result<()> {
         self.clear_batch(None)?;
         let (n, req) = Request::flush();
@@ -650,6 +760,11 @@ struct RawHandle {
     error: Arc<Mutex<Option<CompileError>>>,
     sender: Sender<Request>,
     line_buffer: bool,
+    // Whether writes are captured as the output of the current chunk of input, or sent to the
+    // current client (see `capture`).
+    ordered: bool,
+    // Whether writes go to the client being served, when there is one (see `set_client`).
+    serve: bool,
 }
 
 impl RawHandle {
@@ -663,6 +778,148 @@ impl RawHandle {
     }
 }
 
+// Implementation of --keep-order.
+
+/// The sequence number and output of the chunk of input a thread is processing. The output is
+/// submitted when the thread moves on to another chunk, or when the thread exits.
+#[derive(Default)]
+struct ChunkOutput {
+    // The sequencer of the program running on this thread, if it keeps its output in order; see
+    // `Registry::attach_thread`.
+    sequencer: Option<Arc<Mutex<Sequencer>>>,
+    cur: Option<(u64, Vec<u8>)>,
+}
+
+impl Drop for ChunkOutput {
+    fn drop(&mut self) {
+        if let (Some(seqr), Some((seq, buf))) = (&self.sequencer, self.cur.take()) {
+            let _ = seqr.lock().unwrap().submit(seq, buf);
+        }
+    }
+}
+
+thread_local! {
+    static CUR_CHUNK: RefCell<ChunkOutput> = RefCell::new(Default::default());
+}
+
+/// Emits the output of each chunk once the output of all earlier chunks has been written.
+#[derive(Default)]
+struct Sequencer {
+    next: u64,
+    pending: HashMap<u64, Vec<u8>>,
+    out: Option<FileHandle>,
+}
+
+impl Sequencer {
+    fn submit(&mut self, seq: u64, buf: Vec<u8>) -> Result<()> {
+        self.pending.insert(seq, buf);
+        while let Some(buf) = self.pending.remove(&self.next) {
+            self.next += 1;
+            self.emit(&buf)?;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, buf: &[u8]) -> Result<()> {
+        match &mut self.out {
+            Some(out) => out.write_chunk(buf),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Start buffering output to stdout from the current thread as the output of chunk `seq`,
+/// submitting the output of the previous chunk (if any) to be written.
+///
+/// Chunk producers call this when they hand a chunk of input to a worker; sequence numbers count
+/// up from 0 in input order.
+pub(crate) fn start_chunk(seq: u64) -> Result<()> {
+    let (seqr, prev) = CUR_CHUNK.with(|c| {
+        let mut c = c.borrow_mut();
+        match c.sequencer.clone() {
+            Some(seqr) => (Some(seqr), c.cur.replace((seq, Vec::new()))),
+            None => (None, None),
+        }
+    });
+    match (seqr, prev) {
+        (Some(seqr), Some((prev_seq, buf))) => seqr.lock().unwrap().submit(prev_seq, buf),
+        _ => Ok(()),
+    }
+}
+
+/// Submit the output of the current thread's chunk, if any. Chunk producers call this when they
+/// run out of input.
+pub(crate) fn finish_chunk() -> Result<()> {
+    let (seqr, prev) = CUR_CHUNK.with(|c| {
+        let mut c = c.borrow_mut();
+        (c.sequencer.clone(), c.cur.take())
+    });
+    match (seqr, prev) {
+        (Some(seqr), Some((seq, buf))) => seqr.lock().unwrap().submit(seq, buf),
+        _ => Ok(()),
+    }
+}
+
+/// Write out all remaining chunk output, in order. This is called on the main thread once all
+/// workers have finished; chunks that were never submitted (e.g. because a worker exited early)
+/// are skipped.
+pub(crate) fn flush_ordered() -> Result<()> {
+    finish_chunk()?;
+    let seqr = match CUR_CHUNK.with(|c| c.borrow().sequencer.clone()) {
+        Some(seqr) => seqr,
+        None => return Ok(()),
+    };
+    let mut seqr = seqr.lock().unwrap();
+    let mut rest: Vec<(u64, Vec<u8>)> = seqr.pending.drain().collect();
+    rest.sort_by_key(|(seq, _)| *seq);
+    for (seq, buf) in rest {
+        seqr.next = seq + 1;
+        seqr.emit(&buf)?;
+    }
+    Ok(())
+}
+
+// Implementation of --serve (see `Config::serve`). Programs run through the `embed` API use the
+// same mechanism to collect their output.
+
+thread_local! {
+    static CLIENT: RefCell<Option<io::BufWriter<Box<dyn Write>>>> = RefCell::new(None);
+}
+
+// Write `ss` to the current client, if there is one. A client that goes away early just misses
+// the rest of its output; that is not an error for the server.
+fn send_to_client<'a>(ss: &[&Str<'a>]) -> bool {
+    CLIENT.with(|c| match c.borrow_mut().as_mut() {
+        Some(client) => {
+            for s in ss.iter() {
+                let _ = client.write_all(unsafe { &*s.get_bytes() });
+            }
+            true
+        }
+        None => false,
+    })
+}
+
+/// Send output to standard output from this thread to `client`, until the next call. The output
+/// for the previous client, if any, is flushed before its connection is dropped. Output written
+/// with no client set goes to standard output as usual.
+pub(crate) fn set_client(client: Option<Box<dyn Write>>) {
+    let old = CLIENT.with(|c| c.replace(client.map(io::BufWriter::new)));
+    if let Some(mut old) = old {
+        let _ = old.flush();
+    }
+}
+
+/// Send any buffered output to the current client. Readers call this before waiting for more
+/// input from the client.
+pub(crate) fn flush_client() {
+    CLIENT.with(|c| {
+        if let Some(client) = c.borrow_mut().as_mut() {
+            let _ = client.flush();
+        }
+    })
+}
+
 // Implementation of the "server" thread issuing the writes.
 
 #[derive(Default)]
@@ -932,7 +1189,7 @@ mod tests {
         let s1 = Str::from("hello");
         let s2 = Str::from(" there");
         let fs = FakeFs::default();
-        let mut reg = Registry::from_factory(fs.clone());
+        let mut reg = Registry::from_factory(fs.clone(), &Config::default());
         {
             let handle = reg
                 .get_handle(/*stdout*/ None, FileSpec::default())
@@ -955,7 +1212,7 @@ mod tests {
         let s1 = Str::from("hello");
         let s2 = Str::from(" there");
         let fs = FakeFs::default();
-        let mut reg = Registry::from_factory(fs.clone());
+        let mut reg = Registry::from_factory(fs.clone(), &Config::default());
         {
             let handle = reg.get_handle(Some(&fname), FileSpec::default()).unwrap();
             handle.write(&s1, FileSpec::Append).unwrap();
@@ -985,7 +1242,7 @@ mod tests {
             .set_poison(true);
         let mut threads = Vec::with_capacity(N_THREADS);
         {
-            let reg = Registry::from_factory(fs.clone());
+            let reg = Registry::from_factory(fs.clone(), &Config::default());
             for t in 0..N_THREADS {
                 let mut treg = reg.clone();
                 threads.push(std::thread::spawn(move || {
@@ -0,0 +1,737 @@
+//! A small, lenient scanner for XML and HTML documents.
+//!
+//! This backs the `--xml-record` input mode, which splits a document into one record per element
+//! matching a CSS-style selector, and the `getxml` builtin, which extracts an attribute or the text
+//! of an element from a fragment of markup.
+//!
+//! This is not a conforming parser for either format. Documents are tokenized into tags and text,
+//! and unbalanced markup is handled the way most HTML looks in practice: a closing tag closes any
+//! elements opened after its matching opening tag, closing tags with no match are ignored, and
+//! HTML void elements like `<br>` or `<img>` are treated as empty unless they are immediately
+//! closed (as `<link>` is in RSS feeds). Entities are only decoded in text and attribute values
+//! that are returned by `getxml`; records hold the raw markup.
+use memchr::{memchr, memmem};
+
+use crate::common::Result;
+use crate::runtime::Str;
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+// The contents of these elements are never markup.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+// Elements whose end tags may be omitted in HTML, as in `<li>one<li>two`.
+const OPTIONAL_END_ELEMENTS: &[&str] = &["dd", "dt", "li", "option", "p", "td", "th", "tr"];
+
+fn eq_ignore_case(x: &[u8], y: &[u8]) -> bool {
+    x.eq_ignore_ascii_case(y)
+}
+
+fn is_one_of(name: &[u8], elements: &[&str]) -> bool {
+    elements.iter().any(|e| eq_ignore_case(e.as_bytes(), name))
+}
+
+// Opening an element with an optional end tag closes an open element of the same kind, provided
+// only elements with optional end tags were opened after it. Given the names of the open
+// elements, innermost first, returns how many of them are closed when `name` is opened.
+fn implicitly_closed<'a>(open: impl Iterator<Item = &'a [u8]>, name: &[u8]) -> Option<usize> {
+    if !is_one_of(name, OPTIONAL_END_ELEMENTS) {
+        return None;
+    }
+    for (i, elt) in open.enumerate() {
+        if eq_ignore_case(elt, name) {
+            return Some(i + 1);
+        }
+        if !is_one_of(elt, OPTIONAL_END_ELEMENTS) {
+            return None;
+        }
+    }
+    None
+}
+
+fn is_name_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_' || b == b':'
+}
+
+fn is_name_end(b: u8) -> bool {
+    b.is_ascii_whitespace() || b == b'/' || b == b'>'
+}
+
+enum Token<'a> {
+    Open {
+        name: &'a [u8],
+        attrs: &'a [u8],
+        self_closing: bool,
+    },
+    Close(&'a [u8]),
+    // Text, before entities are decoded.
+    Text(&'a [u8]),
+    // The contents of a CDATA section.
+    CData(&'a [u8]),
+    // Comments, doctype declarations and processing instructions.
+    Other,
+}
+
+#[derive(Clone)]
+struct Tokens<'a> {
+    bs: &'a [u8],
+    pos: usize,
+    // Set after the opening tag of a raw text element.
+    raw_text: Option<&'a [u8]>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(bs: &'a [u8]) -> Tokens<'a> {
+        Tokens {
+            bs,
+            pos: 0,
+            raw_text: None,
+        }
+    }
+
+    // The offset of `pat` at or after `from`, or the end of the input.
+    fn find_from(&self, from: usize, pat: &[u8]) -> (usize, bool) {
+        let from = std::cmp::min(from, self.bs.len());
+        match memmem::find(&self.bs[from..], pat) {
+            Some(ix) => (from + ix, true),
+            None => (self.bs.len(), false),
+        }
+    }
+
+    // The offset just past the '>' ending the tag starting at `pos`, skipping over quoted
+    // attribute values.
+    fn tag_end(&self, pos: usize) -> usize {
+        let mut quote = None;
+        for (i, b) in self.bs[pos..].iter().cloned().enumerate() {
+            match quote {
+                Some(q) if b == q => quote = None,
+                Some(_) => {}
+                None if b == b'"' || b == b'\'' => quote = Some(b),
+                None if b == b'>' => return pos + i + 1,
+                None => {}
+            }
+        }
+        self.bs.len()
+    }
+
+    // Whether the element whose opening tag was just read has no contents. Void elements are
+    // empty unless the next tag closes them.
+    fn is_empty_element(&self, name: &[u8], self_closing: bool) -> bool {
+        if self_closing {
+            return true;
+        }
+        if !is_one_of(name, VOID_ELEMENTS) {
+            return false;
+        }
+        let mut rest = self.clone();
+        loop {
+            match rest.next() {
+                Some((_, Token::Text(_))) => continue,
+                Some((_, Token::Close(close))) => return !eq_ignore_case(name, close),
+                _ => return true,
+            }
+        }
+    }
+
+    fn text(&mut self, start: usize, from: usize) -> (usize, Token<'a>) {
+        let end = memchr(b'<', &self.bs[from..]).map_or(self.bs.len(), |ix| from + ix);
+        self.pos = end;
+        (start, Token::Text(&self.bs[start..end]))
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    // The offset of the start of each token, along with the token. Tokens end where the next one
+    // starts.
+    type Item = (usize, Token<'a>);
+    fn next(&mut self) -> Option<(usize, Token<'a>)> {
+        let start = self.pos;
+        let bs = self.bs;
+        if start >= bs.len() {
+            return None;
+        }
+        if let Some(name) = self.raw_text.take() {
+            // Scan for the matching closing tag, ignoring case.
+            let mut from = start;
+            loop {
+                let (ix, found) = self.find_from(from, b"</");
+                let tag = &bs[ix..];
+                if !found
+                    || (tag.len() >= name.len() + 2
+                        && eq_ignore_case(&tag[2..name.len() + 2], name))
+                {
+                    self.pos = ix;
+                    return Some((start, Token::Text(&bs[start..ix])));
+                }
+                from = ix + 2;
+            }
+        }
+        let rest = &bs[start..];
+        if rest[0] != b'<' {
+            return Some(self.text(start, start));
+        }
+        if rest.starts_with(b"<!--") {
+            let (ix, found) = self.find_from(start + 4, b"-->");
+            self.pos = if found { ix + 3 } else { ix };
+            return Some((start, Token::Other));
+        }
+        if rest.starts_with(b"<![CDATA[") {
+            let (ix, found) = self.find_from(start + 9, b"]]>");
+            self.pos = if found { ix + 3 } else { ix };
+            return Some((start, Token::CData(&bs[start + 9..ix])));
+        }
+        if rest.starts_with(b"<!") || rest.starts_with(b"<?") {
+            self.pos = self.tag_end(start);
+            return Some((start, Token::Other));
+        }
+        if rest.starts_with(b"</") {
+            let name_end = rest[2..]
+                .iter()
+                .position(|b| is_name_end(*b))
+                .map_or(bs.len(), |ix| start + 2 + ix);
+            self.pos = self.tag_end(start);
+            return Some((start, Token::Close(&bs[start + 2..name_end])));
+        }
+        if rest.len() < 2 || !is_name_start(rest[1]) {
+            // A '<' that does not start a tag, as in "a < b".
+            return Some(self.text(start, start + 1));
+        }
+        let name_end = rest[1..]
+            .iter()
+            .position(|b| is_name_end(*b))
+            .map_or(bs.len(), |ix| start + 1 + ix);
+        let end = self.tag_end(start);
+        let mut attrs_end = if bs[end - 1] == b'>' { end - 1 } else { end };
+        let self_closing = attrs_end > name_end && bs[attrs_end - 1] == b'/';
+        if self_closing {
+            attrs_end -= 1;
+        }
+        let name = &bs[start + 1..name_end];
+        self.pos = end;
+        if !self_closing && is_one_of(name, RAW_TEXT_ELEMENTS) {
+            self.raw_text = Some(name);
+        }
+        Some((
+            start,
+            Token::Open {
+                name,
+                attrs: &bs[name_end..std::cmp::max(name_end, attrs_end)],
+                self_closing,
+            },
+        ))
+    }
+}
+
+/// Iterate over the attributes in the body of a tag, yielding their names and raw values.
+/// Attributes without a value (e.g. `disabled`) have an empty value.
+fn attrs<'a>(mut bs: &'a [u8]) -> impl Iterator<Item = (&'a [u8], &'a [u8])> {
+    fn trim_start(bs: &[u8]) -> &[u8] {
+        let ix = bs
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(bs.len());
+        &bs[ix..]
+    }
+    std::iter::from_fn(move || {
+        bs = trim_start(bs);
+        // Skip stray slashes and equals signs.
+        while let Some(b'/' | b'=') = bs.first() {
+            bs = trim_start(&bs[1..]);
+        }
+        if bs.is_empty() {
+            return None;
+        }
+        let name_end = bs
+            .iter()
+            .position(|b| b.is_ascii_whitespace() || *b == b'=' || *b == b'/')
+            .unwrap_or(bs.len());
+        let name = &bs[..name_end];
+        bs = trim_start(&bs[name_end..]);
+        if bs.first() != Some(&b'=') {
+            return Some((name, &bs[..0]));
+        }
+        bs = trim_start(&bs[1..]);
+        let value = match bs.first() {
+            Some(q @ (b'"' | b'\'')) => {
+                let end = memchr(*q, &bs[1..]).map_or(bs.len(), |ix| ix + 1);
+                let value = &bs[1..end];
+                bs = &bs[std::cmp::min(end + 1, bs.len())..];
+                value
+            }
+            _ => {
+                let end = bs
+                    .iter()
+                    .position(|b| b.is_ascii_whitespace())
+                    .unwrap_or(bs.len());
+                let value = &bs[..end];
+                bs = &bs[end..];
+                value
+            }
+        };
+        Some((name, value))
+    })
+}
+
+fn attr<'a>(attrs_bs: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    attrs(attrs_bs)
+        .find(|(n, _)| eq_ignore_case(n, name))
+        .map(|(_, v)| v)
+}
+
+/// Append `bs` to `out`, decoding character and predefined entity references.
+fn decode(mut bs: &[u8], out: &mut Vec<u8>) {
+    while let Some(ix) = memchr(b'&', bs) {
+        out.extend_from_slice(&bs[..ix]);
+        bs = &bs[ix..];
+        let semi = match bs.iter().take(12).position(|b| *b == b';') {
+            Some(semi) => semi,
+            None => {
+                out.push(b'&');
+                bs = &bs[1..];
+                continue;
+            }
+        };
+        let entity = &bs[1..semi];
+        let c = match entity {
+            b"lt" => Some('<'),
+            b"gt" => Some('>'),
+            b"amp" => Some('&'),
+            b"quot" => Some('"'),
+            b"apos" => Some('\''),
+            b"nbsp" => Some('\u{a0}'),
+            _ => {
+                let code = match entity {
+                    [b'#', b'x' | b'X', hex @ ..] => std::str::from_utf8(hex)
+                        .ok()
+                        .and_then(|s| u32::from_str_radix(s, 16).ok()),
+                    [b'#', dec @ ..] => std::str::from_utf8(dec).ok().and_then(|s| s.parse().ok()),
+                    _ => None,
+                };
+                code.and_then(std::char::from_u32)
+            }
+        };
+        match c {
+            Some(c) => {
+                let mut buf = [0u8; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                bs = &bs[semi + 1..];
+            }
+            None => {
+                out.push(b'&');
+                bs = &bs[1..];
+            }
+        }
+    }
+    out.extend_from_slice(bs);
+}
+
+/// A compound selector, like `a.external[href]`, matching a single element.
+#[derive(Clone, Default, Debug)]
+struct Compound {
+    tag: Option<Vec<u8>>,
+    id: Option<Vec<u8>>,
+    classes: Vec<Vec<u8>>,
+    attrs: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl Compound {
+    fn parse(s: &str) -> Result<Compound> {
+        fn ident(s: &str) -> (&str, &str) {
+            let end = s.find(|c| ".#[]=".contains(c)).unwrap_or(s.len());
+            s.split_at(end)
+        }
+        let mut res = Compound::default();
+        let (tag, mut rest) = ident(s);
+        if !tag.is_empty() && tag != "*" {
+            res.tag = Some(tag.as_bytes().to_vec());
+        }
+        while !rest.is_empty() {
+            let (kind, body) = rest.split_at(1);
+            let (name, after) = ident(body);
+            if name.is_empty() {
+                return err!("invalid selector {:?}", s);
+            }
+            rest = after;
+            match kind {
+                "." => res.classes.push(name.as_bytes().to_vec()),
+                "#" => res.id = Some(name.as_bytes().to_vec()),
+                "[" => {
+                    let value = if let Some(after) = rest.strip_prefix('=') {
+                        let end = match after.find(']') {
+                            Some(end) => end,
+                            None => return err!("unterminated attribute in selector {:?}", s),
+                        };
+                        let value = after[..end].trim_matches(|c| c == '"' || c == '\'');
+                        rest = &after[end..];
+                        Some(value.as_bytes().to_vec())
+                    } else {
+                        None
+                    };
+                    rest = match rest.strip_prefix(']') {
+                        Some(rest) => rest,
+                        None => return err!("unterminated attribute in selector {:?}", s),
+                    };
+                    res.attrs.push((name.as_bytes().to_vec(), value));
+                }
+                _ => return err!("invalid selector {:?}", s),
+            }
+        }
+        Ok(res)
+    }
+
+    fn matches(&self, name: &[u8], attrs_bs: &[u8]) -> bool {
+        if let Some(tag) = &self.tag {
+            if !eq_ignore_case(tag, name) {
+                return false;
+            }
+        }
+        if let Some(id) = &self.id {
+            if attr(attrs_bs, b"id") != Some(&id[..]) {
+                return false;
+            }
+        }
+        if !self.classes.is_empty() {
+            let classes = attr(attrs_bs, b"class").unwrap_or_default();
+            let present = classes
+                .split(|b| b.is_ascii_whitespace())
+                .filter(|c| !c.is_empty());
+            if !self
+                .classes
+                .iter()
+                .all(|c| present.clone().any(|p| p == &c[..]))
+            {
+                return false;
+            }
+        }
+        self.attrs
+            .iter()
+            .all(|(name, value)| match (attr(attrs_bs, name), value) {
+                (None, _) => false,
+                (Some(_), None) => true,
+                (Some(v), Some(value)) => v == &value[..],
+            })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+/// A selector for the elements to read as records: compound selectors like `item` or
+/// `div.result[data-id]`, separated by descendant (` `) or child (`>`) combinators.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    // The combinator of the first step is ignored.
+    steps: Vec<(Combinator, Compound)>,
+}
+
+// An open element.
+struct Elt<'a> {
+    name: &'a [u8],
+    attrs: &'a [u8],
+}
+
+impl Selector {
+    pub fn parse(s: &str) -> Result<Selector> {
+        let mut steps = Vec::new();
+        let mut comb = Combinator::Descendant;
+        for part in s.replace('>', " > ").split_whitespace() {
+            if part == ">" {
+                if steps.is_empty() || comb == Combinator::Child {
+                    return err!("invalid selector {:?}", s);
+                }
+                comb = Combinator::Child;
+                continue;
+            }
+            steps.push((comb, Compound::parse(part)?));
+            comb = Combinator::Descendant;
+        }
+        if steps.is_empty() || comb == Combinator::Child {
+            return err!("invalid selector {:?}", s);
+        }
+        Ok(Selector { steps })
+    }
+
+    // Whether the element at the top of `stack` matches.
+    fn matches(&self, stack: &[Elt]) -> bool {
+        fn matches_at(steps: &[(Combinator, Compound)], stack: &[Elt]) -> bool {
+            let ((comb, last), steps) = match steps.split_last() {
+                Some(x) => x,
+                None => return true,
+            };
+            let (elt, stack) = match stack.split_last() {
+                Some(x) => x,
+                None => return false,
+            };
+            if !last.matches(elt.name, elt.attrs) {
+                return false;
+            }
+            if steps.is_empty() {
+                return true;
+            }
+            match comb {
+                Combinator::Child => matches_at(steps, stack),
+                Combinator::Descendant => (1..=stack.len())
+                    .rev()
+                    .any(|i| matches_at(steps, &stack[..i])),
+            }
+        }
+        matches_at(&self.steps[..], stack)
+    }
+
+    /// The byte ranges of the elements in `doc` matching this selector, including their start and
+    /// end tags. Matching elements nested inside of another match are part of the outer record.
+    pub fn records(&self, doc: &[u8]) -> Vec<(usize, usize)> {
+        // Close the open elements from index `ix` onwards. If the record being read is one of
+        // them, it ends at `end`.
+        fn close(
+            stack: &mut Vec<Elt>,
+            ix: usize,
+            end: usize,
+            open: &mut Option<(usize, usize)>,
+            res: &mut Vec<(usize, usize)>,
+        ) {
+            if let Some((depth, start)) = *open {
+                if depth > ix {
+                    res.push((start, end));
+                    *open = None;
+                }
+            }
+            stack.truncate(ix);
+        }
+        let mut res = Vec::new();
+        let mut stack: Vec<Elt> = Vec::new();
+        // The depth and start of the record being read.
+        let mut open: Option<(usize, usize)> = None;
+        let mut tokens = Tokens::new(doc);
+        while let Some((start, tok)) = tokens.next() {
+            match tok {
+                Token::Open {
+                    name,
+                    attrs,
+                    self_closing,
+                } => {
+                    if let Some(n) = implicitly_closed(stack.iter().rev().map(|e| e.name), name) {
+                        let ix = stack.len() - n;
+                        close(&mut stack, ix, start, &mut open, &mut res);
+                    }
+                    let empty = tokens.is_empty_element(name, self_closing);
+                    stack.push(Elt { name, attrs });
+                    if open.is_none() && self.matches(&stack[..]) {
+                        if empty {
+                            res.push((start, tokens.pos));
+                        } else {
+                            open = Some((stack.len(), start));
+                        }
+                    }
+                    if empty {
+                        stack.pop();
+                    }
+                }
+                Token::Close(name) => {
+                    let ix = match stack.iter().rposition(|e| eq_ignore_case(e.name, name)) {
+                        Some(ix) => ix,
+                        None => continue,
+                    };
+                    // If the record is closed implicitly, it ends before this tag.
+                    let end = match open {
+                        Some((depth, _)) if depth == ix + 1 => tokens.pos,
+                        _ => start,
+                    };
+                    close(&mut stack, ix, end, &mut open, &mut res);
+                }
+                Token::Text(_) | Token::CData(_) | Token::Other => {}
+            }
+        }
+        if let Some((_, rstart)) = open {
+            res.push((rstart, doc.len()));
+        }
+        res
+    }
+}
+
+/// Query a fragment of markup, returning the empty string if nothing matches.
+///
+/// `path` is a sequence of compound selectors separated by `/`, each selecting the first matching
+/// child of the element selected so far, starting from the first element in `doc`. If the last
+/// step is of the form `@name`, the value of the `name` attribute of the selected element is
+/// returned; otherwise the result is the element's text, with surrounding whitespace removed.
+pub(crate) fn get(doc: &Str, path: &Str) -> Result<Str<'static>> {
+    let path = path.with_bytes(|bs| String::from_utf8_lossy(bs).into_owned());
+    let mut steps: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let attr_name = match steps.last() {
+        Some(last) if last.starts_with('@') => {
+            let name = &last[1..];
+            steps.pop();
+            Some(name)
+        }
+        _ => None,
+    };
+    let steps = steps
+        .into_iter()
+        .filter(|s| *s != ".")
+        .map(Compound::parse)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(doc.with_bytes(|bs| {
+        let mut out = Vec::new();
+        query(bs, &steps[..], attr_name.map(str::as_bytes), &mut out);
+        Str::from(&out[..]).unmoor()
+    }))
+}
+
+fn query(doc: &[u8], steps: &[Compound], attr_name: Option<&[u8]>, out: &mut Vec<u8>) {
+    // For each open element, the number of steps matched by it and its ancestors, if they all
+    // matched.
+    let mut stack: Vec<(&[u8], Option<usize>)> = Vec::new();
+    let mut seen_root = false;
+    // The depth of the selected element, once its text is being read.
+    let mut selected: Option<usize> = None;
+    let mut tokens = Tokens::new(doc);
+    while let Some((_, tok)) = tokens.next() {
+        match tok {
+            Token::Open {
+                name,
+                attrs,
+                self_closing,
+            } => {
+                if let Some(n) = implicitly_closed(stack.iter().rev().map(|(n, _)| *n), name) {
+                    let ix = stack.len() - n;
+                    stack.truncate(ix);
+                    if matches!(selected, Some(depth) if depth >= ix) {
+                        break;
+                    }
+                }
+                let matched = match stack.last() {
+                    None if seen_root => break,
+                    None => {
+                        seen_root = true;
+                        Some(0)
+                    }
+                    Some((_, Some(k))) if *k < steps.len() && steps[*k].matches(name, attrs) => {
+                        Some(k + 1)
+                    }
+                    Some(_) => None,
+                };
+                let empty = tokens.is_empty_element(name, self_closing);
+                if selected.is_none() && matched == Some(steps.len()) {
+                    if let Some(attr_name) = attr_name {
+                        if let Some(v) = attr(attrs, attr_name) {
+                            decode(v, out);
+                        }
+                        return;
+                    }
+                    if empty {
+                        return;
+                    }
+                    selected = Some(stack.len());
+                }
+                if !empty {
+                    stack.push((name, matched));
+                }
+            }
+            Token::Close(name) => {
+                if let Some(ix) = stack.iter().rposition(|(n, _)| eq_ignore_case(n, name)) {
+                    stack.truncate(ix);
+                    if matches!(selected, Some(depth) if depth >= ix) {
+                        break;
+                    }
+                }
+            }
+            Token::Text(bs) if selected.is_some() => decode(bs, out),
+            Token::CData(bs) if selected.is_some() => out.extend_from_slice(bs),
+            Token::Text(_) | Token::CData(_) | Token::Other => {}
+        }
+    }
+    if selected.is_none() {
+        out.clear();
+        return;
+    }
+    let start = out
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(out.len());
+    let end = out
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |ix| ix + 1);
+    out.truncate(end);
+    out.drain(..start);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records<'a>(sel: &str, doc: &'a str) -> Vec<&'a str> {
+        Selector::parse(sel)
+            .unwrap()
+            .records(doc.as_bytes())
+            .into_iter()
+            .map(|(start, end)| &doc[start..end])
+            .collect()
+    }
+
+    fn get_str(doc: &str, path: &str) -> String {
+        get(&Str::from(doc), &Str::from(path)).unwrap().to_string()
+    }
+
+    #[test]
+    fn select_records() {
+        let rss = r#"<?xml version="1.0"?>
+<rss><channel><title>t</title>
+<item><title>A &amp; B</title><link>http://a</link></item>
+<item><title><![CDATA[<C>]]></title><link>http://c</link></item>
+</channel></rss>"#;
+        assert_eq!(
+            records("item", rss),
+            vec![
+                "<item><title>A &amp; B</title><link>http://a</link></item>",
+                "<item><title><![CDATA[<C>]]></title><link>http://c</link></item>",
+            ]
+        );
+        assert_eq!(records("channel > title", rss), vec!["<title>t</title>"]);
+        let html = r#"<!DOCTYPE html><body><div class="row x" id=r1><img src=a.png><p>one<br>two</div>
+<div class="row"><a href='/b'>b</a></div><script>if (a < b) { "<div class=row>" }</script>"#;
+        assert_eq!(
+            records("div.row", html),
+            vec![
+                r#"<div class="row x" id=r1><img src=a.png><p>one<br>two</div>"#,
+                r#"<div class="row"><a href='/b'>b</a></div>"#,
+            ]
+        );
+        assert_eq!(records("#r1 img", html), vec!["<img src=a.png>"]);
+        assert_eq!(records("body > a[href]", html), Vec::<&str>::new());
+        assert_eq!(
+            records("div a[href='/b']", html),
+            vec!["<a href='/b'>b</a>"]
+        );
+        let list = "<ul class=menu><li>a<li>b<ul><li>c<li>d</ul><li>e</ul>";
+        assert_eq!(
+            records("ul.menu > li", list),
+            vec!["<li>a", "<li>b<ul><li>c<li>d</ul>", "<li>e"]
+        );
+        assert!(Selector::parse("> a").is_err());
+        assert!(Selector::parse("a[href").is_err());
+    }
+
+    #[test]
+    fn get_values() {
+        let item = r#"<item id="7"><title>A &amp; B &#x263A;</title><link>http://a</link>
+<img src=x.png><p class=note>  hi <b>there</b> </p></item>"#;
+        assert_eq!(get_str(item, "@id"), "7");
+        assert_eq!(get_str(item, "title"), "A & B \u{263A}");
+        assert_eq!(get_str(item, "link"), "http://a");
+        assert_eq!(get_str(item, "img/@src"), "x.png");
+        assert_eq!(get_str(item, "p.note"), "hi there");
+        assert_eq!(get_str(item, "p/b"), "there");
+        assert_eq!(get_str(item, "b"), "");
+        assert_eq!(get_str(item, "title/@missing"), "");
+        assert_eq!(get_str("<a>x<b>y</b>z</a>", ""), "xyz");
+        assert_eq!(get_str("<tr><td>1<td>2</tr>", "td"), "1");
+    }
+}
@@ -198,6 +198,15 @@
 //! function and build a subgraph corresponding to it. This is a bit wasteful, as it leads to
 //! duplicate functions for a single callsite; we can probably improve something on that front.
 //!
+//! ## Annotations
+//!
+//! Variables, function parameters and return values can be given a type in the program text
+//! (see [crate::ast::TypeAnn]). Nodes for annotated variables get an extra `Flows` edge from a
+//! constant node holding the declared type. That lets the declared type fill in anything we would
+//! otherwise have to guess, but it does not stop other information from arriving: if the node
+//! winds up with a type other than the declared one, we report an error once we have solved the
+//! constraints.
+//!
 //! # Solving Constraints
 //!
 //! Once we have this graph, can push values of type [`State`] around according to the constraints
@@ -275,6 +284,7 @@
 //! [static analysis algorithms]: https://cs.au.dk/~amoeller/spa/
 //! [Hindley-Milner]: https://en.wikipedia.org/wiki/Hindley%E2%80%93Milner_type_system
 //! [`State`]: [crate::types::State]
+use crate::ast::{ScalarAnn, TypeAnn};
 use crate::builtins;
 use crate::cfg::{self, Function, Ident, ProgramContext};
 use crate::common::{self, FileSpec, NodeIx, NumTy, Result};
@@ -291,6 +301,9 @@ pub(crate) enum BaseTy {
     Int,
     Float,
     Str,
+    // The type of `@/re/` constants. Combining a regex with any other type yields a string, and
+    // regexes are stored as strings in maps.
+    Regex,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
@@ -368,6 +381,14 @@ impl<T> Constraint<T> {
     }
 }
 
+// Maps and iterators hold regexes as strings.
+fn stored(b: Option<BaseTy>) -> Option<BaseTy> {
+    match b {
+        Some(BaseTy::Regex) => Some(BaseTy::Str),
+        b => b,
+    }
+}
+
 impl Constraint<State> {
     fn eval<'a, 'b>(&self, tc: &mut TypeContext<'a, 'b>) -> Result<State> {
         match self {
@@ -375,7 +396,10 @@ impl Constraint<State> {
                 key: None,
                 val: None,
             })),
-            Constraint::KeyIn(Some(TVar::Scalar(k))) => Ok(Some(TVar::Map { key: *k, val: None })),
+            Constraint::KeyIn(Some(TVar::Scalar(k))) => Ok(Some(TVar::Map {
+                key: stored(*k),
+                val: None,
+            })),
             Constraint::KeyIn(op) => err!("Non-scalar KeyIn constraint: {:?}", op),
 
             Constraint::Key(None) => Ok(None),
@@ -388,7 +412,10 @@ impl Constraint<State> {
                 key: None,
                 val: None,
             })),
-            Constraint::ValIn(Some(TVar::Scalar(v))) => Ok(Some(TVar::Map { key: None, val: *v })),
+            Constraint::ValIn(Some(TVar::Scalar(v))) => Ok(Some(TVar::Map {
+                key: None,
+                val: stored(*v),
+            })),
             Constraint::ValIn(op) => err!("Non-scalar ValIn constraint: {:?}", op),
 
             Constraint::Val(None) => Ok(None),
@@ -398,7 +425,7 @@ impl Constraint<State> {
             }
 
             Constraint::IterValIn(None) => Ok(Some(TVar::Iter(None))),
-            Constraint::IterValIn(Some(TVar::Scalar(v))) => Ok(Some(TVar::Iter(*v))),
+            Constraint::IterValIn(Some(TVar::Scalar(v))) => Ok(Some(TVar::Iter(stored(*v)))),
             Constraint::IterValIn(op) => err!("Non-scalar IterValIn constraint: {:?}", op),
 
             Constraint::IterVal(None) => Ok(None),
@@ -411,7 +438,7 @@ impl Constraint<State> {
             Constraint::Flows(s) => Ok(*s),
             Constraint::CallBuiltin(args, f) => {
                 let arg_state: SmallVec<State> = args.iter().map(|ix| *tc.nw.read(*ix)).collect();
-                f.step(&arg_state[..])
+                f.step(&arg_state[..], tc.ieee_div)
             }
             Constraint::CallUDF(nix, args, f) => {
                 let ret_ix = tc.get_function(&tc.func_table[*f as usize], args.clone(), *nix);
@@ -453,7 +480,8 @@ impl Rule {
             use BaseTy::*;
             match (b1, b2) {
                 (Null, x) | (x, Null) => x,
-                (Str, _) | (_, Str) => Str,
+                (Regex, Regex) => Regex,
+                (Str, _) | (_, Str) | (Regex, _) | (_, Regex) => Str,
                 (Float, _) | (_, Float) => Float,
                 (Int, Int) => Int,
             }
@@ -488,6 +516,8 @@ impl Rule {
                                 | (_, Float)
                                 | (Str, _)
                                 | (_, Str)
+                                | (Regex, _)
+                                | (_, Regex)
                                 | (Null, _)
                                 | (_, Null) => Str,
                                 (Int, _) => Int,
@@ -517,6 +547,43 @@ impl Rule {
     }
 }
 
+fn declared_state(ty: TypeAnn) -> State {
+    fn base(s: ScalarAnn) -> BaseTy {
+        match s {
+            ScalarAnn::Int => BaseTy::Int,
+            ScalarAnn::Float => BaseTy::Float,
+            ScalarAnn::Str => BaseTy::Str,
+        }
+    }
+    match ty {
+        TypeAnn::Scalar(s) => TVar::Scalar(base(s)).abs(),
+        TypeAnn::Map { key, val } => TVar::Map {
+            key: base(key),
+            val: base(val),
+        }
+        .abs(),
+    }
+}
+
+// How `ty` is written in a type annotation, for error messages.
+fn annotation_name(ty: compile::Ty) -> &'static str {
+    use compile::Ty::*;
+    match ty {
+        Int => "int",
+        Float => "float",
+        Str => "str",
+        Regex => "a regex",
+        MapIntInt => "map[int]int",
+        MapIntFloat => "map[int]float",
+        MapIntStr => "map[int]str",
+        MapStrInt => "map[str]int",
+        MapStrFloat => "map[str]float",
+        MapStrStr => "map[str]str",
+        IterInt | IterStr => "an iterator",
+        Null => "null",
+    }
+}
+
 fn concrete(state: State) -> TVar<BaseTy> {
     fn concrete_scalar(o: &Option<BaseTy>) -> BaseTy {
         o.unwrap_or(BaseTy::Null)
@@ -535,17 +602,18 @@ fn flatten(tv: TVar<BaseTy>) -> Result<compile::Ty> {
             Int => Ty::Int,
             Float => Ty::Float,
             Str => Ty::Str,
+            Regex => Ty::Regex,
             Null => Ty::Null,
         }
     }
     match tv {
         Scalar(b) => Ok(flatten_base(b)),
         Iter(Int) => Ok(Ty::IterInt),
-        Iter(Null) | Iter(Str) => Ok(Ty::IterStr),
+        Iter(Null) | Iter(Str) | Iter(Regex) => Ok(Ty::IterStr),
         Iter(x) => err!("Iterator over an unsupported type: {:?}", x),
         Map { key, val } => {
             let f = |ty| {
-                if ty == Null {
+                if ty == Null || ty == Regex {
                     Ty::Str
                 } else {
                     flatten_base(ty)
@@ -642,6 +710,14 @@ pub(crate) struct TypeContext<'a, 'b> {
     func_table: &'a [Function<'b, &'b str>],
     local_globals: &'a HashSet<NumTy>,
     udf_nodes: Vec<NodeIx>,
+    // Declared types of variables, keyed by the `low` field of their identifiers.
+    var_types: &'a HashMap<NumTy, (&'b str, TypeAnn)>,
+    // Nodes with a declared type, along with a description of them for error messages.
+    declared: HashMap<NodeIx, (String, TypeAnn)>,
+    // See `ProgramContext::ieee_div`.
+    ieee_div: bool,
+    // See `runtime::Config::strict`.
+    strict: bool,
 }
 
 struct View<'a, 'b, 'c> {
@@ -692,6 +768,10 @@ impl<'b, 'c> TypeContext<'b, 'c> {
             func_table: &pc.funcs[..],
             local_globals: pc.local_globals_ref(),
             udf_nodes: Default::default(),
+            var_types: &pc.var_types,
+            declared: Default::default(),
+            ieee_div: pc.ieee_div,
+            strict: pc.config.strict,
         };
         tc.udf_nodes = (0..pc.funcs.len())
             .map(|_| tc.nw.add_rule(Rule::AlwaysNotify))
@@ -709,6 +789,7 @@ impl<'b, 'c> TypeContext<'b, 'c> {
         }
 
         tc.solve()?;
+        tc.check_declared()?;
         let mut var_tys = HashMap::new();
         let mut func_tys = HashMap::new();
         for (Args { id, args, .. }, ix) in tc.funcs.iter() {
@@ -796,7 +877,22 @@ impl<'b, 'c> TypeContext<'b, 'c> {
                 }
             }
             // Compute an update value based on the newly-evaluated constraints.
-            let (changed, next) = rule.step(&cur_val, &deps[..])?;
+            let (changed, next) = match rule.step(&cur_val, &deps[..]) {
+                Ok(res) => res,
+                Err(e) => {
+                    // The only way a node with a declared type can fail to update is if it is
+                    // used as a map when declared as a scalar, or vice versa.
+                    return match self.declared.get(&ix) {
+                        Some((what, ty @ TypeAnn::Scalar(_))) => {
+                            user_err!("{} is declared as {}, but is used as a map", what, ty)
+                        }
+                        Some((what, ty)) => {
+                            user_err!("{} is declared as {}, but is used as a scalar", what, ty)
+                        }
+                        None => Err(e),
+                    };
+                }
+            };
             if !changed {
                 continue;
             }
@@ -832,6 +928,33 @@ impl<'b, 'c> TypeContext<'b, 'c> {
         Ok(())
     }
 
+    // Report the first node (in the order they were created) whose inferred type differs from its
+    // declared type.
+    fn check_declared(&self) -> Result<()> {
+        let mut declared: Vec<_> = self.declared.iter().collect();
+        declared.sort_by_key(|(ix, _)| ix.index());
+        for (ix, (what, ty)) in declared {
+            let expected = flatten(concrete(declared_state(*ty)))?;
+            let inferred = flatten(concrete(*self.nw.read(*ix)))?;
+            if inferred != expected {
+                return user_err!(
+                    "{} is declared as {}, but its inferred type is {}",
+                    what,
+                    ty,
+                    annotation_name(inferred)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Constrain `ix` to have the type `ty`.
+    fn declare(&mut self, ix: NodeIx, what: String, ty: TypeAnn) {
+        let declared = self.constant(declared_state(ty));
+        self.nw.add_dep(declared, ix, Constraint::Flows(()));
+        self.declared.insert(ix, (what, ty));
+    }
+
     pub(crate) fn constant(&mut self, tv: State) -> NodeIx {
         use hashbrown::hash_map::Entry::*;
         match self.base.entry(tv) {
@@ -855,13 +978,27 @@ impl<'b, 'c> TypeContext<'b, 'c> {
         }
     }
     pub(crate) fn get_node(&mut self, key: Args<Ident>) -> NodeIx {
-        *self.env.entry(key).or_insert(self.nw.add_rule(Rule::Var))
+        if let Some(ix) = self.env.get(&key) {
+            return *ix;
+        }
+        let ix = self.nw.add_rule(Rule::Var);
+        let var_types = self.var_types;
+        if let Some((name, ty)) = var_types.get(&key.id.low) {
+            self.declare(ix, format!("`{}`", name), *ty);
+        }
+        self.env.insert(key, ix);
+        ix
     }
 
     fn get_function<'a>(
         &mut self,
         Function {
-            ident, cfg, args, ..
+            name,
+            ident,
+            cfg,
+            args,
+            ret_type,
+            ..
         }: &Function<'a, &'a str>,
         mut arg_nodes: SmallVec<NodeIx>,
         base_node: NodeIx,
@@ -896,6 +1033,9 @@ impl<'b, 'c> TypeContext<'b, 'c> {
 
         // Create a new function.
         let res = self.nw.add_rule(Rule::Var);
+        if let Some(ty) = ret_type {
+            self.declare(res, format!("the return value of `{}`", name), *ty);
+        }
         self.nw.add_dep(res, base_node, Constraint::Flows(()));
         self.funcs.insert(key.clone(), res);
         let mut view = View {
@@ -1061,7 +1201,11 @@ impl<'b, 'c, 'd> View<'b, 'c, 'd> {
                 let arr_ix = self.val_node(arr);
                 let ix_ix = self.val_node(ix);
                 self.constrain_as_map(arr_ix);
-                self.nw.add_dep(arr_ix, ix_ix, Constraint::Key(()));
+                // With --strict, the index keeps its own type, so that a float index is still a
+                // float when it is converted to the key type (and checked).
+                if !self.tc.strict {
+                    self.nw.add_dep(arr_ix, ix_ix, Constraint::Key(()));
+                }
                 self.nw.add_dep(arr_ix, to, Constraint::Val(()));
             }
             IterBegin(arr) => {
@@ -1100,6 +1244,7 @@ impl<'b, 'c, 'd> View<'b, 'c, 'd> {
             ILit(_) => self.constant(TVar::Scalar(BaseTy::Int).abs()),
             FLit(_) => self.constant(TVar::Scalar(BaseTy::Float).abs()),
             StrLit(_) => self.constant(TVar::Scalar(BaseTy::Str).abs()),
+            RegexLit(_) => self.constant(TVar::Scalar(BaseTy::Regex).abs()),
         }
     }
 
@@ -0,0 +1,58 @@
+//! `--watch`: rerun the program whenever one of its `-f` files changes.
+//!
+//! Each run is a child process with the same arguments, less `--watch`, so the program is parsed
+//! and compiled afresh every time. When a program file changes, a run that is still going is
+//! stopped and a new one started. Input files are read again from the start, while a run reading
+//! from standard input carries on with the rest of the stream (less any input the previous run had
+//! read but not yet processed). A program that fails to parse reports its error, and is run again
+//! once it is fixed.
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// How often the program files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// The modification time of each file. A file that is missing, e.g. while an editor replaces it,
+// has none.
+fn modified(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|f| fs::metadata(f).and_then(|md| md.modified()).ok())
+        .collect()
+}
+
+fn start(args: &[OsString]) -> io::Result<Child> {
+    Command::new(std::env::current_exe()?).args(args).spawn()
+}
+
+/// Run frawk with `args` (not including the program name), and again each time one of `files`
+/// changes. This only returns if a run could not be started or stopped.
+pub(crate) fn run(args: &[OsString], files: &[PathBuf]) -> io::Result<()> {
+    let mut last = modified(files);
+    let mut child = Some(start(args)?);
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if let Some(c) = child.as_mut() {
+            if c.try_wait()?.is_some() {
+                child = None;
+            }
+        }
+        let cur = modified(files);
+        if cur == last {
+            continue;
+        }
+        last = cur;
+        if let Some(mut c) = child.take() {
+            // This fails if the run finished since we checked, which is fine.
+            let _ = c.kill();
+            c.wait()?;
+        }
+        eprintln!("frawk: program changed, running it again");
+        child = Some(start(args)?);
+    }
+}
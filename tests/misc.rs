@@ -1,12 +1,54 @@
-use assert_cmd::Command;
+use assert_cmd::{assert::Assert, Command};
 use std::fs::{read_to_string, File};
 use std::io::Write;
 use tempfile::tempdir;
 
-#[cfg(feature = "llvm_backend")]
-const BACKEND_ARGS: &[&str] = &["-Binterp", "-Bllvm", "-Bcranelift"];
-#[cfg(not(feature = "llvm_backend"))]
-const BACKEND_ARGS: &[&str] = &["-Binterp", "-Bcranelift"];
+const BACKEND_ARGS: &[&str] = &[
+    "-Binterp",
+    #[cfg(feature = "llvm_backend")]
+    "-Bllvm",
+    #[cfg(feature = "cranelift_backend")]
+    "-Bcranelift",
+];
+
+fn frawk(backend_arg: &str) -> Command {
+    let mut cmd = Command::cargo_bin("frawk").unwrap();
+    cmd.arg(backend_arg);
+    cmd
+}
+
+fn stderr_of(assert: &Assert) -> String {
+    String::from_utf8_lossy(&assert.get_output().stderr).into_owned()
+}
+
+// Runs frawk once with each backend: `setup` adds the arguments (and any input or environment)
+// that follow the backend flag. Each run must succeed and print `expected`.
+fn assert_output(setup: impl Fn(&mut Command) -> &mut Command, expected: &str) {
+    for backend_arg in BACKEND_ARGS {
+        setup(&mut frawk(backend_arg))
+            .assert()
+            .success()
+            .stdout(expected.to_owned());
+    }
+}
+
+// Runs frawk once with each backend, as in `assert_output`. Each run must fail with an error
+// message containing `expected`.
+fn assert_error(setup: impl Fn(&mut Command) -> &mut Command, expected: &str) {
+    for backend_arg in BACKEND_ARGS {
+        let assert = setup(&mut frawk(backend_arg)).assert().failure();
+        assert_stderr_contains(&assert, expected);
+    }
+}
+
+fn assert_stderr_contains(assert: &Assert, expected: &str) {
+    let stderr = stderr_of(assert);
+    assert!(
+        stderr.contains(expected),
+        "unexpected error output: {}",
+        stderr
+    );
+}
 
 // A simple function that looks for the "constant folded" regex instructions in the generated
 // output. This is a function that is possible to fool: test cases should be mindful of how it is
@@ -352,69 +394,1930 @@ fn multi_rc() {
 }
 
 #[test]
-fn nested_loops() {
-    let expected = "0 0\n0 1\n0 2\n1 0\n1 1\n1 2\n2 0\n2 1\n2 2\n";
-    let prog: String =
-        "BEGIN { m[0]=0; m[1]=1; m[2]=2; for (i in m) for (j in m) print i,j; }".into();
-    for backend_arg in BACKEND_ARGS {
-        let output = Command::cargo_bin("frawk")
-            .unwrap()
-            .arg(String::from(*backend_arg))
-            .arg(prog.clone())
-            .output()
+fn parallel_worker_count() {
+    let mut text = String::default();
+    for i in 0..10_000 {
+        text.push_str(&format!("{}\n", i));
+    }
+    let (_dir, data) = file_from_string("inputs", &text);
+    assert_output(
+        |cmd| {
+            cmd.arg("-p3")
+                .arg(r#"{ s += $1 } END { print s }"#)
+                .arg(fname_to_string(&data))
+        },
+        "49995000\n",
+    );
+    for bad in [&["-p0"][..], &["-px"], &["-p2", "-j2"]] {
+        Command::cargo_bin("frawk")
             .unwrap()
-            .stdout;
-        unordered_output_equals(expected.as_bytes(), &output[..]);
+            .args(bad)
+            .arg(r#"{ s += $1 } END { print s }"#)
+            .arg(fname_to_string(&data))
+            .assert()
+            .failure();
     }
 }
 
 #[test]
-fn dont_reorder_files_with_f() {
-    let expected = "1 1\n2 2\n3 3\n";
-    let prog = "NR == FNR { print NR, FNR}";
-    let test_data_1 = "1\n2\n3\n";
-    let test_data_2 = "1\n2\n3\n4\n5\n";
+fn pinned_workers() {
+    let mut text = String::default();
+    for i in 0..10_000 {
+        text.push_str(&format!("{}\n", i));
+    }
+    let (_dir, data) = file_from_string("inputs", &text);
+    for pin in ["cores", "nodes"] {
+        assert_output(
+            |cmd| {
+                cmd.arg("-pr")
+                    .arg("-j3")
+                    .arg(format!("--pin-workers={}", pin))
+                    .arg(r#"{ s += $1 } END { print s }"#)
+                    .arg(fname_to_string(&data))
+            },
+            "49995000\n",
+        );
+    }
+}
+
+#[test]
+fn keep_order() {
+    let mut text = String::default();
+    for i in 0..50_000 {
+        text.push_str(&format!("{}\n", i));
+    }
+    let (_dir, data) = file_from_string("inputs", &text);
+    let expected = format!("begin\n{}{}end\n", text, text);
+    for strategy in ["-pr", "-pf"] {
+        assert_output(
+            |cmd| {
+                cmd.arg(strategy)
+                    .arg("-j4")
+                    .arg("--chunk-size=1024")
+                    .arg("--keep-order")
+                    .arg(r#"BEGIN { print "begin" } { print $1 } END { print "end" }"#)
+                    .arg(fname_to_string(&data))
+                    .arg(fname_to_string(&data))
+            },
+            &expected,
+        );
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_input() {
     let tmp = tempdir().unwrap();
-    let prog_file = tmp.path().join("prog");
-    let f1 = tmp.path().join("f1");
-    let f2 = tmp.path().join("f2");
-    File::create(f1.clone())
-        .unwrap()
-        .write_all(test_data_1.as_bytes())
-        .unwrap();
-    File::create(f2.clone())
-        .unwrap()
-        .write_all(test_data_2.as_bytes())
+    let db = tmp.path().join("ref.db");
+    {
+        let conn = rusqlite::Connection::open(&db).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE items (name TEXT, price REAL, qty INTEGER);
+             INSERT INTO items VALUES ('carrots', 1.5, 2), ('potato chips', 3.25, 4), ('custard', NULL, 1);",
+        )
         .unwrap();
-    File::create(prog_file.clone())
+    }
+    let operand = format!(
+        "sqlite://{}?query=SELECT name, price, qty FROM items ORDER BY qty",
+        fname_to_string(&db)
+    );
+    let expected = "custard 3 1\ncarrots 3 2\npotato chips 3 4\n16\ncustard\t\t1\n";
+    assert_output(
+        |cmd| {
+            cmd.arg(r#"{ print $1, NF, $3; total += $2 * $3 } NR == 1 { first = $0 } END { print total; print first }"#).arg(&operand)
+        },
+        &expected,
+    );
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn arrow_input() {
+    use arrow::array::{Float64Array, Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::ipc::writer::{FileWriter, StreamWriter};
+    use arrow::record_batch::RecordBatch;
+    use std::sync::Arc;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("qty", DataType::Int64, true),
+        Field::new("price", DataType::Float64, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(StringArray::from(vec![
+                "carrots",
+                "potato chips",
+                "custard",
+            ])),
+            Arc::new(Int64Array::from(vec![Some(2), Some(4), None])),
+            Arc::new(Float64Array::from(vec![1.5, 3.25, 2.0])),
+        ],
+    )
+    .unwrap();
+    let tmp = tempdir().unwrap();
+    let file = tmp.path().join("items.arrow");
+    let stream = tmp.path().join("items.arrows");
+    {
+        let mut w = FileWriter::try_new(File::create(&file).unwrap(), &schema).unwrap();
+        w.write(&batch).unwrap();
+        w.finish().unwrap();
+        let mut w = StreamWriter::try_new(File::create(&stream).unwrap(), &schema).unwrap();
+        w.write(&batch).unwrap();
+        w.finish().unwrap();
+    }
+    for input in [&file, &stream] {
+        // Only the third column is used, so the file reader projects out the others.
+        assert_output(
+            |cmd| {
+                cmd.arg("-iarrow")
+                    .arg(r#"{ total += $3 } END { print total, NR }"#)
+                    .arg(fname_to_string(input))
+            },
+            "6.75 3\n",
+        );
+        assert_output(
+            |cmd| {
+                cmd.arg("-iarrow")
+                    .arg(r#"{ print NF, $2, $0 }"#)
+                    .arg(fname_to_string(input))
+            },
+            "3 2 carrots\t2\t1.5\n3 4 potato chips\t4\t3.25\n3  custard\t\t2\n",
+        );
+    }
+}
+
+#[cfg(feature = "avro")]
+#[test]
+fn avro_input() {
+    use apache_avro::{types::Record, Schema, Writer};
+
+    let schema = Schema::parse_str(
+        r#"{"type": "record", "name": "item", "fields": [
+            {"name": "name", "type": "string"},
+            {"name": "qty", "type": ["null", "long"]},
+            {"name": "price", "type": "double"}
+        ]}"#,
+    )
+    .unwrap();
+    let mut w = Writer::new(&schema, Vec::new());
+    for (name, qty, price) in [
+        ("carrots", Some(2i64), 1.5),
+        ("potato chips", Some(4), 3.25),
+        ("custard", None, 2.0),
+    ] {
+        let mut r = Record::new(w.schema()).unwrap();
+        r.put("name", name);
+        r.put("qty", qty);
+        r.put("price", price);
+        w.append(r).unwrap();
+    }
+    let tmp = tempdir().unwrap();
+    let file = tmp.path().join("items.avro");
+    File::create(&file)
         .unwrap()
-        .write_all(prog.as_bytes())
+        .write_all(&w.into_inner().unwrap())
         .unwrap();
+    assert_output(
+        |cmd| {
+            cmd.arg("-iavro")
+                .arg("-H")
+                .arg(r#"{ total += $FI["price"] } END { print total, NR }"#)
+                .arg(fname_to_string(&file))
+        },
+        "6.75 3\n",
+    );
+    assert_output(
+        |cmd| {
+            cmd.arg("-iavro")
+                .arg(r#"{ print NF, $2, $0 }"#)
+                .arg(fname_to_string(&file))
+        },
+        "3 2 carrots\t2\t1.5\n3 4 potato chips\t4\t3.25\n3  custard\t\t2\n",
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_input() {
+    let input = r#"{"name": "carrots", "qty": 2, "price": 1.5}
+
+{"price": 3.25, "name": "potato\tchips", "qty": 4}
+{"name": "custard", "qty": null, "price": 2, "tags": ["a", "b"]}
+"#;
+    assert_output(
+        |cmd| {
+            cmd.arg("-ijson")
+                .arg("-H")
+                .arg(r#"{ total += $FI["price"] } END { print total, NR }"#)
+                .write_stdin(input)
+        },
+        "6.75 3\n",
+    );
+    assert_output(
+        |cmd| {
+            cmd.arg("-ijson")
+                .arg(r#"{ print NF, $1, $2, $4 }"#)
+                .write_stdin(input)
+        },
+        "3 carrots 2 \n3 potato\tchips 4 \n4 custard  [\"a\",\"b\"]\n",
+    );
+    assert_output(
+        |cmd| cmd.arg("-ijson").arg(r#"$2 > 3"#).write_stdin(input),
+        r#"{"price": 3.25, "name": "potato\tchips", "qty": 4}
+"#,
+    );
+}
+
+#[test]
+fn xml_records() {
+    let rss = r#"<?xml version="1.0"?>
+<rss><channel><title>Groceries</title>
+<item id="1"><title>Carrots &amp; peas</title><link>http://a</link></item>
+<item id="2"><title><![CDATA[Custard]]></title><link>http://b</link></item>
+</channel></rss>
+"#;
+    let html = r#"<!DOCTYPE html>
+<ul class="menu"><li><a href="/a">A</a><li><a href="/b">B<br>b</a></ul>
+"#;
+    let (_dir, files) = files_from_strings(&[("feed.xml", rss), ("menu.html", html)]);
+    assert_output(
+        |cmd| {
+            cmd.arg("--xml-record=item")
+                .arg(r#"{ print NR, getxml($0, "@id"), getxml($0, "title"), getxml($0, "link") }"#)
+                .arg(&files[0])
+        },
+        "1 1 Carrots & peas http://a\n2 2 Custard http://b\n",
+    );
+    assert_output(
+        |cmd| {
+            cmd.arg("--xml-record=ul.menu > li")
+                .arg(r#"{ print getxml($0, "a/@href"), getxml($0, "a") }"#)
+                .arg(&files[1])
+        },
+        "/a A\n/b Bb\n",
+    );
+}
+
+#[test]
+fn binary_records() {
+    let mut data = Vec::new();
+    for (id, name, score) in [(1i32, "ann", 2.5f64), (-2, "bo", 0.125)] {
+        data.extend_from_slice(&id.to_le_bytes());
+        let mut padded = [0u8; 4];
+        padded[..name.len()].copy_from_slice(name.as_bytes());
+        data.extend_from_slice(&padded);
+        data.extend_from_slice(&score.to_le_bytes());
+    }
+    let tmp = tempdir().unwrap();
+    let data_file = tmp.path().join("records.bin");
+    File::create(&data_file).unwrap().write_all(&data).unwrap();
+    assert_output(
+        |cmd| {
+            cmd.arg("--record-bytes=16")
+                .arg(r#"{ n = unpack("<i 4s d"); print n, $1, $2, $3 }"#)
+                .arg(fname_to_string(&data_file))
+        },
+        "3 1 ann 2.5\n3 -2 bo 0.125\n",
+    );
+}
+
+#[test]
+fn sleep_and_timers() {
+    let prog = r#"BEGIN {
+    t0 = monotonic(); w = gettimeofday();
+    r = sleep(0.05);
+    print r, (monotonic() - t0 >= 0.05), (w > 1600000000), (gettimeofday() >= w);
+}"#;
+    assert_output(|cmd| cmd.arg(prog), "0 1 1 1\n");
+}
+
+#[test]
+fn command_statuses() {
+    let prog = r#"BEGIN {
+    print "b" | "sort"; print "a" | "sort";
+    print "x" | "cat >/dev/null; exit 3";
+    "echo hi; exit 4" | getline line;
+    print line, cmdstatus("cat >/dev/null; exit 3"), cmdstatus("echo hi; exit 4"), cmdstatus("true");
+}"#;
+    // Commands still open at exit are waited on, so the output of sort is complete.
+    assert_output(|cmd| cmd.arg(prog), "hi 3 4 -1\na\nb\n");
     for backend_arg in BACKEND_ARGS {
-        Command::cargo_bin("frawk")
-            .unwrap()
-            .arg(String::from(*backend_arg))
-            .arg(format!("-f{}", fname_to_string(&prog_file)))
-            .arg(fname_to_string(&f1))
-            .arg(fname_to_string(&f2))
+        let assert = frawk(backend_arg)
+            .arg("--fail-on-command-error")
+            .arg(r#"BEGIN { print "a" | "cat; exit 2" }"#)
             .assert()
-            .stdout(String::from(expected));
+            .stdout("a\n")
+            .code(3);
+        assert_stderr_contains(&assert, "command 'cat; exit 2' exited with status 2");
     }
 }
 
-fn fname_to_string(path: &std::path::Path) -> String {
-    path.to_owned().into_os_string().into_string().unwrap()
+#[test]
+fn close_command_reader() {
+    let prog = r#"BEGIN {
+    cmd = "echo a; echo b";
+    while ((cmd | getline x) > 0) print x;
+    close(cmd);
+    cmd | getline x; print x;
+    # Closing the output of a command that is still writing stops it, rather than waiting.
+    "yes | head -n 100000" | getline y;
+    print y, (cmdstatus("yes | head -n 100000") > 0);
+}"#;
+    assert_output(|cmd| cmd.arg(prog), "a\nb\na\ny 1\n");
 }
 
-fn file_from_string(
-    name: impl AsRef<str>,
-    s: impl AsRef<str>,
-) -> (tempfile::TempDir, std::path::PathBuf) {
-    let tmp = tempdir().unwrap();
-    let file = tmp.path().join(name.as_ref());
-    File::create(file.clone())
+#[cfg(unix)]
+#[test]
+fn pty_commands() {
+    let prog = r#"BEGIN {
+    tty = "test -t 1 && echo tty || echo pipe";
+    print usepty(tty);
+    tty | getline x; "test -t 1 && echo tty || echo pipe;" | getline y;
+    print x, y;
+}"#;
+    assert_output(|cmd| cmd.arg(prog), "1\ntty pipe\n");
+}
+
+#[test]
+fn record_limits() {
+    let (_dir, data) = file_from_string("data", "ab\nabcdefghij\nx y z\n");
+    let data = fname_to_string(&data);
+    // Whitespace-separated, single-byte separators, CSV, and regex-separated input.
+    let inputs: &[(&[&str], &str)] = &[
+        (&[], "{ print $0 }"),
+        (&["-F,"], "{ print $0 }"),
+        (&["-icsv"], "{ print $0 }"),
+        (&[], r#"BEGIN { RS = "\n+" } { print $0 }"#),
+    ];
+    for (args, prog) in inputs {
+        assert_error(
+            |cmd| {
+                cmd.args(*args)
+                    .arg("--max-record-bytes=5")
+                    .arg(prog)
+                    .arg(&data)
+            },
+            "the record starting at byte 3 of",
+        );
+        for backend_arg in BACKEND_ARGS {
+            let assert = frawk(backend_arg)
+                .args(*args)
+                .arg("--max-record-bytes=5")
+                .arg("--on-limit=truncate")
+                .arg(prog)
+                .arg(&data)
+                .assert()
+                .success()
+                .stdout("ab\nabcde\nx y z\n");
+            assert_stderr_contains(&assert, "warning: truncated the record");
+        }
+    }
+    assert_error(
+        |cmd| cmd.arg("--max-fields=2").arg("{ print NF }").arg(&data),
+        "has 3 fields, more than 2",
+    );
+    assert_output(
+        |cmd| {
+            cmd.arg("--max-fields=2")
+                .arg("--on-limit=truncate")
+                .arg("{ print NF, $2 }")
+                .arg(&data)
+        },
+        "1 \n1 \n2 y\n",
+    );
+}
+
+#[test]
+fn streamed_records() {
+    let long_field = "z".repeat(500);
+    let inputs = [
+        ("", format!("a b c\nx y {} w v\np q\n", long_field)),
+        ("-F,", format!("a,b,c\nx,y,{},w,v\np,q\n", long_field)),
+    ];
+    for (fs_arg, data) in inputs.iter() {
+        let (_dir, data_file) = file_from_string("data", data);
+        let data_file = fname_to_string(&data_file);
+        assert_output(
+            |cmd| {
+                cmd.args(fs_arg.split_whitespace())
+                    .args([
+                        "--chunk-size=64",
+                        "--stream-records",
+                        "{ print NF, $1, $2 }",
+                    ])
+                    .arg(&data_file)
+            },
+            "3 a b\n5 x y\n2 p q\n",
+        );
+        for (prog, expected) in [
+            ("{ print $0 }", "$0 is not available"),
+            ("{ print $3 }", "field 3 is not available"),
+        ] {
+            assert_error(
+                |cmd| {
+                    cmd.args(fs_arg.split_whitespace())
+                        .args(["--chunk-size=64", "--stream-records", prog])
+                        .arg(&data_file)
+                },
+                expected,
+            );
+        }
+    }
+}
+
+#[test]
+fn distinct_counts() {
+    let mut text = String::default();
+    for i in 0..20_000 {
+        text.push_str(&format!("{} {}\n", i % 5_000, i % 3));
+    }
+    let (_dir, data) = file_from_string("inputs", &text);
+    let prog = r#"BEGIN { print hll_add("b", "x"), hll_add("b", "x") }
+{ hll_add("ids", $1); hll_add("small", $2) }
+END {
+    n = hll_count("ids");
+    print (n > 4900 && n < 5100), hll_count("small"), hll_count("missing");
+}"#;
+    for strategy in ["", "-pr"] {
+        assert_output(
+            |cmd| {
+                cmd.args(strategy.split_whitespace())
+                    .arg(prog)
+                    .arg(fname_to_string(&data))
+            },
+            "1 0\n1 3 0\n",
+        );
+    }
+}
+
+#[test]
+fn reservoir_samples() {
+    let mut text = String::default();
+    for i in 1..=100 {
+        text.push_str(&format!("{}\n", i));
+    }
+    let (_dir, data) = file_from_string("inputs", &text);
+    let prog = r#"{ n = sample(s, 3, $1 + 0); sample(all, 200, $1) }
+END {
+    ok = 1;
+    for (i = 1; i <= 3; i++) if (!(s[i] >= 1 && s[i] <= 100)) ok = 0;
+    print n, length(s), ok, all[1], all[100], length(all);
+}"#;
+    assert_output(
+        |cmd| cmd.arg(prog).arg(fname_to_string(&data)),
+        "100 3 1 1 100 100\n",
+    );
+}
+
+#[test]
+fn sorted_keys() {
+    let (_dir, data) = file_from_string("inputs", "a\nb\na\nc\na\nb\n");
+    let prog = r#"{ n[$1]++ }
+END {
+    k = asorti(n, keys, "@val_num_desc");
+    for (i = 1; i <= k; i++) print keys[i], n[keys[i]];
+    asorti(n, keys);
+    print keys[1], keys[3], length(keys);
+    x[10] = 1; x[9] = 1; x[100] = 1;
+    asorti(x, ks, "@ind_num_asc");
+    print ks[1], ks[2], ks[3];
+    asorti(x, ks, "@ind_str_desc");
+    print ks[1], ks[2], ks[3];
+}"#;
+    assert_output(
+        |cmd| cmd.arg(prog).arg(fname_to_string(&data)),
+        "a 3\nb 2\nc 1\na c 3\n9 10 100\n9 100 10\n",
+    );
+    assert_error(
+        |cmd| cmd.arg(r#"BEGIN { x[1] = 1; asorti(x, y, "@ind_by_asc") }"#),
+        "invalid sort order",
+    );
+}
+
+#[test]
+fn sort_comparators() {
+    let prog = r#"function by_len(k1, v1, k2, v2) { return length(k1) - length(k2) }
+function by_val_desc(k1, v1, k2, v2) { return v2 - v1 }
+BEGIN {
+    a["ccc"] = 1; a["a"] = 3; a["bb"] = 2; a["dd"] = 5;
+    n = asorti(a, ks, "by_len");
+    print n, ks[1], ks[2], ks[3], ks[4];
+    asorti(a, ks, "by_val_desc");
+    print ks[1], ks[2], ks[3], ks[4];
+}"#;
+    assert_output(|cmd| cmd.arg(prog), "4 a bb dd ccc\ndd a bb ccc\n");
+    assert_error(
+        |cmd| cmd.arg(r#"BEGIN { x[1] = 1; asorti(x, y, "no_such_cmp") }"#),
+        "no_such_cmp",
+    );
+    // A function named in a string constant, but never used to sort, keeps its own types.
+    assert_output(
+        |cmd| {
+            cmd.arg(
+                r#"function f(a, b, c, d) { return a[1] b c[1] d }
+BEGIN { x[1] = "x"; print "f", f(x, 1, x, 2) }"#,
+            )
+        },
+        "f x1x2\n",
+    );
+}
+
+#[test]
+fn top_k() {
+    let (_dir, data) = file_from_string("inputs", "a 10\nb 1\nc 2\na 5\nc 20\n");
+    let prog = r#"{ last = topk(heavy, 2, $1, $2) }
+END {
+    n = asorti(heavy, keys, "@val_num_desc");
+    for (i = 1; i <= n; i++) print keys[i], heavy[keys[i]];
+    print last, length(heavy);
+}"#;
+    assert_output(
+        |cmd| cmd.arg(prog).arg(fname_to_string(&data)),
+        "c 23\na 15\n23 2\n",
+    );
+}
+
+#[test]
+fn decimal_comma() {
+    let (_dir, data) = file_from_string("inputs", "a;1,5\nb;2,25\nc;1.234,5\n");
+    let prog = r#"{ total += $2; printf "%s %.2f\n", $1, $2 } END { printf "%'.1f %e\n", total * 1000, total }"#;
+    assert_output(
+        |cmd| {
+            cmd.arg("--decimal-comma")
+                .arg("-F;")
+                .arg(prog)
+                .arg(fname_to_string(&data))
+        },
+        "a 1,50\nb 2,25\nc 1,00\n4.750,0 4,750000e+00\n",
+    );
+}
+
+#[test]
+fn strftime_time_zones() {
+    let prog = r#"BEGIN {
+    print strftime("%Y-%m-%d %H:%M:%S %z", 1700000000, "UTC");
+    print strftime("%F %T %Z", 1700000000, "+05:30");
+    print strftime("%F %R", 0, 1), strftime("%H %Z", 0);
+    print (strftime() != "")
+}"#;
+    assert_output(
+        |cmd| cmd.env("TZ", "UTC").arg(prog),
+        "2023-11-14 22:13:20 +0000\n2023-11-15 03:43:20 +05:30\n1970-01-01 00:00 00 UTC\n1\n",
+    );
+    assert_error(
+        |cmd| cmd.arg(r#"BEGIN { print strftime("%F", 0, "Mars/Olympus_Mons") }"#),
+        "unsupported time zone",
+    );
+}
+
+#[test]
+fn parse_dates() {
+    let (_dir, data) = file_from_string(
+        "inputs",
+        "2023-11-14T22:13:20.5Z\n[14/Nov/2023:23:13:20 +0100]\nTue, 14 Nov 2023 22:13:20 GMT\n2023-11-14 22:13:20\nnot a date\n",
+    );
+    let prog =
+        r#"{ t = parsedate($0); print t, (t < 0 ? "invalid" : strftime("%FT%TZ", t, "UTC")) }"#;
+    assert_output(
+        |cmd| cmd.env("TZ", "UTC").arg(prog).arg(fname_to_string(&data)),
+        concat!(
+            "1700000000.5 2023-11-14T22:13:20Z\n",
+            "1700000000 2023-11-14T22:13:20Z\n",
+            "1700000000 2023-11-14T22:13:20Z\n",
+            "1700000000 2023-11-14T22:13:20Z\n",
+            "-1 invalid\n",
+        ),
+    );
+}
+
+#[test]
+fn match_all() {
+    let prog = r#"{
+        n = matchall($0, "[0-9]+", nums)
+        for (i = 1; i <= n; i++) printf "%s%s", nums[i], (i < n ? "," : "\n")
+        m = matchall($0, "([a-z]+)=([0-9]+)?", kv, 2)
+        print m, kv[1], "[" kv[2] "]", kv[m]
+        print matchall($0, "q", nums), length(nums)
+    }"#;
+    assert_output(
+        |cmd| cmd.arg(prog).write_stdin("x=1 y= zz=333 4\n"),
+        "1,333,4\n3 1 [] 333\n0 0\n",
+    );
+    assert_error(
+        |cmd| cmd.arg(r#"BEGIN { print matchall("abc", "b", arr, 1) }"#),
+        "has no capture group 1",
+    );
+}
+
+#[test]
+fn split_limit() {
+    let prog = r#"{
+        n = split($0, kv, ": ", 2)
+        print n, kv[1], "<" kv[2] ">"
+        print split($0, ws, " ", 3), ws[3]
+        print split($0, all, ":", 0), split($0, all, ":", -2), split($0, all, ":")
+    }"#;
+    assert_output(
+        |cmd| {
+            cmd.arg(prog)
+                .write_stdin("Date: Tue, 14 Nov 2023 22:13:20\n")
+        },
+        "2 Date <Tue, 14 Nov 2023 22:13:20>\n3 14 Nov 2023 22:13:20\n4 4 4\n",
+    );
+}
+
+#[test]
+fn chr_ord() {
+    let prog = r#"BEGIN {
+        print chr(72) chr(105), ord("A"), ord(""), "[" chr(-1) "]"
+        print ord("\303\251"), (chr(233) == "\351"), length(chr(9786))
+    }"#;
+    assert_output(|cmd| cmd.arg(prog), "Hi 65 0 []\n195 1 0\n");
+    assert_output(|cmd| cmd.arg("--utf8").arg(prog), "Hi 65 0 []\n233 0 3\n");
+}
+
+#[test]
+fn byte_length() {
+    let prog =
+        r#"{ total += blength($0) + 1; print blength($0), blength("") } END { print total }"#;
+    assert_output(
+        |cmd| {
+            cmd.arg("--utf8")
+                .arg(prog)
+                .write_stdin("abc\nh\u{e9}llo\n\u{263a}\n")
+        },
+        "3 0\n6 0\n3 0\n15\n",
+    );
+}
+
+#[test]
+fn copy_arrays() {
+    let prog = r#"BEGIN {
+        a["x"] = 1; a["y"] = 2
+        n = copy(b, a)
+        a["x"] = 10; delete a["y"]
+        print n, b["x"], b["y"], length(a), length(b)
+        print copy(b, b), length(b)
+        c["q"] = 3
+        copy(c, a)
+        print length(c), ("q" in c), c["x"]
+    }"#;
+    assert_output(|cmd| cmd.arg(prog), "2 1 2 1 2\n2 2\n1 0 10\n");
+}
+
+#[test]
+fn shadow_extension_builtins() {
+    // Functions that are frawk or gawk extensions can be redefined; standard ones cannot.
+    let prog = r#"function copy(dst, src) { return src "!" }
+function chr(c) { return "<" c ">" }
+BEGIN { a[1] = 1; print copy("x", "y"), chr(65), ord("A"), length(a) }"#;
+    assert_output(|cmd| cmd.arg(prog), "y! <65> 65 1\n");
+    Command::cargo_bin("frawk")
         .unwrap()
-        .write_all(s.as_ref().as_bytes())
-        .unwrap();
-    (tmp, file)
+        .arg(r#"function length(x) { return 1 } BEGIN { print length("abc") }"#)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn constants() {
+    let prog = r#"const SEP = "-"
+const N = 3; const NEG = -1.5
+function twice(N) { return N * 2 }
+BEGIN {
+    for (i = 1; i <= N; i++) printf "%d%s", i, SEP
+    print NEG, twice(5), LIMIT, NAME, (N == 3)
+}"#;
+    assert_output(
+        |cmd| cmd.arg("-DLIMIT=10").arg("-D").arg("NAME=frawk").arg(prog),
+        "1-2-3--1.5 10 10 frawk 1\n",
+    );
+    for (args, prog, expected) in [
+        (
+            &[][..],
+            "const N = 1\nBEGIN { N = 2 }",
+            "cannot assign to constant N",
+        ),
+        (
+            &[][..],
+            "const N = 1\nBEGIN { N++ }",
+            "cannot assign to constant N",
+        ),
+        (
+            &[][..],
+            "const K = 1\nBEGIN { a[1]; for (K in a) print K; }",
+            "cannot assign to constant K",
+        ),
+        (
+            &["-DN=2"][..],
+            "const N = 1\nBEGIN { print N }",
+            "duplicate constant found for name N",
+        ),
+        (
+            &[][..],
+            "const NR = 1\nBEGIN { print NR }",
+            "builtin variable NR",
+        ),
+    ] {
+        assert_error(|cmd| cmd.args(args).arg(prog), expected);
+    }
+}
+
+#[test]
+fn set_record() {
+    let prog = r#"function second(s) { set_record(s); return $2 }
+BEGIN { FS = "," }
+{
+    orig = $0
+    n = set_record("a,b,c")
+    print n, $2, NF
+    print second(orig), $0
+}"#;
+    assert_output(
+        |cmd| cmd.arg(prog).write_stdin("x,y\n1,2,3,4\n"),
+        "3 b 3\ny x,y\n3 b 3\n2 1,2,3,4\n",
+    );
+}
+
+#[test]
+fn resplit() {
+    let prog = r#"BEGIN { FS = "\t" }
+{
+    print NF, $2
+    print resplit(","), $1
+    $2 = "X"
+    print $0
+}"#;
+    assert_output(
+        |cmd| cmd.arg(prog).write_stdin("a\tb,c\nd,e\tf\n"),
+        "2 b,c\n2 a\tb\na\tb X\n2 f\n2 d\nd X\n",
+    );
+}
+
+#[test]
+fn csv_dialect() {
+    let input = "a;'b;c';'it''s'\n1;2;3\n";
+    assert_output(
+        |cmd| {
+            cmd.args(["-icsv", "-ocsv", "--csv-delimiter=;", "--csv-quote='"])
+                .arg("{ print NF, $2, $3 }")
+                .write_stdin(input)
+        },
+        "3;'b;c';'it''s'\r\n3;2;3\r\n",
+    );
+    assert_output(
+        |cmd| {
+            cmd.args(["-icsv", "--csv-delimiter=;", "--csv-quote='"])
+                .arg(r#"{ print escape_csv($2) "|" join_csv(1, 2) }"#)
+                .write_stdin(input)
+        },
+        "'b;c'|a;'b;c'\n2|1;2\n",
+    );
+    let assert = Command::cargo_bin("frawk")
+        .unwrap()
+        .args(["-icsv", "--csv-delimiter=;", "--csv-quote=;"])
+        .arg("{ print }")
+        .write_stdin(input)
+        .assert()
+        .failure();
+    assert_stderr_contains(&assert, "must be different characters");
+}
+
+#[test]
+fn json_output() {
+    let input = "name,age\nbob,42\nann,007\n";
+    assert_output(
+        |cmd| {
+            cmd.args(["-H", "-F,", "--ojson"])
+                .arg("{ print }")
+                .write_stdin(input)
+        },
+        "{\"name\":\"bob\",\"age\":42}\n{\"name\":\"ann\",\"age\":\"007\"}\n",
+    );
+    assert_output(
+        |cmd| {
+            cmd.args(["-H", "-F,", "--ojson"])
+                .arg(r#"{ print $2, $1, length($1), "x\"y" }"#)
+                .write_stdin(input)
+        },
+        concat!(
+            r#"{"age":42,"name":"bob","3":3,"4":"x\"y"}"#,
+            "\n",
+            r#"{"age":"007","name":"ann","3":3,"4":"x\"y"}"#,
+            "\n",
+        ),
+    );
+    assert_output(
+        |cmd| {
+            cmd.arg("--ojson")
+                .arg("{ print; print join_json(2, NF), escape_json($1) }")
+                .write_stdin("a 1.5\n")
+        },
+        concat!(
+            r#"{"$1":"a","$2":1.5}"#,
+            "\n",
+            r#"{"1":"{\"$2\":1.5}","2":"\"a\""}"#,
+            "\n",
+        ),
+    );
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_output() {
+    assert_output(
+        |cmd| {
+            cmd.args(["-H", "-F,", "--omsgpack"])
+                .arg(r#"{ print; print $2, "x" }"#)
+                .write_stdin("name,age\nbob,42\n")
+        },
+        &b"\x82\xa4name\xa3bob\xa3age\x2a\x82\xa3age\x2a\xa12\xa1x"[..],
+    );
+}
+
+#[test]
+fn skip_and_head() {
+    let (_dir, files) = files_from_strings(&[("f1", "h\n1\n2\n"), ("f2", "h\n3\n4\n")]);
+    assert_output(
+        |cmd| {
+            cmd.args(["--skip-file", "1"])
+                .arg("{ print NR, FNR, $0 }")
+                .args(&files)
+        },
+        "1 1 1\n2 2 2\n3 1 3\n4 2 4\n",
+    );
+    assert_output(
+        |cmd| {
+            cmd.args(["--skip", "1", "--head", "2"])
+                .arg("{ print } END { print NR, $0 }")
+                .write_stdin("a\nb\nc\nd\n")
+        },
+        "b\nc\n2 c\n",
+    );
+    assert_output(
+        |cmd| {
+            cmd.args(["-H", "-F,", "--head", "1"])
+                .arg("{ print $2 }")
+                .write_stdin("x,y\n1,2\n3,4\n")
+        },
+        "2\n",
+    );
+}
+
+#[test]
+fn begin_and_end_snippets() {
+    assert_output(
+        |cmd| {
+            cmd.arg("BEGIN { s = 10 } { s += $1 } END { print \"sum\" }")
+                .args(["--end", "print s", "--begin", "s *= 2", "--end", "print NR"])
+                .write_stdin("1\n2\n")
+        },
+        "sum\n23\n2\n",
+    );
+}
+
+#[test]
+fn source_fragments() {
+    let (_dir, lib) = file_from_string(
+        "lib.awk",
+        "function double(x) { return 2 * x }\nBEGIN { print \"lib\" }\n",
+    );
+    assert_output(
+        |cmd| {
+            cmd.args(["-e", "BEGIN { print \"first\" }", "-f"])
+                .arg(fname_to_string(&lib))
+                .args(["--source", "{ print double($1) }"])
+                .write_stdin("1\n21\n")
+        },
+        "first\nlib\n2\n42\n",
+    );
+}
+
+#[test]
+fn include_directive() {
+    let (tmp, _) = files_from_strings(&[
+        (
+            "lib.awk",
+            "@include \"util\"\nfunction double(x) { return add(x, x) }\nBEGIN { print \"lib\" }\n",
+        ),
+        ("util.awk", "function add(x, y) { return x + y }\n"),
+    ]);
+    let prog = "@include \"lib.awk\"\n@include \"lib\" # included once\n{ print double($1) }";
+    for backend_arg in BACKEND_ARGS {
+        frawk(backend_arg)
+            .env("AWKPATH", fname_to_string(tmp.path()))
+            .arg(prog)
+            .write_stdin("1\n21\n")
+            .assert()
+            .success()
+            .stdout("lib\n2\n42\n");
+        frawk(backend_arg)
+            .env("AWKPATH", fname_to_string(tmp.path()))
+            .arg("@include \"missing\"\nBEGIN { print 1 }")
+            .assert()
+            .failure();
+    }
+}
+
+#[test]
+fn shebang_args() {
+    let (_dir, files) = files_from_strings(&[
+        ("script.awk", "#!/usr/bin/frawk -F, -f\n{ print $2 }\n"),
+        ("data", "a,b\nc,d\n"),
+    ]);
+    for backend_arg in BACKEND_ARGS {
+        // This is how the kernel runs `./script.awk data`: with the options from the `#!` line as
+        // a single argument.
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(format!("{} -F, -f", backend_arg))
+            .arg(&files[0])
+            .arg("--")
+            .arg(&files[1])
+            .assert()
+            .success()
+            .stdout("b\nd\n");
+    }
+}
+
+#[test]
+fn exit_statuses() {
+    for backend_arg in BACKEND_ARGS {
+        for (args, status) in [
+            (&["BEGIN { exit 1 }"][..], 1),
+            (&["BEGIN { print 1 +* 2 }"][..], 2),
+            (&["--not-a-flag", "BEGIN {}"][..], 2),
+            (&[r#"BEGIN { assert(0, "no") }"#][..], 3),
+        ] {
+            frawk(backend_arg).args(args).assert().code(status);
+        }
+    }
+}
+
+#[test]
+fn syntax_error_frame() {
+    for backend_arg in BACKEND_ARGS {
+        let assert = frawk(backend_arg)
+            .arg("BEGIN {\n  print 1 +* 2\n}")
+            .assert()
+            .code(2);
+        let stderr = stderr_of(&assert);
+        assert!(
+            stderr.contains("2 |   print 1 +* 2\n  |            ^\n") && !stderr.contains('\x1b'),
+            "unexpected error output: {}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn warning_categories() {
+    let prog = r#"BEGIN { print gensub(/(a)/, "[\\2]", "g", "xa") }"#;
+    for backend_arg in BACKEND_ARGS {
+        let assert = frawk(backend_arg)
+            .arg(prog)
+            .assert()
+            .success()
+            .stdout("x[]\n");
+        let stderr = stderr_of(&assert);
+        assert!(
+            stderr.contains("warning: couldn't substitute match 2")
+                && stderr.contains("[-Wsubstitution]"),
+            "unexpected error output: {}",
+            stderr
+        );
+        frawk(backend_arg)
+            .arg("-Wno-substitution")
+            .arg(prog)
+            .assert()
+            .success()
+            .stdout("x[]\n")
+            .stderr("");
+        frawk(backend_arg)
+            .arg("--werror")
+            .arg(prog)
+            .assert()
+            .code(3);
+        frawk(backend_arg)
+            .arg("-Wno-uninitialized")
+            .arg(prog)
+            .assert()
+            .code(2);
+    }
+}
+
+#[test]
+fn warning_stats() {
+    let prog = "{ s += $1 } END { print s }";
+    for backend_arg in BACKEND_ARGS {
+        let assert = frawk(backend_arg)
+            .arg("-Wnumeric")
+            .arg("--stats")
+            .arg(prog)
+            .write_stdin("1\nx\n3\n")
+            .assert()
+            .success()
+            .stdout("4\n");
+        let stderr = stderr_of(&assert);
+        assert!(
+            stderr.contains(r#"warning: "x" is not a number (NR=2, FILENAME="#)
+                && stderr.contains("[-Wnumeric]")
+                && stderr.contains("stats: warnings: truncation=0, substitution=0, numeric=1"),
+            "unexpected error output: {}",
+            stderr
+        );
+        // Numeric warnings are disabled by default, but still counted.
+        let assert = frawk(backend_arg)
+            .arg("--stats")
+            .arg(prog)
+            .write_stdin("1\nx\n3\n")
+            .assert()
+            .success()
+            .stdout("4\n");
+        let stderr = stderr_of(&assert);
+        assert!(
+            !stderr.contains("warning:") && stderr.contains("numeric=1"),
+            "unexpected error output: {}",
+            stderr
+        );
+        let assert = frawk(backend_arg)
+            .arg("-Wnumeric")
+            .arg("--werror")
+            .arg(prog)
+            .write_stdin("1\nx\n3\n")
+            .assert()
+            .code(3);
+        let stderr = stderr_of(&assert);
+        assert!(
+            stderr.contains(r#"execution: "x" is not a number (NR=2, FILENAME="#)
+                && stderr.contains("[-Wnumeric, with --werror]"),
+            "unexpected error output: {}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn type_annotations() {
+    let cases: &[(&str, &str)] = &[
+        (
+            "# @type total: int\n{ total += $1 }\nEND { print total }",
+            "`total` is declared as int, but its inferred type is float",
+        ),
+        (
+            "# @type seen: int\n{ seen[$1] = 1 }",
+            "`seen` is declared as int, but is used as a map",
+        ),
+        (
+            "function f(x: int): int { return x \"!\" }\nBEGIN { print f(1) }",
+            "the return value of `f` is declared as int, but its inferred type is str",
+        ),
+        (
+            "# @type m: map[float]int\nBEGIN { m[1] = 1 }",
+            "map keys must be int or str",
+        ),
+    ];
+    for backend_arg in BACKEND_ARGS {
+        for (prog, expected) in cases {
+            let assert = frawk(backend_arg).arg(prog).assert().code(2);
+            assert_stderr_contains(&assert, expected);
+        }
+    }
+    assert_output(
+        |cmd| {
+            cmd.arg("# @type total: float\n{ total += $1 }\nEND { print total }")
+                .write_stdin("1\n2.5\n")
+        },
+        "3.5\n",
+    );
+    // Numbers are converted to strings where a string is declared, return values included.
+    assert_output(
+        |cmd| {
+            cmd.arg(
+                "# @type s: str\nfunction f(x: int): str { return x + 0.5 }\n\
+                 BEGIN { s = 1 / 4; t = f(3); print s, t, length(t) }",
+            )
+        },
+        "0.25 3.5 3\n",
+    );
+}
+
+#[test]
+fn strict_mode() {
+    let sum = "{\n\ttotal += $1\n}\nEND { print total }";
+    let index = "BEGIN { a[4 / 2] = 1; x = 3 / 2; a[x] = 2 }";
+    for backend_arg in BACKEND_ARGS {
+        frawk(backend_arg)
+            .arg(sum)
+            .write_stdin("1\nabc\n")
+            .assert()
+            .success()
+            .stdout("1\n");
+        frawk(backend_arg)
+            .arg("--strict")
+            .arg(sum)
+            .write_stdin("1\n 2.5 \n\n")
+            .assert()
+            .success()
+            .stdout("3.5\n");
+        let assert = frawk(backend_arg)
+            .arg("--strict")
+            .arg(sum)
+            .write_stdin("1\nabc\n")
+            .assert()
+            .code(3);
+        assert_stderr_contains(
+            &assert,
+            r#"execution: strict mode: "abc" is not a number at line 2 (NR=2"#,
+        );
+        frawk(backend_arg).arg(index).assert().success();
+        let assert = frawk(backend_arg)
+            .arg("--strict")
+            .arg(index)
+            .assert()
+            .code(3);
+        assert_stderr_contains(
+            &assert,
+            "execution: strict mode: array index 1.5 is not an integer at line 1",
+        );
+    }
+}
+
+#[test]
+fn division_by_zero() {
+    let cases: &[(&str, &str)] = &[
+        ("BEGIN { x = 0; print 1 / x }", "inf\n"),
+        ("BEGIN { x = 0; print -1 / x }", "-inf\n"),
+        ("BEGIN { x = 0; print 5 % x }", "NaN\n"),
+        ("BEGIN { x = 0.0; print 5.5 % x }", "NaN\n"),
+    ];
+    for (prog, out) in cases {
+        for backend_arg in BACKEND_ARGS {
+            let assert = frawk(backend_arg).arg(prog).assert().code(3);
+            assert_stderr_contains(&assert, "execution: division by zero attempted in `");
+        }
+        assert_output(|cmd| cmd.arg("--ieee-div").arg(prog), out);
+    }
+    assert_output(
+        |cmd| cmd.arg("BEGIN { x = -1; print (-9223372036854775807 - 1) % x, 7 % 2 }"),
+        "0 1\n",
+    );
+}
+
+#[test]
+fn golden_tests() {
+    let tmpdir = tempdir().unwrap();
+    let files = [
+        ("sum.awk", "{ total += $1 }\nEND { print total }\n"),
+        ("sum.in", "1\n2\n3\n"),
+        ("sum.out", "6\n"),
+        ("fields.awk", "{ print $2 }\n"),
+        ("fields.args", "-F,\n"),
+        ("fields.in", "a,b\nc,d\n"),
+        ("fields.out", "b\nx\n"),
+        ("begin.awk", "BEGIN { print \"hello\" }\n"),
+        ("begin.out", "hello\n"),
+        ("notes.txt", "not a test\n"),
+    ];
+    for (name, contents) in files {
+        std::fs::write(tmpdir.path().join(name), contents).unwrap();
+    }
+    let assert = Command::cargo_bin("frawk")
+        .unwrap()
+        .arg("test")
+        .arg(tmpdir.path())
+        .assert()
+        .code(1);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout).into_owned();
+    for expected in [
+        "test begin ... ok\ntest fields ... FAILED\ntest sum ... ok\n",
+        "---- fields (interp) ----\n--- fields.out\n+++ output\n b\n-x\n+d\n",
+        "test result: FAILED. 2 passed; 1 failed",
+    ] {
+        assert!(
+            stdout.contains(expected),
+            "expected {:?} in output: {}",
+            expected,
+            stdout
+        );
+    }
+
+    std::fs::write(tmpdir.path().join("fields.out"), "b\nd\n").unwrap();
+    Command::cargo_bin("frawk")
+        .unwrap()
+        .arg("test")
+        .arg(tmpdir.path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn match_captures() {
+    let prog = r#"{
+        if (match($0, /(?P<key>[a-z]+)=(?P<val>[0-9]+)?(;)?/, m)) {
+            print RSTART, RLENGTH, m[0], ("val" in m), (2 in m), (3 in m)
+            print m["key"], m[1], "[" m["val"] "]"
+        } else {
+            print RSTART, RLENGTH, length(m)
+        }
+    }"#;
+    assert_output(
+        |cmd| cmd.arg(prog).write_stdin("12 ab=34;\nx= \n99\n"),
+        concat!(
+            "4 6 ab=34; 1 1 1\n",
+            "ab ab [34]\n",
+            "1 2 x= 0 0 0\n",
+            "x x []\n",
+            "0 -1 0\n",
+        ),
+    );
+}
+
+#[test]
+fn nested_loops() {
+    let expected = "0 0\n0 1\n0 2\n1 0\n1 1\n1 2\n2 0\n2 1\n2 2\n";
+    let prog: String =
+        "BEGIN { m[0]=0; m[1]=1; m[2]=2; for (i in m) for (j in m) print i,j; }".into();
+    for backend_arg in BACKEND_ARGS {
+        let output = Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog.clone())
+            .output()
+            .unwrap()
+            .stdout;
+        unordered_output_equals(expected.as_bytes(), &output[..]);
+    }
+}
+
+#[test]
+fn dont_reorder_files_with_f() {
+    let expected = "1 1\n2 2\n3 3\n";
+    let prog = "NR == FNR { print NR, FNR}";
+    let test_data_1 = "1\n2\n3\n";
+    let test_data_2 = "1\n2\n3\n4\n5\n";
+    let tmp = tempdir().unwrap();
+    let prog_file = tmp.path().join("prog");
+    let f1 = tmp.path().join("f1");
+    let f2 = tmp.path().join("f2");
+    File::create(f1.clone())
+        .unwrap()
+        .write_all(test_data_1.as_bytes())
+        .unwrap();
+    File::create(f2.clone())
+        .unwrap()
+        .write_all(test_data_2.as_bytes())
+        .unwrap();
+    File::create(prog_file.clone())
+        .unwrap()
+        .write_all(prog.as_bytes())
+        .unwrap();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("frawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(format!("-f{}", fname_to_string(&prog_file)))
+            .arg(fname_to_string(&f1))
+            .arg(fname_to_string(&f2))
+            .assert()
+            .stdout(String::from(expected));
+    }
+}
+
+#[test]
+fn beginfile_endfile() {
+    let (tmp, files) = files_from_strings(&[("f1", "1\n2\n3\n"), ("f2", "1\n2\n3\n4\n5\n")]);
+    for (prog, expected) in [
+        (
+            r#"BEGINFILE { n = 0; f++ }
+$1 > 1 { n++ }
+ENDFILE { print f, (FILENAME == ARGV[f]), FNR, n }
+END { print NR }"#,
+            Ok("1 1 3 2\n2 1 5 4\n8\n"),
+        ),
+        (
+            r#"BEGINFILE { if (FILENAME == ARGV[1]) nextfile; }
+{ print FNR, $0 }"#,
+            Ok("1 1\n2 2\n3 3\n4 4\n5 5\n"),
+        ),
+        (
+            r#"{ n = $0; if ((getline) > 0) print n, $0; else print n, "eof"; }
+ENDFILE { print "end"; }"#,
+            Ok("1 2\n3 eof\nend\n1 2\n3 4\n5 eof\nend\n"),
+        ),
+        // Records are not read from the hooks, and a file cannot be skipped once it has ended.
+        (
+            "BEGINFILE { next }",
+            Err("`next` cannot be used in BEGINFILE"),
+        ),
+        (
+            "ENDFILE { if (FNR > 3) nextfile; }",
+            Err("`next` and `nextfile` cannot be used in ENDFILE"),
+        ),
+    ] {
+        match expected {
+            Ok(out) => assert_output(|cmd| cmd.arg(prog).args(&files), out),
+            Err(msg) => assert_error(|cmd| cmd.arg(prog).args(&files), msg),
+        }
+    }
+    // Empty files still have their hooks run, and files that cannot be read can be skipped from
+    // BEGINFILE before they are opened.
+    let empty = tmp.path().join("empty");
+    File::create(empty.clone()).unwrap();
+    let missing = tmp.path().join("missing");
+    let prog = r#"BEGINFILE {
+    if (system("test -r " FILENAME) != 0) { print "skip", ARGIND; nextfile; }
+    print "begin", ARGIND, FNR;
+}
+ENDFILE { print "end", ARGIND, FNR; }
+END { print NR }"#;
+    assert_output(
+        |cmd| {
+            cmd.arg(prog)
+                .arg(&files[0])
+                .arg(&empty)
+                .arg(&missing)
+                .arg(&files[1])
+        },
+        "begin 1 0\nend 1 3\nbegin 2 0\nend 2 0\nskip 3\nbegin 4 0\nend 4 5\n8\n",
+    );
+}
+
+#[test]
+fn argind() {
+    let (_dir, files) = files_from_strings(&[("f1", "a\nb\n"), ("f2", "c\n")]);
+    assert_output(
+        |cmd| {
+            cmd.arg(r#"{ print ARGIND, (ARGV[ARGIND] == FILENAME), $0 }"#)
+                .args(&files)
+        },
+        "1 1 a\n1 1 b\n2 1 c\n",
+    );
+    assert_output(
+        |cmd| cmd.arg("{ print ARGIND, $0 }").write_stdin("x\n"),
+        "0 x\n",
+    );
+}
+
+#[test]
+fn argv_removes_inputs() {
+    let (_dir, files) = files_from_strings(&[("f1", "a\n"), ("f2", "b\n")]);
+    for (prog, expected) in [
+        (
+            r#"BEGIN { ARGV[1] = "" } { print ARGIND, (FILENAME == ARGV[2]), $0 }"#,
+            "2 1 b\n",
+        ),
+        (r#"BEGIN { delete ARGV[2] } { print $0 }"#, "a\n"),
+        (r#"BEGIN { ARGC = 2 } { print $0 }"#, "a\n"),
+    ] {
+        for fmt_arg in ["-F ", "-F,", "-F::", "-icsv"] {
+            assert_output(|cmd| cmd.arg(fmt_arg).arg(prog).args(&files), expected);
+        }
+    }
+    // Operands removed from ARGV need not be files. With every operand removed, standard input is
+    // read instead.
+    assert_output(
+        |cmd| {
+            cmd.arg(r#"BEGIN { n = ARGV[1]; ARGV[1] = "" } { print n, $0 }"#)
+                .arg("5")
+                .write_stdin("x\n")
+        },
+        "5 x\n",
+    );
+}
+
+#[test]
+fn argv_adds_inputs() {
+    let (_dir, files) = files_from_strings(&[("f1", "a\n"), ("f2", "b\n")]);
+    let add = format!(
+        r#"BEGIN {{ ARGV[ARGC++] = "{}" }} {{ print ARGIND, FNR, NR, $0 }}"#,
+        files[1]
+    );
+    for fmt_arg in ["-F ", "-F,", "-F::", "-icsv"] {
+        assert_output(
+            |cmd| cmd.arg(fmt_arg).arg(&add).arg(&files[0]),
+            "1 1 1 a\n2 1 2 b\n",
+        );
+        // An input added to an empty ARGV is read in place of standard input.
+        assert_output(
+            |cmd| cmd.arg(fmt_arg).arg(&add).write_stdin("x\n"),
+            "1 1 1 b\n",
+        );
+    }
+    // Inputs can also be added while reading, and with BEGINFILE and ENDFILE.
+    let mid = format!(
+        r#"FNR == 1 && ARGIND == 1 {{ ARGV[ARGC++] = "{}" }} ENDFILE {{ print ARGIND, FNR }}"#,
+        files[1]
+    );
+    assert_output(|cmd| cmd.arg(&mid).arg(&files[0]), "1 1\n2 1\n");
+}
+
+#[test]
+fn zip_inputs() {
+    let (_dir, files) = files_from_strings(&[("f1", "a\nb\nc\n"), ("f2", "1\n2\n")]);
+    let prog = r#"{ print ARGIND, (ARGV[ARGIND] == FILENAME), FNR, NR, $1 }"#;
+    for fmt_arg in ["-F,", "-icsv"] {
+        assert_output(
+            |cmd| cmd.arg("--zip").arg(fmt_arg).arg(prog).args(&files),
+            "1 1 1 1 a\n2 1 1 2 1\n1 1 2 3 b\n2 1 2 4 2\n1 1 3 5 c\n",
+        );
+    }
+    assert_error(
+        |cmd| {
+            cmd.arg("--zip")
+                .arg("BEGINFILE { print FILENAME }")
+                .arg(&files[0])
+        },
+        "cannot be used with --zip",
+    );
+}
+
+#[test]
+fn checkpoint_resume() {
+    let (tmp, files) = files_from_strings(&[
+        ("first", "1 a\n2 b\n3 a\n"),
+        ("all", "1 a\n2 b\n3 a\n4 b\n5 c\n"),
+    ]);
+    let ck = tmp.path().join("ck");
+    let ck2 = tmp.path().join("ck2");
+    let prog = r#"{ n += $1; c[$2]++; last = $2 } END { print n, last, c["a"], c["b"], c["c"], NR, FNR, v }"#;
+    assert_output(
+        |cmd| cmd.arg("--checkpoint").arg(&ck).arg(prog).arg(&files[0]),
+        "6 a 2 1 0 3 3 \n",
+    );
+    let saved = std::fs::read_to_string(&ck).unwrap();
+    assert!(
+        saved.contains("NR\t3\n"),
+        "unexpected checkpoint: {}",
+        saved
+    );
+
+    // The first three records are skipped, and the totals carry on from the checkpoint. -v
+    // assignments take precedence over saved variables.
+    assert_output(
+        |cmd| {
+            cmd.arg("--resume")
+                .arg(&ck)
+                .arg("--checkpoint")
+                .arg(&ck2)
+                .arg("-vv=x")
+                .arg(prog)
+                .arg(&files[1])
+        },
+        "15 c 2 2 1 5 2 x\n",
+    );
+
+    assert_error(
+        |cmd| cmd.arg("--resume").arg(&files[1]).arg(prog),
+        "not a valid checkpoint",
+    );
+}
+
+#[test]
+fn watch_program() {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command as StdCommand, Stdio};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let tmp = tempdir().unwrap();
+    let prog = tmp.path().join("prog.awk");
+    for backend_arg in BACKEND_ARGS {
+        std::fs::write(&prog, "BEGIN { print \"one\" }\n").unwrap();
+        let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("frawk"))
+            .arg(backend_arg)
+            .arg("--watch")
+            .arg("-f")
+            .arg(fname_to_string(&prog))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let (send, recv) = mpsc::channel();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        std::thread::spawn(move || {
+            for line in stdout.lines() {
+                if send.send(line.unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+        let timeout = Duration::from_secs(30);
+        let first = recv.recv_timeout(timeout);
+        // Make sure the modification time changes on file systems with coarse timestamps.
+        std::thread::sleep(Duration::from_millis(1100));
+        std::fs::write(&prog, "BEGIN { print \"two\" }\n").unwrap();
+        let second = recv.recv_timeout(timeout);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(first, Ok(String::from("one")));
+        assert_eq!(second, Ok(String::from("two")));
+    }
+    assert_error(
+        |cmd| cmd.arg("--watch").arg("BEGIN { print 1 }"),
+        "--watch requires",
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_socket() {
+    use std::io::Read;
+    use std::net::Shutdown;
+    use std::os::unix::net::UnixStream;
+    use std::process::{Command as StdCommand, Stdio};
+    use std::time::Duration;
+
+    let tmp = tempdir().unwrap();
+    for (i, backend_arg) in BACKEND_ARGS.iter().enumerate() {
+        let sock = tmp.path().join(format!("frawk{}.sock", i));
+        let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("frawk"))
+            .arg(backend_arg)
+            .arg(format!("--serve=unix:{}", fname_to_string(&sock)))
+            .arg("{ n++; print FNR, n, $1 * 2 }")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let query = |input: &str| {
+            let mut conn = None;
+            for _ in 0..300 {
+                match UnixStream::connect(&sock) {
+                    Ok(c) => {
+                        conn = Some(c);
+                        break;
+                    }
+                    Err(_) => std::thread::sleep(Duration::from_millis(100)),
+                }
+            }
+            let mut conn = conn.expect("failed to connect to the server");
+            conn.write_all(input.as_bytes()).unwrap();
+            conn.shutdown(Shutdown::Write).unwrap();
+            let mut output = String::new();
+            conn.read_to_string(&mut output).unwrap();
+            output
+        };
+        let first = query("1\n2\n");
+        // Variables keep their values from one client to the next.
+        let second = query("3\n");
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(first, "1 1 2\n2 2 4\n");
+        assert_eq!(second, "1 3 6\n");
+    }
+}
+
+#[test]
+fn auto_input_format() {
+    // "k,v\n1,2\n", gzipped.
+    const GZIPPED: &[u8] = &[
+        31, 139, 8, 0, 0, 0, 0, 0, 2, 3, 203, 214, 41, 227, 50, 212, 49, 226, 2, 0, 62, 91, 16, 3,
+        8, 0, 0, 0,
+    ];
+    let tmpdir = tempdir().unwrap();
+    let mut files = Vec::new();
+    for (name, data) in [
+        ("x.csv", &b"name,note\nbob,\"hi, there\"\n"[..]),
+        ("y.tsv", &b"a\tb c\n"[..]),
+        ("z.txt", &b"p q r\n"[..]),
+        ("w.gz", GZIPPED),
+    ] {
+        let path = tmpdir.path().join(name);
+        File::create(&path).unwrap().write_all(data).unwrap();
+        files.push(path);
+    }
+    for backend_arg in BACKEND_ARGS {
+        let assert = frawk(backend_arg)
+            .arg("--auto")
+            .arg("--stats")
+            .arg(r#"{ print NF "|" $2 }"#)
+            .args(&files)
+            .assert()
+            .success()
+            .stdout("2|note\n2|hi, there\n2|b c\n3|q\n2|v\n2|2\n");
+        let stderr = stderr_of(&assert);
+        assert!(
+            stderr.contains("x.csv: csv\n")
+                && stderr.contains("y.tsv: tsv\n")
+                && stderr.contains("z.txt: whitespace-separated\n")
+                && stderr.contains("w.gz: gzip, csv\n"),
+            "unexpected error output: {}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn assignment_operands() {
+    let (_dir, files) = files_from_strings(&[("f1", "a\n"), ("f2", "b\n")]);
+    let prog = r#"BEGIN { print "begin", x } { print x, $0 } END { print "end", x }"#;
+    assert_output(
+        |cmd| {
+            cmd.arg(prog)
+                .arg("x=1")
+                .arg(&files[0])
+                .arg("x=2")
+                .arg(&files[1])
+                .arg("x=3")
+        },
+        "begin \n1 a\n2 b\nend 3\n",
+    );
+    assert_output(
+        |cmd| cmd.arg(prog).arg("x=1").write_stdin("c\n"),
+        "begin \n1 c\nend 1\n",
+    );
+}
+
+#[test]
+fn csv_field_assignment() {
+    let csv = "a,\"b,c\",d\n";
+    let cases = [
+        (
+            "-icsv",
+            r#"{ $2 = "x"; print $0; print NF, $2 }"#,
+            csv,
+            "a x d\n3 x\n",
+        ),
+        (
+            "-icsv",
+            r#"BEGIN { OFS = "," } { $5 = "e"; print }"#,
+            csv,
+            "a,b,c,d,,e\n",
+        ),
+        (
+            "-icsv",
+            r#"{ $0 = "p,\"q,r\""; print NF, $2 }"#,
+            csv,
+            "2 q,r\n",
+        ),
+        (
+            "-itsv",
+            r#"BEGIN { OFS = "\t" } { $1 = "z"; print }"#,
+            "a\tb\n",
+            "z\tb\n",
+        ),
+    ];
+    for (format, prog, input, expected) in cases {
+        assert_output(|cmd| cmd.arg(format).arg(prog).write_stdin(input), expected);
+    }
+}
+
+#[test]
+fn record_separators() {
+    let cases = [
+        // Paragraph mode: blank lines separate records, and newlines separate fields.
+        (
+            r#"BEGIN { RS = "" } { print NR ": " $1 "|" $NF "|" NF }"#,
+            "\n\na b\nc\n\n\nd e\n\n",
+            "1: a|c|3\n2: d|e|2\n",
+        ),
+        (
+            r#"BEGIN { RS = ""; FS = "," } { print NF, $2 }"#,
+            "a,b\nc\n\nd\n",
+            "3 b\n1 \n",
+        ),
+        (r#"BEGIN { RS = "" } END { print NR }"#, "\n\n\n", "0\n"),
+        // Regular expressions.
+        (
+            r#"BEGIN { RS = "--+" } { print NR, $0 }"#,
+            "a--b---c",
+            "1 a\n2 b\n3 c\n",
+        ),
+        // RT holds the text that ended each record.
+        (
+            r#"BEGIN { RS = "-+" } { printf "%s[%s]", $0, RT }"#,
+            "a--b-c",
+            "a[--]b[-]c[]",
+        ),
+        (
+            r#"BEGIN { RS = "" } { printf "%s[%s]", $0, RT }"#,
+            "a\n\n\nb\n",
+            "a[\n\n\n]b[\n]",
+        ),
+    ];
+    for (prog, input, expected) in cases {
+        assert_output(|cmd| cmd.arg(prog).write_stdin(input), expected);
+    }
+}
+
+#[test]
+fn traditional_mode() {
+    let prog = r#"function and(a, b) { return a "&" b }
+BEGIN { r = "r"; BEGINFILE = 1; print and(1, 2), r"x", BEGINFILE, "\u{41}", length(PID) }"#;
+    assert_output(
+        |cmd| cmd.arg("--traditional").arg(prog),
+        "1&2 rx 1 \\u{41} 0\n",
+    );
+}
+
+#[test]
+fn assert_failure() {
+    let prog = r#"{ assert($1 < 3, "too big"); print } END { print "done" }"#;
+    assert_error(
+        |cmd| cmd.arg(prog).write_stdin("1\n2\n3\n4\n"),
+        "assertion failed at line 1, column 3: too big (NR=3",
+    );
+    assert_output(
+        |cmd| {
+            cmd.arg("--disable-asserts")
+                .arg(prog)
+                .write_stdin("1\n2\n3\n4\n")
+        },
+        "1\n2\n3\n4\ndone\n",
+    );
+}
+
+#[test]
+fn user_defined_assert() {
+    let prog = r#"function assert(c, msg) { if (!c) print "failed: " msg; return 7 }
+{ x = assert($1 < 3, "too big"); y = assert(n++ < 1) }
+END { print x, n }"#;
+    assert_output(
+        |cmd| cmd.arg(prog).write_stdin("1\n3\n"),
+        "failed: too big\nfailed: \n7 2\n",
+    );
+}
+
+#[test]
+fn dump_state() {
+    let prog =
+        r#"function f(x) { return x + 1 } { n = f(n); m[$1] = NR } END { s = "hi"; dump_state() }"#;
+    for backend_arg in BACKEND_ARGS {
+        let assert = frawk(backend_arg)
+            .arg(prog)
+            .write_stdin("a\nb\n")
+            .assert()
+            .success()
+            .stdout(String::new());
+        let stderr = stderr_of(&assert);
+        for line in &[
+            "n = 2\n",
+            "m: map with 2 entries\n",
+            "  m[b] = 2\n",
+            "s = hi\n",
+        ] {
+            assert!(stderr.contains(line), "missing {:?} in {}", line, stderr);
+        }
+        assert!(
+            !stderr.contains("x = "),
+            "unexpected parameter in {}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn mem_profile() {
+    let prog = r#"{ m[$1] = $2; n++ } END { print length(m) }"#;
+    for backend_arg in BACKEND_ARGS {
+        let assert = frawk(backend_arg)
+            .arg("--mem-profile")
+            .arg(prog)
+            .write_stdin("a x\nb y\n")
+            .assert()
+            .success()
+            .stdout("2\n");
+        let stderr = stderr_of(&assert);
+        assert!(stderr.contains("m: 2 entries, ~"), "{}", stderr);
+        assert!(stderr.contains("allocator: "), "{}", stderr);
+        assert!(!stderr.contains("n = "), "{}", stderr);
+    }
+}
+
+#[test]
+fn opt_levels() {
+    let prog = r#"function add(x, y) { return x + y }
+{ s = add(s, $1); m[$2]++ }
+END { print s, length(m) }"#;
+    for opt_level in &["-1", "0", "1", "2", "3"] {
+        assert_output(
+            |cmd| {
+                cmd.arg("-O")
+                    .arg(opt_level)
+                    .arg(prog)
+                    .write_stdin("1 a\n2 b\n3 a\n")
+            },
+            "6 2\n",
+        );
+    }
+}
+
+#[test]
+fn inlined_helpers() {
+    // Small helpers that read and write globals, alongside a recursive function that should not be
+    // inlined into itself.
+    let prog = r#"function bump(x) { total += x; return total }
+function get() { return total }
+function fact(n) { return n <= 1 ? 1 : n * fact(n - 1) }
+{ last = bump($1) }
+END { print last, get(), fact(5) }"#;
+    assert_output(|cmd| cmd.arg(prog).write_stdin("1\n2\n3\n"), "6 6 120\n");
+}
+
+#[test]
+fn global_strings_and_maps() {
+    // Globals of every kind, written and read from functions other than the one that defines them.
+    let prog = r#"function add(k) { seen[k]++; names = names k ";" }
+function count() { n = 0; for (k in seen) n++; return n }
+{ add($1) }
+END { print count(), seen["a"], names }"#;
+    assert_output(|cmd| cmd.arg(prog).write_stdin("a\nb\na\n"), "2 2 a;b;a;\n");
+}
+
+#[test]
+fn string_moves_in_loops() {
+    // Temporaries that are moved into variables inside loops and branches; none of them should be
+    // freed while still reachable.
+    let prog = r#"function tag(s) { return "<" s ">" }
+{ for (i = 0; i < 2; i++) { t = tag($1 i); if (i) last = t; else first = t; } }
+END { print first, last, t }"#;
+    assert_output(
+        |cmd| cmd.arg(prog).write_stdin("a\nbb\n"),
+        "<bb0> <bb1> <bb1>\n",
+    );
+}
+
+#[test]
+fn recursive_locals_survive_calls() {
+    // Each activation has locals of several types that must be restored after the recursive call
+    // returns.
+    let prog = r#"function walk(n, prefix,    s, m, f) {
+    if (n == 0) return prefix
+    s = prefix n; f = n / 2; m[n] = s
+    r = walk(n - 1, s)
+    return r "|" s "," f "," m[n]
+}
+END { print walk(3, ">") }"#;
+    assert_output(
+        |cmd| cmd.arg(prog).write_stdin(""),
+        ">321|>321,0.5,>321|>32,1,>32|>3,1.5,>3\n",
+    );
+}
+
+#[test]
+fn debug_info() {
+    let prog = r#"function double(x) {
+    return 2 * x
+}
+{ s += double($1) }
+END { print s }"#;
+    assert_output(
+        |cmd| cmd.arg("--debug-info").arg(prog).write_stdin("1\n2\n"),
+        "6\n",
+    );
+    #[cfg(feature = "llvm_backend")]
+    {
+        let out = String::from_utf8(
+            Command::cargo_bin("frawk")
+                .unwrap()
+                .arg("--debug-info")
+                .arg("--dump-llvm")
+                .arg(prog)
+                .output()
+                .unwrap()
+                .stdout,
+        )
+        .unwrap();
+        assert!(out.contains("DISubprogram(name: \"double\""), "{}", out);
+        assert!(out.contains("DILocation(line: 2,"), "{}", out);
+    }
+}
+
+fn fname_to_string(path: &std::path::Path) -> String {
+    path.to_owned().into_os_string().into_string().unwrap()
+}
+
+fn file_from_string(
+    name: impl AsRef<str>,
+    s: impl AsRef<str>,
+) -> (tempfile::TempDir, std::path::PathBuf) {
+    let tmp = tempdir().unwrap();
+    let file = tmp.path().join(name.as_ref());
+    File::create(file.clone())
+        .unwrap()
+        .write_all(s.as_ref().as_bytes())
+        .unwrap();
+    (tmp, file)
+}
+
+// Like `file_from_string`, but writes several files to the same directory, returning their paths.
+fn files_from_strings(files: &[(&str, &str)]) -> (tempfile::TempDir, Vec<String>) {
+    let tmp = tempdir().unwrap();
+    let paths = files
+        .iter()
+        .map(|(name, s)| {
+            let file = tmp.path().join(name);
+            File::create(&file)
+                .unwrap()
+                .write_all(s.as_bytes())
+                .unwrap();
+            fname_to_string(&file)
+        })
+        .collect();
+    (tmp, paths)
 }
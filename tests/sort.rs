@@ -19,10 +19,13 @@ fn numbers_str(n: usize) -> (String, String) {
 
 const N: usize = 10_000;
 
-#[cfg(feature = "llvm_backend")]
-const BACKEND_ARGS: &[&str] = &["-Binterp", "-Bllvm", "-Bcranelift"];
-#[cfg(not(feature = "llvm_backend"))]
-const BACKEND_ARGS: &[&str] = &["-Binterp", "-Bcranelift"];
+const BACKEND_ARGS: &[&str] = &[
+    "-Binterp",
+    #[cfg(feature = "llvm_backend")]
+    "-Bllvm",
+    #[cfg(feature = "cranelift_backend")]
+    "-Bcranelift",
+];
 
 #[cfg(not(target_os = "windows"))]
 #[test]